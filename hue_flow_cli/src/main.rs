@@ -1,20 +1,73 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use hue_flow_core::api::client::HueClient;
-use hue_flow_core::api::discovery::discover_bridges;
-use hue_flow_core::api::groups::{flash_light, get_entertainment_groups, set_stream_active};
-use hue_flow_core::effects::{LightEffect, MultiBandEffect, PulseEffect};
-use hue_flow_core::models::HueConfig;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hue_flow_core::analysis::DEFAULT_HOP;
+use hue_flow_core::api::backup::{export_area, import_area, AreaBackup};
+use hue_flow_core::api::build_client;
+use hue_flow_core::api::cache::{self as group_cache, CACHE_TTL};
+use hue_flow_core::api::client::{HueClient, LINK_WINDOW};
+use hue_flow_core::api::discovery::{discover_bridge, discover_bridges, rediscover_bridge};
+use hue_flow_core::api::groups::{
+    channel_map, flash_light, get_entertainment_groups, merge_entertainment_groups,
+    set_stream_active, GroupInfo,
+};
+use hue_flow_core::api::v2::models::{
+    Device, EntertainmentConfiguration, EntertainmentStatus, Light,
+};
+use hue_flow_core::api::v2::{get_resource, get_resources, get_resources_with};
+use hue_flow_core::audio_interface::{AudioSource, SyntheticAudio};
+use hue_flow_core::color::parse_hex;
+use hue_flow_core::config::{self, ConfigOverrides};
+use hue_flow_core::control::auth::{ControlAuth, Scope, ENV_CONTROL_TOKEN};
+use hue_flow_core::control::socket::{self, Query, Request};
+use hue_flow_core::control::{self, ControlCommand, DEFAULT_CONTROL_ADDR};
+use hue_flow_core::effects::{effect_info, EffectParams, Frame, LightEffect, EFFECTS};
+use hue_flow_core::game::GameConfig;
+use hue_flow_core::history::{self, SessionRecorder};
+use hue_flow_core::models::{GroupEntry, HueConfig, LightNode, RetryPolicy, CONFIG_VERSION};
+use hue_flow_core::output::blackout::DEFAULT_FADE;
+use hue_flow_core::output::calibration::{
+    normalize_gains, CameraReading, ChannelCalibration, Judgement, GRAY_RAMP, PRIMARIES,
+};
+use hue_flow_core::output::color_pipeline::ColorPipeline;
+use hue_flow_core::output::companion;
+use hue_flow_core::output::dmx::{DmxProtocol, DmxSink};
+use hue_flow_core::output::drop_boost::DropBoostConfig;
+use hue_flow_core::output::dry_run::{DryRunFormat, DryRunSink};
+use hue_flow_core::output::lifx::LifxSink;
+use hue_flow_core::output::nanoleaf::{self, NanoleafSink};
+use hue_flow_core::output::openrgb::OpenRgbSink;
+use hue_flow_core::output::simulator::SimulatorSink;
+use hue_flow_core::output::theater::TheaterConfig;
+#[cfg(feature = "mqtt")]
+use hue_flow_core::output::zigbee2mqtt::Zigbee2MqttSink;
+use hue_flow_core::output::LightSink;
+use hue_flow_core::playlist::EffectPlaylist;
+use hue_flow_core::preset::{self, Preset};
+use hue_flow_core::show::render::RoomPainter;
+use hue_flow_core::show::timecode::{self, TimecodeClock};
+use hue_flow_core::show::{Show, ShowPlayer};
 use hue_flow_core::stream::dtls::HueStreamer;
-use hue_flow_core::stream::manager::{run_stream_loop, LightState};
+use hue_flow_core::stream::protocol::{encode_message, ProtocolEncoder};
+use hue_flow_core::stream::rate::check_fps;
+use hue_flow_core::stream::supervisor::{StreamState, StreamSupervisor};
+use hue_flow_core::timing::{self, LatencyStats};
+use hue_flow_core::tuning::{LevelMeter, MAX_SENSITIVITY, MIN_SENSITIVITY, PALETTES};
+use hue_flow_core::{FlowEvent, HueFlow, HueFlowBuilder};
 use inquire::{Confirm, Select};
-use std::fs;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::interval;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
 
-const CONFIG_FILE: &str = "hue_config.json";
+/// Daily log files kept in `--log-dir`.
+const LOG_FILES_KEPT: usize = 14;
 
 #[derive(Parser)]
 #[command(name = "hueflow")]
@@ -22,72 +75,988 @@ const CONFIG_FILE: &str = "hue_config.json";
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Also write logs to this directory as JSON lines, one file per day
+    /// (kept for two weeks)
+    #[arg(long, global = true, env = "HUEFLOW_LOG_DIR", value_name = "DIR")]
+    log_dir: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Setup: Discover bridge and register
-    Setup,
+    Setup(SetupArgs),
     /// Run the entertainment stream
-    Run {
-        /// Effect to use: pulse or multiband
-        #[arg(short, long, default_value = "multiband")]
-        effect: String,
-    },
+    Run(Box<RunArgs>),
     /// Show current configuration
-    Config,
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
     /// Test connection by flashing a light
-    Test,
+    Test {
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
     /// Send a static DTLS packet for debugging
-    Static,
+    Static {
+        /// Log each step of the DTLS handshake and explain why it failed
+        #[arg(long)]
+        debug_dtls: bool,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// Manage named effect presets
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+    /// Switch between entertainment groups
+    Group {
+        #[command(subcommand)]
+        action: GroupAction,
+    },
+    /// Back up or restore an entertainment area's lights and positions
+    Area {
+        #[command(subcommand)]
+        action: AreaAction,
+    },
+    /// Inspect audio input devices
+    Audio {
+        #[command(subcommand)]
+        action: AudioAction,
+    },
+    /// Fade a running instance to black and hold (panic button)
+    Blackout {
+        /// Fade duration in milliseconds
+        #[arg(long, default_value_t = 300)]
+        fade_ms: u64,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// Fade a running instance back in after a blackout
+    Resume {
+        /// Fade duration in milliseconds
+        #[arg(long, default_value_t = 300)]
+        fade_ms: u64,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// Skip through or shuffle the playlist of a running instance
+    Playlist {
+        #[command(subcommand)]
+        action: PlaylistAction,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// Turn photosensitive-safe mode of a running instance on or off
+    SafeMode {
+        #[arg(value_enum)]
+        state: Toggle,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// Change the frame rate of a running instance
+    Fps {
+        /// Target frames per second, 10-50
+        fps: u32,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// Show bridge, entertainment area and local stream status
+    Status {
+        /// Control API address of a locally running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// List the available effects with their palette slots
+    Effects,
+    /// Review past sessions: duration, frame rate, errors and effects
+    Stats {
+        /// Number of recent sessions to list
+        #[arg(long, default_value_t = 10)]
+        last: usize,
+    },
+    /// Audition an effect for a few seconds with synthetic audio
+    Preview {
+        /// Effect to show (see `hueflow effects`)
+        effect: String,
+        /// How long to run it, in seconds
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+        /// Where frames go: the Hue Bridge, a simulated room in the terminal or zigbee2mqtt bulbs
+        #[arg(long, value_enum, default_value_t = Sink::Hue)]
+        sink: Sink,
+        /// Number of lights in the simulated room (up to 20)
+        #[arg(long, default_value_t = 8)]
+        sim_lights: usize,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// Measure the band levels of test tones or live audio and tune
+    /// sensitivity, brightness and palette into a preset
+    Tune {
+        /// Audio to measure (asks when not given)
+        #[arg(long, value_enum)]
+        audio: Option<Audio>,
+        /// How long to measure, in seconds
+        #[arg(long, default_value_t = TUNE_SECONDS)]
+        seconds: u64,
+        /// Preset to save the results to; it becomes the active one
+        #[arg(long, default_value = TUNED_PRESET)]
+        name: String,
+    },
+    /// Light up each entertainment channel in turn to identify the physical lights
+    Identify {
+        /// Seconds each channel stays lit
+        #[arg(long, default_value_t = 3)]
+        step: u64,
+        /// Number of passes over all channels
+        #[arg(long, default_value_t = 1)]
+        cycles: u32,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// Match every light to a reference light in primaries and a gray ramp,
+    /// saving per-light gamma and white balance for the color pipeline
+    Calibrate {
+        /// Channel the other lights are matched to (defaults to the first of the group)
+        #[arg(long)]
+        reference: Option<u8>,
+        /// Measure with a webcam through ffmpeg instead of asking, e.g. /dev/video0
+        #[arg(long, value_name = "DEVICE")]
+        camera: Option<String>,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// Measure REST and DTLS round trips, and with a light sensor the delay
+    /// until a flash is visible
+    Latency {
+        /// Number of requests and flashes
+        #[arg(long, default_value_t = 10)]
+        samples: usize,
+        /// Channel to flash (defaults to the first of the group)
+        #[arg(long)]
+        channel: Option<u8>,
+        /// Light sensor printing a line per detected flash, e.g. a photodiode
+        /// on a microcontroller's serial port (set up its baud rate with stty)
+        #[arg(long)]
+        sensor: Option<PathBuf>,
+        #[command(flatten)]
+        conn: ConnectionArgs,
+    },
+    /// Render a recorded show as a top-down animation of the room (.gif,
+    /// or .mp4 and other video formats through ffmpeg)
+    RenderVideo {
+        /// Show recorded with `run --record-show`
+        show: PathBuf,
+        /// Light positions: a JSON list of lights, or a group as in the group
+        /// cache [default: the cached layout of the configured group]
+        #[arg(long, value_name = "FILE")]
+        layout: Option<PathBuf>,
+        /// Where to write the animation; the extension picks the format
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+        /// Width and height of the picture in pixels
+        #[arg(long, default_value_t = 320)]
+        size: u32,
+    },
+    /// Manage API tokens for the control API and the gRPC service
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+    /// Command a running instance over its local control socket
+    Ctl {
+        #[command(subcommand)]
+        action: CtlAction,
+        /// Control socket of the running instance [default: $XDG_RUNTIME_DIR/hueflow.sock]
+        #[arg(long, global = true)]
+        socket: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Upgrade the config file to the current format, keeping a backup
+    Migrate,
+    /// Print one config value, e.g. `fps` or `retry.max_attempts`
+    Get { key: String },
+    /// Change one config value, checked against the config format
+    Set {
+        key: String,
+        /// JSON (`30`, `true`, `null`, `[1, 2]`) or plain text
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CtlAction {
+    /// Switch to an effect with its default palette
+    SetEffect { name: String },
+    /// Switch to a saved preset
+    Preset { name: String },
+    /// Load the active preset again, picking up edits to its file
+    Reload,
+    /// Fade to black and hold
+    Blackout {
+        #[arg(long, default_value_t = DEFAULT_FADE.as_millis() as u64)]
+        fade_ms: u64,
+    },
+    /// Fade back in after a blackout
+    Resume {
+        #[arg(long, default_value_t = DEFAULT_FADE.as_millis() as u64)]
+        fade_ms: u64,
+    },
+    /// Hold channels at a fixed color over the effect, e.g. `override 3,5 ff0000`
+    Override {
+        #[arg(value_delimiter = ',', required = true)]
+        channels: Vec<u8>,
+        /// Hex color such as ff0000
+        color: String,
+    },
+    /// Hand overridden channels back to the effect (all when none are given)
+    Release {
+        #[arg(value_delimiter = ',')]
+        channels: Vec<u8>,
+    },
+    /// Show the stream's frame rate and counters
+    Status,
+    /// End the stream and release the entertainment area
+    Stop,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Effect to use: pulse, warm, spectrum, chase, noise, storm, karaoke or multiband [default: multiband],
+    /// or shuffle for a random effect and palette every few minutes (see `shuffle` in the config)
+    #[arg(short, long, conflicts_with = "preset")]
+    effect: Option<String>,
+    /// Saved preset to start with (defaults to the active preset)
+    #[arg(long)]
+    preset: Option<String>,
+    /// Entertainment group to stream to, by name or ID (defaults to the configured group)
+    #[arg(long)]
+    group: Option<String>,
+    /// Start from the cached group layout without asking the bridge for it
+    #[arg(long)]
+    offline: bool,
+    /// Restrict all output to white tones (color temperature only)
+    #[arg(long)]
+    ct_only: bool,
+    /// Listen address of the control API
+    #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+    control_addr: String,
+    /// Local control socket for `hueflow ctl` [default: $XDG_RUNTIME_DIR/hueflow.sock]
+    #[arg(long, value_name = "PATH")]
+    control_socket: Option<PathBuf>,
+    /// Also serve the gRPC control service, e.g. on 127.0.0.1:7421
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    grpc_addr: Option<String>,
+    /// Channels to leave out of the stream, e.g. 3,5 (added to the config's list)
+    #[arg(long, value_delimiter = ',')]
+    exclude_channel: Vec<u8>,
+    /// Where frames go: the Hue Bridge, a simulated room in the terminal or zigbee2mqtt bulbs
+    #[arg(long, value_enum, default_value_t = Sink::Hue)]
+    sink: Sink,
+    /// Number of lights in the simulated room (up to 20)
+    #[arg(long, default_value_t = 8)]
+    sim_lights: usize,
+    /// Run the whole pipeline but print the frames instead of streaming them;
+    /// neither the bridge nor any other light is contacted
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "console"
+    )]
+    dry_run: Option<DryRun>,
+    /// Write the frames of --dry-run to this file instead of stdout
+    #[arg(long, value_name = "PATH", requires = "dry_run")]
+    dry_run_out: Option<PathBuf>,
+    /// Log p50/p99 latencies of the pipeline stages every few seconds
+    #[arg(long)]
+    trace_timing: bool,
+    /// Log the channel values of every Nth frame, for postmortem analysis
+    /// (best with --log-dir)
+    #[arg(long, value_name = "N")]
+    log_frames: Option<u64>,
+    /// Log each step of the DTLS handshake and explain why it failed
+    #[arg(long)]
+    debug_dtls: bool,
+    /// Fade every color change over this many ms, smoothing lights that visibly
+    /// step (per-channel values from the config take precedence)
+    #[arg(long, value_name = "MS")]
+    smooth_ms: Option<u64>,
+    /// Color saturation: 0 = gray, 1 = unchanged, >1 boosts (overrides the config)
+    #[arg(long)]
+    saturation: Option<f32>,
+    /// Contrast around mid gray: 1 = unchanged (overrides the config)
+    #[arg(long)]
+    contrast: Option<f32>,
+    /// Audio driving the effect
+    #[arg(long, value_enum, default_value_t = Audio::Synthetic)]
+    audio: Audio,
+    /// Input device to capture from with `--audio mic`, instead of the one
+    /// from setup (falls back to the default input while it is disconnected)
+    #[arg(long, value_name = "NAME")]
+    audio_device: Option<String>,
+    /// Samples between overlapping FFT windows of 1024; smaller values give
+    /// smoother, more frequent spectrum updates at more CPU
+    #[arg(long, default_value_t = DEFAULT_HOP)]
+    hop_size: usize,
+    /// Analyze captured audio on a thread of its own, so a slow analysis
+    /// (small hop sizes on weak CPUs) delays the spectrum, not the lights
+    #[arg(long)]
+    analysis_thread: bool,
+    /// Notch out mains hum at this frequency (50 or 60 Hz; overrides the config)
+    #[arg(long, value_name = "HZ")]
+    hum_filter: Option<f32>,
+    /// Silence audio input below this level in dBFS, e.g. -50 (overrides the config)
+    #[arg(long, value_name = "DB", allow_hyphen_values = true)]
+    noise_gate_db: Option<f32>,
+    /// Photosensitive-safe mode: no strobes, flashing kept below 3 Hz
+    /// (also enabled by `safe_mode` in the config)
+    #[arg(long)]
+    safe: bool,
+    /// Don't send frames identical to the previous one (still repeated
+    /// 4 times a second to keep the stream alive), saving Wi-Fi traffic in
+    /// static scenes
+    #[arg(long)]
+    dedup_frames: bool,
+    /// Play frames out this many milliseconds after their render tick, so
+    /// uneven effect timing does not show as uneven motion (40 without a value)
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "40")]
+    jitter_buffer: Option<u64>,
+    /// Render each room listed under `rooms` in the config as one light at
+    /// the middle of its channels, all of which then show the same color
+    #[arg(long)]
+    room_mode: bool,
+    /// Warmer, dimmer output late at night along the config's `circadian`
+    /// curve (on by default when the config has one)
+    #[arg(long, value_enum)]
+    circadian: Option<Toggle>,
+    /// Boost brightness for a few seconds when a drop hits after a build-up
+    /// (also enabled by `drop_boost` in the config)
+    #[arg(long)]
+    drop_boost: bool,
+    /// Theater mode for films: heavy smoothing, dim lights behind the viewer,
+    /// calm during dialogue (also enabled by `theater` in the config)
+    #[arg(long)]
+    theater: bool,
+    /// Listen for game events (UDP JSON) and flash light cues over the
+    /// effect (also enabled by `game` in the config)
+    #[arg(long)]
+    game: bool,
+    /// Play a show recorded with --record-show instead of an effect
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["effect", "preset"])]
+    show: Option<PathBuf>,
+    /// Timecode the show follows [default: free, playing from the start]
+    #[arg(long, value_enum, requires = "show")]
+    timecode: Option<TimecodeSource>,
+    /// MIDI device or pipe to read MTC from (e.g. /dev/snd/midiC1D0), or the
+    /// audio input carrying LTC (default input when not given)
+    #[arg(long, value_name = "DEVICE", requires = "timecode")]
+    timecode_device: Option<String>,
+    /// Timecode of the show's first frame in whole seconds, e.g. 01:00:00
+    #[arg(long, value_name = "HH:MM:SS", requires = "show")]
+    show_start: Option<String>,
+    /// Record the effect's frames, before color correction, as a show file (.hfl) for --show
+    #[arg(long, value_name = "FILE")]
+    record_show: Option<PathBuf>,
+    /// Stop after this many seconds
+    #[arg(long, value_name = "SECS")]
+    duration: Option<u64>,
+    /// Target frame rate, 10-50 (overrides the preset and the config)
+    #[arg(long)]
+    fps: Option<u32>,
+    #[command(flatten)]
+    conn: ConnectionArgs,
+}
+
+impl Default for RunArgs {
+    fn default() -> Self {
+        Self {
+            effect: None,
+            preset: None,
+            group: None,
+            offline: false,
+            ct_only: false,
+            control_addr: DEFAULT_CONTROL_ADDR.to_string(),
+            control_socket: None,
+            #[cfg(feature = "grpc")]
+            grpc_addr: None,
+            exclude_channel: Vec::new(),
+            sink: Sink::Hue,
+            sim_lights: 8,
+            dry_run: None,
+            dry_run_out: None,
+            trace_timing: false,
+            log_frames: None,
+            debug_dtls: false,
+            smooth_ms: None,
+            saturation: None,
+            contrast: None,
+            audio: Audio::Synthetic,
+            audio_device: None,
+            hop_size: DEFAULT_HOP,
+            analysis_thread: false,
+            hum_filter: None,
+            noise_gate_db: None,
+            safe: false,
+            dedup_frames: false,
+            jitter_buffer: None,
+            room_mode: false,
+            circadian: None,
+            drop_boost: false,
+            theater: false,
+            game: false,
+            show: None,
+            timecode: None,
+            timecode_device: None,
+            show_start: None,
+            record_show: None,
+            duration: None,
+            fps: None,
+            conn: ConnectionArgs::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Audio {
+    /// Drifting sine waves, no microphone needed
+    #[default]
+    Synthetic,
+    /// Live capture from an input device (needs the `capture` feature)
+    Mic,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum TimecodeSource {
+    /// No timecode: play from the start once streaming
+    #[default]
+    Free,
+    /// MIDI timecode from a MIDI device
+    Mtc,
+    /// Linear timecode on an audio input (needs the `capture` feature)
+    Ltc,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Toggle {
+    On,
+    Off,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum Sink {
+    /// Stream to the entertainment area on the bridge
+    #[default]
+    Hue,
+    /// Draw the lights as a virtual room in the terminal
+    Sim,
+    /// Publish to bulbs on a zigbee2mqtt coordinator (`zigbee2mqtt` in the
+    /// config, needs `--features mqtt`)
+    Zigbee,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DryRun {
+    /// One readable line per frame
+    Console,
+    /// One JSON object per frame
+    Jsonl,
+}
+
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Create a token, replacing one of the same name, and print it
+    Create {
+        /// Name to tell it apart, e.g. "phone"
+        name: String,
+        /// Only status, the overlay and stream state events, no commands
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// List the tokens by name and scope
+    List,
+    /// Revoke a token; clients using it are refused from the next start
+    Revoke { name: String },
+}
+
+#[derive(Subcommand)]
+enum GroupAction {
+    /// Stream to another entertainment group from now on
+    Use {
+        /// Group name or ID
+        name: String,
+    },
+    /// List the entertainment groups found during setup
+    List,
+    /// Combine entertainment groups into one and stream to it from now on
+    /// (the bridge streams to only one group at a time)
+    Merge {
+        /// Group names or IDs
+        #[arg(num_args = 2.., required = true)]
+        groups: Vec<String>,
+        /// Name of the combined group [default: "HueFlow" and the group names]
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AreaAction {
+    /// Save an entertainment area to a file
+    Export {
+        /// File to write
+        file: PathBuf,
+        /// Group name or ID [default: the group streamed to]
+        #[arg(long)]
+        group: Option<String>,
+    },
+    /// Recreate an entertainment area from a file, e.g. after a bridge reset
+    Import {
+        /// File written by 'area export'
+        file: PathBuf,
+        /// Name of the restored group; a group of that name is updated
+        /// [default: the name in the file]
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PlaylistAction {
+    /// Skip to the next entry
+    Next,
+    /// Go back to the previous entry
+    Prev,
+    /// Play the entries in random order, or in list order again
+    Shuffle {
+        #[arg(value_enum)]
+        state: Toggle,
+    },
+}
+
+#[derive(Subcommand)]
+enum AudioAction {
+    /// List input devices of all audio hosts with their supported configurations
+    List,
+}
+
+#[derive(Subcommand)]
+enum PresetAction {
+    /// Save a named preset
+    Save {
+        name: String,
+        /// Effect to use: pulse, warm, spectrum, chase, noise, storm, karaoke or multiband
+        #[arg(short, long, default_value = "multiband")]
+        effect: String,
+        /// Comma separated hex colors, e.g. ff0000,00ff00,0000ff
+        #[arg(long, value_delimiter = ',')]
+        palette: Vec<String>,
+        /// Master brightness (0.0 - 1.0)
+        #[arg(long, default_value_t = 1.0)]
+        brightness: f32,
+        /// Gain on the audio levels: above 1 for quiet rooms, below 1 for loud ones
+        #[arg(long, default_value_t = 1.0)]
+        sensitivity: f32,
+        /// Restrict all output to white tones
+        #[arg(long)]
+        ct_only: bool,
+        /// Entertainment configuration ID to stream to
+        #[arg(long)]
+        group_id: Option<String>,
+        /// Frame rate while the preset is active, 10-50
+        #[arg(long)]
+        fps: Option<u32>,
+        /// Dim lights as their band gets louder (multiband)
+        #[arg(long)]
+        invert: bool,
+    },
+    /// Make a preset active and switch a running instance to it
+    Load {
+        name: String,
+        /// Control API address of the running instance
+        #[arg(long, default_value = DEFAULT_CONTROL_ADDR)]
+        control_addr: String,
+    },
+    /// List saved presets
+    List,
+}
+
+/// Overrides for the config file and HUEFLOW_* environment variables
+#[derive(Args, Default)]
+struct ConnectionArgs {
+    /// Bridge IP address
+    #[arg(long)]
+    bridge_ip: Option<String>,
+    /// hue-application-key (username) from registration
+    #[arg(long)]
+    app_key: Option<String>,
+    /// Client key (DTLS PSK, hex) from registration
+    #[arg(long)]
+    client_key: Option<String>,
+    /// Entertainment configuration ID (UUID)
+    #[arg(long)]
+    group_id: Option<String>,
+}
+
+impl ConnectionArgs {
+    fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            bridge_ip: self.bridge_ip.clone(),
+            username: self.app_key.clone(),
+            client_key: self.client_key.clone(),
+            application_id: None,
+            entertainment_group_id: self.group_id.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct SetupArgs {
+    /// Never prompt; fail instead (for Docker/Ansible provisioning)
+    #[arg(long, env = "HUEFLOW_NON_INTERACTIVE")]
+    non_interactive: bool,
+    /// Bridge IP address (skips discovery)
+    #[arg(long, env = "HUEFLOW_BRIDGE_IP")]
+    bridge_ip: Option<String>,
+    /// Entertainment group to use, by name or ID
+    #[arg(long, env = "HUEFLOW_GROUP")]
+    group: Option<String>,
+    /// Seconds to wait for the link button in non-interactive mode
+    #[arg(long, env = "HUEFLOW_LINK_TIMEOUT", default_value_t = 60)]
+    link_timeout: u64,
+    /// Audio input device to capture from (skips the device prompt)
+    #[arg(long, env = "HUEFLOW_AUDIO_DEVICE")]
+    audio_device: Option<String>,
+    /// Sample format for the audio device, e.g. i16 or f32
+    #[arg(long, env = "HUEFLOW_AUDIO_FORMAT")]
+    audio_format: Option<String>,
+}
+
+impl Default for SetupArgs {
+    fn default() -> Self {
+        Self {
+            non_interactive: false,
+            bridge_ip: None,
+            group: None,
+            link_timeout: 60,
+            audio_device: None,
+            audio_format: None,
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
+    // Flushes the log file on exit
+    let _log_guard = init_logging(cli.log_dir.as_deref())?;
 
     match cli.command {
-        Some(Commands::Setup) => run_setup().await,
-        Some(Commands::Run { effect }) => run_stream(&effect).await,
-        Some(Commands::Config) => show_config(),
-        Some(Commands::Test) => run_test().await,
-        Some(Commands::Static) => run_static_test().await,
+        Some(Commands::Setup(args)) => run_setup(args).await,
+        Some(Commands::Run(args)) => run_stream(&args).await,
+        Some(Commands::Config { action: None, conn }) => show_config(&conn),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Migrate),
+            ..
+        }) => migrate_config(),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Get { key }),
+            ..
+        }) => get_config_key(&key),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Set { key, value }),
+            ..
+        }) => set_config_key(&key, &value),
+        Some(Commands::Test { conn }) => run_test(&conn).await,
+        Some(Commands::Static { debug_dtls, conn }) => run_static_test(&conn, debug_dtls).await,
+        Some(Commands::Preset { action }) => run_preset(action).await,
+        Some(Commands::Group { action }) => run_group(action).await,
+        Some(Commands::Token { action }) => run_token(action),
+        Some(Commands::Area { action }) => run_area(action).await,
+        Some(Commands::Audio { action }) => run_audio(action),
+        Some(Commands::Status { control_addr, conn }) => run_status(&control_addr, &conn).await,
+        Some(Commands::Blackout {
+            fade_ms,
+            control_addr,
+        }) => {
+            send_control(&control_addr, &format!("blackout?fade_ms={}", fade_ms)).await?;
+            println!("⬛ Blackout engaged");
+            Ok(())
+        }
+        Some(Commands::Resume {
+            fade_ms,
+            control_addr,
+        }) => {
+            send_control(&control_addr, &format!("resume?fade_ms={}", fade_ms)).await?;
+            println!("▶️  Resumed");
+            Ok(())
+        }
+        Some(Commands::Playlist {
+            action,
+            control_addr,
+        }) => {
+            let path = match action {
+                PlaylistAction::Next => "playlist/next",
+                PlaylistAction::Prev => "playlist/prev",
+                PlaylistAction::Shuffle { state: Toggle::On } => "playlist/shuffle/on",
+                PlaylistAction::Shuffle { state: Toggle::Off } => "playlist/shuffle/off",
+            };
+            send_control(&control_addr, path).await?;
+            println!("🎶 Playlist updated");
+            Ok(())
+        }
+        Some(Commands::SafeMode {
+            state,
+            control_addr,
+        }) => {
+            let on = state == Toggle::On;
+            send_control(
+                &control_addr,
+                if on { "safe-mode/on" } else { "safe-mode/off" },
+            )
+            .await?;
+            println!("🛡️  Safe mode {}", if on { "on" } else { "off" });
+            Ok(())
+        }
+        Some(Commands::Fps { fps, control_addr }) => {
+            check_fps(fps).map_err(anyhow::Error::msg)?;
+            send_control(&control_addr, &format!("fps/{}", fps)).await?;
+            println!("🎞️  Frame rate set to {} FPS", fps);
+            Ok(())
+        }
+        Some(Commands::Effects) => {
+            list_effects();
+            Ok(())
+        }
+        Some(Commands::Stats { last }) => show_stats(last),
+        Some(Commands::Preview {
+            effect,
+            seconds,
+            sink,
+            sim_lights,
+            conn,
+        }) => run_preview(effect, seconds, sink, sim_lights, conn).await,
+        Some(Commands::Tune {
+            audio,
+            seconds,
+            name,
+        }) => run_tune(audio, seconds, &name).await,
+        Some(Commands::Identify { step, cycles, conn }) => {
+            run_identify(Duration::from_secs(step), cycles, &conn).await
+        }
+        Some(Commands::Calibrate {
+            reference,
+            camera,
+            conn,
+        }) => run_calibrate(reference, camera.as_deref(), &conn).await,
+        Some(Commands::Latency {
+            samples,
+            channel,
+            sensor,
+            conn,
+        }) => run_latency(samples.max(1), channel, sensor, &conn).await,
+        Some(Commands::RenderVideo {
+            show,
+            layout,
+            out,
+            size,
+        }) => render_video(&show, layout.as_deref(), &out, size),
+        Some(Commands::Ctl { action, socket }) => run_ctl(action, socket).await,
         None => {
-            if config_path().exists() {
+            if load_config(&ConnectionArgs::default()).is_ok() {
                 println!("🎨 HueFlow - Starting entertainment stream...");
                 println!("   Use 'hueflow setup' to reconfigure");
                 println!("   Use 'hueflow run --effect pulse' for pulse effect");
                 println!();
-                run_stream("multiband").await
+                run_stream(&RunArgs::default()).await
             } else {
                 println!("👋 Welcome to HueFlow!");
                 println!("   No configuration found. Starting setup...");
                 println!();
-                run_setup().await
+                run_setup(SetupArgs::default()).await
             }
         }
     }
 }
 
-fn config_path() -> PathBuf {
-    PathBuf::from(CONFIG_FILE)
+/// Logs to the terminal at info level, and with `log_dir` also to daily
+/// rotated JSON files there. Keep the returned guard until exit so buffered
+/// lines reach the file.
+fn init_logging(log_dir: Option<&Path>) -> Result<Option<WorkerGuard>> {
+    let console = tracing_subscriber::fmt::layer().with_filter(LevelFilter::INFO);
+    let Some(dir) = log_dir else {
+        tracing_subscriber::registry().with(console).init();
+        return Ok(None);
+    };
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("hueflow")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES_KEPT)
+        .build(dir)
+        .with_context(|| format!("Cannot log to {}", dir.display()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let file = tracing_subscriber::fmt::layer()
+        .json()
+        .with_writer(writer)
+        .with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .init();
+    Ok(Some(guard))
 }
 
-fn load_config() -> Result<HueConfig> {
-    let content = fs::read_to_string(config_path()).context("Failed to read config file")?;
-    serde_json::from_str(&content).context("Failed to parse config file")
+/// Resolves the config from env vars, the config file and command line flags.
+fn load_config(conn: &ConnectionArgs) -> Result<HueConfig> {
+    Ok(config::resolve(&config::config_path(), &conn.overrides())?)
 }
 
 fn save_config(config: &HueConfig) -> Result<()> {
-    let content = serde_json::to_string_pretty(config)?;
-    fs::write(config_path(), content)?;
+    Ok(config::save_file(&config::config_path(), config)?)
+}
+
+/// Upgrades the config file on disk to the current format.
+fn migrate_config() -> Result<()> {
+    let path = config::config_path();
+    match config::migrate_file(&path)? {
+        None => println!("📋 No config file at {}", path.display()),
+        Some(from) if from < CONFIG_VERSION as u64 => {
+            println!(
+                "✅ Upgraded {} from version {} to {}",
+                path.display(),
+                from,
+                CONFIG_VERSION
+            );
+            println!("   Original kept as {}.v{}.bak", path.display(), from);
+        }
+        Some(_) => println!(
+            "✅ {} is already at version {}",
+            path.display(),
+            CONFIG_VERSION
+        ),
+    }
+    Ok(())
+}
+
+/// The config file, which `config get/set` work on.
+fn stored_config(path: &Path) -> Result<HueConfig> {
+    config::load_file(path)?.with_context(|| {
+        format!(
+            "No config file at {}. Run 'hueflow setup' first.",
+            path.display()
+        )
+    })
+}
+
+fn get_config_key(key: &str) -> Result<()> {
+    let value = config::get_key(&stored_config(&config::config_path())?, key)?;
+    match value {
+        serde_json::Value::String(text) => println!("{}", text),
+        value => println!("{}", serde_json::to_string_pretty(&value)?),
+    }
+    Ok(())
+}
+
+fn set_config_key(key: &str, value: &str) -> Result<()> {
+    let path = config::config_path();
+    let mut stored = stored_config(&path)?;
+    config::set_key(&mut stored, key, value)?;
+    config::save_file(&path, &stored)?;
+    let value = config::get_key(&stored, key)?;
+    println!("✅ {} = {}", key, value);
+    Ok(())
+}
+
+/// Applies `update` to the config file, if there is one (env-only setups have none).
+fn update_config_file(update: impl FnOnce(&mut HueConfig)) -> Result<()> {
+    let path = config::config_path();
+    if let Some(mut stored) = config::load_file(&path)? {
+        update(&mut stored);
+        config::save_file(&path, &stored)?;
+    }
+    Ok(())
+}
+
+/// Loads the config and makes sure the bridge is still at the stored address.
+/// If it moved (e.g. a new DHCP lease), finds it again by bridge ID and saves
+/// the new address.
+async fn connect_config(conn: &ConnectionArgs) -> Result<HueConfig> {
+    let mut config = load_config(conn)?;
+    let probe = tokio::time::timeout(
+        Duration::from_secs(3),
+        HueClient::get_bridge_config(&config.bridge_ip),
+    )
+    .await;
+
+    match probe {
+        // Configs written before rediscovery was added have no bridge ID yet
+        Ok(Ok(bridge)) if config.bridge_id.is_empty() => {
+            let ip = config.bridge_ip.clone();
+            update_config_file(|stored| {
+                if stored.bridge_ip == ip {
+                    stored.bridge_id = bridge.bridge_id;
+                }
+            })?;
+        }
+        Ok(Ok(_)) => {}
+        _ if !config.bridge_id.is_empty() => {
+            println!(
+                "🔍 Bridge not reachable at {}, searching for it...",
+                config.bridge_ip
+            );
+            let ip = rediscover_bridge(&config.bridge_id, &config.retry)
+                .await
+                .context("Bridge not found on the network")?;
+            println!("   Found bridge at {}", ip);
+            config.bridge_ip = ip.clone();
+            update_config_file(|stored| stored.bridge_ip = ip)?;
+        }
+        // Without a bridge ID there is nothing to match; the next request reports the error
+        _ => {}
+    }
+    Ok(config)
+}
+
+/// Retry settings of an existing config file, for setup (which has no
+/// config yet or replaces it).
+fn stored_retry_policy() -> RetryPolicy {
+    config::load_file(&config::config_path())
+        .ok()
+        .flatten()
+        .map(|stored| stored.retry)
+        .unwrap_or_default()
+}
+
+/// Fetches the application ID (PSK Identity) when it was not configured,
+/// e.g. in container deployments that only provide the app and client keys.
+async fn ensure_application_id(config: &mut HueConfig) -> Result<()> {
+    if config.application_id.is_empty() {
+        println!("🔑 Fetching application ID...");
+        config.application_id =
+            HueClient::get_application_id(&config.bridge_ip, &config.username).await?;
+    }
     Ok(())
 }
 
-fn show_config() -> Result<()> {
-    match load_config() {
+fn show_config(conn: &ConnectionArgs) -> Result<()> {
+    match load_config(conn) {
         Ok(config) => {
             println!("📋 Current Configuration:");
             println!("   Bridge IP: {}", config.bridge_ip);
@@ -97,20 +1066,51 @@ fn show_config() -> Result<()> {
                 config.application_id
             );
             println!("   Entertainment Group: {}", config.entertainment_group_id);
+            if !config.swversion.is_empty() {
+                println!(
+                    "   Bridge: {} (firmware {})",
+                    config.bridge_model, config.swversion
+                );
+            }
+            if let Some(device) = &config.audio_device {
+                println!(
+                    "   Audio input: {} ({})",
+                    device,
+                    config
+                        .audio_sample_format
+                        .as_deref()
+                        .unwrap_or("default format")
+                );
+            }
         }
-        Err(_) => {
-            println!("❌ No configuration found. Run 'hueflow setup' first.");
+        Err(e) => {
+            println!("❌ {}", e);
         }
     }
     Ok(())
 }
 
-async fn run_setup() -> Result<()> {
+async fn run_setup(args: SetupArgs) -> Result<()> {
+    if args.non_interactive {
+        return run_setup_non_interactive(&args).await;
+    }
+
+    if let Some(ip) = &args.bridge_ip {
+        println!("📡 Using bridge at: {}", ip);
+        println!();
+        println!("⚠️  Please press the LINK button on your Hue Bridge, then press Enter.");
+        let _ = Confirm::new("Have you pressed the link button?")
+            .with_default(true)
+            .prompt()?;
+
+        return continue_registration(ip, &args).await;
+    }
+
     println!("🔍 Discovering Hue Bridges...");
     println!("   (Checking reachability of each bridge...)");
     println!();
 
-    let bridges = match discover_bridges().await {
+    let bridges = match discover_bridges(&stored_retry_policy()).await {
         Ok(b) if !b.is_empty() => b,
         Ok(_) | Err(_) => {
             println!("⚠️  No bridges found via cloud discovery.");
@@ -124,16 +1124,19 @@ async fn run_setup() -> Result<()> {
                 .with_default(true)
                 .prompt()?;
 
-            return continue_registration(&ip).await;
+            return continue_registration(&ip, &args).await;
         }
     };
 
     println!("Found {} bridge(s):", bridges.len());
     for (i, bridge) in bridges.iter().enumerate() {
-        let status = if i == 0 {
-            "✅ reachable"
+        let status = if bridge.reachable {
+            format!(
+                "✅ {} ({}, firmware {})",
+                bridge.name, bridge.model, bridge.swversion
+            )
         } else {
-            "⚠️  may be unreachable"
+            "⚠️  not reachable".to_string()
         };
         println!(
             "  {}. {} (ID: {}) - {}",
@@ -171,41 +1174,108 @@ async fn run_setup() -> Result<()> {
         .with_default(true)
         .prompt()?;
 
-    continue_registration(&bridge_ip).await
+    continue_registration(&bridge_ip, &args).await
 }
 
-async fn continue_registration(bridge_ip: &str) -> Result<()> {
-    println!("🔐 Registering with bridge...");
+async fn run_setup_non_interactive(args: &SetupArgs) -> Result<()> {
+    let bridge_ip = match &args.bridge_ip {
+        Some(ip) => ip.clone(),
+        None => {
+            println!("🔍 Discovering Hue Bridges...");
+            discover_bridge(&stored_retry_policy())
+                .await
+                .context("No bridge found. Pass --bridge-ip or set HUEFLOW_BRIDGE_IP.")?
+        }
+    };
 
-    let mut config = None;
-    for attempt in 1..=10 {
-        match HueClient::register_user(&bridge_ip, "hueflow#device").await {
-            Ok(cfg) => {
-                config = Some(cfg);
-                break;
-            }
-            Err(hue_flow_core::api::error::HueError::LinkButtonNotPressed) => {
-                if attempt < 10 {
-                    println!(
-                        "   Link button not pressed. Retrying in 5 seconds... ({}/10)",
-                        attempt
-                    );
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+    println!("📡 Using bridge at: {}", bridge_ip);
+    println!(
+        "⚠️  Press the LINK button on your Hue Bridge within {} seconds.",
+        args.link_timeout
+    );
+
+    continue_registration(&bridge_ip, args).await
+}
+
+/// Waits for the link button with a live countdown, offering to retry when
+/// the window runs out.
+async fn register_interactive(bridge_ip: &str) -> Result<HueConfig> {
+    use std::io::Write;
+
+    loop {
+        let mut shown = None;
+        let registered = HueClient::register_when_linked(
+            bridge_ip,
+            "hueflow#device",
+            LINK_WINDOW,
+            &stored_retry_policy(),
+            |remaining| {
+                let secs = remaining.as_secs() + 1;
+                if shown != Some(secs) {
+                    shown = Some(secs);
+                    print!("\r   ⏳ Waiting for the link button... {:>2}s ", secs);
+                    std::io::stdout().flush().ok();
                 }
-            }
-            Err(e) => return Err(e.into()),
+            },
+        )
+        .await?;
+        println!();
+
+        if let Some(config) = registered {
+            return Ok(config);
+        }
+        if !Confirm::new("Link button was not pressed. Try again?")
+            .with_default(true)
+            .prompt()?
+        {
+            anyhow::bail!("Registration cancelled.");
         }
     }
+}
+
+async fn continue_registration(bridge_ip: &str, args: &SetupArgs) -> Result<()> {
+    println!("🔐 Registering with bridge...");
 
-    let mut config = config.context("Failed to register after 10 attempts. Please try again.")?;
+    let mut config = if args.non_interactive {
+        let timeout = Duration::from_secs(args.link_timeout);
+        HueClient::register_when_linked(
+            bridge_ip,
+            "hueflow#device",
+            timeout,
+            &stored_retry_policy(),
+            |_| {},
+        )
+        .await?
+        .with_context(|| {
+            format!(
+                "Link button was not pressed within {} seconds.",
+                args.link_timeout
+            )
+        })?
+    } else {
+        register_interactive(bridge_ip).await?
+    };
     println!("✅ Registered successfully!");
     println!("   Username: {}", config.username);
+    config.retry = stored_retry_policy();
+
+    // The application_id (DTLS PSK Identity) comes with registration; retry if it did not
+    ensure_application_id(&mut config).await?;
+    println!("   Application ID: {}", config.application_id);
 
-    // Fetch the application_id (required for DTLS PSK Identity)
-    println!("🔑 Fetching application ID...");
-    let app_id = HueClient::get_application_id(&config.bridge_ip, &config.username).await?;
-    config.application_id = app_id.clone();
-    println!("   Application ID: {}", app_id);
+    // Refuse early on bridges that cannot do v2 entertainment streaming
+    println!("🧾 Checking bridge firmware...");
+    let bridge = HueClient::get_bridge_config(&config.bridge_ip).await?;
+    config.bridge_model = bridge.model_id.clone();
+    config.swversion = bridge.swversion.clone();
+    config.bridge_id = bridge.bridge_id.clone();
+    println!(
+        "   Model: {} / Firmware: {}",
+        bridge.model_id, bridge.swversion
+    );
+    if let Err(e) = bridge.check_compatibility() {
+        anyhow::bail!("{}", e);
+    }
 
     println!();
     println!("🎭 Loading entertainment groups...");
@@ -213,311 +1283,2446 @@ async fn continue_registration(bridge_ip: &str) -> Result<()> {
     let groups = get_entertainment_groups(&config).await?;
 
     if groups.is_empty() {
-        println!("❌ No entertainment groups found!");
-        println!("   Please create an Entertainment Area in the Hue app first.");
-        return Ok(());
+        anyhow::bail!(
+            "No entertainment groups found. Please create an Entertainment Area in the Hue app first."
+        );
     }
 
-    let group_names: Vec<String> = groups
-        .iter()
-        .map(|g| format!("{} ({} channels)", g.name, g.lights.len()))
-        .collect();
-    let selection = Select::new("Select an entertainment group:", group_names).prompt()?;
+    let selected_index = if let Some(wanted) = &args.group {
+        groups
+            .iter()
+            .position(|g| &g.id == wanted || &g.name == wanted)
+            .with_context(|| {
+                let available: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+                format!(
+                    "Entertainment group '{}' not found. Available: {}",
+                    wanted,
+                    available.join(", ")
+                )
+            })?
+    } else if args.non_interactive {
+        if groups.len() > 1 {
+            let available: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+            anyhow::bail!(
+                "Multiple entertainment groups found, pass --group to choose one of: {}",
+                available.join(", ")
+            );
+        }
+        0
+    } else {
+        let group_names: Vec<String> = groups
+            .iter()
+            .map(|g| format!("{} ({} channels)", g.name, g.lights.len()))
+            .collect();
+        let selection = Select::new("Select an entertainment group:", group_names).prompt()?;
 
-    let selected_index = groups
-        .iter()
-        .position(|g| selection.starts_with(&g.name))
-        .unwrap();
+        groups
+            .iter()
+            .position(|g| selection.starts_with(&g.name))
+            .unwrap()
+    };
     let selected_group = &groups[selected_index];
 
     config.entertainment_group_id = selected_group.id.clone();
+    // Keep all areas so 'hueflow group use' can switch without another setup
+    config.groups = groups.iter().map(GroupEntry::from).collect();
+    choose_audio_input(&mut config, args)?;
+    // Clients keep working when setup runs again
+    if let Ok(Some(previous)) = config::load_file(&config::config_path()) {
+        config.control_auth = previous.control_auth;
+    }
+    if !config.control_auth.requires_token() {
+        config
+            .control_auth
+            .create_token("default", Scope::Control)?;
+    }
     save_config(&config)?;
 
     println!();
-    println!("✅ Setup complete! Configuration saved to {}", CONFIG_FILE);
+    println!(
+        "✅ Setup complete! Configuration saved to {}",
+        config::config_path().display()
+    );
     println!(
         "   Selected group: {} with {} channels",
         selected_group.name,
         selected_group.lights.len()
     );
+    if let Some(token) = config.control_auth.control_token() {
+        println!("   Control API token: {}", token);
+    }
     println!();
+    if !args.non_interactive
+        && Confirm::new("Tune the effect to your room now?")
+            .with_default(true)
+            .prompt()?
+    {
+        run_tune(None, TUNE_SECONDS, TUNED_PRESET).await?;
+        println!();
+    }
     println!("🚀 Run 'hueflow' or 'hueflow run' to start the entertainment stream!");
 
     Ok(())
 }
 
-async fn run_stream(effect_name: &str) -> Result<()> {
-    let config = load_config().context("No configuration found. Run 'hueflow setup' first.")?;
-
-    // Validate that application_id is set
-    if config.application_id.is_empty() {
-        println!("⚠️  Application ID not set. Run 'hueflow setup' to reconfigure.");
+/// Stores the preferred audio input: from the setup flags, or (with the
+/// `capture` feature) picked interactively. Unset means the default input.
+fn choose_audio_input(config: &mut HueConfig, args: &SetupArgs) -> Result<()> {
+    if args.audio_device.is_some() || args.audio_format.is_some() {
+        config.audio_device = args.audio_device.clone();
+        config.audio_sample_format = args.audio_format.clone();
         return Ok(());
     }
+    #[cfg(feature = "capture")]
+    if !args.non_interactive {
+        use hue_flow_core::audio_input::list_input_devices;
 
-    println!("🎭 Loading entertainment group...");
-    let groups = get_entertainment_groups(&config).await?;
-    let group = groups
-        .iter()
-        .find(|g| g.id == config.entertainment_group_id)
-        .context("Configured entertainment group not found")?;
-
-    println!(
-        "   Group: {} with {} channels",
-        group.name,
-        group.lights.len()
-    );
-    println!("   Entertainment Config ID (UUID): {}", group.id);
-    println!(
-        "   Application ID (PSK Identity): {}",
-        config.application_id
-    );
+        let devices = list_input_devices();
+        if devices.is_empty() {
+            return Ok(());
+        }
+        const SYSTEM_DEFAULT: &str = "System default input";
+        let mut options = vec![SYSTEM_DEFAULT.to_string()];
+        options.extend(devices.iter().map(|d| d.name.clone()));
+        let selection = Select::new("🎤 Select the audio input device:", options).prompt()?;
+        let Some(device) = devices.iter().find(|d| d.name == selection) else {
+            config.audio_device = None;
+            config.audio_sample_format = None;
+            return Ok(());
+        };
 
-    // Debug Channel Info
-    println!("   Channels:");
-    for light in &group.lights {
-        println!(
-            "     - Channel {}: at ({:.2}, {:.2}, {:.2})",
-            light.channel_id, light.x, light.y, light.z
-        );
+        const DEVICE_DEFAULT: &str = "Device default";
+        let mut formats = vec![DEVICE_DEFAULT.to_string()];
+        formats.extend(device.sample_formats());
+        let format = Select::new("Sample format:", formats).prompt()?;
+        config.audio_device = Some(device.name.clone());
+        config.audio_sample_format = (format != DEVICE_DEFAULT).then_some(format);
     }
+    Ok(())
+}
 
-    println!("📡 Activating stream mode (v2 API)...");
-    set_stream_active(&config, &group.id, true).await?;
-
-    println!("🔒 Establishing DTLS connection...");
-    // Use application_id as PSK Identity (NOT username!)
-    let streamer = HueStreamer::connect(
-        &config.bridge_ip,
-        &config.application_id,
-        &config.client_key,
-    )
-    .context("Failed to establish DTLS connection")?;
-
-    println!("✅ Connected!");
-    println!();
-    println!("🎨 Starting {} effect...", effect_name);
-    println!("   Press Ctrl+C to stop");
-    println!();
-
-    // Create channel for light states
-    let (tx, rx) = mpsc::channel::<Vec<LightState>>(16);
-
-    // Clone IDs for the streaming task
-    let stream_area_id = group.id.clone();
-
-    // Spawn streaming task
-    let _stream_handle = tokio::task::spawn_blocking(move || {
-        let rt = tokio::runtime::Handle::current();
-        rt.block_on(run_stream_loop(streamer, rx, &stream_area_id));
-    });
-
-    // Create effect
-    let mut effect: Box<dyn LightEffect> = match effect_name {
-        "pulse" => Box::new(PulseEffect::new((255, 100, 50))),
-        _ => Box::new(MultiBandEffect::new()),
+/// `--effect` value that plays random effects from the config's `shuffle`.
+const SHUFFLE: &str = "shuffle";
+
+/// Picks the effect setup for `run`: an explicit `--effect`, otherwise the
+/// requested or active preset, otherwise the default multiband setup.
+fn select_preset(args: &RunArgs, config: &HueConfig) -> Result<Preset> {
+    let mut selected = match (
+        args.effect.as_ref().filter(|effect| *effect != SHUFFLE),
+        args.preset.as_ref().or(config.preset.as_ref()),
+    ) {
+        (Some(effect), _) => Preset {
+            effect: effect.clone(),
+            ..Default::default()
+        },
+        (None, Some(name)) => preset::load(&preset::presets_dir(), name)?,
+        (None, None) => Preset::default(),
     };
+    selected.ct_only |= args.ct_only || config.ct_only;
+    Ok(selected)
+}
 
-    // Convert LightNodes to our format (using channel_id!)
-    let nodes = group.lights.clone();
-
-    // Simulation loop with mock audio data
-    let mut tick_interval = interval(Duration::from_millis(50)); // 20 FPS
-    let mut phase: f32 = 0.0;
-
-    loop {
-        tick_interval.tick().await;
-
-        // Generate mock audio spectrum
-        phase += 0.1;
-        let mock_audio = hue_flow_core::audio_interface::AudioSpectrum {
-            bass: (phase.sin() * 0.5 + 0.5).abs(),
-            mids: ((phase * 1.5).sin() * 0.5 + 0.5).abs(),
-            highs: ((phase * 2.0).sin() * 0.5 + 0.5).abs(),
-            energy: 1.0,
-        };
-
-        // Update effect
-        let colors = effect.update(&mock_audio, &nodes);
-
-        // Convert to LightState - NOTE: id is now channel_id!
-        let states: Vec<LightState> = colors
-            .into_iter()
-            .map(|(channel_id, (r, g, b))| LightState {
-                id: channel_id,
-                r,
-                g,
-                b,
-            })
-            .collect();
+/// The configured playlist, unless an effect or preset was asked for.
+fn selected_playlist(args: &RunArgs, config: &HueConfig) -> Option<EffectPlaylist> {
+    if args.show.is_some() {
+        return None;
+    }
+    if args.effect.as_deref() == Some(SHUFFLE) {
+        return Some(config.shuffle.playlist());
+    }
+    if args.effect.is_some() || args.preset.is_some() {
+        return None;
+    }
+    config.playlist.clone()
+}
 
-        // Debug output
-        if phase.fract() < 0.1 && !states.is_empty() {
-            let first = &states[0];
-            println!(
-                "Values: Bass={:.2} -> Channel {}: RGB({},{},{})",
-                mock_audio.bass, first.id, first.r, first.g, first.b
-            );
+/// Playlist, location, frame rate, circadian curve, drop boost, theater
+/// mode, game cues, ambient light, motion pause, hooks and extra sinks from
+/// the config.
+async fn config_extras(
+    mut builder: HueFlowBuilder,
+    args: &RunArgs,
+    config: &HueConfig,
+) -> HueFlowBuilder {
+    if let Some(playlist) = selected_playlist(args, config) {
+        if args.effect.as_deref() == Some(SHUFFLE) {
+            let shuffle = &config.shuffle;
+            match shuffle.tracks {
+                Some(tracks) => println!(
+                    "   🔀 Shuffle: a new effect every {} min or {} tracks",
+                    shuffle.minutes, tracks
+                ),
+                None => println!("   🔀 Shuffle: a new effect every {} min", shuffle.minutes),
+            }
         }
-
-        if tx.send(states).await.is_err() {
-            break;
+        builder = builder.playlist(playlist);
+    }
+    if args.effect.is_none() && args.show.is_none() {
+        if let Some(name) = args.preset.as_ref().or(config.preset.as_ref()) {
+            builder = builder.preset_name(name.clone());
         }
     }
+    if let Some(location) = config.location {
+        builder = builder.location(location);
+    }
+    let preset_fps = select_preset(args, config).ok().and_then(|p| p.fps);
+    if let Some(fps) = args.fps.or(preset_fps).or(config.fps) {
+        println!("   🎞️  Frame rate: {} FPS", fps);
+        builder = builder.fps(fps);
+    }
+    if let Some(path) = &args.record_show {
+        println!("   ⏺️  Recording show to {}", path.display());
+        builder = builder.record_show(path);
+    }
+    if args.dedup_frames {
+        println!("   ♻️  Repeated frames are skipped (keep-alive only)");
+        builder = builder.dedup_frames(true);
+    }
+    if let Some(ms) = args.jitter_buffer {
+        println!("   ⏱️  Jitter buffer: {} ms", ms);
+        builder = builder.jitter_buffer(Duration::from_millis(ms));
+    }
+    if args.room_mode {
+        if config.rooms.is_empty() {
+            println!("   ⚠️  Room mode: no rooms in the config, lights stay individual");
+        } else {
+            let names: Vec<&str> = config.rooms.keys().map(String::as_str).collect();
+            println!("   🏠 Room mode: {}", names.join(", "));
+            builder = builder.rooms(config.rooms.clone());
+        }
+    }
+    let circadian = match args.circadian {
+        Some(Toggle::On) => Some(config.circadian.clone().unwrap_or_default()),
+        Some(Toggle::Off) => None,
+        None => config.circadian.clone(),
+    };
+    if let Some(circadian) = circadian {
+        println!("   🌙 Circadian mode: warmer and dimmer late at night");
+        builder = builder.circadian(circadian);
+    }
+    let drop_boost = match &config.drop_boost {
+        Some(boost) => Some(boost.clone()),
+        None => args.drop_boost.then(DropBoostConfig::default),
+    };
+    if let Some(boost) = drop_boost {
+        println!(
+            "   💥 Drop boost: {:.1}x for {} s",
+            boost.brightness,
+            boost.duration_ms / 1000
+        );
+        builder = builder.drop_boost(boost);
+    }
+    let theater = match &config.theater {
+        Some(theater) => Some(theater.clone()),
+        None => args.theater.then(TheaterConfig::default),
+    };
+    if let Some(theater) = theater {
+        println!(
+            "   🎬 Theater mode: {} ms smoothing, rear lights at {:.0}%",
+            theater.smoothing_ms,
+            theater.rear_brightness * 100.0
+        );
+        builder = builder.theater(theater);
+    }
+    if let Some(every) = args.log_frames.filter(|every| *every > 0) {
+        println!("   📝 Logging channel values every {} frames", every);
+        builder = builder.log_frames(every);
+    }
+    if args.debug_dtls {
+        println!("   🔬 Tracing the DTLS handshake");
+        builder = builder.debug_dtls(true);
+    }
+    let game = match &config.game {
+        Some(game) => Some(game.clone()),
+        None => args.game.then(GameConfig::default),
+    };
+    if let Some(game) = game {
+        println!("   🎮 Game events: listening on udp://{}", game.listen);
+        builder = builder.game(game);
+    }
+    if let Some(ambient) = &config.ambient {
+        println!(
+            "   🔆 Ambient light: brightness {:.0}-{:.0}% between {} and {} lux",
+            ambient.min_brightness * 100.0,
+            ambient.max_brightness * 100.0,
+            ambient.dark_lux,
+            ambient.bright_lux
+        );
+        builder = builder.ambient(ambient.clone());
+    }
+    if let Some(motion) = &config.motion {
+        println!(
+            "   🚶 Motion: pausing after {} min without motion",
+            motion.empty_minutes
+        );
+        builder = builder.motion(motion.clone());
+    }
+    if !config.app_profiles.is_empty() {
+        let apps: Vec<&str> = config.app_profiles.iter().map(|r| r.app.as_str()).collect();
+        println!("   🎧 App profiles: {}", apps.join(", "));
+        for rule in &config.app_profiles {
+            match (&rule.preset, &rule.effect) {
+                (None, None) => println!(
+                    "   ⚠️  App profile '{}' names no preset or effect",
+                    rule.app
+                ),
+                (None, Some(effect)) if effect_info(effect).is_none() => println!(
+                    "   ⚠️  App profile '{}': unknown effect '{}'",
+                    rule.app, effect
+                ),
+                _ => {}
+            }
+        }
+        builder = builder.app_profiles(config.app_profiles.clone());
+    }
+    if !config.hooks.is_empty() {
+        println!("   🪝 Hooks: {} configured", config.hooks.len());
+        builder = builder.hooks(config.hooks.clone());
+    }
+    if let Some(openrgb) = &config.openrgb {
+        match OpenRgbSink::connect(openrgb.clone()) {
+            Ok(sink) => {
+                let names: Vec<&str> = sink.devices().iter().map(|d| d.name.as_str()).collect();
+                println!("   🖥️  OpenRGB: mirroring to {}", names.join(", "));
+                builder = builder.mirror(sink);
+            }
+            Err(e) => eprintln!("⚠️  OpenRGB unavailable: {:#}", e),
+        }
+    }
+    if !config.lifx.is_empty() {
+        match LifxSink::new(config.lifx.clone()) {
+            Ok(sink) => {
+                println!("   💡 LIFX: {} bulb(s)", config.lifx.len());
+                builder = builder.mirror(sink);
+            }
+            Err(e) => eprintln!("⚠️  LIFX unavailable: {:#}", e),
+        }
+    }
+    if let Some(dmx) = &config.dmx_out {
+        match DmxSink::new(dmx.clone()) {
+            Ok(sink) => {
+                let protocol = match dmx.protocol {
+                    DmxProtocol::Artnet => "Art-Net",
+                    DmxProtocol::Sacn => "sACN",
+                };
+                println!("   🎛️  DMX: {} to {}", protocol, sink.destination());
+                builder = builder.mirror(sink);
+            }
+            Err(e) => eprintln!("⚠️  DMX output unavailable: {:#}", e),
+        }
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(zigbee) = &config.zigbee2mqtt {
+        println!(
+            "   🐝 zigbee2mqtt: {} bulb(s), at most {} updates/s",
+            zigbee.lights.len(),
+            zigbee.max_rate
+        );
+        builder = builder.mirror(Zigbee2MqttSink::new(zigbee.clone()));
+    }
+    for nanoleaf in &config.nanoleaf {
+        let sink = match nanoleaf::enable_streaming(nanoleaf).await {
+            Ok(()) => NanoleafSink::new(nanoleaf),
+            Err(e) => Err(e),
+        };
+        match sink {
+            Ok(sink) => {
+                println!(
+                    "   🔷 Nanoleaf {}: {} panel(s)",
+                    nanoleaf.ip,
+                    nanoleaf.panels.len()
+                );
+                builder = builder.mirror(sink);
+            }
+            Err(e) => eprintln!("⚠️  Nanoleaf {} unavailable: {:#}", nanoleaf.ip, e),
+        }
+    }
+    builder
+}
+
+/// Terminal hotkeys: 'b' + Enter blacks out, 'r' + Enter resumes.
+fn spawn_hotkeys(commands: mpsc::Sender<ControlCommand>) {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let fade_ms = DEFAULT_FADE.as_millis() as u64;
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = match line.trim() {
+                "b" => ControlCommand::Blackout { fade_ms },
+                "r" => ControlCommand::Resume { fade_ms },
+                _ => continue,
+            };
+            if commands.send(command).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+async fn run_stream(args: &RunArgs) -> Result<()> {
+    if let Some(format) = args.dry_run {
+        return run_dry_run(args, format).await;
+    }
+    match args.sink {
+        Sink::Sim => return run_simulator(args).await,
+        Sink::Zigbee => return run_zigbee(args).await,
+        Sink::Hue => {}
+    }
+
+    let mut config = if args.offline {
+        load_config(&args.conn)?
+    } else {
+        connect_config(&args.conn).await?
+    };
+    ensure_application_id(&mut config).await?;
+
+    let active_preset = select_preset(args, &config)?;
+    let effect = run_effect(args, &active_preset)?;
+    if let Some(group_id) = &active_preset.entertainment_group_id {
+        config.entertainment_group_id = group_id.clone();
+    }
+
+    println!("🎭 Loading entertainment group...");
+    let wanted = args
+        .group
+        .as_ref()
+        .unwrap_or(&config.entertainment_group_id);
+    let cache_path = group_cache::cache_path();
+    let mut groups =
+        group_cache::cached_groups(&config, &cache_path, CACHE_TTL, args.offline).await?;
+    if !args.offline && !groups.iter().any(|g| g.matches(wanted)) {
+        // Created since the cache was written
+        groups = group_cache::refresh(&config, &cache_path).await?;
+    }
+    let group = groups
+        .into_iter()
+        .find(|g| g.matches(wanted))
+        .with_context(|| format!("Entertainment group '{}' not found", wanted))?;
+
+    println!(
+        "   Group: {} with {} channels",
+        group.name,
+        group.lights.len()
+    );
+    println!("   Entertainment Config ID (UUID): {}", group.id);
+    println!(
+        "   Application ID (PSK Identity): {}",
+        config.application_id
+    );
+
+    // Debug Channel Info
+    println!("   Channels:");
+    for light in &group.lights {
+        println!(
+            "     - Channel {}: at ({:.2}, {:.2}, {:.2})",
+            light.channel_id, light.x, light.y, light.z
+        );
+    }
+
+    let excluded: HashSet<u8> = config
+        .excluded_channels
+        .iter()
+        .chain(&args.exclude_channel)
+        .copied()
+        .collect();
+    if !excluded.is_empty() {
+        let mut listed: Vec<u8> = excluded.iter().copied().collect();
+        listed.sort();
+        println!("   Excluded channels: {:?}", listed);
+    }
+
+    let smoothing = smoothing_hints(&config, args, group.lights.iter().map(|l| l.channel_id));
+    let color = color_pipeline(&config, args);
+    let audio = audio_source(args, &config)?;
+    let safe_mode = args.safe || config.safe_mode;
+    if safe_mode {
+        println!("   🛡️  Safe mode: flashing limited, strobe effects disabled");
+    }
+    let playlist = selected_playlist(args, &config);
+    if !config.companion.is_empty() {
+        let preset = playlist
+            .as_ref()
+            .and_then(|p| p.entries.first())
+            .map_or(&active_preset, |entry| &entry.preset);
+        println!(
+            "   🎉 Party mode: {} lights and {} rooms follow the palette",
+            config.companion.lights.len(),
+            config.companion.groups.len()
+        );
+        companion::spawn_companion(
+            config.clone(),
+            config.companion.clone(),
+            companion::palette_for(preset),
+        );
+    }
+    let effect_name = match (&playlist, &args.show) {
+        (Some(p), _) => format!("playlist of {}", p.entries.len()),
+        (None, Some(show)) => format!("{} show", show.display()),
+        (None, None) => active_preset.effect.clone(),
+    };
+    let group_id = group.id.clone();
+    let access = config.control_auth.clone();
+    let mut frames: u64 = 0;
+    let mut recovering = false;
+    let mut standby = false;
+    let first_effect = playlist
+        .as_ref()
+        .and_then(|p| p.entries.first())
+        .map_or(&active_preset.effect, |entry| &entry.preset.effect);
+    let session = Arc::new(Mutex::new(SessionRecorder::new(first_effect.clone())));
+    let session_events = session.clone();
+    let flow = config_extras(HueFlow::builder(), args, &config)
+        .await
+        .bridge(config)
+        .group(group)
+        .effect(effect)
+        .audio_source(audio)
+        .excluded_channels(excluded)
+        .smoothing(smoothing)
+        .color_pipeline(color)
+        .safe_mode(safe_mode)
+        .on_event(move |event| match event {
+            FlowEvent::StreamActivated { .. } => println!("🔒 Establishing DTLS connection..."),
+            FlowEvent::Connected => {
+                println!("✅ Connected!");
+                println!();
+                println!("🎨 Starting {} effect...", effect_name);
+                println!(
+                    "   Press Ctrl+C to stop, 'b' + Enter to black out, 'r' + Enter to resume"
+                );
+                println!();
+            }
+            FlowEvent::Frame {
+                audio,
+                frame,
+                metrics,
+            } => {
+                frames += 1;
+                if !frames.is_multiple_of(10) {
+                    return;
+                }
+                if let Some((id, (r, g, b))) = frame.iter().next() {
+                    println!(
+                        "Values: Bass={:.2} -> Channel {}: RGB({},{},{}) @ {} FPS",
+                        audio.bass,
+                        id,
+                        r,
+                        g,
+                        b,
+                        metrics.fps()
+                    );
+                }
+            }
+            FlowEvent::PresetLoaded { name, preset } => {
+                println!("🎛️  Switched to preset '{}' ({})", name, preset.effect);
+                if let Ok(mut session) = session_events.lock() {
+                    session.effect(&preset.effect);
+                }
+                if preset
+                    .entertainment_group_id
+                    .as_ref()
+                    .is_some_and(|id| *id != group_id)
+                {
+                    println!("   Entertainment area change applies on next start");
+                }
+            }
+            FlowEvent::PresetFailed { name, error } => {
+                eprintln!("⚠️  Cannot load preset '{}': {}", name, error)
+            }
+            FlowEvent::Blackout => println!("⬛ Blackout"),
+            FlowEvent::Resumed => println!("▶️  Resume"),
+            FlowEvent::SafeMode {
+                enabled,
+                replaced_strobe,
+            } => {
+                println!("🛡️  Safe mode {}", if enabled { "on" } else { "off" });
+                if replaced_strobe {
+                    println!("   Strobe effect replaced by multiband");
+                }
+            }
+            FlowEvent::PlaylistChanged { entry, .. } => {
+                println!(
+                    "🎶 Playlist: {} for {} s",
+                    entry.preset.effect, entry.duration_secs
+                );
+                if let Ok(mut session) = session_events.lock() {
+                    session.effect(&entry.preset.effect);
+                }
+            }
+            FlowEvent::FpsChanged { fps } => println!("🎞️  Frame rate: {} FPS", fps),
+            FlowEvent::Drop => println!("💥 Drop!"),
+            FlowEvent::RoomEmpty => println!("💤 Room is empty, pausing stream"),
+            FlowEvent::RoomOccupied => println!("🚶 Someone is back, resuming stream"),
+            FlowEvent::Standby { enabled } => {
+                standby = enabled;
+                if enabled {
+                    println!("⏸️  Standby, releasing the entertainment area");
+                } else {
+                    println!("▶️  Streaming again");
+                }
+            }
+            FlowEvent::StreamState { state } => match state {
+                // Released on purpose, nothing to recover
+                StreamState::Recovering if standby => {}
+                StreamState::Recovering => {
+                    recovering = true;
+                    println!("🔄 Stream interrupted, recovering...");
+                }
+                StreamState::Streaming if recovering => {
+                    recovering = false;
+                    println!("✅ Stream recovered");
+                }
+                _ => {}
+            },
+        })
+        .build()?;
+
+    spawn_control(&flow, args, &access)?;
+    if args.trace_timing {
+        spawn_timing_report(&flow);
+    }
+    if let Some(seconds) = args.duration {
+        spawn_stop(&flow, Duration::from_secs(seconds));
+    }
+
+    println!("📡 Activating stream mode (v2 API)...");
+    let metrics = flow.metrics();
+    let result = flow.run().await;
+    if let Ok(session) = session.lock() {
+        let summary = session.finish(
+            metrics.frames_sent(),
+            metrics.write_errors(),
+            result.as_ref().err().map(|e| format!("{:#}", e)),
+        );
+        if let Err(e) = history::record(&history::history_path(), &summary) {
+            tracing::warn!("Cannot record the session: {}", e);
+        }
+    }
+    result
+}
+
+/// Logs stage latencies every 5 seconds while the flow runs.
+fn spawn_timing_report(flow: &HueFlow) {
+    let timings = flow.timings();
+    tokio::spawn(async move {
+        let mut report = interval(Duration::from_secs(5));
+        report.tick().await;
+        loop {
+            report.tick().await;
+            tracing::info!(target: "hueflow::timing", "{}", timings.summary());
+        }
+    });
+}
+
+/// Control API (preset switching etc.) and terminal hotkeys.
+fn spawn_control(flow: &HueFlow, args: &RunArgs, access: &ControlAuth) -> Result<()> {
+    let control_tx = flow.control();
+    spawn_hotkeys(control_tx.clone());
+    let control_addr: std::net::SocketAddr = args
+        .control_addr
+        .parse()
+        .context("Invalid control API address")?;
+    warn_if_open(control_addr, access);
+    let http_tx = control_tx.clone();
+    let frames = flow.frames();
+    let http_access = access.clone();
+    tokio::spawn(async move {
+        if let Err(e) = control::http::serve(control_addr, http_tx, frames, http_access).await {
+            eprintln!("⚠️  Control API unavailable on {}: {}", control_addr, e);
+        }
+    });
+    let socket_path = args
+        .control_socket
+        .clone()
+        .unwrap_or_else(socket::default_socket_path);
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = &args.grpc_addr {
+        let grpc_addr: std::net::SocketAddr = addr.parse().context("Invalid gRPC address")?;
+        warn_if_open(grpc_addr, access);
+        let grpc_tx = control_tx.clone();
+        let states = flow.stream_states();
+        let grpc_access = access.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::grpc::serve(grpc_addr, grpc_tx, states, grpc_access).await {
+                eprintln!("⚠️  gRPC service unavailable on {}: {:#}", grpc_addr, e);
+            }
+        });
+    }
+    let metrics = flow.metrics();
+    tokio::spawn(async move {
+        if let Err(e) = socket::serve(&socket_path, control_tx, metrics).await {
+            eprintln!(
+                "⚠️  Control socket unavailable at {}: {}",
+                socket_path.display(),
+                e
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Warns when anyone who can reach `addr` could take over the lights.
+fn warn_if_open(addr: std::net::SocketAddr, access: &ControlAuth) {
+    if !addr.ip().is_loopback() && !access.requires_token() {
+        eprintln!(
+            "⚠️  {} accepts commands from anyone on the network; add a token with 'hueflow token create'",
+            addr
+        );
+    }
+}
+
+/// Transition hints per channel: `--smooth-ms` for every channel, overridden
+/// by `channel_smoothing_ms` from the config.
+fn smoothing_hints(
+    config: &HueConfig,
+    args: &RunArgs,
+    channels: impl Iterator<Item = u8>,
+) -> HashMap<u8, Duration> {
+    let mut hints: HashMap<u8, Duration> = match args.smooth_ms {
+        Some(ms) => channels.map(|c| (c, Duration::from_millis(ms))).collect(),
+        None => HashMap::new(),
+    };
+    for (&channel, &ms) in &config.channel_smoothing_ms {
+        hints.insert(channel, Duration::from_millis(ms));
+    }
+    hints
+}
+
+/// The effect `run` starts with: the `--show` player, or `preset`'s effect.
+fn run_effect(args: &RunArgs, preset: &Preset) -> Result<Box<dyn LightEffect>> {
+    let Some(path) = &args.show else {
+        return Ok(preset.build_effect()?);
+    };
+    let show = Show::load(path).with_context(|| format!("Cannot load show {}", path.display()))?;
+    let start = match &args.show_start {
+        Some(text) => parse_show_start(text)
+            .with_context(|| format!("Invalid show start '{}', expected HH:MM:SS", text))?,
+        None => Duration::ZERO,
+    };
+    println!(
+        "   🎬 Show {} ({} s)",
+        path.display(),
+        show.duration().as_secs()
+    );
+    let clock = match args.timecode.unwrap_or_default() {
+        TimecodeSource::Free => TimecodeClock::running_from(start, timing::now()),
+        TimecodeSource::Mtc => {
+            let device = args
+                .timecode_device
+                .as_ref()
+                .context("MTC needs --timecode-device, the MIDI device to read")?;
+            println!("   🕰️  Following MIDI timecode from {}", device);
+            let clock = TimecodeClock::default();
+            timecode::spawn_mtc_reader(device.into(), clock.clone())
+                .with_context(|| format!("Cannot read MIDI timecode from {}", device))?;
+            clock
+        }
+        #[cfg(feature = "capture")]
+        TimecodeSource::Ltc => {
+            use hue_flow_core::audio_input::CaptureOptions;
+            let device = args.timecode_device.clone();
+            println!(
+                "   🕰️  Following LTC from {}",
+                device.as_deref().unwrap_or("the default input")
+            );
+            let clock = TimecodeClock::default();
+            let options = CaptureOptions {
+                device,
+                ..Default::default()
+            };
+            timecode::spawn_ltc_reader(options, clock.clone())?;
+            clock
+        }
+        #[cfg(not(feature = "capture"))]
+        TimecodeSource::Ltc => {
+            anyhow::bail!(
+                "This build has no audio capture for LTC; rebuild with `--features capture`"
+            )
+        }
+    };
+    Ok(Box::new(ShowPlayer::new(show, clock, start)))
+}
+
+/// `HH:MM:SS` as time since midnight.
+fn parse_show_start(text: &str) -> Option<Duration> {
+    let parts: Vec<u64> = text
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hours, minutes, seconds] = parts[..] else {
+        return None;
+    };
+    (minutes < 60 && seconds < 60)
+        .then(|| Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+}
+
+/// The audio source selected with `--audio`.
+/// Input filters come from the config unless overridden on the command line.
+#[cfg_attr(not(feature = "capture"), allow(unused_variables))]
+fn audio_source(args: &RunArgs, config: &HueConfig) -> Result<Box<dyn AudioSource>> {
+    match args.audio {
+        Audio::Synthetic => Ok(Box::new(SyntheticAudio::default())),
+        #[cfg(feature = "capture")]
+        Audio::Mic => {
+            use hue_flow_core::audio_input::{AudioInput, CaptureOptions};
+            let device = args.audio_device.clone().or(config.audio_device.clone());
+            println!(
+                "🎤 Capturing audio from {}",
+                device.as_deref().unwrap_or("the default input")
+            );
+            Ok(Box::new(AudioInput::start(CaptureOptions {
+                device,
+                sample_format: config.audio_sample_format.clone(),
+                hop_size: args.hop_size,
+                hum_hz: args.hum_filter.or(config.hum_filter_hz),
+                noise_gate_db: args.noise_gate_db.or(config.noise_gate_db),
+                analysis_thread: args.analysis_thread,
+            })?))
+        }
+        #[cfg(not(feature = "capture"))]
+        Audio::Mic => {
+            anyhow::bail!("This build has no audio capture; rebuild with `--features capture`")
+        }
+    }
+}
+
+/// The configured color correction with `--saturation`/`--contrast` applied.
+fn color_pipeline(config: &HueConfig, args: &RunArgs) -> ColorPipeline {
+    let mut pipeline = config.color.clone();
+    if let Some(saturation) = args.saturation {
+        pipeline.saturation = saturation;
+    }
+    if let Some(contrast) = args.contrast {
+        pipeline.contrast = contrast;
+    }
+    pipeline
+}
+
+/// Renders the effect into a virtual room in the terminal; no bridge needed.
+async fn run_simulator(args: &RunArgs) -> Result<()> {
+    let config = config::load_file(&config::config_path())?.unwrap_or_default();
+    let nodes = SimulatorSink::virtual_room(args.sim_lights);
+    run_without_bridge(args, &config, nodes.clone(), SimulatorSink::stdout(nodes)).await
+}
+
+/// Publishes the effect to the bulbs of a zigbee2mqtt coordinator; no
+/// bridge needed.
+#[cfg(feature = "mqtt")]
+async fn run_zigbee(args: &RunArgs) -> Result<()> {
+    let mut config = config::load_file(&config::config_path())?.unwrap_or_default();
+    // The bulbs are the output here, not a mirror
+    let zigbee = config
+        .zigbee2mqtt
+        .take()
+        .context("No zigbee2mqtt section in the config (see the README)")?;
+    println!(
+        "🐝 zigbee2mqtt at {}:{}: {} bulb(s), at most {} updates/s",
+        zigbee.host,
+        zigbee.port,
+        zigbee.lights.len(),
+        zigbee.max_rate
+    );
+    let nodes = zigbee.nodes(0);
+    run_without_bridge(args, &config, nodes, Zigbee2MqttSink::new(zigbee)).await
+}
+
+#[cfg(not(feature = "mqtt"))]
+async fn run_zigbee(_args: &RunArgs) -> Result<()> {
+    anyhow::bail!("--sink zigbee needs a build with `--features mqtt`")
+}
+
+/// Runs the effect on `nodes` into `sink` instead of a bridge.
+async fn run_without_bridge(
+    args: &RunArgs,
+    config: &HueConfig,
+    nodes: Vec<LightNode>,
+    sink: impl LightSink + 'static,
+) -> Result<()> {
+    let active_preset = select_preset(args, config)?;
+
+    let excluded: HashSet<u8> = config
+        .excluded_channels
+        .iter()
+        .chain(&args.exclude_channel)
+        .copied()
+        .collect();
+    let smoothing = smoothing_hints(config, args, nodes.iter().map(|n| n.channel_id));
+    let audio = audio_source(args, config)?;
+
+    let flow = config_extras(HueFlow::builder(), args, config)
+        .await
+        .sink(sink)
+        .nodes(nodes)
+        .effect(run_effect(args, &active_preset)?)
+        .audio_source(audio)
+        .excluded_channels(excluded)
+        .smoothing(smoothing)
+        .color_pipeline(color_pipeline(config, args))
+        .safe_mode(args.safe || config.safe_mode)
+        .on_event(|event| {
+            if let FlowEvent::PresetFailed { name, error } = event {
+                eprintln!("⚠️  Cannot load preset '{}': {}", name, error)
+            }
+        })
+        .build()?;
+    spawn_control(&flow, args, &config.control_auth)?;
+    if args.trace_timing {
+        spawn_timing_report(&flow);
+    }
+    if let Some(seconds) = args.duration {
+        spawn_stop(&flow, Duration::from_secs(seconds));
+    }
+
+    flow.run().await
+}
+
+/// Ends the flow after `after`, handing the entertainment area back as on exit.
+/// Runs the pipeline into a [`DryRunSink`], on the cached layout of the
+/// configured group if there is one and a virtual room otherwise.
+async fn run_dry_run(args: &RunArgs, format: DryRun) -> Result<()> {
+    let mut config = config::load_file(&config::config_path())?.unwrap_or_default();
+    let active_preset = select_preset(args, &config)?;
+    if let Some(group_id) = &active_preset.entertainment_group_id {
+        config.entertainment_group_id = group_id.clone();
+    }
+    // Mirrors would light real lamps
+    config.openrgb = None;
+    config.lifx.clear();
+    config.nanoleaf.clear();
+    config.dmx_out = None;
+    config.zigbee2mqtt = None;
+
+    let wanted = args
+        .group
+        .as_ref()
+        .unwrap_or(&config.entertainment_group_id);
+    let cached = group_cache::load(&group_cache::cache_path())
+        .filter(|cache| cache.is_for(&config))
+        .and_then(|cache| cache.groups.into_iter().find(|g| g.matches(wanted)));
+    let (area_id, nodes) = match cached {
+        Some(group) => {
+            println!(
+                "🧪 Dry run on '{}' ({} channels, cached layout)",
+                group.name,
+                group.lights.len()
+            );
+            (group.id, group.lights)
+        }
+        None => {
+            println!(
+                "🧪 Dry run on a virtual room of {} lights (no cached layout)",
+                args.sim_lights
+            );
+            (
+                DRY_RUN_AREA.to_string(),
+                SimulatorSink::virtual_room(args.sim_lights),
+            )
+        }
+    };
+
+    let format = match format {
+        DryRun::Console => DryRunFormat::Console,
+        DryRun::Jsonl => DryRunFormat::Jsonl,
+    };
+    let sink = match &args.dry_run_out {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Cannot create {}", path.display()))?;
+            DryRunSink::new(area_id, format, Box::new(std::io::LineWriter::new(file)))
+        }
+        None => DryRunSink::stdout(area_id, format),
+    };
+
+    let excluded: HashSet<u8> = config
+        .excluded_channels
+        .iter()
+        .chain(&args.exclude_channel)
+        .copied()
+        .collect();
+    let smoothing = smoothing_hints(&config, args, nodes.iter().map(|n| n.channel_id));
+    let audio = audio_source(args, &config)?;
+
+    let flow = config_extras(HueFlow::builder(), args, &config)
+        .await
+        .sink(sink)
+        .nodes(nodes)
+        .effect(run_effect(args, &active_preset)?)
+        .audio_source(audio)
+        .excluded_channels(excluded)
+        .smoothing(smoothing)
+        .color_pipeline(color_pipeline(&config, args))
+        .safe_mode(args.safe || config.safe_mode)
+        .on_event(|event| {
+            if let FlowEvent::PresetFailed { name, error } = event {
+                eprintln!("⚠️  Cannot load preset '{}': {}", name, error)
+            }
+        })
+        .build()?;
+    spawn_control(&flow, args, &config.control_auth)?;
+    if args.trace_timing {
+        spawn_timing_report(&flow);
+    }
+    if let Some(seconds) = args.duration {
+        spawn_stop(&flow, Duration::from_secs(seconds));
+    }
+
+    flow.run().await
+}
+
+/// Area ID encoded into dry-run frames without a cached group.
+const DRY_RUN_AREA: &str = "00000000-0000-0000-0000-000000000000";
+
+fn spawn_stop(flow: &HueFlow, after: Duration) {
+    let control_tx = flow.control();
+    tokio::spawn(async move {
+        tokio::time::sleep(after).await;
+        let _ = control_tx.send(ControlCommand::Stop).await;
+    });
+}
+
+/// Fastest frame rate of rendered GIFs; viewers slow down shorter delays.
+const GIF_MAX_FPS: f32 = 25.0;
+
+/// A layout file for `render-video`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayoutFile {
+    Group(GroupInfo),
+    Lights(Vec<LightNode>),
+}
+
+fn render_video(show_path: &Path, layout: Option<&Path>, out: &Path, size: u32) -> Result<()> {
+    let show = Show::load(show_path)
+        .with_context(|| format!("Cannot load show {}", show_path.display()))?;
+    let nodes = match layout {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Cannot read {}", path.display()))?;
+            match serde_json::from_str(&content)
+                .with_context(|| format!("Invalid layout file {}", path.display()))?
+            {
+                LayoutFile::Group(group) => group.lights,
+                LayoutFile::Lights(lights) => lights,
+            }
+        }
+        None => cached_layout().unwrap_or_else(|| {
+            let channels = show.frames.iter().flat_map(|f| f.keys()).max();
+            let count = channels.map_or(0, |&c| c as usize + 1);
+            println!("   No cached layout, lights on a circle (pass --layout for the room's)");
+            SimulatorSink::virtual_room(count)
+        }),
+    };
+    // Video encoders want even sizes
+    let painter = RoomPainter::new(&nodes, size.max(16).next_multiple_of(2));
+    println!(
+        "🎬 Rendering {} frames ({}) of {} lights to {}",
+        show.frames.len(),
+        format_duration(show.duration().as_secs()),
+        nodes.len(),
+        out.display()
+    );
+    let is_gif = out
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+    if is_gif {
+        write_gif(&show, &painter, out)?;
+    } else {
+        write_video(&show, &painter, out)?;
+    }
+    println!("✅ Wrote {}", out.display());
+    Ok(())
+}
+
+/// The configured group's layout from the group cache.
+fn cached_layout() -> Option<Vec<LightNode>> {
+    let config = config::load_file(&config::config_path()).ok()??;
+    let cache = group_cache::load(&group_cache::cache_path()).filter(|c| c.is_for(&config))?;
+    let group = cache
+        .groups
+        .into_iter()
+        .find(|g| g.matches(&config.entertainment_group_id))?;
+    println!("   Layout of '{}' (cached)", group.name);
+    Some(group.lights)
+}
+
+fn write_gif(show: &Show, painter: &RoomPainter, out: &Path) -> Result<()> {
+    let size = painter.size() as u16;
+    let file =
+        std::fs::File::create(out).with_context(|| format!("Cannot create {}", out.display()))?;
+    let mut encoder = gif::Encoder::new(std::io::BufWriter::new(file), size, size, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    // Skip frames above the rate GIF viewers keep up with
+    let step = (show.fps / GIF_MAX_FPS).ceil().max(1.0) as usize;
+    let delay = (100.0 * step as f32 / show.fps).round() as u16;
+    for frame in show.frames.iter().step_by(step) {
+        let mut gif_frame = gif::Frame::from_rgb_speed(size, size, &painter.paint(frame), 10);
+        gif_frame.delay = delay;
+        encoder.write_frame(&gif_frame)?;
+    }
+    Ok(())
+}
+
+/// Pipes the pictures to ffmpeg, which picks the format from `out`.
+fn write_video(show: &Show, painter: &RoomPainter, out: &Path) -> Result<()> {
+    let size = painter.size();
+    let mut ffmpeg = std::process::Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-loglevel",
+            "error",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+        ])
+        .args(["-s", &format!("{}x{}", size, size)])
+        .args([
+            "-r",
+            &show.fps.to_string(),
+            "-i",
+            "-",
+            "-pix_fmt",
+            "yuv420p",
+        ])
+        .arg(out)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow::anyhow!("Writing video needs ffmpeg on the PATH; use a .gif file instead")
+            }
+            _ => anyhow::anyhow!("Cannot start ffmpeg: {}", e),
+        })?;
+    use std::io::Write;
+    let mut stdin = ffmpeg.stdin.take().context("No pipe to ffmpeg")?;
+    for frame in &show.frames {
+        if stdin.write_all(&painter.paint(frame)).is_err() {
+            break;
+        }
+    }
+    drop(stdin);
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        anyhow::bail!("ffmpeg failed ({})", status);
+    }
+    Ok(())
+}
+
+/// Lists the last `last` sessions from the history with overall totals.
+fn show_stats(last: usize) -> Result<()> {
+    let path = history::history_path();
+    let sessions = history::load(&path)
+        .with_context(|| format!("Cannot read session history {}", path.display()))?;
+    if sessions.is_empty() {
+        println!("📊 No sessions recorded yet");
+        return Ok(());
+    }
+
+    let shown = &sessions[sessions.len().saturating_sub(last)..];
+    println!(
+        "📊 Last {} of {} sessions (times in UTC):",
+        shown.len(),
+        sessions.len()
+    );
+    for session in shown {
+        println!(
+            "   {}  {:>8}  {:>5.1} FPS  {:>4} errors  {}",
+            session.started_utc(),
+            format_duration(session.duration_secs),
+            session.average_fps,
+            session.write_errors,
+            session.effects.join(", ")
+        );
+        if let Some(error) = &session.error {
+            println!("      ⚠️  Ended with: {}", error);
+        }
+    }
+
+    let seconds: u64 = sessions.iter().map(|s| s.duration_secs).sum();
+    let frames: u64 = sessions.iter().map(|s| s.frames_sent).sum();
+    let errors: u64 = sessions.iter().map(|s| s.write_errors).sum();
+    let failed = sessions.iter().filter(|s| s.error.is_some()).count();
+    println!();
+    println!(
+        "   Total: {} streamed, {:.1} FPS on average, {} write errors, {} failed sessions",
+        format_duration(seconds),
+        if seconds > 0 {
+            frames as f64 / seconds as f64
+        } else {
+            0.0
+        },
+        errors,
+        failed
+    );
+    Ok(())
+}
+
+/// `1h 05m`, `12m 30s` or `45s`.
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        s if s >= 3600 => format!("{}h {:02}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m {:02}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+fn list_effects() {
+    println!("🎨 Effects:");
+    for info in EFFECTS {
+        println!();
+        println!(
+            "  {}{}",
+            info.name,
+            if info.strobe { " (strobe)" } else { "" }
+        );
+        println!("     {}", info.description);
+        for (slot, (r, g, b)) in info.palette {
+            println!(
+                "     palette: {:<6} default {:02x}{:02x}{:02x}",
+                slot, r, g, b
+            );
+        }
+    }
+    println!();
+    println!("Every effect also takes --brightness and --ct-only in 'hueflow preset save'.");
+    println!("Try one with 'hueflow preview <effect>'.");
+}
+
+/// Runs `effect` on synthetic audio for a few seconds.
+async fn run_preview(
+    effect: String,
+    seconds: u64,
+    sink: Sink,
+    sim_lights: usize,
+    conn: ConnectionArgs,
+) -> Result<()> {
+    let info = effect_info(&effect)
+        .with_context(|| format!("Unknown effect '{}'; 'hueflow effects' lists them", effect))?;
+    println!(
+        "👀 Previewing {} for {} s: {}",
+        info.name, seconds, info.description
+    );
+    run_stream(&RunArgs {
+        effect: Some(effect),
+        sink,
+        sim_lights,
+        duration: Some(seconds),
+        conn,
+        ..Default::default()
+    })
+    .await
+}
+
+async fn run_test(conn: &ConnectionArgs) -> Result<()> {
+    let config = connect_config(conn).await?;
+    println!("🧪 Testing connection to Bridge at {}...", config.bridge_ip);
+    println!("   Using Username: {}", config.username);
+    println!("   Application ID: {}", config.application_id);
+
+    println!("📂 Fetching entertainment groups...");
+    let groups = get_entertainment_groups(&config).await?;
+    let group = groups
+        .iter()
+        .find(|g| g.id == config.entertainment_group_id);
+
+    if let Some(group) = group {
+        println!("✅ Found Entertainment Group: {}", group.name);
+        println!("   Contains {} channels", group.lights.len());
+
+        if let Some(light) = group.lights.first() {
+            println!(
+                "🔦 Flashing Light (Channel {} at {:.2}, {:.2}, {:.2})...",
+                light.channel_id, light.x, light.y, light.z
+            );
+            // Note: flash_light still uses REST API light ID, not channel_id
+            // This may not work correctly if the light ID isn't available
+            flash_light(&config, &light.id).await?;
+            println!("✅ Light flashed successfully!");
+        } else {
+            println!("❌ Group has no channels!");
+        }
+    } else {
+        println!("❌ Configured entertainment group not found on bridge.");
+    }
+    Ok(())
+}
+
+async fn run_static_test(conn: &ConnectionArgs, debug_dtls: bool) -> Result<()> {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    let mut config = connect_config(conn).await?;
+    ensure_application_id(&mut config).await?;
+    let config_arc = Arc::new(config.clone());
+
+    println!("🧪 Static DTLS Test (Correct Protocol)...");
+    println!(
+        "   Application ID (PSK Identity): {}",
+        config.application_id
+    );
+
+    let groups = get_entertainment_groups(&config).await?;
+    let group = groups
+        .iter()
+        .find(|g| g.id == config.entertainment_group_id)
+        .context("Group not found")?;
+
+    println!("   Entertainment Config UUID: {}", group.id);
+    println!(
+        "   Channels: {:?}",
+        group
+            .lights
+            .iter()
+            .map(|l| l.channel_id)
+            .collect::<Vec<_>>()
+    );
+
+    println!("📡 Activating stream (v2 API)...");
+    // Start from a released area, in case an earlier run left it streaming
+    set_stream_active(&config, &group.id, false).await.ok();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let supervisor = StreamSupervisor::new(config.clone(), group.id.clone()).debug_dtls(debug_dtls);
+    supervisor.activate().await?;
+
+    // Spawn Monitor Task
+    let group_id = group.id.clone();
+    let config_monitor = config_arc.clone();
+
+    let monitor_handle = tokio::spawn(async move {
+        loop {
+            if let Ok(area) =
+                get_resource::<EntertainmentConfiguration>(&config_monitor, &group_id).await
+            {
+                println!("   [Monitor] Status: {:?}", area.status);
+                if let Some(streamer) = &area.active_streamer {
+                    println!("   [Monitor] Active Streamer: {}", streamer.rid);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+        }
+    });
+
+    println!("🔒 Connecting DTLS (with correct PSK Identity)...");
+    let mut streamer = supervisor.handshake(&config.credentials()?)?;
+
+    // Build channel map with correct channel_ids
+    let mut light_map = HashMap::new();
+    for light in &group.lights {
+        // Use channel_id (0, 1, 2...) and set to bright RED
+        light_map.insert(light.channel_id, (255, 0, 0));
+    }
+
+    println!(
+        "🎨 Sending RED frames to channels {:?} for 10 seconds...",
+        group
+            .lights
+            .iter()
+            .map(|l| l.channel_id)
+            .collect::<Vec<_>>()
+    );
+
+    // Print the first packet for debugging
+    let packet = encode_message(&group.id, 0, &light_map);
+    println!("📦 Packet Size: {} bytes", packet.len());
+    println!(
+        "📦 Header (first 52 bytes): {:02X?}",
+        &packet[..52.min(packet.len())]
+    );
+
+    let mut encoder = ProtocolEncoder::new(group.id.clone());
+    let mut tick_interval = interval(Duration::from_millis(100));
+    for _ in 0..100 {
+        tick_interval.tick().await;
+        if let Some(packet) = encoder.encode(&light_map) {
+            supervisor.write(&mut streamer, &packet)?;
+        }
+    }
+
+    monitor_handle.abort();
+    supervisor.stop().await.ok();
+    println!("✅ Test finished.");
+    Ok(())
+}
+
+/// Default measurement length of `hueflow tune`.
+const TUNE_SECONDS: u64 = 8;
+/// Preset `hueflow tune` saves to unless told otherwise.
+const TUNED_PRESET: &str = "tuned";
+/// Width of a level bar while measuring.
+const METER_WIDTH: usize = 12;
+
+/// The tuning wizard: measures the band levels, then asks for sensitivity,
+/// brightness ceiling and palette and saves them as the active preset.
+/// Sensitivity is only suggested from live audio; test tones say nothing
+/// about the room and keep the current one.
+async fn run_tune(audio: Option<Audio>, seconds: u64, name: &str) -> Result<()> {
+    use std::io::Write;
+
+    let path = config::config_path();
+    let mut config = config::load_file(&path)?.unwrap_or_default();
+    let dir = preset::presets_dir();
+    let mut tuned = match &config.preset {
+        Some(active) => preset::load(&dir, active)?,
+        None => Preset::default(),
+    };
+
+    println!("🎚️  Tuning '{}' to your room", tuned.effect);
+    let audio = match audio {
+        Some(audio) => audio,
+        None if cfg!(feature = "capture") => {
+            const TONES: &str = "Test tones (no microphone needed, keeps the sensitivity)";
+            const LIVE: &str = "Live audio from the input device";
+            let selection = Select::new("What should be measured?", vec![LIVE, TONES]).prompt()?;
+            if selection == TONES {
+                Audio::Synthetic
+            } else {
+                Audio::Mic
+            }
+        }
+        None => Audio::Synthetic,
+    };
+    if audio == Audio::Mic {
+        println!("   Play music at your usual volume while the levels are measured");
+    }
+    let args = RunArgs {
+        audio,
+        ..Default::default()
+    };
+    let mut source = audio_source(&args, &config)?;
+
+    println!("📊 Measuring for {} s...", seconds);
+    let mut meter = LevelMeter::default();
+    let frame_time = Duration::from_millis(50);
+    let mut tick_interval = interval(frame_time);
+    for _ in 0..(seconds * 1000 / frame_time.as_millis() as u64).max(1) {
+        tick_interval.tick().await;
+        let spectrum = source.next_spectrum();
+        meter.record(&spectrum);
+        print!(
+            "\r   bass {}  mids {}  highs {}",
+            level_bar(spectrum.bass),
+            level_bar(spectrum.mids),
+            level_bar(spectrum.highs)
+        );
+        std::io::stdout().flush().ok();
+    }
+    println!();
+    for (band, stats) in ["bass", "mids", "highs"].iter().zip(meter.bands()) {
+        println!(
+            "   {:<5}  average {:.2}  loud {:.2}  peak {:.2}",
+            band, stats.average, stats.loud, stats.peak
+        );
+    }
+
+    if audio == Audio::Mic {
+        let suggested = match meter.suggested_sensitivity() {
+            Some(sensitivity) => (sensitivity * 100.0).round() / 100.0,
+            None => {
+                println!("⚠️  No sound was picked up; check the input with 'hueflow audio list'");
+                if meter.bands().iter().all(|band| band.peak == 0.0) {
+                    println!(
+                        "   The input was completely silent; {}",
+                        hue_flow_core::audio_input::zero_input_hint()
+                    );
+                }
+                tuned.sensitivity
+            }
+        };
+        let sensitivity = inquire::CustomType::<f32>::new("Sensitivity:")
+            .with_default(suggested)
+            .with_help_message("Above 1 for quiet rooms, below 1 for loud ones")
+            .prompt()?;
+        tuned.sensitivity = sensitivity.clamp(MIN_SENSITIVITY, MAX_SENSITIVITY);
+    } else {
+        println!(
+            "   Test tones do not show how loud your room is; sensitivity stays at {:.2}",
+            tuned.sensitivity
+        );
+    }
+    let brightness = inquire::CustomType::<f32>::new("Brightness ceiling (0.1 - 1.0):")
+        .with_default(tuned.brightness)
+        .prompt()?;
+    tuned.brightness = brightness.clamp(0.1, 1.0);
+
+    const KEEP: &str = "Keep the current colors";
+    let mut palettes = vec![KEEP];
+    palettes.extend(PALETTES.iter().map(|(name, _)| *name));
+    let selection = Select::new("Palette:", palettes).prompt()?;
+    if let Some((_, colors)) = PALETTES.iter().find(|(name, _)| *name == selection) {
+        tuned.palette = colors.to_vec();
+    }
+
+    preset::save(&dir, name, &tuned)?;
+    config.preset = Some(name.to_string());
+    config::save_file(&path, &config)?;
+    println!(
+        "✅ Saved preset '{}' (sensitivity {:.2}, brightness {:.0}%) and made it active",
+        name,
+        tuned.sensitivity,
+        tuned.brightness * 100.0
+    );
+    Ok(())
+}
+
+/// A level (0.0 - 1.0) as a bar of [`METER_WIDTH`] characters.
+fn level_bar(level: f32) -> String {
+    let filled = (level.clamp(0.0, 1.0) * METER_WIDTH as f32).round() as usize;
+    format!("{}{}", "█".repeat(filled), "░".repeat(METER_WIDTH - filled))
+}
+
+/// Frames sent per dark and per lit phase of a latency flash (20 ms each).
+const LATENCY_PHASE_FRAMES: u32 = 30;
+
+async fn run_latency(
+    samples: usize,
+    channel: Option<u8>,
+    sensor: Option<PathBuf>,
+    conn: &ConnectionArgs,
+) -> Result<()> {
+    let mut config = connect_config(conn).await?;
+    ensure_application_id(&mut config).await?;
+    let sensor = sensor.as_deref().map(spawn_sensor).transpose()?;
+
+    println!("⏱️  REST round trips to {}...", config.bridge_ip);
+    let mut rest = Vec::new();
+    for _ in 0..samples {
+        let started = std::time::Instant::now();
+        HueClient::get_bridge_config(&config.bridge_ip).await?;
+        rest.push(started.elapsed());
+    }
+    if let Some(stats) = LatencyStats::from_samples(rest) {
+        println!("   REST: {}", stats);
+    }
+
+    let groups = get_entertainment_groups(&config).await?;
+    let group = groups
+        .iter()
+        .find(|g| g.id == config.entertainment_group_id)
+        .context("Configured entertainment group not found")?;
+    let target = match channel {
+        Some(channel) => group
+            .lights
+            .iter()
+            .find(|l| l.channel_id == channel)
+            .with_context(|| format!("Channel {} is not in '{}'", channel, group.name))?,
+        None => group.lights.first().context("The group has no channels")?,
+    };
+
+    let supervisor = StreamSupervisor::new(config.clone(), group.id.clone());
+    supervisor.activate().await?;
+    let started = std::time::Instant::now();
+    let mut streamer = supervisor.handshake(&config.credentials()?)?;
+    println!(
+        "   DTLS handshake: {:.1} ms (several round trips)",
+        started.elapsed().as_secs_f64() * 1000.0
+    );
+
+    println!(
+        "💡 Flashing channel {} {} times...",
+        target.channel_id, samples
+    );
+    let frame = |color| -> HashMap<u8, (u8, u8, u8)> {
+        group
+            .lights
+            .iter()
+            .map(|l| {
+                (
+                    l.channel_id,
+                    if l.channel_id == target.channel_id {
+                        color
+                    } else {
+                        (0, 0, 0)
+                    },
+                )
+            })
+            .collect()
+    };
+    let (dark, lit) = (frame((0, 0, 0)), frame((255, 255, 255)));
+    let mut encoder = ProtocolEncoder::new(group.id.clone());
+    let mut tick_interval = interval(Duration::from_millis(20));
+    let mut writes = Vec::new();
+    let mut delays = Vec::new();
+    for _ in 0..samples {
+        for _ in 0..LATENCY_PHASE_FRAMES {
+            tick_interval.tick().await;
+            if let Some(packet) = encoder.encode(&dark) {
+                streamer.write_all(&packet)?;
+            }
+        }
+        // Events from the dark phase are noise
+        if let Some(events) = &sensor {
+            while events.try_recv().is_ok() {}
+        }
+        let flashed = std::time::Instant::now();
+        for _ in 0..LATENCY_PHASE_FRAMES {
+            tick_interval.tick().await;
+            if let Some(packet) = encoder.encode(&lit) {
+                let started = std::time::Instant::now();
+                streamer.write_all(&packet)?;
+                writes.push(started.elapsed());
+            }
+        }
+        if let Some(seen) = sensor.as_ref().and_then(|events| events.try_recv().ok()) {
+            delays.push(seen.saturating_duration_since(flashed));
+        }
+    }
+    supervisor.stop().await.ok();
 
-    set_stream_active(&config, &group.id, false).await.ok();
-
+    if let Some(stats) = LatencyStats::from_samples(writes) {
+        println!("   DTLS write: {}", stats);
+    }
+    match LatencyStats::from_samples(delays) {
+        Some(stats) => println!("   Flash to light: {}", stats),
+        None if sensor.is_some() => println!("⚠️  The sensor saw none of the flashes"),
+        None => println!("   Pass --sensor to measure the delay until the light changes"),
+    }
     Ok(())
 }
 
-async fn run_test() -> Result<()> {
-    let config = load_config().context("No configuration found. Run 'hueflow setup' first.")?;
-    println!("🧪 Testing connection to Bridge at {}...", config.bridge_ip);
-    println!("   Using Username: {}", config.username);
-    println!("   Application ID: {}", config.application_id);
+/// Reads a light sensor that prints a line whenever it sees a flash;
+/// the receiver gets the time of each line.
+fn spawn_sensor(path: &Path) -> Result<std::sync::mpsc::Receiver<std::time::Instant>> {
+    use std::io::BufRead;
+
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Cannot open sensor {}", path.display()))?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::BufReader::new(file).lines() {
+            let Ok(line) = line else { break };
+            if !line.trim().is_empty() && tx.send(std::time::Instant::now()).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+/// Colors cycled through while identifying channels, one per channel.
+const IDENTIFY_COLORS: [(u8, u8, u8); 3] = [(255, 255, 255), (255, 0, 255), (0, 255, 255)];
+
+async fn run_identify(step: Duration, cycles: u32, conn: &ConnectionArgs) -> Result<()> {
+    use std::collections::HashMap;
+
+    let mut config = connect_config(conn).await?;
+    ensure_application_id(&mut config).await?;
 
-    println!("📂 Fetching entertainment groups...");
     let groups = get_entertainment_groups(&config).await?;
     let group = groups
         .iter()
-        .find(|g| g.id == config.entertainment_group_id);
+        .find(|g| g.id == config.entertainment_group_id)
+        .context("Configured entertainment group not found")?;
 
-    if let Some(group) = group {
-        println!("✅ Found Entertainment Group: {}", group.name);
-        println!("   Contains {} channels", group.lights.len());
+    let mut lights = group.lights.clone();
+    lights.sort_by_key(|l| l.channel_id);
 
-        if let Some(light) = group.lights.first() {
+    println!(
+        "🔎 Identifying {} channels in '{}' ({}s per channel)",
+        lights.len(),
+        group.name,
+        step.as_secs()
+    );
+
+    println!("📡 Activating stream (v2 API)...");
+    let supervisor = StreamSupervisor::new(config.clone(), group.id.clone());
+    let mut streamer = supervisor.start().await?;
+
+    let frame_time = Duration::from_millis(20);
+    let mut tick_interval = interval(frame_time);
+    let mut encoder = ProtocolEncoder::new(group.id.clone());
+
+    for _ in 0..cycles {
+        for (index, active) in lights.iter().enumerate() {
+            let color = IDENTIFY_COLORS[index % IDENTIFY_COLORS.len()];
             println!(
-                "🔦 Flashing Light (Channel {} at {:.2}, {:.2}, {:.2})...",
-                light.channel_id, light.x, light.y, light.z
+                "💡 Channel {} at ({:.2}, {:.2}, {:.2}) - light {}",
+                active.channel_id, active.x, active.y, active.z, active.id
             );
-            // Note: flash_light still uses REST API light ID, not channel_id
-            // This may not work correctly if the light ID isn't available
-            flash_light(&config, &light.id).await?;
-            println!("✅ Light flashed successfully!");
-        } else {
-            println!("❌ Group has no channels!");
+
+            // All other channels dark so only the active one is visible
+            let frame: HashMap<u8, (u8, u8, u8)> = lights
+                .iter()
+                .map(|l| {
+                    let c = if l.channel_id == active.channel_id {
+                        color
+                    } else {
+                        (0, 0, 0)
+                    };
+                    (l.channel_id, c)
+                })
+                .collect();
+
+            let frames = (step.as_millis() / frame_time.as_millis()).max(1);
+            for _ in 0..frames {
+                tick_interval.tick().await;
+                if let Some(packet) = encoder.encode(&frame) {
+                    supervisor.write(&mut streamer, &packet)?;
+                }
+            }
         }
-    } else {
-        println!("❌ Configured entertainment group not found on bridge.");
     }
+
+    supervisor.stop().await.ok();
+    println!("✅ Identification finished.");
     Ok(())
 }
 
-async fn run_static_test() -> Result<()> {
-    use std::collections::HashMap;
-    use std::sync::Arc;
-    let config = load_config()?;
-    let config_arc = Arc::new(config.clone());
-
-    if config.application_id.is_empty() {
-        println!("⚠️  Application ID not set. Run 'hueflow setup' to reconfigure.");
-        return Ok(());
-    }
-
-    println!("🧪 Static DTLS Test (Correct Protocol)...");
-    println!(
-        "   Application ID (PSK Identity): {}",
-        config.application_id
-    );
+/// Names of the [`PRIMARIES`] lights are compared in.
+const PRIMARY_NAMES: [&str; 3] = ["red", "green", "blue"];
+/// Time the camera gets to adjust to a new test pattern.
+const CAMERA_SETTLE: Duration = Duration::from_millis(1500);
+/// Size the camera picture is scaled to before averaging.
+const CAMERA_PICTURE: (usize, usize) = (64, 48);
+
+async fn run_calibrate(
+    reference: Option<u8>,
+    camera: Option<&str>,
+    conn: &ConnectionArgs,
+) -> Result<()> {
+    let mut config = connect_config(conn).await?;
+    ensure_application_id(&mut config).await?;
 
     let groups = get_entertainment_groups(&config).await?;
     let group = groups
         .iter()
         .find(|g| g.id == config.entertainment_group_id)
-        .context("Group not found")?;
-
-    println!("   Entertainment Config UUID: {}", group.id);
+        .context("Configured entertainment group not found")?;
+    let mut channels: Vec<u8> = group.lights.iter().map(|l| l.channel_id).collect();
+    channels.sort();
+    channels.dedup();
+    let reference = reference
+        .or(channels.first().copied())
+        .context("The entertainment group has no lights")?;
+    if !channels.contains(&reference) {
+        anyhow::bail!("Channel {} is not in '{}'", reference, group.name);
+    }
     println!(
-        "   Channels: {:?}",
-        group
-            .lights
-            .iter()
-            .map(|l| l.channel_id)
-            .collect::<Vec<_>>()
+        "🎚️  Calibrating {} lights of '{}' against channel {}",
+        channels.len() - 1,
+        group.name,
+        reference
     );
 
     println!("📡 Activating stream (v2 API)...");
-    set_stream_active(&config, &group.id, false).await.ok();
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    set_stream_active(&config, &group.id, true).await?;
+    let supervisor = StreamSupervisor::new(config.clone(), group.id.clone());
+    let streamer = supervisor.start().await?;
+    let (patterns, streaming) = spawn_pattern_stream(supervisor, streamer, group.id.clone());
+    let show = |lit: &[(u8, (u8, u8, u8))]| {
+        let frame = channels
+            .iter()
+            .map(|&c| {
+                let color = lit.iter().find(|(l, _)| *l == c).map(|(_, color)| *color);
+                (c, color.unwrap_or_default())
+            })
+            .collect();
+        patterns.send_replace(frame);
+    };
 
-    // Spawn Monitor Task
-    let group_id = group.id.clone();
-    let config_monitor = config_arc.clone();
+    let result = match camera {
+        Some(device) => calibrate_with_camera(&channels, reference, device, show).await,
+        None => calibrate_by_eye(&channels, reference, show),
+    };
+    drop(patterns);
+    if let Ok(supervisor) = streaming.await {
+        supervisor.stop().await.ok();
+    }
+    let mut calibrations = result?;
 
-    let monitor_handle = tokio::spawn(async move {
-        let client = reqwest::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .unwrap();
+    normalize_gains(&mut calibrations);
+    calibrations.retain(|_, c| *c != ChannelCalibration::default());
+    for (channel, c) in calibrations.iter().collect::<BTreeMap<_, _>>() {
+        println!(
+            "   channel {:>2}: gamma {:.2}, gains {:.2} / {:.2} / {:.2}",
+            channel, c.gamma, c.white[0], c.white[1], c.white[2]
+        );
+    }
+    let path = config::config_path();
+    let mut stored =
+        config::load_file(&path)?.context("No configuration found. Run 'hueflow setup' first.")?;
+    stored.color.channels = calibrations;
+    config::save_file(&path, &stored)?;
+    println!("✅ Calibration saved to color.channels in the config");
+    Ok(())
+}
 
-        loop {
-            let url = format!(
-                "https://{}/clip/v2/resource/entertainment_configuration/{}",
-                config_monitor.bridge_ip, group_id
-            );
-            if let Ok(resp) = client
-                .get(&url)
-                .header("hue-application-key", &config_monitor.username)
-                .send()
-                .await
-            {
-                if let Ok(json) = resp.json::<serde_json::Value>().await {
-                    if let Some(data) = json.get("data").and_then(|d| d.get(0)) {
-                        if let Some(status) = data.get("status") {
-                            println!("   [Monitor] Status: {}", status);
-                        }
-                        if let Some(streamer) = data.get("active_streamer") {
-                            println!("   [Monitor] Active Streamer: {}", streamer);
-                        }
-                    }
+/// Streams the latest test pattern to the area until the sender is
+/// dropped, then hands the supervisor back to stop the stream.
+fn spawn_pattern_stream(
+    supervisor: StreamSupervisor,
+    mut streamer: HueStreamer,
+    area_id: String,
+) -> (
+    watch::Sender<Frame>,
+    tokio::task::JoinHandle<StreamSupervisor>,
+) {
+    let (patterns, mut pattern) = watch::channel(HashMap::new());
+    let streaming = tokio::spawn(async move {
+        let mut tick_interval = interval(Duration::from_millis(20));
+        let mut encoder = ProtocolEncoder::new(area_id);
+        while pattern.has_changed().is_ok() {
+            tick_interval.tick().await;
+            let frame = pattern.borrow_and_update().clone();
+            if let Some(packet) = encoder.encode(&frame) {
+                if let Err(e) = supervisor.write(&mut streamer, &packet) {
+                    tracing::warn!("Stream write failed: {}", e);
                 }
             }
-            tokio::time::sleep(Duration::from_millis(1000)).await;
         }
+        supervisor
     });
+    (patterns, streaming)
+}
 
-    println!("🔒 Connecting DTLS (with correct PSK Identity)...");
-    let mut streamer = HueStreamer::connect(
-        &config.bridge_ip,
-        &config.application_id,
-        &config.client_key,
-    )?;
+/// Shows each light next to the reference in the primaries and the gray
+/// ramp, adjusting it until the user judges both alike.
+fn calibrate_by_eye(
+    channels: &[u8],
+    reference: u8,
+    show: impl Fn(&[(u8, (u8, u8, u8))]),
+) -> Result<HashMap<u8, ChannelCalibration>> {
+    println!(
+        "   Compare each light with channel {} and answer how it looks.",
+        reference
+    );
+    let mut calibrations = HashMap::from([(reference, ChannelCalibration::default())]);
+    'lights: for &channel in channels.iter().filter(|&&c| c != reference) {
+        let mut calibration = ChannelCalibration::default();
+        let patterns = PRIMARIES
+            .iter()
+            .zip(PRIMARY_NAMES)
+            .map(|(&color, name)| (color, name.to_string()))
+            .chain(GRAY_RAMP.iter().map(|&v| {
+                let percent = v as u32 * 100 / 255;
+                ((v, v, v), format!("{}% gray", percent))
+            }));
+        for (component, (color, name)) in patterns.enumerate() {
+            loop {
+                // Turn the reference down instead when the light needs more
+                // than full output
+                let mut pair = HashMap::from([
+                    (reference, ChannelCalibration::default()),
+                    (channel, calibration),
+                ]);
+                normalize_gains(&mut pair);
+                show(&[
+                    (reference, pair[&reference].correct(color)),
+                    (channel, pair[&channel].correct(color)),
+                ]);
+                let question = format!("Channel {} in {} looks:", channel, name);
+                let judgement = match Select::new(&question, JUDGEMENTS.to_vec()).prompt()? {
+                    MATCHES => break,
+                    BRIGHTER => Judgement::Brighter,
+                    DIMMER => Judgement::Dimmer,
+                    _ => continue 'lights,
+                };
+                match PRIMARIES.get(component) {
+                    Some(_) => calibration.judge_primary(component, judgement),
+                    None => calibration.judge_gray(judgement),
+                }
+            }
+        }
+        calibrations.insert(channel, calibration);
+    }
+    Ok(calibrations)
+}
 
-    // Build channel map with correct channel_ids
-    let mut light_map = HashMap::new();
-    for light in &group.lights {
-        // Use channel_id (0, 1, 2...) and set to bright RED
-        light_map.insert(light.channel_id, (255, 0, 0));
+const MATCHES: &str = "Matches";
+const BRIGHTER: &str = "Brighter";
+const DIMMER: &str = "Dimmer";
+/// Answers to a comparison; the last leaves the light as it is.
+const JUDGEMENTS: [&str; 4] = [MATCHES, BRIGHTER, DIMMER, "Skip this light"];
+
+/// Reads every light through the camera, pointed at one light at a time,
+/// and matches them to the reference's readings.
+async fn calibrate_with_camera(
+    channels: &[u8],
+    reference: u8,
+    device: &str,
+    show: impl Fn(&[(u8, (u8, u8, u8))]),
+) -> Result<HashMap<u8, ChannelCalibration>> {
+    println!("   Fix the camera's exposure and white balance if it can, and keep");
+    println!("   the same distance to every light.");
+    let gray = GRAY_RAMP[GRAY_RAMP.len() / 2];
+    let mut readings = Vec::new();
+    // The reference first, as the others are matched to it
+    let order =
+        std::iter::once(reference).chain(channels.iter().copied().filter(|&c| c != reference));
+    for channel in order {
+        show(&[(channel, (255, 255, 255))]);
+        let question = format!("Camera pointed at channel {}?", channel);
+        if !Confirm::new(&question).with_default(true).prompt()? {
+            continue;
+        }
+        let read = |color| {
+            show(&[(channel, color)]);
+            async move {
+                tokio::time::sleep(CAMERA_SETTLE).await;
+                camera_average(device)
+            }
+        };
+        let reading = CameraReading {
+            black: read((0, 0, 0)).await?,
+            gray: read((gray, gray, gray)).await?,
+            white: read((255, 255, 255)).await?,
+        };
+        println!("   📷 channel {}: white {:.2?}", channel, reading.white);
+        readings.push((channel, reading));
     }
+    let Some(&(_, reference_reading)) = readings.iter().find(|(c, _)| *c == reference) else {
+        anyhow::bail!("The reference channel {} was not measured", reference);
+    };
+    Ok(readings
+        .iter()
+        .map(|(channel, reading)| {
+            let calibration = ChannelCalibration::from_camera(&reference_reading, reading);
+            (*channel, calibration)
+        })
+        .collect())
+}
 
-    println!(
-        "🎨 Sending RED frames to channels {:?} for 10 seconds...",
-        group
-            .lights
-            .iter()
-            .map(|l| l.channel_id)
-            .collect::<Vec<_>>()
-    );
+/// Average color of a camera picture taken through ffmpeg, 0.0-1.0 per
+/// component.
+fn camera_average(device: &str) -> Result<[f32; 3]> {
+    let (format, input) = if cfg!(target_os = "linux") {
+        ("v4l2", device.to_string())
+    } else if cfg!(target_os = "macos") {
+        ("avfoundation", device.to_string())
+    } else {
+        ("dshow", format!("video={}", device))
+    };
+    let (width, height) = CAMERA_PICTURE;
+    let output = std::process::Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-f", format, "-i", &input])
+        // Later pictures, once the camera has adjusted
+        .args([
+            "-frames:v",
+            "10",
+            "-vf",
+            &format!("scale={}:{}", width, height),
+        ])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                anyhow::anyhow!("--camera needs ffmpeg on the PATH")
+            }
+            _ => anyhow::anyhow!("Cannot start ffmpeg: {}", e),
+        })?;
+    let size = width * height * 3;
+    if !output.status.success() || output.stdout.len() < size {
+        anyhow::bail!(
+            "Cannot read camera {}: {}",
+            device,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let picture = &output.stdout[output.stdout.len() - size..];
+    let mut sum = [0.0f32; 3];
+    for pixel in picture.chunks_exact(3) {
+        for (s, &v) in sum.iter_mut().zip(pixel) {
+            *s += v as f32 / 255.0;
+        }
+    }
+    Ok(sum.map(|s| s / (width * height) as f32))
+}
 
-    // Print the first packet for debugging
-    let packet = hue_flow_core::stream::protocol::create_message(&group.id, &light_map);
-    println!("📦 Packet Size: {} bytes", packet.len());
+/// Sends a command or query over the control socket; see `hueflow ctl`.
+async fn run_ctl(action: CtlAction, socket: Option<PathBuf>) -> Result<()> {
+    let path = socket.unwrap_or_else(socket::default_socket_path);
+    let request = match &action {
+        CtlAction::SetEffect { name } => {
+            if effect_info(name).is_none() {
+                anyhow::bail!("Unknown effect '{}' (see `hueflow effects`)", name);
+            }
+            Request::Command(ControlCommand::SetEffect { name: name.clone() })
+        }
+        CtlAction::Preset { name } => {
+            Request::Command(ControlCommand::LoadPreset { name: name.clone() })
+        }
+        CtlAction::Reload => Request::Command(ControlCommand::Reload),
+        CtlAction::Blackout { fade_ms } => {
+            Request::Command(ControlCommand::Blackout { fade_ms: *fade_ms })
+        }
+        CtlAction::Resume { fade_ms } => {
+            Request::Command(ControlCommand::Resume { fade_ms: *fade_ms })
+        }
+        CtlAction::Override { channels, color } => {
+            let color = parse_hex(color)
+                .with_context(|| format!("Invalid color '{}', expected e.g. ff0000", color))?;
+            Request::Command(ControlCommand::Override {
+                channels: channels.clone(),
+                color,
+            })
+        }
+        CtlAction::Release { channels } => Request::Command(ControlCommand::Release {
+            channels: channels.clone(),
+        }),
+        CtlAction::Status => Request::Query(Query::Status),
+        CtlAction::Stop => Request::Command(ControlCommand::Stop),
+    };
+    let reply = socket::request(&path, &request)
+        .await
+        .with_context(|| format!("No running HueFlow instance at {}", path.display()))?;
+    if let Some(error) = reply.error {
+        anyhow::bail!("{}", error);
+    }
+    match (action, reply.status) {
+        (CtlAction::Status, Some(status)) => println!(
+            "📊 {}, {} FPS, {} frames sent, {} repeats skipped, {} write errors",
+            status.state,
+            status.fps,
+            status.frames_sent,
+            status.frames_skipped,
+            status.write_errors
+        ),
+        _ => println!("✅ Sent"),
+    }
+    Ok(())
+}
+
+/// A request to the control API of a local instance, with the token from
+/// `HUEFLOW_CONTROL_TOKEN` or the config file, over HTTPS when the config
+/// has the API served with TLS.
+fn control_request(
+    method: reqwest::Method,
+    control_addr: &str,
+    path: &str,
+) -> Result<reqwest::RequestBuilder> {
+    let access = match config::load_file(&config::config_path()) {
+        Ok(Some(stored)) => stored.control_auth,
+        _ => ControlAuth::default(),
+    };
+    let mut client = reqwest::Client::builder();
+    let scheme = match &access.tls {
+        Some(files) => {
+            // Usually self-signed, so trusted as its own root
+            let cert = std::fs::read(&files.cert)
+                .with_context(|| format!("Cannot read {}", files.cert.display()))?;
+            client = client.add_root_certificate(reqwest::Certificate::from_pem(&cert)?);
+            "https"
+        }
+        None => "http",
+    };
+    let url = format!("{}://{}/{}", scheme, control_addr, path);
+    let request = client.build()?.request(method, url);
+    let token = std::env::var(ENV_CONTROL_TOKEN)
+        .ok()
+        .or_else(|| access.control_token().map(String::from));
+    Ok(match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    })
+}
+
+/// Sends a command to the control API of a running instance.
+async fn send_control(control_addr: &str, path: &str) -> Result<()> {
+    let resp = control_request(reqwest::Method::POST, control_addr, path)?
+        .send()
+        .await
+        .with_context(|| format!("No running HueFlow instance at {}", control_addr))?;
+    match resp.status() {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => anyhow::bail!(
+            "Control API refused the request (HTTP {}); set {} to a control token",
+            resp.status(),
+            ENV_CONTROL_TOKEN
+        ),
+        status if !status.is_success() => anyhow::bail!("Control API returned HTTP {}", status),
+        _ => Ok(()),
+    }
+}
+
+async fn run_status(control_addr: &str, conn: &ConnectionArgs) -> Result<()> {
+    let config = connect_config(conn).await?;
+
+    let bridge = HueClient::get_bridge_config(&config.bridge_ip).await?;
+    println!("🌉 Bridge: {} at {}", bridge.name, config.bridge_ip);
     println!(
-        "📦 Header (first 52 bytes): {:02X?}",
-        &packet[..52.min(packet.len())]
+        "   Model: {} / Firmware: {} / API: {}",
+        bridge.model_id, bridge.swversion, bridge.api_version
     );
 
-    let mut tick_interval = interval(Duration::from_millis(100));
-    for _ in 0..100 {
-        tick_interval.tick().await;
-        let packet = hue_flow_core::stream::protocol::create_message(&group.id, &light_map);
-        streamer.write_all(&packet)?;
+    let client = build_client()?;
+    let (areas, lights, devices) = tokio::try_join!(
+        get_resources_with::<EntertainmentConfiguration>(&client, &config),
+        get_resources_with::<Light>(&client, &config),
+        get_resources_with::<Device>(&client, &config),
+    )?;
+
+    println!();
+    println!("🎭 Entertainment areas:");
+    for area in &areas {
+        let marker = if area.id == config.entertainment_group_id {
+            "*"
+        } else {
+            " "
+        };
+        let state = match area.status {
+            EntertainmentStatus::Active => "active",
+            EntertainmentStatus::Inactive => "inactive",
+            EntertainmentStatus::Unknown => "unknown",
+        };
+        println!(
+            " {} {} ({} channels): {}",
+            marker,
+            area.metadata.name,
+            area.channels.len(),
+            state
+        );
+        if let Some(streamer) = &area.active_streamer {
+            if streamer.rid == config.application_id {
+                println!("     Streaming: HueFlow (this installation)");
+            } else {
+                println!("     Streaming: another application ({})", streamer.rid);
+            }
+        }
     }
 
-    monitor_handle.abort();
-    set_stream_active(&config, &group.id, false).await.ok();
-    println!("✅ Test finished.");
+    if let Some(area) = areas.iter().find(|a| a.id == config.entertainment_group_id) {
+        println!();
+        println!("💡 Lights in '{}':", area.metadata.name);
+        for service in &area.light_services {
+            let Some(light) = lights.iter().find(|l| l.id == service.rid) else {
+                continue;
+            };
+            let device = devices.iter().find(|d| d.id == light.owner.rid);
+            match device {
+                Some(device) => println!(
+                    "   - {} ({}): software {}",
+                    light.metadata.name,
+                    device.product_data.model_id,
+                    device.product_data.software_version
+                ),
+                None => println!("   - {}", light.metadata.name),
+            }
+        }
+    }
+
+    println!();
+    let running = control_request(reqwest::Method::GET, control_addr, "status")?
+        .timeout(Duration::from_secs(1))
+        .send()
+        .await
+        .is_ok_and(|r| r.status().is_success());
+    if running {
+        println!(
+            "▶️  HueFlow is running locally (control API on {})",
+            control_addr
+        );
+    } else {
+        println!("⏹️  No local HueFlow instance running");
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "capture")]
+fn run_audio(action: AudioAction) -> Result<()> {
+    use hue_flow_core::audio_input::list_input_devices;
+
+    match action {
+        AudioAction::List => {
+            let devices = list_input_devices();
+            if devices.is_empty() {
+                println!("❌ No audio input devices found.");
+                return Ok(());
+            }
+            let configured = config::load_file(&config::config_path())?
+                .and_then(|c| c.audio_device)
+                .unwrap_or_default();
+            for device in devices {
+                let marker = if device.name == configured {
+                    "▶"
+                } else if device.is_default {
+                    "*"
+                } else {
+                    " "
+                };
+                println!("{} [{}] {}", marker, device.host, device.name);
+                for c in &device.configs {
+                    println!(
+                        "      {} ch, {}-{} Hz, {}",
+                        c.channels, c.min_sample_rate, c.max_sample_rate, c.sample_format
+                    );
+                }
+            }
+            println!();
+            println!("   * system default, ▶ configured device");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "capture"))]
+fn run_audio(_action: AudioAction) -> Result<()> {
+    anyhow::bail!("This build has no audio capture; rebuild with `--features capture`")
+}
+
+async fn run_area(action: AreaAction) -> Result<()> {
+    let config = load_config(&ConnectionArgs::default())?;
+    match action {
+        AreaAction::Export { file, group } => {
+            let id = match group {
+                Some(wanted) => get_entertainment_groups(&config)
+                    .await?
+                    .into_iter()
+                    .find(|g| g.matches(&wanted))
+                    .map(|g| g.id)
+                    .with_context(|| format!("Entertainment group '{}' not found", wanted))?,
+                None => config.entertainment_group_id.clone(),
+            };
+            let backup = export_area(&config, &id).await?;
+            let json = serde_json::to_string_pretty(&backup)?;
+            config::write_atomic(&file, json.as_bytes())
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+            println!(
+                "💾 Saved '{}' ({} lights) to {}",
+                backup.name,
+                backup.lights.len(),
+                file.display()
+            );
+        }
+        AreaAction::Import { file, name } => {
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let backup: AreaBackup = serde_json::from_str(&content)
+                .with_context(|| format!("{} is not an area backup", file.display()))?;
+            let name = name.unwrap_or_else(|| backup.name.clone());
+            let (id, missing) = import_area(&config, &backup, &name).await?;
+            for device in &missing {
+                println!("⚠️  '{}' not found on the bridge, left out", device);
+            }
+            println!("✅ Restored '{}' ({})", name, id);
+            println!("   Stream to it with: hueflow group use \"{}\"", name);
+        }
+    }
+    Ok(())
+}
+
+fn run_token(action: TokenAction) -> Result<()> {
+    let path = config::config_path();
+    let mut stored = stored_config(&path)?;
+    let access = &mut stored.control_auth;
+
+    match action {
+        TokenAction::Create { name, read_only } => {
+            let scope = if read_only {
+                Scope::Read
+            } else {
+                Scope::Control
+            };
+            let token = access.create_token(&name, scope)?.token.clone();
+            config::save_file(&path, &stored)?;
+            println!("🔑 Token '{}': {}", name, token);
+            println!("   Send it as 'Authorization: Bearer <token>'; running instances pick it up on their next start.");
+        }
+        TokenAction::List => {
+            if access.tokens.is_empty() {
+                println!("No API tokens; the control API accepts every request.");
+            }
+            for token in &access.tokens {
+                let scope = match token.scope {
+                    Scope::Read => "read only",
+                    Scope::Control => "control",
+                };
+                println!("  - {} ({})", token.name, scope);
+            }
+        }
+        TokenAction::Revoke { name } => {
+            if !access.revoke(&name) {
+                anyhow::bail!("No token named '{}'", name);
+            }
+            config::save_file(&path, &stored)?;
+            println!("🗑️  Revoked token '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+async fn run_group(action: GroupAction) -> Result<()> {
+    let path = config::config_path();
+    let mut stored =
+        config::load_file(&path)?.context("No configuration found. Run 'hueflow setup' first.")?;
+
+    match action {
+        GroupAction::Use { name } => {
+            if stored.find_group(&name).is_none() {
+                // Areas created after setup are not stored yet
+                println!("🎭 Refreshing entertainment groups from the bridge...");
+                let config = load_config(&ConnectionArgs::default())?;
+                stored.groups = get_entertainment_groups(&config)
+                    .await?
+                    .iter()
+                    .map(GroupEntry::from)
+                    .collect();
+            }
+            let group = stored.find_group(&name).cloned().with_context(|| {
+                let available: Vec<&str> = stored.groups.iter().map(|g| g.name.as_str()).collect();
+                format!(
+                    "Entertainment group '{}' not found. Available: {}",
+                    name,
+                    available.join(", ")
+                )
+            })?;
+            stored.entertainment_group_id = group.id.clone();
+            config::save_file(&path, &stored)?;
+            println!("✅ Now streaming to '{}'", group.name);
+        }
+        GroupAction::List => {
+            if stored.groups.is_empty() {
+                println!(
+                    "No entertainment groups stored. Run 'hueflow setup' again to store them."
+                );
+            }
+            for group in &stored.groups {
+                let marker = if group.id == stored.entertainment_group_id {
+                    "*"
+                } else {
+                    " "
+                };
+                println!("{} {} ({})", marker, group.name, group.id);
+            }
+        }
+        GroupAction::Merge { groups, name } => {
+            let config = load_config(&ConnectionArgs::default())?;
+            let available = get_entertainment_groups(&config).await?;
+            let sources = groups
+                .iter()
+                .map(|wanted| {
+                    available
+                        .iter()
+                        .find(|g| g.matches(wanted))
+                        .with_context(|| format!("Entertainment group '{}' not found", wanted))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let ids: Vec<String> = sources.iter().map(|g| g.id.clone()).collect();
+            let name = name.unwrap_or_else(|| {
+                let names: Vec<&str> = sources.iter().map(|g| g.name.as_str()).collect();
+                let name = format!("HueFlow {}", names.join(" + "));
+                name.chars().take(32).collect()
+            });
+
+            println!("🔗 Merging into '{}'...", name);
+            let id = merge_entertainment_groups(&config, &name, &ids).await?;
+            let configs = get_resources::<EntertainmentConfiguration>(&config).await?;
+            let merged = configs
+                .iter()
+                .find(|c| c.id == id)
+                .context("Merged group not found on the bridge")?;
+            let originals: Vec<_> = configs
+                .iter()
+                .filter(|c| ids.contains(&c.id))
+                .cloned()
+                .collect();
+            let map = channel_map(merged, &originals);
+
+            // Per-channel settings of the group streamed so far follow its
+            // lights into the merged group
+            let previous = stored.entertainment_group_id.clone();
+            if ids.contains(&previous) {
+                let moved = |channel: &u8| {
+                    map.iter()
+                        .filter(|(_, (area, c))| *area == previous && c == channel)
+                        .map(|(merged, _)| *merged)
+                        .collect::<Vec<u8>>()
+                };
+                stored.excluded_channels =
+                    stored.excluded_channels.iter().flat_map(moved).collect();
+                stored.channel_smoothing_ms = stored
+                    .channel_smoothing_ms
+                    .iter()
+                    .flat_map(|(channel, ms)| moved(channel).into_iter().map(|c| (c, *ms)))
+                    .collect();
+                for channels in stored.rooms.values_mut() {
+                    *channels = channels.iter().flat_map(moved).collect();
+                }
+            }
+
+            let mut channels: Vec<_> = map.iter().collect();
+            channels.sort();
+            for (channel, (area, source)) in channels {
+                let area = sources
+                    .iter()
+                    .find(|g| &g.id == area)
+                    .map_or(area.as_str(), |g| g.name.as_str());
+                println!("   channel {:>2} ← {} channel {}", channel, area, source);
+            }
+
+            stored.groups = get_entertainment_groups(&config)
+                .await?
+                .iter()
+                .map(GroupEntry::from)
+                .collect();
+            stored.entertainment_group_id = id;
+            config::save_file(&path, &stored)?;
+            println!("✅ Now streaming to '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+async fn run_preset(action: PresetAction) -> Result<()> {
+    let dir = preset::presets_dir();
+    match action {
+        PresetAction::Save {
+            name,
+            effect,
+            palette,
+            brightness,
+            sensitivity,
+            ct_only,
+            group_id,
+            fps,
+            invert,
+        } => {
+            if let Some(fps) = fps {
+                check_fps(fps).map_err(anyhow::Error::msg)?;
+            }
+            let palette = palette
+                .iter()
+                .map(|c| parse_hex(c).with_context(|| format!("Invalid color '{}'", c)))
+                .collect::<Result<Vec<_>>>()?;
+            let new_preset = Preset {
+                effect,
+                palette,
+                brightness: brightness.clamp(0.0, 1.0),
+                sensitivity: sensitivity.clamp(MIN_SENSITIVITY, MAX_SENSITIVITY),
+                ct_only,
+                entertainment_group_id: group_id,
+                fps,
+                params: EffectParams {
+                    invert,
+                    ..Default::default()
+                },
+            };
+            // Fail early on unknown effect names
+            new_preset.build_effect()?;
+            preset::save(&dir, &name, &new_preset)?;
+            println!("💾 Saved preset '{}'", name);
+        }
+        PresetAction::Load { name, control_addr } => {
+            // Validate before activating
+            preset::load(&dir, &name)?;
+
+            let path = config::config_path();
+            let mut stored = config::load_file(&path)?.unwrap_or_default();
+            stored.preset = Some(name.clone());
+            config::save_file(&path, &stored)?;
+            println!("✅ Preset '{}' is now active", name);
+
+            match send_control(&control_addr, &format!("presets/{}", name)).await {
+                Ok(_) => println!("   Running instance switched to '{}'", name),
+                Err(_) => println!("   No running instance found; used on next 'hueflow run'"),
+            }
+        }
+        PresetAction::List => {
+            let names = preset::list(&dir)?;
+            if names.is_empty() {
+                println!("No presets saved yet. Use 'hueflow preset save <name>'.");
+            }
+            for name in names {
+                println!("  - {}", name);
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,29 @@
+//! Per-frame CPU of the analysis and the color math. Compare
+//! `cargo bench -p hue_flow_core` with `--features simd`.
+use criterion::{criterion_group, criterion_main, Criterion};
+use hue_flow_core::analysis::{DefaultAnalyzer, FFT_SIZE};
+use hue_flow_core::audio_interface::AudioProcessor;
+use hue_flow_core::simd::scale_colors;
+use std::collections::HashMap;
+use std::hint::black_box;
+
+fn analysis(c: &mut Criterion) {
+    let samples: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| (i as f32 * 0.05).sin() * 0.5 + (i as f32 * 0.9).sin() * 0.2)
+        .collect();
+    let mut analyzer = DefaultAnalyzer::new(48_000);
+    c.bench_function("analyze window", |b| {
+        b.iter(|| analyzer.process(black_box(&samples)))
+    });
+}
+
+fn colors(c: &mut Criterion) {
+    // A full entertainment area
+    let mut frame: HashMap<u8, (u8, u8, u8)> = (0..20).map(|i| (i, (255, i * 12, 40))).collect();
+    c.bench_function("scale 20 channels", |b| {
+        b.iter(|| scale_colors(frame.values_mut(), black_box(0.7)))
+    });
+}
+
+criterion_group!(benches, analysis, colors);
+criterion_main!(benches);
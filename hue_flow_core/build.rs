@@ -0,0 +1,9 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // No protoc needed on the build machine
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::compile_protos("proto/hueflow.proto").expect("compile hueflow.proto");
+    }
+}
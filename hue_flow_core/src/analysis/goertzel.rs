@@ -0,0 +1,182 @@
+use super::{AutoGain, BASS_MAX_HZ, BASS_MIN_HZ, FFT_SIZE, HIGHS_MAX_HZ, MIDS_MAX_HZ};
+use crate::audio_interface::{AudioProcessor, AudioSpectrum};
+
+/// Probe frequencies per octave within each band.
+const PROBES_PER_OCTAVE: f32 = 3.0;
+/// Periods of its frequency each probe listens to (constant Q). Fewer
+/// periods widen a probe enough to cover the gap to its neighbours, and make
+/// high probes cheap.
+const PROBE_PERIODS: f32 = 8.0;
+/// Fractional bits of samples and window values.
+const SAMPLE_BITS: u32 = 15;
+/// Fractional bits of the Goertzel coefficients (which reach ±2.0).
+const COEFF_BITS: u32 = 14;
+
+/// A single-frequency detector in integer arithmetic.
+#[derive(Debug, Clone)]
+struct Probe {
+    /// 2·cos(ω) with [`COEFF_BITS`] fractional bits.
+    coeff: i64,
+    /// Samples listened to, at most [`FFT_SIZE`].
+    len: usize,
+}
+
+impl Probe {
+    fn new(sample_rate: u32, freq: f32) -> Self {
+        let w = std::f32::consts::TAU * freq / sample_rate as f32;
+        let len = (PROBE_PERIODS * sample_rate as f32 / freq) as usize;
+        Self {
+            coeff: (2.0 * w.cos() * (1 << COEFF_BITS) as f32).round() as i64,
+            len: len.clamp(2, FFT_SIZE),
+        }
+    }
+
+    /// Amplitude of the probed frequency in the newest samples, windowed
+    /// with `window` stretched to the probe's length.
+    fn amplitude(&self, samples: &[i32], window: &[i32]) -> f32 {
+        let samples = &samples[samples.len() - self.len..];
+        let (mut s1, mut s2) = (0i64, 0i64);
+        for (i, &x) in samples.iter().enumerate() {
+            let w = window[i * (FFT_SIZE - 1) / (self.len - 1)] as i64;
+            let s0 = ((x as i64 * w) >> SAMPLE_BITS) + ((self.coeff * s1) >> COEFF_BITS) - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        let power = s1 * s1 + s2 * s2 - ((self.coeff * s1) >> COEFF_BITS) * s2;
+        (power.max(0) as f32).sqrt() * 2.0 / self.len as f32 / (1 << SAMPLE_BITS) as f32
+    }
+}
+
+/// Measures the three bands with a bank of Goertzel filters in fixed point.
+///
+/// Meant for microcontroller-class hosts built without the `fft` feature:
+/// it needs no FFT library and no floating-point work per sample, at the
+/// cost of per-bin detail ([`bins`](Self::bins) stays empty). Levels are
+/// normalized like [`FftAnalyzer`](super::FftAnalyzer)'s.
+#[derive(Debug, Clone)]
+pub struct GoertzelAnalyzer {
+    sample_rate: u32,
+    /// Hann window with [`SAMPLE_BITS`] fractional bits.
+    window: Vec<i32>,
+    bands: [Vec<Probe>; 3],
+    /// The current window in fixed point, zero-padded at the front.
+    fixed: Vec<i32>,
+    gain: AutoGain,
+}
+
+impl GoertzelAnalyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = std::f32::consts::TAU * i as f32 / (FFT_SIZE - 1) as f32;
+                ((0.5 - 0.5 * x.cos()) * (1 << SAMPLE_BITS) as f32) as i32
+            })
+            .collect();
+        // The window cannot resolve frequencies below two bins
+        let lowest = BASS_MIN_HZ.max(2.0 * sample_rate as f32 / FFT_SIZE as f32);
+        let nyquist = sample_rate as f32 / 2.0;
+        let probes = |low: f32, high: f32| -> Vec<Probe> {
+            let high = high.min(nyquist);
+            let count = ((high / low).log2() * PROBES_PER_OCTAVE).ceil().max(1.0) as usize;
+            (0..count)
+                .map(|i| low * (high / low).powf((i as f32 + 0.5) / count as f32))
+                .map(|freq| Probe::new(sample_rate, freq))
+                .collect()
+        };
+        Self {
+            sample_rate,
+            window,
+            bands: [
+                probes(lowest, BASS_MAX_HZ),
+                probes(BASS_MAX_HZ, MIDS_MAX_HZ),
+                probes(MIDS_MAX_HZ, HIGHS_MAX_HZ),
+            ],
+            fixed: Vec::with_capacity(FFT_SIZE),
+            gain: AutoGain::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Always empty; only the FFT provides bins.
+    pub fn bins(&self) -> &[f32] {
+        &[]
+    }
+
+    pub fn bin_hz(&self) -> f32 {
+        0.0
+    }
+
+    /// Strongest probe of a band.
+    fn band(&self, probes: &[Probe]) -> f32 {
+        probes
+            .iter()
+            .map(|p| p.amplitude(&self.fixed, &self.window))
+            .fold(0.0, f32::max)
+    }
+}
+
+impl AudioProcessor for GoertzelAnalyzer {
+    fn process(&mut self, samples: &[f32]) -> AudioSpectrum {
+        let samples = &samples[samples.len().saturating_sub(FFT_SIZE)..];
+        let offset = FFT_SIZE - samples.len();
+        let one = (1 << SAMPLE_BITS) as f32;
+
+        self.fixed.clear();
+        self.fixed.resize(offset, 0);
+        let mut square_sum = 0i64;
+        for sample in samples {
+            let fixed = (sample.clamp(-1.0, 1.0) * (one - 1.0)) as i32;
+            square_sum += fixed as i64 * fixed as i64;
+            self.fixed.push(fixed);
+        }
+
+        let [bass, mids, highs] = [0, 1, 2].map(|i| self.band(&self.bands[i]));
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            ((square_sum / samples.len() as i64) as f32).sqrt() / one
+        };
+        self.gain.apply(bass, mids, highs, rms).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / sample_rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn test_tones_land_in_their_band() {
+        for (freq, band) in [
+            (100.0, 0),
+            (1000.0, 1),
+            (1150.0, 1),
+            (8000.0, 2),
+            (9000.0, 2),
+        ] {
+            let mut analyzer = GoertzelAnalyzer::new(44_100);
+            let s = analyzer.process(&sine(freq, 44_100));
+            let levels = [s.bass, s.mids, s.highs];
+            let loudest = (0..3)
+                .max_by(|a, b| levels[*a].total_cmp(&levels[*b]))
+                .unwrap();
+            assert_eq!(loudest, band, "{} Hz gave {:?}", freq, levels);
+            // Tones between two probes still read close to their level
+            // (0.5 amplitude, halved by the window)
+            let raw = analyzer.band(&analyzer.bands[band]);
+            assert!(raw > 0.12, "{} Hz read {}", freq, raw);
+        }
+
+        let mut analyzer = GoertzelAnalyzer::new(48_000);
+        let s = analyzer.process(&[0.0; FFT_SIZE]);
+        assert_eq!((s.bass, s.mids, s.highs, s.energy), (0.0, 0.0, 0.0, 0.0));
+    }
+}
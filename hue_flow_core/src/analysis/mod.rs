@@ -0,0 +1,700 @@
+//! Analysis of captured audio into the bands effects react to.
+//!
+//! With the default `fft` feature bands come from an [`FftAnalyzer`].
+//! Without it, a fixed-point [`GoertzelAnalyzer`] measures just the three
+//! bands, for hosts too weak for float-heavy DSP.
+//!
+//! Live capture can run the analysis on an [`AnalysisWorker`] thread, so a
+//! slow analysis delays the spectrum instead of the lights.
+mod goertzel;
+mod worker;
+
+use crate::audio_interface::{AudioProcessor, AudioSpectrum, BeatInfo};
+#[cfg(feature = "fft")]
+use crate::simd;
+#[cfg(feature = "fft")]
+use rustfft::num_complex::Complex;
+#[cfg(feature = "fft")]
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+#[cfg(feature = "fft")]
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use goertzel::GoertzelAnalyzer;
+pub use worker::{AnalysisFeed, AnalysisWorker, TimedFrame, BLOCK_QUEUE, FRAME_QUEUE};
+
+/// Analyzer behind [`OverlapAnalyzer`], chosen by the `fft` feature.
+#[cfg(feature = "fft")]
+pub type DefaultAnalyzer = FftAnalyzer;
+#[cfg(not(feature = "fft"))]
+pub type DefaultAnalyzer = GoertzelAnalyzer;
+
+/// Samples per analysis window.
+pub const FFT_SIZE: usize = 1024;
+/// Default samples between overlapping analysis windows (4× overlap).
+pub const DEFAULT_HOP: usize = 256;
+
+/// Band edges in Hz: bass from the first to the second, mids up to the
+/// third, highs up to the fourth.
+const BASS_MIN_HZ: f32 = 20.0;
+const BASS_MAX_HZ: f32 = 250.0;
+const MIDS_MAX_HZ: f32 = 4000.0;
+const HIGHS_MAX_HZ: f32 = 16000.0;
+
+/// How quickly the automatic gain forgets a loud passage (per window).
+const PEAK_DECAY: f32 = 0.995;
+/// Lowest level the automatic gain normalizes against, so silence stays dark.
+const MIN_PEAK: f32 = 0.01;
+
+/// Frames of bass history a new frame is compared against for beats.
+const BEAT_HISTORY: usize = 20;
+/// Bass must rise this far above its recent average to count as a beat.
+const BEAT_RATIO: f32 = 1.4;
+/// Quieter bass never counts as a beat, so noise in silence stays dark.
+const BEAT_MIN_LEVEL: f32 = 0.1;
+/// Shortest time between two beats (240 BPM).
+const BEAT_MIN_GAP: Duration = Duration::from_millis(250);
+
+/// Energy below which audio counts as the gap between two tracks.
+const GAP_LEVEL: f32 = 0.02;
+/// How long the gap must last; shorter breaks belong to the track.
+const GAP_MIN: Duration = Duration::from_millis(1500);
+
+/// Window the energy trend of a build-up is measured over.
+const BUILDUP_WINDOW: Duration = Duration::from_secs(4);
+/// Energy rise per second (of the 0.0 - 1.0 range) that counts as a build-up.
+const BUILDUP_SLOPE: f32 = 0.05;
+/// A drop must follow within this time of the build-up.
+const DROP_WINDOW: Duration = Duration::from_secs(2);
+/// Bass must jump this far above its average during the build-up...
+const DROP_RATIO: f32 = 1.8;
+/// ...and be at least this loud.
+const DROP_MIN_BASS: f32 = 0.5;
+/// Shortest time between two drops.
+const DROP_MIN_GAP: Duration = Duration::from_secs(15);
+
+/// How far back [`SpectrumHistory`] reaches.
+pub const HISTORY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Slowly decaying peaks for automatic gain: one shared by the bands (so
+/// they keep their balance) and one for the RMS level.
+#[derive(Debug, Clone)]
+struct AutoGain {
+    peaks: [f32; 2],
+}
+
+impl Default for AutoGain {
+    fn default() -> Self {
+        Self {
+            peaks: [MIN_PEAK; 2],
+        }
+    }
+}
+
+impl AutoGain {
+    /// Normalizes raw band levels and the RMS level to 0.0 - 1.0. Also
+    /// returns the gain applied to the bands.
+    fn apply(&mut self, bass: f32, mids: f32, highs: f32, rms: f32) -> (AudioSpectrum, f32) {
+        let band_gain = self.gain(0, bass.max(mids).max(highs));
+        let rms_gain = self.gain(1, rms);
+        let spectrum = AudioSpectrum {
+            bass: (bass * band_gain).clamp(0.0, 1.0),
+            mids: (mids * band_gain).clamp(0.0, 1.0),
+            highs: (highs * band_gain).clamp(0.0, 1.0),
+            energy: (rms * rms_gain).clamp(0.0, 1.0),
+        };
+        (spectrum, band_gain)
+    }
+
+    /// Updates the running peak with `loudest` and returns the gain to apply.
+    fn gain(&mut self, index: usize, loudest: f32) -> f32 {
+        let peak = &mut self.peaks[index];
+        *peak = (*peak * PEAK_DECAY).max(loudest).max(MIN_PEAK);
+        1.0 / *peak
+    }
+}
+
+/// Turns windows of mono samples into [`AudioSpectrum`] bands.
+///
+/// Each call analyzes the most recent [`FFT_SIZE`] samples with a Hann window.
+/// A band's level is its strongest bin. Bands share one slowly decaying peak
+/// for normalization (keeping their balance), so quiet and loud sources both
+/// use the full 0.0 - 1.0 range.
+#[cfg(feature = "fft")]
+pub struct FftAnalyzer {
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    /// Magnitudes of the last window, DC to Nyquist.
+    magnitudes: Vec<f32>,
+    /// The magnitudes normalized.
+    bins: Vec<f32>,
+    gain: AutoGain,
+}
+
+#[cfg(feature = "fft")]
+impl FftAnalyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = std::f32::consts::TAU * i as f32 / (FFT_SIZE - 1) as f32;
+                0.5 - 0.5 * x.cos()
+            })
+            .collect();
+        Self {
+            sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            buffer: vec![Complex::default(); FFT_SIZE],
+            magnitudes: vec![0.0; FFT_SIZE / 2 + 1],
+            bins: vec![0.0; FFT_SIZE / 2 + 1],
+            gain: AutoGain::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Magnitudes of the last window, normalized with the same gain as the
+    /// bands.
+    pub fn bins(&self) -> &[f32] {
+        &self.bins
+    }
+
+    /// Width of one bin in Hz.
+    pub fn bin_hz(&self) -> f32 {
+        self.sample_rate as f32 / FFT_SIZE as f32
+    }
+
+    /// Strongest bin between `low` and `high` Hz, scaled to sample amplitude.
+    fn band(&self, low: f32, high: f32) -> f32 {
+        let bin_hz = self.bin_hz();
+        let first = ((low / bin_hz).ceil() as usize).max(1);
+        let last = ((high / bin_hz) as usize).min(FFT_SIZE / 2);
+        if last < first {
+            return 0.0;
+        }
+        simd::max(&self.magnitudes[first..=last]) * 2.0 / FFT_SIZE as f32
+    }
+}
+
+#[cfg(feature = "fft")]
+impl AudioProcessor for FftAnalyzer {
+    fn process(&mut self, samples: &[f32]) -> AudioSpectrum {
+        let samples = &samples[samples.len().saturating_sub(FFT_SIZE)..];
+        // Zero-pad at the front when fewer samples than a window are available
+        let offset = FFT_SIZE - samples.len();
+        for (i, slot) in self.buffer.iter_mut().enumerate() {
+            let sample = if i < offset { 0.0 } else { samples[i - offset] };
+            *slot = Complex::new(sample * self.window[i], 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+        simd::norms(&self.buffer, &mut self.magnitudes);
+
+        let bass = self.band(BASS_MIN_HZ, BASS_MAX_HZ);
+        let mids = self.band(BASS_MAX_HZ, MIDS_MAX_HZ);
+        let highs = self.band(MIDS_MAX_HZ, HIGHS_MAX_HZ);
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (simd::sum_squares(samples) / samples.len() as f32).sqrt()
+        };
+
+        let (spectrum, band_gain) = self.gain.apply(bass, mids, highs, rms);
+        let scale = 2.0 / FFT_SIZE as f32 * band_gain;
+        simd::scale_clamped(&self.magnitudes, scale, &mut self.bins);
+        spectrum
+    }
+}
+
+/// Runs the [`DefaultAnalyzer`] every `hop` samples over the last [`FFT_SIZE`]
+/// samples, instead of once per independent block.
+///
+/// With a hop of 256 at 48 kHz that is ~190 spectra per second. [`push`]
+/// averages all spectra completed by one call, which smooths out the jitter
+/// of single windows.
+///
+/// [`push`]: OverlapAnalyzer::push
+pub struct OverlapAnalyzer {
+    analyzer: DefaultAnalyzer,
+    ring: VecDeque<f32>,
+    hop: usize,
+    /// Samples received since the last analysis.
+    pending: usize,
+    latest: AudioSpectrum,
+}
+
+impl OverlapAnalyzer {
+    /// `hop` is clamped to 1..=[`FFT_SIZE`]; [`FFT_SIZE`] means no overlap.
+    pub fn new(sample_rate: u32, hop: usize) -> Self {
+        Self {
+            analyzer: DefaultAnalyzer::new(sample_rate),
+            ring: VecDeque::from(vec![0.0; FFT_SIZE]),
+            hop: hop.clamp(1, FFT_SIZE),
+            pending: 0,
+            latest: AudioSpectrum::default(),
+        }
+    }
+
+    /// Feeds new samples. Returns the average of the spectra completed by
+    /// them, or `None` if fewer than a hop has arrived since the last one.
+    pub fn push(&mut self, samples: &[f32]) -> Option<AudioSpectrum> {
+        let mut sum = AudioSpectrum::default();
+        let mut count = 0;
+
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = (self.hop - self.pending).min(rest.len());
+            self.ring.extend(&rest[..take]);
+            self.ring.drain(..self.ring.len() - FFT_SIZE);
+            self.pending += take;
+            rest = &rest[take..];
+
+            if self.pending == self.hop {
+                self.pending = 0;
+                let s = self.analyzer.process(self.ring.make_contiguous());
+                sum.bass += s.bass;
+                sum.mids += s.mids;
+                sum.highs += s.highs;
+                sum.energy += s.energy;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return None;
+        }
+        let n = count as f32;
+        self.latest = AudioSpectrum {
+            bass: sum.bass / n,
+            mids: sum.mids / n,
+            highs: sum.highs / n,
+            energy: sum.energy / n,
+        };
+        Some(self.latest)
+    }
+
+    /// The most recent result of [`push`](Self::push).
+    pub fn latest(&self) -> AudioSpectrum {
+        self.latest
+    }
+
+    /// Bins of the most recent window; empty without the `fft` feature.
+    pub fn bins(&self) -> &[f32] {
+        self.analyzer.bins()
+    }
+
+    pub fn bin_hz(&self) -> f32 {
+        self.analyzer.bin_hz()
+    }
+}
+
+/// Finds beats as sudden rises of the bass over its recent average.
+///
+/// Works on the band summary alone, so it runs on every audio source.
+#[derive(Debug, Clone, Default)]
+pub struct BeatDetector {
+    history: VecDeque<f32>,
+    last_beat: Option<Instant>,
+}
+
+impl BeatDetector {
+    pub fn update(&mut self, spectrum: &AudioSpectrum, now: Instant) -> BeatInfo {
+        let bass = spectrum.bass;
+        // Nothing to compare the very first frame against
+        let average = if self.history.is_empty() {
+            f32::MAX
+        } else {
+            self.history.iter().sum::<f32>() / self.history.len() as f32
+        };
+        if self.history.len() == BEAT_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(bass);
+
+        let rested = self
+            .last_beat
+            .is_none_or(|t| now.duration_since(t) >= BEAT_MIN_GAP);
+        let onset = rested && bass >= BEAT_MIN_LEVEL && bass > average * BEAT_RATIO;
+        if onset {
+            self.last_beat = Some(now);
+        }
+        BeatInfo {
+            onset,
+            strength: if onset {
+                ((bass - average) / (1.0 - average).max(0.01)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Finds track changes as sound returning after a gap of near silence.
+///
+/// Gapless mixes go unnoticed; it is meant for playlists and albums.
+#[derive(Debug, Clone, Default)]
+pub struct TrackChangeDetector {
+    silent_since: Option<Instant>,
+    /// Sound seen since the last change, so a quiet start is no new track.
+    playing: bool,
+}
+
+impl TrackChangeDetector {
+    /// True on the first audible frame after a long enough gap.
+    pub fn update(&mut self, spectrum: &AudioSpectrum, now: Instant) -> bool {
+        if spectrum.energy < GAP_LEVEL {
+            self.silent_since.get_or_insert(now);
+            return false;
+        }
+        let gap = self
+            .silent_since
+            .take()
+            .is_some_and(|since| now.duration_since(since) >= GAP_MIN);
+        let changed = gap && self.playing;
+        self.playing = true;
+        changed
+    }
+}
+
+/// Finds drops: a build-up of steadily rising energy, then a sudden surge
+/// of bass.
+#[derive(Debug, Clone, Default)]
+pub struct DropDetector {
+    /// Time, energy and bass of the frames in [`BUILDUP_WINDOW`].
+    history: VecDeque<(Instant, f32, f32)>,
+    building_until: Option<Instant>,
+    last_drop: Option<Instant>,
+}
+
+impl DropDetector {
+    /// True on the frame a drop hits.
+    pub fn update(&mut self, spectrum: &AudioSpectrum, now: Instant) -> bool {
+        while self
+            .history
+            .front()
+            .is_some_and(|(at, _, _)| now.duration_since(*at) > BUILDUP_WINDOW)
+        {
+            self.history.pop_front();
+        }
+        let covered = self
+            .history
+            .front()
+            .is_some_and(|(at, _, _)| now.duration_since(*at) >= BUILDUP_WINDOW * 3 / 4);
+        if covered && self.energy_slope() >= BUILDUP_SLOPE {
+            self.building_until = Some(now + DROP_WINDOW);
+        }
+        let bass_average = if self.history.is_empty() {
+            f32::MAX
+        } else {
+            self.history.iter().map(|(_, _, bass)| bass).sum::<f32>() / self.history.len() as f32
+        };
+        self.history
+            .push_back((now, spectrum.energy, spectrum.bass));
+
+        let armed = self.building_until.is_some_and(|until| now <= until);
+        let rested = self
+            .last_drop
+            .is_none_or(|t| now.duration_since(t) >= DROP_MIN_GAP);
+        let surge = spectrum.bass >= DROP_MIN_BASS && spectrum.bass > bass_average * DROP_RATIO;
+        let drop = armed && rested && surge;
+        if drop {
+            self.last_drop = Some(now);
+            self.building_until = None;
+            self.history.clear();
+        }
+        drop
+    }
+
+    /// Least-squares slope of the energy over the history, per second.
+    fn energy_slope(&self) -> f32 {
+        let Some(&(start, _, _)) = self.history.front() else {
+            return 0.0;
+        };
+        slope(
+            self.history
+                .iter()
+                .map(|(at, energy, _)| (at.duration_since(start).as_secs_f32(), *energy)),
+        )
+    }
+}
+
+/// Least-squares slope of `(seconds, value)` points, per second.
+fn slope(points: impl Iterator<Item = (f32, f32)> + Clone) -> f32 {
+    let (count, sum_t, sum_v) = points
+        .clone()
+        .fold((0, 0.0, 0.0), |(n, t, v), p| (n + 1, t + p.0, v + p.1));
+    if count == 0 {
+        return 0.0;
+    }
+    let (mean_t, mean_v) = (sum_t / count as f32, sum_v / count as f32);
+    let (covariance, variance) = points.fold((0.0, 0.0), |(c, v), (t, value)| {
+        (
+            c + (t - mean_t) * (value - mean_v),
+            v + (t - mean_t) * (t - mean_t),
+        )
+    });
+    if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    }
+}
+
+/// The band summaries of the last [`HISTORY_WINDOW`], oldest first, for
+/// effects that follow trends (a build-up, a fading ending) rather than
+/// the current frame alone.
+///
+/// Kept by the render loop and handed to effects in
+/// [`AnalysisFrame::history`](crate::audio_interface::AnalysisFrame::history).
+#[derive(Debug, Clone, Default)]
+pub struct SpectrumHistory {
+    frames: VecDeque<(Instant, AudioSpectrum)>,
+}
+
+impl SpectrumHistory {
+    /// Adds the spectrum of the frame at `now` and forgets those older than
+    /// [`HISTORY_WINDOW`].
+    pub fn push(&mut self, spectrum: AudioSpectrum, now: Instant) {
+        while self
+            .frames
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) > HISTORY_WINDOW)
+        {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((now, spectrum));
+    }
+
+    /// Spectra, oldest first.
+    pub fn spectra(&self) -> impl DoubleEndedIterator<Item = &AudioSpectrum> + '_ {
+        self.frames.iter().map(|(_, spectrum)| spectrum)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The same history with every spectrum passed through `f`.
+    pub fn map(&self, f: impl Fn(&AudioSpectrum) -> AudioSpectrum) -> Self {
+        Self {
+            frames: self.frames.iter().map(|(at, s)| (*at, f(s))).collect(),
+        }
+    }
+
+    /// Time between the oldest and the newest spectrum.
+    pub fn span(&self) -> Duration {
+        match (self.frames.front(), self.frames.back()) {
+            (Some((first, _)), Some((last, _))) => last.saturating_duration_since(*first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Average of every band.
+    pub fn average(&self) -> AudioSpectrum {
+        let n = self.frames.len().max(1) as f32;
+        let sum = self
+            .spectra()
+            .fold(AudioSpectrum::default(), |sum, s| AudioSpectrum {
+                bass: sum.bass + s.bass,
+                mids: sum.mids + s.mids,
+                highs: sum.highs + s.highs,
+                energy: sum.energy + s.energy,
+            });
+        AudioSpectrum {
+            bass: sum.bass / n,
+            mids: sum.mids / n,
+            highs: sum.highs / n,
+            energy: sum.energy / n,
+        }
+    }
+
+    /// How fast `level` changes per second over the window (least squares):
+    /// positive while it builds up, negative while it fades out.
+    ///
+    /// ```
+    /// # use hue_flow_core::analysis::SpectrumHistory;
+    /// # let history = SpectrumHistory::default();
+    /// let rising = history.trend(|s| s.energy) > 0.1;
+    /// ```
+    pub fn trend(&self, level: impl Fn(&AudioSpectrum) -> f32) -> f32 {
+        let Some(&(start, _)) = self.frames.front() else {
+            return 0.0;
+        };
+        slope(
+            self.frames
+                .iter()
+                .map(|(at, s)| (at.duration_since(start).as_secs_f32(), level(s))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / sample_rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "fft")]
+    fn test_tones_land_in_their_band() {
+        for (freq, band) in [(100.0, 0), (1000.0, 1), (8000.0, 2)] {
+            let mut analyzer = FftAnalyzer::new(44_100);
+            let s = analyzer.process(&sine(freq, 44_100));
+            let levels = [s.bass, s.mids, s.highs];
+            let loudest = (0..3)
+                .max_by(|a, b| levels[*a].total_cmp(&levels[*b]))
+                .unwrap();
+            assert_eq!(loudest, band, "{} Hz gave {:?}", freq, levels);
+        }
+    }
+
+    #[test]
+    fn test_overlap_hops() {
+        let mut overlap = OverlapAnalyzer::new(44_100, DEFAULT_HOP);
+        assert!(overlap.push(&[0.0; DEFAULT_HOP - 1]).is_none());
+        // Completing the hop analyzes once; a tone fills the window hop by hop
+        assert!(overlap.push(&[0.0]).is_some());
+
+        let tone = sine(100.0, 44_100);
+        let s = overlap.push(&tone).unwrap();
+        assert!(s.bass > s.mids && s.bass > s.highs, "{:?}", s);
+        assert_eq!(overlap.latest().bass, s.bass);
+
+        // A hop of FFT_SIZE analyzes independent blocks
+        let mut blocks = OverlapAnalyzer::new(44_100, FFT_SIZE);
+        assert!(blocks.push(&tone[..FFT_SIZE - 1]).is_none());
+        assert!(blocks.push(&tone[FFT_SIZE - 1..]).is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "fft")]
+    fn test_bins_follow_the_tone() {
+        let mut analyzer = FftAnalyzer::new(48_000);
+        analyzer.process(&sine(1500.0, 48_000));
+        let bins = analyzer.bins();
+        assert_eq!(bins.len(), FFT_SIZE / 2 + 1);
+        let loudest = (0..bins.len())
+            .max_by(|a, b| bins[*a].total_cmp(&bins[*b]))
+            .unwrap();
+        assert!((loudest as f32 * analyzer.bin_hz() - 1500.0).abs() < analyzer.bin_hz());
+        assert_eq!(bins[loudest], 1.0);
+    }
+
+    #[test]
+    fn test_beats_on_bass_hits() {
+        let mut beats = BeatDetector::default();
+        let start = Instant::now();
+        let quiet = AudioSpectrum {
+            bass: 0.2,
+            ..Default::default()
+        };
+        let hit = AudioSpectrum {
+            bass: 0.9,
+            ..Default::default()
+        };
+        for i in 0..10 {
+            let at = start + Duration::from_millis(i * 50);
+            assert!(!beats.update(&quiet, at).onset);
+        }
+        let beat = beats.update(&hit, start + Duration::from_millis(500));
+        assert!(beat.onset && beat.strength > 0.5, "{:?}", beat);
+        // Still loud 50ms later: too soon for another beat
+        assert!(!beats.update(&hit, start + Duration::from_millis(550)).onset);
+    }
+
+    #[test]
+    fn test_track_changes_after_gaps() {
+        let mut tracks = TrackChangeDetector::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let sound = AudioSpectrum {
+            energy: 0.5,
+            ..Default::default()
+        };
+        let silence = AudioSpectrum::default();
+        // The first track starting is no change
+        assert!(!tracks.update(&silence, at(0)));
+        assert!(!tracks.update(&sound, at(2000)));
+        // A short break within the track
+        assert!(!tracks.update(&silence, at(3000)));
+        assert!(!tracks.update(&sound, at(3500)));
+        // The gap to the next track
+        assert!(!tracks.update(&silence, at(4000)));
+        assert!(tracks.update(&sound, at(6000)));
+        assert!(!tracks.update(&sound, at(6050)));
+    }
+
+    #[test]
+    fn test_drop_after_buildup() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let frame = |energy, bass| AudioSpectrum {
+            energy,
+            bass,
+            ..Default::default()
+        };
+
+        // Steady music: a bass hit is just a beat
+        let mut drops = DropDetector::default();
+        for i in 0..100 {
+            assert!(!drops.update(&frame(0.5, 0.3), at(i * 50)));
+        }
+        assert!(!drops.update(&frame(0.8, 0.9), at(5000)));
+
+        // Energy climbing over 5 s with thin bass, then the bass slams in
+        let mut drops = DropDetector::default();
+        for i in 0..100 {
+            let energy = 0.2 + 0.006 * i as f32;
+            assert!(!drops.update(&frame(energy, 0.2), at(i * 50)));
+        }
+        assert!(drops.update(&frame(0.9, 0.9), at(5000)));
+        // Once per drop
+        assert!(!drops.update(&frame(0.9, 0.9), at(5050)));
+    }
+
+    #[test]
+    fn test_history_trends() {
+        let start = Instant::now();
+        let mut history = SpectrumHistory::default();
+        assert_eq!(history.trend(|s| s.energy), 0.0);
+
+        // 3 s of energy falling by 0.1 per second at 20 FPS
+        for i in 0..60 {
+            let spectrum = AudioSpectrum {
+                energy: 1.0 - 0.1 * i as f32 / 20.0,
+                bass: 0.5,
+                ..Default::default()
+            };
+            history.push(spectrum, start + Duration::from_millis(i * 50));
+        }
+        // Only the last 2 s are kept
+        assert_eq!(history.len(), 41);
+        assert_eq!(history.span(), HISTORY_WINDOW);
+        assert!((history.trend(|s| s.energy) + 0.1).abs() < 1e-3);
+        assert!(history.trend(|s| s.bass).abs() < 1e-6);
+        assert!((history.average().bass - 0.5).abs() < 1e-6);
+        assert!(history.spectra().next_back().unwrap().energy < 0.71);
+    }
+
+    #[test]
+    fn test_silence_is_dark() {
+        let mut analyzer = DefaultAnalyzer::new(48_000);
+        let s = analyzer.process(&[0.0; FFT_SIZE]);
+        assert_eq!((s.bass, s.mids, s.highs, s.energy), (0.0, 0.0, 0.0, 0.0));
+        // Short input is zero-padded rather than rejected
+        let s = analyzer.process(&[0.0; 10]);
+        assert_eq!(s.energy, 0.0);
+    }
+}
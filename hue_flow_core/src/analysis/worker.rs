@@ -0,0 +1,149 @@
+use super::OverlapAnalyzer;
+use crate::audio_input::InputFilter;
+use crate::audio_interface::AnalysisFrame;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// Sample blocks waiting for the worker; more are dropped rather than
+/// blocking capture.
+pub const BLOCK_QUEUE: usize = 64;
+/// Analyzed frames waiting for the render loop.
+pub const FRAME_QUEUE: usize = 16;
+
+/// An analysis result and when the newest samples in it were captured.
+#[derive(Debug, Clone)]
+pub struct TimedFrame {
+    pub frame: AnalysisFrame,
+    pub captured: Instant,
+}
+
+/// Sending side of an [`AnalysisWorker`], for the capture stage.
+#[derive(Clone)]
+pub struct AnalysisFeed {
+    blocks: SyncSender<(Instant, Vec<f32>)>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AnalysisFeed {
+    /// Queues a block of samples captured at `captured`. Never blocks;
+    /// returns `false` when the queue is full and the block was dropped.
+    pub fn push(&self, samples: Vec<f32>, captured: Instant) -> bool {
+        match self.blocks.try_send((captured, samples)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+/// Filters and analyzes samples on a thread of its own, so large windows
+/// or small hops cannot stall capture or rendering.
+///
+/// Capture pushes blocks through an [`AnalysisFeed`], the render loop takes
+/// the newest [`TimedFrame`]; both queues are bounded. The thread ends once
+/// every feed is dropped.
+pub struct AnalysisWorker {
+    feed: AnalysisFeed,
+    frames: Receiver<TimedFrame>,
+    latest: Option<TimedFrame>,
+}
+
+impl AnalysisWorker {
+    pub fn spawn(mut analyzer: OverlapAnalyzer, mut filter: InputFilter) -> std::io::Result<Self> {
+        let (blocks, block_rx) = mpsc::sync_channel::<(Instant, Vec<f32>)>(BLOCK_QUEUE);
+        let (frame_tx, frames) = mpsc::sync_channel(FRAME_QUEUE);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let worker_dropped = dropped.clone();
+        thread::Builder::new()
+            .name("hueflow-analysis".to_string())
+            .spawn(move || {
+                while let Ok((mut captured, mut samples)) = block_rx.recv() {
+                    // Catch up on everything queued in one go
+                    while let Ok((at, more)) = block_rx.try_recv() {
+                        samples.extend(more);
+                        captured = at;
+                    }
+                    filter.process(&mut samples);
+                    let Some(spectrum) = analyzer.push(&samples) else {
+                        continue;
+                    };
+                    let frame = AnalysisFrame {
+                        spectrum,
+                        bins: analyzer.bins().to_vec(),
+                        bin_hz: analyzer.bin_hz(),
+                        ..Default::default()
+                    };
+                    match frame_tx.try_send(TimedFrame { frame, captured }) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            worker_dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(TrySendError::Disconnected(_)) => break,
+                    }
+                }
+            })?;
+        Ok(Self {
+            feed: AnalysisFeed { blocks, dropped },
+            frames,
+            latest: None,
+        })
+    }
+
+    /// A feed for the capture stage.
+    pub fn feed(&self) -> AnalysisFeed {
+        self.feed.clone()
+    }
+
+    /// The newest analyzed frame, `None` until the first one is done.
+    pub fn latest(&mut self) -> Option<&TimedFrame> {
+        if let Some(newest) = self.frames.try_iter().last() {
+            self.latest = Some(newest);
+        }
+        self.latest.as_ref()
+    }
+
+    /// Sample blocks and frames dropped because a queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.feed.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_input::ANALYSIS_RATE;
+    use std::time::Duration;
+
+    #[test]
+    fn test_worker_analyzes_in_background() {
+        let analyzer = OverlapAnalyzer::new(ANALYSIS_RATE, 256);
+        let mut worker = AnalysisWorker::spawn(analyzer, InputFilter::default()).unwrap();
+        assert!(worker.latest().is_none());
+
+        let feed = worker.feed();
+        let captured = Instant::now();
+        let tone: Vec<f32> = (0..2048)
+            .map(|i| (std::f32::consts::TAU * 100.0 * i as f32 / ANALYSIS_RATE as f32).sin())
+            .collect();
+        assert!(feed.push(tone, captured));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while worker.latest().is_none() {
+            assert!(Instant::now() < deadline, "no frame analyzed");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let latest = worker.latest().unwrap();
+        assert_eq!(latest.captured, captured);
+        assert!(
+            latest.frame.spectrum.bass > 0.5,
+            "{:?}",
+            latest.frame.spectrum
+        );
+        assert_eq!(worker.dropped(), 0);
+    }
+}
@@ -0,0 +1,247 @@
+//! Backups of entertainment areas, so a carefully placed layout survives a
+//! bridge reset.
+//!
+//! A backup lists every light of the area with its positions (one per
+//! channel; gradient strips have several). Lights are stored by their
+//! entertainment service ID and by device name: after a reset the bridge
+//! hands out new IDs, and the name is how a light is found again.
+use crate::api::build_client;
+use crate::api::error::HueError;
+use crate::api::groups::{check_area_name, save_area};
+use crate::api::v2::get_resources_with;
+use crate::api::v2::models::{
+    Device, Entertainment, EntertainmentConfiguration, Locations, Position, ResourceLink,
+    ServiceLocation,
+};
+use crate::models::HueConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Format version written to new backups.
+pub const AREA_BACKUP_VERSION: u32 = 1;
+
+/// An entertainment area as saved by `hueflow area export`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AreaBackup {
+    pub version: u32,
+    pub name: String,
+    /// `screen`, `music`, `3dspace` or `other`.
+    pub configuration_type: String,
+    pub lights: Vec<LightPlacement>,
+}
+
+/// Where one light of a backed-up area is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightPlacement {
+    /// Entertainment service ID on the bridge the backup was made on.
+    pub service: String,
+    /// Name of the light's device, to find it again after a reset.
+    pub device: String,
+    pub positions: Vec<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equalization_factor: Option<f64>,
+}
+
+/// An entertainment service with the name of its device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceName {
+    pub service: String,
+    pub device: String,
+}
+
+/// The areas and entertainment services on the bridge, the services with
+/// their device names. The three lists are fetched at once.
+async fn fetch_layout(
+    config: &HueConfig,
+) -> Result<(Vec<EntertainmentConfiguration>, Vec<ServiceName>), HueError> {
+    let client = build_client()?;
+    let (areas, services, devices) = tokio::try_join!(
+        get_resources_with::<EntertainmentConfiguration>(&client, config),
+        get_resources_with::<Entertainment>(&client, config),
+        get_resources_with::<Device>(&client, config),
+    )?;
+    let devices: HashMap<String, String> = devices
+        .into_iter()
+        .map(|d| (d.id, d.metadata.name))
+        .collect();
+    let services = services
+        .into_iter()
+        .map(|e| ServiceName {
+            device: devices.get(&e.owner.rid).cloned().unwrap_or_default(),
+            service: e.id,
+        })
+        .collect();
+    Ok((areas, services))
+}
+
+/// The backup of `area`.
+pub fn area_backup(area: &EntertainmentConfiguration, services: &[ServiceName]) -> AreaBackup {
+    let lights = area
+        .locations
+        .iter()
+        .flat_map(|l| &l.service_locations)
+        .map(|location| LightPlacement {
+            service: location.service.rid.clone(),
+            device: services
+                .iter()
+                .find(|s| s.service == location.service.rid)
+                .map(|s| s.device.clone())
+                .unwrap_or_default(),
+            positions: location.positions.clone(),
+            equalization_factor: location.equalization_factor,
+        })
+        .collect();
+    AreaBackup {
+        version: AREA_BACKUP_VERSION,
+        name: area.metadata.name.clone(),
+        configuration_type: area.configuration_type.clone(),
+        lights,
+    }
+}
+
+/// The service locations restoring `backup` on a bridge with `services`,
+/// and the device names of the lights that were not found.
+///
+/// A light is found by its service ID, else by its device name if exactly
+/// one device has that name.
+pub fn restore_locations(
+    backup: &AreaBackup,
+    services: &[ServiceName],
+) -> (Vec<ServiceLocation>, Vec<String>) {
+    let mut locations = Vec::new();
+    let mut missing = Vec::new();
+    for light in &backup.lights {
+        let by_id = services.iter().find(|s| s.service == light.service);
+        let by_name = || {
+            let mut named = services
+                .iter()
+                .filter(|s| !light.device.is_empty() && s.device == light.device);
+            named.next().filter(|_| named.next().is_none())
+        };
+        match by_id.or_else(by_name) {
+            Some(found) => locations.push(ServiceLocation {
+                service: ResourceLink {
+                    rid: found.service.clone(),
+                    rtype: "entertainment".to_string(),
+                },
+                positions: light.positions.clone(),
+                equalization_factor: light.equalization_factor,
+            }),
+            None => missing.push(if light.device.is_empty() {
+                light.service.clone()
+            } else {
+                light.device.clone()
+            }),
+        }
+    }
+    (locations, missing)
+}
+
+/// Backs up the entertainment area with ID `area_id`.
+pub async fn export_area(config: &HueConfig, area_id: &str) -> Result<AreaBackup, HueError> {
+    let (configs, services) = fetch_layout(config).await?;
+    let area = configs
+        .iter()
+        .find(|c| c.id == area_id)
+        .ok_or_else(|| HueError::ApiError(format!("Entertainment area {} not found", area_id)))?;
+    Ok(area_backup(area, &services))
+}
+
+/// Restores `backup` as the area named `name`, creating it or updating the
+/// area of that name. Returns the area's ID and the lights that were not
+/// found; fails if none were.
+pub async fn import_area(
+    config: &HueConfig,
+    backup: &AreaBackup,
+    name: &str,
+) -> Result<(String, Vec<String>), HueError> {
+    check_area_name(name)?;
+    if backup.version > AREA_BACKUP_VERSION {
+        return Err(HueError::Other(format!(
+            "Area backup version {} is newer than this HueFlow supports ({})",
+            backup.version, AREA_BACKUP_VERSION
+        )));
+    }
+    let (configs, services) = fetch_layout(config).await?;
+    let (service_locations, missing) = restore_locations(backup, &services);
+    if service_locations.is_empty() {
+        return Err(HueError::Other(format!(
+            "None of the lights in '{}' were found on the bridge",
+            backup.name
+        )));
+    }
+    let configuration_type = if backup.configuration_type.is_empty() {
+        "3dspace"
+    } else {
+        &backup.configuration_type
+    };
+    let id = save_area(
+        config,
+        &configs,
+        name,
+        configuration_type,
+        Locations { service_locations },
+    )
+    .await?;
+    Ok((id, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn service(service: &str, device: &str) -> ServiceName {
+        ServiceName {
+            service: service.to_string(),
+            device: device.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let area: EntertainmentConfiguration = serde_json::from_value(json!({
+            "id": "tv",
+            "metadata": { "name": "TV" },
+            "configuration_type": "screen",
+            "channels": [],
+            "locations": { "service_locations": [
+                {
+                    "service": { "rid": "strip", "rtype": "entertainment" },
+                    "positions": [{ "x": -0.5, "y": 1.0, "z": 0.0 }, { "x": 0.5, "y": 1.0, "z": 0.0 }],
+                    "equalization_factor": 0.8
+                },
+                {
+                    "service": { "rid": "bulb", "rtype": "entertainment" },
+                    "positions": [{ "x": 1.0, "y": 0.0, "z": 0.0 }]
+                }
+            ]}
+        }))
+        .unwrap();
+        let backup = area_backup(
+            &area,
+            &[service("strip", "TV strip"), service("bulb", "Floor lamp")],
+        );
+        assert_eq!(backup.name, "TV");
+        assert_eq!(backup.lights[0].device, "TV strip");
+        assert_eq!(backup.lights[0].positions.len(), 2);
+
+        let json = serde_json::to_string(&backup).unwrap();
+        assert_eq!(serde_json::from_str::<AreaBackup>(&json).unwrap(), backup);
+
+        // After a reset: the strip has a new ID, the lamp is gone
+        let (locations, missing) = restore_locations(&backup, &[service("new-strip", "TV strip")]);
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].service.rid, "new-strip");
+        assert_eq!(locations[0].positions, backup.lights[0].positions);
+        assert_eq!(locations[0].equalization_factor, Some(0.8));
+        assert_eq!(missing, ["Floor lamp"]);
+
+        // Two devices of the same name cannot be told apart
+        let (locations, _) = restore_locations(
+            &backup,
+            &[service("a", "TV strip"), service("b", "TV strip")],
+        );
+        assert!(locations.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! Local cache of the entertainment areas' layouts (channels and their
+//! positions), so a stream can start without waiting for the bridge's REST
+//! API, or while it is briefly unreachable.
+//!
+//! A fresh cache is used right away and refreshed in the background for the
+//! next start; a stale one only when the bridge cannot be asked.
+use crate::api::error::HueError;
+use crate::api::groups::{get_entertainment_groups, GroupInfo};
+use crate::config::{config_dir, write_atomic};
+use crate::models::HueConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File name of the cache in the config directory.
+pub const CACHE_FILE: &str = "groups.json";
+/// Age up to which the cache is used without asking the bridge first.
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The areas of one bridge as last fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupCache {
+    /// Bridge ID (or IP for configs without one) the areas belong to.
+    pub bridge: String,
+    /// Fetch time, in seconds since the Unix epoch.
+    pub fetched: u64,
+    pub groups: Vec<GroupInfo>,
+}
+
+impl GroupCache {
+    /// A cache of `groups`, fetched now from the bridge of `config`.
+    pub fn new(config: &HueConfig, groups: Vec<GroupInfo>) -> Self {
+        Self {
+            bridge: bridge_key(config),
+            fetched: unix_now(),
+            groups,
+        }
+    }
+
+    /// Time since the areas were fetched.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.fetched))
+    }
+
+    /// True if the cache is of the bridge of `config`.
+    pub fn is_for(&self, config: &HueConfig) -> bool {
+        self.bridge == bridge_key(config)
+    }
+}
+
+fn bridge_key(config: &HueConfig) -> String {
+    if config.bridge_id.is_empty() {
+        config.bridge_ip.clone()
+    } else {
+        config.bridge_id.clone()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Default cache file: `<config dir>/groups.json`.
+pub fn cache_path() -> PathBuf {
+    config_dir().join(CACHE_FILE)
+}
+
+/// Loads the cache at `path`; `None` when there is none or it does not
+/// parse.
+pub fn load(path: &Path) -> Option<GroupCache> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            tracing::warn!("Ignoring the group cache {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Writes `cache` to `path`.
+pub fn save(path: &Path, cache: &GroupCache) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomic(path, serde_json::to_string_pretty(cache)?.as_bytes())
+}
+
+/// Fetches the areas from the bridge and caches them at `path`.
+pub async fn refresh(config: &HueConfig, path: &Path) -> Result<Vec<GroupInfo>, HueError> {
+    let groups = get_entertainment_groups(config).await?;
+    if let Err(e) = save(path, &GroupCache::new(config, groups.clone())) {
+        tracing::warn!("Cannot write the group cache {}: {}", path.display(), e);
+    }
+    Ok(groups)
+}
+
+/// The areas of the bridge of `config`, from the cache at `path` where
+/// possible.
+///
+/// A cache younger than `ttl` is returned at once and refreshed in the
+/// background. Otherwise the bridge is asked, falling back to an older
+/// cache if it fails. With `offline`, the bridge is never asked and any
+/// cache is used, however old.
+pub async fn cached_groups(
+    config: &HueConfig,
+    path: &Path,
+    ttl: Duration,
+    offline: bool,
+) -> Result<Vec<GroupInfo>, HueError> {
+    let cache = load(path).filter(|c| c.is_for(config));
+    match cache {
+        Some(cache) if offline => Ok(cache.groups),
+        None if offline => Err(HueError::Other(format!(
+            "No cached entertainment groups in {}; run once without --offline",
+            path.display()
+        ))),
+        Some(cache) if cache.age() < ttl => {
+            let (config, path) = (config.clone(), path.to_path_buf());
+            tokio::spawn(async move {
+                if let Err(e) = refresh(&config, &path).await {
+                    tracing::debug!("Group cache refresh failed: {}", e);
+                }
+            });
+            Ok(cache.groups)
+        }
+        cache => match refresh(config, path).await {
+            Ok(groups) => Ok(groups),
+            Err(e) => match cache {
+                Some(cache) => {
+                    tracing::warn!(
+                        "Bridge did not answer ({}), using groups cached {} min ago",
+                        e,
+                        cache.age().as_secs() / 60
+                    );
+                    Ok(cache.groups)
+                }
+                None => Err(e),
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LightNode;
+
+    fn config(bridge_id: &str) -> HueConfig {
+        HueConfig {
+            bridge_ip: "192.0.2.1".to_string(),
+            bridge_id: bridge_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn group() -> GroupInfo {
+        GroupInfo {
+            id: "tv".to_string(),
+            name: "TV".to_string(),
+            lights: vec![LightNode {
+                id: "strip".to_string(),
+                channel_id: 0,
+                x: -0.5,
+                y: 1.0,
+                z: 0.0,
+                segment: 0,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_groups() {
+        let dir = std::env::temp_dir().join(format!("hueflow-cache-{}", std::process::id()));
+        let path = dir.join(CACHE_FILE);
+        let bridge = config("001788fffe000000");
+        assert!(cached_groups(&bridge, &path, CACHE_TTL, true)
+            .await
+            .is_err());
+
+        save(&path, &GroupCache::new(&bridge, vec![group()])).unwrap();
+        let groups = cached_groups(&bridge, &path, CACHE_TTL, true)
+            .await
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].lights[0].x, -0.5);
+
+        // Another bridge's areas are not used
+        let cache = load(&path).unwrap();
+        assert!(cache.is_for(&bridge));
+        assert!(!cache.is_for(&config("001788fffe111111")));
+        assert!(cache.age() < Duration::from_secs(5));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
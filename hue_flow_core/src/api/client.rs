@@ -1,9 +1,66 @@
-use crate::api::error::HueError;
-use crate::models::HueConfig;
+use crate::api::error::{HueError, V1ResponseItem};
+use crate::api::retry::retry;
+use crate::api::url_host;
+use crate::models::{HueConfig, RetryPolicy, CONFIG_VERSION};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
 
 pub struct HueClient;
 
+/// How long the bridge accepts new registrations after the link button is pressed.
+pub const LINK_WINDOW: Duration = Duration::from_secs(30);
+
+/// Interval between registration attempts while waiting for the link button.
+pub const LINK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Oldest bridge firmware supporting the v2 Entertainment API.
+pub const MIN_ENTERTAINMENT_SWVERSION: u64 = 1948086000;
+
+/// Public part of the bridge configuration returned by `GET /api/config`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BridgeConfig {
+    pub name: String,
+    #[serde(rename = "bridgeid")]
+    pub bridge_id: String,
+    #[serde(rename = "modelid")]
+    pub model_id: String,
+    pub swversion: String,
+    #[serde(rename = "apiversion")]
+    pub api_version: String,
+}
+
+impl BridgeConfig {
+    /// Returns an error when the bridge cannot run v2 entertainment streaming.
+    pub fn check_compatibility(&self) -> Result<(), HueError> {
+        check_compatibility(&self.model_id, &self.swversion)
+    }
+}
+
+/// Checks a bridge model/firmware pair against the v2 Entertainment API requirements.
+pub fn check_compatibility(model_id: &str, swversion: &str) -> Result<(), HueError> {
+    // BSB001 is the round v1 bridge, which has no entertainment support at all
+    if model_id == "BSB001" {
+        return Err(HueError::UnsupportedBridge(
+            "the v1 (round) Hue Bridge does not support entertainment streaming".to_string(),
+        ));
+    }
+
+    let version: u64 = swversion.parse().map_err(|_| {
+        HueError::UnsupportedBridge(format!("unrecognized firmware version '{}'", swversion))
+    })?;
+
+    if version < MIN_ENTERTAINMENT_SWVERSION {
+        return Err(HueError::UnsupportedBridge(format!(
+            "firmware {} is too old for the v2 Entertainment API (need {} or newer). \
+             Update the bridge in the Hue app and try again.",
+            version, MIN_ENTERTAINMENT_SWVERSION
+        )));
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize)]
 struct RegisterBody<'a> {
     devicetype: &'a str,
@@ -16,24 +73,11 @@ struct RegisterSuccess {
     clientkey: String,
 }
 
-#[derive(Deserialize)]
-struct HueErrorResponse {
-    #[serde(rename = "type")]
-    error_type: i32,
-    description: String,
-}
-
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum RegisterResponseItem {
-    Success { success: RegisterSuccess },
-    Error { error: HueErrorResponse },
-}
-
 impl HueClient {
     /// Registers a new application with the Hue Bridge.
-    /// Returns a HueConfig with username and client_key.
-    /// Note: application_id must be fetched separately via get_application_id().
+    /// Returns a HueConfig with username, client_key and application_id.
+    /// The application_id stays empty if the bridge does not report it; fetch
+    /// it later via get_application_id().
     pub async fn register_user(ip: &str, devicename: &str) -> Result<HueConfig, HueError> {
         let client = reqwest::Client::builder()
             .danger_accept_invalid_certs(true)
@@ -44,29 +88,30 @@ impl HueClient {
             generateclientkey: true,
         };
 
-        let url = format!("https://{}/api", ip);
+        let url = format!("https://{}/api", url_host(ip));
         let resp = client.post(&url).json(&body).send().await?;
 
-        let items: Vec<RegisterResponseItem> = resp.json().await?;
+        let items: Vec<V1ResponseItem<RegisterSuccess>> = resp.json().await?;
 
-        if let Some(item) = items.first() {
+        if let Some(item) = items.into_iter().next() {
             match item {
-                RegisterResponseItem::Success { success } => {
+                V1ResponseItem::Success { success } => {
+                    let application_id = Self::get_application_id(ip, &success.username)
+                        .await
+                        .unwrap_or_else(|e| {
+                            tracing::warn!("Registered, but no application ID yet: {}", e);
+                            String::new()
+                        });
                     Ok(HueConfig {
+                        version: CONFIG_VERSION,
                         bridge_ip: ip.to_string(),
-                        username: success.username.clone(),
-                        client_key: success.clientkey.clone(),
-                        application_id: String::new(), // Must be fetched via get_application_id()
-                        entertainment_group_id: String::new(),
+                        username: success.username,
+                        client_key: success.clientkey,
+                        application_id,
+                        ..Default::default()
                     })
                 }
-                RegisterResponseItem::Error { error } => {
-                    if error.error_type == 101 {
-                        Err(HueError::LinkButtonNotPressed)
-                    } else {
-                        Err(HueError::ApiError(error.description.clone()))
-                    }
-                }
+                V1ResponseItem::Error { error } => Err(error.into()),
             }
         } else {
             Err(HueError::ApiError(
@@ -75,17 +120,58 @@ impl HueClient {
         }
     }
 
+    /// Registers as soon as the link button is pressed, giving up after `timeout`.
+    ///
+    /// The `linkbutton` flag in the bridge config is only visible with an app
+    /// key, which we do not have yet, so the registration request itself is
+    /// the probe. Polling every [`LINK_POLL_INTERVAL`] completes registration
+    /// within a second of the press. `on_wait` receives the remaining time
+    /// before each retry, e.g. for a countdown. Returns `None` on timeout.
+    /// Network failures are retried according to `policy`.
+    pub async fn register_when_linked(
+        ip: &str,
+        devicename: &str,
+        timeout: Duration,
+        policy: &RetryPolicy,
+        mut on_wait: impl FnMut(Duration),
+    ) -> Result<Option<HueConfig>, HueError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match retry(policy, "Registration", || {
+                Self::register_user(ip, devicename)
+            })
+            .await
+            {
+                Ok(config) => return Ok(Some(config)),
+                Err(HueError::LinkButtonNotPressed) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+                    on_wait(remaining);
+                    tokio::time::sleep(LINK_POLL_INTERVAL.min(remaining)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     /// Fetches the hue-application-id from the bridge.
     /// This ID is required as the PSK Identity for DTLS streaming.
     ///
     /// The bridge returns the application ID in the response header "hue-application-id"
     /// when calling GET /auth/v1 with the hue-application-key header.
     pub async fn get_application_id(ip: &str, username: &str) -> Result<String, HueError> {
+        Self::application_id_from(&format!("https://{}", url_host(ip)), username).await
+    }
+
+    /// [`get_application_id`](Self::get_application_id) against any base URL.
+    async fn application_id_from(base_url: &str, username: &str) -> Result<String, HueError> {
         let client = reqwest::Client::builder()
             .danger_accept_invalid_certs(true)
             .build()?;
 
-        let url = format!("https://{}/auth/v1", ip);
+        let url = format!("{}/auth/v1", base_url);
         let resp = client
             .get(&url)
             .header("hue-application-key", username)
@@ -111,6 +197,26 @@ impl HueClient {
 
         Ok(app_id)
     }
+
+    /// Fetches the public bridge configuration (model, firmware version, ...).
+    /// This endpoint does not require authentication.
+    pub async fn get_bridge_config(ip: &str) -> Result<BridgeConfig, HueError> {
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()?;
+
+        let url = format!("https://{}/api/config", url_host(ip));
+        let resp = client.get(&url).send().await?;
+
+        if !resp.status().is_success() {
+            return Err(HueError::ApiError(format!(
+                "Failed to get bridge config: HTTP {}",
+                resp.status()
+            )));
+        }
+
+        Ok(resp.json().await?)
+    }
 }
 
 #[cfg(test)]
@@ -127,8 +233,8 @@ mod tests {
             }
         }]);
 
-        let items: Vec<RegisterResponseItem> = serde_json::from_value(json).unwrap();
-        if let RegisterResponseItem::Success { success } = &items[0] {
+        let items: Vec<V1ResponseItem<RegisterSuccess>> = serde_json::from_value(json).unwrap();
+        if let V1ResponseItem::Success { success } = &items[0] {
             assert_eq!(success.username, "myuser");
             assert_eq!(success.clientkey, "mykey");
         } else {
@@ -146,11 +252,82 @@ mod tests {
             }
         }]);
 
-        let items: Vec<RegisterResponseItem> = serde_json::from_value(json).unwrap();
-        if let RegisterResponseItem::Error { error } = &items[0] {
+        let items: Vec<V1ResponseItem<RegisterSuccess>> = serde_json::from_value(json).unwrap();
+        if let V1ResponseItem::Error { error } = &items[0] {
             assert_eq!(error.error_type, 101);
         } else {
             panic!("Expected error");
         }
     }
+
+    #[tokio::test]
+    async fn test_get_application_id() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let bridge = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/auth/v1"))
+            .and(header("hue-application-key", "myuser"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("hue-application-id", "0ea2d8a5-4c57-46ab-9d7a-52a7ea5c0b38"),
+            )
+            .mount(&bridge)
+            .await;
+        // Unknown keys are rejected
+        Mock::given(path("/auth/v1"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&bridge)
+            .await;
+
+        let id = HueClient::application_id_from(&bridge.uri(), "myuser")
+            .await
+            .unwrap();
+        assert_eq!(id, "0ea2d8a5-4c57-46ab-9d7a-52a7ea5c0b38");
+
+        let err = HueClient::application_id_from(&bridge.uri(), "other")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("403"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_get_application_id_missing_header() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let bridge = MockServer::start().await;
+        Mock::given(path("/auth/v1"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&bridge)
+            .await;
+
+        let err = HueClient::application_id_from(&bridge.uri(), "myuser")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, HueError::ApiError(ref m) if m.contains("hue-application-id")));
+    }
+
+    #[test]
+    fn test_bridge_compatibility() {
+        let config: BridgeConfig = serde_json::from_value(json!({
+            "name": "Hue Bridge",
+            "datastoreversion": "163",
+            "swversion": "1967054020",
+            "apiversion": "1.67.0",
+            "mac": "ec:b5:fa:00:00:00",
+            "bridgeid": "ECB5FAFFFE000000",
+            "factorynew": false,
+            "replacesbridgeid": null,
+            "modelid": "BSB002",
+            "starterkitid": ""
+        }))
+        .unwrap();
+        assert!(config.check_compatibility().is_ok());
+
+        assert!(check_compatibility("BSB002", "1941132080").is_err());
+        assert!(check_compatibility("BSB001", "1967054020").is_err());
+        assert!(check_compatibility("BSB002", "garbage").is_err());
+    }
 }
@@ -1,8 +1,28 @@
+use crate::api::client::HueClient;
 use crate::api::error::HueError;
+use crate::api::retry::retry;
+use crate::models::RetryPolicy;
 use reqwest::Client;
 use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
 
+/// mDNS multicast groups and port.
+const MDNS_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+const MDNS_PORT: u16 = 5353;
+/// DNS record type of an IPv6 address.
+const TYPE_AAAA: u16 = 28;
+
+/// Service name the bridge announces via mDNS, in DNS label encoding.
+const HUE_SERVICE: &[u8] = b"\x04_hue\x04_tcp\x05local\x00";
+
+/// How long each candidate gets to answer the reachability probe.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A candidate as reported by the meethue.com N-UPnP API.
 #[derive(Deserialize, Debug, Clone)]
 pub struct DiscoveredBridge {
     #[serde(rename = "internalipaddress")]
@@ -10,9 +30,27 @@ pub struct DiscoveredBridge {
     pub id: String,
 }
 
-/// Discover Hue Bridges using the meethue.com N-UPnP API
-/// Returns all discovered bridges, sorted by reachability
-pub async fn discover_bridges() -> Result<Vec<DiscoveredBridge>, HueError> {
+/// A discovered bridge, with the details its `/api/config` reported.
+/// `name`, `model` and `swversion` stay empty when it did not answer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bridge {
+    pub ip: String,
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub swversion: String,
+    /// Answered `/api/config` within [`PROBE_TIMEOUT`].
+    pub reachable: bool,
+}
+
+/// Discover Hue Bridges using the meethue.com N-UPnP API.
+/// Returns all discovered bridges, reachable ones first.
+pub async fn discover_bridges(policy: &RetryPolicy) -> Result<Vec<Bridge>, HueError> {
+    let devices = retry(policy, "Bridge discovery", fetch_candidates).await?;
+    Ok(probe_bridges(devices, PROBE_TIMEOUT).await)
+}
+
+async fn fetch_candidates() -> Result<Vec<DiscoveredBridge>, HueError> {
     let client = Client::builder()
         .timeout(Duration::from_secs(10))
         .build()
@@ -25,43 +63,51 @@ pub async fn discover_bridges() -> Result<Vec<DiscoveredBridge>, HueError> {
     if devices.is_empty() {
         return Err(HueError::DiscoveryFailed);
     }
+    Ok(devices)
+}
 
-    // Verify which bridges are actually reachable
-    let mut reachable = Vec::new();
-    let mut unreachable = Vec::new();
+/// Asks every candidate for its `/api/config` at the same time, so one
+/// stale entry does not hold up the others. Reachable bridges come first;
+/// otherwise the order of `candidates` is kept.
+pub async fn probe_bridges(candidates: Vec<DiscoveredBridge>, timeout: Duration) -> Vec<Bridge> {
+    let mut probes = JoinSet::new();
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        probes.spawn(async move { (index, probe_bridge(candidate, timeout).await) });
+    }
 
-    for device in devices {
-        if is_bridge_reachable(&device.ip).await {
-            reachable.push(device);
-        } else {
-            unreachable.push(device);
+    let mut bridges = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok(probed) = result {
+            bridges.push(probed);
         }
     }
-
-    // Return reachable bridges first, then unreachable ones
-    reachable.extend(unreachable);
-    Ok(reachable)
+    bridges.sort_by_key(|(index, bridge)| (!bridge.reachable, *index));
+    bridges.into_iter().map(|(_, bridge)| bridge).collect()
 }
 
-/// Check if a bridge is reachable by making a simple HTTP request
-async fn is_bridge_reachable(ip: &str) -> bool {
-    let client = match Client::builder()
-        .timeout(Duration::from_secs(3))
-        .danger_accept_invalid_certs(true)
-        .build()
-    {
-        Ok(c) => c,
-        Err(_) => return false,
+async fn probe_bridge(candidate: DiscoveredBridge, timeout: Duration) -> Bridge {
+    let mut bridge = Bridge {
+        ip: candidate.ip,
+        id: candidate.id,
+        ..Default::default()
     };
-
-    // Try to reach the bridge's description endpoint
-    let url = format!("http://{}/api/0/config", ip);
-    client.get(&url).send().await.is_ok()
+    if let Ok(Ok(config)) =
+        tokio::time::timeout(timeout, HueClient::get_bridge_config(&bridge.ip)).await
+    {
+        if bridge.id.is_empty() {
+            bridge.id = config.bridge_id.to_lowercase();
+        }
+        bridge.name = config.name;
+        bridge.model = config.model_id;
+        bridge.swversion = config.swversion;
+        bridge.reachable = true;
+    }
+    bridge
 }
 
 /// Legacy function for backwards compatibility - returns first reachable bridge
-pub async fn discover_bridge() -> Result<String, HueError> {
-    let bridges = discover_bridges().await?;
+pub async fn discover_bridge(policy: &RetryPolicy) -> Result<String, HueError> {
+    let bridges = discover_bridges(policy).await?;
 
     // Return the first bridge (which should be the first reachable one)
     bridges
@@ -69,3 +115,266 @@ pub async fn discover_bridge() -> Result<String, HueError> {
         .map(|b| b.ip.clone())
         .ok_or(HueError::DiscoveryFailed)
 }
+
+/// Discover bridges on the local network via mDNS (`_hue._tcp.local`), over
+/// IPv4 and IPv6. Returns the addresses of all bridges answering within
+/// `timeout`.
+///
+/// Bridges answering over IPv6 are reported by the routable addresses in
+/// their AAAA records; link-local ones cannot be used in URLs.
+pub async fn discover_bridges_mdns(timeout: Duration) -> Result<Vec<String>, HueError> {
+    let io = |e: std::io::Error| HueError::Other(format!("mDNS discovery failed: {}", e));
+    let (v4, v6) = tokio::join!(
+        mdns_query_on(
+            (Ipv4Addr::UNSPECIFIED, 0).into(),
+            (MDNS_V4, MDNS_PORT).into(),
+            timeout
+        ),
+        mdns_query_on(
+            (Ipv6Addr::UNSPECIFIED, 0).into(),
+            (MDNS_V6, MDNS_PORT).into(),
+            timeout
+        ),
+    );
+    let mut found = v4.map_err(io)?;
+    match v6 {
+        Ok(addresses) => {
+            for ip in addresses {
+                if !found.contains(&ip) {
+                    found.push(ip);
+                }
+            }
+        }
+        // Hosts without IPv6 are common
+        Err(e) => tracing::debug!("mDNS over IPv6 failed: {}", e),
+    }
+    Ok(found)
+}
+
+/// Sends the query to `group` from `local` and collects bridge addresses
+/// from the answers until `timeout`.
+async fn mdns_query_on(
+    local: SocketAddr,
+    group: SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<Vec<String>> {
+    let socket = UdpSocket::bind(local).await?;
+    // Sent from an ephemeral port, so bridges answer by unicast (RFC 6762 legacy query)
+    socket.send_to(&mdns_query(), group).await?;
+
+    let mut found = Vec::new();
+    let mut buf = [0u8; 1500];
+    let deadline = tokio::time::Instant::now() + timeout;
+    while let Ok(Ok((len, from))) =
+        tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await
+    {
+        let packet = &buf[..len];
+        if !is_hue_response(packet) {
+            continue;
+        }
+        for ip in response_addresses(packet, from.ip()) {
+            if !found.contains(&ip) {
+                found.push(ip);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Addresses to reach the bridge that sent the Hue response `packet` from
+/// `from`: the sender itself over IPv4, else its routable AAAA records.
+fn response_addresses(packet: &[u8], from: IpAddr) -> Vec<String> {
+    match from {
+        IpAddr::V4(ip) => vec![ip.to_string()],
+        IpAddr::V6(ip) if !is_link_local(&ip) => vec![ip.to_string()],
+        IpAddr::V6(_) => aaaa_records(packet)
+            .into_iter()
+            .filter(|ip| !is_link_local(ip))
+            .map(|ip| ip.to_string())
+            .collect(),
+    }
+}
+
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// The IPv6 addresses in the AAAA records of a DNS message, in any section.
+fn aaaa_records(packet: &[u8]) -> Vec<Ipv6Addr> {
+    let count = |at: usize| u16::from_be_bytes([packet[at], packet[at + 1]]) as usize;
+    if packet.len() < 12 {
+        return Vec::new();
+    }
+    let questions = count(4);
+    let records = count(6) + count(8) + count(10);
+
+    let mut addresses = Vec::new();
+    let mut pos = 12;
+    for _ in 0..questions {
+        let Some(end) = skip_name(packet, pos) else {
+            return addresses;
+        };
+        pos = end + 4; // QTYPE, QCLASS
+    }
+    for _ in 0..records {
+        let Some(end) = skip_name(packet, pos) else {
+            break;
+        };
+        // TYPE, CLASS, TTL, RDLENGTH, then the data
+        let Some(header) = packet.get(end..end + 10) else {
+            break;
+        };
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data = end + 10;
+        let Some(rdata) = packet.get(data..data + length) else {
+            break;
+        };
+        if rtype == TYPE_AAAA && length == 16 {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(rdata);
+            addresses.push(Ipv6Addr::from(octets));
+        }
+        pos = data + length;
+    }
+    addresses
+}
+
+/// Position after the (possibly compressed) domain name at `pos`.
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer ends the name
+            l if l & 0xc0 == 0xc0 => return Some(pos + 2),
+            l => pos += 1 + l,
+        }
+    }
+}
+
+/// Finds the bridge with `bridge_id` (from `/api/config`) after its address
+/// changed, e.g. because the router handed out a new DHCP lease.
+/// Tries cloud discovery first, then mDNS. Returns the bridge's current IP.
+pub async fn rediscover_bridge(bridge_id: &str, policy: &RetryPolicy) -> Result<String, HueError> {
+    // Cloud discovery already reports bridge IDs
+    if let Ok(bridges) = discover_bridges(policy).await {
+        if let Some(bridge) = bridges.iter().find(|b| same_bridge(&b.id, bridge_id)) {
+            return Ok(bridge.ip.clone());
+        }
+    }
+
+    // mDNS answers only carry addresses; ask each bridge for its ID
+    for ip in discover_bridges_mdns(Duration::from_secs(3)).await? {
+        if let Ok(config) = HueClient::get_bridge_config(&ip).await {
+            if same_bridge(&config.bridge_id, bridge_id) {
+                return Ok(ip);
+            }
+        }
+    }
+
+    Err(HueError::DiscoveryFailed)
+}
+
+/// Cloud discovery reports IDs in lower case, `/api/config` in upper case.
+fn same_bridge(a: &str, b: &str) -> bool {
+    !a.is_empty() && a.eq_ignore_ascii_case(b)
+}
+
+/// PTR query for the Hue service.
+fn mdns_query() -> Vec<u8> {
+    // Header: id 0, standard query, one question
+    let mut query = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    query.extend_from_slice(HUE_SERVICE);
+    // QTYPE PTR, QCLASS IN
+    query.extend_from_slice(&[0x00, 0x0C, 0x00, 0x01]);
+    query
+}
+
+/// True for mDNS responses that mention the Hue service.
+fn is_hue_response(packet: &[u8]) -> bool {
+    let is_response = packet.len() > 12 && packet[2] & 0x80 != 0;
+    is_response && packet.windows(HUE_SERVICE.len()).any(|w| w == HUE_SERVICE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdns_response_detection() {
+        let query = mdns_query();
+        // Our own query must not count as an answer
+        assert!(!is_hue_response(&query));
+
+        let mut response = query.clone();
+        response[2] = 0x84; // QR + authoritative answer
+        assert!(is_hue_response(&response));
+
+        let mut other = response.clone();
+        other[13..17].copy_from_slice(b"_ftp");
+        assert!(!is_hue_response(&other));
+    }
+
+    #[test]
+    fn test_aaaa_records() {
+        // A response with the question and one AAAA record pointing back
+        // at the name of the question
+        let mut response = mdns_query();
+        response[2] = 0x84;
+        response[7] = 1; // one answer
+        response.extend_from_slice(&[0xc0, 12]);
+        response.extend_from_slice(&[0, 28, 0, 1, 0, 0, 0, 120, 0, 16]);
+        let global: Ipv6Addr = "2001:db8::17".parse().unwrap();
+        response.extend_from_slice(&global.octets());
+
+        assert_eq!(aaaa_records(&response), [global]);
+        let link_local: IpAddr = "fe80::17".parse().unwrap();
+        assert_eq!(response_addresses(&response, link_local), ["2001:db8::17"]);
+        assert_eq!(
+            response_addresses(&response, "192.168.1.2".parse().unwrap()),
+            ["192.168.1.2"]
+        );
+        // Truncated messages yield what was complete
+        assert!(aaaa_records(&response[..response.len() - 1]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_probes_run_concurrently() {
+        // A bridge that accepts connections and never answers, so every
+        // probe runs into the timeout
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                open.push(stream);
+            }
+        });
+
+        let timeout = Duration::from_millis(500);
+        let candidates = (0..4)
+            .map(|i| DiscoveredBridge {
+                ip: addr.to_string(),
+                id: format!("bridge{}", i),
+            })
+            .collect();
+        let started = std::time::Instant::now();
+        let bridges = probe_bridges(candidates, timeout).await;
+        // About one timeout in total, where one after the other takes four
+        let elapsed = started.elapsed();
+        assert!(elapsed >= timeout, "{:?}", elapsed);
+        assert!(elapsed < timeout * 2, "{:?}", elapsed);
+
+        let ids: Vec<&str> = bridges.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["bridge0", "bridge1", "bridge2", "bridge3"]);
+        assert!(bridges.iter().all(|b| !b.reachable && b.name.is_empty()));
+    }
+
+    #[test]
+    fn test_same_bridge() {
+        assert!(same_bridge("001788fffe2a1b3c", "001788FFFE2A1B3C"));
+        assert!(!same_bridge("001788fffe2a1b3c", "001788fffe000000"));
+        assert!(!same_bridge("", ""));
+    }
+}
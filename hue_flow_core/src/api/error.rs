@@ -1,3 +1,4 @@
+use serde::Deserialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +7,14 @@ pub enum HueError {
     DiscoveryFailed,
     #[error("Link button not pressed. Please press the link button on the Hue Bridge.")]
     LinkButtonNotPressed,
+    #[error("Unsupported bridge: {0}")]
+    UnsupportedBridge(String),
+    #[error("Not authorized: {0}. Run 'hueflow setup' again.")]
+    Unauthorized(String),
+    #[error("Not found on the bridge: {0}")]
+    ResourceNotFound(String),
+    #[error("Cannot change streaming: {0}")]
+    StreamOwnership(String),
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
     #[error("API error: {0}")]
@@ -15,3 +24,34 @@ pub enum HueError {
     #[error("Other error: {0}")]
     Other(String),
 }
+
+/// One entry of the array every v1 API call answers with.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum V1ResponseItem<T> {
+    Success { success: T },
+    Error { error: V1Error },
+}
+
+/// A v1 API error (see "Error Messages" in the Hue API docs).
+#[derive(Deserialize, Debug, Clone)]
+pub struct V1Error {
+    #[serde(rename = "type")]
+    pub error_type: i32,
+    #[serde(default)]
+    pub address: String,
+    pub description: String,
+}
+
+impl From<V1Error> for HueError {
+    fn from(error: V1Error) -> Self {
+        match error.error_type {
+            1 => HueError::Unauthorized(error.description),
+            3 => HueError::ResourceNotFound(error.address),
+            101 => HueError::LinkButtonNotPressed,
+            // 307: "Cannot claim stream ownership"
+            307 => HueError::StreamOwnership(error.description),
+            _ => HueError::ApiError(format!("{} ({})", error.description, error.error_type)),
+        }
+    }
+}
@@ -1,61 +1,39 @@
-use crate::api::error::HueError;
-use crate::models::{HueConfig, LightNode};
+use crate::api::error::{HueError, V1ResponseItem};
+use crate::api::retry::retry;
+use crate::api::v2::models::{
+    Channel, EntertainmentConfiguration, Locations, ResourceLink, ServiceLocation, V2Response,
+};
+use crate::api::v2::{get_resource, get_resources};
+use crate::api::{build_client, url_host};
+use crate::models::{group_matches, GroupEntry, HueConfig, LightNode};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+/// Longest entertainment configuration name the bridge accepts.
+const MAX_NAME_LEN: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupInfo {
     pub id: String, // v2 API UUID (for stream activation and DTLS streaming)
     pub name: String,
     pub lights: Vec<LightNode>,
 }
 
-// V2 API structures
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct V2Response<T> {
-    data: Vec<T>,
-}
-
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct V2EntertainmentConfig {
-    id: String,
-    metadata: V2Metadata,
-    channels: Vec<V2Channel>,
-    #[serde(default)]
-    status: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct V2Metadata {
-    name: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct V2Channel {
-    channel_id: u8,
-    position: V2Position,
-    #[serde(default)]
-    members: Vec<V2ChannelMember>,
-}
-
-#[derive(Deserialize, Debug)]
-struct V2Position {
-    x: f64,
-    y: f64,
-    z: f64,
-}
-
-#[derive(Deserialize, Debug, Default)]
-struct V2ChannelMember {
-    service: Option<V2ServiceRef>,
+impl GroupInfo {
+    /// True if `wanted` is this group's ID or (case-insensitive) name.
+    pub fn matches(&self, wanted: &str) -> bool {
+        group_matches(&self.id, &self.name, wanted)
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct V2ServiceRef {
-    rid: String,
-    rtype: String,
+impl From<&GroupInfo> for GroupEntry {
+    fn from(group: &GroupInfo) -> Self {
+        Self {
+            id: group.id.clone(),
+            name: group.name.clone(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -63,50 +41,31 @@ struct StreamAction {
     action: String,
 }
 
-// Helper to build a client with insecure certs (Hue Bridge standard)
-fn build_client() -> Result<reqwest::Client, HueError> {
-    reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .map_err(HueError::Network)
-}
-
 /// Fetches entertainment configurations from the v2 API.
 /// Returns groups with proper channel_id mapping for streaming.
+///
+/// All areas come in one request, channels included, so this is one round
+/// trip however many areas the bridge has.
 pub async fn get_entertainment_groups(config: &HueConfig) -> Result<Vec<GroupInfo>, HueError> {
-    let client = build_client()?;
+    retry(&config.retry, "Fetching entertainment groups", || {
+        fetch_entertainment_groups(config)
+    })
+    .await
+}
 
+async fn fetch_entertainment_groups(config: &HueConfig) -> Result<Vec<GroupInfo>, HueError> {
     // Use v2 API to get entertainment configurations with channels
-    let url = format!(
-        "https://{}/clip/v2/resource/entertainment_configuration",
-        config.bridge_ip
-    );
-
-    let resp = client
-        .get(&url)
-        .header("hue-application-key", &config.username)
-        .send()
-        .await?;
-
-    if !resp.status().is_success() {
-        return Err(HueError::ApiError(format!(
-            "Failed to get entertainment configurations: HTTP {}",
-            resp.status()
-        )));
-    }
-
-    let v2_response: V2Response<V2EntertainmentConfig> = resp.json().await?;
+    let configs = get_resources::<EntertainmentConfiguration>(config).await?;
 
     let mut result = Vec::new();
 
-    for cfg in v2_response.data {
+    for cfg in configs {
         let mut lights = Vec::new();
 
         for channel in &cfg.channels {
             // Get light ID from channel members if available
-            let light_id = channel
-                .members
-                .first()
+            let member = channel.members.first();
+            let light_id = member
                 .and_then(|m| m.service.as_ref())
                 .map(|s| s.rid.clone())
                 .unwrap_or_else(|| format!("channel_{}", channel.channel_id));
@@ -117,6 +76,7 @@ pub async fn get_entertainment_groups(config: &HueConfig) -> Result<Vec<GroupInf
                 x: channel.position.x,
                 y: channel.position.y,
                 z: channel.position.z,
+                segment: member.map_or(0, |m| m.index),
             });
         }
 
@@ -132,16 +92,57 @@ pub async fn get_entertainment_groups(config: &HueConfig) -> Result<Vec<GroupInf
 
 /// Activates or deactivates streaming for an entertainment configuration.
 /// Uses the v2 API with {"action": "start"} or {"action": "stop"}.
+///
+/// Firmware that rejects the v2 action is switched through the deprecated
+/// v1 groups endpoint instead, using the area's v1 group.
 pub async fn set_stream_active(
     config: &HueConfig,
     entertainment_config_id: &str,
     active: bool,
+) -> Result<(), HueError> {
+    let v2 = retry(&config.retry, "Stream activation", || {
+        put_stream_action(config, entertainment_config_id, active)
+    })
+    .await;
+    match v2 {
+        Err(HueError::ResourceNotFound(_) | HueError::ApiError(_)) => {
+            let area =
+                get_resource::<EntertainmentConfiguration>(config, entertainment_config_id).await;
+            let Some(group_id) = area
+                .ok()
+                .and_then(|a| a.id_v1)
+                .and_then(|id| v1_group_number(&id).map(str::to_string))
+            else {
+                return v2;
+            };
+            tracing::info!(
+                "v2 stream action rejected, falling back to v1 group {}",
+                group_id
+            );
+            set_stream_active_v1(config, &group_id, active).await
+        }
+        result => result,
+    }
+}
+
+/// The group number of a v1 resource path like `/groups/200`.
+fn v1_group_number(id_v1: &str) -> Option<&str> {
+    id_v1
+        .strip_prefix("/groups/")
+        .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+async fn put_stream_action(
+    config: &HueConfig,
+    entertainment_config_id: &str,
+    active: bool,
 ) -> Result<(), HueError> {
     let client = build_client()?;
 
     let url = format!(
         "https://{}/clip/v2/resource/entertainment_configuration/{}",
-        config.bridge_ip, entertainment_config_id
+        url_host(&config.bridge_ip),
+        entertainment_config_id
     );
 
     let body = StreamAction {
@@ -161,34 +162,252 @@ pub async fn set_stream_active(
 
     let status = resp.status();
     let response_text = resp.text().await?;
+    check_v2_response(status, &response_text)
+}
+
+/// Activates or deactivates streaming through the v1 groups endpoint
+/// (`PUT /api/<key>/groups/<id>` with `{"stream": {"active": ...}}`), for
+/// firmware without the v2 action. `group_id` is the v1 group number.
+pub async fn set_stream_active_v1(
+    config: &HueConfig,
+    group_id: &str,
+    active: bool,
+) -> Result<(), HueError> {
+    retry(&config.retry, "Stream activation", || async {
+        let client = build_client()?;
+        let url = format!(
+            "https://{}/api/{}/groups/{}",
+            url_host(&config.bridge_ip),
+            config.username,
+            group_id
+        );
+        let body = serde_json::json!({ "stream": { "active": active } });
+        let resp = client.put(&url).json(&body).send().await?;
+        check_v1_response(&resp.text().await?)
+    })
+    .await
+}
+
+/// Fails with the first error of a v1 response array.
+fn check_v1_response(text: &str) -> Result<(), HueError> {
+    let items: Vec<V1ResponseItem<serde_json::Value>> = serde_json::from_str(text)?;
+    for item in items {
+        if let V1ResponseItem::Error { error } = item {
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+/// Fails on an error status or errors listed in a v2 response.
+fn check_v2_response(status: StatusCode, text: &str) -> Result<(), HueError> {
+    let errors = serde_json::from_str::<V2Response<serde_json::Value>>(text)
+        .map(|r| r.errors)
+        .unwrap_or_default();
+    if status.is_success() && errors.is_empty() {
+        return Ok(());
+    }
+    let reason = if errors.is_empty() {
+        format!("HTTP {}", status)
+    } else {
+        let descriptions: Vec<&str> = errors.iter().map(|e| e.description.as_str()).collect();
+        descriptions.join("; ")
+    };
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => HueError::Unauthorized(reason),
+        StatusCode::NOT_FOUND => HueError::ResourceNotFound(reason),
+        StatusCode::CONFLICT => HueError::StreamOwnership(reason),
+        _ => HueError::ApiError(reason),
+    })
+}
+
+#[derive(Serialize)]
+struct NewConfiguration<'a> {
+    #[serde(rename = "type")]
+    rtype: &'static str,
+    metadata: NameOnly<'a>,
+    configuration_type: &'a str,
+    locations: Locations,
+}
+
+#[derive(Serialize)]
+struct NameOnly<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct LocationsUpdate {
+    locations: Locations,
+}
+
+/// Combines several entertainment areas into one, so a single stream can
+/// drive all of them (the bridge only accepts one streamer at a time).
+///
+/// The combined area keeps every light where it is in its own area. An area
+/// named `name` from an earlier merge is updated rather than duplicated.
+/// Returns the ID of the combined area.
+pub async fn merge_entertainment_groups(
+    config: &HueConfig,
+    name: &str,
+    area_ids: &[String],
+) -> Result<String, HueError> {
+    check_area_name(name)?;
+    let configs = get_resources::<EntertainmentConfiguration>(config).await?;
+    let mut sources = Vec::new();
+    for id in area_ids {
+        let area = configs
+            .iter()
+            .find(|c| &c.id == id)
+            .ok_or_else(|| HueError::ApiError(format!("Entertainment area {} not found", id)))?;
+        sources.push(area.clone());
+    }
+    let locations = Locations {
+        service_locations: merged_locations(&sources),
+    };
 
-    if !status.is_success() {
+    if let Some(area) = configs.iter().find(|c| c.metadata.name == name) {
+        if area_ids.contains(&area.id) {
+            return Err(HueError::Other(format!(
+                "'{}' is one of the areas being merged",
+                name
+            )));
+        }
+    }
+    save_area(config, &configs, name, "3dspace", locations).await
+}
+
+/// Creates an entertainment area named `name` holding `locations`, or
+/// updates the lights of the area already named so. Returns its ID.
+pub(crate) async fn save_area(
+    config: &HueConfig,
+    configs: &[EntertainmentConfiguration],
+    name: &str,
+    configuration_type: &str,
+    locations: Locations,
+) -> Result<String, HueError> {
+    let client = build_client()?;
+    let base = format!(
+        "https://{}/clip/v2/resource/entertainment_configuration",
+        url_host(&config.bridge_ip)
+    );
+    let existing = configs.iter().find(|c| c.metadata.name == name);
+    let request = match existing {
+        Some(area) => client
+            .put(format!("{}/{}", base, area.id))
+            .json(&LocationsUpdate { locations }),
+        None => client.post(&base).json(&NewConfiguration {
+            rtype: "entertainment_configuration",
+            metadata: NameOnly { name },
+            configuration_type,
+            locations,
+        }),
+    };
+    let resp = request
+        .header("hue-application-key", &config.username)
+        .send()
+        .await?;
+    let status = resp.status();
+    let response_text = resp.text().await?;
+    let response: V2Response<ResourceLink> =
+        serde_json::from_str(&response_text).unwrap_or(V2Response {
+            errors: Vec::new(),
+            data: Vec::new(),
+        });
+    if !status.is_success() || !response.errors.is_empty() {
+        let reasons: Vec<&str> = response
+            .errors
+            .iter()
+            .map(|e| e.description.as_str())
+            .collect();
         return Err(HueError::ApiError(format!(
-            "Failed to {} stream: HTTP {} - {}",
-            if active { "start" } else { "stop" },
+            "Failed to save area '{}': HTTP {} - {}",
+            name,
             status,
-            response_text
+            if reasons.is_empty() {
+                response_text.clone()
+            } else {
+                reasons.join("; ")
+            }
         )));
     }
 
-    // Check for error in response body
-    if response_text.contains("\"error\"") {
-        return Err(HueError::ApiError(format!(
-            "Failed to {} stream: {}",
-            if active { "start" } else { "stop" },
-            response_text
-        )));
+    match existing {
+        Some(area) => Ok(area.id.clone()),
+        None => response
+            .data
+            .into_iter()
+            .next()
+            .map(|link| link.rid)
+            .ok_or_else(|| HueError::ApiError("Bridge did not return the new area".to_string())),
     }
+}
 
+/// Fails unless the bridge accepts `name` as an area name.
+pub(crate) fn check_area_name(name: &str) -> Result<(), HueError> {
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(HueError::Other(format!(
+            "Area names must be 1-{} characters",
+            MAX_NAME_LEN
+        )));
+    }
     Ok(())
 }
 
+/// Service locations of all `areas` in one list. A light in more than one
+/// area keeps the position of its first.
+pub fn merged_locations(areas: &[EntertainmentConfiguration]) -> Vec<ServiceLocation> {
+    let mut merged: Vec<ServiceLocation> = Vec::new();
+    for area in areas {
+        for location in area.locations.iter().flat_map(|l| &l.service_locations) {
+            if merged.iter().all(|m| m.service != location.service) {
+                merged.push(location.clone());
+            }
+        }
+    }
+    merged
+}
+
+/// Which channel of which source area each channel of a merged area shows:
+/// merged channel ID to (source area ID, source channel ID).
+///
+/// Channels are matched by the light segment they drive, so settings made
+/// per channel in a source area can be carried over.
+pub fn channel_map(
+    merged: &EntertainmentConfiguration,
+    sources: &[EntertainmentConfiguration],
+) -> HashMap<u8, (String, u8)> {
+    let member = |channel: &Channel| {
+        channel
+            .members
+            .first()
+            .and_then(|m| m.service.as_ref().map(|s| (s.rid.clone(), m.index)))
+    };
+    let mut map = HashMap::new();
+    for channel in &merged.channels {
+        let Some(wanted) = member(channel) else {
+            continue;
+        };
+        let source = sources.iter().find_map(|area| {
+            area.channels
+                .iter()
+                .find(|c| member(c).as_ref() == Some(&wanted))
+                .map(|c| (area.id.clone(), c.channel_id))
+        });
+        if let Some(source) = source {
+            map.insert(channel.channel_id, source);
+        }
+    }
+    map
+}
+
 /// Flash a light using the v1 API (for testing connectivity)
 pub async fn flash_light(config: &HueConfig, light_id: &str) -> Result<(), HueError> {
     let client = build_client()?;
     let url = format!(
         "https://{}/api/{}/lights/{}/state",
-        config.bridge_ip, config.username, light_id
+        url_host(&config.bridge_ip),
+        config.username,
+        light_id
     );
 
     let body = serde_json::json!({
@@ -210,6 +429,7 @@ pub async fn flash_light(config: &HueConfig, light_id: &str) -> Result<(), HueEr
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::v2::models::V2Response;
     use serde_json::json;
 
     #[test]
@@ -236,11 +456,110 @@ mod tests {
             }]
         });
 
-        let response: V2Response<V2EntertainmentConfig> = serde_json::from_value(json).unwrap();
+        let response: V2Response<EntertainmentConfiguration> =
+            serde_json::from_value(json).unwrap();
         assert_eq!(response.data.len(), 1);
         assert_eq!(response.data[0].id, "1a8d99cc-967b-44f2-9202-43f976c0fa6b");
         assert_eq!(response.data[0].channels.len(), 2);
         assert_eq!(response.data[0].channels[0].channel_id, 0);
         assert_eq!(response.data[0].channels[1].channel_id, 1);
     }
+
+    #[test]
+    fn test_v1_group_number() {
+        assert_eq!(v1_group_number("/groups/200"), Some("200"));
+        assert_eq!(v1_group_number("/lights/3"), None);
+        assert_eq!(v1_group_number("/groups/"), None);
+    }
+
+    #[test]
+    fn test_stream_activation_errors() {
+        assert!(check_v1_response(r#"[{"success": {"/groups/200/stream/active": true}}]"#).is_ok());
+        let taken = r#"[{"error": {"type": 307, "address": "/groups/200/stream/active",
+            "description": "Cannot claim stream ownership"}}]"#;
+        assert!(matches!(
+            check_v1_response(taken),
+            Err(HueError::StreamOwnership(_))
+        ));
+        let unknown =
+            r#"[{"error": {"type": 3, "address": "/groups/9", "description": "not available"}}]"#;
+        assert!(matches!(
+            check_v1_response(unknown),
+            Err(HueError::ResourceNotFound(address)) if address == "/groups/9"
+        ));
+
+        assert!(
+            check_v2_response(StatusCode::OK, r#"{"data": [{"rid": "a"}], "errors": []}"#).is_ok()
+        );
+        let forbidden = r#"{"data": [], "errors": [{"description": "unauthorized user"}]}"#;
+        assert!(matches!(
+            check_v2_response(StatusCode::FORBIDDEN, forbidden),
+            Err(HueError::Unauthorized(reason)) if reason == "unauthorized user"
+        ));
+        assert!(matches!(
+            check_v2_response(StatusCode::SERVICE_UNAVAILABLE, "busy"),
+            Err(HueError::ApiError(reason)) if reason.contains("503")
+        ));
+    }
+
+    fn area(id: &str, members: &[(u8, &str, u32)]) -> EntertainmentConfiguration {
+        let channels: Vec<_> = members
+            .iter()
+            .map(|(channel_id, service, index)| {
+                json!({
+                    "channel_id": channel_id,
+                    "position": { "x": *channel_id as f64 / 10.0, "y": 0.0, "z": 0.0 },
+                    "members": [{ "service": { "rid": service, "rtype": "entertainment" }, "index": index }]
+                })
+            })
+            .collect();
+        let mut services: Vec<&str> = members.iter().map(|m| m.1).collect();
+        services.dedup();
+        let locations: Vec<_> = services
+            .iter()
+            .map(|s| {
+                json!({
+                    "service": { "rid": s, "rtype": "entertainment" },
+                    "positions": [{ "x": 0.0, "y": 0.0, "z": 0.0 }]
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": id,
+            "metadata": { "name": id },
+            "channels": channels,
+            "locations": { "service_locations": locations }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_areas() {
+        // A gradient strip (two segments) in the TV area, a bulb in both
+        let tv = area("tv", &[(0, "strip", 0), (1, "strip", 1), (2, "bulb", 0)]);
+        let kitchen = area("kitchen", &[(0, "bulb", 0), (1, "spot", 0)]);
+        let sources = [tv, kitchen];
+
+        let services: Vec<String> = merged_locations(&sources)
+            .into_iter()
+            .map(|l| l.service.rid)
+            .collect();
+        assert_eq!(services, vec!["strip", "bulb", "spot"]);
+
+        // The bridge numbers the merged channels itself
+        let merged = area(
+            "merged",
+            &[
+                (0, "spot", 0),
+                (1, "bulb", 0),
+                (2, "strip", 1),
+                (3, "strip", 0),
+            ],
+        );
+        let map = channel_map(&merged, &sources);
+        assert_eq!(map[&0], ("kitchen".to_string(), 1));
+        assert_eq!(map[&1], ("tv".to_string(), 2));
+        assert_eq!(map[&2], ("tv".to_string(), 1));
+        assert_eq!(map[&3], ("tv".to_string(), 0));
+    }
 }
@@ -0,0 +1,97 @@
+use crate::api::error::HueError;
+use crate::api::{build_client, url_host};
+use crate::color::rgb_to_xy;
+use crate::models::HueConfig;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// A light or a room/zone addressed over the v1 REST API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightTarget {
+    /// v1 light ID
+    Light(String),
+    /// v1 group ID of a room or zone
+    Group(String),
+}
+
+impl LightTarget {
+    fn path(&self) -> String {
+        match self {
+            LightTarget::Light(id) => format!("lights/{}/state", id),
+            LightTarget::Group(id) => format!("groups/{}/action", id),
+        }
+    }
+}
+
+/// Fades `target` to `color` over `transition` (in steps of 100 ms, as the
+/// bridge counts). Black switches the target off.
+///
+/// Every call is one REST command; keep to the bridge's limits of about ten
+/// light commands or one group command per second.
+pub async fn set_color(
+    config: &HueConfig,
+    target: &LightTarget,
+    color: (u8, u8, u8),
+    transition: Duration,
+) -> Result<(), HueError> {
+    let client = build_client()?;
+    let url = format!(
+        "https://{}/api/{}/{}",
+        url_host(&config.bridge_ip),
+        config.username,
+        target.path()
+    );
+
+    let resp = client
+        .put(&url)
+        .json(&state_body(color, transition))
+        .send()
+        .await?;
+    let status = resp.status();
+    let response_text = resp.text().await?;
+
+    // v1 reports failures as 200 with an error entry
+    if !status.is_success() || response_text.contains("\"error\"") {
+        return Err(HueError::ApiError(format!(
+            "Failed to set {:?}: HTTP {} - {}",
+            target, status, response_text
+        )));
+    }
+    Ok(())
+}
+
+fn state_body(color: (u8, u8, u8), transition: Duration) -> Value {
+    let transitiontime = (transition.as_millis() / 100).min(u16::MAX as u128) as u16;
+    let ((x, y), brightness) = rgb_to_xy(color);
+    if brightness <= 0.0 {
+        return json!({ "on": false, "transitiontime": transitiontime });
+    }
+    json!({
+        "on": true,
+        "xy": [x, y],
+        "bri": (brightness * 254.0).round().clamp(1.0, 254.0) as u8,
+        "transitiontime": transitiontime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_body() {
+        let body = state_body((255, 0, 0), Duration::from_secs(4));
+        assert_eq!(body["on"], true);
+        assert_eq!(body["bri"], 254);
+        assert_eq!(body["transitiontime"], 40);
+        assert!((body["xy"][0].as_f64().unwrap() - 0.7006).abs() < 0.001);
+
+        let off = state_body((0, 0, 0), Duration::from_millis(250));
+        assert_eq!(off, json!({ "on": false, "transitiontime": 2 }));
+
+        assert_eq!(
+            LightTarget::Group("3".to_string()).path(),
+            "groups/3/action"
+        );
+    }
+}
@@ -1,4 +1,44 @@
-pub mod error;
-pub mod discovery;
+pub mod backup;
+pub mod cache;
 pub mod client;
+pub mod discovery;
+pub mod error;
 pub mod groups;
+pub mod lights;
+pub mod retry;
+pub mod v2;
+
+use crate::api::error::HueError;
+use std::borrow::Cow;
+use std::net::Ipv6Addr;
+
+// Helper to build a client with insecure certs (Hue Bridge standard)
+pub fn build_client() -> Result<reqwest::Client, HueError> {
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(HueError::Network)
+}
+
+/// `ip` as the host part of a URL: IPv6 addresses go in brackets.
+pub fn url_host(ip: &str) -> Cow<'_, str> {
+    if ip.parse::<Ipv6Addr>().is_ok() {
+        Cow::Owned(format!("[{}]", ip))
+    } else {
+        Cow::Borrowed(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("192.168.1.2"), "192.168.1.2");
+        assert_eq!(url_host("2001:db8::1"), "[2001:db8::1]");
+        // Already a URL host, with or without port
+        assert_eq!(url_host("[2001:db8::1]"), "[2001:db8::1]");
+        assert_eq!(url_host("127.0.0.1:9"), "127.0.0.1:9");
+    }
+}
@@ -0,0 +1,104 @@
+//! Retrying bridge requests according to the configured [`RetryPolicy`].
+use crate::api::error::HueError;
+use crate::models::RetryPolicy;
+use std::future::Future;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl HueError {
+    /// True for failures worth another try: timeouts and connection
+    /// problems, not answers from the bridge.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            HueError::Network(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            _ => false,
+        }
+    }
+}
+
+/// Runs `op` until it succeeds, fails for good or has used up the policy's
+/// attempts, waiting with exponential backoff in between. `what` names the
+/// operation in the log.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, what: &str, mut op: F) -> Result<T, HueError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, HueError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if e.is_transient() && attempt < policy.max_attempts => {
+                let delay = policy.delay(attempt, random_unit());
+                tracing::warn!(
+                    "{} failed (attempt {}/{}): {}; retrying in {:?}",
+                    what,
+                    attempt,
+                    policy.max_attempts,
+                    e,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A value in -1.0 - 1.0 for jitter; clock noise is random enough here.
+fn random_unit() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    (nanos % 2001) as f64 / 1000.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, 0.0).as_millis(), 500);
+        assert_eq!(policy.delay(3, 0.0).as_millis(), 2000);
+        assert_eq!(policy.delay(3, 1.0).as_millis(), 2500);
+        assert_eq!(policy.delay(3, -1.0).as_millis(), 1500);
+        // Configs may set only part of the policy
+        let parsed: RetryPolicy = serde_json::from_str(r#"{"max_attempts": 2}"#).unwrap();
+        assert_eq!(parsed.base_delay_ms, 500);
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_transient_failures() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 1,
+            jitter: 0.0,
+        };
+        // Nothing listens on port 9 of localhost: connection refused
+        let unreachable = || async {
+            reqwest::get("http://127.0.0.1:9/")
+                .await
+                .map_err(HueError::Network)
+        };
+        let calls = AtomicU32::new(0);
+        let result = retry(&policy, "test", || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            unreachable()
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&policy, "test", || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Err(HueError::LinkButtonNotPressed) }
+        })
+        .await;
+        assert!(matches!(result, Err(HueError::LinkButtonNotPressed)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
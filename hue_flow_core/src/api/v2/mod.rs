@@ -0,0 +1,74 @@
+pub mod models;
+
+use crate::api::error::HueError;
+use crate::api::{build_client, url_host};
+use crate::models::HueConfig;
+use models::{Resource, V2Response};
+
+/// Fetches all resources of type `T` from `/clip/v2/resource/<rtype>`.
+pub async fn get_resources<T: Resource>(config: &HueConfig) -> Result<Vec<T>, HueError> {
+    get_resources_with(&build_client()?, config).await
+}
+
+/// Like [`get_resources`], on an existing `client`. Requests sharing a
+/// client (also concurrent ones) reuse its connections instead of each
+/// paying for a TLS handshake with the bridge.
+pub async fn get_resources_with<T: Resource>(
+    client: &reqwest::Client,
+    config: &HueConfig,
+) -> Result<Vec<T>, HueError> {
+    let url = format!(
+        "https://{}/clip/v2/resource/{}",
+        url_host(&config.bridge_ip),
+        T::RTYPE
+    );
+    fetch(client, config, &url).await
+}
+
+/// Fetches a single resource of type `T` by its v2 UUID.
+pub async fn get_resource<T: Resource>(config: &HueConfig, id: &str) -> Result<T, HueError> {
+    let url = format!(
+        "https://{}/clip/v2/resource/{}/{}",
+        url_host(&config.bridge_ip),
+        T::RTYPE,
+        id
+    );
+    fetch(&build_client()?, config, &url)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| HueError::ApiError(format!("{} {} not found", T::RTYPE, id)))
+}
+
+async fn fetch<T: Resource>(
+    client: &reqwest::Client,
+    config: &HueConfig,
+    url: &str,
+) -> Result<Vec<T>, HueError> {
+    let resp = client
+        .get(url)
+        .header("hue-application-key", &config.username)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        return Err(HueError::ApiError(format!(
+            "Failed to get {}: HTTP {}",
+            T::RTYPE,
+            resp.status()
+        )));
+    }
+
+    let response: V2Response<T> = resp.json().await?;
+
+    if response.data.is_empty() && !response.errors.is_empty() {
+        let descriptions: Vec<&str> = response
+            .errors
+            .iter()
+            .map(|e| e.description.as_str())
+            .collect();
+        return Err(HueError::ApiError(descriptions.join("; ")));
+    }
+
+    Ok(response.data)
+}
@@ -0,0 +1,419 @@
+//! Typed serde models for the CLIP v2 resources used by HueFlow.
+//!
+//! Only the fields HueFlow actually consumes are modelled; everything else the
+//! bridge sends is ignored during deserialization.
+use serde::{Deserialize, Serialize};
+
+/// A CLIP v2 resource type that can be fetched from `/clip/v2/resource/<rtype>`.
+pub trait Resource: serde::de::DeserializeOwned {
+    const RTYPE: &'static str;
+}
+
+/// Envelope wrapping every CLIP v2 response.
+#[derive(Deserialize, Debug, Clone)]
+pub struct V2Response<T> {
+    #[serde(default)]
+    pub errors: Vec<V2Error>,
+    #[serde(default = "Vec::new")]
+    pub data: Vec<T>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct V2Error {
+    pub description: String,
+}
+
+/// Reference to another resource (`{"rid": "...", "rtype": "light"}`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResourceLink {
+    pub rid: String,
+    pub rtype: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Metadata {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub archetype: Option<String>,
+}
+
+// ===== light =====
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Light {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    pub owner: ResourceLink,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub on: Option<OnState>,
+    #[serde(default)]
+    pub dimming: Option<Dimming>,
+    #[serde(default)]
+    pub color: Option<LightColor>,
+    #[serde(default)]
+    pub color_temperature: Option<ColorTemperature>,
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+impl Resource for Light {
+    const RTYPE: &'static str = "light";
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct OnState {
+    pub on: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Dimming {
+    pub brightness: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct XyColor {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightColor {
+    pub xy: XyColor,
+    #[serde(default)]
+    pub gamut_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct ColorTemperature {
+    #[serde(default)]
+    pub mirek: Option<u16>,
+    #[serde(default)]
+    pub mirek_valid: bool,
+}
+
+// ===== device =====
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    pub product_data: ProductData,
+    #[serde(default)]
+    pub services: Vec<ResourceLink>,
+}
+
+impl Resource for Device {
+    const RTYPE: &'static str = "device";
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProductData {
+    pub model_id: String,
+    #[serde(default)]
+    pub manufacturer_name: String,
+    #[serde(default)]
+    pub product_name: String,
+    #[serde(default)]
+    pub software_version: String,
+}
+
+// ===== entertainment =====
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Entertainment {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    pub owner: ResourceLink,
+    #[serde(default)]
+    pub renderer: bool,
+    #[serde(default)]
+    pub proxy: bool,
+    #[serde(default)]
+    pub max_streams: Option<u32>,
+    #[serde(default)]
+    pub segments: Option<Segments>,
+}
+
+impl Resource for Entertainment {
+    const RTYPE: &'static str = "entertainment";
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Segments {
+    #[serde(default)]
+    pub configurable: bool,
+    #[serde(default)]
+    pub max_segments: u32,
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: u32,
+    pub length: u32,
+}
+
+// ===== entertainment_configuration =====
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EntertainmentStatus {
+    Active,
+    #[default]
+    Inactive,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EntertainmentConfiguration {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub configuration_type: String,
+    #[serde(default)]
+    pub status: EntertainmentStatus,
+    /// The application currently streaming to this area (an `auth_v1` link).
+    #[serde(default)]
+    pub active_streamer: Option<ResourceLink>,
+    #[serde(default)]
+    pub stream_proxy: Option<StreamProxy>,
+    pub channels: Vec<Channel>,
+    #[serde(default)]
+    pub locations: Option<Locations>,
+    #[serde(default)]
+    pub light_services: Vec<ResourceLink>,
+}
+
+impl Resource for EntertainmentConfiguration {
+    const RTYPE: &'static str = "entertainment_configuration";
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StreamProxy {
+    pub mode: String,
+    pub node: ResourceLink,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Channel {
+    pub channel_id: u8,
+    pub position: Position,
+    #[serde(default)]
+    pub members: Vec<ChannelMember>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ChannelMember {
+    #[serde(default)]
+    pub service: Option<ResourceLink>,
+    #[serde(default)]
+    pub index: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Locations {
+    #[serde(default)]
+    pub service_locations: Vec<ServiceLocation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceLocation {
+    pub service: ResourceLink,
+    #[serde(default)]
+    pub positions: Vec<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equalization_factor: Option<f64>,
+}
+
+// ===== light_level =====
+
+/// Illuminance reported by a motion sensor's light sensor.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightLevel {
+    pub id: String,
+    pub owner: ResourceLink,
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub light: Option<LightLevelState>,
+}
+
+impl Resource for LightLevel {
+    const RTYPE: &'static str = "light_level";
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightLevelState {
+    /// 10000 * log10(lux) + 1; superseded by `light_level_report` in newer firmware.
+    #[serde(default)]
+    pub light_level: Option<u32>,
+    #[serde(default)]
+    pub light_level_valid: bool,
+    #[serde(default)]
+    pub light_level_report: Option<LightLevelReport>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightLevelReport {
+    pub light_level: u32,
+}
+
+impl LightLevel {
+    /// Current illuminance in lux, if the sensor has a valid reading.
+    pub fn lux(&self) -> Option<f32> {
+        let light = self.light.as_ref()?;
+        let level = match &light.light_level_report {
+            Some(report) => report.light_level,
+            None if light.light_level_valid => light.light_level?,
+            None => return None,
+        };
+        Some(10f32.powf((level.max(1) - 1) as f32 / 10_000.0))
+    }
+}
+
+// ===== room / zone =====
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Room {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub children: Vec<ResourceLink>,
+    #[serde(default)]
+    pub services: Vec<ResourceLink>,
+}
+
+impl Resource for Room {
+    const RTYPE: &'static str = "room";
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Zone {
+    pub id: String,
+    #[serde(default)]
+    pub id_v1: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub children: Vec<ResourceLink>,
+    #[serde(default)]
+    pub services: Vec<ResourceLink>,
+}
+
+impl Resource for Zone {
+    const RTYPE: &'static str = "zone";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_entertainment_configuration_with_streamer() {
+        let json = json!({
+            "errors": [],
+            "data": [{
+                "id": "1a8d99cc-967b-44f2-9202-43f976c0fa6b",
+                "type": "entertainment_configuration",
+                "metadata": { "name": "TV" },
+                "configuration_type": "screen",
+                "status": "active",
+                "active_streamer": { "rid": "b1f7a7c2-0000-4000-8000-000000000001", "rtype": "auth_v1" },
+                "channels": [{
+                    "channel_id": 0,
+                    "position": { "x": -0.5, "y": 0.5, "z": 0.0 },
+                    "members": [{
+                        "service": { "rid": "e6b6f1d4-0000-4000-8000-000000000002", "rtype": "entertainment" },
+                        "index": 0
+                    }]
+                }],
+                "light_services": [{ "rid": "a1", "rtype": "light" }]
+            }]
+        });
+
+        let response: V2Response<EntertainmentConfiguration> =
+            serde_json::from_value(json).unwrap();
+        let cfg = &response.data[0];
+        assert_eq!(cfg.status, EntertainmentStatus::Active);
+        assert_eq!(cfg.active_streamer.as_ref().unwrap().rtype, "auth_v1");
+        assert_eq!(
+            cfg.channels[0].members[0].service.as_ref().unwrap().rtype,
+            "entertainment"
+        );
+        assert_eq!(cfg.light_services.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_device_and_light() {
+        let device: Device = serde_json::from_value(json!({
+            "id": "d1",
+            "metadata": { "name": "Hue go", "archetype": "hue_go" },
+            "product_data": {
+                "model_id": "LLC020",
+                "manufacturer_name": "Signify Netherlands B.V.",
+                "product_name": "Hue go",
+                "software_version": "1.101.2"
+            },
+            "services": [{ "rid": "l1", "rtype": "light" }]
+        }))
+        .unwrap();
+        assert_eq!(device.product_data.software_version, "1.101.2");
+
+        let light: Light = serde_json::from_value(json!({
+            "id": "l1",
+            "owner": { "rid": "d1", "rtype": "device" },
+            "on": { "on": true },
+            "dimming": { "brightness": 42.5 },
+            "color": { "xy": { "x": 0.3, "y": 0.4 }, "gamut_type": "C" },
+            "mode": "streaming"
+        }))
+        .unwrap();
+        assert_eq!(light.owner.rid, device.id);
+        assert!(light.on.unwrap().on);
+        assert_eq!(light.mode.as_deref(), Some("streaming"));
+    }
+
+    #[test]
+    fn test_light_level_lux() {
+        let sensor: LightLevel = serde_json::from_value(json!({
+            "id": "ll1",
+            "owner": { "rid": "d2", "rtype": "device" },
+            "enabled": true,
+            "light": { "light_level": 20001, "light_level_valid": true }
+        }))
+        .unwrap();
+        assert!((sensor.lux().unwrap() - 100.0).abs() < 0.01);
+
+        let reported: LightLevel = serde_json::from_value(json!({
+            "id": "ll2",
+            "owner": { "rid": "d3", "rtype": "device" },
+            "light": { "light_level_report": { "changed": "2024-01-01T00:00:00Z", "light_level": 1 } }
+        }))
+        .unwrap();
+        assert_eq!(reported.lux(), Some(1.0));
+    }
+}
@@ -0,0 +1,368 @@
+//! Picks the preset or effect by the application playing audio, e.g. a
+//! party preset for Spotify, a punchy one for a game and a calm one for a
+//! video player.
+//!
+//! Rules are checked in order against the applications with an active
+//! audio stream; the first that matches switches the running effect
+//! through the control channel. Detection asks the OS mixer: WASAPI
+//! sessions on Windows, the audio power assertions of `coreaudiod` on
+//! macOS and `pactl` (PulseAudio or PipeWire) on Linux.
+use crate::control::ControlCommand;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "bridge")]
+use std::time::Duration;
+
+/// How often the playing applications are checked.
+#[cfg(feature = "bridge")]
+const CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// What to switch to while an application plays audio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppRule {
+    /// Executable name without extension, case-insensitive, e.g. "spotify"
+    /// or "vlc".
+    pub app: String,
+    /// Preset to load; wins over `effect`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Effect to switch to with its default palette.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effect: Option<String>,
+}
+
+impl AppRule {
+    /// True if `app`, an executable name or path, is this rule's application.
+    pub fn matches(&self, app: &str) -> bool {
+        app_name(app).eq_ignore_ascii_case(self.app.trim())
+    }
+
+    /// The command switching to this rule's preset or effect, `None` when
+    /// it names neither.
+    pub fn command(&self) -> Option<ControlCommand> {
+        match (&self.preset, &self.effect) {
+            (Some(name), _) => Some(ControlCommand::LoadPreset { name: name.clone() }),
+            (None, Some(name)) => Some(ControlCommand::SetEffect { name: name.clone() }),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Index of the first rule matching any of the playing `apps`.
+pub fn select(rules: &[AppRule], apps: &[String]) -> Option<usize> {
+    rules
+        .iter()
+        .position(|rule| apps.iter().any(|app| rule.matches(app)))
+}
+
+/// The executable name in `path`, without directory and `.exe`.
+fn app_name(path: &str) -> &str {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path).trim();
+    let stem = name.len().saturating_sub(4);
+    match name.get(stem..) {
+        Some(extension) if stem > 0 && extension.eq_ignore_ascii_case(".exe") => &name[..stem],
+        _ => name,
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub use macos_assertions::active_audio_apps;
+#[cfg(target_os = "linux")]
+pub use pulse_streams::active_audio_apps;
+#[cfg(windows)]
+pub use windows_sessions::active_audio_apps;
+
+/// Executables (names or paths) of the applications playing audio now.
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+pub fn active_audio_apps() -> std::io::Result<Vec<String>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Detecting the application playing audio is not supported on this platform",
+    ))
+}
+
+/// Switches to the first matching rule whenever the playing applications
+/// change, until `commands` closes. Nothing changes while no rule matches,
+/// and a manual switch holds until another rule matches.
+#[cfg(feature = "bridge")]
+pub fn spawn_app_watcher(rules: Vec<AppRule>, commands: tokio::sync::mpsc::Sender<ControlCommand>) {
+    tokio::spawn(async move {
+        let mut current = None;
+        let mut check = tokio::time::interval(CHECK_INTERVAL);
+        while !commands.is_closed() {
+            check.tick().await;
+            let apps = match tokio::task::spawn_blocking(active_audio_apps).await {
+                Ok(Ok(apps)) => apps,
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+                    tracing::warn!("App profiles: {}", e);
+                    return;
+                }
+                Ok(Err(e)) => {
+                    tracing::debug!("App profiles: cannot list audio applications: {}", e);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            let selected = select(&rules, &apps);
+            if selected != current {
+                if let Some(rule) = selected.map(|i| &rules[i]) {
+                    if let Some(command) = rule.command() {
+                        tracing::info!("App profiles: {} is playing", rule.app);
+                        if commands.send(command).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            current = selected;
+        }
+    });
+}
+
+#[cfg(windows)]
+mod windows_sessions {
+    use std::io;
+    use windows::core::{Interface, PWSTR};
+    use windows::Win32::Foundation::{CloseHandle, FALSE};
+    use windows::Win32::Media::Audio::{
+        eRender, AudioSessionStateActive, IAudioSessionControl2, IAudioSessionManager2,
+        IMMDeviceEnumerator, MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
+    };
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED,
+    };
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    /// Executables (paths) of the applications playing audio now.
+    pub fn active_audio_apps() -> io::Result<Vec<String>> {
+        // SAFETY: plain COM calls on interfaces this function owns
+        unsafe { sessions() }.map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    /// Processes with an active session on any playback device.
+    unsafe fn sessions() -> windows::core::Result<Vec<String>> {
+        // Fails harmlessly when the thread already joined an apartment
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let devices = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+        let mut apps = Vec::new();
+        for device in 0..devices.GetCount()? {
+            let manager: IAudioSessionManager2 =
+                devices.Item(device)?.Activate(CLSCTX_ALL, None)?;
+            let sessions = manager.GetSessionEnumerator()?;
+            for session in 0..sessions.GetCount()? {
+                let session: IAudioSessionControl2 = sessions.GetSession(session)?.cast()?;
+                if session.GetState()? != AudioSessionStateActive {
+                    continue;
+                }
+                if let Some(name) = session
+                    .GetProcessId()
+                    .ok()
+                    .and_then(|pid| process_path(pid))
+                {
+                    apps.push(name);
+                }
+            }
+        }
+        Ok(apps)
+    }
+
+    /// Executable path of `pid`; `None` for the system sounds (pid 0) and
+    /// processes this user may not inspect.
+    unsafe fn process_path(pid: u32) -> Option<String> {
+        if pid == 0 {
+            return None;
+        }
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid).ok()?;
+        let mut buffer = [0u16; 1024];
+        let mut len = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        result.ok()?;
+        Some(String::from_utf16_lossy(&buffer[..len as usize]))
+    }
+}
+
+#[cfg(any(target_os = "macos", test))]
+mod macos_assertions {
+    /// PIDs audio power assertions were created for, from the output of
+    /// `pmset -g assertions`: `coreaudiod` holds one per application
+    /// playing, each followed by a `Created for PID: <pid>.` line.
+    pub fn audio_pids(assertions: &str) -> Vec<u32> {
+        let mut pids = Vec::new();
+        let mut audio = false;
+        for line in assertions.lines().map(str::trim) {
+            if let Some(pid) = line.strip_prefix("Created for PID:") {
+                let pid = pid.trim().trim_end_matches('.').parse();
+                if let (true, Ok(pid)) = (audio, pid) {
+                    if !pids.contains(&pid) {
+                        pids.push(pid);
+                    }
+                }
+            } else if line.starts_with("pid ") {
+                audio = line.contains("(coreaudiod)") && line.contains("com.apple.audio.");
+            }
+        }
+        pids
+    }
+
+    /// Executables (paths) of the applications playing audio now.
+    #[cfg(target_os = "macos")]
+    pub fn active_audio_apps() -> std::io::Result<Vec<String>> {
+        use std::process::Command;
+        let output = Command::new("pmset").args(["-g", "assertions"]).output()?;
+        let pids = audio_pids(&String::from_utf8_lossy(&output.stdout));
+        if pids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pids: Vec<String> = pids.iter().map(u32::to_string).collect();
+        let output = Command::new("ps")
+            .args(["-o", "comm=", "-p", &pids.join(",")])
+            .output()?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+}
+
+#[cfg(any(target_os = "linux", test))]
+mod pulse_streams {
+    /// Binaries of the playing (not corked) streams in the output of
+    /// `pactl list sink-inputs`.
+    pub fn playing_binaries(sink_inputs: &str) -> Vec<String> {
+        let mut apps = Vec::new();
+        let mut stream: Option<(bool, Option<String>)> = None;
+        for line in sink_inputs.lines().map(str::trim) {
+            if line.starts_with("Sink Input #") {
+                apps.extend(stream.take().and_then(playing));
+                stream = Some((false, None));
+            } else if let Some((corked, binary)) = &mut stream {
+                if let Some(value) = line.strip_prefix("Corked:") {
+                    *corked = value.trim() == "yes";
+                } else if let Some(value) = line.strip_prefix("application.process.binary =") {
+                    *binary = Some(value.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+        apps.extend(stream.and_then(playing));
+        apps
+    }
+
+    fn playing((corked, binary): (bool, Option<String>)) -> Option<String> {
+        binary.filter(|_| !corked)
+    }
+
+    /// Executables (names) of the applications playing audio now.
+    #[cfg(target_os = "linux")]
+    pub fn active_audio_apps() -> std::io::Result<Vec<String>> {
+        let output = std::process::Command::new("pactl")
+            .args(["list", "sink-inputs"])
+            .env("LC_ALL", "C")
+            .output()?;
+        Ok(playing_binaries(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(app: &str, preset: Option<&str>, effect: Option<&str>) -> AppRule {
+        AppRule {
+            app: app.to_string(),
+            preset: preset.map(str::to_string),
+            effect: effect.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_select_first_matching_rule() {
+        let rules = vec![
+            rule("cs2", Some("game"), None),
+            rule("Spotify", Some("party"), Some("pulse")),
+            rule("vlc", None, Some("ambient")),
+            rule("empty", None, None),
+        ];
+        let apps = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(select(&rules, &apps(&[])), None);
+        assert_eq!(
+            select(&rules, &apps(&[r"C:\Program Files\VideoLAN\VLC\vlc.exe"])),
+            Some(2)
+        );
+        // Rule order decides, not the order the apps are listed in
+        assert_eq!(
+            select(
+                &rules,
+                &apps(&[
+                    "/Applications/Spotify.app/Contents/MacOS/Spotify",
+                    "CS2.EXE"
+                ])
+            ),
+            Some(0)
+        );
+        assert_eq!(select(&rules, &apps(&["spotify-launcher"])), None);
+
+        assert_eq!(
+            rules[1].command(),
+            Some(ControlCommand::LoadPreset {
+                name: "party".to_string()
+            })
+        );
+        assert_eq!(
+            rules[2].command(),
+            Some(ControlCommand::SetEffect {
+                name: "ambient".to_string()
+            })
+        );
+        assert_eq!(rules[3].command(), None);
+    }
+
+    #[test]
+    fn test_parse_platform_output() {
+        let pmset = "\
+Assertion status system-wide:
+   PreventUserIdleSystemSleep     1
+Listed by owning process:
+   pid 318(coreaudiod): [0x0001] 00:02:11 PreventUserIdleSleep named: \"com.apple.audio.BuiltInSpeakerDevice.context.preventuseridlesleep\"
+\tCreated for PID: 2875.
+   pid 318(coreaudiod): [0x0002] 00:02:11 NoIdleSleepAssertion named: \"com.apple.audio.BuiltInSpeakerDevice.context.noidlesleep\"
+\tCreated for PID: 2875.
+   pid 412(bluetoothd): [0x0003] 00:00:40 PreventUserIdleSleep named: \"com.apple.BTStack\"
+\tCreated for PID: 99.
+   pid 318(coreaudiod): [0x0004] 00:00:05 PreventUserIdleSleep named: \"com.apple.audio.context.preventuseridlesleep\"
+\tCreated for PID: 5120.
+";
+        assert_eq!(macos_assertions::audio_pids(pmset), vec![2875, 5120]);
+
+        let pactl = "\
+Sink Input #42
+\tDriver: protocol-native.c
+\tCorked: no
+\tProperties:
+\t\tapplication.name = \"Spotify\"
+\t\tapplication.process.binary = \"spotify\"
+Sink Input #43
+\tCorked: yes
+\tProperties:
+\t\tapplication.process.binary = \"firefox\"
+Sink Input #44
+\tCorked: no
+\tProperties:
+\t\tapplication.process.binary = \"mpv\"
+";
+        assert_eq!(
+            pulse_streams::playing_binaries(pactl),
+            vec!["spotify", "mpv"]
+        );
+    }
+}
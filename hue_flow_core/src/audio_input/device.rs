@@ -0,0 +1,409 @@
+use super::{zero_input_hint, ZeroInputDetector, ZERO_INPUT_TIMEOUT};
+use super::{CaptureOptions, InputFilter};
+use super::{DeviceChoice, DeviceWatch, Resampler, ANALYSIS_RATE};
+use crate::analysis::{AnalysisFeed, AnalysisWorker, OverlapAnalyzer};
+use crate::audio_interface::{AnalysisFrame, AudioSource, AudioSpectrum};
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample, SupportedStreamConfig};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the capture thread checks for lost or returning devices.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A stream that delivers nothing for this long counts as lost.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+/// Most samples kept for the analyzer when it is not polled (one second).
+const MAX_QUEUED: usize = ANALYSIS_RATE as usize;
+
+/// State shared between the capture thread, the stream callback and readers.
+struct Shared {
+    /// Mono samples at [`ANALYSIS_RATE`] not yet analyzed.
+    samples: Mutex<VecDeque<f32>>,
+    /// Receives the samples instead of `samples` with an analysis thread.
+    feed: Option<AnalysisFeed>,
+    active: AtomicBool,
+    last_data: Mutex<Option<Instant>>,
+    device: Mutex<Option<String>>,
+    lost: AtomicBool,
+    stop: AtomicBool,
+    /// The device delivers only zeros, see [`ZeroInputDetector`].
+    zero_input: AtomicBool,
+}
+
+/// Captures mono audio from an input device and analyzes it per frame.
+///
+/// The cpal stream lives on a dedicated thread (streams are not `Send`),
+/// which also reopens capture when the device disappears; see
+/// [`DeviceWatch`] for the fallback rules.
+pub struct AudioInput {
+    shared: Arc<Shared>,
+    analyzer: OverlapAnalyzer,
+    filter: InputFilter,
+    queued: Vec<f32>,
+    worker: Option<AnalysisWorker>,
+}
+
+/// An input device and the stream configurations it supports.
+#[derive(Debug, Clone)]
+pub struct InputDeviceInfo {
+    /// Audio API, e.g. "ALSA", "WASAPI" or "CoreAudio".
+    pub host: String,
+    pub name: String,
+    /// Default input device of its host.
+    pub is_default: bool,
+    pub configs: Vec<InputConfigInfo>,
+}
+
+/// One supported configuration range of an input device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputConfigInfo {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// e.g. "f32" or "i16"
+    pub sample_format: String,
+}
+
+impl InputDeviceInfo {
+    /// Distinct sample formats over all configurations.
+    pub fn sample_formats(&self) -> Vec<String> {
+        let mut formats: Vec<String> = Vec::new();
+        for config in &self.configs {
+            if !formats.contains(&config.sample_format) {
+                formats.push(config.sample_format.clone());
+            }
+        }
+        formats
+    }
+}
+
+/// Names of all input devices on the default host.
+pub fn input_device_names() -> Vec<String> {
+    names(&cpal::default_host())
+}
+
+/// All input devices of every available audio host.
+pub fn list_input_devices() -> Vec<InputDeviceInfo> {
+    let mut found = Vec::new();
+    for id in cpal::available_hosts() {
+        let Ok(host) = cpal::host_from_id(id) else {
+            continue;
+        };
+        let default_name = host.default_input_device().and_then(|d| d.name().ok());
+        let Ok(devices) = host.input_devices() else {
+            continue;
+        };
+        for device in devices {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+            let configs = device
+                .supported_input_configs()
+                .map(|configs| {
+                    configs
+                        .map(|c| InputConfigInfo {
+                            channels: c.channels(),
+                            min_sample_rate: c.min_sample_rate().0,
+                            max_sample_rate: c.max_sample_rate().0,
+                            sample_format: c.sample_format().to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            found.push(InputDeviceInfo {
+                host: id.name().to_string(),
+                is_default: default_name.as_ref() == Some(&name),
+                name,
+                configs,
+            });
+        }
+    }
+    found
+}
+
+fn names(host: &cpal::Host) -> Vec<String> {
+    host.input_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+impl AudioInput {
+    /// Starts capturing from `options.device` (by name), or the default
+    /// input device when `None` or not connected.
+    pub fn start(options: CaptureOptions) -> Result<Self> {
+        let filter = options.input_filter();
+        let analyzer = OverlapAnalyzer::new(ANALYSIS_RATE, options.hop_size);
+        let worker = if options.analysis_thread {
+            let analyzer = OverlapAnalyzer::new(ANALYSIS_RATE, options.hop_size);
+            let worker = AnalysisWorker::spawn(analyzer, filter.clone())
+                .context("Cannot start audio analysis thread")?;
+            Some(worker)
+        } else {
+            None
+        };
+        let shared = Arc::new(Shared {
+            samples: Mutex::new(VecDeque::new()),
+            feed: worker.as_ref().map(AnalysisWorker::feed),
+            active: AtomicBool::new(false),
+            last_data: Mutex::new(None),
+            device: Mutex::new(None),
+            lost: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+            zero_input: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        thread::Builder::new()
+            .name("hueflow-audio".to_string())
+            .spawn(move || capture_thread(thread_shared, options.device, options.sample_format))
+            .context("Cannot start audio capture thread")?;
+
+        Ok(Self {
+            shared,
+            analyzer,
+            filter,
+            queued: Vec::new(),
+            worker,
+        })
+    }
+
+    /// Device currently captured from, if any.
+    pub fn device_name(&self) -> Option<String> {
+        self.shared.device.lock().unwrap().clone()
+    }
+
+    /// The device has delivered nothing but zeros for a while, which
+    /// usually means the OS blocks it; see [`zero_input_hint`].
+    pub fn zero_input(&self) -> bool {
+        self.shared.zero_input.load(Ordering::Relaxed)
+    }
+
+    /// Moves the captured samples (mono, at [`ANALYSIS_RATE`]) not analyzed
+    /// yet to `out`, for readers of the signal itself such as LTC timecode.
+    /// Nothing arrives here with an analysis thread.
+    pub fn drain_samples(&mut self, out: &mut Vec<f32>) {
+        out.extend(self.shared.samples.lock().unwrap().drain(..));
+    }
+
+    /// How long ago the samples behind the latest analysis were captured,
+    /// with an analysis thread.
+    pub fn analysis_delay(&mut self) -> Option<Duration> {
+        let latest = self.worker.as_mut()?.latest()?;
+        Some(latest.captured.elapsed())
+    }
+}
+
+impl Drop for AudioInput {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl AudioSource for AudioInput {
+    fn next_spectrum(&mut self) -> AudioSpectrum {
+        self.next_frame().spectrum
+    }
+
+    fn next_frame(&mut self) -> AnalysisFrame {
+        if !self.shared.active.load(Ordering::Relaxed) {
+            return AnalysisFrame::default();
+        }
+        if let Some(worker) = &mut self.worker {
+            return worker
+                .latest()
+                .map(|latest| latest.frame.clone())
+                .unwrap_or_default();
+        }
+
+        self.queued.clear();
+        self.queued
+            .extend(self.shared.samples.lock().unwrap().drain(..));
+        self.filter.process(&mut self.queued);
+        let spectrum = self
+            .analyzer
+            .push(&self.queued)
+            .unwrap_or_else(|| self.analyzer.latest());
+        AnalysisFrame {
+            spectrum,
+            bins: self.analyzer.bins().to_vec(),
+            bin_hz: self.analyzer.bin_hz(),
+            ..Default::default()
+        }
+    }
+}
+
+fn capture_thread(shared: Arc<Shared>, preferred: Option<String>, format: Option<String>) {
+    let host = cpal::default_host();
+    let mut watch = DeviceWatch::new(preferred);
+    let mut stream: Option<cpal::Stream> = None;
+
+    while !shared.stop.load(Ordering::Relaxed) {
+        let stalled = stream.is_some()
+            && shared
+                .last_data
+                .lock()
+                .unwrap()
+                .is_none_or(|t| t.elapsed() > STALL_TIMEOUT);
+        let lost = shared.lost.swap(false, Ordering::Relaxed) || stalled;
+
+        if let Some(choice) = watch.poll(lost, &names(&host)) {
+            if let Some(name) = watch.current() {
+                tracing::warn!("Audio device '{}' lost or replaced", name);
+            }
+            stream = None;
+            watch.closed();
+            shared.active.store(false, Ordering::Relaxed);
+            shared.samples.lock().unwrap().clear();
+            shared.zero_input.store(false, Ordering::Relaxed);
+            *shared.device.lock().unwrap() = None;
+
+            match open(&host, &choice, format.as_deref(), &shared) {
+                Ok((new_stream, name)) => {
+                    tracing::info!("Capturing audio from '{}'", name);
+                    *shared.device.lock().unwrap() = Some(name.clone());
+                    shared.active.store(true, Ordering::Relaxed);
+                    watch.opened(&choice, name);
+                    stream = Some(new_stream);
+                }
+                Err(e) => tracing::warn!("Cannot open audio input: {:#}", e),
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    drop(stream);
+}
+
+fn open(
+    host: &cpal::Host,
+    choice: &DeviceChoice,
+    format: Option<&str>,
+    shared: &Arc<Shared>,
+) -> Result<(cpal::Stream, String)> {
+    let device = match choice {
+        DeviceChoice::Named(name) => host
+            .input_devices()?
+            .find(|d| d.name().ok().as_ref() == Some(name))
+            .with_context(|| format!("Audio device '{}' not found", name))?,
+        DeviceChoice::Default => host
+            .default_input_device()
+            .context("No default audio input device")?,
+    };
+    let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+    let config = stream_config(&device, format)?;
+    let channels = config.channels() as usize;
+
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build::<f32>(&device, &config.into(), channels, shared)?,
+        cpal::SampleFormat::I16 => build::<i16>(&device, &config.into(), channels, shared)?,
+        cpal::SampleFormat::U16 => build::<u16>(&device, &config.into(), channels, shared)?,
+        cpal::SampleFormat::I32 => build::<i32>(&device, &config.into(), channels, shared)?,
+        format => anyhow::bail!("Unsupported sample format {:?}", format),
+    };
+    stream.play()?;
+    Ok((stream, name))
+}
+
+/// The device's default configuration, switched to `format` when the device
+/// supports it.
+fn stream_config(device: &cpal::Device, format: Option<&str>) -> Result<SupportedStreamConfig> {
+    let default = device.default_input_config()?;
+    let Some(format) = format else {
+        return Ok(default);
+    };
+    if default.sample_format().to_string() == format {
+        return Ok(default);
+    }
+
+    let rate = default.sample_rate();
+    let matching = device
+        .supported_input_configs()?
+        .filter(|c| c.sample_format().to_string() == format)
+        .max_by_key(|c| {
+            (
+                c.min_sample_rate() <= rate && rate <= c.max_sample_rate(),
+                c.channels(),
+            )
+        });
+    match matching {
+        Some(c) if c.min_sample_rate() <= rate && rate <= c.max_sample_rate() => {
+            Ok(c.with_sample_rate(rate))
+        }
+        Some(c) => Ok(c.with_max_sample_rate()),
+        None => {
+            tracing::warn!(
+                "Sample format '{}' not supported, using {}",
+                format,
+                default.sample_format()
+            );
+            Ok(default)
+        }
+    }
+}
+
+fn build<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channels: usize,
+    shared: &Arc<Shared>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    *shared.last_data.lock().unwrap() = Some(Instant::now());
+    let mut resampler = Resampler::new(config.sample_rate.0)?;
+    let mut mono = Vec::new();
+    let mut resampled = Vec::new();
+    let mut zeros = ZeroInputDetector::default();
+
+    let data_shared = shared.clone();
+    let error_shared = shared.clone();
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            // Downmix interleaved frames to mono
+            mono.clear();
+            mono.extend(data.chunks(channels).map(|frame| {
+                frame.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / channels as f32
+            }));
+            match zeros.update(&mono, Instant::now()) {
+                Some(true) => {
+                    data_shared.zero_input.store(true, Ordering::Relaxed);
+                    tracing::warn!(
+                        "The audio input has delivered only zeros for {} s; {}",
+                        ZERO_INPUT_TIMEOUT.as_secs(),
+                        zero_input_hint()
+                    );
+                }
+                Some(false) => {
+                    data_shared.zero_input.store(false, Ordering::Relaxed);
+                    tracing::info!("The audio input delivers sound again");
+                }
+                None => {}
+            }
+            resampled.clear();
+            resampler.process(&mono, &mut resampled);
+
+            if let Some(feed) = &data_shared.feed {
+                feed.push(resampled.clone(), Instant::now());
+                *data_shared.last_data.lock().unwrap() = Some(Instant::now());
+                return;
+            }
+            let mut samples = data_shared.samples.lock().unwrap();
+            samples.extend(resampled.iter().copied());
+            let excess = samples.len().saturating_sub(MAX_QUEUED);
+            samples.drain(..excess);
+            drop(samples);
+            *data_shared.last_data.lock().unwrap() = Some(Instant::now());
+        },
+        move |e| {
+            tracing::warn!("Audio stream error: {}", e);
+            error_shared.lost.store(true, Ordering::Relaxed);
+        },
+        None,
+    )?;
+    Ok(stream)
+}
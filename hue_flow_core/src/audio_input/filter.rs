@@ -0,0 +1,182 @@
+use std::f64::consts::TAU;
+
+/// Notches per hum filter: the mains frequency and its next two harmonics,
+/// which all fall into the bass band.
+const HUM_HARMONICS: usize = 3;
+/// Quality of each notch; high enough to leave neighbouring notes alone.
+const NOTCH_Q: f32 = 30.0;
+
+/// Time for the gate to close once the level drops below the threshold.
+const GATE_RELEASE_SECS: f32 = 0.1;
+/// Fade time of the gate gain, to avoid clicks.
+const GATE_RAMP_SECS: f32 = 0.005;
+
+/// Second-order IIR filter (RBJ audio EQ cookbook). Computed in f64: at
+/// mains frequencies the coefficients sit too close to 1.0 for f32.
+#[derive(Debug, Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn notch(sample_rate: u32, freq: f32, q: f32) -> Self {
+        let w0 = TAU * freq as f64 / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * q as f64);
+        let a0 = 1.0 + alpha;
+        let cos = w0.cos();
+        Self {
+            b: [1.0 / a0, -2.0 * cos / a0, 1.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let input = input as f64;
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output as f32
+    }
+}
+
+/// Removes mains hum (50 or 60 Hz and harmonics) that would otherwise keep
+/// the bass band lit.
+#[derive(Debug, Clone)]
+pub struct HumFilter {
+    notches: Vec<Biquad>,
+}
+
+impl HumFilter {
+    pub fn new(sample_rate: u32, mains_hz: f32) -> Self {
+        let nyquist = sample_rate as f32 / 2.0;
+        let notches = (1..=HUM_HARMONICS)
+            .map(|n| mains_hz * n as f32)
+            .filter(|freq| *freq < nyquist)
+            .map(|freq| Biquad::notch(sample_rate, freq, NOTCH_Q))
+            .collect();
+        Self { notches }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            for notch in &mut self.notches {
+                *sample = notch.process(*sample);
+            }
+        }
+    }
+}
+
+/// Silences the input while its level stays below a threshold, so
+/// microphone hiss in a quiet room does not drive the effects.
+#[derive(Debug, Clone)]
+pub struct NoiseGate {
+    threshold: f32,
+    release: f32,
+    ramp: f32,
+    envelope: f32,
+    gain: f32,
+}
+
+impl NoiseGate {
+    /// `threshold_db` is in dBFS, e.g. -50.0.
+    pub fn new(sample_rate: u32, threshold_db: f32) -> Self {
+        let rate = sample_rate as f32;
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            release: (-1.0 / (GATE_RELEASE_SECS * rate)).exp(),
+            ramp: 1.0 / (GATE_RAMP_SECS * rate),
+            envelope: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.envelope = sample.abs().max(self.envelope * self.release);
+            let target = if self.envelope > self.threshold {
+                1.0
+            } else {
+                0.0
+            };
+            if self.gain < target {
+                self.gain = (self.gain + self.ramp).min(target);
+            } else {
+                self.gain = (self.gain - self.ramp).max(target);
+            }
+            *sample *= self.gain;
+        }
+    }
+}
+
+/// Clean-up applied to captured audio before analysis.
+#[derive(Debug, Clone, Default)]
+pub struct InputFilter {
+    pub hum: Option<HumFilter>,
+    pub gate: Option<NoiseGate>,
+}
+
+impl InputFilter {
+    /// Hum removal runs first, so hum alone never opens the gate.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if let Some(hum) = &mut self.hum {
+            hum.process(samples);
+        }
+        if let Some(gate) = &mut self.gate {
+            gate.process(samples);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn sine(freq: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / RATE as f32).sin() * amplitude)
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn test_hum_filter_notches_mains() {
+        let mut hum = HumFilter::new(RATE, 50.0);
+        let mut mains = sine(50.0, 0.5, RATE as usize * 2);
+        hum.process(&mut mains);
+        // Skip the filter settling in
+        assert!(peak(&mains[RATE as usize..]) < 0.01);
+
+        let mut hum = HumFilter::new(RATE, 50.0);
+        let mut note = sine(75.0, 0.5, RATE as usize);
+        hum.process(&mut note);
+        assert!(peak(&note[RATE as usize / 2..]) > 0.4);
+    }
+
+    #[test]
+    fn test_noise_gate() {
+        let mut filter = InputFilter {
+            hum: None,
+            gate: Some(NoiseGate::new(RATE, -50.0)),
+        };
+        // Hiss around -66 dBFS stays closed
+        let mut hiss = sine(3000.0, 0.0005, 4800);
+        filter.process(&mut hiss);
+        assert_eq!(peak(&hiss), 0.0);
+
+        let mut music = sine(440.0, 0.3, 4800);
+        filter.process(&mut music);
+        assert!(peak(&music[480..]) > 0.29);
+    }
+}
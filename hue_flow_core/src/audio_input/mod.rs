@@ -0,0 +1,287 @@
+//! Live audio capture from an input device.
+//!
+//! [`AudioInput`] (feature `capture`) records from the preferred device, falls
+//! back to the system default input when that device disappears (USB
+//! interface unplugged, Bluetooth headset disconnected) and moves back once it
+//! returns. The policy lives in [`DeviceWatch`] so it works with any backend.
+//! Captured audio is converted to [`ANALYSIS_RATE`] and cleaned up by an
+//! [`InputFilter`] before analysis.
+//!
+//! A device that delivers nothing but zeros is usually blocked by the OS
+//! (macOS microphone permission, another application holding a Windows
+//! device in exclusive mode) rather than listening to a quiet room; the
+//! [`ZeroInputDetector`] notices and [`zero_input_hint`] says what to do.
+#[cfg(feature = "capture")]
+mod device;
+mod filter;
+mod resample;
+
+use crate::analysis::DEFAULT_HOP;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "capture")]
+pub use device::{
+    input_device_names, list_input_devices, AudioInput, InputConfigInfo, InputDeviceInfo,
+};
+pub use filter::{HumFilter, InputFilter, NoiseGate};
+pub use resample::{Resampler, ANALYSIS_RATE};
+
+/// Settings for [`AudioInput`] (feature `capture`).
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    /// Preferred input device by name; `None` uses the default input.
+    pub device: Option<String>,
+    /// Preferred sample format, e.g. "i16" or "f32"; `None` uses the device default.
+    pub sample_format: Option<String>,
+    /// Samples between overlapping analysis windows, see [`OverlapAnalyzer`].
+    ///
+    /// [`OverlapAnalyzer`]: crate::analysis::OverlapAnalyzer
+    pub hop_size: usize,
+    /// Mains frequency (50 or 60 Hz) to notch out; `None` disables.
+    pub hum_hz: Option<f32>,
+    /// Noise gate threshold in dBFS, e.g. -50.0; `None` disables.
+    pub noise_gate_db: Option<f32>,
+    /// Filter and analyze on an [`AnalysisWorker`] thread instead of the
+    /// thread asking for frames.
+    ///
+    /// [`AnalysisWorker`]: crate::analysis::AnalysisWorker
+    pub analysis_thread: bool,
+}
+
+impl CaptureOptions {
+    /// The input filter described by these options.
+    pub fn input_filter(&self) -> InputFilter {
+        InputFilter {
+            hum: self.hum_hz.map(|hz| HumFilter::new(ANALYSIS_RATE, hz)),
+            gate: self
+                .noise_gate_db
+                .map(|db| NoiseGate::new(ANALYSIS_RATE, db)),
+        }
+    }
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            device: None,
+            sample_format: None,
+            hop_size: DEFAULT_HOP,
+            hum_hz: None,
+            noise_gate_db: None,
+            analysis_thread: false,
+        }
+    }
+}
+
+/// Input that is exactly zero for this long is reported as blocked. Even a
+/// silent room gives a microphone some noise.
+pub const ZERO_INPUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Notices input made of exact zeros, as delivered by devices the OS keeps
+/// from the application.
+#[derive(Debug, Clone)]
+pub struct ZeroInputDetector {
+    timeout: Duration,
+    zero_since: Option<Instant>,
+    silent: bool,
+}
+
+impl Default for ZeroInputDetector {
+    fn default() -> Self {
+        Self::new(ZERO_INPUT_TIMEOUT)
+    }
+}
+
+impl ZeroInputDetector {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            zero_since: None,
+            silent: false,
+        }
+    }
+
+    /// Feeds a block of raw samples (before any noise gate). Returns
+    /// `Some(true)` once the input has been all zeros for the timeout, and
+    /// `Some(false)` when a signal returns after that.
+    pub fn update(&mut self, samples: &[f32], now: Instant) -> Option<bool> {
+        if samples.is_empty() {
+            return None;
+        }
+        if samples.iter().any(|&s| s != 0.0) {
+            self.zero_since = None;
+            return std::mem::take(&mut self.silent).then_some(false);
+        }
+        let since = *self.zero_since.get_or_insert(now);
+        if !self.silent && now.duration_since(since) >= self.timeout {
+            self.silent = true;
+            return Some(true);
+        }
+        None
+    }
+
+    /// The input is currently reported as all zeros.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+}
+
+/// What usually makes capture deliver only zeros on this OS, and the fix.
+pub fn zero_input_hint() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "macOS records silence until the app is allowed to use the microphone: \
+         enable your terminal under System Settings > Privacy & Security > \
+         Microphone, then restart HueFlow"
+    } else if cfg!(windows) {
+        "another application may hold the device in exclusive mode: close it or \
+         untick 'Allow applications to take exclusive control of this device' \
+         (Sound settings > device properties > Advanced); also check that \
+         Settings > Privacy & security > Microphone allows desktop apps"
+    } else {
+        "check that the input is not muted and its capture level is up \
+         (alsamixer, pavucontrol), and that your user may use the audio device \
+         (e.g. the 'audio' group)"
+    }
+}
+
+/// Device capture should open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceChoice {
+    /// The preferred device, by name.
+    Named(String),
+    /// Whatever the system default input is.
+    Default,
+}
+
+#[derive(Debug, Clone)]
+struct Opened {
+    name: String,
+    preferred: bool,
+}
+
+/// Decides when to reopen capture as devices come and go.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceWatch {
+    preferred: Option<String>,
+    opened: Option<Opened>,
+}
+
+impl DeviceWatch {
+    pub fn new(preferred: Option<String>) -> Self {
+        Self {
+            preferred,
+            opened: None,
+        }
+    }
+
+    /// Checks the current device against the `available` device names.
+    ///
+    /// Returns the device to (re)open, or `None` to keep the current stream.
+    /// `lost` reports that the running stream failed or stopped delivering.
+    pub fn poll(&self, lost: bool, available: &[String]) -> Option<DeviceChoice> {
+        let preferred_available = self
+            .preferred
+            .as_ref()
+            .filter(|name| available.contains(name));
+        let choose = || match preferred_available {
+            Some(name) => DeviceChoice::Named(name.clone()),
+            None => DeviceChoice::Default,
+        };
+
+        let Some(opened) = &self.opened else {
+            return Some(choose());
+        };
+        if lost || !available.contains(&opened.name) {
+            return Some(choose());
+        }
+        if !opened.preferred && preferred_available.is_some() {
+            return Some(choose());
+        }
+        None
+    }
+
+    /// Records that `name` was opened for `choice`.
+    pub fn opened(&mut self, choice: &DeviceChoice, name: String) {
+        let preferred =
+            matches!(choice, DeviceChoice::Named(_)) || self.preferred.as_ref() == Some(&name);
+        self.opened = Some(Opened { name, preferred });
+    }
+
+    /// Records that capture is not running, so the next poll retries.
+    pub fn closed(&mut self) {
+        self.opened = None;
+    }
+
+    /// Name of the device currently captured from.
+    pub fn current(&self) -> Option<&str> {
+        self.opened.as_ref().map(|o| o.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_falls_back_and_reattaches() {
+        let mut watch = DeviceWatch::new(Some("USB Interface".to_string()));
+        let with_usb = names(&["Built-in Mic", "USB Interface"]);
+        let without_usb = names(&["Built-in Mic"]);
+
+        let choice = watch.poll(false, &with_usb).unwrap();
+        assert_eq!(choice, DeviceChoice::Named("USB Interface".to_string()));
+        watch.opened(&choice, "USB Interface".to_string());
+        assert_eq!(watch.poll(false, &with_usb), None);
+
+        // Unplugged: fall back to the default input
+        let choice = watch.poll(false, &without_usb).unwrap();
+        assert_eq!(choice, DeviceChoice::Default);
+        watch.opened(&choice, "Built-in Mic".to_string());
+        assert_eq!(watch.poll(false, &without_usb), None);
+
+        // Plugged back in: return to it
+        assert_eq!(
+            watch.poll(false, &with_usb),
+            Some(DeviceChoice::Named("USB Interface".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_zero_input() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut detector = ZeroInputDetector::new(Duration::from_secs(5));
+        assert_eq!(detector.update(&[0.0; 64], at(0)), None);
+        assert_eq!(detector.update(&[], at(10)), None);
+        assert_eq!(detector.update(&[0.0, -0.0], at(4)), None);
+        assert_eq!(detector.update(&[0.0; 64], at(5)), Some(true));
+        assert!(detector.is_silent());
+        // Reported once
+        assert_eq!(detector.update(&[0.0; 64], at(6)), None);
+
+        assert_eq!(detector.update(&[0.0, 1e-6], at(7)), Some(false));
+        assert_eq!(detector.update(&[0.01; 64], at(8)), None);
+        // A quiet stretch shorter than the timeout is not reported
+        assert_eq!(detector.update(&[0.0; 64], at(9)), None);
+        assert_eq!(detector.update(&[0.0; 64], at(12)), None);
+    }
+
+    #[test]
+    fn test_lost_stream_reopens() {
+        let mut watch = DeviceWatch::default();
+        let available = names(&["Built-in Mic"]);
+        let choice = watch.poll(false, &available).unwrap();
+        watch.opened(&choice, "Built-in Mic".to_string());
+
+        assert_eq!(watch.poll(false, &available), None);
+        assert_eq!(watch.poll(true, &available), Some(DeviceChoice::Default));
+
+        watch.closed();
+        assert_eq!(watch.current(), None);
+        assert_eq!(watch.poll(false, &available), Some(DeviceChoice::Default));
+    }
+}
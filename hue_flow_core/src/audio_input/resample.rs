@@ -0,0 +1,152 @@
+use anyhow::Result;
+#[cfg(feature = "fft")]
+use rubato::{FftFixedIn, Resampler as _};
+
+/// Sample rate the analyzer always receives, whatever the device runs at.
+pub const ANALYSIS_RATE: u32 = 48_000;
+
+/// Input frames converted per step; small enough to add no noticeable latency.
+#[cfg(feature = "fft")]
+const CHUNK: usize = 512;
+
+/// Converts mono audio from a device's sample rate to [`ANALYSIS_RATE`],
+/// so FFT band boundaries stay correct on 22.05 kHz or 96 kHz devices.
+///
+/// Input can arrive in chunks of any size; leftovers are kept for the next
+/// call. Matching rates pass through untouched.
+#[cfg(feature = "fft")]
+pub struct Resampler {
+    inner: Option<FftFixedIn<f32>>,
+    pending: Vec<f32>,
+}
+
+#[cfg(feature = "fft")]
+impl Resampler {
+    pub fn new(input_rate: u32) -> Result<Self> {
+        let inner = if input_rate == ANALYSIS_RATE {
+            None
+        } else {
+            Some(FftFixedIn::new(
+                input_rate as usize,
+                ANALYSIS_RATE as usize,
+                CHUNK,
+                2,
+                1,
+            )?)
+        };
+        Ok(Self {
+            inner,
+            pending: Vec::with_capacity(CHUNK * 2),
+        })
+    }
+
+    /// Converts `samples` and appends the result to `out`.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let Some(inner) = &mut self.inner else {
+            out.extend_from_slice(samples);
+            return;
+        };
+
+        self.pending.extend_from_slice(samples);
+        let mut start = 0;
+        while self.pending.len() - start >= inner.input_frames_next() {
+            let end = start + inner.input_frames_next();
+            match inner.process(&[&self.pending[start..end]], None) {
+                Ok(converted) => out.extend_from_slice(&converted[0]),
+                Err(e) => tracing::warn!("Resampling failed: {}", e),
+            }
+            start = end;
+        }
+        self.pending.drain(..start);
+    }
+}
+
+/// Converts mono audio to [`ANALYSIS_RATE`] by linear interpolation.
+///
+/// Used without the `fft` feature. It aliases more than the band-limited
+/// resampler, which the three-band analysis tolerates.
+#[cfg(not(feature = "fft"))]
+pub struct Resampler {
+    /// Input samples per output sample; `None` passes through.
+    step: Option<f64>,
+    /// Position of the next output sample after `previous`, in input samples.
+    position: f64,
+    previous: f32,
+}
+
+#[cfg(not(feature = "fft"))]
+impl Resampler {
+    pub fn new(input_rate: u32) -> Result<Self> {
+        anyhow::ensure!(input_rate > 0, "Invalid sample rate 0");
+        Ok(Self {
+            step: (input_rate != ANALYSIS_RATE).then(|| input_rate as f64 / ANALYSIS_RATE as f64),
+            position: 0.0,
+            previous: 0.0,
+        })
+    }
+
+    /// Converts `samples` and appends the result to `out`.
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let Some(step) = self.step else {
+            out.extend_from_slice(samples);
+            return;
+        };
+        for &sample in samples {
+            while self.position <= 1.0 {
+                let t = self.position as f32;
+                out.push(self.previous + (sample - self.previous) * t);
+                self.position += step;
+            }
+            self.position -= 1.0;
+            self.previous = sample;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{DefaultAnalyzer, FFT_SIZE};
+    use crate::audio_interface::AudioProcessor;
+
+    fn sine(freq: f32, rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn test_matching_rate_passes_through() {
+        let mut resampler = Resampler::new(ANALYSIS_RATE).unwrap();
+        let mut out = Vec::new();
+        resampler.process(&[0.1, 0.2, 0.3], &mut out);
+        assert_eq!(out, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn test_odd_rates_keep_band_boundaries() {
+        for rate in [22_050, 96_000] {
+            let mut resampler = Resampler::new(rate).unwrap();
+            let input = sine(150.0, rate, rate as usize / 2);
+            let mut out = Vec::new();
+            // Feed in uneven pieces, as capture callbacks do
+            for piece in input.chunks(333) {
+                resampler.process(piece, &mut out);
+            }
+
+            // The FFT resampler holds back up to a few chunks
+            let expected = ANALYSIS_RATE as usize / 2;
+            assert!(
+                out.len() > expected - 2048 && out.len() <= expected + 1,
+                "{} Hz gave {} samples",
+                rate,
+                out.len()
+            );
+
+            // 150 Hz must still land in the bass band at the analysis rate
+            let mut analyzer = DefaultAnalyzer::new(ANALYSIS_RATE);
+            let s = analyzer.process(&out[out.len() - FFT_SIZE..]);
+            assert!(s.bass > 0.9 && s.mids < 0.5, "{} Hz gave {:?}", rate, s);
+        }
+    }
+}
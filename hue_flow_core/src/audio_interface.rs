@@ -6,6 +6,138 @@ pub struct AudioSpectrum {
     pub energy: f32,
 }
 
+/// Onset information for the current frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BeatInfo {
+    /// A beat starts on this frame.
+    pub onset: bool,
+    /// How far the bass rose above its recent average (0.0 - 1.0).
+    pub strength: f32,
+}
+
+/// Position on the beat grid, see [`TempoClock`](crate::tempo::TempoClock).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TempoInfo {
+    pub bpm: f32,
+    /// Beats counted since the clock started.
+    pub beat: u64,
+    /// Progress through the current beat (0.0 - 1.0).
+    pub phase: f32,
+    /// The clock follows the music; otherwise it free-runs at the last tempo.
+    pub locked: bool,
+}
+
+impl TempoInfo {
+    /// Bars counted since the clock started (4/4).
+    pub fn bar(&self) -> u64 {
+        self.beat / crate::tempo::BEATS_PER_BAR
+    }
+
+    /// Beat within the bar (0 - 3).
+    pub fn beat_in_bar(&self) -> u64 {
+        self.beat % crate::tempo::BEATS_PER_BAR
+    }
+
+    /// Progress through the current bar (0.0 - 1.0).
+    pub fn bar_phase(&self) -> f32 {
+        (self.beat_in_bar() as f32 + self.phase) / crate::tempo::BEATS_PER_BAR as f32
+    }
+}
+
+/// Everything an effect gets to see about one frame of audio.
+///
+/// Sources without an FFT (e.g. [`SyntheticAudio`]) leave `bins` empty, so
+/// effects should fall back to `spectrum` when there are none.
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisFrame {
+    /// Band summary.
+    pub spectrum: AudioSpectrum,
+    /// Normalized magnitudes (0.0 - 1.0) from DC up to Nyquist.
+    pub bins: Vec<f32>,
+    /// Width of one bin in Hz.
+    pub bin_hz: f32,
+    /// Filled in by the render loop, whatever the source.
+    pub beat: BeatInfo,
+    /// Filled in by the render loop, like `beat`.
+    pub tempo: TempoInfo,
+    /// A drop hits on this frame (see [`DropDetector`](crate::analysis::DropDetector)).
+    /// Filled in by the render loop, like `beat`.
+    pub drop: bool,
+    /// Band summaries of the last two seconds, including this frame's.
+    /// Filled in by the render loop, like `beat`.
+    pub history: crate::analysis::SpectrumHistory,
+    /// When the frame is rendered, see [`timing::now`](crate::timing::now).
+    /// Effects animate by it rather than reading the clock themselves, which
+    /// panics on wasm32-unknown-unknown; hosts there pass their own time.
+    /// Filled in by the render loop, like `beat`.
+    pub time: std::time::Duration,
+}
+
+impl AnalysisFrame {
+    /// Strongest bin between `low` and `high` Hz, or 0.0 without bins.
+    pub fn peak(&self, low: f32, high: f32) -> f32 {
+        if self.bins.is_empty() || self.bin_hz <= 0.0 {
+            return 0.0;
+        }
+        let first = (low / self.bin_hz).ceil().max(0.0) as usize;
+        let last = ((high / self.bin_hz) as usize).min(self.bins.len() - 1);
+        self.bins
+            .get(first..=last)
+            .map(|bins| bins.iter().copied().fold(0.0, f32::max))
+            .unwrap_or(0.0)
+    }
+}
+
+impl From<AudioSpectrum> for AnalysisFrame {
+    fn from(spectrum: AudioSpectrum) -> Self {
+        Self {
+            spectrum,
+            ..Default::default()
+        }
+    }
+}
+
 pub trait AudioProcessor {
     fn process(&mut self, samples: &[f32]) -> AudioSpectrum;
 }
+
+/// Supplies the audio spectrum for each rendered frame.
+///
+/// Sources are polled once per frame by the `HueFlow` render loop and should
+/// return the most recent analysis without blocking.
+pub trait AudioSource: Send {
+    fn next_spectrum(&mut self) -> AudioSpectrum;
+
+    /// The band summary plus the FFT bins, for sources that have them.
+    fn next_frame(&mut self) -> AnalysisFrame {
+        self.next_spectrum().into()
+    }
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for Box<S> {
+    fn next_spectrum(&mut self) -> AudioSpectrum {
+        (**self).next_spectrum()
+    }
+
+    fn next_frame(&mut self) -> AnalysisFrame {
+        (**self).next_frame()
+    }
+}
+
+/// Drifting sine waves on each band, for demos and testing without a microphone.
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticAudio {
+    phase: f32,
+}
+
+impl AudioSource for SyntheticAudio {
+    fn next_spectrum(&mut self) -> AudioSpectrum {
+        self.phase += 0.1;
+        AudioSpectrum {
+            bass: (self.phase.sin() * 0.5 + 0.5).abs(),
+            mids: ((self.phase * 1.5).sin() * 0.5 + 0.5).abs(),
+            highs: ((self.phase * 2.0).sin() * 0.5 + 0.5).abs(),
+            energy: 1.0,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Color model helpers shared by effects.
+
+/// Warmest color temperature supported by Hue white ambiance lights (≈500 mirek).
+pub const MIN_KELVIN: f32 = 2000.0;
+/// Coolest color temperature supported by Hue white ambiance lights (≈153 mirek).
+pub const MAX_KELVIN: f32 = 6500.0;
+
+/// Converts a blackbody color temperature in Kelvin to full-brightness RGB.
+///
+/// Uses Tanner Helland's curve fit, accurate enough for lighting between
+/// 1000 K and 40000 K.
+pub fn kelvin_to_rgb(kelvin: f32) -> (u8, u8, u8) {
+    let temp = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if temp <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (temp - 60.0).powf(-0.133_204_76)
+    };
+
+    let g = if temp <= 66.0 {
+        99.470_8 * temp.ln() - 161.119_57
+    } else {
+        288.122_16 * (temp - 60.0).powf(-0.075_514_846)
+    };
+
+    let b = if temp >= 66.0 {
+        255.0
+    } else if temp <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (temp - 10.0).ln() - 305.044_8
+    };
+
+    (
+        r.clamp(0.0, 255.0) as u8,
+        g.clamp(0.0, 255.0) as u8,
+        b.clamp(0.0, 255.0) as u8,
+    )
+}
+
+/// A color the output stages work on: an effect's 8-bit color, or an
+/// unrounded one (`[f32; 3]`, 0.0 - 255.0 per component) that keeps dimmed
+/// levels apart which would round to the same 8-bit value.
+pub trait Rgb: Copy {
+    /// Components as floats, 0.0 - 255.0.
+    fn to_rgb(self) -> [f32; 3];
+    /// From float components, clamped to 0.0 - 255.0.
+    fn from_rgb(rgb: [f32; 3]) -> Self;
+}
+
+impl Rgb for (u8, u8, u8) {
+    fn to_rgb(self) -> [f32; 3] {
+        [self.0 as f32, self.1 as f32, self.2 as f32]
+    }
+
+    fn from_rgb(rgb: [f32; 3]) -> Self {
+        let channel = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        (channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+    }
+}
+
+impl Rgb for [f32; 3] {
+    fn to_rgb(self) -> [f32; 3] {
+        self
+    }
+
+    fn from_rgb(rgb: [f32; 3]) -> Self {
+        rgb.map(|v| v.clamp(0.0, 255.0))
+    }
+}
+
+/// Scales an RGB color by `brightness` (0.0 - 1.0).
+pub fn scale(color: (u8, u8, u8), brightness: f32) -> (u8, u8, u8) {
+    let brightness = brightness.clamp(0.0, 1.0);
+    (
+        (color.0 as f32 * brightness) as u8,
+        (color.1 as f32 * brightness) as u8,
+        (color.2 as f32 * brightness) as u8,
+    )
+}
+
+/// Blends from `a` (`t` = 0.0) to `b` (`t` = 1.0).
+pub fn mix(a: (u8, u8, u8), b: (u8, u8, u8), t: f32) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+/// Projects an arbitrary RGB color onto the blackbody curve.
+///
+/// Brightness is preserved (max channel), while the hue is reduced to a warmth:
+/// red-dominant colors map towards `min_kelvin`, blue-dominant colors towards
+/// `max_kelvin` and neutral/green colors to the middle of the range.
+pub fn constrain_to_ct(color: (u8, u8, u8), min_kelvin: f32, max_kelvin: f32) -> (u8, u8, u8) {
+    let (r, g, b) = (color.0 as f32, color.1 as f32, color.2 as f32);
+    let brightness = r.max(g).max(b) / 255.0;
+    if brightness <= 0.0 {
+        return (0, 0, 0);
+    }
+
+    let coolness = if r + b > 0.0 { b / (r + b) } else { 0.5 };
+    let kelvin = min_kelvin + coolness * (max_kelvin - min_kelvin);
+    scale(kelvin_to_rgb(kelvin), brightness)
+}
+
+/// Converts RGB to CIE xy chromaticity and a brightness (0.0 - 1.0), as the
+/// REST API expects for `xy`/`bri`.
+///
+/// Follows Philips' recipe: sRGB gamma is removed and the wide-gamut D65
+/// matrix applied. Black maps to the white point at zero brightness.
+pub fn rgb_to_xy(color: (u8, u8, u8)) -> ((f32, f32), f32) {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c > 0.04045 {
+            ((c + 0.055) / 1.055).powf(2.4)
+        } else {
+            c / 12.92
+        }
+    };
+    let (r, g, b) = (linear(color.0), linear(color.1), linear(color.2));
+    let x = r * 0.664_511 + g * 0.154_324 + b * 0.162_028;
+    let y = r * 0.283_881 + g * 0.668_433 + b * 0.047_685;
+    let z = r * 0.000_088 + g * 0.072_310 + b * 0.986_039;
+    let sum = x + y + z;
+    let brightness = color.0.max(color.1).max(color.2) as f32 / 255.0;
+    if sum <= 0.0 {
+        return ((0.3127, 0.3290), 0.0);
+    }
+    ((x / sum, y / sum), brightness)
+}
+
+/// Fully saturated color at `degrees` around the color wheel (0 = red,
+/// 120 = green, 240 = blue).
+pub fn hue_to_rgb(degrees: f32) -> (u8, u8, u8) {
+    let h = degrees.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let channel = |v: f32| (v * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+/// Parses a hex color like `ff8000` or `#ff8000`.
+pub fn parse_hex(s: &str) -> Option<(u8, u8, u8)> {
+    let s = s.trim().trim_start_matches('#');
+    if s.len() != 6 {
+        return None;
+    }
+    let bytes = hex::decode(s).ok()?;
+    Some((bytes[0], bytes[1], bytes[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kelvin_to_rgb_endpoints() {
+        // Candle light is strongly red, daylight is roughly neutral
+        let warm = kelvin_to_rgb(2000.0);
+        assert_eq!(warm.0, 255);
+        assert!(warm.2 < 60);
+
+        let daylight = kelvin_to_rgb(6600.0);
+        assert!(daylight.0 > 240 && daylight.1 > 240 && daylight.2 > 240);
+    }
+
+    #[test]
+    fn test_rgb_to_xy() {
+        let ((x, y), bri) = rgb_to_xy((255, 0, 0));
+        assert!((x - 0.7006).abs() < 0.001 && (y - 0.2993).abs() < 0.001);
+        assert_eq!(bri, 1.0);
+
+        let ((x, y), bri) = rgb_to_xy((128, 128, 128));
+        assert!((x - 0.3227).abs() < 0.001 && (y - 0.3290).abs() < 0.001);
+        assert!((bri - 0.5).abs() < 0.01);
+
+        assert_eq!(rgb_to_xy((0, 0, 0)).1, 0.0);
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("#ff8000"), Some((255, 128, 0)));
+        assert_eq!(parse_hex("00ff00"), Some((0, 255, 0)));
+        assert_eq!(parse_hex("fff"), None);
+        assert_eq!(parse_hex("zzzzzz"), None);
+    }
+
+    #[test]
+    fn test_hue_to_rgb() {
+        assert_eq!(hue_to_rgb(0.0), (255, 0, 0));
+        assert_eq!(hue_to_rgb(120.0), (0, 255, 0));
+        assert_eq!(hue_to_rgb(-120.0), (0, 0, 255));
+        assert_eq!(hue_to_rgb(390.0), (255, 128, 0));
+    }
+
+    #[test]
+    fn test_constrain_to_ct() {
+        assert_eq!(
+            constrain_to_ct((0, 0, 0), MIN_KELVIN, MAX_KELVIN),
+            (0, 0, 0)
+        );
+
+        let red = constrain_to_ct((255, 0, 0), MIN_KELVIN, MAX_KELVIN);
+        assert_eq!(red, kelvin_to_rgb(MIN_KELVIN));
+
+        let dim_blue = constrain_to_ct((0, 0, 128), MIN_KELVIN, MAX_KELVIN);
+        assert!(dim_blue.2 <= 128);
+        assert!(dim_blue.0 > 0 && dim_blue.1 > 0);
+    }
+}
@@ -0,0 +1,643 @@
+//! Configuration sources for [`HueConfig`].
+//!
+//! A configuration is assembled from a chain of providers, each overriding the
+//! fields set by the previous one:
+//!
+//! 1. Environment variables (`HUEFLOW_BRIDGE_IP`, `HUEFLOW_APP_KEY`,
+//!    `HUEFLOW_CLIENT_KEY`, `HUEFLOW_APPLICATION_ID`, `HUEFLOW_GROUP_ID`)
+//! 2. The JSON config file (`hue_config.json`, or the path in `HUEFLOW_CONFIG`)
+//! 3. Command line flags
+//!
+//! This lets container deployments run entirely from environment variables
+//! without mounting a config file.
+//!
+//! Config files carry a `version`; files written by older releases are
+//! upgraded by [`migrate`] when loaded, and on disk by [`migrate_file`].
+//! Writes go through [`write_atomic`], so a crash mid-write leaves the old
+//! file intact.
+use crate::credentials::{parse_client_key, CredentialsError};
+use crate::models::{HueConfig, CONFIG_VERSION};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+pub const CONFIG_FILE: &str = "hue_config.json";
+
+pub const ENV_CONFIG_PATH: &str = "HUEFLOW_CONFIG";
+pub const ENV_BRIDGE_IP: &str = "HUEFLOW_BRIDGE_IP";
+pub const ENV_APP_KEY: &str = "HUEFLOW_APP_KEY";
+pub const ENV_CLIENT_KEY: &str = "HUEFLOW_CLIENT_KEY";
+pub const ENV_APPLICATION_ID: &str = "HUEFLOW_APPLICATION_ID";
+pub const ENV_GROUP_ID: &str = "HUEFLOW_GROUP_ID";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to access config file {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Missing configuration value '{0}'. Run 'hueflow setup' or set {1}.")]
+    Missing(&'static str, &'static str),
+    #[error(
+        "Config file {0} has version {1}, newer than this release supports ({CONFIG_VERSION})"
+    )]
+    TooNew(PathBuf, u64),
+    #[error("Unknown config key '{0}'")]
+    UnknownKey(String),
+    #[error("Invalid value for '{0}': {1}")]
+    InvalidValue(String, String),
+    #[error("Invalid credentials: {0}. Run 'hueflow setup' again.")]
+    Credentials(#[from] CredentialsError),
+}
+
+/// Field values supplied by a single configuration source.
+/// `None` leaves the value from the previous source untouched.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub bridge_ip: Option<String>,
+    pub username: Option<String>,
+    pub client_key: Option<String>,
+    pub application_id: Option<String>,
+    pub entertainment_group_id: Option<String>,
+}
+
+impl ConfigOverrides {
+    /// Reads overrides from the `HUEFLOW_*` environment variables.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads overrides through an arbitrary key lookup (used by `from_env`).
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |key: &str| lookup(key).filter(|v| !v.is_empty());
+        Self {
+            bridge_ip: get(ENV_BRIDGE_IP),
+            username: get(ENV_APP_KEY),
+            client_key: get(ENV_CLIENT_KEY),
+            application_id: get(ENV_APPLICATION_ID),
+            entertainment_group_id: get(ENV_GROUP_ID),
+        }
+    }
+
+    /// Applies the values present in this source onto `config`.
+    pub fn apply(&self, config: &mut HueConfig) {
+        self.apply_where(config, |_| true);
+    }
+
+    /// Applies the values present in this source only where `config` has none,
+    /// i.e. with lower precedence than the values already in `config`.
+    pub fn apply_missing(&self, config: &mut HueConfig) {
+        self.apply_where(config, |current| current.is_empty());
+    }
+
+    fn apply_where(&self, config: &mut HueConfig, should_set: impl Fn(&str) -> bool) {
+        let set = |target: &mut String, value: &Option<String>| {
+            if let Some(v) = value {
+                if should_set(target) {
+                    *target = v.clone();
+                }
+            }
+        };
+        set(&mut config.bridge_ip, &self.bridge_ip);
+        set(&mut config.username, &self.username);
+        set(&mut config.client_key, &self.client_key);
+        set(&mut config.application_id, &self.application_id);
+        set(
+            &mut config.entertainment_group_id,
+            &self.entertainment_group_id,
+        );
+    }
+}
+
+/// Path of the config file: `HUEFLOW_CONFIG` if set, otherwise `hue_config.json`
+/// in the working directory.
+pub fn config_path() -> PathBuf {
+    std::env::var_os(ENV_CONFIG_PATH)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+}
+
+/// Directory holding the config file; presets and other state live next to it.
+pub fn config_dir() -> PathBuf {
+    config_path()
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Loads the config file, returning `Ok(None)` when it does not exist.
+pub fn load_file(path: &Path) -> Result<Option<HueConfig>, ConfigError> {
+    Ok(read_file(path)?.map(|(config, _)| config))
+}
+
+/// Loads and upgrades the config file, with the version it had on disk.
+fn read_file(path: &Path) -> Result<Option<(HueConfig, u64)>, ConfigError> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+    };
+    let parse_error = |e| ConfigError::Parse(path.to_path_buf(), e);
+    let mut value: Value = serde_json::from_str(&content).map_err(parse_error)?;
+    let from = migrate(&mut value).map_err(|v| ConfigError::TooNew(path.to_path_buf(), v))?;
+    if from < CONFIG_VERSION as u64 {
+        tracing::info!(
+            "Upgraded config {} from version {} to {}",
+            path.display(),
+            from,
+            CONFIG_VERSION
+        );
+    }
+    let config = serde_json::from_value(value).map_err(parse_error)?;
+    Ok(Some((config, from)))
+}
+
+/// Upgrades a parsed config file to [`CONFIG_VERSION`] in place.
+///
+/// Returns the version the file had, or that version as the error when it is
+/// newer than this release understands. Files without a `version` are
+/// version 0: early releases stored the bridge address as `ip`.
+pub fn migrate(value: &mut Value) -> Result<u64, u64> {
+    let Some(fields) = value.as_object_mut() else {
+        return Ok(CONFIG_VERSION as u64);
+    };
+    let version = fields.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CONFIG_VERSION as u64 {
+        return Err(version);
+    }
+
+    if version < 1 {
+        if let Some(ip) = fields.remove("ip") {
+            fields.entry("bridge_ip").or_insert(ip);
+        }
+    }
+
+    fields.insert("version".to_string(), CONFIG_VERSION.into());
+    Ok(version)
+}
+
+/// Upgrades the config file at `path` to [`CONFIG_VERSION`] on disk, keeping
+/// the original as `<file>.v<version>.bak`.
+///
+/// Returns the version the file had (unchanged when already current), or
+/// `None` when there is no config file.
+pub fn migrate_file(path: &Path) -> Result<Option<u64>, ConfigError> {
+    let Some((config, from)) = read_file(path)? else {
+        return Ok(None);
+    };
+    if from < CONFIG_VERSION as u64 {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{}.bak", from));
+        fs::copy(path, &backup).map_err(|e| ConfigError::Io(PathBuf::from(&backup), e))?;
+        save_file(path, &config)?;
+    }
+    Ok(Some(from))
+}
+
+/// Writes `config` to `path` as pretty-printed JSON, at [`CONFIG_VERSION`].
+pub fn save_file(path: &Path, config: &HueConfig) -> Result<(), ConfigError> {
+    let config = HueConfig {
+        version: CONFIG_VERSION,
+        ..config.clone()
+    };
+    let content = serde_json::to_string_pretty(&config)
+        .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+    write_atomic(path, content.as_bytes()).map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+}
+
+/// Replaces the file at `path` with `content` all at once: writes a
+/// temporary file next to it, flushes it to disk and renames it over the
+/// original. Readers see the old or the new file, never a partial one.
+///
+/// The new file keeps the original's permissions (owner-only for a new
+/// file, as configs hold keys), and each write gets its own temporary file
+/// so concurrent writers do not clobber each other's.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = path.with_file_name(temp_name);
+
+    let written = create_like(&temp, path).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    match written.and_then(|_| fs::rename(&temp, path)) {
+        Ok(()) => sync_parent(path),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Creates `temp`, which must not exist yet, with the permissions of
+/// `original`.
+#[cfg(unix)]
+fn create_like(temp: &Path, original: &Path) -> io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mode = match fs::metadata(original) {
+        Ok(metadata) => metadata.permissions().mode() & 0o7777,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0o600,
+        Err(e) => return Err(e),
+    };
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(temp)?;
+    // The umask may have taken bits away
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_like(temp: &Path, original: &Path) -> io::Result<File> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)?;
+    if let Ok(metadata) = fs::metadata(original) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    Ok(file)
+}
+
+/// Flushes the directory entry of a renamed file, so the rename itself
+/// survives a crash.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads a single value from `config` by key, with `.` between nested keys
+/// (`retry.max_attempts`).
+pub fn get_key(config: &HueConfig, key: &str) -> Result<Value, ConfigError> {
+    let value = serde_json::to_value(config).map_err(|e| invalid(key, e))?;
+    key.split('.')
+        .try_fold(&value, |value, part| value.get(part))
+        .cloned()
+        .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))
+}
+
+/// Sets a single value in `config` by key (see [`get_key`]), checked
+/// against the config schema and the documented ranges (frame rate,
+/// brightness and jitter 0.0 - 1.0, DMX addresses). `raw` is JSON (`30`, `true`, `[1, 2]`,
+/// `null`), or plain text for text values.
+///
+/// Sections that are off (`null`) are created with their defaults, e.g.
+/// `theater.rear_brightness` turns on theater mode.
+pub fn set_key(config: &mut HueConfig, key: &str, raw: &str) -> Result<(), ConfigError> {
+    let mut root = serde_json::to_value(&*config).map_err(|e| invalid(key, e))?;
+    let (path, last) = match key.rsplit_once('.') {
+        Some((path, last)) => (path.split('.').collect(), last),
+        None => (Vec::new(), key),
+    };
+    let mut parent = &mut root;
+    for part in path {
+        let fields = parent
+            .as_object_mut()
+            .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))?;
+        let child = fields
+            .get_mut(part)
+            .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))?;
+        if child.is_null() {
+            *child = Value::Object(Default::default());
+        }
+        parent = child;
+    }
+    let fields = parent
+        .as_object_mut()
+        .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))?;
+    let current = fields.get(last);
+    let value = match current {
+        // Text stays text, even when it looks like a number
+        Some(Value::String(_)) if !raw.starts_with('"') => Value::String(raw.to_string()),
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    };
+    fields.insert(last.to_string(), value);
+
+    let updated: HueConfig = serde_json::from_value(root).map_err(|e| invalid(key, e))?;
+    // Keys the schema does not know are dropped when parsing
+    get_key(&updated, key)?;
+    // Only the key set (or the keys of the section set) is checked, so an
+    // out-of-range value elsewhere does not block fixing another
+    let set =
+        |name: &str| name == key || name.strip_prefix(key).is_some_and(|r| r.starts_with('.'));
+    if let Some((name, error)) = out_of_range(&updated)
+        .into_iter()
+        .find(|(name, _)| set(name))
+    {
+        return Err(ConfigError::InvalidValue(name.to_string(), error));
+    }
+    *config = updated;
+    Ok(())
+}
+
+/// Values of `config` outside their documented range, by key.
+fn out_of_range(config: &HueConfig) -> Vec<(&'static str, String)> {
+    let mut errors = Vec::new();
+    let mut fraction = |name, value: f32| {
+        if !(0.0..=1.0).contains(&value) {
+            errors.push((name, format!("must be 0.0 - 1.0, got {}", value)));
+        }
+    };
+    fraction("companion.brightness", config.companion.brightness);
+    fraction("retry.jitter", config.retry.jitter as f32);
+    if let Some(theater) = &config.theater {
+        fraction("theater.rear_brightness", theater.rear_brightness);
+    }
+    if let Some(ambient) = &config.ambient {
+        fraction("ambient.min_brightness", ambient.min_brightness);
+        fraction("ambient.max_brightness", ambient.max_brightness);
+    }
+    #[cfg(feature = "bridge")]
+    if let Some(Err(error)) = config.fps.map(crate::stream::rate::check_fps) {
+        errors.push(("fps", error));
+    }
+    if config.retry.max_attempts == 0 {
+        errors.push(("retry.max_attempts", "must be at least 1".to_string()));
+    }
+    if let Some(dmx) = &config.dmx_out {
+        let size = crate::output::dmx::UNIVERSE_SIZE;
+        if !(1..=size).contains(&(dmx.start_address as usize)) {
+            let error = format!("must be 1-{}, got {}", size, dmx.start_address);
+            errors.push(("dmx_out.start_address", error));
+        }
+    }
+    errors
+}
+
+fn invalid(key: &str, error: serde_json::Error) -> ConfigError {
+    ConfigError::InvalidValue(key.to_string(), error.to_string())
+}
+
+/// Resolves the effective configuration from env vars, the config file at
+/// `path` and the command line overrides `cli` (in that order of precedence).
+pub fn resolve(path: &Path, cli: &ConfigOverrides) -> Result<HueConfig, ConfigError> {
+    resolve_with(ConfigOverrides::from_env(), path, cli)
+}
+
+fn resolve_with(
+    env: ConfigOverrides,
+    path: &Path,
+    cli: &ConfigOverrides,
+) -> Result<HueConfig, ConfigError> {
+    let mut config = load_file(path)?.unwrap_or_default();
+    env.apply_missing(&mut config);
+    cli.apply(&mut config);
+
+    if config.bridge_ip.is_empty() {
+        return Err(ConfigError::Missing("bridge_ip", ENV_BRIDGE_IP));
+    }
+    if config.username.is_empty() {
+        return Err(ConfigError::Missing("username", ENV_APP_KEY));
+    }
+    if config.client_key.is_empty() {
+        return Err(ConfigError::Missing("client_key", ENV_CLIENT_KEY));
+    }
+    // The application ID may still be fetched from the bridge
+    if config.application_id.is_empty() {
+        parse_client_key(&config.client_key)?;
+    } else {
+        config.credentials()?;
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_env_only_config() {
+        let vars: HashMap<&str, &str> = [
+            (ENV_BRIDGE_IP, "192.168.1.2"),
+            (ENV_APP_KEY, "app-key"),
+            (ENV_CLIENT_KEY, "00112233445566778899aabbccddeeff"),
+            (ENV_GROUP_ID, "1a8d99cc-967b-44f2-9202-43f976c0fa6b"),
+        ]
+        .into_iter()
+        .collect();
+        let env = ConfigOverrides::from_lookup(|k| vars.get(k).map(|v| v.to_string()));
+
+        let path = Path::new("/nonexistent/hue_config.json");
+        let config = resolve_with(env, path, &ConfigOverrides::default()).unwrap();
+        assert_eq!(config.bridge_ip, "192.168.1.2");
+        assert_eq!(config.username, "app-key");
+        assert!(config.application_id.is_empty());
+    }
+
+    #[test]
+    fn test_cli_overrides_env() {
+        let env = ConfigOverrides {
+            bridge_ip: Some("10.0.0.1".to_string()),
+            username: Some("user".to_string()),
+            client_key: Some("00112233445566778899aabbccddeeff".to_string()),
+            ..Default::default()
+        };
+        let cli = ConfigOverrides {
+            bridge_ip: Some("10.0.0.2".to_string()),
+            ..Default::default()
+        };
+
+        let path = Path::new("/nonexistent/hue_config.json");
+        let config = resolve_with(env, path, &cli).unwrap();
+        assert_eq!(config.bridge_ip, "10.0.0.2");
+        assert_eq!(config.username, "user");
+    }
+
+    #[test]
+    fn test_migrates_legacy_file() {
+        let dir = std::env::temp_dir().join(format!("hueflow-migrate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+        fs::write(
+            &path,
+            r#"{"ip": "192.168.1.5", "username": "user", "client_key": "key"}"#,
+        )
+        .unwrap();
+
+        let config = load_file(&path).unwrap().unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.bridge_ip, "192.168.1.5");
+        assert!(config.application_id.is_empty());
+        assert!(config.entertainment_group_id.is_empty());
+
+        // On disk too, keeping the original
+        assert_eq!(migrate_file(&path).unwrap(), Some(0));
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(migrated.contains(r#""version": 1"#), "{}", migrated);
+        assert!(migrated.contains(r#""bridge_ip": "192.168.1.5""#));
+        let backup = fs::read_to_string(dir.join(format!("{}.v0.bak", CONFIG_FILE))).unwrap();
+        assert!(backup.contains(r#""ip""#));
+        assert_eq!(migrate_file(&path).unwrap(), Some(1));
+        assert_eq!(migrate_file(&dir.join("missing.json")).unwrap(), None);
+
+        fs::write(&path, r#"{"version": 99, "bridge_ip": "x"}"#).unwrap();
+        assert!(matches!(load_file(&path), Err(ConfigError::TooNew(_, 99))));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_get_and_set_keys() {
+        let mut config = HueConfig::default();
+        set_key(&mut config, "fps", "30").unwrap();
+        set_key(&mut config, "safe_mode", "true").unwrap();
+        set_key(&mut config, "bridge_ip", "192.168.1.2").unwrap();
+        set_key(&mut config, "retry.max_attempts", "6").unwrap();
+        set_key(&mut config, "theater.rear_brightness", "0.2").unwrap();
+        assert_eq!(config.fps, Some(30));
+        assert!(config.safe_mode);
+        assert_eq!(config.bridge_ip, "192.168.1.2");
+        assert_eq!(config.retry.max_attempts, 6);
+        assert_eq!(config.theater.as_ref().unwrap().rear_brightness, 0.2);
+        assert_eq!(get_key(&config, "retry.max_attempts").unwrap(), 6);
+        set_key(&mut config, "fps", "null").unwrap();
+        assert_eq!(config.fps, None);
+
+        assert!(matches!(
+            set_key(&mut config, "fps", "fast"),
+            Err(ConfigError::InvalidValue(..))
+        ));
+        assert!(matches!(
+            set_key(&mut config, "no_such_key", "1"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(matches!(
+            set_key(&mut config, "retry.no_such_key", "1"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(matches!(
+            get_key(&config, "retry.nope"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert_eq!(config.fps, None);
+    }
+
+    #[test]
+    fn test_set_key_checks_ranges() {
+        use crate::models::RetryPolicy;
+        let mut config = HueConfig::default();
+        #[cfg(feature = "bridge")]
+        assert!(matches!(
+            set_key(&mut config, "fps", "60"),
+            Err(ConfigError::InvalidValue(..))
+        ));
+        for (key, raw) in [
+            ("theater.rear_brightness", "1.5"),
+            ("companion.brightness", "-0.1"),
+            ("retry.jitter", "2"),
+            ("retry.max_attempts", "0"),
+            ("retry", r#"{"jitter": 2}"#),
+        ] {
+            let result = set_key(&mut config, key, raw);
+            assert!(
+                matches!(result, Err(ConfigError::InvalidValue(..))),
+                "{} = {}: {:?}",
+                key,
+                raw,
+                result
+            );
+        }
+        assert_eq!(config.fps, None);
+        assert!(config.theater.is_none());
+        assert_eq!(config.retry, RetryPolicy::default());
+
+        // Other keys stay settable next to a bad value from the file
+        config.companion.brightness = 2.0;
+        set_key(&mut config, "retry.jitter", "0.5").unwrap();
+        assert_eq!(config.retry.jitter, 0.5);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("hueflow-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        // No temporary file left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(write_atomic(&dir.join("missing").join("file"), b"x").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o600);
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            write_atomic(&path, b"third").unwrap();
+            assert_eq!(mode(&path), 0o640);
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_missing_required_value() {
+        let path = Path::new("/nonexistent/hue_config.json");
+        let err = resolve_with(
+            ConfigOverrides::default(),
+            path,
+            &ConfigOverrides::default(),
+        );
+        assert!(matches!(err, Err(ConfigError::Missing("bridge_ip", _))));
+    }
+
+    #[test]
+    fn test_invalid_credentials() {
+        let path = Path::new("/nonexistent/hue_config.json");
+        let env = ConfigOverrides {
+            bridge_ip: Some("10.0.0.1".to_string()),
+            username: Some("user".to_string()),
+            client_key: Some("not-a-key".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            resolve_with(env.clone(), path, &ConfigOverrides::default()),
+            Err(ConfigError::Credentials(CredentialsError::ClientKeyLength(
+                9
+            )))
+        ));
+
+        let cli = ConfigOverrides {
+            client_key: Some("00112233445566778899aabbccddeeff".to_string()),
+            application_id: Some("user".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            resolve_with(env, path, &cli),
+            Err(ConfigError::Credentials(
+                CredentialsError::IdentityIsUsername
+            ))
+        ));
+    }
+}
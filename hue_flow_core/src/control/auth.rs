@@ -0,0 +1,226 @@
+//! API tokens and TLS for the HTTP API, the overlay and the gRPC service.
+//!
+//! Without tokens every request is let through, as before there were any.
+//! Once `control_auth.tokens` lists one, requests must present a token as
+//! `Authorization: Bearer <token>`, or in a `token` query parameter for
+//! browser sources that cannot set headers. A token's [`Scope`] decides
+//! which endpoints it may use.
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Token local clients (e.g. `hueflow blackout`) send, in place of the
+/// config's.
+pub const ENV_CONTROL_TOKEN: &str = "HUEFLOW_CONTROL_TOKEN";
+
+/// What a token may do; each scope includes the ones before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Status, the overlay and stream state events.
+    Read,
+    /// Commands as well.
+    #[default]
+    Control,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Tells tokens apart, e.g. "phone" or "obs".
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// Certificate chain and private key (PEM files) to serve over TLS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Access settings of the control surfaces.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlAuth {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<ApiToken>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsFiles>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AuthError {
+    #[error("Missing or unknown API token")]
+    Unauthenticated,
+    #[error("API token '{0}' is not allowed to do this")]
+    Forbidden(String),
+}
+
+impl ControlAuth {
+    pub fn requires_token(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Checks the `presented` token for an endpoint needing `scope`.
+    pub fn authorize(&self, presented: Option<&str>, scope: Scope) -> Result<(), AuthError> {
+        if self.tokens.is_empty() {
+            return Ok(());
+        }
+        let token = presented
+            .and_then(|presented| {
+                self.tokens
+                    .iter()
+                    .find(|t| constant_time_eq(t.token.as_bytes(), presented.as_bytes()))
+            })
+            .ok_or(AuthError::Unauthenticated)?;
+        if token.scope >= scope {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden(token.name.clone()))
+        }
+    }
+
+    /// A token for pages that only watch, e.g. the overlay opened in a
+    /// browser: the first read-only one, else one that may also control.
+    pub fn read_token(&self) -> Option<&str> {
+        self.tokens
+            .iter()
+            .min_by_key(|t| t.scope)
+            .map(|t| t.token.as_str())
+    }
+
+    /// The first token allowed to send commands, for local clients.
+    pub fn control_token(&self) -> Option<&str> {
+        self.tokens
+            .iter()
+            .find(|t| t.scope == Scope::Control)
+            .map(|t| t.token.as_str())
+    }
+
+    /// Adds a new random token, replacing one of the same name.
+    #[cfg(feature = "bridge")]
+    pub fn create_token(
+        &mut self,
+        name: &str,
+        scope: Scope,
+    ) -> Result<&ApiToken, openssl::error::ErrorStack> {
+        let token = ApiToken {
+            name: name.to_string(),
+            token: generate_token()?,
+            scope,
+        };
+        self.revoke(name);
+        self.tokens.push(token);
+        Ok(self.tokens.last().unwrap())
+    }
+
+    /// Removes the token named `name`; false if there was none.
+    pub fn revoke(&mut self, name: &str) -> bool {
+        let before = self.tokens.len();
+        self.tokens.retain(|t| t.name != name);
+        self.tokens.len() != before
+    }
+}
+
+/// The token of an `Authorization` header value.
+pub fn bearer(header: &str) -> Option<&str> {
+    header.strip_prefix("Bearer ").map(str::trim)
+}
+
+/// The `token` parameter of a URL query string.
+pub fn query_token(query: &str) -> Option<&str> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+}
+
+/// 32 random bytes, hex encoded.
+#[cfg(feature = "bridge")]
+pub fn generate_token() -> Result<String, openssl::error::ErrorStack> {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(hex::encode(bytes))
+}
+
+/// Compares without returning early, so response times do not give away
+/// how much of a guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The rustls server config for `files`.
+#[cfg(feature = "bridge")]
+pub fn server_tls_config(
+    files: &TlsFiles,
+) -> std::io::Result<std::sync::Arc<tokio_rustls::rustls::ServerConfig>> {
+    use std::io::{Error, ErrorKind};
+    use tokio_rustls::rustls::crypto::ring;
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_rustls::rustls::ServerConfig;
+
+    let invalid = |path: &PathBuf, e: &dyn std::fmt::Display| {
+        Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    };
+    let certs = CertificateDer::pem_file_iter(&files.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(&files.cert, &e))?;
+    let key = PrivateKeyDer::from_pem_file(&files.key).map_err(|e| invalid(&files.key, &e))?;
+    let config = ServerConfig::builder_with_provider(ring::default_provider().into())
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| invalid(&files.cert, &e))?;
+    Ok(std::sync::Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_by_scope() {
+        let mut auth = ControlAuth::default();
+        assert!(auth.authorize(None, Scope::Control).is_ok());
+
+        auth.tokens = vec![
+            ApiToken {
+                name: "obs".into(),
+                token: "r3ad".into(),
+                scope: Scope::Read,
+            },
+            ApiToken {
+                name: "phone".into(),
+                token: "c0ntrol".into(),
+                scope: Scope::Control,
+            },
+        ];
+        assert_eq!(
+            auth.authorize(None, Scope::Read),
+            Err(AuthError::Unauthenticated)
+        );
+        assert_eq!(
+            auth.authorize(Some("r3a"), Scope::Read),
+            Err(AuthError::Unauthenticated)
+        );
+        assert!(auth.authorize(Some("r3ad"), Scope::Read).is_ok());
+        assert_eq!(
+            auth.authorize(Some("r3ad"), Scope::Control),
+            Err(AuthError::Forbidden("obs".into()))
+        );
+        assert!(auth.authorize(Some("c0ntrol"), Scope::Read).is_ok());
+        assert!(auth.authorize(Some("c0ntrol"), Scope::Control).is_ok());
+        assert_eq!(auth.control_token(), Some("c0ntrol"));
+        assert_eq!(auth.read_token(), Some("r3ad"));
+
+        assert!(auth.revoke("phone"));
+        assert!(!auth.revoke("phone"));
+        assert_eq!(auth.control_token(), None);
+        assert!(auth.revoke("obs"));
+        assert_eq!(auth.read_token(), None);
+
+        assert_eq!(bearer("Bearer c0ntrol"), Some("c0ntrol"));
+        assert_eq!(bearer("Basic abc"), None);
+        assert_eq!(query_token("size=40&token=r3ad"), Some("r3ad"));
+    }
+}
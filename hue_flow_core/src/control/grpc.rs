@@ -0,0 +1,241 @@
+//! gRPC control service, for integrations that prefer typed clients
+//! generated from `proto/hueflow.proto` over the HTTP API.
+//!
+//! `StopStream` puts the instance on [`ControlCommand::Standby`] (or ends it
+//! with `shutdown`), `StartStream` brings it back, `SetEffect` matches
+//! `POST /effects/{name}` and `StreamStateEvents` follows the bridge stream's
+//! [`StreamState`].
+//!
+//! Calls carry an API token as `authorization: Bearer <token>` metadata
+//! (see [`auth`](crate::control::auth)); `StreamStateEvents` needs
+//! [`Scope::Read`], the rest [`Scope::Control`].
+use crate::control::auth::{self, AuthError, ControlAuth, Scope};
+use crate::control::ControlCommand;
+use crate::effects::effect_info;
+use crate::stream::supervisor::StreamState;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+/// Messages and client/server stubs generated from `hueflow.proto`.
+pub mod proto {
+    tonic::include_proto!("hueflow.v1");
+}
+
+use proto::hue_flow_control_server::{HueFlowControl, HueFlowControlServer};
+use proto::{
+    Ack, SetEffectRequest, StartStreamRequest, StopStreamRequest, StreamStateEvent,
+    StreamStateEventsRequest,
+};
+
+/// Default listen address of the gRPC service (localhost only).
+pub const DEFAULT_GRPC_ADDR: &str = "127.0.0.1:7421";
+
+impl From<StreamState> for proto::StreamState {
+    fn from(state: StreamState) -> Self {
+        match state {
+            StreamState::Idle => Self::Idle,
+            StreamState::Activating => Self::Activating,
+            StreamState::Handshaking => Self::Handshaking,
+            StreamState::Streaming => Self::Streaming,
+            StreamState::Recovering => Self::Recovering,
+        }
+    }
+}
+
+impl From<AuthError> for Status {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthenticated => Status::unauthenticated(e.to_string()),
+            AuthError::Forbidden(_) => Status::permission_denied(e.to_string()),
+        }
+    }
+}
+
+/// The `HueFlowControl` service of a running flow.
+pub struct ControlService {
+    commands: mpsc::Sender<ControlCommand>,
+    states: watch::Receiver<StreamState>,
+    access: Arc<ControlAuth>,
+}
+
+impl ControlService {
+    pub fn new(
+        commands: mpsc::Sender<ControlCommand>,
+        states: watch::Receiver<StreamState>,
+    ) -> Self {
+        Self {
+            commands,
+            states,
+            access: Arc::default(),
+        }
+    }
+
+    /// Tokens calls are checked against; open to all without.
+    pub fn access(mut self, access: ControlAuth) -> Self {
+        self.access = Arc::new(access);
+        self
+    }
+
+    fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> Result<(), Status> {
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(auth::bearer);
+        Ok(self.access.authorize(presented, scope)?)
+    }
+
+    async fn forward(&self, command: ControlCommand) -> Result<Response<Ack>, Status> {
+        match self.commands.send(command).await {
+            Ok(()) => Ok(Response::new(Ack {})),
+            Err(_) => Err(Status::unavailable("Stream is not running")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl HueFlowControl for ControlService {
+    async fn start_stream(
+        &self,
+        request: Request<StartStreamRequest>,
+    ) -> Result<Response<Ack>, Status> {
+        self.authorize(&request, Scope::Control)?;
+        self.forward(ControlCommand::Standby { enabled: false })
+            .await
+    }
+
+    async fn stop_stream(
+        &self,
+        request: Request<StopStreamRequest>,
+    ) -> Result<Response<Ack>, Status> {
+        self.authorize(&request, Scope::Control)?;
+        let command = if request.into_inner().shutdown {
+            ControlCommand::Stop
+        } else {
+            ControlCommand::Standby { enabled: true }
+        };
+        self.forward(command).await
+    }
+
+    async fn set_effect(
+        &self,
+        request: Request<SetEffectRequest>,
+    ) -> Result<Response<Ack>, Status> {
+        self.authorize(&request, Scope::Control)?;
+        let name = request.into_inner().name;
+        if effect_info(&name).is_none() {
+            return Err(Status::not_found(format!("Unknown effect: {}", name)));
+        }
+        self.forward(ControlCommand::SetEffect { name }).await
+    }
+
+    type StreamStateEventsStream =
+        Pin<Box<dyn Stream<Item = Result<StreamStateEvent, Status>> + Send>>;
+
+    async fn stream_state_events(
+        &self,
+        request: Request<StreamStateEventsRequest>,
+    ) -> Result<Response<Self::StreamStateEventsStream>, Status> {
+        self.authorize(&request, Scope::Read)?;
+        let events = WatchStream::new(self.states.clone()).map(|state| {
+            Ok(StreamStateEvent {
+                state: proto::StreamState::from(state).into(),
+            })
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Serves the gRPC service on `addr` until the task is dropped, checking
+/// calls against the tokens of `access` and over TLS with `access.tls`.
+pub async fn serve(
+    addr: SocketAddr,
+    commands: mpsc::Sender<ControlCommand>,
+    states: watch::Receiver<StreamState>,
+    access: ControlAuth,
+) -> anyhow::Result<()> {
+    let mut server = Server::builder();
+    if let Some(files) = &access.tls {
+        let identity = Identity::from_pem(std::fs::read(&files.cert)?, std::fs::read(&files.key)?);
+        server = server.tls_config(ServerTlsConfig::new().identity(identity))?;
+    }
+    let service = ControlService::new(commands, states).access(access);
+    server
+        .add_service(HueFlowControlServer::new(service))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_service_forwards_commands_and_states() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let (states_tx, states) = watch::channel(StreamState::Streaming);
+        let mut access = ControlAuth::default();
+        access.tokens.push(auth::ApiToken {
+            name: "dashboard".into(),
+            token: "r3ad".into(),
+            scope: Scope::Read,
+        });
+        let service = ControlService::new(tx, states).access(access);
+        let with_token = |mut request: Request<_>| {
+            let token = "Bearer r3ad".parse().unwrap();
+            request.metadata_mut().insert("authorization", token);
+            request
+        };
+
+        let unauthenticated = service
+            .stop_stream(Request::new(StopStreamRequest { shutdown: false }))
+            .await
+            .unwrap_err();
+        assert_eq!(unauthenticated.code(), tonic::Code::Unauthenticated);
+        let forbidden = service
+            .stop_stream(with_token(Request::new(StopStreamRequest {
+                shutdown: false,
+            })))
+            .await
+            .unwrap_err();
+        assert_eq!(forbidden.code(), tonic::Code::PermissionDenied);
+        let service = ControlService {
+            access: Arc::default(),
+            ..service
+        };
+
+        service
+            .stop_stream(Request::new(StopStreamRequest { shutdown: false }))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await,
+            Some(ControlCommand::Standby { enabled: true })
+        );
+        let unknown = service
+            .set_effect(Request::new(SetEffectRequest {
+                name: "no-such-effect".into(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(unknown.code(), tonic::Code::NotFound);
+
+        let mut events = service
+            .stream_state_events(Request::new(StreamStateEventsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        let state =
+            |event: Option<Result<StreamStateEvent, Status>>| event.unwrap().unwrap().state();
+        assert_eq!(state(events.next().await), proto::StreamState::Streaming);
+        states_tx.send_replace(StreamState::Recovering);
+        assert_eq!(state(events.next().await), proto::StreamState::Recovering);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::color::parse_hex;
+use crate::control::auth::{self, AuthError, ControlAuth, Scope};
+use crate::control::overlay::{self, OverlayLight};
+use crate::control::ControlCommand;
+use crate::effects::effect_info;
+use crate::output::blackout::DEFAULT_FADE;
+use crate::stream::rate::check_fps;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Time a client gets to finish the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds the control API router.
+///
+/// Routes:
+/// - `POST /presets/{name}` - load a saved preset
+/// - `POST /effects/{name}` - switch to an effect with its default palette
+/// - `POST /reload` - load the active preset again from disk
+/// - `POST /blackout?fade_ms=300` - fade to black and hold
+/// - `POST /resume?fade_ms=300` - fade back in after a blackout
+/// - `POST /safe-mode/on`, `POST /safe-mode/off` - toggle photosensitive-safe mode
+/// - `POST /playlist/next`, `POST /playlist/prev` - skip through the playlist
+/// - `POST /playlist/shuffle/on`, `POST /playlist/shuffle/off` - toggle shuffle
+/// - `POST /override/{channels}?color=ff0000` - hold channels (e.g. `3,5`) at a color
+/// - `POST /release/{channels}`, `POST /release` - hand channels (or all) back to the effect
+/// - `POST /fps/{fps}` - change the target frame rate (10-50)
+/// - `GET /status` - 200 while the stream is running
+pub fn router(commands: mpsc::Sender<ControlCommand>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/presets/{name}", post(load_preset))
+        .route("/effects/{name}", post(set_effect))
+        .route("/reload", post(reload))
+        .route("/blackout", post(blackout))
+        .route("/resume", post(resume))
+        .route("/safe-mode/{state}", post(safe_mode))
+        .route("/playlist/next", post(playlist_next))
+        .route("/playlist/prev", post(playlist_prev))
+        .route("/playlist/shuffle/{state}", post(playlist_shuffle))
+        .route("/override/{channels}", post(set_override))
+        .route("/release/{channels}", post(release))
+        .route("/release", post(release_all))
+        .route("/fps/{fps}", post(set_fps))
+        .with_state(commands)
+}
+
+/// Serves the control API, and the streamer [`overlay`] fed by `frames`, on
+/// `addr` until the task is dropped.
+///
+/// Requests are checked against the tokens of `access` (see [`auth`]):
+/// `GET` endpoints need [`Scope::Read`], commands [`Scope::Control`]. With
+/// `access.tls` set, only HTTPS is served.
+pub async fn serve(
+    addr: SocketAddr,
+    commands: mpsc::Sender<ControlCommand>,
+    frames: watch::Receiver<Vec<OverlayLight>>,
+    access: ControlAuth,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let tls = access
+        .tls
+        .as_ref()
+        .map(auth::server_tls_config)
+        .transpose()?;
+    let app = app(commands, frames, access);
+    match tls {
+        Some(config) => axum::serve(TlsListener::new(listener, config)?, app).await,
+        None => axum::serve(listener, app).await,
+    }
+}
+
+/// The control API and overlay routes behind the token check of `access`.
+fn app(
+    commands: mpsc::Sender<ControlCommand>,
+    frames: watch::Receiver<Vec<OverlayLight>>,
+    access: ControlAuth,
+) -> Router {
+    router(commands)
+        .merge(overlay::router(frames))
+        .layer(middleware::from_fn_with_state(Arc::new(access), authorize))
+}
+
+async fn authorize(
+    State(access): State<Arc<ControlAuth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let scope = if request.method() == Method::GET {
+        Scope::Read
+    } else {
+        Scope::Control
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(auth::bearer)
+        .or_else(|| request.uri().query().and_then(auth::query_token));
+    match access.authorize(presented, scope) {
+        Ok(()) => next.run(request).await,
+        Err(AuthError::Unauthenticated) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(AuthError::Forbidden(_)) => StatusCode::FORBIDDEN.into_response(),
+    }
+}
+
+/// Accepts TLS connections, handshaking in the background so a slow client
+/// does not hold up the others.
+struct TlsListener {
+    handshaken: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    fn new(
+        listener: TcpListener,
+        config: Arc<tokio_rustls::rustls::ServerConfig>,
+    ) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, handshaken) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    // E.g. out of file descriptors; give it a moment
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let (acceptor, tx) = (acceptor.clone(), tx.clone());
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            handshaken,
+            local_addr,
+        })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.handshaken.recv().await {
+            Some(connection) => connection,
+            // The accepting task only ends once this listener is gone
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+async fn load_preset(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    forward(&commands, ControlCommand::LoadPreset { name }).await
+}
+
+async fn set_effect(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    if effect_info(&name).is_none() {
+        return StatusCode::NOT_FOUND;
+    }
+    forward(&commands, ControlCommand::SetEffect { name }).await
+}
+
+async fn reload(State(commands): State<mpsc::Sender<ControlCommand>>) -> StatusCode {
+    forward(&commands, ControlCommand::Reload).await
+}
+
+#[derive(Deserialize)]
+struct FadeQuery {
+    fade_ms: Option<u64>,
+}
+
+impl FadeQuery {
+    fn fade_ms(&self) -> u64 {
+        self.fade_ms.unwrap_or(DEFAULT_FADE.as_millis() as u64)
+    }
+}
+
+async fn blackout(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Query(query): Query<FadeQuery>,
+) -> StatusCode {
+    let fade_ms = query.fade_ms();
+    forward(&commands, ControlCommand::Blackout { fade_ms }).await
+}
+
+async fn resume(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Query(query): Query<FadeQuery>,
+) -> StatusCode {
+    let fade_ms = query.fade_ms();
+    forward(&commands, ControlCommand::Resume { fade_ms }).await
+}
+
+async fn safe_mode(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(state): Path<String>,
+) -> StatusCode {
+    let enabled = match state.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return StatusCode::BAD_REQUEST,
+    };
+    forward(&commands, ControlCommand::SafeMode { enabled }).await
+}
+
+async fn playlist_next(State(commands): State<mpsc::Sender<ControlCommand>>) -> StatusCode {
+    forward(&commands, ControlCommand::PlaylistNext).await
+}
+
+async fn playlist_prev(State(commands): State<mpsc::Sender<ControlCommand>>) -> StatusCode {
+    forward(&commands, ControlCommand::PlaylistPrev).await
+}
+
+async fn playlist_shuffle(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(state): Path<String>,
+) -> StatusCode {
+    let enabled = match state.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return StatusCode::BAD_REQUEST,
+    };
+    forward(&commands, ControlCommand::PlaylistShuffle { enabled }).await
+}
+
+#[derive(Deserialize)]
+struct ColorQuery {
+    color: String,
+}
+
+/// Parses a comma-separated channel list such as `3,5`.
+fn parse_channels(list: &str) -> Option<Vec<u8>> {
+    list.split(',').map(|c| c.trim().parse().ok()).collect()
+}
+
+async fn set_override(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(channels): Path<String>,
+    Query(query): Query<ColorQuery>,
+) -> StatusCode {
+    let (Some(channels), Some(color)) = (parse_channels(&channels), parse_hex(&query.color)) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    forward(&commands, ControlCommand::Override { channels, color }).await
+}
+
+async fn release(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(channels): Path<String>,
+) -> StatusCode {
+    let Some(channels) = parse_channels(&channels) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    forward(&commands, ControlCommand::Release { channels }).await
+}
+
+async fn release_all(State(commands): State<mpsc::Sender<ControlCommand>>) -> StatusCode {
+    let channels = Vec::new();
+    forward(&commands, ControlCommand::Release { channels }).await
+}
+
+async fn set_fps(
+    State(commands): State<mpsc::Sender<ControlCommand>>,
+    Path(fps): Path<u32>,
+) -> StatusCode {
+    if check_fps(fps).is_err() {
+        return StatusCode::BAD_REQUEST;
+    }
+    forward(&commands, ControlCommand::SetFps { fps }).await
+}
+
+async fn status(State(commands): State<mpsc::Sender<ControlCommand>>) -> StatusCode {
+    if commands.is_closed() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    }
+}
+
+async fn forward(commands: &mpsc::Sender<ControlCommand>, command: ControlCommand) -> StatusCode {
+    match commands.send(command).await {
+        Ok(_) => StatusCode::ACCEPTED,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::auth::ApiToken;
+    use axum::body::Body;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tower::ServiceExt;
+
+    fn secured() -> (Router, mpsc::Receiver<ControlCommand>) {
+        let access = ControlAuth {
+            tokens: vec![
+                ApiToken {
+                    name: "obs".into(),
+                    token: "r3ad".into(),
+                    scope: Scope::Read,
+                },
+                ApiToken {
+                    name: "phone".into(),
+                    token: "c0ntrol".into(),
+                    scope: Scope::Control,
+                },
+            ],
+            ..Default::default()
+        };
+        with_access(access)
+    }
+
+    fn with_access(access: ControlAuth) -> (Router, mpsc::Receiver<ControlCommand>) {
+        let (commands, received) = mpsc::channel(4);
+        let (_, frames) = watch::channel(Vec::new());
+        (app(commands, frames, access), received)
+    }
+
+    async fn send(app: &Router, method: Method, uri: &str, token: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request.body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_checked() {
+        let (app, mut received) = secured();
+        assert_eq!(
+            send(&app, Method::GET, "/status", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/reload", Some("wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/status", Some("r3ad")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/reload", Some("r3ad")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert!(received.try_recv().is_err());
+        assert_eq!(
+            send(&app, Method::POST, "/reload", Some("c0ntrol")).await,
+            StatusCode::ACCEPTED
+        );
+        assert!(matches!(received.try_recv(), Ok(ControlCommand::Reload)));
+
+        // Browser sources cannot set headers, so the overlay takes the token
+        // from the query
+        assert_eq!(
+            send(&app, Method::GET, "/overlay", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/overlay?token=r3ad", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/overlay/ws", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overlay_feed_takes_query_token() {
+        // Upgrading needs a real connection, which `oneshot` does not have
+        let (app, _received) = secured();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET /overlay/ws?token=r3ad HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\n\
+             Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            addr
+        );
+        stream.write_all(handshake.as_bytes()).await.unwrap();
+        let mut response = [0; 12];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"HTTP/1.1 101");
+    }
+
+    #[tokio::test]
+    async fn test_open_without_tokens() {
+        let (app, mut received) = with_access(ControlAuth::default());
+        assert_eq!(
+            send(&app, Method::POST, "/reload", None).await,
+            StatusCode::ACCEPTED
+        );
+        assert!(matches!(received.try_recv(), Ok(ControlCommand::Reload)));
+        assert_eq!(
+            send(&app, Method::GET, "/status", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/overlay", None).await,
+            StatusCode::OK
+        );
+    }
+}
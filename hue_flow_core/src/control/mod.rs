@@ -0,0 +1,58 @@
+//! Control API for a running HueFlow instance.
+//!
+//! Commands arrive over the HTTP API (see [`http`]), the local control
+//! socket (see [`socket`]) or gRPC (`grpc`, with the `grpc` feature) and
+//! are forwarded to the streaming loop through an mpsc channel of
+//! [`ControlCommand`]s.
+pub mod auth;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "bridge")]
+pub mod http;
+#[cfg(feature = "bridge")]
+pub mod overlay;
+#[cfg(feature = "bridge")]
+pub mod socket;
+
+use serde::{Deserialize, Serialize};
+
+/// Default listen address of the control API (localhost only).
+pub const DEFAULT_CONTROL_ADDR: &str = "127.0.0.1:7420";
+
+/// A command for the running streaming loop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Switch to the named preset.
+    LoadPreset { name: String },
+    /// Switch to the named effect with its default palette.
+    SetEffect { name: String },
+    /// Load the active preset again from disk, picking up edits.
+    Reload,
+    /// Fade all channels to black over `fade_ms` and hold.
+    Blackout { fade_ms: u64 },
+    /// Fade back in from a blackout over `fade_ms`.
+    Resume { fade_ms: u64 },
+    /// Turn photosensitive-safe mode on or off.
+    SafeMode { enabled: bool },
+    /// Skip to the next playlist entry.
+    PlaylistNext,
+    /// Go back to the previous playlist entry.
+    PlaylistPrev,
+    /// Turn playlist shuffle on or off.
+    PlaylistShuffle { enabled: bool },
+    /// Hold `channels` at `color` over the effect output until released.
+    Override {
+        channels: Vec<u8>,
+        color: (u8, u8, u8),
+    },
+    /// Hand `channels` back to the effect; an empty list releases all.
+    Release { channels: Vec<u8> },
+    /// Stop streaming and release the entertainment area while enabled,
+    /// keeping the instance running to pick up again when disabled.
+    Standby { enabled: bool },
+    /// Change the target frame rate (10-50 FPS).
+    SetFps { fps: u32 },
+    /// End the stream and release the entertainment area.
+    Stop,
+}
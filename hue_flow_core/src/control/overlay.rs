@@ -0,0 +1,107 @@
+//! Overlay for streamers: a small HTML page showing the room's lights live,
+//! meant as an OBS browser source.
+//!
+//! `GET /overlay` serves the page; it draws every light as a glow at its
+//! place in the entertainment area, fed by the frames on the `GET
+//! /overlay/ws` WebSocket. The background is transparent, so the lights
+//! float over the stream; `?size=<px>` sets the glow radius.
+use crate::models::LightNode;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::{Html, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::sync::watch;
+
+const PAGE: &str = include_str!("overlay.html");
+
+/// A light as the overlay draws it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverlayLight {
+    pub channel: u8,
+    /// Left (-1.0) to right (1.0).
+    pub x: f64,
+    /// Back (-1.0) to front (1.0).
+    pub y: f64,
+    pub color: (u8, u8, u8),
+}
+
+/// The lights of `frame` with their positions from `nodes`, by channel.
+/// Channels without a node are left out.
+pub fn snapshot(nodes: &[LightNode], frame: &HashMap<u8, (u8, u8, u8)>) -> Vec<OverlayLight> {
+    let mut lights: Vec<OverlayLight> = nodes
+        .iter()
+        .filter_map(|node| {
+            frame.get(&node.channel_id).map(|&color| OverlayLight {
+                channel: node.channel_id,
+                x: node.x,
+                y: node.y,
+                color,
+            })
+        })
+        .collect();
+    lights.sort_by_key(|light| light.channel);
+    lights
+}
+
+/// Builds the overlay routes, fed by the frames the flow publishes (see
+/// [`HueFlow::frames`](crate::flow::HueFlow::frames)).
+pub fn router(frames: watch::Receiver<Vec<OverlayLight>>) -> Router {
+    Router::new()
+        .route("/overlay", get(page))
+        .route("/overlay/ws", get(feed))
+        .with_state(frames)
+}
+
+async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
+
+async fn feed(
+    State(frames): State<watch::Receiver<Vec<OverlayLight>>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| send_frames(socket, frames))
+}
+
+/// Sends every new frame as JSON until the client leaves or the flow ends.
+async fn send_frames(mut socket: WebSocket, mut frames: watch::Receiver<Vec<OverlayLight>>) {
+    while frames.changed().await.is_ok() {
+        let json = match serde_json::to_string(&*frames.borrow_and_update()) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("Overlay: cannot encode frame: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::line_layout;
+
+    #[test]
+    fn test_snapshot() {
+        let nodes = line_layout(3);
+        let frame = HashMap::from([(2, (0, 0, 255)), (0, (255, 0, 0)), (200, (1, 2, 3))]);
+        let lights = snapshot(&nodes, &frame);
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].channel, 0);
+        assert_eq!(lights[0].x, nodes[0].x);
+        assert_eq!(lights[1].color, (0, 0, 255));
+        assert_eq!(
+            serde_json::to_string(&lights[1]).unwrap(),
+            format!(
+                r#"{{"channel":2,"x":{:?},"y":{:?},"color":[0,0,255]}}"#,
+                nodes[2].x, nodes[2].y
+            )
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Control over a local socket: a Unix domain socket, or a named pipe on
+//! Windows.
+//!
+//! Scripts and other local processes talk to a running instance without
+//! HTTP, one JSON object per line each way. Requests are the
+//! [`ControlCommand`]s as serialized (`{"command": "blackout", "fade_ms":
+//! 300}`) plus `{"command": "status"}`; every request gets a [`Reply`].
+use crate::control::ControlCommand;
+use crate::stream::rate::StreamMetrics;
+use crate::stream::supervisor::StreamState;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// A line sent to the control socket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Request {
+    Query(Query),
+    Command(ControlCommand),
+}
+
+/// Requests answered by the socket itself rather than the streaming loop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Query {
+    /// Live stream counters.
+    Status,
+}
+
+/// Answer to every request.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+/// Stream counters reported by [`Query::Status`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub fps: u32,
+    pub frames_sent: u64,
+    pub write_errors: u64,
+    /// Missing in replies of older versions.
+    #[serde(default)]
+    pub state: StreamState,
+    /// Repeated frames not sent, see [`StreamOptions::dedup`]. Missing in
+    /// replies of older versions.
+    ///
+    /// [`StreamOptions::dedup`]: crate::stream::manager::StreamOptions::dedup
+    #[serde(default)]
+    pub frames_skipped: u64,
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Default::default()
+        }
+    }
+
+    fn error(error: impl Into<String>) -> Self {
+        Self {
+            error: Some(error.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Where the socket lives unless configured otherwise:
+/// `$XDG_RUNTIME_DIR/hueflow.sock` (or the temp directory) on Unix,
+/// `\\.\pipe\hueflow` on Windows.
+pub fn default_socket_path() -> PathBuf {
+    if cfg!(windows) {
+        return PathBuf::from(r"\\.\pipe\hueflow");
+    }
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("hueflow.sock")
+}
+
+/// Serves the control socket at `path` until the task is dropped.
+///
+/// On Unix the socket is made group-writable, so members of the owner's
+/// group can command a daemon run by another user. A leftover socket from
+/// an instance that is gone is replaced; one still in use is an error.
+#[cfg(unix)]
+pub async fn serve(
+    path: &Path,
+    commands: mpsc::Sender<ControlCommand>,
+    metrics: Arc<StreamMetrics>,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("{} is in use by another instance", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle(stream, commands.clone(), metrics.clone()));
+    }
+}
+
+/// Serves the control pipe at `path` until the task is dropped.
+#[cfg(windows)]
+pub async fn serve(
+    path: &Path,
+    commands: mpsc::Sender<ControlCommand>,
+    metrics: Arc<StreamMetrics>,
+) -> std::io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)?;
+    loop {
+        server.connect().await?;
+        // A fresh instance takes the next client while this one is served
+        let client = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+        tokio::spawn(handle(client, commands.clone(), metrics.clone()));
+    }
+}
+
+/// Answers requests on one connection until the client hangs up.
+async fn handle(
+    stream: impl AsyncRead + AsyncWrite,
+    commands: mpsc::Sender<ControlCommand>,
+    metrics: Arc<StreamMetrics>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(Request::Query(Query::Status)) => Reply {
+                status: Some(Status {
+                    fps: metrics.fps(),
+                    frames_sent: metrics.frames_sent(),
+                    write_errors: metrics.write_errors(),
+                    state: metrics.state(),
+                    frames_skipped: metrics.frames_skipped(),
+                }),
+                ..Reply::ok()
+            },
+            Ok(Request::Command(command)) => match commands.send(command).await {
+                Ok(()) => Reply::ok(),
+                Err(_) => Reply::error("Stream is not running"),
+            },
+            Err(e) => Reply::error(format!("Invalid request: {}", e)),
+        };
+        let mut out = serde_json::to_vec(&reply)?;
+        out.push(b'\n');
+        writer.write_all(&out).await?;
+    }
+    Ok(())
+}
+
+/// Sends one request to the instance listening at `path` and waits for
+/// the reply.
+pub async fn request(path: &Path, request: &Request) -> std::io::Result<Reply> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut out = serde_json::to_vec(request)?;
+    out.push(b'\n');
+    writer.write_all(&out).await?;
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "No reply from HueFlow")
+        })?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_socket_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hueflow-socket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hueflow.sock");
+        let (tx, mut rx) = mpsc::channel(4);
+        let server = tokio::spawn({
+            let path = path.clone();
+            async move { serve(&path, tx, Arc::default()).await }
+        });
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let reply = request(
+            &path,
+            &Request::Command(ControlCommand::Blackout { fade_ms: 5 }),
+        )
+        .await
+        .unwrap();
+        assert!(reply.ok);
+        assert_eq!(
+            rx.recv().await,
+            Some(ControlCommand::Blackout { fade_ms: 5 })
+        );
+
+        let reply = request(&path, &Request::Query(Query::Status))
+            .await
+            .unwrap();
+        assert_eq!(reply.status.map(|s| s.frames_sent), Some(0));
+
+        // Commands are the serialized ControlCommands
+        let raw: Request = serde_json::from_str(r#"{"command": "set_fps", "fps": 30}"#).unwrap();
+        assert_eq!(raw, Request::Command(ControlCommand::SetFps { fps: 30 }));
+
+        server.abort();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
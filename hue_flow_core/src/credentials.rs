@@ -0,0 +1,118 @@
+//! Streaming credentials: the DTLS PSK identity and key, checked when the
+//! config is loaded rather than during the handshake.
+//!
+//! The bridge only says "handshake failed" for wrong credentials, and the
+//! most common mistake is using the username (`hue-application-key`) as the
+//! PSK identity instead of the application ID from `/auth/v1`.
+use std::fmt;
+use thiserror::Error;
+
+/// Length of the client key in bytes (32 hex digits).
+pub const CLIENT_KEY_LEN: usize = 16;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CredentialsError {
+    #[error("client_key must be 32 hex digits, got {0} characters")]
+    ClientKeyLength(usize),
+    #[error("client_key is not hexadecimal")]
+    ClientKeyNotHex,
+    #[error("application_id '{0}' is not a UUID as returned by /auth/v1")]
+    Identity(String),
+    #[error(
+        "application_id is the username; the PSK identity is the application ID from /auth/v1"
+    )]
+    IdentityIsUsername,
+}
+
+/// A validated PSK identity (`application_id`) and key (`client_key`).
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    application_id: String,
+    client_key: [u8; CLIENT_KEY_LEN],
+}
+
+impl Credentials {
+    /// Checks and bundles an application ID and a hex client key.
+    pub fn new(application_id: &str, client_key: &str) -> Result<Self, CredentialsError> {
+        check_application_id(application_id)?;
+        Ok(Self {
+            application_id: application_id.to_string(),
+            client_key: parse_client_key(client_key)?,
+        })
+    }
+
+    /// The PSK identity.
+    pub fn application_id(&self) -> &str {
+        &self.application_id
+    }
+
+    /// The PSK.
+    pub fn client_key(&self) -> &[u8] {
+        &self.client_key
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("application_id", &self.application_id)
+            .field("client_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Decodes a client key of exactly 32 hex digits.
+pub fn parse_client_key(hex_key: &str) -> Result<[u8; CLIENT_KEY_LEN], CredentialsError> {
+    if hex_key.len() != CLIENT_KEY_LEN * 2 {
+        return Err(CredentialsError::ClientKeyLength(hex_key.chars().count()));
+    }
+    let mut key = [0u8; CLIENT_KEY_LEN];
+    hex::decode_to_slice(hex_key, &mut key).map_err(|_| CredentialsError::ClientKeyNotHex)?;
+    Ok(key)
+}
+
+/// Fails unless `application_id` has the form of a UUID
+/// (`xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`, hex digits).
+pub fn check_application_id(application_id: &str) -> Result<(), CredentialsError> {
+    let uuid = application_id.len() == 36
+        && application_id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    if uuid {
+        Ok(())
+    } else {
+        Err(CredentialsError::Identity(application_id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "1a8d99cc-967b-44f2-9202-43f976c0fa6b";
+    const KEY: &str = "00112233445566778899AABBCCDDEEFF";
+
+    #[test]
+    fn test_credentials() {
+        let credentials = Credentials::new(ID, KEY).unwrap();
+        assert_eq!(credentials.application_id(), ID);
+        assert_eq!(credentials.client_key()[15], 0xff);
+        assert!(!format!("{:?}", credentials).contains("AABB"));
+
+        assert_eq!(
+            Credentials::new(ID, "0011"),
+            Err(CredentialsError::ClientKeyLength(4))
+        );
+        assert_eq!(
+            Credentials::new(ID, &"zz".repeat(16)),
+            Err(CredentialsError::ClientKeyNotHex)
+        );
+        // A username in place of the application ID
+        let username = "Xa7vK2-mAqL8pZ3r0sT1uV5wY9bC4dE6fG8hJ0kL";
+        assert!(matches!(
+            Credentials::new(username, KEY),
+            Err(CredentialsError::Identity(_))
+        ));
+    }
+}
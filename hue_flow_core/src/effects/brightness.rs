@@ -0,0 +1,41 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use crate::simd::scale_colors;
+use std::time::Duration;
+
+/// Scales the output of any effect by a master brightness (0.0 - 1.0).
+pub struct BrightnessEffect {
+    inner: Box<dyn LightEffect>,
+    pub brightness: f32,
+}
+
+impl BrightnessEffect {
+    pub fn new(inner: Box<dyn LightEffect>, brightness: f32) -> Self {
+        Self { inner, brightness }
+    }
+}
+
+impl LightEffect for BrightnessEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut frame = self.inner.update_frame(analysis, nodes);
+        scale_colors(frame.values_mut(), self.brightness);
+        frame
+    }
+
+    fn smoothing(&self) -> Option<Duration> {
+        self.inner.smoothing()
+    }
+
+    fn is_strobe(&self) -> bool {
+        self.inner.is_strobe()
+    }
+
+    fn set_daylight(&mut self, daylight: f32) {
+        self.inner.set_daylight(daylight)
+    }
+}
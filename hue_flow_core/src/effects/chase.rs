@@ -0,0 +1,62 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::scale;
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::cmp::Ordering;
+
+/// Brightness left on the lit light at the end of its beat.
+const TAIL: f32 = 0.3;
+
+/// Runs a single light from left to right, one step per beat, locked to the
+/// tempo clock so it stays on the grid through quiet passages.
+///
+/// Each lit light fades over its beat; the level follows the music's energy.
+pub struct ChaseEffect {
+    pub color: (u8, u8, u8),
+}
+
+impl ChaseEffect {
+    pub fn new(color: (u8, u8, u8)) -> Self {
+        Self { color }
+    }
+}
+
+impl Default for ChaseEffect {
+    fn default() -> Self {
+        Self::new((255, 180, 0))
+    }
+}
+
+impl LightEffect for ChaseEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut sorted: Vec<&LightNode> = nodes.iter().collect();
+        sorted.sort_by(|a, b| {
+            a.x.partial_cmp(&b.x)
+                .unwrap_or(Ordering::Equal)
+                .then(a.channel_id.cmp(&b.channel_id))
+        });
+        if sorted.is_empty() {
+            return Frame::new();
+        }
+
+        let lit = (analysis.tempo.beat % sorted.len() as u64) as usize;
+        let level = (1.0 - (1.0 - TAIL) * analysis.tempo.phase)
+            * (0.5 + 0.5 * analysis.spectrum.energy.clamp(0.0, 1.0));
+        sorted
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let color = if i == lit {
+                    scale(self.color, level)
+                } else {
+                    (0, 0, 0)
+                };
+                (node.channel_id, color)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,158 @@
+//! Spatial grouping of lights, so effects that split the room into zones
+//! get compact zones on irregular layouts too.
+use crate::models::LightNode;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Lloyd iterations after which clustering stops even if not settled.
+const MAX_ITERATIONS: usize = 20;
+
+type Point = [f64; 3];
+
+/// Splits `nodes` into up to `groups` spatial clusters (k-means over x/y/z)
+/// and returns the group of each channel. Groups are numbered by their
+/// center from left to right (then front to back, bottom to top), so group
+/// 0 is the leftmost.
+///
+/// Fewer groups come back when there are fewer distinct positions. Seeding
+/// is deterministic: the leftmost light, then each time the light farthest
+/// from every seed so far.
+pub fn partition(nodes: &[LightNode], groups: usize) -> HashMap<u8, usize> {
+    let points: Vec<Point> = nodes.iter().map(|n| [n.x, n.y, n.z]).collect();
+    let mut centers = seed(&points, groups);
+    let mut labels = vec![0; points.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let assigned: Vec<usize> = points.iter().map(|p| nearest(&centers, p)).collect();
+        let settled = assigned == labels;
+        labels = assigned;
+        for (k, center) in centers.iter_mut().enumerate() {
+            let members: Vec<&Point> = points
+                .iter()
+                .zip(&labels)
+                .filter(|(_, &label)| label == k)
+                .map(|(p, _)| p)
+                .collect();
+            // Seeds are lights, so a cluster keeps at least its own seed
+            if !members.is_empty() {
+                *center = mean(&members);
+            }
+        }
+        if settled {
+            break;
+        }
+    }
+
+    let mut order: Vec<usize> = (0..centers.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (centers[a], centers[b]);
+        (0..3)
+            .map(|axis| a[axis].partial_cmp(&b[axis]).unwrap_or(Ordering::Equal))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut rank = vec![0; centers.len()];
+    for (position, &k) in order.iter().enumerate() {
+        rank[k] = position;
+    }
+    nodes
+        .iter()
+        .zip(labels)
+        .map(|(node, label)| (node.channel_id, rank[label]))
+        .collect()
+}
+
+fn seed(points: &[Point], groups: usize) -> Vec<Point> {
+    let Some(first) = points
+        .iter()
+        .min_by(|a, b| a[0].partial_cmp(&b[0]).unwrap_or(Ordering::Equal))
+    else {
+        return Vec::new();
+    };
+    let mut centers = vec![*first];
+    while centers.len() < groups {
+        let farthest = points
+            .iter()
+            .map(|p| (p, distance(&centers[nearest(&centers, p)], p)))
+            .fold(None, |best: Option<(&Point, f64)>, (p, d)| match best {
+                Some((_, best_d)) if best_d >= d => best,
+                _ => Some((p, d)),
+            });
+        match farthest {
+            Some((p, d)) if d > 0.0 => centers.push(*p),
+            _ => break,
+        }
+    }
+    centers
+}
+
+fn nearest(centers: &[Point], p: &Point) -> usize {
+    let mut best = (0, f64::INFINITY);
+    for (k, center) in centers.iter().enumerate() {
+        let d = distance(center, p);
+        if d < best.1 {
+            best = (k, d);
+        }
+    }
+    best.0
+}
+
+fn distance(a: &Point, b: &Point) -> f64 {
+    (0..3).map(|axis| (a[axis] - b[axis]).powi(2)).sum()
+}
+
+fn mean(points: &[&Point]) -> Point {
+    let n = points.len() as f64;
+    let mut sum = [0.0; 3];
+    for p in points {
+        for axis in 0..3 {
+            sum[axis] += p[axis];
+        }
+    }
+    sum.map(|s| s / n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(channel_id: u8, x: f64, y: f64) -> LightNode {
+        LightNode {
+            id: channel_id.to_string(),
+            channel_id,
+            x,
+            y,
+            z: 0.0,
+            segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_partition_l_shaped_room() {
+        // Three lights along the back wall, three down the right-hand wall
+        // and a pair in the far left corner
+        let nodes = vec![
+            node(0, -1.0, 1.0),
+            node(1, -0.9, 0.9),
+            node(2, 0.0, 1.0),
+            node(3, 0.2, 1.0),
+            node(4, 0.4, 1.0),
+            node(5, 1.0, 0.0),
+            node(6, 1.0, -0.5),
+            node(7, 1.0, -1.0),
+        ];
+        let groups = partition(&nodes, 3);
+        let group = |channel| groups[&channel];
+        assert_eq!((group(0), group(1)), (0, 0));
+        assert_eq!((group(2), group(3), group(4)), (1, 1, 1));
+        assert_eq!((group(5), group(6), group(7)), (2, 2, 2));
+    }
+
+    #[test]
+    fn test_partition_few_positions() {
+        assert!(partition(&[], 3).is_empty());
+        let stacked = vec![node(0, 0.5, 0.5), node(1, 0.5, 0.5)];
+        assert_eq!(partition(&stacked, 3), HashMap::from([(0, 0), (1, 0)]));
+        let pair = vec![node(4, 1.0, 0.0), node(2, -1.0, 0.0)];
+        assert_eq!(partition(&pair, 3), HashMap::from([(2, 0), (4, 1)]));
+    }
+}
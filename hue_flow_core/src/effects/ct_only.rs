@@ -0,0 +1,51 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{constrain_to_ct, MAX_KELVIN, MIN_KELVIN};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::time::Duration;
+
+/// Restricts the output of any effect to white tones on the blackbody curve.
+///
+/// Brightness dynamics of the wrapped effect are kept; colors are reduced to
+/// a warm/cool white between `min_kelvin` and `max_kelvin`.
+pub struct CtOnlyEffect {
+    inner: Box<dyn LightEffect>,
+    pub min_kelvin: f32,
+    pub max_kelvin: f32,
+}
+
+impl CtOnlyEffect {
+    pub fn new(inner: Box<dyn LightEffect>) -> Self {
+        Self {
+            inner,
+            min_kelvin: MIN_KELVIN,
+            max_kelvin: MAX_KELVIN,
+        }
+    }
+}
+
+impl LightEffect for CtOnlyEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut frame = self.inner.update_frame(analysis, nodes);
+        for color in frame.values_mut() {
+            *color = constrain_to_ct(*color, self.min_kelvin, self.max_kelvin);
+        }
+        frame
+    }
+
+    fn smoothing(&self) -> Option<Duration> {
+        self.inner.smoothing()
+    }
+
+    fn is_strobe(&self) -> bool {
+        self.inner.is_strobe()
+    }
+
+    fn set_daylight(&mut self, daylight: f32) {
+        self.inner.set_daylight(daylight)
+    }
+}
@@ -0,0 +1,115 @@
+//! Attack, hold and fall-off for levels driving lights, shared by effects
+//! so they all rise and fall with the same feel.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Timing of an [`Envelope`]. Rise and fall are exponential: after
+/// `attack_ms` (or `decay_ms`) the level has covered about two thirds of
+/// the way to the input.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dynamics {
+    /// Rise time towards a louder input; 0 jumps straight up.
+    pub attack_ms: u64,
+    /// How long a peak is held before falling off.
+    pub hold_ms: u64,
+    /// Fall time towards a quieter input once the hold is over.
+    pub decay_ms: u64,
+}
+
+impl Default for Dynamics {
+    fn default() -> Self {
+        Self {
+            attack_ms: 0,
+            hold_ms: 50,
+            decay_ms: 250,
+        }
+    }
+}
+
+/// Follows a level with [`Dynamics`]: quick to light up, holding peaks
+/// briefly and fading out rather than flickering.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    dynamics: Dynamics,
+    level: f32,
+    peak_at: Option<Duration>,
+    last: Option<Duration>,
+}
+
+impl Envelope {
+    pub fn new(dynamics: Dynamics) -> Self {
+        Self {
+            dynamics,
+            level: 0.0,
+            peak_at: None,
+            last: None,
+        }
+    }
+
+    /// Current output level.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Feeds the input level at `now` (a frame's
+    /// [`time`](crate::audio_interface::AnalysisFrame::time)) and returns
+    /// the output level.
+    pub fn update(&mut self, input: f32, now: Duration) -> f32 {
+        let elapsed = self
+            .last
+            .map_or(Duration::ZERO, |last| now.saturating_sub(last));
+        self.last = Some(now);
+        let follow = |ms: u64| {
+            if ms == 0 {
+                1.0
+            } else {
+                1.0 - (-elapsed.as_secs_f32() * 1000.0 / ms as f32).exp()
+            }
+        };
+
+        if input >= self.level {
+            self.level += (input - self.level) * follow(self.dynamics.attack_ms);
+            self.peak_at = Some(now);
+        } else {
+            let hold = Duration::from_millis(self.dynamics.hold_ms);
+            let holding = self.peak_at.is_some_and(|at| now.saturating_sub(at) < hold);
+            if !holding {
+                self.level += (input - self.level) * follow(self.dynamics.decay_ms);
+            }
+        }
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attack_hold_decay() {
+        let mut envelope = Envelope::new(Dynamics {
+            attack_ms: 0,
+            hold_ms: 100,
+            decay_ms: 200,
+        });
+        let at = Duration::from_millis;
+
+        assert_eq!(envelope.update(1.0, at(0)), 1.0);
+        // Held right after the peak
+        assert_eq!(envelope.update(0.0, at(50)), 1.0);
+        // Then falling off exponentially
+        let level = envelope.update(0.0, at(100));
+        assert!((level - (-0.25f32).exp()).abs() < 1e-4, "{}", level);
+        let level = envelope.update(0.0, at(300));
+        assert!((level - (-1.25f32).exp()).abs() < 1e-4, "{}", level);
+
+        let mut slow = Envelope::new(Dynamics {
+            attack_ms: 100,
+            ..Default::default()
+        });
+        slow.update(0.0, at(0));
+        let level = slow.update(1.0, at(100));
+        assert!((level - 0.632).abs() < 0.001, "{}", level);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{hue_to_rgb, mix, scale};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::cmp::Ordering;
+use std::time::Duration;
+
+/// Range of sung fundamentals, bass to soprano.
+const PITCH_HZ: (f32, f32) = (80.0, 1000.0);
+/// Band the voice level is measured in.
+const VOICE_HZ: (f32, f32) = (150.0, 3000.0);
+/// Voice level above which someone is singing.
+const SINGING: f32 = 0.25;
+/// Quiet needed before singing counts as a new phrase.
+const PHRASE_GAP: Duration = Duration::from_millis(400);
+/// Fade time of the accent flash.
+const FLASH_TIME: f32 = 0.3;
+/// Time the hue takes to follow the pitch, so vibrato does not flicker.
+const HUE_TIME: f32 = 0.15;
+/// Pitch that maps to hue 0 (red): C, so every C is red in every octave.
+const HUE_BASE_HZ: f32 = 65.41;
+
+/// Fundamental of the strongest voice in `analysis`, by harmonic product
+/// spectrum over the FFT bins; `None` without bins or without a clear
+/// voice.
+pub fn vocal_pitch(analysis: &AnalysisFrame) -> Option<f32> {
+    let bins = &analysis.bins;
+    if bins.is_empty() || analysis.bin_hz <= 0.0 {
+        return None;
+    }
+    let first = ((PITCH_HZ.0 / analysis.bin_hz).ceil() as usize).max(1);
+    let last = ((PITCH_HZ.1 / analysis.bin_hz) as usize).min(bins.len() - 1);
+    let (best, score) = (first..=last)
+        .map(|k| {
+            // Harmonics past the end count as absent but not as zero
+            let harmonic = |n: usize| bins.get(k * n).copied().unwrap_or(0.0).max(0.05);
+            (k, bins[k] * harmonic(2) * harmonic(3))
+        })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+    if bins[best] < 0.1 || score <= 0.0 {
+        return None;
+    }
+
+    // Between bins: the peak of a parabola through the neighbours
+    let (a, b, c) = (
+        bins[best - 1],
+        bins[best],
+        bins.get(best + 1).copied().unwrap_or(0.0),
+    );
+    let curvature = a - 2.0 * b + c;
+    let offset = if curvature.abs() > 1e-6 {
+        (0.5 * (a - c) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    Some((best as f32 + offset) * analysis.bin_hz)
+}
+
+/// Hue for a pitch: once around the color wheel per octave, so a note has
+/// the same color whoever sings it.
+pub fn pitch_hue(hz: f32) -> f32 {
+    (hz / HUE_BASE_HZ).log2().rem_euclid(1.0) * 360.0
+}
+
+/// For karaoke on a microphone: the room takes the color of the sung note
+/// and glows with the voice, and the outermost lights flash `accent` when
+/// a new phrase starts.
+pub struct KaraokeEffect {
+    pub accent: (u8, u8, u8),
+    hue: f32,
+    level: f32,
+    quiet_since: Option<Duration>,
+    flash_at: Option<Duration>,
+    last: Option<Duration>,
+}
+
+impl KaraokeEffect {
+    pub fn new(accent: (u8, u8, u8)) -> Self {
+        Self {
+            accent,
+            hue: 0.0,
+            level: 0.0,
+            quiet_since: None,
+            flash_at: None,
+            last: None,
+        }
+    }
+
+    fn render(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode], now: Duration) -> Frame {
+        let elapsed = self
+            .last
+            .map_or(0.0, |last| now.saturating_sub(last).as_secs_f32());
+        self.last = Some(now);
+
+        self.level = if analysis.bins.is_empty() {
+            analysis.spectrum.mids
+        } else {
+            analysis.peak(VOICE_HZ.0, VOICE_HZ.1)
+        }
+        .clamp(0.0, 1.0);
+        if let Some(hz) = vocal_pitch(analysis) {
+            // The short way around the wheel
+            let delta = (pitch_hue(hz) - self.hue + 540.0).rem_euclid(360.0) - 180.0;
+            let follow = 1.0 - (-elapsed / HUE_TIME).exp();
+            self.hue = (self.hue + delta * follow).rem_euclid(360.0);
+        }
+
+        if self.level < SINGING {
+            self.quiet_since.get_or_insert(now);
+        } else if let Some(since) = self.quiet_since.take() {
+            if now.saturating_sub(since) >= PHRASE_GAP {
+                self.flash_at = Some(now);
+            }
+        }
+        let flash = self.flash_at.map_or(0.0, |at| {
+            (-now.saturating_sub(at).as_secs_f32() / FLASH_TIME).exp()
+        });
+
+        let mut sorted: Vec<&LightNode> = nodes.iter().collect();
+        sorted.sort_by(|a, b| {
+            a.x.partial_cmp(&b.x)
+                .unwrap_or(Ordering::Equal)
+                .then(a.channel_id.cmp(&b.channel_id))
+        });
+        let base = scale(hue_to_rgb(self.hue), 0.2 + 0.8 * self.level);
+        sorted
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let accent = sorted.len() < 3 || i == 0 || i == sorted.len() - 1;
+                let color = if accent {
+                    mix(base, self.accent, flash)
+                } else {
+                    base
+                };
+                (node.channel_id, color)
+            })
+            .collect()
+    }
+}
+
+impl Default for KaraokeEffect {
+    fn default() -> Self {
+        Self::new((255, 255, 255))
+    }
+}
+
+impl LightEffect for KaraokeEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        self.render(analysis, nodes, analysis.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::line_layout;
+
+    const BIN_HZ: f32 = 48_000.0 / 1024.0;
+
+    /// A sung note: a fundamental and fading harmonics.
+    fn voice(hz: f32, level: f32) -> AnalysisFrame {
+        let mut bins = vec![0.0; 513];
+        for harmonic in 1..8 {
+            let position = hz * harmonic as f32 / BIN_HZ;
+            let k = position.floor() as usize;
+            let frac = position - k as f32;
+            let amplitude = level / harmonic as f32;
+            bins[k] += amplitude * (1.0 - frac);
+            bins[k + 1] += amplitude * frac;
+        }
+        AnalysisFrame {
+            bins,
+            bin_hz: BIN_HZ,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vocal_pitch() {
+        // Within a bin: low notes are only a few bins in
+        for hz in [110.0, 220.0, 330.0, 440.0] {
+            let found = vocal_pitch(&voice(hz, 1.0)).unwrap();
+            assert!(
+                (found - hz).abs() < BIN_HZ / 2.0,
+                "{} found as {}",
+                hz,
+                found
+            );
+        }
+        assert_eq!(vocal_pitch(&voice(220.0, 0.05)), None);
+        assert_eq!(vocal_pitch(&AnalysisFrame::default()), None);
+
+        assert!(pitch_hue(HUE_BASE_HZ * 4.0) < 1e-3);
+        assert!((pitch_hue(HUE_BASE_HZ * 2f32.sqrt()) - 180.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_phrase_flashes_accents() {
+        let mut effect = KaraokeEffect::new((255, 255, 255));
+        let nodes = line_layout(4);
+        let start = Duration::from_secs(1);
+        effect.render(&AnalysisFrame::default(), &nodes, start);
+
+        let sung = start + PHRASE_GAP;
+        let frame = effect.render(&voice(261.6, 1.0), &nodes, sung);
+        assert_eq!(frame[&0], (255, 255, 255));
+        assert_eq!(frame[&3], (255, 255, 255));
+        assert_ne!(frame[&1], (255, 255, 255));
+
+        // Still singing: no new flash, the accents fade back
+        let later = sung + Duration::from_secs(2);
+        let frame = effect.render(&voice(261.6, 1.0), &nodes, later);
+        assert_eq!(frame[&0], frame[&1]);
+    }
+}
@@ -1,45 +1,380 @@
-use crate::audio_interface::AudioSpectrum;
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{hue_to_rgb, Rgb};
 use crate::models::LightNode;
-use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
+
+mod brightness;
+mod chase;
+mod cluster;
+mod ct_only;
+pub mod dynamics;
+mod karaoke;
+mod noise;
+mod sensitivity;
+mod spectrum;
+mod storm;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod warm_pulse;
+
+pub use brightness::BrightnessEffect;
+pub use chase::ChaseEffect;
+pub use cluster::partition;
+pub use ct_only::CtOnlyEffect;
+use dynamics::{Dynamics, Envelope};
+pub use karaoke::KaraokeEffect;
+pub use noise::NoiseFieldEffect;
+pub use sensitivity::SensitivityEffect;
+pub use spectrum::SpectrumEffect;
+pub use storm::StormEffect;
+pub use warm_pulse::WarmPulseEffect;
+
+/// Effect names accepted by [`create_effect`].
+pub const EFFECT_NAMES: &[&str] = &[
+    "chase",
+    "karaoke",
+    "multiband",
+    "noise",
+    "pulse",
+    "spectrum",
+    "storm",
+    "warm",
+];
+
+/// Description of a registered effect, for listings such as `hueflow effects`.
+#[derive(Debug, Clone, Copy)]
+pub struct EffectInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Palette slots the effect reads, with their defaults. Brightness and
+    /// CT-only apply to every effect.
+    pub palette: &'static [(&'static str, (u8, u8, u8))],
+    /// Flashes whole rooms; refused in photosensitive-safe mode.
+    pub strobe: bool,
+}
+
+/// Every effect [`create_effect`] knows, in [`EFFECT_NAMES`] order.
+pub const EFFECTS: &[EffectInfo] = &[
+    EffectInfo {
+        name: "chase",
+        description: "One light at a time runs left to right, a step per beat",
+        palette: &[("color", (255, 180, 0))],
+        strobe: false,
+    },
+    EffectInfo {
+        name: "karaoke",
+        description: "The sung note colors the room; outer lights flash on new phrases",
+        palette: &[("accent", (255, 255, 255))],
+        strobe: false,
+    },
+    EffectInfo {
+        name: "multiband",
+        description: "Bass, mids and highs each drive one zone of the room",
+        palette: &[
+            ("bass", (255, 0, 0)),
+            ("mids", (0, 255, 0)),
+            ("highs", (0, 0, 255)),
+        ],
+        strobe: false,
+    },
+    EffectInfo {
+        name: "noise",
+        description: "Flowing color clouds drifting faster with the energy",
+        palette: &[("low", (0, 60, 255)), ("high", (255, 0, 140))],
+        strobe: false,
+    },
+    EffectInfo {
+        name: "pulse",
+        description: "All lights flash together on each bass hit",
+        palette: &[("color", (255, 100, 50))],
+        strobe: true,
+    },
+    EffectInfo {
+        name: "spectrum",
+        description: "Graphic equalizer across the room, bass on the left",
+        palette: &[("low", (255, 0, 80)), ("high", (0, 120, 255))],
+        strobe: false,
+    },
+    EffectInfo {
+        name: "storm",
+        description: "Dim storm light with lightning on treble hits and rumbling bass",
+        palette: &[("ambience", (40, 50, 80)), ("flash", (220, 230, 255))],
+        strobe: true,
+    },
+    EffectInfo {
+        name: "warm",
+        description: "Subtle warm-white pulsing for relaxed listening",
+        palette: &[],
+        strobe: false,
+    },
+];
+
+/// Looks up a registered effect by name.
+pub fn effect_info(name: &str) -> Option<&'static EffectInfo> {
+    EFFECTS.iter().find(|info| info.name == name)
+}
+
+/// Creates an effect by name, using `palette` for its colors where applicable.
+/// Returns `None` for unknown names.
+pub fn create_effect(name: &str, palette: &[(u8, u8, u8)]) -> Option<Box<dyn LightEffect>> {
+    create_effect_with(name, palette, &EffectParams::default())
+}
+
+/// Like [`create_effect`], tuned by `params`.
+pub fn create_effect_with(
+    name: &str,
+    palette: &[(u8, u8, u8)],
+    params: &EffectParams,
+) -> Option<Box<dyn LightEffect>> {
+    let color = |i: usize, default: (u8, u8, u8)| palette.get(i).copied().unwrap_or(default);
+    match name {
+        "chase" => Some(Box::new(ChaseEffect::new(color(0, (255, 180, 0))))),
+        "karaoke" => Some(Box::new(KaraokeEffect::new(color(0, (255, 255, 255))))),
+        "multiband" => Some(Box::new(MultiBandEffect::with_params(
+            [
+                color(0, (255, 0, 0)),
+                color(1, (0, 255, 0)),
+                color(2, (0, 0, 255)),
+            ],
+            palette,
+            params,
+        ))),
+        "noise" => Some(Box::new(NoiseFieldEffect::new(
+            color(0, (0, 60, 255)),
+            color(1, (255, 0, 140)),
+        ))),
+        "pulse" => {
+            let mut effect = PulseEffect::new(color(0, (255, 100, 50)));
+            effect.envelope = params.dynamics.map(Envelope::new);
+            Some(Box::new(effect))
+        }
+        "spectrum" => {
+            let mut effect = SpectrumEffect::new(color(0, (255, 0, 80)), color(1, (0, 120, 255)));
+            effect.dynamics = params.dynamics;
+            Some(Box::new(effect))
+        }
+        "storm" => Some(Box::new(StormEffect::new(
+            color(0, (40, 50, 80)),
+            color(1, (220, 230, 255)),
+        ))),
+        "warm" => Some(Box::new(WarmPulseEffect::default())),
+        _ => None,
+    }
+}
+
+/// Effect tuning beyond the palette, stored with a
+/// [`Preset`](crate::preset::Preset). Effects ignore what does not apply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectParams {
+    /// How `multiband` colors the bass, mids and highs bands, in that order;
+    /// bands without an entry keep their palette slot.
+    pub bands: Vec<BandColor>,
+    /// Lights go dark as their band gets loud, for "dark pulses on the
+    /// beat" over a lit room (`multiband`).
+    pub invert: bool,
+    /// Attack, hold and fall-off of the levels driving the lights
+    /// (`pulse`, `multiband`, `spectrum`); raw levels when `None`.
+    pub dynamics: Option<Dynamics>,
+}
+
+impl EffectParams {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Where a band's color comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum BandColor {
+    /// A color of the preset palette, by index.
+    Palette { index: usize },
+    /// Fully saturated color turning around the color wheel.
+    Rotate(HueRotation),
+}
+
+/// A hue turning at a steady rate.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HueRotation {
+    /// Starting hue in degrees (0 = red, 120 = green, 240 = blue).
+    #[serde(default)]
+    pub hue: f32,
+    #[serde(default = "default_degrees_per_sec")]
+    pub degrees_per_sec: f32,
+}
+
+fn default_degrees_per_sec() -> f32 {
+    10.0
+}
+
+/// Colors keyed by streaming channel_id (not the REST API light ID).
+///
+/// The frame type of HueFlow: effects return it, and the stream turns it
+/// into `LightState`s with `LightState::from_frame` (feature `bridge`).
+pub type Frame = HashMap<u8, (u8, u8, u8)>;
+
+/// A [`Frame`] with unrounded colors (0.0 - 255.0 per component). The render
+/// loop runs the output stages on it, so dimming does not round dark colors
+/// together before the stream sends them with 16 bits.
+pub type PreciseFrame = HashMap<u8, [f32; 3]>;
+
+/// The unrounded version of `frame`.
+pub fn precise(frame: &Frame) -> PreciseFrame {
+    frame
+        .iter()
+        .map(|(&c, color)| (c, color.to_rgb()))
+        .collect()
+}
+
+/// `frame` rounded to 8 bits, for outputs and observers that take [`Frame`]s.
+pub fn rounded(frame: &PreciseFrame) -> Frame {
+    frame
+        .iter()
+        .map(|(&c, &color)| (c, Rgb::from_rgb(color)))
+        .collect()
+}
 
 /// Trait for light effects that map audio to colors.
-/// The returned HashMap uses channel_id (u8) as key, not the REST API light ID.
 pub trait LightEffect: Send + Sync {
-    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> HashMap<u8, (u8, u8, u8)>;
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame;
+
+    /// Like [`update`](Self::update), with the full FFT bins, beat info and
+    /// time. The render loop calls this; override it for effects that need
+    /// more than the three bands or move over time. Through `update`, the
+    /// latter render as at time zero.
+    fn update_frame(&mut self, frame: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        self.update(&frame.spectrum, nodes)
+    }
+
+    /// Transition time the output layer should fade each new frame in over,
+    /// for effects that look better smoothed than stepped. `None` jumps.
+    fn smoothing(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Strobe-class effects flash whole rooms at full contrast; they are
+    /// refused while photosensitive-safe mode is on.
+    fn is_strobe(&self) -> bool {
+        false
+    }
+
+    /// Daylight at the lights' location from 0.0 (night) to 1.0 (day), see
+    /// [`solar::daylight`](crate::solar::daylight). Ambient effects turn
+    /// warmer and dimmer after sunset; others ignore it.
+    fn set_daylight(&mut self, _daylight: f32) {}
 }
 
 pub struct PulseEffect {
     pub color: (u8, u8, u8),
+    /// Smooths the flashes when set.
+    pub envelope: Option<Envelope>,
 }
 
 impl PulseEffect {
     pub fn new(color: (u8, u8, u8)) -> Self {
-        Self { color }
+        Self {
+            color,
+            envelope: None,
+        }
     }
 }
 
 impl LightEffect for PulseEffect {
-    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> HashMap<u8, (u8, u8, u8)> {
-        let brightness = (audio.bass * audio.energy).clamp(0.0, 1.0);
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let audio = &analysis.spectrum;
+        let mut brightness = (audio.bass * audio.energy).clamp(0.0, 1.0);
+        if let Some(envelope) = &mut self.envelope {
+            brightness = envelope.update(brightness, analysis.time);
+        }
         let r = (self.color.0 as f32 * brightness) as u8;
         let g = (self.color.1 as f32 * brightness) as u8;
         let b = (self.color.2 as f32 * brightness) as u8;
 
-        let mut result = HashMap::new();
+        let mut result = Frame::new();
         for node in nodes {
             // Use channel_id directly (already u8)
             result.insert(node.channel_id, (r, g, b));
         }
         result
     }
+
+    /// Every light jumps between dark and full color on each bass hit.
+    fn is_strobe(&self) -> bool {
+        true
+    }
 }
 
-pub struct MultiBandEffect;
+pub struct MultiBandEffect {
+    /// Colors for the bass, mids and highs bands.
+    pub band_colors: [(u8, u8, u8); 3],
+    /// Bands whose hue turns over time instead of keeping their color.
+    pub hue_rotation: [Option<HueRotation>; 3],
+    /// Lights dim as their band gets louder.
+    pub invert: bool,
+    /// Smooth the bass, mids and highs levels when set.
+    pub envelopes: Option<[Envelope; 3]>,
+    /// Frame time of the first frame, where rotating hues start.
+    started: Option<Duration>,
+    /// Band of each channel, clustered for `layout`.
+    zones: HashMap<u8, usize>,
+    layout: Vec<(u8, [f64; 3])>,
+}
 
 impl MultiBandEffect {
     pub fn new() -> Self {
-        Self
+        Self::with_colors([(255, 0, 0), (0, 255, 0), (0, 0, 255)])
+    }
+
+    pub fn with_colors(band_colors: [(u8, u8, u8); 3]) -> Self {
+        Self {
+            band_colors,
+            hue_rotation: [None; 3],
+            invert: false,
+            envelopes: None,
+            started: None,
+            zones: HashMap::new(),
+            layout: Vec::new(),
+        }
+    }
+
+    /// Band colors from `params.bands`, falling back to `band_colors`.
+    pub fn with_params(
+        band_colors: [(u8, u8, u8); 3],
+        palette: &[(u8, u8, u8)],
+        params: &EffectParams,
+    ) -> Self {
+        let mut effect = Self::with_colors(band_colors);
+        for (band, mapping) in params.bands.iter().take(3).enumerate() {
+            match mapping {
+                BandColor::Palette { index } => {
+                    if let Some(color) = palette.get(*index) {
+                        effect.band_colors[band] = *color;
+                    }
+                }
+                BandColor::Rotate(rotation) => effect.hue_rotation[band] = Some(*rotation),
+            }
+        }
+        effect.invert = params.invert;
+        effect.envelopes = params
+            .dynamics
+            .map(|dynamics| std::array::from_fn(|_| Envelope::new(dynamics)));
+        effect
+    }
+
+    fn colors(&mut self, now: Duration) -> [(u8, u8, u8); 3] {
+        let started = *self.started.get_or_insert(now);
+        let elapsed = now.saturating_sub(started).as_secs_f32();
+        std::array::from_fn(|band| match self.hue_rotation[band] {
+            Some(rotation) => hue_to_rgb(rotation.hue + rotation.degrees_per_sec * elapsed),
+            None => self.band_colors[band],
+        })
     }
 }
 
@@ -50,11 +385,33 @@ impl Default for MultiBandEffect {
 }
 
 impl LightEffect for MultiBandEffect {
-    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> HashMap<u8, (u8, u8, u8)> {
-        let mut result = HashMap::new();
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut result = Frame::new();
         if nodes.is_empty() {
             return result;
         }
+        let audio = &analysis.spectrum;
+        let mut levels = [audio.bass, audio.mids, audio.highs];
+        if let Some(envelopes) = &mut self.envelopes {
+            for (level, envelope) in levels.iter_mut().zip(envelopes) {
+                *level = envelope.update(level.clamp(0.0, 1.0), analysis.time);
+            }
+        }
+        let colors = self.colors(analysis.time);
+        let mut paint = |node: &LightNode, band: usize| {
+            let level = levels[band].clamp(0.0, 1.0);
+            let brightness = if self.invert { 1.0 - level } else { level };
+            let color = colors[band];
+            let r = (color.0 as f32 * brightness) as u8;
+            let g = (color.1 as f32 * brightness) as u8;
+            let b = (color.2 as f32 * brightness) as u8;
+            // Use channel_id directly
+            result.insert(node.channel_id, (r, g, b));
+        };
 
         // Check if we have position data (at least one node has non-zero coordinate)
         let has_positions = nodes
@@ -62,49 +419,88 @@ impl LightEffect for MultiBandEffect {
             .any(|n| n.x.abs() > 0.001 || n.y.abs() > 0.001 || n.z.abs() > 0.001);
 
         if !has_positions {
-            // Modulo channel_id fallback
+            // Modulo channel_id fallback: bass, mids, highs in turn
             for node in nodes {
-                let (val, color) = match node.channel_id % 3 {
-                    0 => (audio.bass, (255, 0, 0)),  // Bass -> Red
-                    1 => (audio.mids, (0, 255, 0)),  // Mids -> Green
-                    2 => (audio.highs, (0, 0, 255)), // Highs -> Blue
-                    _ => (0.0, (0, 0, 0)),
-                };
-                let brightness = val.clamp(0.0, 1.0);
-                let r = (color.0 as f32 * brightness) as u8;
-                let g = (color.1 as f32 * brightness) as u8;
-                let b = (color.2 as f32 * brightness) as u8;
-                result.insert(node.channel_id, (r, g, b));
+                paint(node, node.channel_id as usize % 3);
             }
         } else {
-            // Sort by X position for spatial effect
-            let mut sorted_nodes: Vec<&LightNode> = nodes.iter().collect();
-            sorted_nodes.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
-
-            let count = sorted_nodes.len();
-
-            for (i, node) in sorted_nodes.iter().enumerate() {
-                let section = if count < 3 {
-                    i // if 1 node: 0 -> Bass. if 2 nodes: 0->Bass, 1->Mids.
-                } else {
-                    // Partition into 3 sections based on position
-                    (i * 3) / count
-                };
-
-                let (val, color) = match section {
-                    0 => (audio.bass, (255, 0, 0)),
-                    1 => (audio.mids, (0, 255, 0)),
-                    _ => (audio.highs, (0, 0, 255)),
-                };
-
-                let brightness = val.clamp(0.0, 1.0);
-                let r = (color.0 as f32 * brightness) as u8;
-                let g = (color.1 as f32 * brightness) as u8;
-                let b = (color.2 as f32 * brightness) as u8;
-                // Use channel_id directly
-                result.insert(node.channel_id, (r, g, b));
+            // One spatial cluster per band, bass on the left; re-clustered
+            // only when the layout changes
+            let layout: Vec<(u8, [f64; 3])> = nodes
+                .iter()
+                .map(|n| (n.channel_id, [n.x, n.y, n.z]))
+                .collect();
+            if layout != self.layout {
+                self.zones = partition(nodes, self.band_colors.len());
+                self.layout = layout;
+            }
+
+            for node in nodes {
+                let band = self.zones.get(&node.channel_id).copied().unwrap_or(2);
+                paint(node, band.min(2));
             }
         }
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_matches_factory() {
+        let names: Vec<&str> = EFFECTS.iter().map(|info| info.name).collect();
+        assert_eq!(names, EFFECT_NAMES);
+        for info in EFFECTS {
+            let effect = create_effect(info.name, &[]).unwrap();
+            assert_eq!(effect.is_strobe(), info.strobe, "{}", info.name);
+        }
+        assert!(effect_info("strobe").is_none());
+    }
+
+    #[test]
+    fn test_multiband_params() {
+        let params: EffectParams = serde_json::from_value(serde_json::json!({
+            "bands": [
+                { "mode": "palette", "index": 3 },
+                { "mode": "rotate", "hue": 240, "degrees_per_sec": 0 }
+            ],
+            "invert": true
+        }))
+        .unwrap();
+        let palette = [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 255)];
+        let mut effect = create_effect_with("multiband", &palette, &params).unwrap();
+
+        let nodes = testing::unpositioned_layout(3);
+        let audio = AudioSpectrum {
+            bass: 1.0,
+            mids: 0.0,
+            highs: 0.5,
+            energy: 1.0,
+        };
+        let frame = effect.update(&audio, &nodes);
+        // Loud bass goes dark, quiet mids are fully lit
+        assert_eq!(frame[&0], (0, 0, 0));
+        assert_eq!(frame[&1], (0, 0, 255));
+        assert_eq!(frame[&2], (0, 0, 127));
+    }
+
+    #[test]
+    fn test_multiband_rotates_with_frame_time() {
+        let params: EffectParams = serde_json::from_value(serde_json::json!({
+            "bands": [{ "mode": "rotate", "hue": 0, "degrees_per_sec": 120 }]
+        }))
+        .unwrap();
+        let mut effect = create_effect_with("multiband", &[], &params).unwrap();
+        let nodes = testing::unpositioned_layout(1);
+        let frame = |secs: u64| AnalysisFrame {
+            spectrum: testing::spectrum(1.0, 0.0, 0.0, 1.0),
+            time: Duration::from_secs(secs),
+            ..Default::default()
+        };
+        // Hues turn from the first frame on, whatever its time
+        assert_eq!(effect.update_frame(&frame(10), &nodes)[&0], (255, 0, 0));
+        assert_eq!(effect.update_frame(&frame(11), &nodes)[&0], (0, 255, 0));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{mix, scale};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::time::Duration;
+
+/// Brightness in silence, so the clouds never go fully dark.
+const MIN_LEVEL: f32 = 0.35;
+/// How much faster the clouds drift at full energy than in silence.
+const ENERGY_SPEEDUP: f32 = 4.0;
+
+/// Slowly flowing clouds of color: each light samples 3D simplex noise at
+/// its position, drifting through the field over time.
+///
+/// Noise picks a color between `low_color` and `high_color`; the music's
+/// energy speeds up the flow and brightens the room.
+pub struct NoiseFieldEffect {
+    pub low_color: (u8, u8, u8),
+    pub high_color: (u8, u8, u8),
+    /// Spatial frequency: higher values give smaller clouds.
+    pub scale: f32,
+    /// Drift through the field per second in silence.
+    pub speed: f32,
+    time: f32,
+    last: Option<Duration>,
+}
+
+impl NoiseFieldEffect {
+    pub fn new(low_color: (u8, u8, u8), high_color: (u8, u8, u8)) -> Self {
+        Self {
+            low_color,
+            high_color,
+            scale: 1.5,
+            speed: 0.15,
+            time: 0.0,
+            last: None,
+        }
+    }
+
+    fn render(&self, energy: f32, nodes: &[LightNode]) -> Frame {
+        let level = MIN_LEVEL + (1.0 - MIN_LEVEL) * energy;
+        nodes
+            .iter()
+            .map(|node| {
+                let n = simplex3(
+                    node.x as f32 * self.scale,
+                    node.y as f32 * self.scale,
+                    node.z as f32 * self.scale + self.time,
+                );
+                let color = mix(self.low_color, self.high_color, (n + 1.0) / 2.0);
+                (node.channel_id, scale(color, level))
+            })
+            .collect()
+    }
+}
+
+impl Default for NoiseFieldEffect {
+    fn default() -> Self {
+        Self::new((0, 60, 255), (255, 0, 140))
+    }
+}
+
+impl LightEffect for NoiseFieldEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let now = analysis.time;
+        let energy = analysis.spectrum.energy.clamp(0.0, 1.0);
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_sub(last).as_secs_f32();
+            self.time += elapsed * self.speed * (1.0 + ENERGY_SPEEDUP * energy);
+        }
+        self.last = Some(now);
+        self.render(energy, nodes)
+    }
+}
+
+/// Gradients of 3D simplex noise: the edge midpoints of a cube.
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Gradient for a lattice point, from an integer hash instead of the usual
+/// permutation table.
+fn gradient(i: i32, j: i32, k: i32) -> [f32; 3] {
+    let mut h = (i as u32).wrapping_mul(0x8da6_b343)
+        ^ (j as u32).wrapping_mul(0xd816_3841)
+        ^ (k as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    GRADIENTS[(h % 12) as usize]
+}
+
+/// 3D simplex noise (after Gustavson), smooth and roughly in -1.0 - 1.0.
+pub fn simplex3(x: f32, y: f32, z: f32) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    // Skew into the simplex grid to find the containing cell
+    let s = (x + y + z) * F3;
+    let (i, j, k) = (
+        (x + s).floor() as i32,
+        (y + s).floor() as i32,
+        (z + s).floor() as i32,
+    );
+    let t = (i + j + k) as f32 * G3;
+    let d0 = [x - (i as f32 - t), y - (j as f32 - t), z - (k as f32 - t)];
+
+    // Which of the six simplices of the cell: walk the axes largest first
+    let (o1, o2) = if d0[0] >= d0[1] {
+        if d0[1] >= d0[2] {
+            ([1, 0, 0], [1, 1, 0])
+        } else if d0[0] >= d0[2] {
+            ([1, 0, 0], [1, 0, 1])
+        } else {
+            ([0, 0, 1], [1, 0, 1])
+        }
+    } else if d0[1] < d0[2] {
+        ([0, 0, 1], [0, 1, 1])
+    } else if d0[0] < d0[2] {
+        ([0, 1, 0], [0, 1, 1])
+    } else {
+        ([0, 1, 0], [1, 1, 0])
+    };
+
+    let corners = [[0, 0, 0], o1, o2, [1, 1, 1]];
+    let total: f32 = corners
+        .iter()
+        .enumerate()
+        .map(|(n, o)| {
+            let offset = n as f32 * G3;
+            let d = [
+                d0[0] - o[0] as f32 + offset,
+                d0[1] - o[1] as f32 + offset,
+                d0[2] - o[2] as f32 + offset,
+            ];
+            let falloff = 0.6 - d[0] * d[0] - d[1] * d[1] - d[2] * d[2];
+            if falloff <= 0.0 {
+                return 0.0;
+            }
+            let g = gradient(i + o[0], j + o[1], k + o[2]);
+            falloff.powi(4) * (g[0] * d[0] + g[1] * d[1] + g[2] * d[2])
+        })
+        .sum();
+    32.0 * total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::line_layout;
+
+    #[test]
+    fn test_simplex_is_smooth_and_bounded() {
+        let mut previous = simplex3(0.0, 1.3, 0.0);
+        let (mut min, mut max) = (f32::MAX, f32::MIN);
+        for step in 1..2000 {
+            let t = step as f32 * 0.01;
+            let n = simplex3(t * 0.7, 1.3 - t * 0.2, t * 0.4);
+            assert!((-1.0..=1.0).contains(&n), "{}", n);
+            assert!((n - previous).abs() < 0.1, "jump at {}", t);
+            previous = n;
+            min = min.min(n);
+            max = max.max(n);
+        }
+        // Actually varies
+        assert!(max - min > 0.8, "{} - {}", min, max);
+    }
+
+    #[test]
+    fn test_energy_brightens() {
+        let effect = NoiseFieldEffect::new((255, 0, 0), (255, 0, 0));
+        let nodes = line_layout(4);
+        let quiet = effect.render(0.0, &nodes);
+        let loud = effect.render(1.0, &nodes);
+        assert_eq!(quiet[&0], (89, 0, 0));
+        assert_eq!(loud[&3], (255, 0, 0));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::time::Duration;
+
+/// Amplifies (or attenuates) the audio levels any effect sees, so quiet
+/// rooms still reach full brightness and loud ones do not sit at the top.
+pub struct SensitivityEffect {
+    inner: Box<dyn LightEffect>,
+    pub sensitivity: f32,
+}
+
+impl SensitivityEffect {
+    pub fn new(inner: Box<dyn LightEffect>, sensitivity: f32) -> Self {
+        Self { inner, sensitivity }
+    }
+
+    fn level(&self, level: f32) -> f32 {
+        (level * self.sensitivity).clamp(0.0, 1.0)
+    }
+
+    fn spectrum(&self, spectrum: &AudioSpectrum) -> AudioSpectrum {
+        AudioSpectrum {
+            bass: self.level(spectrum.bass),
+            mids: self.level(spectrum.mids),
+            highs: self.level(spectrum.highs),
+            energy: self.level(spectrum.energy),
+        }
+    }
+}
+
+impl LightEffect for SensitivityEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut scaled = analysis.clone();
+        scaled.spectrum = self.spectrum(&analysis.spectrum);
+        for bin in &mut scaled.bins {
+            *bin = self.level(*bin);
+        }
+        scaled.history = analysis.history.map(|s| self.spectrum(s));
+        self.inner.update_frame(&scaled, nodes)
+    }
+
+    fn smoothing(&self) -> Option<Duration> {
+        self.inner.smoothing()
+    }
+
+    fn is_strobe(&self) -> bool {
+        self.inner.is_strobe()
+    }
+
+    fn set_daylight(&mut self, daylight: f32) {
+        self.inner.set_daylight(daylight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shows the bass level on channel 0.
+    struct Bass;
+
+    impl LightEffect for Bass {
+        fn update(&mut self, audio: &AudioSpectrum, _nodes: &[LightNode]) -> Frame {
+            Frame::from([(0, ((audio.bass * 100.0).round() as u8, 0, 0))])
+        }
+    }
+
+    #[test]
+    fn test_sensitivity() {
+        let audio = AudioSpectrum {
+            bass: 0.3,
+            ..Default::default()
+        };
+        let mut louder = SensitivityEffect::new(Box::new(Bass), 2.0);
+        assert_eq!(louder.update(&audio, &[])[&0].0, 60);
+        // Capped at full level
+        louder.sensitivity = 5.0;
+        assert_eq!(louder.update(&audio, &[])[&0].0, 100);
+        let mut quieter = SensitivityEffect::new(Box::new(Bass), 0.5);
+        assert_eq!(quieter.update(&audio, &[])[&0].0, 15);
+    }
+}
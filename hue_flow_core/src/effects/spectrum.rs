@@ -0,0 +1,139 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{mix, scale};
+use crate::effects::dynamics::{Dynamics, Envelope};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Lowest and highest frequency spread across the lights.
+const LOW_HZ: f32 = 40.0;
+const HIGH_HZ: f32 = 16000.0;
+
+/// Spectrum analyzer across the room: lights from left to right each show
+/// a slice of the FFT, spaced logarithmically like a graphic equalizer.
+///
+/// Colors fade from `low_color` on the bass side to `high_color` on the
+/// treble side. Sources without FFT bins fall back to the three bands.
+pub struct SpectrumEffect {
+    pub low_color: (u8, u8, u8),
+    pub high_color: (u8, u8, u8),
+    /// Gives each light's level attack, hold and fall-off when set.
+    pub dynamics: Option<Dynamics>,
+    envelopes: HashMap<u8, Envelope>,
+}
+
+impl SpectrumEffect {
+    pub fn new(low_color: (u8, u8, u8), high_color: (u8, u8, u8)) -> Self {
+        Self {
+            low_color,
+            high_color,
+            dynamics: None,
+            envelopes: HashMap::new(),
+        }
+    }
+}
+
+impl Default for SpectrumEffect {
+    fn default() -> Self {
+        Self::new((255, 0, 80), (0, 120, 255))
+    }
+}
+
+/// Band level for a frequency, when no bins are available.
+fn band_level(spectrum: &AudioSpectrum, hz: f32) -> f32 {
+    if hz < 250.0 {
+        spectrum.bass
+    } else if hz < 4000.0 {
+        spectrum.mids
+    } else {
+        spectrum.highs
+    }
+}
+
+impl LightEffect for SpectrumEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        let mut sorted: Vec<&LightNode> = nodes.iter().collect();
+        sorted.sort_by(|a, b| {
+            a.x.partial_cmp(&b.x)
+                .unwrap_or(Ordering::Equal)
+                .then(a.channel_id.cmp(&b.channel_id))
+        });
+
+        let count = sorted.len() as f32;
+        let ratio = HIGH_HZ / LOW_HZ;
+        sorted
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let low = LOW_HZ * ratio.powf(i as f32 / count);
+                let high = LOW_HZ * ratio.powf((i + 1) as f32 / count);
+                let mut level = if analysis.bins.is_empty() {
+                    band_level(&analysis.spectrum, (low * high).sqrt())
+                } else {
+                    analysis.peak(low, high)
+                };
+                if let Some(dynamics) = self.dynamics {
+                    level = self
+                        .envelopes
+                        .entry(node.channel_id)
+                        .or_insert_with(|| Envelope::new(dynamics))
+                        .update(level.clamp(0.0, 1.0), analysis.time);
+                }
+                let t = if count > 1.0 {
+                    i as f32 / (count - 1.0)
+                } else {
+                    0.0
+                };
+                let color = mix(self.low_color, self.high_color, t);
+                (node.channel_id, scale(color, level.clamp(0.0, 1.0)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(channel_id: u8, x: f64) -> LightNode {
+        LightNode {
+            id: channel_id.to_string(),
+            channel_id,
+            x,
+            y: 0.0,
+            z: 0.0,
+            segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_lights_follow_their_slice() {
+        let nodes = [node(0, 1.0), node(1, -1.0)];
+        let mut bins = vec![0.0; 513];
+        // A tone at ~8 kHz: only the right-hand (treble) light reacts
+        bins[171] = 1.0;
+        let frame = AnalysisFrame {
+            bins,
+            bin_hz: 48_000.0 / 1024.0,
+            ..Default::default()
+        };
+        let mut effect = SpectrumEffect::new((255, 0, 0), (0, 0, 255));
+        let colors = effect.update_frame(&frame, &nodes);
+        assert_eq!(colors[&1], (0, 0, 0));
+        assert_eq!(colors[&0], (0, 0, 255));
+
+        // Without bins the bands stand in
+        let bass = AudioSpectrum {
+            bass: 1.0,
+            ..Default::default()
+        };
+        let colors = effect.update(&bass, &nodes);
+        assert_eq!(colors[&1], (255, 0, 0));
+        assert_eq!(colors[&0], (0, 0, 0));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::{mix, scale};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Strikes per second in silence, about one every half minute.
+const BASE_RATE: f32 = 0.03;
+/// Extra strikes per second per unit of treble transient.
+const TRANSIENT_RATE: f32 = 3.0;
+/// How long a strike lasts, flickers and afterglow included.
+const STRIKE_TIME: Duration = Duration::from_millis(450);
+/// Lights this close to the struck one flash with it.
+const STRIKE_RADIUS: f64 = 0.8;
+/// Share of the ambience a full-bass rumble takes away.
+const RUMBLE_DEPTH: f32 = 0.6;
+/// Time the treble average and the rumble take to follow the music.
+const FOLLOW_TIME: f32 = 0.4;
+
+/// A lightning strike on some of the lights.
+struct Strike {
+    channels: Vec<u8>,
+    started: Duration,
+}
+
+impl Strike {
+    /// Flash intensity at `now`: a few hard flickers, then
+    /// a quick fade.
+    fn intensity(&self, now: Duration) -> f32 {
+        let t = now.saturating_sub(self.started).as_secs_f32();
+        if t >= STRIKE_TIME.as_secs_f32() {
+            return 0.0;
+        }
+        let flicker = if t < 0.25 && (t * 20.0) as u32 % 2 == 1 {
+            0.25
+        } else {
+            1.0
+        };
+        flicker * (-t / 0.15).exp()
+    }
+}
+
+/// Thunderstorm ambience: dim blue-gray light that darkens with rumbling
+/// bass, and lightning striking a few neighbouring lights at a time, more
+/// often on sharp treble (rain, crashes, thunder claps).
+///
+/// Lightning flashes at full contrast, so this counts as a strobe effect.
+pub struct StormEffect {
+    pub ambience: (u8, u8, u8),
+    pub flash: (u8, u8, u8),
+    strike: Option<Strike>,
+    treble: f32,
+    rumble: f32,
+    last: Option<Duration>,
+    rng: u64,
+}
+
+impl StormEffect {
+    pub fn new(ambience: (u8, u8, u8), flash: (u8, u8, u8)) -> Self {
+        // Random per instance without a clock, which wasm32 lacks
+        let seed = RandomState::new().build_hasher().finish();
+        Self {
+            ambience,
+            flash,
+            strike: None,
+            treble: 0.0,
+            rumble: 0.0,
+            last: None,
+            rng: seed | 1,
+        }
+    }
+
+    /// Uniform in 0.0 - 1.0, from a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Strikes a random light and its neighbours.
+    fn strike(&mut self, nodes: &[LightNode], now: Duration) {
+        if nodes.is_empty() {
+            return;
+        }
+        let index = ((self.random() * nodes.len() as f32) as usize).min(nodes.len() - 1);
+        let center = &nodes[index];
+        let channels = nodes
+            .iter()
+            .filter(|n| {
+                let d =
+                    (n.x - center.x).powi(2) + (n.y - center.y).powi(2) + (n.z - center.z).powi(2);
+                d.sqrt() <= STRIKE_RADIUS
+            })
+            .map(|n| n.channel_id)
+            .collect();
+        self.strike = Some(Strike {
+            channels,
+            started: now,
+        });
+    }
+
+    fn render(&mut self, spectrum: &AudioSpectrum, nodes: &[LightNode], now: Duration) -> Frame {
+        let elapsed = self
+            .last
+            .map_or(0.0, |last| now.saturating_sub(last).as_secs_f32());
+        self.last = Some(now);
+        let follow = 1.0 - (-elapsed / FOLLOW_TIME).exp();
+
+        let highs = spectrum.highs.clamp(0.0, 1.0);
+        let transient = (highs - self.treble).max(0.0);
+        self.treble += (highs - self.treble) * follow;
+        self.rumble += (spectrum.bass.clamp(0.0, 1.0) - self.rumble) * follow;
+
+        let striking = self
+            .strike
+            .as_ref()
+            .is_some_and(|s| now.saturating_sub(s.started) < STRIKE_TIME);
+        let rate = BASE_RATE + TRANSIENT_RATE * transient;
+        if !striking && self.random() < 1.0 - (-rate * elapsed).exp() {
+            self.strike(nodes, now);
+        }
+
+        let ambience = scale(self.ambience, 1.0 - RUMBLE_DEPTH * self.rumble);
+        nodes
+            .iter()
+            .map(|node| {
+                let flash = self
+                    .strike
+                    .as_ref()
+                    .filter(|s| s.channels.contains(&node.channel_id))
+                    .map_or(0.0, |s| s.intensity(now));
+                (node.channel_id, mix(ambience, self.flash, flash))
+            })
+            .collect()
+    }
+}
+
+impl Default for StormEffect {
+    fn default() -> Self {
+        Self::new((40, 50, 80), (220, 230, 255))
+    }
+}
+
+impl LightEffect for StormEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        self.render(&analysis.spectrum, nodes, analysis.time)
+    }
+
+    /// Lightning flashes groups of lights at full contrast.
+    fn is_strobe(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::{line_layout, spectrum};
+
+    #[test]
+    fn test_strike_lights_neighbours() {
+        let mut storm = StormEffect::default();
+        let nodes = line_layout(5);
+        let start = Duration::from_secs(1);
+        storm.strike(&nodes, start);
+        let struck = storm.strike.as_ref().unwrap().channels.clone();
+        // Lights are 0.5 apart: the struck one and its direct neighbours
+        assert!((1..=3).contains(&struck.len()), "{:?}", struck);
+
+        let frame = storm.render(&spectrum(0.0, 0.0, 0.0, 0.0), &nodes, start);
+        for node in &nodes {
+            let expected = if struck.contains(&node.channel_id) {
+                (220, 230, 255)
+            } else {
+                (40, 50, 80)
+            };
+            assert_eq!(frame[&node.channel_id], expected);
+        }
+
+        // Gone after the strike, back to the ambience
+        let later = start + STRIKE_TIME;
+        storm.rng = 1 << 63; // Keep the next draw from striking again
+        let frame = storm.render(&spectrum(0.0, 0.0, 0.0, 0.0), &nodes, later);
+        assert!(frame.values().all(|&c| c == (40, 50, 80)));
+    }
+
+    #[test]
+    fn test_rumble_dims_ambience() {
+        // A fixed seed that does not strike
+        let mut storm = StormEffect {
+            rng: 1 << 63,
+            ..Default::default()
+        };
+        let nodes = line_layout(2);
+        let start = Duration::from_secs(1);
+        storm.render(&spectrum(0.0, 0.0, 0.0, 0.0), &nodes, start);
+        let later = start + Duration::from_secs(5);
+        let frame = storm.render(&spectrum(1.0, 0.0, 0.0, 1.0), &nodes, later);
+        assert_eq!(frame[&0], (16, 20, 32));
+    }
+}
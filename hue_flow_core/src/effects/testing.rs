@@ -0,0 +1,181 @@
+//! Golden-frame testing for effects. Only in test builds, or with the
+//! `testing` feature.
+//!
+//! Feed an effect a scripted sequence of [`AudioSpectrum`]s on a synthetic
+//! layout, render the frames as text and compare them with a snapshot file:
+//!
+//! ```no_run
+//! use hue_flow_core::effects::testing::{assert_golden, line_layout, render, standard_script};
+//! use hue_flow_core::effects::PulseEffect;
+//!
+//! let mut effect = PulseEffect::new((255, 0, 0));
+//! let frames = render(&mut effect, &standard_script(), &line_layout(4));
+//! assert_golden("tests/golden/pulse.txt", &frames);
+//! ```
+//!
+//! Run with `UPDATE_GOLDEN=1` to (re)write the snapshots after an intended
+//! change, and review the diff.
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::effects::LightEffect;
+use crate::models::LightNode;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Time between the frames of [`render`], the default 20 FPS.
+pub const FRAME_TIME: Duration = Duration::from_millis(50);
+
+/// Shorthand for a spectrum in scripts.
+pub fn spectrum(bass: f32, mids: f32, highs: f32, energy: f32) -> AudioSpectrum {
+    AudioSpectrum {
+        bass,
+        mids,
+        highs,
+        energy,
+    }
+}
+
+/// Silence, each band alone, everything at once and a decay: the cases most
+/// effects treat differently.
+pub fn standard_script() -> Vec<AudioSpectrum> {
+    vec![
+        spectrum(0.0, 0.0, 0.0, 0.0),
+        spectrum(1.0, 0.0, 0.0, 1.0),
+        spectrum(0.0, 1.0, 0.0, 1.0),
+        spectrum(0.0, 0.0, 1.0, 1.0),
+        spectrum(1.0, 1.0, 1.0, 1.0),
+        spectrum(0.5, 0.25, 0.75, 0.5),
+        spectrum(0.1, 0.1, 0.1, 0.2),
+    ]
+}
+
+/// `count` lights evenly spaced from left (x = -1.0) to right (x = 1.0).
+pub fn line_layout(count: usize) -> Vec<LightNode> {
+    (0..count)
+        .map(|i| {
+            let x = if count > 1 {
+                -1.0 + 2.0 * i as f64 / (count - 1) as f64
+            } else {
+                0.0
+            };
+            node(i as u8, x)
+        })
+        .collect()
+}
+
+/// `count` lights without positions, as on bridges that report none.
+pub fn unpositioned_layout(count: usize) -> Vec<LightNode> {
+    (0..count).map(|i| node(i as u8, 0.0)).collect()
+}
+
+fn node(channel_id: u8, x: f64) -> LightNode {
+    LightNode {
+        id: (channel_id as u32 + 1).to_string(),
+        channel_id,
+        x,
+        y: 0.0,
+        z: 0.0,
+        segment: 0,
+    }
+}
+
+/// Runs `script` through `effect`, a frame every [`FRAME_TIME`], and renders
+/// one line per frame, with channels in order: `0: 0=#ff0000 1=#000000`.
+pub fn render(
+    effect: &mut dyn LightEffect,
+    script: &[AudioSpectrum],
+    nodes: &[LightNode],
+) -> String {
+    let mut out = String::new();
+    for (i, audio) in script.iter().enumerate() {
+        let analysis = AnalysisFrame {
+            time: FRAME_TIME * i as u32,
+            ..(*audio).into()
+        };
+        let frame = effect.update_frame(&analysis, nodes);
+        let mut channels: Vec<_> = frame.into_iter().collect();
+        channels.sort_unstable_by_key(|(channel, _)| *channel);
+
+        write!(out, "{}:", i).unwrap();
+        for (channel, (r, g, b)) in channels {
+            write!(out, " {}=#{:02x}{:02x}{:02x}", channel, r, g, b).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Compares `actual` with the snapshot at `path`, or writes it there when
+/// the `UPDATE_GOLDEN` environment variable is set.
+///
+/// # Panics
+///
+/// When the snapshot is missing or differs, listing the differing frames.
+pub fn assert_golden(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "Cannot read golden file {}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            e
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let mut report = String::new();
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    for i in 0..expected_lines.len().max(actual_lines.len()) {
+        let want = expected_lines.get(i).copied().unwrap_or("<missing>");
+        let got = actual_lines.get(i).copied().unwrap_or("<missing>");
+        if want != got {
+            writeln!(report, "  expected {}\n  actual   {}", want, got).unwrap();
+        }
+    }
+    panic!(
+        "Frames differ from {} (run with UPDATE_GOLDEN=1 to accept):\n{}",
+        path.display(),
+        report
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::{MultiBandEffect, PulseEffect};
+    use std::path::PathBuf;
+
+    fn golden(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.txt", name))
+    }
+
+    #[test]
+    fn test_pulse_golden() {
+        let mut effect = PulseEffect::new((255, 100, 50));
+        let frames = render(&mut effect, &standard_script(), &line_layout(3));
+        assert_golden(golden("pulse"), &frames);
+    }
+
+    #[test]
+    fn test_multiband_golden() {
+        let mut effect = MultiBandEffect::new();
+        let frames = render(&mut effect, &standard_script(), &line_layout(6));
+        assert_golden(golden("multiband_positioned"), &frames);
+
+        let mut effect = MultiBandEffect::new();
+        let frames = render(&mut effect, &standard_script(), &unpositioned_layout(4));
+        assert_golden(golden("multiband_unpositioned"), &frames);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::audio_interface::AudioSpectrum;
+use crate::color::{kelvin_to_rgb, scale};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use std::time::Duration;
+
+/// Color temperature after sunset when following the daylight.
+const NIGHT_KELVIN: f32 = 2200.0;
+/// Color temperature at midday when following the daylight.
+const DAY_KELVIN: f32 = 4000.0;
+/// Brightness at night relative to the day.
+const NIGHT_LEVEL: f32 = 0.5;
+
+/// Subtle warm-white pulsing, meant for relaxed listening rather than parties.
+///
+/// With a location configured it follows the sun: cool and bright during
+/// the day, warmer and dimmer at night, in place of `kelvin`.
+pub struct WarmPulseEffect {
+    pub kelvin: f32,
+    /// Brightness kept between beats (0.0 - 1.0), so lights never go fully dark.
+    pub min_brightness: f32,
+    daylight: Option<f32>,
+}
+
+impl WarmPulseEffect {
+    pub fn new(kelvin: f32, min_brightness: f32) -> Self {
+        Self {
+            kelvin,
+            min_brightness,
+            daylight: None,
+        }
+    }
+}
+
+impl Default for WarmPulseEffect {
+    fn default() -> Self {
+        Self::new(2700.0, 0.2)
+    }
+}
+
+impl LightEffect for WarmPulseEffect {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        let level = (audio.bass * audio.energy).clamp(0.0, 1.0);
+        let min = self.min_brightness.clamp(0.0, 1.0);
+        let (kelvin, daylight_level) = match self.daylight {
+            Some(d) => (
+                NIGHT_KELVIN + (DAY_KELVIN - NIGHT_KELVIN) * d,
+                NIGHT_LEVEL + (1.0 - NIGHT_LEVEL) * d,
+            ),
+            None => (self.kelvin, 1.0),
+        };
+        let color = scale(
+            kelvin_to_rgb(kelvin),
+            (min + (1.0 - min) * level) * daylight_level,
+        );
+
+        nodes.iter().map(|n| (n.channel_id, color)).collect()
+    }
+
+    /// Slow warm pulses should breathe, not flicker.
+    fn smoothing(&self) -> Option<Duration> {
+        Some(Duration::from_millis(120))
+    }
+
+    fn set_daylight(&mut self, daylight: f32) {
+        self.daylight = Some(daylight.clamp(0.0, 1.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::{line_layout, spectrum};
+
+    #[test]
+    fn test_night_is_warmer_and_dimmer() {
+        let nodes = line_layout(1);
+        let audio = spectrum(0.0, 0.0, 0.0, 0.0);
+        let mut effect = WarmPulseEffect::default();
+
+        effect.set_daylight(1.0);
+        let (dr, _, db) = effect.update(&audio, &nodes)[&0];
+        effect.set_daylight(0.0);
+        let (nr, _, nb) = effect.update(&audio, &nodes)[&0];
+        assert!(nr < dr, "night red {} vs day {}", nr, dr);
+        assert!((nb as f32 / nr as f32) < (db as f32 / dr as f32));
+    }
+}
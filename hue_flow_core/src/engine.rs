@@ -1,7 +1,9 @@
-use crate::audio_interface::AudioSpectrum;
-use crate::models::LightNode;
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
 use crate::effects::LightEffect;
+use crate::models::LightNode;
 use crate::stream::manager::LightState;
+use crate::timing;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 pub struct EntertainmentEngine {
@@ -38,12 +40,13 @@ impl EntertainmentEngine {
         loop {
             match self.audio_rx.recv().await {
                 Ok(audio) => {
-                    let updates_map = self.effect.update(&audio, &self.nodes);
-                    let mut updates_vec = Vec::new();
-                    for (id, (r, g, b)) in updates_map {
-                        updates_vec.push(LightState { id, r, g, b });
-                    }
-                    if let Err(_) = self.dtls_tx.send(updates_vec).await {
+                    let analysis = AnalysisFrame {
+                        time: timing::now(),
+                        ..audio.into()
+                    };
+                    let frame = self.effect.update_frame(&analysis, &self.nodes);
+                    let updates_vec = LightState::from_frame(frame, |_| Duration::ZERO);
+                    if self.dtls_tx.send(updates_vec).await.is_err() {
                         break; // Receiver closed
                     }
                 }
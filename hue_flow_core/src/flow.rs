@@ -0,0 +1,1236 @@
+//! High-level entry point for embedding the whole HueFlow pipeline.
+//!
+//! [`HueFlow`] ties together group lookup, stream activation, the DTLS
+//! session, takeover handling, the control channel and the render loop:
+//!
+//! ```no_run
+//! # async fn demo(config: hue_flow_core::models::HueConfig) -> anyhow::Result<()> {
+//! use hue_flow_core::audio_interface::SyntheticAudio;
+//! use hue_flow_core::effects::MultiBandEffect;
+//! use hue_flow_core::HueFlow;
+//!
+//! HueFlow::builder()
+//!     .bridge(config)
+//!     .effect(Box::new(MultiBandEffect::new()))
+//!     .audio_source(SyntheticAudio::default())
+//!     .build()?
+//!     .run()
+//!     .await
+//! # }
+//! ```
+use crate::analysis::{BeatDetector, DropDetector, SpectrumHistory, TrackChangeDetector};
+use crate::api::client::check_compatibility;
+use crate::api::error::HueError;
+use crate::api::groups::{get_entertainment_groups, GroupInfo};
+use crate::app_profiles::{self, AppRule};
+use crate::audio_interface::{AudioSource, AudioSpectrum, SyntheticAudio};
+use crate::control::overlay::{self, OverlayLight};
+use crate::control::ControlCommand;
+use crate::effects::{self, Frame, LightEffect, MultiBandEffect, PreciseFrame};
+use crate::game::{self, CueLayer, GameConfig};
+use crate::hooks::{Hook, HookEvent, HookRunner};
+use crate::models::{HueConfig, LightNode};
+use crate::output::ambient::{self, AmbientCompensation, AmbientConfig};
+use crate::output::blackout::Blackout;
+use crate::output::circadian::Circadian;
+use crate::output::color_pipeline::ColorPipeline;
+use crate::output::drop_boost::{DropBoost, DropBoostConfig};
+use crate::output::overrides::Overrides;
+use crate::output::rooms::RoomMap;
+use crate::output::safe_mode::SafeMode;
+use crate::output::smoothing::Smoother;
+use crate::output::theater::{TheaterConfig, TheaterMode};
+use crate::output::{LightSink, VIRTUAL_CHANNEL_BASE};
+use crate::playlist::{EffectPlaylist, PlaylistEntry, PlaylistPlayer};
+use crate::presence::{self, MotionConfig};
+use crate::preset::{self, Preset};
+use crate::show::ShowWriter;
+use crate::solar::{self, Location};
+use crate::stream::manager::{run_stream_loop_with_options, LightState, StreamOptions};
+use crate::stream::rate::{check_fps, StreamMetrics, MAX_FPS};
+use crate::stream::supervisor::{StreamState, StreamSupervisor};
+use crate::stream::takeover::spawn_takeover_watcher;
+use crate::tempo::TempoClock;
+use crate::timing::{self, Stage, StageTimings};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, watch};
+use tokio::time::interval;
+use tracing::{field, Instrument};
+
+/// Default interval between rendered effect frames (20 FPS).
+pub const DEFAULT_RENDER_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time between frames at `fps`.
+fn frame_interval(fps: u32) -> Duration {
+    Duration::from_secs_f64(1.0 / fps as f64)
+}
+
+/// Default poll interval of the takeover watcher.
+pub const DEFAULT_TAKEOVER_POLL: Duration = Duration::from_secs(2);
+
+/// Progress notifications from a running [`HueFlow`].
+pub enum FlowEvent<'a> {
+    /// The entertainment area was switched to streaming mode.
+    StreamActivated { group: &'a GroupInfo },
+    /// The DTLS session is up; frames are about to flow.
+    Connected,
+    /// The bridge stream moved on in its lifecycle, e.g. to
+    /// [`StreamState::Recovering`] while another application owns the area.
+    StreamState { state: StreamState },
+    /// A frame was rendered and handed to the stream.
+    Frame {
+        audio: &'a AudioSpectrum,
+        frame: &'a Frame,
+        metrics: &'a StreamMetrics,
+    },
+    /// A preset was loaded (or an effect set by name, as a preset of its
+    /// own) through the control channel.
+    PresetLoaded { name: &'a str, preset: &'a Preset },
+    /// Loading a preset or effect through the control channel failed.
+    PresetFailed { name: &'a str, error: String },
+    /// A blackout fade started.
+    Blackout,
+    /// A resume fade started.
+    Resumed,
+    /// Photosensitive-safe mode was switched through the control channel.
+    /// `replaced_strobe` is set when the running strobe effect was swapped
+    /// for the default effect.
+    SafeMode {
+        enabled: bool,
+        replaced_strobe: bool,
+    },
+    /// A drop hit after a build-up.
+    Drop,
+    /// Motion sensors saw nobody for a while; streaming pauses.
+    RoomEmpty,
+    /// Someone is back in the room; streaming resumes.
+    RoomOccupied,
+    /// Standby was switched through the control channel; the area is
+    /// released while it is on.
+    Standby { enabled: bool },
+    /// The target frame rate was changed through the control channel.
+    FpsChanged { fps: u32 },
+    /// The playlist moved on to another entry, on its own or by command.
+    PlaylistChanged {
+        index: usize,
+        entry: &'a PlaylistEntry,
+    },
+}
+
+type EventHandler = Box<dyn FnMut(FlowEvent<'_>) + Send>;
+
+/// Builder for [`HueFlow`]; see [`HueFlow::builder`].
+pub struct HueFlowBuilder {
+    config: Option<HueConfig>,
+    group: Option<GroupInfo>,
+    nodes: Option<Vec<LightNode>>,
+    sink: Option<Box<dyn LightSink>>,
+    mirrors: Vec<Box<dyn LightSink>>,
+    effect: Option<Box<dyn LightEffect>>,
+    playlist: Option<EffectPlaylist>,
+    preset_name: Option<String>,
+    location: Option<Location>,
+    audio: Option<Box<dyn AudioSource>>,
+    excluded_channels: HashSet<u8>,
+    smoothing: HashMap<u8, Duration>,
+    rooms: RoomMap,
+    color: ColorPipeline,
+    circadian: Option<Circadian>,
+    drop_boost: Option<DropBoostConfig>,
+    theater: Option<TheaterConfig>,
+    game: Option<GameConfig>,
+    ambient: Option<AmbientConfig>,
+    motion: Option<MotionConfig>,
+    app_profiles: Vec<AppRule>,
+    safe_mode: bool,
+    dedup_frames: bool,
+    jitter_buffer: Option<Duration>,
+    record_show: Option<PathBuf>,
+    render_interval: Duration,
+    fps: Option<u32>,
+    takeover_poll: Duration,
+    log_frames: Option<u64>,
+    debug_dtls: bool,
+    hooks: Vec<Hook>,
+    presets_dir: Option<PathBuf>,
+    on_event: Option<EventHandler>,
+}
+
+impl HueFlowBuilder {
+    /// Bridge connection and credentials (required unless a [`sink`](Self::sink) is set).
+    pub fn bridge(mut self, config: HueConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Entertainment group to stream to. When omitted, the group named by
+    /// `entertainment_group_id` in the bridge config is looked up on start.
+    pub fn group(mut self, group: GroupInfo) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Light layout for a [`sink`](Self::sink) when no group is given.
+    pub fn nodes(mut self, nodes: Vec<LightNode>) -> Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    /// Writes frames to `sink` instead of streaming to the bridge. No bridge
+    /// connection is made; the layout comes from the group or [`nodes`](Self::nodes).
+    pub fn sink(mut self, sink: impl LightSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Also writes every frame to `sink`, in step with the bridge (or the
+    /// main [`sink`](Self::sink)). Its [`virtual_nodes`](LightSink::virtual_nodes)
+    /// join the layout.
+    pub fn mirror(mut self, sink: impl LightSink + 'static) -> Self {
+        self.mirrors.push(Box::new(sink));
+        self
+    }
+
+    /// Effect rendered on start. Defaults to [`MultiBandEffect`].
+    pub fn effect(mut self, effect: Box<dyn LightEffect>) -> Self {
+        self.effect = Some(effect);
+        self
+    }
+
+    /// Rotates through the playlist's entries instead of rendering the
+    /// [`effect`](Self::effect). Loading a preset ends the playlist; the
+    /// `Playlist*` [`ControlCommand`]s skip and shuffle.
+    pub fn playlist(mut self, playlist: EffectPlaylist) -> Self {
+        self.playlist = Some(playlist);
+        self
+    }
+
+    /// Name of the preset the [`effect`](Self::effect) was built from,
+    /// loaded again by [`ControlCommand::Reload`].
+    pub fn preset_name(mut self, name: impl Into<String>) -> Self {
+        self.preset_name = Some(name.into());
+        self
+    }
+
+    /// Where the lights are; ambient effects then follow sunrise and sunset
+    /// (see [`LightEffect::set_daylight`]).
+    pub fn location(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Audio feeding the effect. Defaults to [`SyntheticAudio`].
+    pub fn audio_source(mut self, source: impl AudioSource + 'static) -> Self {
+        self.audio = Some(Box::new(source));
+        self
+    }
+
+    /// Channels left out of the layout and of every frame.
+    pub fn excluded_channels(mut self, channels: impl IntoIterator<Item = u8>) -> Self {
+        self.excluded_channels.extend(channels);
+        self
+    }
+
+    /// Per-channel transition hints: each new color fades in over the given
+    /// time, dithered across the intermediate stream frames. Useful for
+    /// bulbs that visibly step. Overrides the effect's own
+    /// [`smoothing`](LightEffect::smoothing) hint for these channels.
+    pub fn smoothing(mut self, channels: impl IntoIterator<Item = (u8, Duration)>) -> Self {
+        self.smoothing.extend(channels);
+        self
+    }
+
+    /// Room mode: each room's channels are rendered by the effect as one
+    /// light at their average position, and its color is copied to all of
+    /// them. Channels in no room stay individual lights.
+    pub fn rooms(mut self, rooms: impl IntoIterator<Item = (String, Vec<u8>)>) -> Self {
+        self.rooms = RoomMap::new(rooms);
+        self
+    }
+
+    /// Color correction applied to every frame (matrix, saturation, contrast).
+    pub fn color_pipeline(mut self, pipeline: ColorPipeline) -> Self {
+        self.color = pipeline;
+        self
+    }
+
+    /// Adapts every frame to the white point and brightness of a daily
+    /// curve, warmer and dimmer late at night.
+    pub fn circadian(mut self, circadian: Circadian) -> Self {
+        self.circadian = Some(circadian);
+        self
+    }
+
+    /// Boosts brightness (and optionally strobes) for a few seconds when a
+    /// drop hits after a build-up.
+    pub fn drop_boost(mut self, config: DropBoostConfig) -> Self {
+        self.drop_boost = Some(config);
+        self
+    }
+
+    /// Calm output for films: heavy smoothing, dimmed lights behind the
+    /// viewer and even less response while people talk.
+    pub fn theater(mut self, config: TheaterConfig) -> Self {
+        self.theater = Some(config);
+        self
+    }
+
+    /// Listens for game events over UDP and flashes their cues over the
+    /// effect.
+    pub fn game(mut self, config: GameConfig) -> Self {
+        self.game = Some(config);
+        self
+    }
+
+    /// Scales output brightness to the ambient light a sensor measures:
+    /// dimmer in a dark room, full in daylight.
+    pub fn ambient(mut self, config: AmbientConfig) -> Self {
+        self.ambient = Some(config);
+        self
+    }
+
+    /// Pauses streaming while motion sensors see nobody in the room, and
+    /// resumes once someone is back. Only applies when streaming to the bridge.
+    pub fn motion(mut self, config: MotionConfig) -> Self {
+        self.motion = Some(config);
+        self
+    }
+
+    /// Switches preset or effect by the application playing audio, by the
+    /// first matching rule; see [`app_profiles`].
+    pub fn app_profiles(mut self, rules: Vec<AppRule>) -> Self {
+        self.app_profiles = rules;
+        self
+    }
+
+    /// Logs the channel values of every `every`th frame (target
+    /// `hueflow::frames`) for postmortem analysis; 0 turns it off.
+    pub fn log_frames(mut self, every: u64) -> Self {
+        self.log_frames = (every > 0).then_some(every);
+        self
+    }
+
+    /// Logs each step of the DTLS handshake, the negotiated cipher and
+    /// the MTU (target `hueflow::dtls`), and why a handshake failed.
+    pub fn debug_dtls(mut self, enabled: bool) -> Self {
+        self.debug_dtls = enabled;
+        self
+    }
+
+    /// Photosensitive-safe output: flashing is kept below 3 Hz, luminance
+    /// changes are rate limited and strobe-class effects are refused.
+    /// Can be toggled later with [`ControlCommand::SafeMode`].
+    pub fn safe_mode(mut self, enabled: bool) -> Self {
+        self.safe_mode = enabled;
+        self
+    }
+
+    /// Skip frames identical to the previous one, re-sending it only as a
+    /// keep-alive, to save Wi-Fi traffic in static scenes. See
+    /// [`StreamOptions::dedup`](crate::stream::manager::StreamOptions::dedup).
+    pub fn dedup_frames(mut self, enabled: bool) -> Self {
+        self.dedup_frames = enabled;
+        self
+    }
+
+    /// Play frames out `delay` after their render tick, smoothing over
+    /// uneven effect computation at the cost of that much latency. See
+    /// [`JitterBuffer`](crate::stream::jitter::JitterBuffer).
+    pub fn jitter_buffer(mut self, delay: Duration) -> Self {
+        self.jitter_buffer = Some(delay);
+        self
+    }
+
+    /// Record the effect's frames as a show (`.hfl`) that a
+    /// [`ShowPlayer`](crate::show::ShowPlayer) can play back to timecode.
+    /// Frames are taken before color correction and the brightness stages,
+    /// which apply again on playback.
+    pub fn record_show(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_show = Some(path.into());
+        self
+    }
+
+    /// Interval between rendered effect frames.
+    pub fn render_interval(mut self, interval: Duration) -> Self {
+        self.render_interval = interval;
+        self
+    }
+
+    /// Target frame rate of both rendering and the stream (up to
+    /// [`MAX_FPS`]), instead of the default 20 FPS render interval with the
+    /// stream running at up to 50. Changed live by [`ControlCommand::SetFps`]
+    /// and by presets that set one.
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    /// How often to check whether another application took over the area.
+    pub fn takeover_poll(mut self, poll: Duration) -> Self {
+        self.takeover_poll = poll;
+        self
+    }
+
+    /// Shell commands and webhooks run on beats, track changes and when the
+    /// stream starts and stops.
+    pub fn hooks(mut self, hooks: impl IntoIterator<Item = Hook>) -> Self {
+        self.hooks.extend(hooks);
+        self
+    }
+
+    /// Directory presets are loaded from by [`ControlCommand::LoadPreset`].
+    /// Defaults to [`preset::presets_dir`].
+    pub fn presets_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.presets_dir = Some(dir.into());
+        self
+    }
+
+    /// Callback for progress notifications and rendered frames.
+    pub fn on_event(mut self, handler: impl FnMut(FlowEvent<'_>) + Send + 'static) -> Self {
+        self.on_event = Some(Box::new(handler));
+        self
+    }
+
+    pub fn build(self) -> Result<HueFlow, HueError> {
+        if self.config.is_none() && self.sink.is_none() {
+            return Err(HueError::Other("No bridge configured".to_string()));
+        }
+        if let Some(fps) = self.fps {
+            check_fps(fps).map_err(HueError::Other)?;
+        }
+        let (control_tx, control_rx) = mpsc::channel(8);
+        let (frames_tx, _) = watch::channel(Vec::new());
+        let (states_tx, _) = watch::channel(StreamState::Idle);
+        Ok(HueFlow {
+            config: self.config,
+            group: self.group,
+            nodes: self.nodes,
+            sink: self.sink,
+            mirrors: self.mirrors,
+            effect: self
+                .effect
+                .unwrap_or_else(|| Box::new(MultiBandEffect::new())),
+            playlist: self.playlist,
+            preset_name: self.preset_name,
+            location: self.location,
+            audio: self
+                .audio
+                .unwrap_or_else(|| Box::new(SyntheticAudio::default())),
+            excluded_channels: self.excluded_channels,
+            smoothing: self.smoothing,
+            rooms: self.rooms,
+            color: self.color,
+            circadian: self.circadian,
+            drop_boost: self.drop_boost,
+            theater: self.theater,
+            game: self.game,
+            ambient: self.ambient,
+            motion: self.motion,
+            app_profiles: self.app_profiles,
+            safe_mode: self.safe_mode,
+            dedup_frames: self.dedup_frames,
+            jitter_buffer: self.jitter_buffer,
+            record_show: self.record_show,
+            render_interval: self.render_interval,
+            fps: self.fps,
+            takeover_poll: self.takeover_poll,
+            log_frames: self.log_frames,
+            debug_dtls: self.debug_dtls,
+            hooks: self.hooks,
+            presets_dir: self.presets_dir.unwrap_or_else(preset::presets_dir),
+            on_event: self.on_event.unwrap_or_else(|| Box::new(|_| {})),
+            metrics: Arc::default(),
+            timings: Arc::default(),
+            control_tx,
+            control_rx,
+            frames_tx,
+            states_tx,
+        })
+    }
+}
+
+/// A configured streaming pipeline, ready to [`run`](HueFlow::run).
+pub struct HueFlow {
+    config: Option<HueConfig>,
+    group: Option<GroupInfo>,
+    nodes: Option<Vec<LightNode>>,
+    sink: Option<Box<dyn LightSink>>,
+    mirrors: Vec<Box<dyn LightSink>>,
+    effect: Box<dyn LightEffect>,
+    playlist: Option<EffectPlaylist>,
+    preset_name: Option<String>,
+    location: Option<Location>,
+    audio: Box<dyn AudioSource>,
+    excluded_channels: HashSet<u8>,
+    smoothing: HashMap<u8, Duration>,
+    rooms: RoomMap,
+    color: ColorPipeline,
+    circadian: Option<Circadian>,
+    drop_boost: Option<DropBoostConfig>,
+    theater: Option<TheaterConfig>,
+    game: Option<GameConfig>,
+    ambient: Option<AmbientConfig>,
+    motion: Option<MotionConfig>,
+    app_profiles: Vec<AppRule>,
+    safe_mode: bool,
+    dedup_frames: bool,
+    jitter_buffer: Option<Duration>,
+    record_show: Option<PathBuf>,
+    render_interval: Duration,
+    fps: Option<u32>,
+    takeover_poll: Duration,
+    log_frames: Option<u64>,
+    debug_dtls: bool,
+    hooks: Vec<Hook>,
+    presets_dir: PathBuf,
+    on_event: EventHandler,
+    metrics: Arc<StreamMetrics>,
+    timings: Arc<StageTimings>,
+    control_tx: mpsc::Sender<ControlCommand>,
+    control_rx: mpsc::Receiver<ControlCommand>,
+    frames_tx: watch::Sender<Vec<OverlayLight>>,
+    states_tx: watch::Sender<StreamState>,
+}
+
+impl HueFlow {
+    pub fn builder() -> HueFlowBuilder {
+        HueFlowBuilder {
+            config: None,
+            group: None,
+            nodes: None,
+            sink: None,
+            mirrors: Vec::new(),
+            effect: None,
+            playlist: None,
+            preset_name: None,
+            location: None,
+            audio: None,
+            excluded_channels: HashSet::new(),
+            smoothing: HashMap::new(),
+            rooms: RoomMap::default(),
+            color: ColorPipeline::default(),
+            circadian: None,
+            drop_boost: None,
+            theater: None,
+            game: None,
+            ambient: None,
+            motion: None,
+            app_profiles: Vec::new(),
+            safe_mode: false,
+            dedup_frames: false,
+            jitter_buffer: None,
+            record_show: None,
+            render_interval: DEFAULT_RENDER_INTERVAL,
+            fps: None,
+            takeover_poll: DEFAULT_TAKEOVER_POLL,
+            log_frames: None,
+            debug_dtls: false,
+            hooks: Vec::new(),
+            presets_dir: None,
+            on_event: None,
+        }
+    }
+
+    /// Sender for steering the running pipeline (presets, blackout, stop).
+    /// Hand clones to the control API, hotkeys or your own UI.
+    pub fn control(&self) -> mpsc::Sender<ControlCommand> {
+        self.control_tx.clone()
+    }
+
+    /// The lights of every rendered frame with their positions, e.g. for the
+    /// streamer [`overlay`](crate::control::overlay).
+    pub fn frames(&self) -> watch::Receiver<Vec<OverlayLight>> {
+        self.frames_tx.subscribe()
+    }
+
+    /// State of the bridge stream, see [`StreamSupervisor`]. Stays
+    /// [`StreamState::Idle`] for a custom sink.
+    pub fn stream_states(&self) -> watch::Receiver<StreamState> {
+        self.states_tx.subscribe()
+    }
+
+    /// Live stream counters (FPS, frames sent, write errors).
+    pub fn metrics(&self) -> Arc<StreamMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Recent per-stage latencies (audio, effect, encode, send).
+    pub fn timings(&self) -> Arc<StageTimings> {
+        self.timings.clone()
+    }
+
+    /// Streams until [`ControlCommand::Stop`] is received or the stream ends,
+    /// then hands the entertainment area back to the bridge.
+    pub async fn run(self) -> Result<()> {
+        let HueFlow {
+            config,
+            group,
+            nodes,
+            sink,
+            mut mirrors,
+            mut effect,
+            playlist,
+            mut preset_name,
+            location,
+            mut audio,
+            excluded_channels,
+            smoothing,
+            mut rooms,
+            color,
+            circadian,
+            drop_boost,
+            theater,
+            game,
+            ambient,
+            motion,
+            app_profiles,
+            safe_mode,
+            dedup_frames,
+            jitter_buffer,
+            record_show,
+            render_interval,
+            fps,
+            takeover_poll,
+            log_frames,
+            debug_dtls,
+            hooks,
+            presets_dir,
+            mut on_event,
+            metrics,
+            timings,
+            control_tx,
+            mut control_rx,
+            frames_tx,
+            states_tx,
+        } = self;
+        if !app_profiles.is_empty() {
+            app_profiles::spawn_app_watcher(app_profiles, control_tx.clone());
+        }
+        // Only external handles should keep the control channel open
+        drop(control_tx);
+
+        let mut playlist = playlist
+            .map(|p| PlaylistPlayer::new(p, Instant::now()))
+            .transpose()?;
+        if safe_mode {
+            if let Some(player) = &mut playlist {
+                if !player.set_skip_strobe(true, Instant::now()) {
+                    anyhow::bail!("Every playlist entry is a strobe effect, disabled in safe mode");
+                }
+            } else if effect.is_strobe() {
+                anyhow::bail!("Strobe effects are disabled in safe mode");
+            }
+        }
+        let mut playlist_index = playlist.as_ref().map(PlaylistPlayer::index);
+        let mut safe_mode = safe_mode.then(SafeMode::default);
+        let (fps_tx, fps_rx) = watch::channel(fps.unwrap_or(MAX_FPS));
+
+        let ambient_lux = ambient
+            .as_ref()
+            .map(|ambient| ambient::spawn_sensor(config.clone(), ambient));
+        let mut ambient = ambient.map(AmbientCompensation::new);
+        let mut presence = match (&config, &motion) {
+            (Some(config), Some(motion)) if sink.is_none() => {
+                Some(presence::spawn_presence_watcher(config.clone(), motion))
+            }
+            _ => None,
+        };
+        let (standby_tx, standby_rx) = watch::channel(false);
+        let mut states = states_tx.subscribe();
+
+        let (layout, mut output) = match sink {
+            Some(sink) => {
+                let layout = group
+                    .map(|g| g.lights)
+                    .or(nodes)
+                    .context("A sink needs a group or node layout")?;
+                (
+                    layout,
+                    Output::Sink {
+                        sink,
+                        smoother: Smoother::default(),
+                    },
+                )
+            }
+            None => {
+                let config = config.context("No bridge configured")?;
+                let options = StreamOptions {
+                    ownership: None,
+                    // Standby releases the area just like an empty room
+                    presence: Some(spawn_stream_gate(presence.clone(), standby_rx)),
+                    metrics: metrics.clone(),
+                    timings: timings.clone(),
+                    excluded_channels: excluded_channels.clone(),
+                    max_fps: Some(fps_rx),
+                    supervisor: None,
+                    dedup: dedup_frames,
+                    jitter_buffer,
+                };
+                connect_bridge(
+                    config,
+                    group,
+                    takeover_poll,
+                    debug_dtls,
+                    states_tx.clone(),
+                    options,
+                    &mut on_event,
+                )
+                .await?
+            }
+        };
+
+        // Excluded channels are not part of the layout effects see
+        let mut nodes: Vec<LightNode> = layout
+            .into_iter()
+            .filter(|l| !excluded_channels.contains(&l.channel_id))
+            .collect();
+        let mut virtual_channels = HashSet::new();
+        for mirror in &mut mirrors {
+            let first = VIRTUAL_CHANNEL_BASE.saturating_add(virtual_channels.len() as u8);
+            let placed = mirror.virtual_nodes(first);
+            virtual_channels.extend(placed.iter().map(|n| n.channel_id));
+            nodes.extend(placed);
+        }
+        // Effects render each room as one light on the next free channel
+        let first = VIRTUAL_CHANNEL_BASE.saturating_add(virtual_channels.len() as u8);
+        let effect_nodes = rooms.collapse(nodes.clone(), first);
+
+        let mut tick_interval = interval(fps.map_or(render_interval, frame_interval));
+        let mut recorder = match record_show {
+            Some(path) => {
+                let fps = 1.0 / tick_interval.period().as_secs_f32();
+                let writer = ShowWriter::create(&path, fps)
+                    .with_context(|| format!("Cannot record show to {}", path.display()))?;
+                Some((writer, Instant::now()))
+            }
+            None => None,
+        };
+        let mut blackout = Blackout::default();
+        let mut overrides = Overrides::default();
+        let mut beats = BeatDetector::default();
+        let mut tempo = TempoClock::default();
+        let mut tracks = TrackChangeDetector::default();
+        let mut drops = DropDetector::default();
+        let mut history = SpectrumHistory::default();
+        let mut drop_boost = drop_boost.map(DropBoost::new);
+        let mut theater = theater.map(TheaterMode::new);
+        let mut game_events =
+            match &game {
+                Some(game) => Some(game::spawn_listener(game).await.with_context(|| {
+                    format!("Cannot listen for game events on {}", game.listen)
+                })?),
+                None => None,
+            };
+        let mut cues = game.map(CueLayer::new);
+        let mut hooks = HookRunner::new(hooks);
+        hooks.fire(HookEvent::StreamStarted, 0.0, Instant::now());
+        let mut frame_number: u64 = 0;
+
+        'render: loop {
+            // The scheduled tick, however late this iteration runs
+            let rendered = tick_interval.tick().await.into_std();
+
+            while let Ok(command) = control_rx.try_recv() {
+                let command = match command {
+                    ControlCommand::Reload => match &preset_name {
+                        Some(name) => ControlCommand::LoadPreset { name: name.clone() },
+                        None => {
+                            tracing::warn!("No active preset to reload");
+                            continue;
+                        }
+                    },
+                    command => command,
+                };
+                let by_effect = matches!(command, ControlCommand::SetEffect { .. });
+                match command {
+                    ControlCommand::LoadPreset { name } | ControlCommand::SetEffect { name } => {
+                        let loaded = if by_effect {
+                            Ok(Preset {
+                                effect: name.clone(),
+                                ..Default::default()
+                            })
+                        } else {
+                            preset::load(&presets_dir, &name)
+                        };
+                        match loaded.and_then(|p| p.build_effect().map(|e| (p, e))) {
+                            Ok((_, new_effect))
+                                if safe_mode.is_some() && new_effect.is_strobe() =>
+                            {
+                                on_event(FlowEvent::PresetFailed {
+                                    name: &name,
+                                    error: "Strobe effects are disabled in safe mode".to_string(),
+                                })
+                            }
+                            Ok((loaded, new_effect)) => {
+                                effect = new_effect;
+                                playlist = None;
+                                preset_name = (!by_effect).then(|| name.clone());
+                                if let Some(fps) = loaded.fps.filter(|f| check_fps(*f).is_ok()) {
+                                    fps_tx.send_replace(fps);
+                                    tick_interval = interval(frame_interval(fps));
+                                }
+                                on_event(FlowEvent::PresetLoaded {
+                                    name: &name,
+                                    preset: &loaded,
+                                });
+                            }
+                            Err(e) => on_event(FlowEvent::PresetFailed {
+                                name: &name,
+                                error: e.to_string(),
+                            }),
+                        }
+                    }
+                    ControlCommand::Blackout { fade_ms } => {
+                        blackout.engage(Duration::from_millis(fade_ms));
+                        on_event(FlowEvent::Blackout);
+                    }
+                    ControlCommand::Resume { fade_ms } => {
+                        blackout.release(Duration::from_millis(fade_ms));
+                        on_event(FlowEvent::Resumed);
+                    }
+                    ControlCommand::SafeMode { enabled } => {
+                        let replaced_strobe = match &mut playlist {
+                            Some(player) => !player.set_skip_strobe(enabled, Instant::now()),
+                            None => enabled && effect.is_strobe(),
+                        };
+                        if replaced_strobe {
+                            effect = Box::new(MultiBandEffect::new());
+                            playlist = None;
+                        }
+                        if enabled != safe_mode.is_some() {
+                            safe_mode = enabled.then(SafeMode::default);
+                        }
+                        on_event(FlowEvent::SafeMode {
+                            enabled,
+                            replaced_strobe,
+                        });
+                    }
+                    ControlCommand::Override { channels, color } => {
+                        overrides.set(&channels, color);
+                    }
+                    ControlCommand::Release { channels } => overrides.release(&channels),
+                    ControlCommand::Standby { enabled } => {
+                        if standby_tx.send_replace(enabled) != enabled {
+                            on_event(FlowEvent::Standby { enabled });
+                        }
+                    }
+                    ControlCommand::PlaylistNext => {
+                        if let Some(player) = &mut playlist {
+                            player.next(Instant::now());
+                        }
+                    }
+                    ControlCommand::PlaylistPrev => {
+                        if let Some(player) = &mut playlist {
+                            player.prev(Instant::now());
+                        }
+                    }
+                    ControlCommand::PlaylistShuffle { enabled } => {
+                        if let Some(player) = &mut playlist {
+                            player.set_shuffle(enabled);
+                        }
+                    }
+                    ControlCommand::SetFps { fps } => match check_fps(fps) {
+                        Ok(fps) => {
+                            fps_tx.send_replace(fps);
+                            tick_interval = interval(frame_interval(fps));
+                            on_event(FlowEvent::FpsChanged { fps });
+                        }
+                        Err(e) => tracing::warn!("{}", e),
+                    },
+                    // Turned into LoadPreset above
+                    ControlCommand::Reload => {}
+                    ControlCommand::Stop => break 'render,
+                }
+            }
+
+            if states.has_changed().unwrap_or(false) {
+                let state = *states.borrow_and_update();
+                metrics.set_state(state);
+                on_event(FlowEvent::StreamState { state });
+            }
+
+            if let Some(presence) = &mut presence {
+                if presence.has_changed().unwrap_or(false) {
+                    if *presence.borrow_and_update() {
+                        on_event(FlowEvent::RoomOccupied);
+                    } else {
+                        on_event(FlowEvent::RoomEmpty);
+                    }
+                }
+                // Nothing to render for while nobody is watching
+                if !*presence.borrow() {
+                    continue;
+                }
+            }
+            if *standby_tx.borrow() {
+                continue;
+            }
+
+            frame_number += 1;
+            let span = tracing::debug_span!(
+                "render_frame",
+                frame = frame_number,
+                audio_us = field::Empty,
+                effect_us = field::Empty
+            );
+
+            let started = Instant::now();
+            let mut analysis = span.in_scope(|| audio.next_frame());
+            analysis.beat = beats.update(&analysis.spectrum, started);
+            analysis.tempo = tempo.update(analysis.beat.onset, started);
+            if analysis.beat.onset {
+                hooks.fire(HookEvent::Beat, analysis.tempo.bpm, started);
+            }
+            if tracks.update(&analysis.spectrum, started) {
+                hooks.fire(HookEvent::TrackChange, analysis.tempo.bpm, started);
+                if let Some(player) = &mut playlist {
+                    player.track_changed(started);
+                }
+            }
+            analysis.drop = drops.update(&analysis.spectrum, started);
+            history.push(analysis.spectrum, started);
+            analysis.history = history.clone();
+            analysis.time = timing::now();
+            if analysis.drop {
+                if let Some(boost) = &mut drop_boost {
+                    boost.trigger(started);
+                }
+                hooks.fire(HookEvent::Drop, analysis.tempo.bpm, started);
+                on_event(FlowEvent::Drop);
+            }
+            let audio_time = timings.record(Stage::Audio, started);
+            span.record("audio_us", audio_time.as_micros() as u64);
+
+            let active: &mut dyn LightEffect = match &mut playlist {
+                Some(player) => player,
+                None => effect.as_mut(),
+            };
+            if let Some(location) = location {
+                active.set_daylight(solar::daylight(location, SystemTime::now()));
+            }
+            let started = Instant::now();
+            let mut colors = span.in_scope(|| active.update_frame(&analysis, &effect_nodes));
+            let effect_time = timings.record(Stage::Effect, started);
+            span.record("effect_us", effect_time.as_micros() as u64);
+            rooms.expand(&mut colors);
+            // Before the output stages, which play back applies again
+            if let Some((writer, since)) = &mut recorder {
+                let mut recorded = colors.clone();
+                recorded.retain(|channel, _| !virtual_channels.contains(channel));
+                if let Err(e) =
+                    writer.write_at(&recorded, rendered.saturating_duration_since(*since))
+                {
+                    tracing::warn!("Show recording stopped: {}", e);
+                    recorder = None;
+                }
+            }
+
+            // Unrounded from here on, so dim and corrected colors keep the
+            // levels between 8-bit steps for the 16-bit stream
+            let mut colors = effects::precise(&colors);
+            color.apply(&mut colors);
+            if let Some(boost) = &drop_boost {
+                boost.apply(&mut colors, safe_mode.is_none());
+            }
+            if let Some(theater) = &mut theater {
+                theater.apply(&mut colors, &nodes, &analysis, started);
+            }
+            if let (Some(cues), Some(events)) = (&mut cues, &mut game_events) {
+                while let Ok(event) = events.try_recv() {
+                    cues.trigger(&event, started);
+                }
+                cues.apply(&mut colors, started);
+            }
+            if let (Some(ambient), Some(lux)) = (&mut ambient, &ambient_lux) {
+                ambient.apply(&mut colors, *lux.borrow(), started);
+            }
+            if let Some(circadian) = &circadian {
+                circadian.apply(&mut colors, SystemTime::now());
+            }
+            // Claimed channels show their fixed color, but still black out
+            overrides.apply(&mut colors);
+            blackout.apply(&mut colors);
+            // Last, so that blackout fades are limited as well
+            if let Some(safe_mode) = &mut safe_mode {
+                safe_mode.apply(&mut colors);
+            }
+            let frame = effects::rounded(&colors);
+            if !frames_tx.is_closed() {
+                frames_tx.send_replace(overlay::snapshot(&nodes, &frame));
+            }
+            if log_frames.is_some_and(|every| frame_number.is_multiple_of(every)) {
+                let mut channels: Vec<_> = frame.iter().collect();
+                channels.sort();
+                tracing::info!(
+                    target: "hueflow::frames",
+                    frame = frame_number,
+                    bass = analysis.spectrum.bass,
+                    channels = ?channels,
+                    "Frame"
+                );
+            }
+            on_event(FlowEvent::Frame {
+                audio: &analysis.spectrum,
+                frame: &frame,
+                metrics: &metrics,
+            });
+
+            for mirror in &mut mirrors {
+                if let Err(e) = mirror.write_frame(&frame) {
+                    tracing::warn!("Mirror write failed: {}", e);
+                }
+            }
+            colors.retain(|channel, _| !virtual_channels.contains(channel));
+
+            let effect_hint = active.smoothing().unwrap_or_default();
+            if let Some(player) = &playlist {
+                if playlist_index != Some(player.index()) {
+                    playlist_index = Some(player.index());
+                    on_event(FlowEvent::PlaylistChanged {
+                        index: player.index(),
+                        entry: player.entry(),
+                    });
+                }
+            }
+            let transition = |channel: u8| smoothing.get(&channel).copied().unwrap_or(effect_hint);
+            if !output
+                .send(colors, rendered, &transition, &metrics, &timings)
+                .instrument(span)
+                .await
+            {
+                break;
+            }
+        }
+
+        if let Output::Bridge { supervisor, .. } = output {
+            supervisor.stop().await.ok();
+        }
+        hooks.fire(HookEvent::StreamStopped, 0.0, Instant::now());
+        hooks.finish().await;
+
+        Ok(())
+    }
+}
+
+/// Where rendered frames go.
+enum Output {
+    /// The DTLS streaming task, see [`run_stream_loop_with_options`].
+    Bridge {
+        /// Deactivates streaming, also when `run` exits early or panics.
+        supervisor: Arc<StreamSupervisor>,
+        frames: mpsc::Sender<Vec<LightState>>,
+    },
+    /// Smoothed here, as the bridge stream loop would do.
+    Sink {
+        sink: Box<dyn LightSink>,
+        smoother: Smoother,
+    },
+}
+
+impl Output {
+    /// Hands a frame on; returns false once the output has shut down.
+    async fn send(
+        &mut self,
+        frame: PreciseFrame,
+        rendered: Instant,
+        transition: &dyn Fn(u8) -> Duration,
+        metrics: &StreamMetrics,
+        timings: &StageTimings,
+    ) -> bool {
+        match self {
+            Output::Bridge { frames, .. } => {
+                // NOTE: id is the channel_id, not the light id
+                let states = LightState::from_frame_at(frame, transition, rendered);
+                frames.send(states).await.is_ok()
+            }
+            Output::Sink { sink, smoother } => {
+                let started = Instant::now();
+                for (channel_id, color) in frame {
+                    smoother.set_target(channel_id, color, transition(channel_id), started);
+                }
+                let result = sink.write_frame(&smoother.frame(started));
+                timings.record(Stage::Send, started);
+                match result {
+                    Ok(()) => metrics.record_sent(),
+                    Err(e) => {
+                        metrics.record_error();
+                        eprintln!("⚠️  Sink write failed: {}", e);
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Whether to stream to the area: someone is in the room (when motion is
+/// watched) and the flow is not on standby.
+fn spawn_stream_gate(
+    mut presence: Option<watch::Receiver<bool>>,
+    mut standby: watch::Receiver<bool>,
+) -> watch::Receiver<bool> {
+    let open = |presence: &Option<watch::Receiver<bool>>, standby: &watch::Receiver<bool>| {
+        presence.as_ref().is_none_or(|p| *p.borrow()) && !*standby.borrow()
+    };
+    let (tx, rx) = watch::channel(open(&presence, &standby));
+    tokio::spawn(async move {
+        loop {
+            let changed = match &mut presence {
+                Some(p) => tokio::select! {
+                    changed = p.changed() => changed,
+                    changed = standby.changed() => changed,
+                },
+                None => standby.changed().await,
+            };
+            if changed.is_err() || tx.send(open(&presence, &standby)).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Activates streaming on the group and spawns the DTLS streaming task with
+/// `options` plus a takeover watcher. Returns the group's layout and the
+/// output feeding the task.
+async fn connect_bridge(
+    config: HueConfig,
+    group: Option<GroupInfo>,
+    takeover_poll: Duration,
+    debug_dtls: bool,
+    states: watch::Sender<StreamState>,
+    mut options: StreamOptions,
+    on_event: &mut EventHandler,
+) -> Result<(Vec<LightNode>, Output)> {
+    // Configs written before the firmware check was added have no version stored
+    if !config.swversion.is_empty() {
+        check_compatibility(&config.bridge_model, &config.swversion)?;
+    }
+    let credentials = config.credentials()?;
+
+    let group = match group {
+        Some(group) => group,
+        None => get_entertainment_groups(&config)
+            .await?
+            .into_iter()
+            .find(|g| g.id == config.entertainment_group_id)
+            .context("Configured entertainment group not found")?,
+    };
+
+    let supervisor = Arc::new(
+        StreamSupervisor::new(config.clone(), group.id.clone())
+            .debug_dtls(debug_dtls)
+            .states(states),
+    );
+    supervisor.activate().await?;
+    on_event(FlowEvent::StreamActivated { group: &group });
+
+    // Use application_id as PSK Identity (NOT username!)
+    let streamer = supervisor.handshake(&credentials)?;
+    on_event(FlowEvent::Connected);
+    options.supervisor = Some(supervisor.clone());
+
+    // Pause politely while another application (e.g. Hue Sync) owns the area
+    options.ownership = Some(spawn_takeover_watcher(
+        config.clone(),
+        group.id.clone(),
+        takeover_poll,
+        options.presence.clone(),
+    ));
+
+    let (tx, rx) = mpsc::channel::<Vec<LightState>>(16);
+    let stream_area_id = group.id.clone();
+    let _stream_handle = tokio::task::spawn_blocking(move || {
+        let rt = tokio::runtime::Handle::current();
+        rt.block_on(run_stream_loop_with_options(
+            streamer,
+            rx,
+            &stream_area_id,
+            options,
+        ));
+    });
+
+    let output = Output::Bridge {
+        supervisor,
+        frames: tx,
+    };
+    Ok((group.lights, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_interface::AudioSpectrum;
+    use crate::effects::testing::line_layout;
+    use crate::show::{Show, ShowPlayer, TimecodeClock};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    struct Constant((u8, u8, u8));
+
+    impl LightEffect for Constant {
+        fn update(&mut self, _audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+            nodes.iter().map(|n| (n.channel_id, self.0)).collect()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<Vec<Frame>>>);
+
+    impl LightSink for Collect {
+        fn write_frame(&mut self, frame: &Frame) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(frame.clone());
+            Ok(())
+        }
+    }
+
+    /// Runs `effect` through a saturation boost until a few frames are out.
+    async fn render(effect: Box<dyn LightEffect>, record: Option<&Path>) -> Frame {
+        let sink = Collect::default();
+        let mut builder = HueFlow::builder()
+            .nodes(line_layout(2))
+            .sink(sink.clone())
+            .effect(effect)
+            .color_pipeline(ColorPipeline {
+                saturation: 1.5,
+                ..Default::default()
+            })
+            .render_interval(Duration::from_millis(5));
+        if let Some(path) = record {
+            builder = builder.record_show(path);
+        }
+        let flow = builder.build().unwrap();
+        let control = flow.control();
+        let stop = async {
+            while sink.0.lock().unwrap().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            control.send(ControlCommand::Stop).await.unwrap();
+        };
+        let (ran, ()) = tokio::join!(flow.run(), stop);
+        ran.unwrap();
+        let frames = sink.0.lock().unwrap();
+        frames.last().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_recorded_show_plays_back_unchanged() {
+        let path = std::env::temp_dir().join(format!("hueflow-flow-{}.hfl", std::process::id()));
+        let live = render(Box::new(Constant((200, 100, 50))), Some(&path)).await;
+        let show = Show::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(show.frames[0][&0], (200, 100, 50));
+
+        // A clock that stays at the first frame
+        let clock = TimecodeClock::default();
+        clock.set(Duration::ZERO, timing::now() + Duration::from_secs(3600));
+        let player = ShowPlayer::new(show, clock, Duration::ZERO);
+        let played = render(Box::new(player), None).await;
+        assert_eq!(played, live);
+        assert_ne!(live[&0], (200, 100, 50));
+    }
+}
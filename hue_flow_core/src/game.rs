@@ -0,0 +1,247 @@
+//! Game integration: game mods and other apps send small JSON events over
+//! UDP, e.g. `{"event": "explosion", "intensity": 0.8}`, which flash light
+//! cues over the audio effect.
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default listen address for game events (localhost only).
+pub const DEFAULT_GAME_ADDR: &str = "127.0.0.1:7421";
+/// Largest datagram read; events are far smaller.
+#[cfg(feature = "bridge")]
+const MAX_DATAGRAM: usize = 2048;
+
+/// An event sent by a game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameEvent {
+    /// Event name, looked up in the configured cues.
+    pub event: String,
+    /// Strength from 0.0 to 1.0; scales how far the cue covers the effect.
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl GameEvent {
+    /// Parses one datagram; `None` for anything that is not an event.
+    pub fn parse(datagram: &[u8]) -> Option<Self> {
+        serde_json::from_slice(datagram).ok()
+    }
+}
+
+/// The light response to an event: a flash of `color` fading out over
+/// `duration_ms`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameCue {
+    pub color: (u8, u8, u8),
+    #[serde(default = "default_duration_ms")]
+    pub duration_ms: u64,
+    /// Channels the cue covers; all lights when empty.
+    #[serde(default)]
+    pub channels: Vec<u8>,
+}
+
+fn default_duration_ms() -> u64 {
+    600
+}
+
+/// Where game events come from and how they light the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// UDP address to listen on.
+    pub listen: String,
+    /// Cues by event name, in addition to (or replacing) the built-in
+    /// `explosion`, `damage`, `heal` and `pickup`.
+    pub cues: HashMap<String, GameCue>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            listen: DEFAULT_GAME_ADDR.to_string(),
+            cues: HashMap::new(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// The cue for `event`: configured, else built in.
+    pub fn cue(&self, event: &str) -> Option<GameCue> {
+        if let Some(cue) = self.cues.get(event) {
+            return Some(cue.clone());
+        }
+        let (color, duration_ms) = match event {
+            "explosion" => ((255, 140, 20), 900),
+            "damage" => ((255, 0, 0), 400),
+            "heal" => ((0, 255, 80), 800),
+            "pickup" => ((255, 220, 0), 250),
+            _ => return None,
+        };
+        Some(GameCue {
+            color,
+            duration_ms,
+            channels: Vec::new(),
+        })
+    }
+}
+
+/// A cue that was triggered.
+#[derive(Debug, Clone)]
+struct ActiveCue {
+    cue: GameCue,
+    intensity: f32,
+    started: Instant,
+}
+
+impl ActiveCue {
+    /// Blend toward the cue color at `now`, fading out linearly.
+    fn amount(&self, now: Instant) -> f32 {
+        let duration = Duration::from_millis(self.cue.duration_ms.max(1));
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed >= duration {
+            return 0.0;
+        }
+        self.intensity * (1.0 - elapsed.as_secs_f32() / duration.as_secs_f32())
+    }
+}
+
+/// Output stage layering game cues over the effect's frame.
+#[derive(Debug, Clone)]
+pub struct CueLayer {
+    config: GameConfig,
+    active: Vec<ActiveCue>,
+}
+
+impl CueLayer {
+    pub fn new(config: GameConfig) -> Self {
+        Self {
+            config,
+            active: Vec::new(),
+        }
+    }
+
+    /// Starts the cue for `event`; unknown events are ignored. Returns
+    /// whether a cue started.
+    pub fn trigger(&mut self, event: &GameEvent, now: Instant) -> bool {
+        let Some(cue) = self.config.cue(&event.event) else {
+            tracing::debug!("Game: no cue for event '{}'", event.event);
+            return false;
+        };
+        self.active.push(ActiveCue {
+            cue,
+            intensity: event.intensity.clamp(0.0, 1.0),
+            started: now,
+        });
+        true
+    }
+
+    /// Blends the running cues into `frame`, later cues on top.
+    pub fn apply<C: Rgb>(&mut self, frame: &mut HashMap<u8, C>, now: Instant) {
+        self.active.retain(|active| active.amount(now) > 0.0);
+        for active in &self.active {
+            let amount = active.amount(now).clamp(0.0, 1.0);
+            let cue = active.cue.color.to_rgb();
+            for (channel, color) in frame.iter_mut() {
+                if active.cue.channels.is_empty() || active.cue.channels.contains(channel) {
+                    let from = color.to_rgb();
+                    *color = C::from_rgb(std::array::from_fn(|i| {
+                        from[i] + (cue[i] - from[i]) * amount
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// Starts listening for game events on `config.listen` in the background.
+/// Datagrams that are not events are dropped, as are events the render
+/// loop has no room for.
+#[cfg(feature = "bridge")]
+pub async fn spawn_listener(
+    config: &GameConfig,
+) -> std::io::Result<tokio::sync::mpsc::Receiver<GameEvent>> {
+    let socket = tokio::net::UdpSocket::bind(&config.listen).await?;
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut buffer = [0u8; MAX_DATAGRAM];
+        while !tx.is_closed() {
+            let (len, from) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    tracing::debug!("Game: receive failed: {}", e);
+                    continue;
+                }
+            };
+            match GameEvent::parse(&buffer[..len]) {
+                Some(event) => {
+                    let _ = tx.try_send(event);
+                }
+                None => tracing::debug!("Game: ignoring datagram from {}", from),
+            }
+        }
+    });
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        let event = GameEvent::parse(br#"{"event":"explosion","intensity":0.8}"#).unwrap();
+        assert_eq!(event.event, "explosion");
+        assert_eq!(event.intensity, 0.8);
+        assert_eq!(
+            GameEvent::parse(br#"{"event":"heal"}"#).unwrap().intensity,
+            1.0
+        );
+        assert_eq!(GameEvent::parse(b"hello"), None);
+    }
+
+    #[test]
+    fn test_cue_fades_over_frame() {
+        let mut config = GameConfig::default();
+        config.cues.insert(
+            "hit".to_string(),
+            GameCue {
+                color: (255, 0, 0),
+                duration_ms: 1000,
+                channels: vec![1],
+            },
+        );
+        let mut layer = CueLayer::new(config);
+        let start = Instant::now();
+        let hit = GameEvent {
+            event: "hit".to_string(),
+            intensity: 1.0,
+        };
+        assert!(layer.trigger(&hit, start));
+        assert!(!layer.trigger(
+            &GameEvent {
+                event: "unknown".to_string(),
+                intensity: 1.0,
+            },
+            start
+        ));
+
+        let mut frame = HashMap::from([(0, (0, 0, 255)), (1, (0, 0, 255))]);
+        layer.apply(&mut frame, start);
+        assert_eq!(frame[&0], (0, 0, 255));
+        assert_eq!(frame[&1], (255, 0, 0));
+
+        let mut frame = HashMap::from([(1, (0, 0, 255))]);
+        layer.apply(&mut frame, start + Duration::from_millis(500));
+        assert_eq!(frame[&1], (128, 0, 128));
+
+        let mut frame = HashMap::from([(1, (0, 0, 255))]);
+        layer.apply(&mut frame, start + Duration::from_secs(1));
+        assert_eq!(frame[&1], (0, 0, 255));
+        assert!(layer.active.is_empty());
+    }
+}
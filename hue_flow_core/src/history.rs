@@ -0,0 +1,216 @@
+//! Session history: a summary of every stream, kept next to the config so
+//! performance can be reviewed afterwards ("it was laggy last night").
+//!
+//! Sessions are stored one JSON object per line, oldest first.
+use crate::config::{config_dir, write_atomic};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// File name of the history in the config directory.
+pub const HISTORY_FILE: &str = "sessions.jsonl";
+/// Sessions kept; older ones are dropped when a new one is recorded.
+const MAX_SESSIONS: usize = 1000;
+
+/// What happened during one stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Start, in seconds since the Unix epoch.
+    pub started: u64,
+    pub duration_secs: u64,
+    pub frames_sent: u64,
+    pub average_fps: f32,
+    pub write_errors: u64,
+    /// Effects shown, in order of first use.
+    pub effects: Vec<String>,
+    /// Why the stream ended, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SessionSummary {
+    /// Start as `YYYY-MM-DD HH:MM` in UTC.
+    pub fn started_utc(&self) -> String {
+        let days = (self.started / 86_400) as i64;
+        let seconds = self.started % 86_400;
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds % 3600 / 60
+        )
+    }
+}
+
+/// Calendar date of a day count since 1970-01-01 (after Howard Hinnant's
+/// `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Collects a session's summary while it streams.
+#[derive(Debug, Clone)]
+pub struct SessionRecorder {
+    started: SystemTime,
+    clock: Instant,
+    effects: Vec<String>,
+}
+
+impl SessionRecorder {
+    /// Starts a session showing `effect`.
+    pub fn new(effect: impl Into<String>) -> Self {
+        Self {
+            started: SystemTime::now(),
+            clock: Instant::now(),
+            effects: vec![effect.into()],
+        }
+    }
+
+    /// Notes a switch to `effect`.
+    pub fn effect(&mut self, effect: &str) {
+        if !self.effects.iter().any(|e| e == effect) {
+            self.effects.push(effect.to_string());
+        }
+    }
+
+    /// The summary of the session so far, with the stream's counters.
+    pub fn finish(
+        &self,
+        frames_sent: u64,
+        write_errors: u64,
+        error: Option<String>,
+    ) -> SessionSummary {
+        let elapsed = self.clock.elapsed().as_secs_f32();
+        SessionSummary {
+            started: self
+                .started
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            duration_secs: elapsed as u64,
+            frames_sent,
+            average_fps: if elapsed > 0.0 {
+                frames_sent as f32 / elapsed
+            } else {
+                0.0
+            },
+            write_errors,
+            effects: self.effects.clone(),
+            error,
+        }
+    }
+}
+
+/// Default history file: `<config dir>/sessions.jsonl`.
+pub fn history_path() -> PathBuf {
+    config_dir().join(HISTORY_FILE)
+}
+
+/// Appends `session` to the history at `path`, dropping the oldest sessions
+/// beyond the limit.
+pub fn record(path: &Path, session: &SessionSummary) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let line = serde_json::to_string(session)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    drop(file);
+
+    let content = fs::read_to_string(path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() > MAX_SESSIONS {
+        let kept = lines[lines.len() - MAX_SESSIONS..].join("\n");
+        write_atomic(path, (kept + "\n").as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Loads the history at `path`, oldest first; empty when there is none.
+/// Lines that do not parse are skipped.
+pub fn load(path: &Path) -> io::Result<Vec<SessionSummary>> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                tracing::warn!("Skipping a session in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(started: u64) -> SessionSummary {
+        SessionSummary {
+            started,
+            duration_secs: 60,
+            frames_sent: 3000,
+            average_fps: 50.0,
+            write_errors: 0,
+            effects: vec!["multiband".to_string()],
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let dir = std::env::temp_dir().join(format!("hueflow-history-{}", std::process::id()));
+        let path = dir.join(HISTORY_FILE);
+        assert!(load(&path).unwrap().is_empty());
+
+        for started in 0..3 {
+            record(&path, &session(started)).unwrap();
+        }
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "not a session\n",
+        )
+        .unwrap();
+        let sessions = load(&path).unwrap();
+        assert_eq!(sessions, vec![session(0), session(1), session(2)]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = SessionRecorder::new("pulse");
+        recorder.effect("storm");
+        recorder.effect("pulse");
+        let summary = recorder.finish(10, 2, Some("bridge gone".to_string()));
+        assert_eq!(summary.effects, ["pulse", "storm"]);
+        assert_eq!(summary.write_errors, 2);
+        assert_eq!(summary.error.as_deref(), Some("bridge gone"));
+    }
+
+    #[test]
+    fn test_started_utc() {
+        assert_eq!(session(0).started_utc(), "1970-01-01 00:00");
+        // 2024-02-29 is a leap day
+        assert_eq!(session(1_709_217_000).started_utc(), "2024-02-29 14:30");
+    }
+}
@@ -0,0 +1,183 @@
+//! User hooks: shell commands or webhooks run on stream events, e.g. to
+//! trigger a fog machine on beats or start a camera with the stream.
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "bridge")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "bridge")]
+use tokio::task::JoinSet;
+
+/// What a [`Hook`] reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// A beat starts.
+    Beat,
+    /// A drop hit after a build-up.
+    Drop,
+    /// Sound returned after a gap between tracks.
+    TrackChange,
+    /// The stream is up and frames are flowing.
+    StreamStarted,
+    /// The stream ended; hooks get a few seconds before the process exits.
+    StreamStopped,
+}
+
+impl HookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::Beat => "beat",
+            HookEvent::Drop => "drop",
+            HookEvent::TrackChange => "track_change",
+            HookEvent::StreamStarted => "stream_started",
+            HookEvent::StreamStopped => "stream_stopped",
+        }
+    }
+}
+
+/// A command and/or webhook run on an event.
+///
+/// Commands run through the shell (`sh -c`, `cmd /C` on Windows) with
+/// `HUEFLOW_EVENT` and `HUEFLOW_BPM` set. Webhooks get a `POST` with
+/// `{"event": ..., "bpm": ...}`. Neither holds up the stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Events closer together than this are skipped, e.g. to give a fog
+    /// machine time to recover between beats.
+    #[serde(default)]
+    pub min_interval_ms: u64,
+}
+
+/// How long [`HookRunner::finish`] waits for hooks still running.
+#[cfg(feature = "bridge")]
+const FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fires the configured hooks in the background.
+#[cfg(feature = "bridge")]
+pub struct HookRunner {
+    hooks: Vec<(Hook, Option<Instant>)>,
+    running: JoinSet<()>,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "bridge")]
+impl HookRunner {
+    pub fn new(hooks: Vec<Hook>) -> Self {
+        Self {
+            hooks: hooks.into_iter().map(|hook| (hook, None)).collect(),
+            running: JoinSet::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Starts the hooks for `event` that are not cooling down.
+    pub fn fire(&mut self, event: HookEvent, bpm: f32, now: Instant) {
+        // Reap hooks that are done
+        while self.running.try_join_next().is_some() {}
+        for (hook, last) in &mut self.hooks {
+            let cooling = last.is_some_and(|at| {
+                now.duration_since(at) < Duration::from_millis(hook.min_interval_ms)
+            });
+            if hook.event != event || cooling {
+                continue;
+            }
+            *last = Some(now);
+            if let Some(command) = hook.command.clone() {
+                self.running.spawn(run_command(command, event, bpm));
+            }
+            if let Some(url) = hook.url.clone() {
+                self.running
+                    .spawn(post_webhook(self.client.clone(), url, event, bpm));
+            }
+        }
+    }
+
+    /// Waits briefly for hooks still running, so those fired as the
+    /// stream stops are not cut off when the process exits.
+    pub async fn finish(mut self) {
+        let all_done = async { while self.running.join_next().await.is_some() {} };
+        if tokio::time::timeout(FINISH_TIMEOUT, all_done)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Hooks still running after {:?}", FINISH_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(feature = "bridge")]
+async fn run_command(command: String, event: HookEvent, bpm: f32) {
+    let mut shell = if cfg!(windows) {
+        let mut shell = tokio::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(&command)
+        .env("HUEFLOW_EVENT", event.as_str())
+        .env("HUEFLOW_BPM", format!("{:.1}", bpm))
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!("Hook '{}' exited with {}", command, status),
+        Err(e) => tracing::warn!("Hook '{}' failed to start: {}", command, e),
+    }
+}
+
+#[cfg(feature = "bridge")]
+async fn post_webhook(client: reqwest::Client, url: String, event: HookEvent, bpm: f32) {
+    let body = serde_json::json!({ "event": event, "bpm": bpm });
+    let result = client
+        .post(&url)
+        .json(&body)
+        .timeout(FINISH_TIMEOUT)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status);
+    if let Err(e) = result {
+        tracing::warn!("Webhook {} failed: {}", url, e);
+    }
+}
+
+#[cfg(all(test, feature = "bridge"))]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_webhooks_respect_min_interval() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/beat"))
+            .and(body_json(
+                serde_json::json!({ "event": "beat", "bpm": 120.0 }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let mut hooks = HookRunner::new(vec![Hook {
+            event: HookEvent::Beat,
+            command: None,
+            url: Some(format!("{}/beat", server.uri())),
+            min_interval_ms: 1000,
+        }]);
+        let start = Instant::now();
+        hooks.fire(HookEvent::Beat, 120.0, start);
+        hooks.fire(HookEvent::Beat, 120.0, start + Duration::from_millis(500));
+        hooks.fire(HookEvent::Beat, 120.0, start + Duration::from_millis(1000));
+        hooks.fire(HookEvent::StreamStarted, 120.0, start);
+        hooks.finish().await;
+    }
+}
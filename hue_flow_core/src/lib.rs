@@ -1,6 +1,34 @@
-pub mod audio_interface;
+pub mod analysis;
+#[cfg(feature = "bridge")]
 pub mod api;
-pub mod models;
-pub mod stream;
+pub mod audio_input;
+pub mod audio_interface;
+pub mod color;
+pub mod config;
+pub mod control;
 pub mod effects;
+#[cfg(feature = "bridge")]
 pub mod engine;
+#[cfg(feature = "bridge")]
+pub mod flow;
+pub mod game;
+pub mod hooks;
+pub mod models;
+pub mod output;
+pub mod playlist;
+pub mod preset;
+pub mod solar;
+#[cfg(feature = "bridge")]
+pub mod stream;
+pub mod tempo;
+pub mod timing;
+
+#[cfg(feature = "bridge")]
+pub use flow::{FlowEvent, HueFlow, HueFlowBuilder};
+pub mod app_profiles;
+pub mod credentials;
+pub mod history;
+pub mod presence;
+pub mod show;
+pub mod simd;
+pub mod tuning;
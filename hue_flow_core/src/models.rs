@@ -1,12 +1,226 @@
+use crate::app_profiles::AppRule;
+use crate::control::auth::ControlAuth;
+use crate::credentials::{Credentials, CredentialsError};
+use crate::game::GameConfig;
+use crate::hooks::Hook;
+use crate::output::ambient::AmbientConfig;
+use crate::output::circadian::Circadian;
+use crate::output::color_pipeline::ColorPipeline;
+use crate::output::dmx::DmxOutConfig;
+use crate::output::drop_boost::DropBoostConfig;
+use crate::output::nanoleaf::NanoleafConfig;
+use crate::output::openrgb::OpenRgbConfig;
+use crate::output::theater::TheaterConfig;
+use crate::output::zigbee2mqtt::Zigbee2MqttConfig;
+use crate::output::PlacedLight;
+use crate::playlist::{EffectPlaylist, ShuffleConfig};
+use crate::presence::MotionConfig;
+use crate::solar::Location;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Format version written to new config files; older files are upgraded on
+/// load by [`config::migrate`](crate::config::migrate).
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HueConfig {
+    #[serde(default)]
+    pub version: u32, // Config file format, see CONFIG_VERSION
     pub bridge_ip: String,
-    pub username: String,       // Used as "hue-application-key" in REST headers
-    pub client_key: String,     // Used as PSK for DTLS encryption
+    pub username: String,   // Used as "hue-application-key" in REST headers
+    pub client_key: String, // Used as PSK for DTLS encryption
+    #[serde(default)]
     pub application_id: String, // Used as PSK Identity for DTLS (from /auth/v1)
+    #[serde(default)]
     pub entertainment_group_id: String,
+    #[serde(default)]
+    pub bridge_model: String, // e.g. "BSB002", from /api/config
+    #[serde(default)]
+    pub swversion: String, // Bridge firmware version, from /api/config
+    #[serde(default)]
+    pub bridge_id: String, // e.g. "001788FFFE2A1B3C", to find the bridge again after an IP change
+    #[serde(default)]
+    pub ct_only: bool, // Restrict all effect output to white tones
+    #[serde(default)]
+    pub preset: Option<String>, // Active preset name (see preset module)
+    #[serde(default)]
+    pub excluded_channels: Vec<u8>, // Channels never streamed to (keep their normal state)
+    #[serde(default)]
+    pub groups: Vec<GroupEntry>, // All entertainment areas found during setup
+    #[serde(default)]
+    pub channel_smoothing_ms: HashMap<u8, u64>, // Per-channel fade time for lights that visibly step
+    #[serde(default)]
+    pub rooms: BTreeMap<String, Vec<u8>>, // Room name -> channels that room mode renders as one light
+    #[serde(default)]
+    pub color: ColorPipeline, // Color matrix, saturation and contrast applied to every frame
+    #[serde(default)]
+    pub safe_mode: bool, // Photosensitive-safe output: no strobes, flashing kept below 3 Hz
+    #[serde(default)]
+    pub audio_device: Option<String>, // Preferred input device; falls back to the default input
+    #[serde(default)]
+    pub audio_sample_format: Option<String>, // e.g. "i16" or "f32"; device default when unset
+    #[serde(default)]
+    pub hum_filter_hz: Option<f32>, // Mains hum to notch out of the audio input (50 or 60)
+    #[serde(default)]
+    pub noise_gate_db: Option<f32>, // Audio below this level (dBFS) is treated as silence
+    #[serde(default)]
+    pub playlist: Option<EffectPlaylist>, // Effects 'hueflow run' rotates through when no preset is given
+    #[serde(default)]
+    pub shuffle: ShuffleConfig, // Effect whitelist, palettes and pace of '--effect shuffle'
+    #[serde(default)]
+    pub companion: CompanionConfig, // Lights outside the entertainment area that follow the palette
+    #[serde(default)]
+    pub location: Option<Location>, // Latitude/longitude; ambient effects follow sunrise and sunset
+    #[serde(default)]
+    pub openrgb: Option<OpenRgbConfig>, // Mirror frames to PC peripherals through an OpenRGB server
+    #[serde(default)]
+    pub nanoleaf: Vec<NanoleafConfig>, // Nanoleaf controllers whose panels join the room layout
+    #[serde(default)]
+    pub lifx: Vec<PlacedLight>, // LIFX bulbs (by IP address) placed in the room layout
+    #[serde(default)]
+    pub dmx_out: Option<DmxOutConfig>, // Mirror frames to DMX fixtures as an Art-Net or sACN universe
+    #[serde(default)]
+    pub zigbee2mqtt: Option<Zigbee2MqttConfig>, // Bulbs on a zigbee2mqtt coordinator, with or without a bridge
+    #[serde(default)]
+    pub fps: Option<u32>, // Target frame rate (10-50); default renders at 20 and streams at up to 50
+    #[serde(default)]
+    pub retry: RetryPolicy, // How often and how patiently bridge requests are retried
+    #[serde(default)]
+    pub circadian: Option<Circadian>, // Warmer, dimmer output late at night (white point curve)
+    #[serde(default)]
+    pub hooks: Vec<Hook>, // Commands and webhooks run on beats, drops, track changes, stream start/stop
+    #[serde(default)]
+    pub drop_boost: Option<DropBoostConfig>, // Brighter output (and optional strobe) for a while after a drop
+    #[serde(default)]
+    pub theater: Option<TheaterConfig>, // Calm output for films: smoothing, dim rear lights, quiet during dialogue
+    #[serde(default)]
+    pub game: Option<GameConfig>, // UDP listener for game events that flash light cues over the effect
+    #[serde(default)]
+    pub ambient: Option<AmbientConfig>, // Light sensor that output brightness follows (dim room, dim lights)
+    #[serde(default)]
+    pub motion: Option<MotionConfig>, // Motion sensors that pause streaming while the room is empty
+    #[serde(default)]
+    pub app_profiles: Vec<AppRule>, // Presets or effects picked by the application playing audio
+    #[serde(default)]
+    pub control_auth: ControlAuth, // API tokens and TLS for the control API and gRPC service
+}
+
+impl HueConfig {
+    /// Looks up a stored entertainment area by ID or (case-insensitive) name.
+    pub fn find_group(&self, wanted: &str) -> Option<&GroupEntry> {
+        self.groups.iter().find(|g| g.matches(wanted))
+    }
+
+    /// The streaming credentials of this config, validated.
+    pub fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        if !self.username.is_empty() && self.application_id == self.username {
+            return Err(CredentialsError::IdentityIsUsername);
+        }
+        Credentials::new(&self.application_id, &self.client_key)
+    }
+}
+
+/// An entertainment area known from setup, for switching between areas.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupEntry {
+    pub id: String, // v2 entertainment configuration UUID
+    pub name: String,
+}
+
+impl GroupEntry {
+    pub fn matches(&self, wanted: &str) -> bool {
+        group_matches(&self.id, &self.name, wanted)
+    }
+}
+
+pub(crate) fn group_matches(id: &str, name: &str, wanted: &str) -> bool {
+    id == wanted || name.eq_ignore_ascii_case(wanted)
+}
+
+/// Retries of network operations (discovery, registration, group queries,
+/// stream activation) after transient failures, with exponential backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "default_retry_attempts")]
+    pub max_attempts: u32, // Including the first try; 1 disables retrying
+    #[serde(default = "default_retry_delay")]
+    pub base_delay_ms: u64, // Wait before the first retry, doubling after each
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: f64, // 0.0 - 1.0, random share added to or taken off each wait
+}
+
+fn default_retry_attempts() -> u32 {
+    4
+}
+
+fn default_retry_delay() -> u64 {
+    500
+}
+
+fn default_retry_jitter() -> f64 {
+    0.25
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_attempts(),
+            base_delay_ms: default_retry_delay(),
+            jitter: default_retry_jitter(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after failed attempt number `attempt` (from 1). `random` in
+    /// -1.0 - 1.0 picks the point within the jitter range.
+    pub fn delay(&self, attempt: u32, random: f64) -> std::time::Duration {
+        let backoff =
+            self.base_delay_ms as f64 * 2f64.powi(attempt.saturating_sub(1).min(16) as i32);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random.clamp(-1.0, 1.0);
+        std::time::Duration::from_millis((backoff * (1.0 + jitter)) as u64)
+    }
+}
+
+/// Party mode: lights and rooms outside the entertainment area that drift
+/// through the effect's palette over the REST API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompanionConfig {
+    #[serde(default)]
+    pub lights: Vec<String>, // v1 light IDs
+    #[serde(default)]
+    pub groups: Vec<String>, // v1 room or zone IDs
+    #[serde(default = "default_companion_step")]
+    pub step_secs: u64, // Time each color holds (and fades in over)
+    #[serde(default = "default_companion_brightness")]
+    pub brightness: f32, // 0.0 - 1.0, kept below the main area
+}
+
+fn default_companion_step() -> u64 {
+    8
+}
+
+fn default_companion_brightness() -> f32 {
+    0.6
+}
+
+impl Default for CompanionConfig {
+    fn default() -> Self {
+        Self {
+            lights: Vec::new(),
+            groups: Vec::new(),
+            step_secs: default_companion_step(),
+            brightness: default_companion_brightness(),
+        }
+    }
+}
+
+impl CompanionConfig {
+    /// True when no lights or rooms are selected (party mode off).
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty() && self.groups.is_empty()
+    }
 }
 
 /// Represents a light channel in an entertainment configuration.
@@ -18,4 +232,8 @@ pub struct LightNode {
     pub x: f64,
     pub y: f64,
     pub z: f64,
+    /// Segment of its device the channel drives (the bridge's member
+    /// index), 0 for single-segment lights.
+    #[serde(default)]
+    pub segment: u32,
 }
@@ -0,0 +1,292 @@
+//! Scales output brightness to the room's ambient light, so daytime shows
+//! stay visible and nighttime shows are not blinding.
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Time for the output to follow about two thirds of a change in ambient
+/// light, so passing clouds or a switched lamp do not make it jump.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Where the ambient light reading comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum AmbientSensor {
+    /// The light sensor of a Hue motion sensor, by its `light_level`
+    /// resource ID; the first enabled one when omitted.
+    Hue {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// A sensor publishing over MQTT (needs the `mqtt` feature). The payload
+    /// is a plain number of lux or a JSON object with a lux field, such as
+    /// zigbee2mqtt's `illuminance_lux`.
+    Mqtt {
+        host: String,
+        #[serde(default = "default_mqtt_port")]
+        port: u16,
+        topic: String,
+        #[serde(default = "default_lux_field")]
+        field: String,
+    },
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_lux_field() -> String {
+    "illuminance_lux".to_string()
+}
+
+/// Ambient light compensation: output brightness from `min_brightness` in a
+/// room at `dark_lux` or darker up to `max_brightness` at `bright_lux` or
+/// brighter, following lux on a log scale as the eye does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmbientConfig {
+    #[serde(flatten)]
+    pub sensor: AmbientSensor,
+    #[serde(default = "default_dark_lux")]
+    pub dark_lux: f32,
+    #[serde(default = "default_bright_lux")]
+    pub bright_lux: f32,
+    #[serde(default = "default_min_brightness")]
+    pub min_brightness: f32,
+    #[serde(default = "default_max_brightness")]
+    pub max_brightness: f32,
+    /// How often the Hue sensor is read (MQTT sensors push their readings).
+    #[serde(default = "default_poll_secs")]
+    pub poll_secs: u64,
+}
+
+fn default_dark_lux() -> f32 {
+    5.0
+}
+
+fn default_bright_lux() -> f32 {
+    500.0
+}
+
+fn default_min_brightness() -> f32 {
+    0.35
+}
+
+fn default_max_brightness() -> f32 {
+    1.0
+}
+
+fn default_poll_secs() -> u64 {
+    10
+}
+
+impl AmbientConfig {
+    /// Output brightness for an ambient light level.
+    pub fn brightness(&self, lux: f32) -> f32 {
+        let (dark, bright) = (self.dark_lux.max(0.1), self.bright_lux.max(0.2));
+        let t = if bright > dark {
+            ((lux.max(0.1).ln() - dark.ln()) / (bright.ln() - dark.ln())).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        self.min_brightness + (self.max_brightness - self.min_brightness) * t
+    }
+}
+
+/// Output stage scaling every frame by the brightness for the latest
+/// ambient reading, easing towards it over [`SETTLE_TIME`].
+#[derive(Debug, Clone)]
+pub struct AmbientCompensation {
+    config: AmbientConfig,
+    level: Option<f32>,
+    last: Option<Instant>,
+}
+
+impl AmbientCompensation {
+    pub fn new(config: AmbientConfig) -> Self {
+        Self {
+            config,
+            level: None,
+            last: None,
+        }
+    }
+
+    /// Scales `frame` in place for `lux`; frames pass unchanged until the
+    /// first reading.
+    pub fn apply<C: Rgb>(&mut self, frame: &mut HashMap<u8, C>, lux: Option<f32>, now: Instant) {
+        let Some(lux) = lux else {
+            return;
+        };
+        let target = self.config.brightness(lux);
+        let elapsed = self
+            .last
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last = Some(now);
+        let level = match self.level {
+            Some(level) => {
+                let k = 1.0 - (-elapsed.as_secs_f32() / SETTLE_TIME.as_secs_f32()).exp();
+                level + (target - level) * k
+            }
+            None => target,
+        };
+        self.level = Some(level);
+        for color in frame.values_mut() {
+            *color = C::from_rgb(color.to_rgb().map(|v| v * level));
+        }
+    }
+}
+
+/// Reads lux from an MQTT payload: a bare number or `field` of a JSON object.
+pub fn parse_lux(payload: &[u8], field: &str) -> Option<f32> {
+    let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    value
+        .as_f64()
+        .or_else(|| value.get(field)?.as_f64())
+        .map(|lux| lux as f32)
+}
+
+/// Starts reading the sensor in the background; the receiver holds the
+/// latest reading in lux. `bridge` is needed for Hue sensors.
+#[cfg(feature = "bridge")]
+pub fn spawn_sensor(
+    bridge: Option<crate::models::HueConfig>,
+    config: &AmbientConfig,
+) -> tokio::sync::watch::Receiver<Option<f32>> {
+    let (tx, rx) = tokio::sync::watch::channel(None);
+    match config.sensor.clone() {
+        AmbientSensor::Hue { id } => match bridge {
+            Some(bridge) => {
+                let poll = Duration::from_secs(config.poll_secs.max(1));
+                tokio::spawn(poll_hue_sensor(bridge, id, poll, tx));
+            }
+            None => tracing::warn!("Ambient light: a Hue sensor needs a bridge connection"),
+        },
+        #[cfg(feature = "mqtt")]
+        AmbientSensor::Mqtt {
+            host,
+            port,
+            topic,
+            field,
+        } => {
+            tokio::spawn(read_mqtt_sensor(host, port, topic, field, tx));
+        }
+        #[cfg(not(feature = "mqtt"))]
+        AmbientSensor::Mqtt { .. } => {
+            tracing::warn!("Ambient light: MQTT sensors need the `mqtt` feature")
+        }
+    }
+    rx
+}
+
+#[cfg(feature = "bridge")]
+async fn poll_hue_sensor(
+    bridge: crate::models::HueConfig,
+    id: Option<String>,
+    poll: Duration,
+    tx: tokio::sync::watch::Sender<Option<f32>>,
+) {
+    use crate::api::v2::get_resources;
+    use crate::api::v2::models::LightLevel;
+
+    let mut ticks = tokio::time::interval(poll);
+    while !tx.is_closed() {
+        ticks.tick().await;
+        match get_resources::<LightLevel>(&bridge).await {
+            Ok(sensors) => {
+                let sensor = sensors.iter().find(|s| match &id {
+                    Some(id) => s.id == *id,
+                    None => s.enabled,
+                });
+                match sensor.and_then(LightLevel::lux) {
+                    Some(lux) => {
+                        tx.send_replace(Some(lux));
+                    }
+                    None => tracing::debug!("Ambient light: no valid reading"),
+                }
+            }
+            Err(e) => tracing::warn!("Ambient light: cannot read the sensor: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+async fn read_mqtt_sensor(
+    host: String,
+    port: u16,
+    topic: String,
+    field: String,
+    tx: tokio::sync::watch::Sender<Option<f32>>,
+) {
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+
+    let options = MqttOptions::new(format!("hueflow-{}", std::process::id()), host, port);
+    let (client, mut events) = AsyncClient::new(options, 10);
+    if let Err(e) = client.subscribe(&topic, QoS::AtMostOnce).await {
+        tracing::warn!("Ambient light: cannot subscribe to {}: {}", topic, e);
+        return;
+    }
+    while !tx.is_closed() {
+        match events.poll().await {
+            Ok(Event::Incoming(Packet::Publish(message))) => {
+                match parse_lux(&message.payload, &field) {
+                    Some(lux) => {
+                        tx.send_replace(Some(lux));
+                    }
+                    None => tracing::debug!("Ambient light: no lux in message on {}", topic),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // The event loop reconnects on the next poll
+                tracing::warn!("Ambient light: MQTT connection failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AmbientConfig {
+        serde_json::from_str(r#"{"source": "hue"}"#).unwrap()
+    }
+
+    #[test]
+    fn test_brightness_curve() {
+        let config = config();
+        assert_eq!(config.sensor, AmbientSensor::Hue { id: None });
+        assert_eq!(config.brightness(0.0), 0.35);
+        assert_eq!(config.brightness(5.0), 0.35);
+        assert_eq!(config.brightness(2000.0), 1.0);
+        // 50 lux is halfway between 5 and 500 on a log scale
+        assert!((config.brightness(50.0) - 0.675).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_compensation_settles() {
+        let mut stage = AmbientCompensation::new(config());
+        let start = Instant::now();
+        let mut frame = HashMap::from([(0, (200, 100, 0))]);
+        stage.apply(&mut frame, None, start);
+        assert_eq!(frame[&0], (200, 100, 0));
+
+        stage.apply(&mut frame, Some(1.0), start);
+        assert_eq!(frame[&0], (70, 35, 0));
+
+        // Daylight: eases up rather than jumping
+        let mut frame = HashMap::from([(0, (200, 100, 0))]);
+        stage.apply(&mut frame, Some(1000.0), start + SETTLE_TIME);
+        let (r, _, _) = frame[&0];
+        assert!(r > 150 && r < 200, "{}", r);
+    }
+
+    #[test]
+    fn test_parse_lux() {
+        assert_eq!(parse_lux(b"42.5", "illuminance_lux"), Some(42.5));
+        let z2m = br#"{"illuminance": 17000, "illuminance_lux": 50, "occupancy": false}"#;
+        assert_eq!(parse_lux(z2m, "illuminance_lux"), Some(50.0));
+        assert_eq!(parse_lux(b"on", "illuminance_lux"), None);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::color::Rgb;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default fade time for blackout and resume.
+pub const DEFAULT_FADE: Duration = Duration::from_millis(300);
+
+/// Panic/blackout control: crossfades all channels to black and holds there
+/// until resumed, then fades back in.
+#[derive(Debug, Clone)]
+pub struct Blackout {
+    /// Level at the start of the current fade (0.0 = black, 1.0 = full output).
+    from: f32,
+    target: f32,
+    started: Instant,
+    fade: Duration,
+}
+
+impl Default for Blackout {
+    fn default() -> Self {
+        Self {
+            from: 1.0,
+            target: 1.0,
+            started: Instant::now(),
+            fade: DEFAULT_FADE,
+        }
+    }
+}
+
+impl Blackout {
+    /// Starts fading to black over `fade`.
+    pub fn engage(&mut self, fade: Duration) {
+        self.fade_to(0.0, fade, Instant::now());
+    }
+
+    /// Starts fading back to the effect output over `fade`.
+    pub fn release(&mut self, fade: Duration) {
+        self.fade_to(1.0, fade, Instant::now());
+    }
+
+    /// True while blacked out or fading towards black.
+    pub fn is_engaged(&self) -> bool {
+        self.target == 0.0
+    }
+
+    /// Scales `frame` by the current blackout level.
+    pub fn apply<C: Rgb>(&self, frame: &mut HashMap<u8, C>) {
+        self.apply_at(frame, Instant::now());
+    }
+
+    fn fade_to(&mut self, target: f32, fade: Duration, now: Instant) {
+        // Start from wherever a running fade currently is
+        self.from = self.level_at(now);
+        self.target = target;
+        self.started = now;
+        self.fade = fade;
+    }
+
+    fn level_at(&self, now: Instant) -> f32 {
+        if self.fade.is_zero() {
+            return self.target;
+        }
+        let t = (now.saturating_duration_since(self.started).as_secs_f32()
+            / self.fade.as_secs_f32())
+        .clamp(0.0, 1.0);
+        self.from + (self.target - self.from) * t
+    }
+
+    fn apply_at<C: Rgb>(&self, frame: &mut HashMap<u8, C>, now: Instant) {
+        let level = self.level_at(now).clamp(0.0, 1.0);
+        if level >= 1.0 {
+            return;
+        }
+        for color in frame.values_mut() {
+            *color = C::from_rgb(color.to_rgb().map(|v| v * level));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_to_black_and_back() {
+        let start = Instant::now();
+        let mut blackout = Blackout::default();
+        blackout.fade_to(0.0, Duration::from_millis(300), start);
+        assert!(blackout.is_engaged());
+
+        let mut frame = HashMap::from([(0u8, (200u8, 100u8, 50u8))]);
+        blackout.apply_at(&mut frame, start + Duration::from_millis(150));
+        assert_eq!(frame[&0], (100, 50, 25));
+
+        let mut frame = HashMap::from([(0u8, (200u8, 100u8, 50u8))]);
+        blackout.apply_at(&mut frame, start + Duration::from_secs(5));
+        assert_eq!(frame[&0], (0, 0, 0));
+
+        blackout.fade_to(
+            1.0,
+            Duration::from_millis(300),
+            start + Duration::from_secs(5),
+        );
+        let mut frame = HashMap::from([(0u8, (200u8, 100u8, 50u8))]);
+        blackout.apply_at(&mut frame, start + Duration::from_secs(6));
+        assert_eq!(frame[&0], (200, 100, 50));
+    }
+
+    #[test]
+    fn test_unrounded_frame_keeps_dim_levels() {
+        let start = Instant::now();
+        let mut blackout = Blackout::default();
+        blackout.fade_to(0.0, Duration::from_millis(300), start);
+
+        // In 8 bits, 3 and 2 at half level would both round to 2
+        let mut frame = HashMap::from([(0u8, [3.0f32, 2.0, 0.0])]);
+        blackout.apply_at(&mut frame, start + Duration::from_millis(150));
+        assert_eq!(frame[&0], [1.5, 1.0, 0.0]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Per-light calibration: gamma and white balance that make mismatched
+//! lamps look alike, measured by `hueflow calibrate` and applied by the
+//! [`ColorPipeline`](crate::output::color_pipeline::ColorPipeline).
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Gray levels lights are compared at, from dark to bright.
+pub const GRAY_RAMP: [u8; 3] = [64, 128, 191];
+/// Colors lights are compared in, to balance red, green and blue.
+pub const PRIMARIES: [(u8, u8, u8); 3] = [(255, 0, 0), (0, 255, 0), (0, 0, 255)];
+
+/// Factor a gain changes by per judgement.
+const GAIN_STEP: f32 = 0.92;
+/// Amount the gamma changes by per judgement.
+const GAMMA_STEP: f32 = 0.1;
+const GAMMA_RANGE: (f32, f32) = (0.4, 3.0);
+
+/// Correction of one channel, applied after the global color stages.
+///
+/// Each component is scaled by its `white` gain, then raised to `gamma`:
+/// above 1.0 darkens mid tones, below 1.0 brightens them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelCalibration {
+    pub gamma: f32,
+    /// Gains of red, green and blue.
+    pub white: [f32; 3],
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            white: [1.0; 3],
+        }
+    }
+}
+
+/// How a light looked next to the reference light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Matches,
+    Brighter,
+    Dimmer,
+}
+
+/// Average camera colors of one light showing black, mid gray and white
+/// (see [`GRAY_RAMP`]), each component 0.0-1.0 as the camera encodes it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraReading {
+    pub black: [f32; 3],
+    pub gray: [f32; 3],
+    pub white: [f32; 3],
+}
+
+impl ChannelCalibration {
+    pub fn correct(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        Rgb::from_rgb(self.correct_rgb(color.to_rgb()))
+    }
+
+    /// Like [`correct`](Self::correct), unrounded (0.0 - 255.0 per component).
+    pub fn correct_rgb(&self, color: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|i| {
+            let v = (color[i] / 255.0 * self.white[i].max(0.0)).clamp(0.0, 1.0);
+            v.powf(self.gamma.max(0.01)) * 255.0
+        })
+    }
+
+    /// Adjusts the gain of `component` (0 = red) after the light looked
+    /// brighter or dimmer in that primary than the reference.
+    pub fn judge_primary(&mut self, component: usize, judgement: Judgement) {
+        match judgement {
+            Judgement::Matches => {}
+            Judgement::Brighter => self.white[component] *= GAIN_STEP,
+            Judgement::Dimmer => self.white[component] /= GAIN_STEP,
+        }
+    }
+
+    /// Adjusts the gamma after the light looked brighter or dimmer in a
+    /// gray than the reference.
+    pub fn judge_gray(&mut self, judgement: Judgement) {
+        let step = match judgement {
+            Judgement::Matches => return,
+            Judgement::Brighter => GAMMA_STEP,
+            Judgement::Dimmer => -GAMMA_STEP,
+        };
+        self.gamma = (self.gamma + step).clamp(GAMMA_RANGE.0, GAMMA_RANGE.1);
+    }
+
+    /// The calibration matching a light to the reference from camera
+    /// readings of both, taken the same way (distance, framing, exposure).
+    ///
+    /// Gains come from the ratio of the white readings. The gamma makes the
+    /// light's gray-to-white ratio that of the reference; the camera's own
+    /// encoding divides out as both go through it.
+    pub fn from_camera(reference: &CameraReading, light: &CameraReading) -> Self {
+        let lit = |reading: &[f32; 3], black: &[f32; 3]| -> [f32; 3] {
+            std::array::from_fn(|i| (reading[i] - black[i]).max(1e-3))
+        };
+        let (ref_white, light_white) = (
+            lit(&reference.white, &reference.black),
+            lit(&light.white, &light.black),
+        );
+        let luma = |rgb: [f32; 3]| rgb.iter().sum::<f32>() / 3.0;
+        let ratio = |reading: &CameraReading, white: [f32; 3]| {
+            (luma(lit(&reading.gray, &reading.black)) / luma(white)).clamp(0.01, 0.99)
+        };
+        let gamma = ratio(reference, ref_white).ln() / ratio(light, light_white).ln();
+        Self {
+            gamma: gamma.clamp(GAMMA_RANGE.0, GAMMA_RANGE.1),
+            white: std::array::from_fn(|i| ref_white[i] / light_white[i]),
+        }
+    }
+}
+
+/// Scales the gains of all channels so the largest of each component is
+/// 1.0: brighter lights are turned down to match the dimmest, as none can
+/// go above full output.
+pub fn normalize_gains(channels: &mut HashMap<u8, ChannelCalibration>) {
+    for component in 0..3 {
+        let max = channels
+            .values()
+            .map(|c| c.white[component])
+            .fold(0.0, f32::max);
+        if max > 0.0 {
+            for calibration in channels.values_mut() {
+                calibration.white[component] /= max;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correct_and_judge() {
+        let mut calibration = ChannelCalibration::default();
+        assert_eq!(calibration.correct((255, 128, 7)), (255, 128, 7));
+
+        calibration.judge_primary(2, Judgement::Brighter);
+        calibration.judge_gray(Judgement::Brighter);
+        assert_eq!(calibration.white, [1.0, 1.0, GAIN_STEP]);
+        assert!((calibration.gamma - 1.1).abs() < 1e-6);
+        let (r, g, b) = calibration.correct((255, 128, 255));
+        assert_eq!(r, 255);
+        assert!(g < 128);
+        assert!(b < 255);
+
+        let mut channels = HashMap::from([
+            (0, ChannelCalibration::default()),
+            (
+                1,
+                ChannelCalibration {
+                    white: [1.25, 1.0, 0.5],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        normalize_gains(&mut channels);
+        assert_eq!(channels[&0].white, [0.8, 1.0, 1.0]);
+        assert_eq!(channels[&1].white, [1.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_from_camera() {
+        let reference = CameraReading {
+            black: [0.1; 3],
+            gray: [0.5; 3],
+            white: [0.9; 3],
+        };
+        assert_eq!(
+            ChannelCalibration::from_camera(&reference, &reference),
+            ChannelCalibration::default()
+        );
+        // Bluish light whose mid tones come out too bright
+        let light = CameraReading {
+            black: [0.1; 3],
+            gray: [0.7; 3],
+            white: [0.9, 0.9, 1.0],
+        };
+        let calibration = ChannelCalibration::from_camera(&reference, &light);
+        assert!(calibration.gamma > 1.0);
+        assert_eq!(calibration.white[0], 1.0);
+        assert!(calibration.white[2] < 1.0);
+    }
+}
@@ -0,0 +1,237 @@
+//! Shifts all output towards warmer, dimmer white points late at night so
+//! audio-reactive lighting does not keep anyone awake.
+use crate::color::{kelvin_to_rgb, Rgb, MAX_KELVIN};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One point of the [`Circadian`] curve.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CircadianPoint {
+    /// Local time of day in hours, 0.0 - 24.0 (e.g. 22.5 for 22:30).
+    pub hour: f32,
+    /// White point output is adapted to; 6500 leaves colors unchanged.
+    pub kelvin: f32,
+    /// Brightness scale, 1.0 = unchanged.
+    #[serde(default = "full_brightness")]
+    pub brightness: f32,
+}
+
+fn full_brightness() -> f32 {
+    1.0
+}
+
+/// Filter stage after the effect that adapts every frame to the white point
+/// and brightness of a daily curve, blending linearly between its points
+/// (and across midnight from the last point to the first).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Circadian {
+    /// Sorted by hour, see [`Circadian::new`].
+    #[serde(deserialize_with = "sorted_curve")]
+    curve: Vec<CircadianPoint>,
+    /// Fixed offset of local time from UTC, e.g. 60 for CET. Unset, the
+    /// system's local time zone is used, including daylight saving time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utc_offset_minutes: Option<i32>,
+}
+
+fn sorted_curve<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<CircadianPoint>, D::Error> {
+    let mut curve = Vec::deserialize(deserializer)?;
+    curve.sort_by(|a: &CircadianPoint, b| a.hour.total_cmp(&b.hour));
+    Ok(curve)
+}
+
+impl Default for Circadian {
+    /// Neutral from 9:00 to 19:00, warming through the evening to a dim
+    /// 2200 K candle tone at night.
+    fn default() -> Self {
+        let point = |hour, kelvin, brightness| CircadianPoint {
+            hour,
+            kelvin,
+            brightness,
+        };
+        Self {
+            curve: vec![
+                point(2.0, 2200.0, 0.5),
+                point(7.0, 2700.0, 0.8),
+                point(9.0, MAX_KELVIN, 1.0),
+                point(19.0, MAX_KELVIN, 1.0),
+                point(22.0, 2700.0, 0.8),
+            ],
+            utc_offset_minutes: None,
+        }
+    }
+}
+
+impl Circadian {
+    /// Curve through `curve` (in any order) in the system's local time.
+    pub fn new(mut curve: Vec<CircadianPoint>) -> Self {
+        curve.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+        Self {
+            curve,
+            utc_offset_minutes: None,
+        }
+    }
+
+    /// Points of the curve, sorted by hour.
+    pub fn curve(&self) -> &[CircadianPoint] {
+        &self.curve
+    }
+
+    /// White point and brightness at `at`.
+    pub fn at(&self, at: SystemTime) -> (f32, f32) {
+        let secs = at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let offset = match self.utc_offset_minutes {
+            Some(minutes) => minutes as i64 * 60,
+            None => local_offset(secs),
+        };
+        self.at_hour((secs + offset).rem_euclid(86_400) as f32 / 3600.0)
+    }
+
+    /// White point and brightness at local time `hour`.
+    pub fn at_hour(&self, hour: f32) -> (f32, f32) {
+        let curve = &self.curve;
+        let (Some(first), Some(last)) = (curve.first().copied(), curve.last().copied()) else {
+            return (MAX_KELVIN, 1.0);
+        };
+        let next = curve.partition_point(|p| p.hour <= hour);
+        // Before the first point and after the last, blend across midnight
+        let (from, to) = match (next.checked_sub(1), curve.get(next)) {
+            (Some(i), Some(to)) => (curve[i], *to),
+            _ => (last, first),
+        };
+        let span = (to.hour - from.hour).rem_euclid(24.0);
+        let t = if span > 0.0 {
+            (hour - from.hour).rem_euclid(24.0) / span
+        } else {
+            0.0
+        };
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        (
+            lerp(from.kelvin, to.kelvin),
+            lerp(from.brightness, to.brightness),
+        )
+    }
+
+    /// Adapts all colors of `frame` in place to the curve at `at`.
+    pub fn apply<C: Rgb>(&self, frame: &mut HashMap<u8, C>, at: SystemTime) {
+        let (kelvin, brightness) = self.at(at);
+        for color in frame.values_mut() {
+            *color = C::from_rgb(adapt_rgb(color.to_rgb(), kelvin, brightness));
+        }
+    }
+}
+
+/// Offset in seconds of the system's local time from UTC at `secs` since
+/// the epoch, 0 where the platform has no time zone.
+#[cfg(unix)]
+fn local_offset(secs: i64) -> i64 {
+    let time = secs as libc::time_t;
+    // SAFETY: all-zero is a valid `tm`, and `localtime_r` only writes to it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+#[cfg(windows)]
+fn local_offset(_secs: i64) -> i64 {
+    use windows::Win32::System::Time::{
+        GetTimeZoneInformation, TIME_ZONE_ID_INVALID, TIME_ZONE_INFORMATION,
+    };
+    const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+    let mut info = TIME_ZONE_INFORMATION::default();
+    // Bias is in minutes, UTC minus local time
+    let bias = match unsafe { GetTimeZoneInformation(&mut info) } {
+        TIME_ZONE_ID_INVALID => return 0,
+        TIME_ZONE_ID_DAYLIGHT => info.Bias + info.DaylightBias,
+        _ => info.Bias + info.StandardBias,
+    };
+    -(bias as i64) * 60
+}
+
+#[cfg(not(any(unix, windows)))]
+fn local_offset(_secs: i64) -> i64 {
+    0
+}
+
+/// Moves `color` from the neutral white point to `kelvin` by scaling each
+/// channel with the white point's tint, then dims it.
+pub fn adapt(color: (u8, u8, u8), kelvin: f32, brightness: f32) -> (u8, u8, u8) {
+    Rgb::from_rgb(adapt_rgb(color.to_rgb(), kelvin, brightness))
+}
+
+/// Like [`adapt`], unrounded (0.0 - 255.0 per component).
+pub fn adapt_rgb(color: [f32; 3], kelvin: f32, brightness: f32) -> [f32; 3] {
+    let neutral = kelvin_to_rgb(MAX_KELVIN).to_rgb();
+    let target = kelvin_to_rgb(kelvin).to_rgb();
+    let brightness = brightness.clamp(0.0, 1.0);
+    std::array::from_fn(|i| {
+        let gain = (target[i] / neutral[i].max(1.0)).min(1.0);
+        color[i] * gain * brightness
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_curve() {
+        let circadian = Circadian::default();
+        assert_eq!(circadian.at_hour(12.0), (MAX_KELVIN, 1.0));
+        assert_eq!(circadian.at_hour(22.0), (2700.0, 0.8));
+        assert_eq!(circadian.at_hour(2.0), (2200.0, 0.5));
+        // Across midnight: 22:00 -> 2:00
+        let (kelvin, brightness) = circadian.at_hour(0.0);
+        assert_eq!(kelvin, 2450.0);
+        assert!((brightness - 0.65).abs() < 1e-6);
+
+        // 23:00 UTC is 0:00 in CET
+        let cet = Circadian {
+            utc_offset_minutes: Some(60),
+            ..Default::default()
+        };
+        let day = UNIX_EPOCH + Duration::from_secs(19_723 * 86_400);
+        assert_eq!(cet.at(day + Duration::from_secs(23 * 3600)).0, 2450.0);
+
+        assert_eq!(Circadian::new(Vec::new()).at_hour(3.0), (MAX_KELVIN, 1.0));
+    }
+
+    #[test]
+    fn test_curve_is_sorted_on_load() {
+        let circadian: Circadian = serde_json::from_str(
+            r#"{"utc_offset_minutes": 0, "curve": [
+                {"hour": 20, "kelvin": 2200},
+                {"hour": 8, "kelvin": 6500}
+            ]}"#,
+        )
+        .unwrap();
+        let hours: Vec<f32> = circadian.curve().iter().map(|p| p.hour).collect();
+        assert_eq!(hours, [8.0, 20.0]);
+        assert_eq!(circadian.at_hour(14.0), (4350.0, 1.0));
+        assert_eq!(circadian, {
+            let mut new = Circadian::new(circadian.curve().iter().rev().copied().collect());
+            new.utc_offset_minutes = Some(0);
+            new
+        });
+    }
+
+    #[test]
+    fn test_adapt() {
+        assert_eq!(adapt((200, 100, 50), MAX_KELVIN, 1.0), (200, 100, 50));
+        // Warm white keeps red, cuts blue hardest
+        let (r, g, b) = adapt((255, 255, 255), 2200.0, 1.0);
+        assert_eq!(r, 255);
+        assert!(g < 200 && b < g, "{:?}", (r, g, b));
+        assert_eq!(adapt((255, 0, 0), MAX_KELVIN, 0.5), (128, 0, 0));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::color::Rgb;
+use crate::output::calibration::ChannelCalibration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Rec. 709 luma weights, used as the gray point for saturation.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Global color correction applied to every frame before it is sent.
+///
+/// Runs in this order: the 3×3 `matrix` (rows produce output R, G, B from
+/// input RGB), then `saturation` (0.0 = gray, 1.0 = unchanged, above 1.0
+/// boosts), then `contrast` around mid gray (1.0 = unchanged). Use it to
+/// compensate fixtures that wash colors out, or to crank saturation for a
+/// party. Last, lights with an entry in `channels` get their own
+/// calibration (see `hueflow calibrate`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorPipeline {
+    pub matrix: [[f32; 3]; 3],
+    pub saturation: f32,
+    pub contrast: f32,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub channels: HashMap<u8, ChannelCalibration>,
+}
+
+impl Default for ColorPipeline {
+    fn default() -> Self {
+        Self {
+            matrix: IDENTITY,
+            saturation: 1.0,
+            contrast: 1.0,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl ColorPipeline {
+    /// True when applying the pipeline leaves every color unchanged.
+    pub fn is_identity(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Corrects all colors of `frame` in place.
+    pub fn apply<C: Rgb>(&self, frame: &mut HashMap<u8, C>) {
+        if self.is_identity() {
+            return;
+        }
+        for (channel, color) in frame.iter_mut() {
+            let mut rgb = self.correct_rgb(color.to_rgb());
+            if let Some(calibration) = self.channels.get(channel) {
+                rgb = calibration.correct_rgb(rgb);
+            }
+            *color = C::from_rgb(rgb);
+        }
+    }
+
+    /// Corrects a single color.
+    pub fn correct(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        Rgb::from_rgb(self.correct_rgb(color.to_rgb()))
+    }
+
+    /// Corrects a single unrounded color (0.0 - 255.0 per component).
+    pub fn correct_rgb(&self, color: [f32; 3]) -> [f32; 3] {
+        let input = color.map(|v| v / 255.0);
+        let mut rgb: [f32; 3] =
+            std::array::from_fn(|i| (0..3).map(|j| self.matrix[i][j] * input[j]).sum());
+
+        let gray: f32 = (0..3).map(|i| LUMA[i] * rgb[i]).sum();
+        for v in &mut rgb {
+            *v = gray + (*v - gray) * self.saturation.max(0.0);
+            *v = (*v - 0.5) * self.contrast.max(0.0) + 0.5;
+        }
+        rgb.map(|v| v.clamp(0.0, 1.0) * 255.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_identity() {
+        let pipeline = ColorPipeline::default();
+        assert!(pipeline.is_identity());
+        for color in [(0, 0, 0), (255, 128, 7), (12, 200, 255)] {
+            assert_eq!(pipeline.correct(color), color);
+        }
+    }
+
+    #[test]
+    fn test_saturation() {
+        let gray = ColorPipeline {
+            saturation: 0.0,
+            ..Default::default()
+        };
+        let (r, g, b) = gray.correct((255, 0, 0));
+        assert_eq!((r, g, b), (54, 54, 54));
+
+        let boost = ColorPipeline {
+            saturation: 2.0,
+            ..Default::default()
+        };
+        // Dominant channel grows, weak channels shrink
+        let (r, g, b) = boost.correct((200, 100, 100));
+        assert!(r > 200 && g < 100 && b < 100);
+    }
+
+    #[test]
+    fn test_matrix_and_contrast() {
+        let swap = ColorPipeline {
+            matrix: [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+            ..Default::default()
+        };
+        let mut frame = HashMap::from([(3, (255, 10, 0))]);
+        swap.apply(&mut frame);
+        assert_eq!(frame[&3], (0, 10, 255));
+
+        let contrast = ColorPipeline {
+            contrast: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(contrast.correct((0, 255, 0)), (64, 191, 64));
+
+        let calibrated = ColorPipeline {
+            channels: HashMap::from([(
+                3,
+                ChannelCalibration {
+                    white: [0.5, 1.0, 1.0],
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let mut frame = HashMap::from([(3, (255, 10, 0)), (4, (255, 10, 0))]);
+        calibrated.apply(&mut frame);
+        assert_eq!(frame[&3], (128, 10, 0));
+        assert_eq!(frame[&4], (255, 10, 0));
+    }
+}
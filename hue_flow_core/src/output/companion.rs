@@ -0,0 +1,127 @@
+//! Party mode: lights outside the entertainment area drift slowly through
+//! the palette over the REST API, while the area itself gets the full-rate
+//! stream.
+use crate::api::lights::{set_color, LightTarget};
+use crate::color::{kelvin_to_rgb, scale};
+use crate::effects::effect_info;
+use crate::models::{CompanionConfig, HueConfig};
+use crate::preset::Preset;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, sleep_until, Instant};
+
+/// Gap between commands to single lights (the bridge takes about ten per second).
+pub const LIGHT_COMMAND_GAP: Duration = Duration::from_millis(100);
+/// Gap between commands to rooms and zones (about one per second).
+pub const GROUP_COMMAND_GAP: Duration = Duration::from_secs(1);
+
+/// Colors the companion lights cycle through for `preset`: its palette, or
+/// the effect's default colors when it has none.
+pub fn palette_for(preset: &Preset) -> Vec<(u8, u8, u8)> {
+    if !preset.palette.is_empty() {
+        return preset.palette.clone();
+    }
+    match effect_info(&preset.effect) {
+        Some(info) if !info.palette.is_empty() => {
+            info.palette.iter().map(|(_, color)| *color).collect()
+        }
+        // Warm white for effects without colors
+        _ => vec![kelvin_to_rgb(2200.0), kelvin_to_rgb(2700.0)],
+    }
+}
+
+/// The color each target shows on `step`: neighbours are one palette entry
+/// apart, so the lights never all match.
+fn step_colors(
+    companion: &CompanionConfig,
+    palette: &[(u8, u8, u8)],
+    step: usize,
+) -> Vec<(LightTarget, (u8, u8, u8))> {
+    let targets = companion
+        .lights
+        .iter()
+        .map(|id| LightTarget::Light(id.clone()))
+        .chain(
+            companion
+                .groups
+                .iter()
+                .map(|id| LightTarget::Group(id.clone())),
+        );
+    targets
+        .enumerate()
+        .map(|(i, target)| {
+            let color = palette[(step + i) % palette.len()];
+            (target, scale(color, companion.brightness))
+        })
+        .collect()
+}
+
+/// Time one pass over all targets takes at the rate limits.
+fn pass_time(companion: &CompanionConfig) -> Duration {
+    LIGHT_COMMAND_GAP * companion.lights.len() as u32
+        + GROUP_COMMAND_GAP * companion.groups.len() as u32
+}
+
+/// Spawns the party mode task. Each step every target fades to its next
+/// palette color over the step time, which is stretched when needed to
+/// stay within the bridge's rate limits. Failed commands are logged and
+/// retried on the next step.
+pub fn spawn_companion(
+    config: HueConfig,
+    companion: CompanionConfig,
+    palette: Vec<(u8, u8, u8)>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if companion.is_empty() || palette.is_empty() {
+            return;
+        }
+        let step_time = Duration::from_secs(companion.step_secs).max(pass_time(&companion));
+        for step in 0.. {
+            let started = Instant::now();
+            for (target, color) in step_colors(&companion, &palette, step) {
+                if let Err(e) = set_color(&config, &target, color, step_time).await {
+                    tracing::warn!("Party mode: {}", e);
+                }
+                sleep(match target {
+                    LightTarget::Light(_) => LIGHT_COMMAND_GAP,
+                    LightTarget::Group(_) => GROUP_COMMAND_GAP,
+                })
+                .await;
+            }
+            sleep_until(started + step_time).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets_step_through_palette() {
+        let companion = CompanionConfig {
+            lights: vec!["4".to_string(), "7".to_string()],
+            groups: vec!["2".to_string()],
+            brightness: 1.0,
+            ..Default::default()
+        };
+        let palette = [(255, 0, 0), (0, 255, 0)];
+
+        let colors = step_colors(&companion, &palette, 1);
+        assert_eq!(
+            colors,
+            vec![
+                (LightTarget::Light("4".to_string()), (0, 255, 0)),
+                (LightTarget::Light("7".to_string()), (255, 0, 0)),
+                (LightTarget::Group("2".to_string()), (0, 255, 0)),
+            ]
+        );
+        assert_eq!(pass_time(&companion), Duration::from_millis(1200));
+
+        let chase = Preset {
+            effect: "chase".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(palette_for(&chase), vec![(255, 180, 0)]);
+    }
+}
@@ -0,0 +1,256 @@
+//! DMX output: the frames re-broadcast as an Art-Net or sACN (E1.31)
+//! universe, so conventional RGB fixtures follow the same effect as the Hue
+//! lights.
+use crate::output::LightSink;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+/// UDP port of Art-Net.
+pub const ARTNET_PORT: u16 = 6454;
+/// UDP port of sACN.
+pub const SACN_PORT: u16 = 5568;
+/// Slots in a DMX universe.
+pub const UNIVERSE_SIZE: usize = 512;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const ARTNET_VERSION: u16 = 14;
+const ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+/// sACN priority of our data; receivers merge sources by it (default 100).
+const SACN_PRIORITY: u8 = 100;
+const SOURCE_NAME: &str = "HueFlow";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DmxProtocol {
+    #[default]
+    Artnet,
+    Sacn,
+}
+
+/// DMX output settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DmxOutConfig {
+    #[serde(default)]
+    pub protocol: DmxProtocol,
+    /// Node to send to. Defaults to broadcast for Art-Net and to the
+    /// universe's multicast group for sACN.
+    #[serde(default)]
+    pub target: Option<IpAddr>,
+    /// Universe to send; defaults to 0 for Art-Net and 1 for sACN, the
+    /// first universe of each.
+    #[serde(default)]
+    pub universe: Option<u16>,
+    /// DMX address (1-512) of channel 0's red slot. Every channel is an RGB
+    /// fixture of three slots, channel `n` starting at `start_address + 3n`.
+    #[serde(default = "default_start_address")]
+    pub start_address: u16,
+    /// Start addresses of single channels, in place of the above.
+    #[serde(default)]
+    pub patch: HashMap<u8, u16>,
+}
+
+fn default_start_address() -> u16 {
+    1
+}
+
+impl DmxOutConfig {
+    fn universe(&self) -> u16 {
+        self.universe.unwrap_or(match self.protocol {
+            DmxProtocol::Artnet => 0,
+            DmxProtocol::Sacn => 1,
+        })
+    }
+
+    fn destination(&self) -> SocketAddr {
+        let universe = self.universe();
+        match self.protocol {
+            DmxProtocol::Artnet => SocketAddr::new(
+                self.target.unwrap_or(IpAddr::V4(Ipv4Addr::BROADCAST)),
+                ARTNET_PORT,
+            ),
+            DmxProtocol::Sacn => {
+                let [hi, lo] = universe.to_be_bytes();
+                let group = IpAddr::V4(Ipv4Addr::new(239, 255, hi, lo));
+                SocketAddr::new(self.target.unwrap_or(group), SACN_PORT)
+            }
+        }
+    }
+
+    /// Zero-based index of the first slot of `channel`'s fixture.
+    fn slot(&self, channel: u8) -> usize {
+        let address = match self.patch.get(&channel) {
+            Some(&address) => address as usize,
+            None => self.start_address as usize + 3 * channel as usize,
+        };
+        address.saturating_sub(1)
+    }
+}
+
+/// Sends every frame as one DMX universe (see
+/// [`HueFlowBuilder::mirror`](crate::HueFlowBuilder::mirror)).
+///
+/// The whole universe goes out on every frame, as DMX receivers expect a
+/// steady refresh; fixtures past slot 512 are left out.
+pub struct DmxSink {
+    config: DmxOutConfig,
+    destination: SocketAddr,
+    socket: UdpSocket,
+    cid: [u8; 16],
+    sequence: u8,
+}
+
+impl DmxSink {
+    pub fn new(config: DmxOutConfig) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        // sACN identifies sources by CID; keep instances on one host apart
+        let mut cid = *b"HueFlow-sACN\0\0\0\0";
+        cid[12..].copy_from_slice(&std::process::id().to_be_bytes());
+        Ok(Self {
+            destination: config.destination(),
+            config,
+            socket,
+            cid,
+            sequence: 0,
+        })
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// The frame laid out as DMX slots.
+    fn universe(&self, frame: &HashMap<u8, (u8, u8, u8)>) -> [u8; UNIVERSE_SIZE] {
+        let mut slots = [0; UNIVERSE_SIZE];
+        for (&channel, &(r, g, b)) in frame {
+            let first = self.config.slot(channel);
+            if let Some(fixture) = slots.get_mut(first..first + 3) {
+                fixture.copy_from_slice(&[r, g, b]);
+            }
+        }
+        slots
+    }
+}
+
+impl LightSink for DmxSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        let slots = self.universe(frame);
+        self.sequence = self.sequence.wrapping_add(1);
+        let universe = self.config.universe();
+        let packet = match self.config.protocol {
+            DmxProtocol::Artnet => art_dmx(self.sequence, universe, &slots),
+            DmxProtocol::Sacn => sacn_data(&self.cid, self.sequence, universe, &slots),
+        };
+        self.socket.send_to(&packet, self.destination)?;
+        Ok(())
+    }
+}
+
+/// An ArtDmx packet. `universe` is the 15-bit port address.
+fn art_dmx(sequence: u8, universe: u16, slots: &[u8]) -> Vec<u8> {
+    let mut p = Vec::with_capacity(18 + slots.len());
+    p.extend_from_slice(ARTNET_ID);
+    p.extend_from_slice(&ARTNET_OP_DMX.to_le_bytes());
+    p.extend_from_slice(&ARTNET_VERSION.to_be_bytes());
+    p.push(sequence);
+    // Physical port
+    p.push(0);
+    p.extend_from_slice(&(universe & 0x7fff).to_le_bytes());
+    p.extend_from_slice(&(slots.len() as u16).to_be_bytes());
+    p.extend_from_slice(slots);
+    p
+}
+
+/// An E1.31 data packet: root, framing and DMP layers.
+fn sacn_data(cid: &[u8; 16], sequence: u8, universe: u16, slots: &[u8]) -> Vec<u8> {
+    let len = 126 + slots.len();
+    // Each layer starts with its length from there on, flagged 0x7
+    let pdu_length = |from: usize| (0x7000 | (len - from) as u16).to_be_bytes();
+    let mut p = Vec::with_capacity(len);
+    // Root layer
+    p.extend_from_slice(&0x0010u16.to_be_bytes());
+    p.extend_from_slice(&0u16.to_be_bytes());
+    p.extend_from_slice(ACN_ID);
+    p.extend_from_slice(&pdu_length(16));
+    p.extend_from_slice(&4u32.to_be_bytes());
+    p.extend_from_slice(cid);
+    // Framing layer
+    p.extend_from_slice(&pdu_length(38));
+    p.extend_from_slice(&2u32.to_be_bytes());
+    let mut name = [0u8; 64];
+    name[..SOURCE_NAME.len()].copy_from_slice(SOURCE_NAME.as_bytes());
+    p.extend_from_slice(&name);
+    p.push(SACN_PRIORITY);
+    // No synchronization universe
+    p.extend_from_slice(&0u16.to_be_bytes());
+    p.push(sequence);
+    // Options
+    p.push(0);
+    p.extend_from_slice(&universe.to_be_bytes());
+    // DMP layer
+    p.extend_from_slice(&pdu_length(115));
+    p.push(0x02);
+    p.push(0xa1);
+    // First property address and increment
+    p.extend_from_slice(&0u16.to_be_bytes());
+    p.extend_from_slice(&1u16.to_be_bytes());
+    // Start code and slots
+    p.extend_from_slice(&(slots.len() as u16 + 1).to_be_bytes());
+    p.push(0);
+    p.extend_from_slice(slots);
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_universe_layout() {
+        let config = DmxOutConfig {
+            start_address: 10,
+            patch: HashMap::from([(2, 100), (3, 511)]),
+            ..Default::default()
+        };
+        let sink = DmxSink::new(config).unwrap();
+        let frame = HashMap::from([
+            (0, (1, 2, 3)),
+            (1, (4, 5, 6)),
+            (2, (7, 8, 9)),
+            (3, (10, 11, 12)),
+        ]);
+        let slots = sink.universe(&frame);
+        assert_eq!(&slots[9..15], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(&slots[99..102], &[7, 8, 9]);
+        // Channel 3's fixture does not fit in the universe
+        assert_eq!(&slots[510..], &[0, 0]);
+        assert_eq!(sink.destination(), "255.255.255.255:6454".parse().unwrap());
+    }
+
+    #[test]
+    fn test_packets() {
+        let slots = [0xff; UNIVERSE_SIZE];
+        let p = art_dmx(3, 0x0102, &slots);
+        assert_eq!(p.len(), 530);
+        assert_eq!(&p[8..12], &[0x00, 0x50, 0x00, 14]);
+        assert_eq!(&p[12..18], &[3, 0, 0x02, 0x01, 0x02, 0x00]);
+
+        let p = sacn_data(&[7; 16], 3, 258, &slots);
+        assert_eq!(p.len(), 638);
+        assert_eq!(&p[4..16], ACN_ID);
+        assert_eq!(&p[16..18], &[0x72, 0x6e]);
+        assert_eq!(&p[38..40], &[0x72, 0x58]);
+        assert_eq!(&p[111..115], &[3, 0, 1, 2]);
+        assert_eq!(&p[115..117], &[0x72, 0x0b]);
+        assert_eq!(&p[123..126], &[0x02, 0x01, 0x00]);
+
+        let config = DmxOutConfig {
+            protocol: DmxProtocol::Sacn,
+            universe: Some(258),
+            ..Default::default()
+        };
+        assert_eq!(config.destination(), "239.255.1.2:5568".parse().unwrap());
+    }
+}
@@ -0,0 +1,119 @@
+//! Intensifies the output for a few seconds when a drop hits.
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How hard and how long drops are boosted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DropBoostConfig {
+    /// How long the boost lasts; it eases out over this time.
+    pub duration_ms: u64,
+    /// Brightness gain right at the drop (1.0 = none). A color stops
+    /// brightening once one of its components is full, so it keeps its hue.
+    pub brightness: f32,
+    /// Flashes per second during the boost, 0.0 for none. Never in safe mode.
+    pub strobe_hz: f32,
+}
+
+impl Default for DropBoostConfig {
+    fn default() -> Self {
+        Self {
+            duration_ms: 8000,
+            brightness: 1.6,
+            strobe_hz: 0.0,
+        }
+    }
+}
+
+/// Output stage boosting brightness, and optionally strobing, after
+/// [`trigger`](Self::trigger).
+#[derive(Debug, Clone)]
+pub struct DropBoost {
+    config: DropBoostConfig,
+    started: Option<Instant>,
+}
+
+impl DropBoost {
+    pub fn new(config: DropBoostConfig) -> Self {
+        Self {
+            config,
+            started: None,
+        }
+    }
+
+    /// Starts (or restarts) the boost at `now`.
+    pub fn trigger(&mut self, now: Instant) {
+        self.started = Some(now);
+    }
+
+    /// Boosts `frame` in place while a boost is running; strobing only with
+    /// `allow_strobe`.
+    pub fn apply<C: Rgb>(&self, frame: &mut HashMap<u8, C>, allow_strobe: bool) {
+        self.apply_at(frame, allow_strobe, Instant::now());
+    }
+
+    fn apply_at<C: Rgb>(&self, frame: &mut HashMap<u8, C>, allow_strobe: bool, now: Instant) {
+        let Some(started) = self.started else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(started);
+        let duration = Duration::from_millis(self.config.duration_ms);
+        if elapsed >= duration {
+            return;
+        }
+        // Strongest at the drop, easing out
+        let remaining = 1.0 - elapsed.as_secs_f32() / duration.as_secs_f32();
+        let intensity = remaining * remaining;
+        let mut gain = 1.0 + (self.config.brightness.max(0.0) - 1.0) * intensity;
+        if allow_strobe && self.config.strobe_hz > 0.0 {
+            let phase = (elapsed.as_secs_f32() * self.config.strobe_hz).fract();
+            if phase >= 0.5 {
+                gain *= 1.0 - intensity;
+            }
+        }
+        for color in frame.values_mut() {
+            let rgb = color.to_rgb();
+            // Scaled as a whole, so no component clips and the hue holds
+            let brightest = rgb.into_iter().fold(0.0, f32::max);
+            let gain = if brightest > 0.0 {
+                gain.min(255.0 / brightest)
+            } else {
+                gain
+            };
+            *color = C::from_rgb(rgb.map(|v| v * gain));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boost_eases_out() {
+        let mut boost = DropBoost::new(DropBoostConfig {
+            duration_ms: 1000,
+            brightness: 2.0,
+            strobe_hz: 4.0,
+        });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let apply = |boost: &DropBoost, allow_strobe, ms| {
+            let mut frame = HashMap::from([(0, (100, 50, 200))]);
+            boost.apply_at(&mut frame, allow_strobe, at(ms));
+            frame[&0]
+        };
+
+        assert_eq!(apply(&boost, true, 0), (100, 50, 200));
+        boost.trigger(start);
+        // Blue is full at 1.275 times, and red and green stop there too
+        assert_eq!(apply(&boost, true, 0), (128, 64, 255));
+        assert_eq!(apply(&boost, true, 500), (125, 63, 250));
+        // Second half of a strobe cycle
+        assert_eq!(apply(&boost, true, 150), (48, 24, 96));
+        assert_eq!(apply(&boost, false, 150), (128, 64, 255));
+        assert_eq!(apply(&boost, true, 1000), (100, 50, 200));
+    }
+}
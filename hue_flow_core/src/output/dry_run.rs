@@ -0,0 +1,130 @@
+//! Prints frames instead of streaming them, for debugging effects without
+//! touching the lights or the bridge.
+//!
+//! Frames still go through the [`ProtocolEncoder`], so the sequence numbers,
+//! message sizes and the 60 Hz limit are those a real stream would see.
+use crate::output::LightSink;
+use crate::stream::protocol::ProtocolEncoder;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::time::Instant;
+
+/// How [`DryRunSink`] prints frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DryRunFormat {
+    /// One readable line per frame.
+    #[default]
+    Console,
+    /// One JSON object per frame, for scripts.
+    Jsonl,
+}
+
+/// A frame as printed in the JSONL format.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DryRunFrame {
+    /// Time since the first frame.
+    pub t_ms: u64,
+    pub sequence: u8,
+    /// Size of the encoded message.
+    pub bytes: usize,
+    /// Channel ID -> RGB.
+    pub channels: BTreeMap<u8, [u8; 3]>,
+}
+
+/// Encodes every frame as for the bridge and prints it.
+pub struct DryRunSink {
+    encoder: ProtocolEncoder,
+    format: DryRunFormat,
+    started: Option<Instant>,
+    out: Box<dyn Write + Send>,
+}
+
+impl DryRunSink {
+    /// Prints frames for the area `area_id` to stdout.
+    pub fn stdout(area_id: impl Into<String>, format: DryRunFormat) -> Self {
+        Self::new(area_id, format, Box::new(std::io::stdout()))
+    }
+
+    pub fn new(
+        area_id: impl Into<String>,
+        format: DryRunFormat,
+        out: Box<dyn Write + Send>,
+    ) -> Self {
+        Self {
+            encoder: ProtocolEncoder::new(area_id),
+            format,
+            started: None,
+            out,
+        }
+    }
+
+    /// Encodes `frame` and formats it; `None` when the encoder refuses it
+    /// (sooner than 60 Hz after the previous one), as the bridge stream
+    /// would skip it.
+    pub fn line(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> Option<String> {
+        let sequence = self.encoder.sequence();
+        let message = self.encoder.encode_pooled(frame)?;
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let record = DryRunFrame {
+            t_ms: started.elapsed().as_millis() as u64,
+            sequence,
+            bytes: message.len(),
+            channels: frame
+                .iter()
+                .map(|(&id, &(r, g, b))| (id, [r, g, b]))
+                .collect(),
+        };
+        Some(match self.format {
+            DryRunFormat::Jsonl => serde_json::to_string(&record).ok()?,
+            DryRunFormat::Console => {
+                let channels: Vec<String> = record
+                    .channels
+                    .iter()
+                    .map(|(id, [r, g, b])| format!("{}:#{:02x}{:02x}{:02x}", id, r, g, b))
+                    .collect();
+                format!(
+                    "{:>7.3}s  seq {:>3}  {:>3} B  {}",
+                    record.t_ms as f64 / 1000.0,
+                    record.sequence,
+                    record.bytes,
+                    channels.join(" ")
+                )
+            }
+        })
+    }
+}
+
+impl LightSink for DryRunSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        if let Some(line) = self.line(frame) {
+            writeln!(self.out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: &str = "1a8d99cc-967b-44f2-9202-43f976c0fa6b";
+
+    #[test]
+    fn test_dry_run_lines() {
+        let frame = HashMap::from([(1, (0, 128, 255)), (0, (255, 0, 0))]);
+        let mut sink = DryRunSink::new(AREA, DryRunFormat::Jsonl, Box::new(std::io::sink()));
+        let line = sink.line(&frame).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["sequence"], 0);
+        // 16-byte header, area ID, 7 bytes per channel
+        assert_eq!(value["bytes"], 16 + 36 + 2 * 7);
+        assert_eq!(value["channels"]["1"], serde_json::json!([0, 128, 255]));
+        // Too soon after the previous frame for the bridge
+        assert!(sink.line(&frame).is_none());
+
+        let mut sink = DryRunSink::new(AREA, DryRunFormat::Console, Box::new(std::io::sink()));
+        let line = sink.line(&frame).unwrap();
+        assert!(line.ends_with("0:#ff0000 1:#0080ff"), "{}", line);
+    }
+}
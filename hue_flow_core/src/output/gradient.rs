@@ -0,0 +1,152 @@
+//! Gradients across devices with several channels in a line (gradient
+//! lightstrips and tubes).
+//!
+//! The bridge gives each segment of such a device its own channel, all
+//! sharing the device's light ID. Effects describe a color at any position
+//! along the device with a [`Gradient`], and [`paint_strips`] resamples it
+//! onto however many segments each device has.
+use crate::color::mix;
+use crate::effects::Frame;
+use crate::models::LightNode;
+
+/// Colors at positions from 0.0 (start of a device) to 1.0 (its end), with
+/// linear blending in between.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Gradient {
+    stops: Vec<(f32, (u8, u8, u8))>,
+}
+
+impl Gradient {
+    /// Stops may come in any order; positions are clamped to 0.0 - 1.0.
+    pub fn new(stops: impl IntoIterator<Item = (f32, (u8, u8, u8))>) -> Self {
+        let mut stops: Vec<_> = stops
+            .into_iter()
+            .map(|(at, color)| (at.clamp(0.0, 1.0), color))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// Evenly spaced colors from start to end.
+    pub fn even(colors: &[(u8, u8, u8)]) -> Self {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, *color)),
+        )
+    }
+
+    /// The color at `at`; before the first stop and after the last the
+    /// nearest stop's color. Black without stops.
+    pub fn sample(&self, at: f32) -> (u8, u8, u8) {
+        let next = self.stops.partition_point(|(position, _)| *position < at);
+        match (
+            next.checked_sub(1).map(|i| self.stops[i]),
+            self.stops.get(next),
+        ) {
+            (Some((from_at, from)), Some(&(to_at, to))) => {
+                let span = to_at - from_at;
+                let t = if span > 0.0 {
+                    (at - from_at) / span
+                } else {
+                    1.0
+                };
+                mix(from, to, t)
+            }
+            (Some((_, color)), None) | (None, Some(&(_, color))) => color,
+            (None, None) => (0, 0, 0),
+        }
+    }
+}
+
+/// Devices with more than one channel in `nodes`: the light ID and its
+/// channels in segment order ([`LightNode::segment`]; channel IDs need not
+/// follow the segments).
+pub fn strips(nodes: &[LightNode]) -> Vec<(&str, Vec<u8>)> {
+    let mut devices: Vec<(&str, Vec<&LightNode>)> = Vec::new();
+    for node in nodes {
+        match devices.iter_mut().find(|(id, _)| *id == node.id) {
+            Some((_, segments)) => segments.push(node),
+            None => devices.push((&node.id, vec![node])),
+        }
+    }
+    devices
+        .into_iter()
+        .filter(|(_, segments)| segments.len() > 1)
+        .map(|(id, mut segments)| {
+            segments.sort_by_key(|node| (node.segment, node.channel_id));
+            (id, segments.iter().map(|node| node.channel_id).collect())
+        })
+        .collect()
+}
+
+/// Paints the gradient `for_strip` picks for each multi-channel device onto
+/// its channels, sampling at the center of each segment. Single-channel
+/// lights in `frame` are left alone.
+pub fn paint_strips<'a>(
+    frame: &mut Frame,
+    nodes: &'a [LightNode],
+    mut for_strip: impl FnMut(&'a str) -> Gradient,
+) {
+    for (id, channels) in strips(nodes) {
+        let gradient = for_strip(id);
+        let count = channels.len() as f32;
+        for (i, channel) in channels.into_iter().enumerate() {
+            frame.insert(channel, gradient.sample((i as f32 + 0.5) / count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, channel_id: u8, segment: u32) -> LightNode {
+        LightNode {
+            id: id.to_string(),
+            channel_id,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            segment,
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let gradient = Gradient::new([(1.0, (0, 0, 200)), (0.5, (200, 0, 0))]);
+        assert_eq!(gradient.sample(0.0), (200, 0, 0));
+        assert_eq!(gradient.sample(0.75), (100, 0, 100));
+        assert_eq!(gradient.sample(1.0), (0, 0, 200));
+        assert_eq!(Gradient::default().sample(0.3), (0, 0, 0));
+        assert_eq!(Gradient::even(&[(10, 20, 30)]).sample(0.9), (10, 20, 30));
+    }
+
+    #[test]
+    fn test_paint_strips() {
+        // A five-segment strip between two bulbs, its channels not in
+        // segment order
+        let nodes = vec![
+            node("bulb", 0, 0),
+            node("strip", 3, 0),
+            node("strip", 1, 1),
+            node("strip", 2, 2),
+            node("strip", 4, 3),
+            node("strip", 5, 4),
+            node("other bulb", 6, 0),
+        ];
+        assert_eq!(strips(&nodes), vec![("strip", vec![3, 1, 2, 4, 5])]);
+
+        let mut frame = Frame::from([(0, (9, 9, 9))]);
+        paint_strips(&mut frame, &nodes, |_| {
+            Gradient::even(&[(0, 0, 0), (250, 250, 250)])
+        });
+        assert_eq!(frame[&0], (9, 9, 9));
+        assert_eq!(frame[&3], (25, 25, 25));
+        assert_eq!(frame[&2], (125, 125, 125));
+        assert_eq!(frame[&5], (225, 225, 225));
+        assert!(!frame.contains_key(&6));
+    }
+}
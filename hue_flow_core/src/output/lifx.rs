@@ -0,0 +1,193 @@
+//! LIFX bulbs over the LAN protocol, placed in the room next to the Hue
+//! lights.
+use crate::models::LightNode;
+use crate::output::{place_lights, LightSink, PlacedLight};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// UDP port of the LAN protocol.
+pub const LIFX_PORT: u16 = 56700;
+/// LIFX asks for at most 20 messages per second per bulb.
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest fade sent along with a change: a bulb that was left alone for a
+/// while still follows the next change within a couple of updates.
+const MAX_FADE: Duration = Duration::from_millis(100);
+/// Color temperature sent along with colors; only used for whites.
+const KELVIN: u16 = 3500;
+
+const PROTOCOL: u16 = 1024;
+const ADDRESSABLE: u16 = 1 << 12;
+const TAGGED: u16 = 1 << 13;
+const SET_COLOR: u16 = 102;
+/// Identifies our messages in replies (which we do not ask for).
+const SOURCE: u32 = 0x4846_4c57;
+
+struct Bulb {
+    addr: SocketAddr,
+    channel: u8,
+    last: Option<((u8, u8, u8), Instant)>,
+}
+
+/// Drives LIFX bulbs addressed by IP, each a light of its own in the layout
+/// (see [`HueFlowBuilder::mirror`](crate::HueFlowBuilder::mirror)).
+///
+/// Bulbs only hear about changes, at most 20 times per second, and fade
+/// over the time since their last update (up to [`MAX_FADE`]) so they move
+/// smoothly.
+pub struct LifxSink {
+    lights: Vec<PlacedLight>,
+    bulbs: Vec<Bulb>,
+    socket: UdpSocket,
+    sequence: u8,
+}
+
+impl LifxSink {
+    /// `lights` are placed bulbs with their IP address as ID.
+    pub fn new(lights: Vec<PlacedLight>) -> anyhow::Result<Self> {
+        let bulbs = lights
+            .iter()
+            .map(|light| {
+                let ip: IpAddr = light
+                    .id
+                    .parse()
+                    .with_context(|| format!("LIFX bulb '{}' is not an IP address", light.id))?;
+                Ok(Bulb {
+                    addr: SocketAddr::new(ip, LIFX_PORT),
+                    channel: 0,
+                    last: None,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            lights,
+            bulbs,
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            sequence: 0,
+        })
+    }
+}
+
+impl LightSink for LifxSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        let now = Instant::now();
+        for bulb in &mut self.bulbs {
+            let color = frame.get(&bulb.channel).copied().unwrap_or_default();
+            let Some(fade) = fade(bulb.last, color, now) else {
+                continue;
+            };
+            self.sequence = self.sequence.wrapping_add(1);
+            self.socket
+                .send_to(&set_color(self.sequence, color, fade), bulb.addr)?;
+            bulb.last = Some((color, now));
+        }
+        Ok(())
+    }
+
+    fn virtual_nodes(&mut self, first_channel: u8) -> Vec<LightNode> {
+        let nodes = place_lights(&self.lights, first_channel);
+        for (bulb, node) in self.bulbs.iter_mut().zip(&nodes) {
+            bulb.channel = node.channel_id;
+        }
+        nodes
+    }
+}
+
+/// Fade time for sending `color` to a bulb last sent `last`, or `None` when
+/// it is unchanged or was updated too recently.
+fn fade(
+    last: Option<((u8, u8, u8), Instant)>,
+    color: (u8, u8, u8),
+    now: Instant,
+) -> Option<Duration> {
+    match last {
+        Some((last, _)) if last == color => None,
+        Some((_, at)) if now.duration_since(at) < MIN_INTERVAL => None,
+        Some((_, at)) => Some(now.duration_since(at).min(MAX_FADE)),
+        None => Some(Duration::ZERO),
+    }
+}
+
+/// A SetColor message to whichever bulb receives it.
+fn set_color(sequence: u8, color: (u8, u8, u8), fade: Duration) -> Vec<u8> {
+    let (hue, saturation, brightness) = hsb(color);
+    let mut m = Vec::with_capacity(49);
+    // Frame header
+    m.extend_from_slice(&49u16.to_le_bytes());
+    m.extend_from_slice(&(PROTOCOL | ADDRESSABLE | TAGGED).to_le_bytes());
+    m.extend_from_slice(&SOURCE.to_le_bytes());
+    // Frame address: no target MAC, no acknowledgement
+    m.extend_from_slice(&[0; 8 + 6]);
+    m.push(0);
+    m.push(sequence);
+    // Protocol header
+    m.extend_from_slice(&[0; 8]);
+    m.extend_from_slice(&SET_COLOR.to_le_bytes());
+    m.extend_from_slice(&[0; 2]);
+    // Payload
+    m.push(0);
+    for value in [hue, saturation, brightness, KELVIN] {
+        m.extend_from_slice(&value.to_le_bytes());
+    }
+    m.extend_from_slice(&(fade.as_millis().min(u32::MAX as u128) as u32).to_le_bytes());
+    m
+}
+
+/// RGB to LIFX's 16-bit hue, saturation and brightness.
+fn hsb((r, g, b): (u8, u8, u8)) -> (u16, u16, u16) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    } / 6.0;
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    let scale = |v: f32| (v * 65535.0).round() as u16;
+    (scale(hue), scale(saturation), scale(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_color_message() {
+        let m = set_color(7, (0, 255, 0), Duration::from_millis(120));
+        assert_eq!(m.len(), 49);
+        assert_eq!(&m[0..2], &49u16.to_le_bytes());
+        assert_eq!(&m[2..4], &0x3400u16.to_le_bytes());
+        assert_eq!(m[23], 7);
+        assert_eq!(&m[32..34], &SET_COLOR.to_le_bytes());
+        // Green: a third around the hue circle, full saturation and brightness
+        assert_eq!(&m[37..39], &21845u16.to_le_bytes());
+        assert_eq!(&m[39..43], &[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(&m[45..49], &120u32.to_le_bytes());
+
+        assert_eq!(hsb((0, 0, 0)), (0, 0, 0));
+        assert_eq!(hsb((255, 0, 0)).0, 0);
+    }
+
+    #[test]
+    fn test_fade_is_capped() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let red = (255, 0, 0);
+        let blue = (0, 0, 255);
+        assert_eq!(fade(None, red, start), Some(Duration::ZERO));
+        assert_eq!(fade(Some((red, start)), red, at(500)), None);
+        assert_eq!(fade(Some((red, start)), blue, at(20)), None);
+        assert_eq!(
+            fade(Some((red, start)), blue, at(70)),
+            Some(Duration::from_millis(70))
+        );
+        // Not a slow fade after a long static scene
+        assert_eq!(fade(Some((red, start)), blue, at(60_000)), Some(MAX_FADE));
+    }
+}
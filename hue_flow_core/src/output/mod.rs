@@ -0,0 +1,74 @@
+//! Output stages applied to effect frames before they are streamed, and
+//! sinks that can receive frames instead of the bridge.
+pub mod ambient;
+pub mod blackout;
+pub mod calibration;
+pub mod circadian;
+pub mod color_pipeline;
+#[cfg(feature = "bridge")]
+pub mod companion;
+pub mod dmx;
+pub mod drop_boost;
+#[cfg(feature = "bridge")]
+pub mod dry_run;
+pub mod gradient;
+pub mod lifx;
+pub mod nanoleaf;
+pub mod openrgb;
+pub mod overrides;
+pub mod rooms;
+pub mod safe_mode;
+pub mod simulator;
+pub mod smoothing;
+pub mod theater;
+pub mod zigbee2mqtt;
+
+use crate::models::LightNode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// First channel ID handed to virtual nodes, above any entertainment area's
+/// channels (the bridge streams to at most 20).
+pub const VIRTUAL_CHANNEL_BASE: u8 = 64;
+
+/// Destination for rendered frames (channel id -> RGB).
+pub trait LightSink: Send {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()>;
+
+    /// Lights of its own this sink adds to the layout effects render, e.g.
+    /// PC peripherals or other brands' lamps placed in the room. Called once
+    /// before the first frame; the sink numbers its lights from
+    /// `first_channel` and finds their colors under those channels in every
+    /// frame. Virtual channels are never streamed to the bridge.
+    fn virtual_nodes(&mut self, _first_channel: u8) -> Vec<LightNode> {
+        Vec::new()
+    }
+}
+
+/// A light outside the entertainment area, placed in the room's coordinates
+/// (-1.0 - 1.0, as the bridge reports channel positions).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlacedLight {
+    /// Device-specific address: a panel ID, an IP address, ...
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub z: f64,
+}
+
+/// Layout nodes for `lights`, numbered from `first_channel`.
+pub fn place_lights(lights: &[PlacedLight], first_channel: u8) -> Vec<LightNode> {
+    lights
+        .iter()
+        .zip(first_channel..)
+        .map(|(light, channel_id)| LightNode {
+            id: light.id.clone(),
+            channel_id,
+            x: light.x,
+            y: light.y,
+            z: light.z,
+            segment: 0,
+        })
+        .collect()
+}
@@ -0,0 +1,159 @@
+//! Nanoleaf panels over the External Control (v2) UDP streaming protocol.
+use crate::models::LightNode;
+use crate::output::{place_lights, LightSink, PlacedLight};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+/// Port of the controller's REST API.
+pub const NANOLEAF_API_PORT: u16 = 16021;
+/// UDP port frames are streamed to once external control is on.
+pub const NANOLEAF_STREAM_PORT: u16 = 60222;
+/// Fade per frame, in the protocol's 100 ms steps.
+const TRANSITION: u16 = 1;
+
+/// A Nanoleaf controller and where its panels are in the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NanoleafConfig {
+    pub ip: String,
+    /// Auth token from `POST /api/v1/new` while the power button is held.
+    pub token: String,
+    /// Panels with their panel ID (see `GET /api/v1/<token>/panelLayout/layout`).
+    pub panels: Vec<PlacedLight>,
+}
+
+/// Streams frames to the panels of one controller, each panel a light of its
+/// own in the layout (see [`HueFlowBuilder::mirror`](crate::HueFlowBuilder::mirror)).
+///
+/// The controller must be switched to external control first; see
+/// [`enable_streaming`].
+pub struct NanoleafSink {
+    panels: Vec<PlacedLight>,
+    /// Panel ID and channel of each panel.
+    channels: Vec<(u16, u8)>,
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl NanoleafSink {
+    pub fn new(config: &NanoleafConfig) -> anyhow::Result<Self> {
+        let ip: IpAddr = config
+            .ip
+            .parse()
+            .with_context(|| format!("Invalid Nanoleaf address '{}'", config.ip))?;
+        let channels = config
+            .panels
+            .iter()
+            .map(|p| {
+                let id =
+                    p.id.parse()
+                        .with_context(|| format!("Invalid Nanoleaf panel ID '{}'", p.id))?;
+                Ok((id, 0))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            panels: config.panels.clone(),
+            channels,
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            target: SocketAddr::new(ip, NANOLEAF_STREAM_PORT),
+        })
+    }
+}
+
+impl LightSink for NanoleafSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        let panels = self
+            .channels
+            .iter()
+            .map(|(id, channel)| (*id, frame.get(channel).copied().unwrap_or_default()));
+        self.socket.send_to(&encode_frame(panels), self.target)?;
+        Ok(())
+    }
+
+    fn virtual_nodes(&mut self, first_channel: u8) -> Vec<LightNode> {
+        let nodes = place_lights(&self.panels, first_channel);
+        for (panel, node) in self.channels.iter_mut().zip(&nodes) {
+            panel.1 = node.channel_id;
+        }
+        nodes
+    }
+}
+
+/// A v2 frame: panel count, then per panel its ID, R, G, B, W and the
+/// transition time (all integers big-endian).
+fn encode_frame(panels: impl ExactSizeIterator<Item = (u16, (u8, u8, u8))>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(2 + 8 * panels.len());
+    bytes.extend_from_slice(&(panels.len() as u16).to_be_bytes());
+    for (id, (r, g, b)) in panels {
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&[r, g, b, 0]);
+        bytes.extend_from_slice(&TRANSITION.to_be_bytes());
+    }
+    bytes
+}
+
+/// Switches the controller to external control so it accepts streamed frames.
+#[cfg(feature = "bridge")]
+pub async fn enable_streaming(config: &NanoleafConfig) -> anyhow::Result<()> {
+    let base_url = format!("http://{}:{}", config.ip, NANOLEAF_API_PORT);
+    enable_streaming_at(&base_url, &config.token).await
+}
+
+#[cfg(feature = "bridge")]
+async fn enable_streaming_at(base_url: &str, token: &str) -> anyhow::Result<()> {
+    let body = serde_json::json!({
+        "write": {
+            "command": "display",
+            "animType": "extControl",
+            "extControlVersion": "v2"
+        }
+    });
+    let resp = reqwest::Client::new()
+        .put(format!("{}/api/v1/{}/effects", base_url, token))
+        .json(&body)
+        .send()
+        .await
+        .context("Nanoleaf controller unreachable")?;
+    if !resp.status().is_success() {
+        anyhow::bail!("Nanoleaf refused external control: HTTP {}", resp.status());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_frame() {
+        let frame = encode_frame([(0x1234, (255, 128, 0)), (7, (0, 0, 9))].into_iter());
+        assert_eq!(
+            frame,
+            vec![0, 2, 0x12, 0x34, 255, 128, 0, 0, 0, 1, 0, 7, 0, 0, 9, 0, 0, 1]
+        );
+    }
+
+    #[cfg(feature = "bridge")]
+    #[tokio::test]
+    async fn test_enable_streaming() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/api/v1/token123/effects"))
+            .and(body_partial_json(
+                serde_json::json!({ "write": { "animType": "extControl" } }),
+            ))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        enable_streaming_at(&server.uri(), "token123")
+            .await
+            .unwrap();
+        assert!(enable_streaming_at(&server.uri(), "wrong").await.is_err());
+    }
+}
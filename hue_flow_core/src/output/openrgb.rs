@@ -0,0 +1,465 @@
+//! Mirrors frames to PC peripherals (keyboards, mice, case lighting) through
+//! an [OpenRGB](https://openrgb.org) SDK server.
+use crate::effects::Frame;
+use crate::models::LightNode;
+use crate::output::{LightSink, VIRTUAL_CHANNEL_BASE};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+/// Default address of the OpenRGB SDK server.
+pub const DEFAULT_OPENRGB_ADDR: &str = "127.0.0.1:6742";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Wait between reconnection attempts after the server went away.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+/// Frames waiting for the writer thread; newer ones are dropped beyond.
+const FRAME_QUEUE: usize = 2;
+
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const SET_CLIENT_NAME: u32 = 50;
+const UPDATE_LEDS: u32 = 1050;
+const SET_CUSTOM_MODE: u32 = 1100;
+
+/// How peripherals get their colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenRgbMapping {
+    /// Every device shows the average color of the room's lights.
+    #[default]
+    Average,
+    /// Every device is a light of its own in the layout the effect renders,
+    /// at its configured position.
+    Nodes,
+}
+
+/// OpenRGB output settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenRgbConfig {
+    #[serde(default = "default_address")]
+    pub address: String,
+    #[serde(default)]
+    pub mapping: OpenRgbMapping,
+    /// Room positions (x, y) of the devices in OpenRGB's order, for the
+    /// `nodes` mapping. Devices without one sit in the middle of the room.
+    #[serde(default)]
+    pub positions: Vec<(f64, f64)>,
+}
+
+fn default_address() -> String {
+    DEFAULT_OPENRGB_ADDR.to_string()
+}
+
+impl Default for OpenRgbConfig {
+    fn default() -> Self {
+        Self {
+            address: default_address(),
+            mapping: OpenRgbMapping::default(),
+            positions: Vec::new(),
+        }
+    }
+}
+
+/// A device reported by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenRgbDevice {
+    pub name: String,
+    pub leds: usize,
+}
+
+/// Sink writing to every device of an OpenRGB server, in step with the
+/// frames streamed to the bridge (see [`HueFlowBuilder::mirror`](crate::HueFlowBuilder::mirror)).
+///
+/// The socket is written on a thread of its own, so a slow server never
+/// holds up rendering: frames it cannot keep up with are dropped. When the
+/// server goes away, frames are dropped too and the connection is retried
+/// every few seconds.
+pub struct OpenRgbSink {
+    config: OpenRgbConfig,
+    devices: Vec<OpenRgbDevice>,
+    /// Frames for the writer thread, with the first device's channel.
+    frames: SyncSender<(u8, Frame)>,
+    /// Channel of the first device's virtual node.
+    first_channel: u8,
+}
+
+impl OpenRgbSink {
+    /// Connects to the server and switches all devices to direct control.
+    pub fn connect(config: OpenRgbConfig) -> anyhow::Result<Self> {
+        let (stream, devices) = open(&config.address)?;
+        let (frames, queued) = mpsc::sync_channel(FRAME_QUEUE);
+        let writer = Writer {
+            config: config.clone(),
+            devices: devices.clone(),
+            stream: Some(stream),
+            last_attempt: Instant::now(),
+        };
+        std::thread::Builder::new()
+            .name("openrgb".to_string())
+            .spawn(move || writer.run(queued))?;
+        Ok(Self {
+            config,
+            devices,
+            frames,
+            first_channel: VIRTUAL_CHANNEL_BASE,
+        })
+    }
+
+    pub fn devices(&self) -> &[OpenRgbDevice] {
+        &self.devices
+    }
+}
+
+impl LightSink for OpenRgbSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        match self.frames.try_send((self.first_channel, frame.clone())) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => bail!("OpenRGB writer stopped"),
+        }
+    }
+
+    fn virtual_nodes(&mut self, first_channel: u8) -> Vec<LightNode> {
+        if self.config.mapping != OpenRgbMapping::Nodes {
+            return Vec::new();
+        }
+        self.first_channel = first_channel;
+        self.devices
+            .iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let (x, y) = self.config.positions.get(i).copied().unwrap_or_default();
+                LightNode {
+                    id: format!("openrgb:{}", device.name),
+                    channel_id: first_channel.saturating_add(i as u8),
+                    x,
+                    y,
+                    z: 0.0,
+                    segment: 0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Owns the connection and writes the frames of an [`OpenRgbSink`] until
+/// the sink is dropped.
+struct Writer {
+    config: OpenRgbConfig,
+    devices: Vec<OpenRgbDevice>,
+    stream: Option<TcpStream>,
+    last_attempt: Instant,
+}
+
+impl Writer {
+    fn run(mut self, frames: Receiver<(u8, Frame)>) {
+        for (first_channel, frame) in frames {
+            if let Err(e) = self.write(first_channel, &frame) {
+                tracing::warn!("{:#}", e);
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        first_channel: u8,
+        frame: &HashMap<u8, (u8, u8, u8)>,
+    ) -> anyhow::Result<()> {
+        if self.stream.is_none() {
+            if self.last_attempt.elapsed() < RECONNECT_INTERVAL {
+                return Ok(());
+            }
+            self.last_attempt = Instant::now();
+            // Device lists may have changed while the server was away
+            if let Ok((stream, devices)) = open(&self.config.address) {
+                self.stream = Some(stream);
+                self.devices = devices;
+            }
+        }
+        // One write per frame keeps all devices in step
+        let mut packets = Vec::new();
+        for (i, device) in self.devices.iter().enumerate() {
+            let color = match self.config.mapping {
+                OpenRgbMapping::Average => average(frame),
+                OpenRgbMapping::Nodes => frame
+                    .get(&first_channel.saturating_add(i as u8))
+                    .copied()
+                    .unwrap_or_default(),
+            };
+            packets.extend(update_leds(i as u32, color, device.leds));
+        }
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+        if let Err(e) = stream.write_all(&packets) {
+            self.stream = None;
+            self.last_attempt = Instant::now();
+            return Err(e).context("OpenRGB server disconnected");
+        }
+        Ok(())
+    }
+}
+
+/// Average color of the room's lights (virtual nodes left out).
+fn average(frame: &HashMap<u8, (u8, u8, u8)>) -> (u8, u8, u8) {
+    let (mut sum, mut count) = ([0u32; 3], 0u32);
+    for (_, (r, g, b)) in frame.iter().filter(|(c, _)| **c < VIRTUAL_CHANNEL_BASE) {
+        sum[0] += *r as u32;
+        sum[1] += *g as u32;
+        sum[2] += *b as u32;
+        count += 1;
+    }
+    if count == 0 {
+        return (0, 0, 0);
+    }
+    let avg = |s: u32| (s / count) as u8;
+    (avg(sum[0]), avg(sum[1]), avg(sum[2]))
+}
+
+/// Connects, introduces the client and lists the devices.
+fn open(address: &str) -> anyhow::Result<(TcpStream, Vec<OpenRgbDevice>)> {
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("Cannot resolve OpenRGB address {}", address))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .with_context(|| format!("Cannot reach OpenRGB server at {}", address))?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+
+    stream.write_all(&packet(0, SET_CLIENT_NAME, b"HueFlow\0"))?;
+    stream.write_all(&packet(0, REQUEST_CONTROLLER_COUNT, &[]))?;
+    let reply = read_reply(&mut stream, REQUEST_CONTROLLER_COUNT)?;
+    let count = u32::from_le_bytes(reply.get(..4).context("Short OpenRGB reply")?.try_into()?);
+
+    let mut devices = Vec::new();
+    for index in 0..count {
+        stream.write_all(&packet(index, REQUEST_CONTROLLER_DATA, &[]))?;
+        let data = read_reply(&mut stream, REQUEST_CONTROLLER_DATA)?;
+        devices.push(parse_controller(&data).context("Malformed OpenRGB controller data")?);
+        stream.write_all(&packet(index, SET_CUSTOM_MODE, &[]))?;
+    }
+    Ok((stream, devices))
+}
+
+/// An SDK packet: "ORGB", device index, packet ID, payload size, payload.
+fn packet(device: u32, id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16 + payload.len());
+    bytes.extend_from_slice(b"ORGB");
+    bytes.extend_from_slice(&device.to_le_bytes());
+    bytes.extend_from_slice(&id.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Sets all `leds` of a device to `color`.
+fn update_leds(device: u32, (r, g, b): (u8, u8, u8), leds: usize) -> Vec<u8> {
+    let leds = leds.min(u16::MAX as usize);
+    let size = 4 + 2 + 4 * leds;
+    let mut payload = Vec::with_capacity(size);
+    payload.extend_from_slice(&(size as u32).to_le_bytes());
+    payload.extend_from_slice(&(leds as u16).to_le_bytes());
+    for _ in 0..leds {
+        payload.extend_from_slice(&[r, g, b, 0]);
+    }
+    packet(device, UPDATE_LEDS, &payload)
+}
+
+/// Reads packets until the reply to `id`, returning its payload.
+fn read_reply(stream: &mut TcpStream, id: u32) -> anyhow::Result<Vec<u8>> {
+    loop {
+        let mut header = [0u8; 16];
+        stream.read_exact(&mut header)?;
+        if &header[..4] != b"ORGB" {
+            bail!("Not an OpenRGB server");
+        }
+        let packet_id = u32::from_le_bytes(header[8..12].try_into()?);
+        let size = u32::from_le_bytes(header[12..16].try_into()?) as usize;
+        let mut payload = vec![0u8; size];
+        stream.read_exact(&mut payload)?;
+        if packet_id == id {
+            return Ok(payload);
+        }
+    }
+}
+
+/// Reads controller data of protocol version 0, keeping the name and the
+/// LED count.
+fn parse_controller(data: &[u8]) -> Option<OpenRgbDevice> {
+    let mut r = Reader { data, pos: 0 };
+    r.skip(4 + 4)?; // data size, device type
+    let name = r.string()?;
+    for _ in 0..4 {
+        r.string()?; // description, version, serial, location
+    }
+    let modes = r.u16()?;
+    r.skip(4)?; // active mode
+    for _ in 0..modes {
+        r.string()?;
+        r.skip(4 * 9)?; // value, flags, speed and color limits, speed, direction, color mode
+        let colors = r.u16()? as usize;
+        r.skip(4 * colors)?;
+    }
+    let zones = r.u16()?;
+    for _ in 0..zones {
+        r.string()?;
+        r.skip(4 * 4)?; // type, LED limits and count
+        let matrix = r.u16()? as usize;
+        r.skip(matrix)?;
+    }
+    let leds = r.u16()?;
+    for _ in 0..leds {
+        r.string()?;
+        r.skip(4)?; // value
+    }
+    Some(OpenRgbDevice {
+        name,
+        leds: leds as usize,
+    })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn skip(&mut self, n: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.skip(2)?.try_into().ok()?))
+    }
+
+    /// Length-prefixed, NUL-terminated string.
+    fn string(&mut self) -> Option<String> {
+        let len = self.u16()? as usize;
+        let bytes = self.skip(len)?;
+        let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Some(String::from_utf8_lossy(text).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u16 + 1).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+        out.push(0);
+    }
+
+    /// Controller data for a device with one mode, one zone and `leds` LEDs.
+    fn controller(name: &str, leds: u16) -> Vec<u8> {
+        let mut d = vec![0; 8];
+        string(&mut d, name);
+        for field in ["desc", "1.0", "serial", "usb"] {
+            string(&mut d, field);
+        }
+        d.extend_from_slice(&1u16.to_le_bytes());
+        d.extend_from_slice(&0i32.to_le_bytes());
+        string(&mut d, "Direct");
+        d.extend_from_slice(&[0; 36]);
+        d.extend_from_slice(&1u16.to_le_bytes());
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&1u16.to_le_bytes());
+        string(&mut d, "Keys");
+        d.extend_from_slice(&[0; 16]);
+        d.extend_from_slice(&0u16.to_le_bytes());
+        d.extend_from_slice(&leds.to_le_bytes());
+        for i in 0..leds {
+            string(&mut d, &format!("Key {}", i));
+            d.extend_from_slice(&[0; 4]);
+        }
+        d.extend_from_slice(&leds.to_le_bytes());
+        d.extend(std::iter::repeat_n(0, 4 * leds as usize));
+        d
+    }
+
+    #[test]
+    fn test_lists_devices_and_mirrors_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            loop {
+                let mut header = [0u8; 16];
+                if s.read_exact(&mut header).is_err() {
+                    return received;
+                }
+                let device = u32::from_le_bytes(header[4..8].try_into().unwrap());
+                let id = u32::from_le_bytes(header[8..12].try_into().unwrap());
+                let mut payload =
+                    vec![0; u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize];
+                s.read_exact(&mut payload).unwrap();
+                let reply = match id {
+                    REQUEST_CONTROLLER_COUNT => Some(2u32.to_le_bytes().to_vec()),
+                    REQUEST_CONTROLLER_DATA => Some(controller(
+                        ["Keyboard", "Mouse"][device as usize],
+                        3 - device as u16,
+                    )),
+                    _ => None,
+                };
+                match reply {
+                    Some(reply) => s.write_all(&packet(device, id, &reply)).unwrap(),
+                    None => received.push((device, id, payload)),
+                }
+            }
+        });
+
+        let config = OpenRgbConfig {
+            address,
+            mapping: OpenRgbMapping::Nodes,
+            positions: vec![(0.0, 1.0)],
+        };
+        let mut sink = OpenRgbSink::connect(config).unwrap();
+        let names: Vec<_> = sink
+            .devices()
+            .iter()
+            .map(|d| (d.name.as_str(), d.leds))
+            .collect();
+        assert_eq!(names, vec![("Keyboard", 3), ("Mouse", 2)]);
+
+        let nodes = sink.virtual_nodes(VIRTUAL_CHANNEL_BASE);
+        assert_eq!(nodes[0].channel_id, VIRTUAL_CHANNEL_BASE);
+        assert_eq!((nodes[0].x, nodes[0].y), (0.0, 1.0));
+        assert_eq!((nodes[1].x, nodes[1].y), (0.0, 0.0));
+
+        let frame = HashMap::from([(0, (9, 9, 9)), (VIRTUAL_CHANNEL_BASE, (255, 0, 0))]);
+        sink.write_frame(&frame).unwrap();
+        drop(sink);
+
+        let received = server.join().unwrap();
+        let updates: Vec<_> = received
+            .iter()
+            .filter(|(_, id, _)| *id == UPDATE_LEDS)
+            .collect();
+        assert_eq!(updates.len(), 2);
+        // Keyboard: three red LEDs; the mouse has no node color and stays dark
+        assert_eq!(&updates[0].2[4..6], &3u16.to_le_bytes());
+        assert_eq!(&updates[0].2[6..10], &[255, 0, 0, 0]);
+        assert_eq!(&updates[1].2[6..10], &[0, 0, 0, 0]);
+        assert!(received.iter().any(|(_, id, _)| *id == SET_CUSTOM_MODE));
+    }
+
+    #[test]
+    fn test_average_ignores_virtual_nodes() {
+        let frame = HashMap::from([
+            (0, (200, 0, 0)),
+            (1, (0, 100, 0)),
+            (VIRTUAL_CHANNEL_BASE, (255, 255, 255)),
+        ]);
+        assert_eq!(average(&frame), (100, 50, 0));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::color::Rgb;
+use std::collections::HashMap;
+
+/// Channels claimed by external controllers through the control API, each
+/// held at a fixed color (e.g. a "do not disturb" lamp) while the rest of the
+/// area keeps following the effect.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    colors: HashMap<u8, (u8, u8, u8)>,
+}
+
+impl Overrides {
+    /// Holds `channels` at `color` until they are released.
+    pub fn set(&mut self, channels: &[u8], color: (u8, u8, u8)) {
+        for &channel in channels {
+            self.colors.insert(channel, color);
+        }
+    }
+
+    /// Hands `channels` back to the effect; an empty list releases all.
+    pub fn release(&mut self, channels: &[u8]) {
+        if channels.is_empty() {
+            self.colors.clear();
+        }
+        for channel in channels {
+            self.colors.remove(channel);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Replaces the effect output of the claimed channels. Channels the
+    /// frame does not carry are left out rather than added.
+    pub fn apply<C: Rgb>(&self, frame: &mut HashMap<u8, C>) {
+        for (channel, color) in frame.iter_mut() {
+            if let Some(&held) = self.colors.get(channel) {
+                *color = C::from_rgb(held.to_rgb());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_composite_and_release() {
+        let mut overrides = Overrides::default();
+        overrides.set(&[1, 9], (255, 0, 0));
+        let mut frame = HashMap::from([(0, (10, 20, 30)), (1, (10, 20, 30))]);
+        overrides.apply(&mut frame);
+        assert_eq!(frame[&0], (10, 20, 30));
+        assert_eq!(frame[&1], (255, 0, 0));
+        assert!(!frame.contains_key(&9));
+
+        overrides.release(&[1]);
+        let mut frame = HashMap::from([(1, (10, 20, 30))]);
+        overrides.apply(&mut frame);
+        assert_eq!(frame[&1], (10, 20, 30));
+
+        overrides.release(&[]);
+        assert!(overrides.is_empty());
+    }
+}
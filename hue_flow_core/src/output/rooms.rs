@@ -0,0 +1,133 @@
+//! Room mode: several channels in one room or zone rendered as a single light.
+//!
+//! Effects see one node per room, placed at the average position of its
+//! lights, and the color they give it is copied back to every channel of
+//! the room before the frame goes out.
+use crate::models::LightNode;
+use std::collections::{BTreeMap, HashMap};
+
+/// One room collapsed to a virtual node.
+#[derive(Debug, Clone, PartialEq)]
+struct Room {
+    channel: u8,
+    members: Vec<u8>,
+}
+
+/// Channels grouped into rooms, by room name.
+#[derive(Debug, Clone, Default)]
+pub struct RoomMap {
+    rooms: BTreeMap<String, Vec<u8>>,
+    collapsed: Vec<Room>,
+}
+
+impl RoomMap {
+    pub fn new(rooms: impl IntoIterator<Item = (String, Vec<u8>)>) -> Self {
+        Self {
+            rooms: rooms.into_iter().collect(),
+            collapsed: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    /// Replaces the lights of every room with one node, numbered from
+    /// `first_channel` in room name order. Rooms without any light in
+    /// `nodes` are left out; lights in no room stay as they are. A channel
+    /// listed in two rooms belongs to the first.
+    pub fn collapse(&mut self, nodes: Vec<LightNode>, first_channel: u8) -> Vec<LightNode> {
+        self.collapsed.clear();
+        let mut rest = nodes;
+        let mut collapsed = Vec::new();
+        for (name, channels) in &self.rooms {
+            let (members, others): (Vec<LightNode>, Vec<LightNode>) = rest
+                .into_iter()
+                .partition(|n| channels.contains(&n.channel_id));
+            rest = others;
+            if members.is_empty() {
+                continue;
+            }
+            let channel = first_channel.saturating_add(collapsed.len() as u8);
+            let count = members.len() as f64;
+            let mean = |axis: fn(&LightNode) -> f64| members.iter().map(axis).sum::<f64>() / count;
+            collapsed.push(LightNode {
+                id: name.clone(),
+                channel_id: channel,
+                x: mean(|n| n.x),
+                y: mean(|n| n.y),
+                z: mean(|n| n.z),
+                segment: 0,
+            });
+            self.collapsed.push(Room {
+                channel,
+                members: members.iter().map(|n| n.channel_id).collect(),
+            });
+        }
+        rest.extend(collapsed);
+        rest
+    }
+
+    /// Gives every light of a collapsed room its room's color.
+    pub fn expand(&self, frame: &mut HashMap<u8, (u8, u8, u8)>) {
+        for room in &self.collapsed {
+            if let Some(color) = frame.remove(&room.channel) {
+                frame.extend(room.members.iter().map(|&member| (member, color)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(channel_id: u8, x: f64, y: f64) -> LightNode {
+        LightNode {
+            id: format!("light-{channel_id}"),
+            channel_id,
+            x,
+            y,
+            z: 0.0,
+            segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_collapse_and_expand() {
+        let mut rooms = RoomMap::new([
+            ("kitchen".to_string(), vec![0, 1]),
+            ("office".to_string(), vec![1, 3, 9]),
+            ("porch".to_string(), vec![7]),
+        ]);
+        let nodes = vec![
+            node(0, -1.0, 0.5),
+            node(1, 0.0, 1.0),
+            node(2, 0.5, 0.5),
+            node(3, 1.0, -1.0),
+        ];
+        let collapsed = rooms.collapse(nodes, 64);
+        // Channel 2 stays, kitchen and office become nodes, the porch has
+        // no lights here
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(collapsed[0].channel_id, 2);
+        assert_eq!(collapsed[1].id, "kitchen");
+        assert_eq!(collapsed[1].channel_id, 64);
+        assert_eq!((collapsed[1].x, collapsed[1].y), (-0.5, 0.75));
+        assert_eq!(collapsed[2].id, "office");
+        assert_eq!(collapsed[2].channel_id, 65);
+        assert_eq!((collapsed[2].x, collapsed[2].y), (1.0, -1.0));
+
+        let mut frame = HashMap::from([(2, (1, 2, 3)), (64, (255, 0, 0)), (65, (0, 0, 255))]);
+        rooms.expand(&mut frame);
+        assert_eq!(
+            frame,
+            HashMap::from([
+                (0, (255, 0, 0)),
+                (1, (255, 0, 0)),
+                (2, (1, 2, 3)),
+                (3, (0, 0, 255)),
+            ])
+        );
+    }
+}
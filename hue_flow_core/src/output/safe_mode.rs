@@ -0,0 +1,198 @@
+use crate::color::Rgb;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Fastest luminance change allowed, in full-range swings per second.
+pub const MAX_LUMA_RATE: f32 = 4.0;
+/// Luminance change (0.0 - 1.0) that counts as a flash transition.
+pub const FLASH_THRESHOLD: f32 = 0.1;
+/// Opposing transitions allowed per second; two make a flash, so this keeps
+/// flashing below 3 Hz.
+pub const MAX_TRANSITIONS_PER_SEC: usize = 5;
+
+const FLASH_WINDOW: Duration = Duration::from_secs(1);
+/// Longest frame gap used for the rate limit, so a stalled loop cannot jump.
+const MAX_FRAME_GAP: Duration = Duration::from_millis(100);
+
+/// Photosensitive-safe output stage.
+///
+/// Caps how fast each channel's luminance may change between frames and holds
+/// a channel when reversing direction would exceed [`MAX_TRANSITIONS_PER_SEC`],
+/// following the general flash threshold of WCAG 2.3.1.
+#[derive(Debug, Default)]
+pub struct SafeMode {
+    channels: HashMap<u8, ChannelHistory>,
+    last_frame: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct ChannelHistory {
+    color: [f32; 3],
+    /// Luminance of the brightest/darkest point since the last reversal.
+    extreme: f32,
+    /// +1 while getting brighter, -1 while getting darker, 0 before any change.
+    direction: i8,
+    transitions: VecDeque<Instant>,
+}
+
+/// Relative luminance (Rec. 709 weights) of an RGB color, 0.0 - 1.0.
+pub fn luminance(color: (u8, u8, u8)) -> f32 {
+    luma(color.to_rgb())
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    (0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]) / 255.0
+}
+
+fn mix(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    std::array::from_fn(|i| from[i] + (to[i] - from[i]) * t)
+}
+
+impl SafeMode {
+    /// Limits `frame` in place against the previously applied frames.
+    pub fn apply<C: Rgb>(&mut self, frame: &mut HashMap<u8, C>) {
+        self.apply_at(frame, Instant::now());
+    }
+
+    fn apply_at<C: Rgb>(&mut self, frame: &mut HashMap<u8, C>, now: Instant) {
+        let elapsed = self
+            .last_frame
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last))
+            .min(MAX_FRAME_GAP);
+        self.last_frame = Some(now);
+        let max_step = MAX_LUMA_RATE * elapsed.as_secs_f32();
+
+        for (&channel, color) in frame.iter_mut() {
+            let Some(history) = self.channels.get_mut(&channel) else {
+                self.channels.insert(
+                    channel,
+                    ChannelHistory {
+                        color: color.to_rgb(),
+                        extreme: luma(color.to_rgb()),
+                        direction: 0,
+                        transitions: VecDeque::new(),
+                    },
+                );
+                continue;
+            };
+            *color = C::from_rgb(history.limit(color.to_rgb(), max_step, now));
+        }
+    }
+}
+
+impl ChannelHistory {
+    fn limit(&mut self, wanted: [f32; 3], max_step: f32, now: Instant) -> [f32; 3] {
+        let current = luma(self.color);
+        let delta = luma(wanted) - current;
+        let mut color = if delta.abs() > max_step {
+            mix(self.color, wanted, max_step / delta.abs())
+        } else {
+            wanted
+        };
+
+        let level = luma(color);
+        let direction = if level > self.extreme {
+            1
+        } else if level < self.extreme {
+            -1
+        } else {
+            0
+        };
+
+        if direction == 0 || direction == self.direction || self.direction == 0 {
+            if direction != 0 {
+                self.direction = direction;
+                self.extreme = level;
+            }
+        } else if (level - self.extreme).abs() >= FLASH_THRESHOLD {
+            while self
+                .transitions
+                .front()
+                .is_some_and(|t| now.saturating_duration_since(*t) >= FLASH_WINDOW)
+            {
+                self.transitions.pop_front();
+            }
+            if self.transitions.len() >= MAX_TRANSITIONS_PER_SEC {
+                // Reversing now would flash too often: hold the current color
+                color = self.color;
+            } else {
+                self.transitions.push_back(now);
+                self.direction = direction;
+                self.extreme = level;
+            }
+        }
+
+        self.color = color;
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn test_luminance_rate_is_capped() {
+        let start = Instant::now();
+        let mut safe = SafeMode::default();
+        let mut frame = HashMap::from([(0, (0, 0, 0))]);
+        safe.apply_at(&mut frame, start);
+
+        let mut frame = HashMap::from([(0, (255, 255, 255))]);
+        safe.apply_at(&mut frame, start + FRAME);
+        // 4 swings/s over 20 ms allow 8% of the range
+        let luma = luminance(frame[&0]);
+        assert!((luma - 0.08).abs() < 0.01, "luma {}", luma);
+    }
+
+    #[test]
+    fn test_flashing_is_limited_below_3_hz() {
+        let start = Instant::now();
+        let mut safe = SafeMode::default();
+        let mut previous = 0.0;
+        let mut last_change = 0.0f32;
+        let mut reversals = 0;
+
+        // Ask for a 5 Hz black/white strobe for one second
+        for i in 0..10u32 {
+            let wanted = if i % 2 == 0 {
+                (0, 0, 0)
+            } else {
+                (255, 255, 255)
+            };
+            let mut frame = HashMap::from([(0, wanted)]);
+            safe.apply_at(&mut frame, start + Duration::from_millis(100) * i);
+
+            let luma = luminance(frame[&0]);
+            let change = luma - previous;
+            if change.abs() >= FLASH_THRESHOLD {
+                if last_change != 0.0 && change.signum() != last_change.signum() {
+                    reversals += 1;
+                }
+                last_change = change;
+            }
+            previous = luma;
+        }
+
+        assert!(reversals > 0);
+        assert!(
+            reversals <= MAX_TRANSITIONS_PER_SEC,
+            "{} reversals",
+            reversals
+        );
+    }
+
+    #[test]
+    fn test_slow_changes_pass_through() {
+        let start = Instant::now();
+        let mut safe = SafeMode::default();
+        for i in 0..10u32 {
+            let level = (i * 5) as u8;
+            let mut frame = HashMap::from([(2, (level, level, level))]);
+            safe.apply_at(&mut frame, start + FRAME * i);
+            assert_eq!(frame[&2], (level, level, level));
+        }
+    }
+}
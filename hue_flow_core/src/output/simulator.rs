@@ -0,0 +1,160 @@
+use crate::models::LightNode;
+use crate::output::LightSink;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::io::Write;
+
+/// Grid size of the simulated room in cells. Each cell is two characters
+/// wide so the room looks roughly square in a terminal.
+const COLUMNS: usize = 24;
+const ROWS: usize = 12;
+
+/// Most lights in a virtual room; an entertainment area has up to 20
+/// channels.
+pub const MAX_VIRTUAL_LIGHTS: usize = 20;
+
+/// Characters from dark to bright for terminals without color support.
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Renders light positions and colors as a top-down ASCII view of the room,
+/// for developing effects without a bridge.
+///
+/// x runs left to right, y from the back (top) to the front (bottom).
+pub struct SimulatorSink {
+    nodes: Vec<LightNode>,
+    ansi: bool,
+    out: Box<dyn Write + Send>,
+}
+
+impl SimulatorSink {
+    /// Draws to stdout with true-color ANSI escapes, redrawing in place.
+    pub fn stdout(nodes: Vec<LightNode>) -> Self {
+        Self::new(nodes, true, Box::new(std::io::stdout()))
+    }
+
+    pub fn new(nodes: Vec<LightNode>, ansi: bool, out: Box<dyn Write + Send>) -> Self {
+        Self { nodes, ansi, out }
+    }
+
+    /// A virtual room with `count` lights in a circle around the listener,
+    /// at most [`MAX_VIRTUAL_LIGHTS`] like an entertainment area.
+    pub fn virtual_room(count: usize) -> Vec<LightNode> {
+        let count = count.min(MAX_VIRTUAL_LIGHTS);
+        (0..count)
+            .map(|i| {
+                let angle = TAU * i as f64 / count as f64;
+                LightNode {
+                    id: format!("sim_{}", i),
+                    channel_id: i as u8,
+                    x: angle.sin() * 0.9,
+                    y: angle.cos() * 0.9,
+                    z: 0.0,
+                    segment: 0,
+                }
+            })
+            .collect()
+    }
+
+    /// Renders one frame as text, one line per grid row.
+    pub fn render(&self, frame: &HashMap<u8, (u8, u8, u8)>) -> String {
+        let mut grid = vec![vec![None; COLUMNS]; ROWS];
+        for node in &self.nodes {
+            let color = frame.get(&node.channel_id).copied().unwrap_or((0, 0, 0));
+            let column = cell(node.x, COLUMNS);
+            let row = cell(-node.y, ROWS);
+            grid[row][column] = Some((node.channel_id, color));
+        }
+
+        let border = format!("+{}+\n", "-".repeat(COLUMNS * 2));
+        let mut text = border.clone();
+        for row in grid {
+            text.push('|');
+            for light in row {
+                match light {
+                    None => text.push_str("  "),
+                    Some((channel_id, (r, g, b))) if self.ansi => text.push_str(&format!(
+                        "\x1b[48;2;{};{};{}m{:>2}\x1b[0m",
+                        r, g, b, channel_id
+                    )),
+                    Some((_, color)) => {
+                        let c = RAMP[luma(color) * (RAMP.len() - 1) / 255] as char;
+                        text.push(c);
+                        text.push(c);
+                    }
+                }
+            }
+            text.push_str("|\n");
+        }
+        text.push_str(&border);
+        text
+    }
+}
+
+impl LightSink for SimulatorSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        let text = self.render(frame);
+        if self.ansi {
+            // Clear the screen so the room redraws in place
+            self.out.write_all(b"\x1b[H\x1b[2J")?;
+        }
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Maps a coordinate in -1.0..=1.0 to a cell index.
+fn cell(value: f64, cells: usize) -> usize {
+    let normalized = (value.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    (normalized * (cells - 1) as f64).round() as usize
+}
+
+/// Perceived brightness (0-255).
+fn luma((r, g, b): (u8, u8, u8)) -> usize {
+    (r as usize * 299 + g as usize * 587 + b as usize * 114) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_positions() {
+        let nodes = vec![
+            LightNode {
+                id: "left".into(),
+                channel_id: 0,
+                x: -1.0,
+                y: 1.0,
+                z: 0.0,
+                segment: 0,
+            },
+            LightNode {
+                id: "right".into(),
+                channel_id: 1,
+                x: 1.0,
+                y: -1.0,
+                z: 0.0,
+                segment: 0,
+            },
+        ];
+        let sink = SimulatorSink::new(nodes, false, Box::new(std::io::sink()));
+        let frame = HashMap::from([(0, (255, 255, 255)), (1, (0, 0, 0))]);
+
+        let text = sink.render(&frame);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), ROWS + 2);
+        // Back-left light is bright, front-right light is dark
+        assert!(lines[1].starts_with("|@@"));
+        assert!(lines[ROWS].ends_with("  |"));
+    }
+
+    #[test]
+    fn test_virtual_room() {
+        let room = SimulatorSink::virtual_room(8);
+        assert_eq!(room.len(), 8);
+        assert!(room.iter().all(|n| n.x.abs() <= 1.0 && n.y.abs() <= 1.0));
+        assert_eq!(room[7].channel_id, 7);
+        assert_eq!(SimulatorSink::virtual_room(300).len(), MAX_VIRTUAL_LIGHTS);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::color::Rgb;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Turns per-light transition hints into intermediate frames.
+///
+/// Instead of jumping to a new color, a channel with a transition time fades
+/// there over the following stream frames. Rounding to 8 bits carries the
+/// error over to the next frame (temporal dithering), so slow fades on lights
+/// that visibly step alternate between neighbouring levels instead of
+/// stalling on one.
+#[derive(Debug, Default)]
+pub struct Smoother {
+    channels: HashMap<u8, ChannelFade>,
+}
+
+#[derive(Debug)]
+struct ChannelFade {
+    from: [f32; 3],
+    to: [f32; 3],
+    started: Instant,
+    duration: Duration,
+    /// Rounding error carried into the next frame.
+    error: [f32; 3],
+}
+
+impl ChannelFade {
+    fn value_at(&self, now: Instant) -> [f32; 3] {
+        let progress = if self.duration.is_zero() {
+            1.0
+        } else {
+            (now.saturating_duration_since(self.started).as_secs_f32()
+                / self.duration.as_secs_f32())
+            .min(1.0)
+        };
+        std::array::from_fn(|i| self.from[i] + (self.to[i] - self.from[i]) * progress)
+    }
+}
+
+impl Smoother {
+    /// Sets a new target color for `channel`, reached after `transition`.
+    pub fn set_target(&mut self, channel: u8, color: impl Rgb, transition: Duration, now: Instant) {
+        let to = color.to_rgb();
+        match self.channels.get_mut(&channel) {
+            Some(fade) if fade.to != to => {
+                fade.from = fade.value_at(now);
+                fade.to = to;
+                fade.started = now;
+                fade.duration = transition;
+            }
+            Some(_) => {}
+            None => {
+                self.channels.insert(
+                    channel,
+                    ChannelFade {
+                        from: to,
+                        to,
+                        started: now,
+                        duration: transition,
+                        error: [0.0; 3],
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn remove(&mut self, channel: u8) {
+        self.channels.remove(&channel);
+    }
+
+    /// Unrounded colors (0.0 - 255.0 per component) of all channels at
+    /// `now`, for outputs finer than 8 bits.
+    pub fn frame_precise(&self, now: Instant) -> HashMap<u8, [f32; 3]> {
+        self.channels
+            .iter()
+            .map(|(&channel, fade)| (channel, fade.value_at(now)))
+            .collect()
+    }
+
+    /// Colors of all channels at `now`.
+    pub fn frame(&mut self, now: Instant) -> HashMap<u8, (u8, u8, u8)> {
+        self.channels
+            .iter_mut()
+            .map(|(&channel, fade)| {
+                let value = fade.value_at(now);
+                let mut out = [0u8; 3];
+                for i in 0..3 {
+                    let wanted = value[i] + fade.error[i];
+                    out[i] = wanted.round().clamp(0.0, 255.0) as u8;
+                    fade.error[i] = wanted - out[i] as f32;
+                }
+                (channel, (out[0], out[1], out[2]))
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition_interpolates() {
+        let start = Instant::now();
+        let mut smoother = Smoother::default();
+        smoother.set_target(0, (0, 0, 0), Duration::ZERO, start);
+        smoother.set_target(0, (200, 0, 0), Duration::from_millis(100), start);
+
+        assert_eq!(smoother.frame(start)[&0], (0, 0, 0));
+        assert_eq!(
+            smoother.frame(start + Duration::from_millis(50))[&0],
+            (100, 0, 0)
+        );
+        assert_eq!(
+            smoother.frame(start + Duration::from_millis(200))[&0],
+            (200, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_no_transition_jumps() {
+        let start = Instant::now();
+        let mut smoother = Smoother::default();
+        smoother.set_target(1, (10, 20, 30), Duration::ZERO, start);
+        smoother.set_target(1, (255, 0, 0), Duration::ZERO, start);
+        assert_eq!(smoother.frame(start)[&1], (255, 0, 0));
+    }
+
+    #[test]
+    fn test_dithering_averages_fractional_levels() {
+        let start = Instant::now();
+        let mut smoother = Smoother::default();
+        smoother.set_target(0, (0, 0, 0), Duration::ZERO, start);
+        // Halfway through a 0 -> 1 fade the exact level is 0.5
+        smoother.set_target(0, (1, 0, 0), Duration::from_secs(2), start);
+        let now = start + Duration::from_secs(1);
+
+        let sum: u32 = (0..10).map(|_| smoother.frame(now)[&0].0 as u32).sum();
+        assert_eq!(sum, 5);
+        assert_eq!(smoother.frame_precise(now)[&0], [0.5, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Theater mode for films: slow, calm output that keeps quiet behind the
+//! viewer and holds still while people talk.
+use crate::audio_interface::AnalysisFrame;
+use crate::color::Rgb;
+use crate::models::LightNode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Frequency range of the voice, where dialogue puts most of its energy.
+const VOICE_HZ: (f32, f32) = (300.0, 3400.0);
+/// Range the voice share is measured against.
+const AUDIBLE_HZ: (f32, f32) = (60.0, 8000.0);
+/// Time the speech estimate takes to rise when people start talking...
+const SPEECH_ATTACK: f32 = 0.2;
+/// ...and to fall once they stop, bridging pauses between sentences.
+const SPEECH_RELEASE: f32 = 1.5;
+
+/// How calm theater mode keeps the room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TheaterConfig {
+    /// Time colors take to follow the effect (about two thirds of the way).
+    pub smoothing_ms: u64,
+    /// Brightness cap for lights behind the viewer (negative z).
+    pub rear_brightness: f32,
+    /// How strongly lights still follow the effect during dialogue (1.0 =
+    /// as usual); smoothing is slowed down by the same factor.
+    pub speech_response: f32,
+}
+
+impl Default for TheaterConfig {
+    fn default() -> Self {
+        Self {
+            smoothing_ms: 600,
+            rear_brightness: 0.3,
+            speech_response: 0.2,
+        }
+    }
+}
+
+/// How much a frame of audio sounds like speech, from 0.0 to 1.0, or
+/// `None` without FFT bins.
+///
+/// Speech puts most of its energy in the voice band and, being harmonic,
+/// has a peaky spectrum there (low spectral flatness); music and effects
+/// spread wider and denser.
+pub fn speech_likelihood(analysis: &AnalysisFrame) -> Option<f32> {
+    if analysis.bins.is_empty() || analysis.bin_hz <= 0.0 {
+        return None;
+    }
+    let bin = |hz: f32| ((hz / analysis.bin_hz) as usize).min(analysis.bins.len());
+    let power = |range: (f32, f32)| -> Vec<f32> {
+        analysis.bins[bin(range.0)..bin(range.1)]
+            .iter()
+            .map(|m| m * m)
+            .collect()
+    };
+    let voice = power(VOICE_HZ);
+    let total: f32 = power(AUDIBLE_HZ).iter().sum();
+    if voice.is_empty() || total <= 1e-6 {
+        return Some(0.0);
+    }
+
+    let voice_total: f32 = voice.iter().sum();
+    let share = voice_total / total;
+    let mean = voice_total / voice.len() as f32;
+    let log_mean = voice.iter().map(|p| (p + 1e-9).ln()).sum::<f32>() / voice.len() as f32;
+    let flatness = log_mean.exp() / mean;
+
+    let share_score = ((share - 0.5) / 0.3).clamp(0.0, 1.0);
+    let tonal_score = ((0.5 - flatness) / 0.4).clamp(0.0, 1.0);
+    Some(share_score * tonal_score)
+}
+
+/// Output stage for films: smooths colors heavily, caps lights behind the
+/// viewer and responds even less while [`speech_likelihood`] says someone
+/// is talking.
+#[derive(Debug, Clone)]
+pub struct TheaterMode {
+    config: TheaterConfig,
+    colors: HashMap<u8, [f32; 3]>,
+    speech: f32,
+    last: Option<Instant>,
+}
+
+impl TheaterMode {
+    pub fn new(config: TheaterConfig) -> Self {
+        Self {
+            config,
+            colors: HashMap::new(),
+            speech: 0.0,
+            last: None,
+        }
+    }
+
+    /// Replaces `frame` with its calmed-down version.
+    pub fn apply<C: Rgb>(
+        &mut self,
+        frame: &mut HashMap<u8, C>,
+        nodes: &[LightNode],
+        analysis: &AnalysisFrame,
+        now: Instant,
+    ) {
+        let elapsed = self.last.map_or(0.0, |last| {
+            now.saturating_duration_since(last).as_secs_f32()
+        });
+        let first = self.last.is_none();
+        self.last = Some(now);
+
+        let likelihood = speech_likelihood(analysis).unwrap_or(0.0);
+        let time = if likelihood > self.speech {
+            SPEECH_ATTACK
+        } else {
+            SPEECH_RELEASE
+        };
+        self.speech += (likelihood - self.speech) * (1.0 - (-elapsed / time).exp());
+
+        let min_response = self.config.speech_response.clamp(0.01, 1.0);
+        let response = 1.0 - (1.0 - min_response) * self.speech;
+        let smoothing = self.config.smoothing_ms as f32 / 1000.0 / response;
+        let follow = if first || smoothing <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-elapsed / smoothing).exp()
+        };
+
+        let rear_cap = self.config.rear_brightness.clamp(0.0, 1.0) * 255.0;
+        for (channel, color) in frame.iter_mut() {
+            let target = color.to_rgb();
+            let current = self.colors.entry(*channel).or_insert(target);
+            for (c, t) in current.iter_mut().zip(target) {
+                *c += (t - *c) * follow;
+            }
+            let mut out = *current;
+            let behind = nodes.iter().any(|n| n.channel_id == *channel && n.z < 0.0);
+            let peak = out[0].max(out[1]).max(out[2]);
+            if behind && peak > rear_cap {
+                out = out.map(|c| c * rear_cap / peak);
+            }
+            *color = C::from_rgb(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const BIN_HZ: f32 = 48_000.0 / 1024.0;
+
+    fn frame_with(bins: Vec<f32>) -> AnalysisFrame {
+        AnalysisFrame {
+            bins,
+            bin_hz: BIN_HZ,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_speech_likelihood() {
+        // A voice: harmonics of 180 Hz, strongest in the voice band
+        let mut voice = vec![0.0; 513];
+        for harmonic in 1..20 {
+            let hz = 180.0 * harmonic as f32;
+            voice[(hz / BIN_HZ).round() as usize] = if hz > 300.0 { 0.8 } else { 0.2 };
+        }
+        assert!(speech_likelihood(&frame_with(voice)).unwrap() > 0.8);
+
+        // Dense, broadband music
+        let music: Vec<f32> = (0..513).map(|i| 0.5 + 0.1 * (i % 3) as f32).collect();
+        assert_eq!(speech_likelihood(&frame_with(music)), Some(0.0));
+
+        assert_eq!(speech_likelihood(&AnalysisFrame::default()), None);
+    }
+
+    #[test]
+    fn test_smoothing_and_rear_cap() {
+        let nodes = vec![
+            LightNode {
+                id: "1".to_string(),
+                channel_id: 0,
+                x: 0.0,
+                y: 1.0,
+                z: 0.5,
+                segment: 0,
+            },
+            LightNode {
+                id: "2".to_string(),
+                channel_id: 1,
+                x: 0.0,
+                y: -1.0,
+                z: -0.5,
+                segment: 0,
+            },
+        ];
+        let mut theater = TheaterMode::new(TheaterConfig::default());
+        let silence = AnalysisFrame::default();
+        let start = Instant::now();
+
+        let mut frame = HashMap::from([(0, (0, 0, 0)), (1, (255, 255, 255))]);
+        theater.apply(&mut frame, &nodes, &silence, start);
+        // Behind the viewer: capped at 30 %
+        assert_eq!(frame[&1], (77, 77, 77));
+
+        // Eases towards a sudden change rather than jumping
+        let mut frame = HashMap::from([(0, (255, 0, 0)), (1, (255, 255, 255))]);
+        theater.apply(
+            &mut frame,
+            &nodes,
+            &silence,
+            start + Duration::from_millis(600),
+        );
+        assert_eq!(frame[&0], (161, 0, 0));
+    }
+}
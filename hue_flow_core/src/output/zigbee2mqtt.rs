@@ -0,0 +1,272 @@
+//! Hue (or other Zigbee) bulbs paired to a zigbee2mqtt coordinator instead
+//! of a Hue Bridge, driven by `set` messages over MQTT.
+//!
+//! A Zigbee network carries only a handful of color commands per second,
+//! far from the bridge's entertainment stream, so frames are thinned out to
+//! `max_rate` messages per second for all bulbs together and each bulb
+//! fades over the time since its last update.
+use crate::models::LightNode;
+use crate::output::{place_lights, PlacedLight};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default color commands per second for the whole network.
+pub const DEFAULT_MAX_RATE: f32 = 10.0;
+/// Longest fade sent along with an update.
+const MAX_TRANSITION: Duration = Duration::from_secs(1);
+
+/// zigbee2mqtt output settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Zigbee2MqttConfig {
+    /// MQTT broker zigbee2mqtt is connected to.
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_base_topic")]
+    pub base_topic: String,
+    /// Bulbs by zigbee2mqtt friendly name, placed in the room.
+    pub lights: Vec<PlacedLight>,
+    /// Color commands per second for all bulbs together.
+    #[serde(default = "default_max_rate")]
+    pub max_rate: f32,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_base_topic() -> String {
+    "zigbee2mqtt".to_string()
+}
+
+fn default_max_rate() -> f32 {
+    DEFAULT_MAX_RATE
+}
+
+impl Zigbee2MqttConfig {
+    /// The bulbs as layout nodes, numbered from `first_channel`; from 0 when
+    /// they are the only output.
+    pub fn nodes(&self, first_channel: u8) -> Vec<LightNode> {
+        place_lights(&self.lights, first_channel)
+    }
+}
+
+/// The `set` message showing `color`, fading over `transition`.
+///
+/// zigbee2mqtt takes brightness apart from the color, so the color goes
+/// out at full level and its brightest component sets the brightness.
+pub fn set_payload((r, g, b): (u8, u8, u8), transition: Duration) -> serde_json::Value {
+    let transition = (transition.as_secs_f64() * 10.0).round() / 10.0;
+    let max = r.max(g).max(b);
+    if max == 0 {
+        return json!({ "state": "OFF", "transition": transition });
+    }
+    let full = |v: u8| (v as u32 * 255 / max as u32) as u8;
+    json!({
+        "state": "ON",
+        "brightness": (max as u32 * 254).div_ceil(255),
+        "color": { "r": full(r), "g": full(g), "b": full(b) },
+        "transition": transition,
+    })
+}
+
+struct Bulb {
+    channel: u8,
+    last: Option<((u8, u8, u8), Instant)>,
+}
+
+/// Picks which bulbs get an update: a token bucket of `max_rate` messages
+/// per second, handed to the bulbs that changed and waited longest.
+pub struct UpdatePacer {
+    bulbs: Vec<Bulb>,
+    max_rate: f32,
+    tokens: f32,
+    refilled: Option<Instant>,
+}
+
+impl UpdatePacer {
+    /// Paces `count` bulbs on channels `0..count`.
+    pub fn new(count: usize, max_rate: f32) -> Self {
+        Self {
+            bulbs: (0..count)
+                .map(|i| Bulb {
+                    channel: i as u8,
+                    last: None,
+                })
+                .collect(),
+            max_rate: max_rate.max(0.1),
+            tokens: 1.0,
+            refilled: None,
+        }
+    }
+
+    /// Moves the bulbs to the channels of `nodes`, in order.
+    pub fn assign(&mut self, nodes: &[LightNode]) {
+        for (bulb, node) in self.bulbs.iter_mut().zip(nodes) {
+            bulb.channel = node.channel_id;
+        }
+    }
+
+    /// Bulbs (by index) to update for `frame`, with their color and fade.
+    pub fn due(
+        &mut self,
+        frame: &HashMap<u8, (u8, u8, u8)>,
+        now: Instant,
+    ) -> Vec<(usize, (u8, u8, u8), Duration)> {
+        let elapsed = self
+            .refilled
+            .map_or(Duration::ZERO, |at| now.saturating_duration_since(at));
+        self.refilled = Some(now);
+        // Up to a second's worth saved up, so a burst after a quiet spell
+        // stays short
+        self.tokens = (self.tokens + elapsed.as_secs_f32() * self.max_rate).min(self.max_rate);
+
+        let mut changed: Vec<_> = self
+            .bulbs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, bulb)| {
+                let color = frame.get(&bulb.channel).copied().unwrap_or_default();
+                match bulb.last {
+                    Some((last, _)) if last == color => None,
+                    Some((_, at)) => Some((i, color, Some(at))),
+                    None => Some((i, color, None)),
+                }
+            })
+            .collect();
+        // Never updated first, then the longest waiting
+        changed.sort_by_key(|(_, _, at)| *at);
+
+        let mut due = Vec::new();
+        for (i, color, at) in changed {
+            if self.tokens < 1.0 {
+                break;
+            }
+            self.tokens -= 1.0;
+            let fade = at.map_or(Duration::ZERO, |at| {
+                now.saturating_duration_since(at).min(MAX_TRANSITION)
+            });
+            self.bulbs[i].last = Some((color, now));
+            due.push((i, color, fade));
+        }
+        due
+    }
+}
+
+/// Publishes frames to zigbee2mqtt bulbs (see
+/// [`HueFlowBuilder::sink`](crate::HueFlowBuilder::sink) for running without
+/// a bridge, or [`HueFlowBuilder::mirror`](crate::HueFlowBuilder::mirror)
+/// next to one).
+#[cfg(feature = "mqtt")]
+pub struct Zigbee2MqttSink {
+    config: Zigbee2MqttConfig,
+    pacer: UpdatePacer,
+    client: rumqttc::AsyncClient,
+    connection: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "mqtt")]
+impl Zigbee2MqttSink {
+    /// Connects to the broker in the background; needs a Tokio runtime.
+    pub fn new(config: Zigbee2MqttConfig) -> Self {
+        use rumqttc::{AsyncClient, MqttOptions};
+
+        let options = MqttOptions::new(
+            format!("hueflow-out-{}", std::process::id()),
+            &config.host,
+            config.port,
+        );
+        let (client, mut events) = AsyncClient::new(options, 64);
+        let connection = tokio::spawn(async move {
+            loop {
+                if let Err(e) = events.poll().await {
+                    // The event loop reconnects on the next poll
+                    tracing::warn!("zigbee2mqtt: MQTT connection failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        });
+        Self {
+            pacer: UpdatePacer::new(config.lights.len(), config.max_rate),
+            config,
+            client,
+            connection,
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl crate::output::LightSink for Zigbee2MqttSink {
+    fn write_frame(&mut self, frame: &HashMap<u8, (u8, u8, u8)>) -> anyhow::Result<()> {
+        use rumqttc::QoS;
+
+        for (i, color, fade) in self.pacer.due(frame, Instant::now()) {
+            let topic = format!(
+                "{}/{}/set",
+                self.config.base_topic, self.config.lights[i].id
+            );
+            let payload = set_payload(color, fade).to_string();
+            self.client
+                .try_publish(topic, QoS::AtMostOnce, false, payload)?;
+        }
+        Ok(())
+    }
+
+    fn virtual_nodes(&mut self, first_channel: u8) -> Vec<LightNode> {
+        let nodes = self.config.nodes(first_channel);
+        self.pacer.assign(&nodes);
+        nodes
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl Drop for Zigbee2MqttSink {
+    fn drop(&mut self) {
+        self.connection.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_payload() {
+        let payload = set_payload((128, 64, 0), Duration::from_millis(240));
+        assert_eq!(payload["state"], "ON");
+        assert_eq!(payload["brightness"], 128);
+        assert_eq!(payload["color"], json!({ "r": 255, "g": 127, "b": 0 }));
+        assert_eq!(payload["transition"], 0.2);
+        assert_eq!(set_payload((0, 0, 0), Duration::ZERO)["state"], "OFF");
+        assert_eq!(
+            set_payload((255, 255, 255), Duration::ZERO)["brightness"],
+            254
+        );
+    }
+
+    #[test]
+    fn test_pacer_shares_the_rate() {
+        let mut pacer = UpdatePacer::new(3, 10.0);
+        let start = Instant::now();
+        let red = HashMap::from([(0, (255, 0, 0)), (1, (255, 0, 0)), (2, (255, 0, 0))]);
+        // One message to start with
+        assert_eq!(pacer.due(&red, start).len(), 1);
+        // 100 ms later the next, to a bulb not updated yet
+        let due = pacer.due(&red, start + Duration::from_millis(100));
+        assert_eq!(due.len(), 1);
+        assert_ne!(due[0].0, 0);
+        // Unchanged bulbs get nothing; the rest catch up
+        let due = pacer.due(&red, start + Duration::from_secs(1));
+        assert_eq!(due.len(), 1);
+        assert!(pacer.due(&red, start + Duration::from_secs(2)).is_empty());
+
+        // A change goes to the bulb that waited longest, fading over the wait
+        let blue = HashMap::from([(0, (0, 0, 255)), (1, (0, 0, 255)), (2, (0, 0, 255))]);
+        let due = pacer.due(&blue, start + Duration::from_millis(2050));
+        assert_eq!(due.len(), 3);
+        assert_eq!(due[0].0, 0);
+        assert_eq!(due[0].2, MAX_TRANSITION);
+    }
+}
@@ -0,0 +1,586 @@
+//! Effect playlists: a rotation of effect setups, each shown for a while and
+//! crossfaded into the next.
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::color::mix;
+use crate::effects::{effect_info, Frame, LightEffect, EFFECTS};
+use crate::models::LightNode;
+use crate::preset::{Preset, PresetError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// One effect setup in a playlist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    /// Effect, palette, brightness and CT-only as in a preset; the
+    /// entertainment area of the preset is ignored.
+    #[serde(flatten)]
+    pub preset: Preset,
+    /// How long the entry plays before the next one fades in.
+    pub duration_secs: u64,
+    /// How likely the entry is picked relative to the others in
+    /// [`random`](EffectPlaylist::random) order.
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Effects cycled through instead of a single preset, as stored in the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectPlaylist {
+    pub entries: Vec<PlaylistEntry>,
+    /// Crossfade between consecutive entries.
+    #[serde(default = "default_crossfade_ms")]
+    pub crossfade_ms: u64,
+    /// Play the entries in random order, reshuffled on every pass.
+    #[serde(default)]
+    pub shuffle: bool,
+    /// Pick every next entry at random by its weight instead of playing
+    /// through the list; never the same one twice in a row.
+    #[serde(default)]
+    pub random: bool,
+    /// Also move on after this many track changes heard in the audio.
+    #[serde(default)]
+    pub tracks: Option<u32>,
+}
+
+fn default_crossfade_ms() -> u64 {
+    2000
+}
+
+/// Shuffle mode (`--effect shuffle`): a new effect and palette every few
+/// minutes, picked at random from a weighted whitelist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShuffleConfig {
+    /// Effect names with their weights; every non-strobe effect, equally
+    /// likely, when empty.
+    #[serde(default)]
+    pub effects: BTreeMap<String, f32>,
+    /// Palettes the effects are combined with; each effect's own colors
+    /// when empty.
+    #[serde(default)]
+    pub palettes: Vec<Vec<(u8, u8, u8)>>,
+    /// How long each combination plays.
+    #[serde(default = "default_shuffle_minutes")]
+    pub minutes: u64,
+    /// Also pick a new combination after this many track changes.
+    #[serde(default)]
+    pub tracks: Option<u32>,
+    #[serde(default = "default_crossfade_ms")]
+    pub crossfade_ms: u64,
+}
+
+fn default_shuffle_minutes() -> u64 {
+    5
+}
+
+impl Default for ShuffleConfig {
+    fn default() -> Self {
+        Self {
+            effects: BTreeMap::new(),
+            palettes: Vec::new(),
+            minutes: default_shuffle_minutes(),
+            tracks: None,
+            crossfade_ms: default_crossfade_ms(),
+        }
+    }
+}
+
+impl ShuffleConfig {
+    /// A random playlist of every effect and palette combination.
+    pub fn playlist(&self) -> EffectPlaylist {
+        let effects: Vec<(String, f32)> = if self.effects.is_empty() {
+            EFFECTS
+                .iter()
+                .filter(|info| !info.strobe)
+                .map(|info| (info.name.to_string(), 1.0))
+                .collect()
+        } else {
+            self.effects
+                .iter()
+                .filter(|(_, weight)| **weight > 0.0)
+                .map(|(name, weight)| (name.clone(), *weight))
+                .collect()
+        };
+        let palettes = match self.palettes.is_empty() {
+            true => vec![Vec::new()],
+            false => self.palettes.clone(),
+        };
+        let entries = effects
+            .iter()
+            .flat_map(|(effect, weight)| {
+                palettes.iter().map(|palette| PlaylistEntry {
+                    preset: Preset {
+                        effect: effect.clone(),
+                        palette: palette.clone(),
+                        ..Default::default()
+                    },
+                    duration_secs: self.minutes * 60,
+                    weight: *weight,
+                })
+            })
+            .collect();
+        EffectPlaylist {
+            entries,
+            crossfade_ms: self.crossfade_ms,
+            shuffle: false,
+            random: true,
+            tracks: self.tracks,
+        }
+    }
+}
+
+/// Plays an [`EffectPlaylist`] as a single [`LightEffect`].
+///
+/// The outgoing effect keeps animating during the crossfade. Strobe-class
+/// entries can be skipped for photosensitive-safe mode.
+pub struct PlaylistPlayer {
+    playlist: EffectPlaylist,
+    /// Entry indices in play order; shuffled when shuffle is on.
+    order: Vec<usize>,
+    position: usize,
+    current: Box<dyn LightEffect>,
+    started: Instant,
+    /// The previous effect and when the fade away from it began.
+    fading: Option<(Box<dyn LightEffect>, Instant)>,
+    /// Entry played before the current one in random order.
+    previous: Option<usize>,
+    /// Track changes heard while the current entry played.
+    tracks: u32,
+    skip_strobe: bool,
+    rng: u64,
+}
+
+impl PlaylistPlayer {
+    /// Starts at the first entry (or a random one with shuffle or random
+    /// order on). Fails on
+    /// an empty playlist or an entry that does not build.
+    pub fn new(playlist: EffectPlaylist, now: Instant) -> Result<Self, PresetError> {
+        for entry in &playlist.entries {
+            entry.preset.build_effect()?;
+        }
+        let first = playlist
+            .entries
+            .first()
+            .ok_or(PresetError::EmptyPlaylist)?
+            .preset
+            .build_effect()?;
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        let mut player = Self {
+            order: (0..playlist.entries.len()).collect(),
+            position: 0,
+            current: first,
+            started: now,
+            fading: None,
+            previous: None,
+            tracks: 0,
+            skip_strobe: false,
+            rng: seed | 1,
+            playlist,
+        };
+        if player.playlist.random {
+            player.position = player.pick();
+            player.switch(now, false);
+        } else if player.playlist.shuffle {
+            player.shuffle_order();
+            player.switch(now, false);
+        }
+        Ok(player)
+    }
+
+    /// Index of the playing entry in the playlist.
+    pub fn index(&self) -> usize {
+        self.order[self.position]
+    }
+
+    pub fn entry(&self) -> &PlaylistEntry {
+        &self.playlist.entries[self.index()]
+    }
+
+    /// Skips to the next entry, crossfading.
+    pub fn next(&mut self, now: Instant) {
+        self.step(true, now);
+    }
+
+    /// Goes back to the previous entry, crossfading.
+    pub fn prev(&mut self, now: Instant) {
+        self.step(false, now);
+    }
+
+    /// Counts a track change, moving on once the playlist's
+    /// [`tracks`](EffectPlaylist::tracks) are reached.
+    pub fn track_changed(&mut self, now: Instant) {
+        let Some(tracks) = self.playlist.tracks else {
+            return;
+        };
+        self.tracks += 1;
+        if self.tracks >= tracks {
+            self.next(now);
+        }
+    }
+
+    /// Turns shuffle on (reshuffling the entries after the current one) or
+    /// off (continuing in list order from the current one). Random order
+    /// is not affected.
+    pub fn set_shuffle(&mut self, enabled: bool) {
+        if self.playlist.random {
+            return;
+        }
+        let index = self.index();
+        self.playlist.shuffle = enabled;
+        if enabled {
+            self.shuffle_order();
+            let at = self.order.iter().position(|&i| i == index).unwrap_or(0);
+            self.order.swap(0, at);
+        } else {
+            self.order = (0..self.playlist.entries.len()).collect();
+        }
+        self.position = self.order.iter().position(|&i| i == index).unwrap_or(0);
+    }
+
+    /// Leaves out strobe-class entries while `skip` is set, moving on if
+    /// the current one is one. Returns false when no other entry is left.
+    pub fn set_skip_strobe(&mut self, skip: bool, now: Instant) -> bool {
+        self.skip_strobe = skip;
+        if !skip || !self.is_strobe() {
+            return true;
+        }
+        self.next(now);
+        !self.is_strobe()
+    }
+
+    /// Renders a frame at `now`, moving on once the entry's time is up.
+    pub fn render(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode], now: Instant) -> Frame {
+        let duration = Duration::from_secs(self.entry().duration_secs);
+        if now.duration_since(self.started) >= duration {
+            self.next(now);
+        }
+
+        let frame = self.current.update_frame(analysis, nodes);
+        let crossfade = Duration::from_millis(self.playlist.crossfade_ms);
+        let Some((previous, since)) = &mut self.fading else {
+            return frame;
+        };
+        let elapsed = now.duration_since(*since);
+        if elapsed >= crossfade {
+            self.fading = None;
+            return frame;
+        }
+
+        let t = elapsed.as_secs_f32() / crossfade.as_secs_f32();
+        let old = previous.update_frame(analysis, nodes);
+        nodes
+            .iter()
+            .map(|n| {
+                let from = old.get(&n.channel_id).copied().unwrap_or_default();
+                let to = frame.get(&n.channel_id).copied().unwrap_or_default();
+                (n.channel_id, mix(from, to, t))
+            })
+            .collect()
+    }
+
+    fn step(&mut self, forward: bool, now: Instant) {
+        if self.playlist.random {
+            let current = self.position;
+            self.position = match (forward, self.previous) {
+                (false, Some(previous)) => previous,
+                _ => self.pick(),
+            };
+            self.previous = Some(current);
+            self.switch(now, true);
+            return;
+        }
+        let len = self.order.len();
+        for _ in 0..len {
+            if forward {
+                self.position += 1;
+                if self.position == len {
+                    self.position = 0;
+                    if self.playlist.shuffle {
+                        let last = self.order[len - 1];
+                        self.shuffle_order();
+                        if self.order[0] == last {
+                            self.order.swap(0, len - 1);
+                        }
+                    }
+                }
+            } else {
+                self.position = (self.position + len - 1) % len;
+            }
+            let strobe = effect_info(&self.entry().preset.effect).is_some_and(|i| i.strobe);
+            if !(self.skip_strobe && strobe) {
+                break;
+            }
+        }
+        self.switch(now, true);
+    }
+
+    /// Replaces the running effect with the current entry's.
+    fn switch(&mut self, now: Instant, crossfade: bool) {
+        // Entries were checked in new()
+        let Ok(effect) = self.entry().preset.build_effect() else {
+            return;
+        };
+        let previous = std::mem::replace(&mut self.current, effect);
+        self.fading = crossfade.then_some((previous, now));
+        self.started = now;
+        self.tracks = 0;
+    }
+
+    /// A random entry other than the current one, by weight, leaving out
+    /// strobes while they are skipped. Stays on the current one when there
+    /// is no other.
+    fn pick(&mut self) -> usize {
+        let current = self.index();
+        let candidates: Vec<(usize, f32)> = self
+            .playlist
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(i, entry)| {
+                let strobe = effect_info(&entry.preset.effect).is_some_and(|i| i.strobe);
+                *i != current && entry.weight > 0.0 && !(self.skip_strobe && strobe)
+            })
+            .map(|(i, entry)| (i, entry.weight))
+            .collect();
+        let total: f32 = candidates.iter().map(|(_, weight)| weight).sum();
+        let mut target = (self.random() % 1_000_000) as f32 / 1_000_000.0 * total;
+        for (i, weight) in &candidates {
+            if target < *weight {
+                return *i;
+            }
+            target -= weight;
+        }
+        candidates.last().map_or(current, |(i, _)| *i)
+    }
+
+    /// Fisher-Yates with a xorshift generator; no need for a crypto RNG here.
+    fn shuffle_order(&mut self) {
+        for i in (1..self.order.len()).rev() {
+            let j = (self.random() % (i as u64 + 1)) as usize;
+            self.order.swap(i, j);
+        }
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+impl LightEffect for PlaylistPlayer {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        self.render(analysis, nodes, Instant::now())
+    }
+
+    fn smoothing(&self) -> Option<Duration> {
+        self.current.smoothing()
+    }
+
+    fn is_strobe(&self) -> bool {
+        self.current.is_strobe()
+    }
+
+    fn set_daylight(&mut self, daylight: f32) {
+        self.current.set_daylight(daylight);
+        if let Some((previous, _)) = &mut self.fading {
+            previous.set_daylight(daylight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::line_layout;
+
+    fn entry(effect: &str, palette: Vec<(u8, u8, u8)>) -> PlaylistEntry {
+        PlaylistEntry {
+            preset: Preset {
+                effect: effect.to_string(),
+                palette,
+                ..Default::default()
+            },
+            duration_secs: 10,
+            weight: 1.0,
+        }
+    }
+
+    fn playlist(entries: Vec<PlaylistEntry>) -> EffectPlaylist {
+        EffectPlaylist {
+            entries,
+            crossfade_ms: 2000,
+            shuffle: false,
+            random: false,
+            tracks: None,
+        }
+    }
+
+    #[test]
+    fn test_rotates_with_crossfade() {
+        let start = Instant::now();
+        let mut player = PlaylistPlayer::new(
+            playlist(vec![
+                entry("pulse", vec![(200, 0, 0)]),
+                entry("pulse", vec![(0, 0, 200)]),
+            ]),
+            start,
+        )
+        .unwrap();
+        let nodes = line_layout(2);
+        let loud = AnalysisFrame::from(AudioSpectrum {
+            bass: 1.0,
+            mids: 1.0,
+            highs: 1.0,
+            energy: 1.0,
+        });
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+
+        assert_eq!(player.render(&loud, &nodes, at(9.0))[&0], (200, 0, 0));
+        assert_eq!(player.index(), 0);
+        // Time is up: halfway through the fade one second later
+        player.render(&loud, &nodes, at(10.0));
+        assert_eq!(player.index(), 1);
+        assert_eq!(player.render(&loud, &nodes, at(11.0))[&0], (100, 0, 100));
+        assert_eq!(player.render(&loud, &nodes, at(12.5))[&0], (0, 0, 200));
+
+        // Manual skips wrap around both ways
+        player.next(at(13.0));
+        assert_eq!(player.index(), 0);
+        player.prev(at(13.0));
+        player.prev(at(13.0));
+        assert_eq!(player.index(), 0);
+    }
+
+    #[test]
+    fn test_shuffle_and_strobe_skipping() {
+        let start = Instant::now();
+        let names = ["chase", "multiband", "pulse", "spectrum", "warm"];
+        let entries = names.iter().map(|n| entry(n, Vec::new())).collect();
+        let mut player = PlaylistPlayer::new(playlist(entries), start).unwrap();
+
+        player.set_shuffle(true);
+        // One pass plays every entry once, and the next pass does not
+        // repeat the last one straight away
+        let mut seen = vec![player.index()];
+        for _ in 1..names.len() {
+            player.next(start);
+            seen.push(player.index());
+        }
+        let last = player.index();
+        player.next(start);
+        assert_ne!(player.index(), last);
+        seen.sort_unstable();
+        assert_eq!(seen, vec![0, 1, 2, 3, 4]);
+
+        player.set_shuffle(false);
+        while player.index() != 1 {
+            player.next(start);
+        }
+        player.next(start);
+        assert_eq!(player.entry().preset.effect, "pulse");
+        assert!(player.set_skip_strobe(true, start));
+        assert_eq!(player.entry().preset.effect, "spectrum");
+        player.prev(start);
+        assert_eq!(player.entry().preset.effect, "multiband");
+
+        let only_strobe = playlist(vec![entry("pulse", Vec::new())]);
+        let mut player = PlaylistPlayer::new(only_strobe, start).unwrap();
+        assert!(!player.set_skip_strobe(true, start));
+    }
+
+    #[test]
+    fn test_random_by_weight_and_tracks() {
+        let start = Instant::now();
+        let mut entries = vec![
+            entry("spectrum", Vec::new()),
+            entry("warm", Vec::new()),
+            entry("chase", Vec::new()),
+            entry("pulse", Vec::new()),
+        ];
+        entries[1].weight = 3.0;
+        entries[2].weight = 0.0;
+        let mut random = playlist(entries);
+        random.random = true;
+        random.tracks = Some(2);
+        let mut player = PlaylistPlayer::new(random, start).unwrap();
+
+        let mut counts = [0; 4];
+        for _ in 0..2000 {
+            let before = player.index();
+            player.next(start);
+            assert_ne!(player.index(), before);
+            counts[player.index()] += 1;
+        }
+        assert_eq!(counts[2], 0);
+        // Warm comes up about three times as often as each of the others
+        // would without the no-repeat rule
+        assert!(counts[1] > counts[0] && counts[1] > counts[3], "{counts:?}");
+
+        let played = player.index();
+        player.next(start);
+        player.prev(start);
+        assert_eq!(player.index(), played);
+
+        player.track_changed(start);
+        assert_eq!(player.index(), played);
+        player.track_changed(start);
+        assert_ne!(player.index(), played);
+
+        // Only warm is left once strobes are skipped
+        assert!(player.set_skip_strobe(true, start));
+        for _ in 0..10 {
+            player.next(start);
+            assert!(!player.is_strobe());
+        }
+    }
+
+    #[test]
+    fn test_shuffle_combinations() {
+        let shuffle = ShuffleConfig {
+            effects: BTreeMap::from([("warm".to_string(), 2.0), ("strobe".to_string(), 0.0)]),
+            palettes: vec![vec![(255, 0, 0)], vec![(0, 0, 255)]],
+            minutes: 3,
+            tracks: Some(1),
+            ..Default::default()
+        };
+        let playlist = shuffle.playlist();
+        assert!(playlist.random);
+        assert_eq!(playlist.tracks, Some(1));
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[1].preset.palette, vec![(0, 0, 255)]);
+        assert_eq!(playlist.entries[1].duration_secs, 180);
+        assert_eq!(playlist.entries[1].weight, 2.0);
+
+        // Without a whitelist every non-strobe effect takes part
+        let all = ShuffleConfig::default().playlist();
+        assert!(all
+            .entries
+            .iter()
+            .all(|e| !effect_info(&e.preset.effect).unwrap().strobe));
+        assert!(PlaylistPlayer::new(all, Instant::now()).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_bad_entries() {
+        let start = Instant::now();
+        assert!(PlaylistPlayer::new(playlist(Vec::new()), start).is_err());
+        let unknown = playlist(vec![entry("nope", Vec::new())]);
+        assert!(matches!(
+            PlaylistPlayer::new(unknown, start),
+            Err(PresetError::UnknownEffect(_))
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Pauses streaming while the room is empty, going by Hue motion sensors,
+//! so an always-on daemon does not keep the lights and the bridge busy for
+//! nobody.
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Which motion sensors count and how long the room has to be still.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionConfig {
+    /// Motion sensors by their `motion` resource or device ID; every sensor
+    /// on the bridge when empty.
+    pub sensors: Vec<String>,
+    /// Minutes without motion after which the room counts as empty. Hue
+    /// sensors do not see someone sitting still, so keep this generous.
+    pub empty_minutes: u64,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            sensors: Vec::new(),
+            empty_minutes: 15,
+        }
+    }
+}
+
+/// Whether the room is occupied, from the time of the last motion. The room
+/// counts as occupied on start.
+#[derive(Debug, Clone)]
+pub struct Presence {
+    empty_after: Duration,
+    last_motion: Instant,
+}
+
+impl Presence {
+    pub fn new(empty_after: Duration, now: Instant) -> Self {
+        Self {
+            empty_after,
+            last_motion: now,
+        }
+    }
+
+    pub fn motion(&mut self, now: Instant) {
+        self.last_motion = self.last_motion.max(now);
+    }
+
+    pub fn is_present(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_motion) < self.empty_after
+    }
+}
+
+/// One message of the v2 event stream.
+#[derive(Debug, Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    data: Vec<MotionUpdate>,
+}
+
+/// The parts of an updated resource that matter here; updates of other
+/// resource types have no `motion`.
+#[derive(Debug, Deserialize)]
+struct MotionUpdate {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    owner: Option<Owner>,
+    #[serde(default)]
+    motion: Option<MotionState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Owner {
+    rid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MotionState {
+    #[serde(default)]
+    motion: bool,
+}
+
+/// Whether the `data:` payload of an event stream message reports motion on
+/// one of `sensors` (any sensor when empty).
+pub fn motion_detected(data: &str, sensors: &[String]) -> bool {
+    let Ok(events) = serde_json::from_str::<Vec<StreamEvent>>(data) else {
+        return false;
+    };
+    events
+        .iter()
+        .filter(|event| event.kind == "update")
+        .flat_map(|event| &event.data)
+        .filter(|update| update.motion.as_ref().is_some_and(|m| m.motion))
+        .any(|update| {
+            sensors.is_empty()
+                || sensors.iter().any(|sensor| {
+                    *sensor == update.id || update.owner.as_ref().is_some_and(|o| o.rid == *sensor)
+                })
+        })
+}
+
+/// How often the watcher re-checks whether the room became empty.
+#[cfg(feature = "bridge")]
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Starts following motion on the bridge's v2 event stream in the
+/// background; the receiver tells whether the room is occupied. The event
+/// stream is reopened when the bridge drops it.
+#[cfg(feature = "bridge")]
+pub fn spawn_presence_watcher(
+    config: crate::models::HueConfig,
+    motion: &MotionConfig,
+) -> tokio::sync::watch::Receiver<bool> {
+    let (tx, rx) = tokio::sync::watch::channel(true);
+    let sensors = motion.sensors.clone();
+    let empty_after = Duration::from_secs(motion.empty_minutes.max(1) * 60);
+
+    tokio::spawn(async move {
+        let mut presence = Presence::new(empty_after, Instant::now());
+        let mut check = tokio::time::interval(CHECK_INTERVAL);
+        let mut events: Option<reqwest::Response> = None;
+        let mut buffer = String::new();
+        while !tx.is_closed() {
+            let Some(response) = &mut events else {
+                match open_event_stream(&config).await {
+                    Ok(response) => events = Some(response),
+                    Err(e) => {
+                        tracing::warn!("Motion: cannot open the event stream: {}", e);
+                        tokio::time::sleep(CHECK_INTERVAL).await;
+                    }
+                }
+                continue;
+            };
+            let mut closed = false;
+            tokio::select! {
+                chunk = response.chunk() => match chunk {
+                    Ok(Some(bytes)) => {
+                        buffer.push_str(&String::from_utf8_lossy(&bytes));
+                        while let Some(end) = buffer.find('\n') {
+                            let line: String = buffer.drain(..=end).collect();
+                            let data = line.trim_end().strip_prefix("data:");
+                            if data.is_some_and(|data| motion_detected(data, &sensors)) {
+                                presence.motion(Instant::now());
+                            }
+                        }
+                    }
+                    Ok(None) => closed = true,
+                    Err(e) => {
+                        tracing::debug!("Motion: event stream failed: {}", e);
+                        closed = true;
+                    }
+                },
+                _ = check.tick() => {}
+            }
+            if closed {
+                events = None;
+                buffer.clear();
+            }
+            let present = presence.is_present(Instant::now());
+            tx.send_if_modified(|current| std::mem::replace(current, present) != present);
+        }
+    });
+
+    rx
+}
+
+#[cfg(feature = "bridge")]
+async fn open_event_stream(
+    config: &crate::models::HueConfig,
+) -> Result<reqwest::Response, crate::api::error::HueError> {
+    let url = format!(
+        "https://{}/eventstream/clip/v2",
+        crate::api::url_host(&config.bridge_ip)
+    );
+    let response = crate::api::build_client()?
+        .get(url)
+        .header("hue-application-key", &config.username)
+        .header("Accept", "text/event-stream")
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence_times_out() {
+        let start = Instant::now();
+        let mut presence = Presence::new(Duration::from_secs(60), start);
+        assert!(presence.is_present(start + Duration::from_secs(59)));
+        assert!(!presence.is_present(start + Duration::from_secs(60)));
+        presence.motion(start + Duration::from_secs(90));
+        assert!(presence.is_present(start + Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn test_motion_detected() {
+        let data = r#"[{
+            "creationtime": "2024-01-01T00:00:00Z",
+            "id": "e1",
+            "type": "update",
+            "data": [
+                {"id": "l1", "type": "light", "on": {"on": true}},
+                {"id": "m1", "owner": {"rid": "d1", "rtype": "device"}, "type": "motion",
+                 "motion": {"motion": true, "motion_valid": true}}
+            ]
+        }]"#;
+        assert!(motion_detected(data, &[]));
+        assert!(motion_detected(data, &["m1".to_string()]));
+        assert!(motion_detected(data, &["d1".to_string()]));
+        assert!(!motion_detected(data, &["m2".to_string()]));
+
+        let still = data.replace(r#""motion": true"#, r#""motion": false"#);
+        assert!(!motion_detected(&still, &[]));
+        assert!(!motion_detected(": hi", &[]));
+    }
+}
@@ -0,0 +1,198 @@
+//! Named effect presets stored as JSON files in `<config dir>/presets/`.
+use crate::config::{config_dir, write_atomic};
+use crate::effects::{
+    create_effect_with, BrightnessEffect, CtOnlyEffect, EffectParams, LightEffect,
+    SensitivityEffect,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PresetError {
+    #[error("Invalid preset name '{0}' (use letters, digits, '-' and '_')")]
+    InvalidName(String),
+    #[error("Preset '{0}' not found")]
+    NotFound(String),
+    #[error("Unknown effect '{0}'")]
+    UnknownEffect(String),
+    #[error("Playlist has no entries")]
+    EmptyPlaylist,
+    #[error("Preset I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Preset parse error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Everything needed to recreate an effect setup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub effect: String,
+    /// Effect colors; for `multiband` the bass, mids and highs colors.
+    #[serde(default)]
+    pub palette: Vec<(u8, u8, u8)>,
+    /// Master brightness (0.0 - 1.0).
+    #[serde(default = "default_brightness")]
+    pub brightness: f32,
+    /// Gain on the audio levels driving the effect: above 1.0 for quiet
+    /// rooms, below for loud ones.
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f32,
+    #[serde(default)]
+    pub ct_only: bool,
+    /// Entertainment area to stream to; `None` keeps the configured one.
+    #[serde(default)]
+    pub entertainment_group_id: Option<String>,
+    /// Target frame rate while the preset is active; `None` keeps the current one.
+    #[serde(default)]
+    pub fps: Option<u32>,
+    /// Band color mapping and other effect tuning.
+    #[serde(default, skip_serializing_if = "EffectParams::is_default")]
+    pub params: EffectParams,
+}
+
+fn default_brightness() -> f32 {
+    1.0
+}
+
+fn default_sensitivity() -> f32 {
+    1.0
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            effect: "multiband".to_string(),
+            palette: Vec::new(),
+            brightness: default_brightness(),
+            sensitivity: default_sensitivity(),
+            ct_only: false,
+            entertainment_group_id: None,
+            fps: None,
+            params: EffectParams::default(),
+        }
+    }
+}
+
+impl Preset {
+    /// Builds the effect described by this preset, including sensitivity,
+    /// brightness and CT constraints.
+    pub fn build_effect(&self) -> Result<Box<dyn LightEffect>, PresetError> {
+        let mut effect = create_effect_with(&self.effect, &self.palette, &self.params)
+            .ok_or_else(|| PresetError::UnknownEffect(self.effect.clone()))?;
+        if self.sensitivity != 1.0 {
+            effect = Box::new(SensitivityEffect::new(effect, self.sensitivity));
+        }
+        if self.ct_only {
+            effect = Box::new(CtOnlyEffect::new(effect));
+        }
+        if self.brightness < 1.0 {
+            effect = Box::new(BrightnessEffect::new(effect, self.brightness));
+        }
+        Ok(effect)
+    }
+}
+
+/// Default preset directory: `<config dir>/presets`.
+pub fn presets_dir() -> PathBuf {
+    config_dir().join("presets")
+}
+
+fn preset_path(dir: &Path, name: &str) -> Result<PathBuf, PresetError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(PresetError::InvalidName(name.to_string()));
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+pub fn save(dir: &Path, name: &str, preset: &Preset) -> Result<(), PresetError> {
+    let path = preset_path(dir, name)?;
+    fs::create_dir_all(dir)?;
+    write_atomic(&path, serde_json::to_string_pretty(preset)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn load(dir: &Path, name: &str) -> Result<Preset, PresetError> {
+    let path = preset_path(dir, name)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(PresetError::NotFound(name.to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Lists the names of all saved presets, sorted alphabetically.
+pub fn list(dir: &Path) -> Result<Vec<String>, PresetError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hueflow-presets-{}", std::process::id()));
+        let preset = Preset {
+            effect: "pulse".to_string(),
+            palette: vec![(255, 0, 128)],
+            brightness: 0.6,
+            sensitivity: 1.5,
+            ct_only: false,
+            entertainment_group_id: None,
+            fps: None,
+            params: EffectParams::default(),
+        };
+
+        save(&dir, "party1", &preset).unwrap();
+        assert_eq!(load(&dir, "party1").unwrap(), preset);
+        assert_eq!(list(&dir).unwrap(), vec!["party1".to_string()]);
+        assert!(matches!(
+            load(&dir, "missing"),
+            Err(PresetError::NotFound(_))
+        ));
+        assert!(matches!(
+            save(&dir, "../escape", &preset),
+            Err(PresetError::InvalidName(_))
+        ));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_build_unknown_effect() {
+        let preset = Preset {
+            effect: "nope".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            preset.build_effect(),
+            Err(PresetError::UnknownEffect(_))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Pre-rendered light shows (`.hfl` files) played back in sync with
+//! external timecode, for stage productions run from a show controller.
+//!
+//! A show is recorded from a normal run and stored one JSON object per
+//! line: a header with the frame rate, then one frame (channel -> RGB) per
+//! line. [`ShowPlayer`] plays it as an effect at the position of a
+//! [`TimecodeClock`], fed by MIDI timecode or LTC audio.
+pub mod render;
+pub mod timecode;
+
+use crate::audio_interface::{AnalysisFrame, AudioSpectrum};
+use crate::effects::{Frame, LightEffect};
+use crate::models::LightNode;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+
+pub use timecode::{FrameRate, Timecode, TimecodeClock};
+
+/// File extension of recorded shows.
+pub const SHOW_EXTENSION: &str = "hfl";
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ShowError {
+    #[error("Not a HueFlow show (expected an .{} header)", SHOW_EXTENSION)]
+    NotAShow,
+    #[error("Show format version {0} is not supported")]
+    Version(u32),
+    #[error("Show I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Show parse error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// First line of a show file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    hfl: u32,
+    fps: f32,
+}
+
+/// Frames at a fixed rate, starting at position zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Show {
+    pub fps: f32,
+    pub frames: Vec<Frame>,
+}
+
+impl Show {
+    pub fn load(path: &Path) -> Result<Self, ShowError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(|_| ShowError::NotAShow)?,
+            None => return Err(ShowError::NotAShow),
+        };
+        if header.hfl != FORMAT_VERSION {
+            return Err(ShowError::Version(header.hfl));
+        }
+        let frames = lines
+            .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<Result<_, ShowError>>()?;
+        Ok(Self {
+            fps: header.fps,
+            frames,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames.len() as f64 / self.fps as f64)
+    }
+
+    /// The frame showing at `position`, `None` past the end.
+    pub fn frame_at(&self, position: Duration) -> Option<&Frame> {
+        let index = (position.as_secs_f64() * self.fps as f64) as usize;
+        self.frames.get(index)
+    }
+}
+
+/// Writes a show frame by frame while it is rendered.
+pub struct ShowWriter {
+    out: BufWriter<File>,
+    fps: f32,
+    written: u64,
+}
+
+impl ShowWriter {
+    /// Creates `path` for frames rendered at `fps`.
+    pub fn create(path: &Path, fps: f32) -> Result<Self, ShowError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let header = Header {
+            hfl: FORMAT_VERSION,
+            fps,
+        };
+        writeln!(out, "{}", serde_json::to_string(&header)?)?;
+        Ok(Self {
+            out,
+            fps,
+            written: 0,
+        })
+    }
+
+    /// Appends the next frame.
+    pub fn write(&mut self, frame: &Frame) -> Result<(), ShowError> {
+        writeln!(self.out, "{}", serde_json::to_string(frame)?)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Writes `frame` as the frame at `position` into the show, repeating
+    /// it over frames missed since the last one and leaving it out when
+    /// that frame is already written, so the show keeps time when frames
+    /// come late or the render rate changes.
+    pub fn write_at(&mut self, frame: &Frame, position: Duration) -> Result<(), ShowError> {
+        let index = (position.as_secs_f64() * self.fps as f64) as u64;
+        while self.written <= index {
+            self.write(frame)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), ShowError> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Plays a [`Show`] as an effect, at the position of a [`TimecodeClock`].
+///
+/// Timecode `start` is the show's first frame. Before it, past the end and
+/// until timecode arrives the lights are dark; when timecode stops, the
+/// show holds.
+pub struct ShowPlayer {
+    show: Show,
+    clock: TimecodeClock,
+    start: Duration,
+}
+
+impl ShowPlayer {
+    pub fn new(show: Show, clock: TimecodeClock, start: Duration) -> Self {
+        Self { show, clock, start }
+    }
+
+    /// The show's frame at `now` (on the clock's timeline), for the lights
+    /// in `nodes`.
+    pub fn render(&self, nodes: &[LightNode], now: Duration) -> Frame {
+        let frame = self
+            .clock
+            .position(now)
+            .and_then(|position| position.checked_sub(self.start))
+            .and_then(|position| self.show.frame_at(position));
+        nodes
+            .iter()
+            .map(|n| {
+                let color = frame.and_then(|f| f.get(&n.channel_id)).copied();
+                (n.channel_id, color.unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
+impl LightEffect for ShowPlayer {
+    fn update(&mut self, audio: &AudioSpectrum, nodes: &[LightNode]) -> Frame {
+        self.update_frame(&(*audio).into(), nodes)
+    }
+
+    fn update_frame(&mut self, analysis: &AnalysisFrame, nodes: &[LightNode]) -> Frame {
+        self.render(nodes, analysis.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::testing::line_layout;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_record_and_play() {
+        let path = std::env::temp_dir().join(format!("hueflow-show-{}.hfl", std::process::id()));
+        let mut writer = ShowWriter::create(&path, 10.0).unwrap();
+        for i in 0..20u8 {
+            writer
+                .write(&HashMap::from([(0, (i, 0, 0)), (1, (0, i, 0))]))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let show = Show::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(show.fps, 10.0);
+        assert_eq!(show.duration(), Duration::from_secs(2));
+        assert_eq!(
+            show.frame_at(Duration::from_millis(1550)).unwrap()[&1],
+            (0, 15, 0)
+        );
+        assert!(show.frame_at(Duration::from_secs(2)).is_none());
+
+        // The show starts at timecode 01:00:00:00
+        let start = Duration::from_secs(1);
+        let clock = TimecodeClock::default();
+        let player = ShowPlayer::new(show, clock.clone(), Duration::from_secs(3600));
+        let nodes = line_layout(3);
+        let dark = HashMap::from([(0, (0, 0, 0)), (1, (0, 0, 0)), (2, (0, 0, 0))]);
+        assert_eq!(player.render(&nodes, start), dark);
+        clock.set(Duration::from_millis(3_600_500), start);
+        let frame = player.render(&nodes, start + Duration::from_millis(100));
+        assert_eq!(frame[&0], (6, 0, 0));
+        assert_eq!(frame[&2], (0, 0, 0));
+        clock.set(Duration::from_secs(10), start);
+        assert_eq!(player.render(&nodes, start), dark);
+
+        // Late frames fill the gap, early ones are left out
+        let mut writer = ShowWriter::create(&path, 10.0).unwrap();
+        let frame = |r: u8| HashMap::from([(0, (r, 0, 0))]);
+        writer.write_at(&frame(1), Duration::ZERO).unwrap();
+        writer
+            .write_at(&frame(2), Duration::from_millis(250))
+            .unwrap();
+        writer
+            .write_at(&frame(3), Duration::from_millis(260))
+            .unwrap();
+        writer
+            .write_at(&frame(4), Duration::from_millis(300))
+            .unwrap();
+        writer.finish().unwrap();
+        let show = Show::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reds: Vec<u8> = show.frames.iter().map(|f| f[&0].0).collect();
+        assert_eq!(reds, vec![1, 2, 2, 4]);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("hueflow-notshow-{}.hfl", std::process::id()));
+        std::fs::write(&path, "{\"effect\":\"pulse\"}\n").unwrap();
+        assert!(matches!(Show::load(&path), Err(ShowError::NotAShow)));
+        std::fs::write(&path, "{\"hfl\":9,\"fps\":20}\n").unwrap();
+        assert!(matches!(Show::load(&path), Err(ShowError::Version(9))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
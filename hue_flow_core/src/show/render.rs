@@ -0,0 +1,100 @@
+//! Top-down pictures of the room for sharing a show as a video or GIF,
+//! each light a glow of its color at its place in the entertainment area.
+use crate::effects::Frame;
+use crate::models::LightNode;
+
+/// Floor color behind the lights.
+const BACKGROUND: (u8, u8, u8) = (18, 18, 22);
+/// Share of the picture the room spans; the rest leaves room for glows.
+const ROOM_SCALE: f32 = 0.8;
+/// Glow radius relative to the picture size.
+const GLOW_RADIUS: f32 = 0.12;
+
+/// Paints frames for a fixed layout, as square RGB pictures with the back
+/// of the room at the top and the front at the bottom.
+pub struct RoomPainter {
+    size: u32,
+    /// Channel and pixel position of every light.
+    lights: Vec<(u8, f32, f32)>,
+}
+
+impl RoomPainter {
+    /// Pictures of `size` x `size` pixels of the lights in `nodes`.
+    pub fn new(nodes: &[LightNode], size: u32) -> Self {
+        let half = size as f32 / 2.0;
+        let lights = nodes
+            .iter()
+            .map(|node| {
+                let x = half + node.x.clamp(-1.0, 1.0) as f32 * half * ROOM_SCALE;
+                let y = half + node.y.clamp(-1.0, 1.0) as f32 * half * ROOM_SCALE;
+                (node.channel_id, x, y)
+            })
+            .collect();
+        Self { size, lights }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// `frame` as RGB pixels, row by row. Lights missing from the frame are
+    /// dark.
+    pub fn paint(&self, frame: &Frame) -> Vec<u8> {
+        let radius = self.size as f32 * GLOW_RADIUS;
+        let lights: Vec<_> = self
+            .lights
+            .iter()
+            .map(|&(channel, x, y)| {
+                let (r, g, b) = frame.get(&channel).copied().unwrap_or_default();
+                (x, y, [r as f32, g as f32, b as f32])
+            })
+            .collect();
+        let mut pixels = Vec::with_capacity((self.size * self.size * 3) as usize);
+        for py in 0..self.size {
+            for px in 0..self.size {
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let mut rgb = [BACKGROUND.0, BACKGROUND.1, BACKGROUND.2].map(f32::from);
+                for (x, y, color) in &lights {
+                    let d = (cx - x).hypot(cy - y) / radius;
+                    if d > 2.5 {
+                        continue;
+                    }
+                    // Solid bulb in the middle, soft falloff around it
+                    let weight = if d < 0.25 { 1.0 } else { (-d * d * 1.5).exp() };
+                    for (c, l) in rgb.iter_mut().zip(color) {
+                        *c += l * weight;
+                    }
+                }
+                pixels.extend(rgb.map(|c| c.min(255.0) as u8));
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_paint_light_at_its_place() {
+        let node = |channel_id: u8, x, y| LightNode {
+            id: channel_id.to_string(),
+            channel_id,
+            x,
+            y,
+            z: 0.0,
+            segment: 0,
+        };
+        // Front left and back right
+        let painter = RoomPainter::new(&[node(0, -1.0, 1.0), node(1, 1.0, -1.0)], 100);
+        let pixels = painter.paint(&HashMap::from([(0, (255, 0, 0))]));
+        assert_eq!(pixels.len(), 100 * 100 * 3);
+        let at = |x: usize, y: usize| &pixels[(y * 100 + x) * 3..][..3];
+        assert_eq!(at(10, 90), &[255, 18, 22]);
+        // The dark light leaves the floor as it is
+        assert_eq!(at(90, 10), &[18, 18, 22]);
+        assert_eq!(at(50, 50), &[18, 18, 22]);
+    }
+}
@@ -0,0 +1,604 @@
+//! SMPTE timecode from show control: MIDI timecode (MTC) bytes and linear
+//! timecode (LTC) audio, decoded into a shared [`TimecodeClock`].
+use crate::timing;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How far the clock runs on by itself after the last timecode before it
+/// holds, covering the gaps between quarter frames or LTC frames.
+pub const FREEWHEEL: Duration = Duration::from_millis(200);
+
+/// SMPTE frame rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 29.97 FPS drop-frame (NTSC video).
+    Fps2997Drop,
+    Fps30,
+}
+
+impl FrameRate {
+    /// Nominal frames per second, i.e. frame numbers per timecode second.
+    pub fn frames(self) -> u32 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997Drop | FrameRate::Fps30 => 30,
+        }
+    }
+
+    /// Actual frames per second.
+    pub fn fps(self) -> f64 {
+        match self {
+            FrameRate::Fps2997Drop => 30_000.0 / 1001.0,
+            other => other.frames() as f64,
+        }
+    }
+
+    /// The rate of the MTC rate bits.
+    fn from_mtc(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps2997Drop,
+            _ => FrameRate::Fps30,
+        }
+    }
+}
+
+/// A position as hours, minutes, seconds and frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    /// Parses `HH:MM:SS:FF` (`;` before the frames for drop-frame) at `rate`.
+    pub fn parse(text: &str, rate: FrameRate) -> Option<Self> {
+        let parts: Vec<u8> = text
+            .split([':', ';'])
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let [hours, minutes, seconds, frames] = parts[..] else {
+            return None;
+        };
+        let valid = hours < 24 && minutes < 60 && seconds < 60 && (frames as u32) < rate.frames();
+        valid.then_some(Self {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            rate,
+        })
+    }
+
+    /// Time since `00:00:00:00`.
+    pub fn position(&self) -> Duration {
+        let seconds = self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64;
+        let mut frames = seconds * self.rate.frames() as u64 + self.frames as u64;
+        if self.rate == FrameRate::Fps2997Drop {
+            // Frame numbers 0 and 1 are skipped every minute but every tenth
+            let minutes = self.hours as u64 * 60 + self.minutes as u64;
+            frames -= 2 * (minutes - minutes / 10);
+        }
+        Duration::from_secs_f64(frames as f64 / self.rate.fps())
+    }
+
+    /// Length of one frame.
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate.fps())
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.rate == FrameRate::Fps2997Drop {
+            ';'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+/// The show position last heard from the timecode source, shared between
+/// the reader and the player. Times are on the [`timing::now`] timeline,
+/// which rendered frames follow.
+#[derive(Debug, Clone)]
+pub struct TimecodeClock {
+    last: Arc<Mutex<Option<(Duration, Duration)>>>,
+    freewheel: Duration,
+}
+
+impl Default for TimecodeClock {
+    fn default() -> Self {
+        Self {
+            last: Arc::new(Mutex::new(None)),
+            freewheel: FREEWHEEL,
+        }
+    }
+}
+
+impl TimecodeClock {
+    /// A clock without a timecode source, running from `position` at `now`.
+    pub fn running_from(position: Duration, now: Duration) -> Self {
+        let clock = Self {
+            freewheel: Duration::MAX,
+            ..Default::default()
+        };
+        clock.set(position, now);
+        clock
+    }
+
+    /// Records that the source was at `position` at `at`.
+    pub fn set(&self, position: Duration, at: Duration) {
+        *self.last.lock().unwrap() = Some((position, at));
+    }
+
+    /// The position at `now`: the last one heard, run on for up to
+    /// [`FREEWHEEL`], then held until timecode comes in again. `None`
+    /// before the first timecode.
+    pub fn position(&self, now: Duration) -> Option<Duration> {
+        let (position, at) = (*self.last.lock().unwrap())?;
+        Some(position + now.saturating_sub(at).min(self.freewheel))
+    }
+}
+
+/// Decodes MIDI timecode from a MIDI byte stream: quarter frames while the
+/// source runs and full-frame messages when it locates.
+#[derive(Debug, Default)]
+pub struct MtcDecoder {
+    /// The last status byte, when its data bytes are still expected.
+    status: Option<u8>,
+    /// Quarter-frame pieces, by piece number.
+    pieces: [u8; 8],
+    /// Pieces received since piece 0, as a bit set.
+    received: u8,
+    sysex: Vec<u8>,
+}
+
+impl MtcDecoder {
+    /// Feeds one byte, returning the timecode it completes.
+    ///
+    /// A quarter-frame timecode is only complete after all eight pieces,
+    /// two frames after it was current, so two frames are added.
+    pub fn push(&mut self, byte: u8) -> Option<Timecode> {
+        if byte >= 0xF8 {
+            // Real-time messages may appear anywhere
+            return None;
+        }
+        if byte & 0x80 != 0 {
+            self.status = Some(byte);
+            if byte == 0xF0 {
+                self.sysex.clear();
+            } else if byte == 0xF7 {
+                self.status = None;
+                return self.full_frame();
+            }
+            return None;
+        }
+        match self.status? {
+            0xF1 => {
+                self.status = None;
+                self.quarter_frame(byte)
+            }
+            0xF0 => {
+                self.sysex.push(byte);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn quarter_frame(&mut self, data: u8) -> Option<Timecode> {
+        let piece = (data >> 4) as usize;
+        self.pieces[piece] = data & 0x0F;
+        if piece == 0 {
+            self.received = 0;
+        }
+        self.received |= 1 << piece;
+        if piece != 7 || self.received != 0xFF {
+            return None;
+        }
+        let p = &self.pieces;
+        let timecode = Timecode {
+            frames: p[0] | (p[1] & 0x01) << 4,
+            seconds: p[2] | (p[3] & 0x03) << 4,
+            minutes: p[4] | (p[5] & 0x03) << 4,
+            hours: p[6] | (p[7] & 0x01) << 4,
+            rate: FrameRate::from_mtc(p[7] >> 1),
+        };
+        Some(advance(timecode, 2))
+    }
+
+    /// `F0 7F <device> 01 01 hr mn sc fr F7`
+    fn full_frame(&mut self) -> Option<Timecode> {
+        let sysex = std::mem::take(&mut self.sysex);
+        let [0x7F, _, 0x01, 0x01, hours, minutes, seconds, frames] = sysex[..] else {
+            return None;
+        };
+        Some(Timecode {
+            hours: hours & 0x1F,
+            minutes,
+            seconds,
+            frames,
+            rate: FrameRate::from_mtc(hours >> 5),
+        })
+    }
+}
+
+/// `timecode` moved on by `frames`.
+fn advance(timecode: Timecode, frames: u8) -> Timecode {
+    let rate = timecode.rate.frames() as u8;
+    let mut next = timecode;
+    next.frames += frames;
+    if next.frames >= rate {
+        next.frames -= rate;
+        next.seconds += 1;
+        if next.seconds == 60 {
+            next.seconds = 0;
+            next.minutes += 1;
+            if next.minutes == 60 {
+                next.minutes = 0;
+                next.hours = (next.hours + 1) % 24;
+            }
+        }
+    }
+    next
+}
+
+/// Sync word ending every LTC frame, as its bits 64-79 in transmit order.
+const LTC_SYNC: u128 = 0xBFFC;
+const LTC_BITS: u32 = 80;
+
+/// Decodes linear timecode from audio samples (biphase mark code, 80 bits
+/// per frame).
+#[derive(Debug)]
+pub struct LtcDecoder {
+    sample_rate: u32,
+    /// Estimated samples per bit.
+    period: f32,
+    /// Samples since the last transition.
+    since: f32,
+    high: bool,
+    /// A half-bit was seen; the next one completes a 1.
+    half: bool,
+    /// The last 80 bits, the newest at the top.
+    bits: u128,
+}
+
+impl LtcDecoder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            // Between 24 and 30 FPS, close enough to lock onto either
+            period: sample_rate as f32 / (25.0 * LTC_BITS as f32),
+            since: 0.0,
+            high: false,
+            half: false,
+            bits: 0,
+        }
+    }
+
+    /// Feeds samples, returning the last timecode completed in them and how
+    /// many samples before their end it completed.
+    pub fn push(&mut self, samples: &[f32]) -> Option<(Timecode, usize)> {
+        let mut decoded = None;
+        for (i, &sample) in samples.iter().enumerate() {
+            self.since += 1.0;
+            // Hysteresis against noise around zero
+            let high = if self.high {
+                sample > -0.05
+            } else {
+                sample > 0.05
+            };
+            if high == self.high {
+                continue;
+            }
+            self.high = high;
+            let interval = std::mem::take(&mut self.since);
+            if let Some(timecode) = self.transition(interval) {
+                decoded = Some((timecode, samples.len() - 1 - i));
+            }
+        }
+        decoded
+    }
+
+    fn transition(&mut self, interval: f32) -> Option<Timecode> {
+        if interval < self.period * 0.75 {
+            self.half = !self.half;
+            if self.half {
+                return None;
+            }
+            self.period += (interval * 2.0 - self.period) * 0.05;
+            self.bit(true)
+        } else if interval < self.period * 1.5 {
+            self.half = false;
+            self.period += (interval - self.period) * 0.05;
+            self.bit(false)
+        } else {
+            // Silence or a different rate: start over, learning the new one
+            self.half = false;
+            self.period = self
+                .period
+                .max(interval.min(self.sample_rate as f32 / 400.0));
+            None
+        }
+    }
+
+    fn bit(&mut self, one: bool) -> Option<Timecode> {
+        self.bits = self.bits >> 1 | (one as u128) << (LTC_BITS - 1);
+        if self.bits >> 64 != LTC_SYNC {
+            return None;
+        }
+        let field = |at: u32, len: u32| ((self.bits >> at) & ((1 << len) - 1)) as u8;
+        let bits_per_second = self.sample_rate as f32 / self.period;
+        let rate = if field(10, 1) == 1 {
+            FrameRate::Fps2997Drop
+        } else {
+            match (bits_per_second / LTC_BITS as f32).round() as u32 {
+                ..=24 => FrameRate::Fps24,
+                25..=27 => FrameRate::Fps25,
+                _ => FrameRate::Fps30,
+            }
+        };
+        let timecode = Timecode {
+            frames: field(0, 4) + 10 * field(8, 2),
+            seconds: field(16, 4) + 10 * field(24, 3),
+            minutes: field(32, 4) + 10 * field(40, 3),
+            hours: field(48, 4) + 10 * field(56, 2),
+            rate,
+        };
+        // The frame ends with its sync word; the next one starts now
+        Some(advance(timecode, 1))
+    }
+}
+
+/// Reads MTC from a raw MIDI device or pipe (e.g. `/dev/snd/midiC1D0` on
+/// Linux) on a thread of its own, updating `clock`.
+pub fn spawn_mtc_reader(path: PathBuf, clock: TimecodeClock) -> io::Result<()> {
+    let mut device = std::fs::File::open(&path)?;
+    thread::Builder::new()
+        .name("hueflow-mtc".to_string())
+        .spawn(move || {
+            let mut decoder = MtcDecoder::default();
+            let mut buffer = [0u8; 256];
+            loop {
+                let read = match device.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(e) => {
+                        tracing::warn!("Cannot read MIDI timecode from {}: {}", path.display(), e);
+                        break;
+                    }
+                };
+                let now = timing::now();
+                for &byte in &buffer[..read] {
+                    if let Some(timecode) = decoder.push(byte) {
+                        clock.set(timecode.position(), now);
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Decodes LTC from an audio input on a thread of its own, updating `clock`.
+#[cfg(feature = "capture")]
+pub fn spawn_ltc_reader(
+    options: crate::audio_input::CaptureOptions,
+    clock: TimecodeClock,
+) -> anyhow::Result<()> {
+    use crate::audio_input::{AudioInput, ANALYSIS_RATE};
+    use anyhow::Context;
+
+    let mut input = AudioInput::start(options)?;
+    thread::Builder::new()
+        .name("hueflow-ltc".to_string())
+        .spawn(move || {
+            let mut decoder = LtcDecoder::new(ANALYSIS_RATE);
+            let mut samples = Vec::new();
+            loop {
+                samples.clear();
+                input.drain_samples(&mut samples);
+                let now = timing::now();
+                if let Some((timecode, ago)) = decoder.push(&samples) {
+                    let ago = Duration::from_secs_f64(ago as f64 / ANALYSIS_RATE as f64);
+                    clock.set(timecode.position(), now.saturating_sub(ago));
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+        })
+        .context("Cannot start LTC reader thread")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tc(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate) -> Timecode {
+        Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            rate,
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let one_hour = tc(1, 0, 0, 0, FrameRate::Fps25);
+        assert_eq!(one_hour.position(), Duration::from_secs(3600));
+        assert_eq!(
+            tc(0, 0, 1, 12, FrameRate::Fps24).position(),
+            Duration::from_millis(1500)
+        );
+        // 00:10:00;00 drop-frame is frame 17982, skipping 2 numbers in 9
+        // of the 10 minutes
+        let drop = tc(0, 10, 0, 0, FrameRate::Fps2997Drop);
+        let expected = 17_982.0 * 1001.0 / 30_000.0;
+        assert!((drop.position().as_secs_f64() - expected).abs() < 1e-6);
+        assert_eq!(drop.to_string(), "00:10:00;00");
+
+        assert_eq!(
+            Timecode::parse("01:00:00:00", FrameRate::Fps25),
+            Some(one_hour)
+        );
+        assert_eq!(Timecode::parse("01:00:00:25", FrameRate::Fps25), None);
+        assert_eq!(Timecode::parse("01:00:00", FrameRate::Fps25), None);
+        assert_eq!(
+            advance(tc(0, 59, 59, 29, FrameRate::Fps30), 2),
+            tc(1, 0, 0, 1, FrameRate::Fps30)
+        );
+    }
+
+    #[test]
+    fn test_clock_freewheels_then_holds() {
+        let start = Duration::from_secs(1);
+        let clock = TimecodeClock::default();
+        assert_eq!(clock.position(start), None);
+        clock.set(Duration::from_secs(10), start);
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        assert_eq!(clock.position(at(100)), Some(Duration::from_millis(10_100)));
+        assert_eq!(
+            clock.position(at(5000)),
+            Some(Duration::from_millis(10_200))
+        );
+
+        let free = TimecodeClock::running_from(Duration::ZERO, start);
+        assert_eq!(free.position(at(5000)), Some(Duration::from_secs(5)));
+    }
+
+    /// Quarter frames of `timecode`, pieces 0-7.
+    fn quarter_frames(timecode: Timecode, rate_bits: u8) -> Vec<u8> {
+        let Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            ..
+        } = timecode;
+        let pieces = [
+            frames & 0x0F,
+            frames >> 4,
+            seconds & 0x0F,
+            seconds >> 4,
+            minutes & 0x0F,
+            minutes >> 4,
+            hours & 0x0F,
+            hours >> 4 | rate_bits << 1,
+        ];
+        pieces
+            .iter()
+            .enumerate()
+            .flat_map(|(piece, value)| [0xF1, (piece as u8) << 4 | value])
+            .collect()
+    }
+
+    #[test]
+    fn test_mtc() {
+        let mut decoder = MtcDecoder::default();
+        let mut decoded = Vec::new();
+        let mut bytes = quarter_frames(tc(1, 2, 3, 20, FrameRate::Fps25), 1);
+        // A clock tick in between does not disturb the pieces
+        bytes.insert(5, 0xF8);
+        // Full frame: 10:20:30:15 at 30 FPS
+        bytes.extend([0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x60 | 10, 20, 30, 15, 0xF7]);
+        for byte in bytes {
+            decoded.extend(decoder.push(byte));
+        }
+        assert_eq!(
+            decoded,
+            vec![
+                tc(1, 2, 3, 22, FrameRate::Fps25),
+                tc(10, 20, 30, 15, FrameRate::Fps30)
+            ]
+        );
+
+        // Pieces from the middle of a frame are not enough
+        let partial = quarter_frames(tc(0, 0, 0, 0, FrameRate::Fps24), 0);
+        let mut decoder = MtcDecoder::default();
+        assert!(partial[8..].iter().all(|&b| decoder.push(b).is_none()));
+    }
+
+    /// LTC audio for consecutive frames from `start`, biphase mark coded.
+    fn ltc_audio(start: Timecode, count: usize, sample_rate: u32) -> Vec<f32> {
+        let fps = start.rate.fps();
+        let samples_per_bit = sample_rate as f64 / (fps * LTC_BITS as f64);
+        let mut audio = Vec::new();
+        let mut level = 1.0f32;
+        let mut position = 0.0f64;
+        let mut emit = |half_bits: f64, level: f32, audio: &mut Vec<f32>| {
+            position += half_bits * samples_per_bit / 2.0;
+            while (audio.len() as f64) < position {
+                audio.push(level * 0.5);
+            }
+        };
+        let mut timecode = start;
+        for _ in 0..count {
+            let mut bits = [false; 80];
+            let mut set = |at: usize, len: usize, value: u8| {
+                for i in 0..len {
+                    bits[at + i] = value >> i & 1 == 1;
+                }
+            };
+            set(0, 4, timecode.frames % 10);
+            set(8, 2, timecode.frames / 10);
+            set(16, 4, timecode.seconds % 10);
+            set(24, 3, timecode.seconds / 10);
+            set(32, 4, timecode.minutes % 10);
+            set(40, 3, timecode.minutes / 10);
+            set(48, 4, timecode.hours % 10);
+            set(56, 2, timecode.hours / 10);
+            for i in 0..16 {
+                bits[64 + i] = LTC_SYNC >> i & 1 == 1;
+            }
+            for bit in bits {
+                level = -level;
+                if bit {
+                    emit(1.0, level, &mut audio);
+                    level = -level;
+                    emit(1.0, level, &mut audio);
+                } else {
+                    emit(2.0, level, &mut audio);
+                }
+            }
+            timecode = advance(timecode, 1);
+        }
+        // The next frame's first transition ends the last bit
+        emit(2.0, -level, &mut audio);
+        audio
+    }
+
+    #[test]
+    fn test_ltc() {
+        for rate in [FrameRate::Fps24, FrameRate::Fps25, FrameRate::Fps30] {
+            let start = tc(1, 59, 59, 10, rate);
+            let audio = ltc_audio(start, 20, 48_000);
+            let mut decoder = LtcDecoder::new(48_000);
+            let mut last = None;
+            for block in audio.chunks(480) {
+                if let Some((timecode, _)) = decoder.push(block) {
+                    last = Some(timecode);
+                }
+            }
+            // The last frame sent, plus the one now starting
+            assert_eq!(last, Some(advance(start, 20)), "{rate:?}");
+        }
+    }
+}
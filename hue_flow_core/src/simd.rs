@@ -0,0 +1,157 @@
+//! The per-frame hot loops of the analysis and the color math.
+//!
+//! With the `simd` feature they process eight values at a time with `wide`
+//! (SSE/AVX on x86, NEON on ARM); without it they are plain loops. Both
+//! paths give the same results up to float rounding.
+#[cfg(feature = "fft")]
+use rustfft::num_complex::Complex;
+#[cfg(feature = "simd")]
+use wide::f32x8;
+
+#[cfg(feature = "simd")]
+const LANES: usize = 8;
+
+/// Sum of the squared samples.
+pub fn sum_squares(samples: &[f32]) -> f32 {
+    #[cfg(feature = "simd")]
+    {
+        let chunks = samples.chunks_exact(LANES);
+        let rest: f32 = chunks.remainder().iter().map(|s| s * s).sum();
+        let sum = chunks.fold(f32x8::splat(0.0), |sum, chunk| {
+            let v = f32x8::new(chunk.try_into().unwrap());
+            v.mul_add(v, sum)
+        });
+        sum.reduce_add() + rest
+    }
+    #[cfg(not(feature = "simd"))]
+    samples.iter().map(|s| s * s).sum()
+}
+
+/// Largest value, or 0.0 for none.
+pub fn max(values: &[f32]) -> f32 {
+    #[cfg(feature = "simd")]
+    {
+        let chunks = values.chunks_exact(LANES);
+        let rest = chunks.remainder().iter().copied().fold(0.0, f32::max);
+        let max = chunks.fold(f32x8::splat(0.0), |max, chunk| {
+            max.max(f32x8::new(chunk.try_into().unwrap()))
+        });
+        max.to_array().into_iter().fold(rest, f32::max)
+    }
+    #[cfg(not(feature = "simd"))]
+    values.iter().copied().fold(0.0, f32::max)
+}
+
+/// Writes the magnitude of each of `values` to `out`, as far as both go.
+#[cfg(feature = "fft")]
+pub fn norms(values: &[Complex<f32>], out: &mut [f32]) {
+    let len = values.len().min(out.len());
+    let (values, out) = (&values[..len], &mut out[..len]);
+    #[cfg(feature = "simd")]
+    let (values, out) = {
+        let split = len - len % LANES;
+        for (chunk, out) in values[..split]
+            .chunks_exact(LANES)
+            .zip(out[..split].chunks_exact_mut(LANES))
+        {
+            let re = f32x8::new(std::array::from_fn(|i| chunk[i].re));
+            let im = f32x8::new(std::array::from_fn(|i| chunk[i].im));
+            out.copy_from_slice(&re.mul_add(re, im * im).sqrt().to_array());
+        }
+        (&values[split..], &mut out[split..])
+    };
+    for (c, out) in values.iter().zip(out) {
+        *out = (c.re * c.re + c.im * c.im).sqrt();
+    }
+}
+
+/// Writes each of `values` times `scale`, clamped to 0.0 - 1.0, to `out`.
+pub fn scale_clamped(values: &[f32], scale: f32, out: &mut [f32]) {
+    let len = values.len().min(out.len());
+    let (values, out) = (&values[..len], &mut out[..len]);
+    #[cfg(feature = "simd")]
+    let (values, out) = {
+        let split = len - len % LANES;
+        let (scale, zero, one) = (f32x8::splat(scale), f32x8::splat(0.0), f32x8::splat(1.0));
+        for (chunk, out) in values[..split]
+            .chunks_exact(LANES)
+            .zip(out[..split].chunks_exact_mut(LANES))
+        {
+            let v = f32x8::new(chunk.try_into().unwrap()) * scale;
+            out.copy_from_slice(&v.max(zero).min(one).to_array());
+        }
+        (&values[split..], &mut out[split..])
+    };
+    for (value, out) in values.iter().zip(out) {
+        *out = (value * scale).clamp(0.0, 1.0);
+    }
+}
+
+/// Scales every color by `brightness` (0.0 - 1.0), like
+/// [`color::scale`](crate::color::scale).
+pub fn scale_colors<'a>(colors: impl IntoIterator<Item = &'a mut (u8, u8, u8)>, brightness: f32) {
+    let brightness = brightness.clamp(0.0, 1.0);
+    #[cfg(feature = "simd")]
+    {
+        let mut colors: Vec<_> = colors.into_iter().collect();
+        let mut chunks = colors.chunks_exact_mut(LANES);
+        let gain = f32x8::splat(brightness);
+        for chunk in &mut chunks {
+            let channel = |get: fn(&(u8, u8, u8)) -> u8| {
+                (f32x8::new(std::array::from_fn(|i| get(chunk[i]) as f32)) * gain).to_array()
+            };
+            let (r, g, b) = (channel(|c| c.0), channel(|c| c.1), channel(|c| c.2));
+            for (i, color) in chunk.iter_mut().enumerate() {
+                **color = (r[i] as u8, g[i] as u8, b[i] as u8);
+            }
+        }
+        for color in chunks.into_remainder() {
+            **color = crate::color::scale(**color, brightness);
+        }
+    }
+    #[cfg(not(feature = "simd"))]
+    for color in colors {
+        *color = crate::color::scale(*color, brightness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_scalar() {
+        // Long enough for full lanes and a remainder
+        let values: Vec<f32> = (0..21).map(|i| (i as f32 * 0.37).sin()).collect();
+        let squares: f32 = values.iter().map(|v| v * v).sum();
+        assert!((sum_squares(&values) - squares).abs() < 1e-4);
+        assert_eq!(max(&values), values.iter().copied().fold(0.0, f32::max));
+        assert_eq!(max(&[]), 0.0);
+
+        let mut out = vec![0.0; 21];
+        scale_clamped(&values, 2.0, &mut out);
+        for (value, out) in values.iter().zip(&out) {
+            assert_eq!(*out, (value * 2.0).clamp(0.0, 1.0));
+        }
+
+        let original: Vec<(u8, u8, u8)> = (0..11u8).map(|i| (i * 20, 255 - i, i)).collect();
+        let mut colors = original.clone();
+        scale_colors(&mut colors, 0.5);
+        for (color, original) in colors.iter().zip(&original) {
+            assert_eq!(*color, crate::color::scale(*original, 0.5));
+        }
+    }
+
+    #[cfg(feature = "fft")]
+    #[test]
+    fn test_norms() {
+        let values: Vec<Complex<f32>> = (0..13)
+            .map(|i| Complex::new(3.0 * i as f32, 4.0 * i as f32))
+            .collect();
+        let mut out = vec![0.0; 10];
+        norms(&values, &mut out);
+        for (i, norm) in out.iter().enumerate() {
+            assert!((norm - 5.0 * i as f32).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Sun position for a location, so ambient effects can follow the day.
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sun elevation (degrees) below which it is fully night: the end of civil
+/// twilight.
+const NIGHT_ELEVATION: f64 = -6.0;
+/// Sun elevation above which it is fully day.
+const DAY_ELEVATION: f64 = 6.0;
+
+/// Where the lights are, in decimal degrees (north and east positive).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Elevation of the sun above the horizon at `at`, in degrees.
+///
+/// Uses the low-precision formulas of the Astronomical Almanac (good to
+/// about a degree until 2050), plenty for deciding between day and night.
+pub fn sun_elevation(location: Location, at: SystemTime) -> f64 {
+    let secs = at
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    // Days since J2000.0 (2000-01-01 12:00 UTC)
+    let d = secs / 86_400.0 - 10_957.5;
+
+    let mean_longitude = 280.460 + 0.985_647_4 * d;
+    let anomaly = (357.528 + 0.985_600_3 * d).to_radians();
+    let ecliptic =
+        (mean_longitude + 1.915 * anomaly.sin() + 0.020 * (2.0 * anomaly).sin()).to_radians();
+    let obliquity = (23.439 - 0.000_000_4 * d).to_radians();
+
+    let right_ascension = (obliquity.cos() * ecliptic.sin()).atan2(ecliptic.cos());
+    let declination = (obliquity.sin() * ecliptic.sin()).asin();
+    let sidereal_hours = 18.697_374_558 + 24.065_709_824_419_08 * d;
+    let hour_angle = (sidereal_hours * 15.0 + location.longitude).to_radians() - right_ascension;
+
+    let latitude = location.latitude.to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+/// How much daylight there is at `at`: 0.0 at night, 1.0 during the day,
+/// easing in between through civil twilight.
+pub fn daylight(location: Location, at: SystemTime) -> f32 {
+    let t = (sun_elevation(location, at) - NIGHT_ELEVATION) / (DAY_ELEVATION - NIGHT_ELEVATION);
+    let t = t.clamp(0.0, 1.0) as f32;
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn utc(unix_secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(unix_secs)
+    }
+
+    #[test]
+    fn test_day_and_night() {
+        let berlin = Location {
+            latitude: 52.52,
+            longitude: 13.40,
+        };
+        // 2024-06-21: noon sun is 61° high, it is dark at midnight
+        let midsummer = 1_718_928_000;
+        let noon = sun_elevation(berlin, utc(midsummer + 11 * 3600));
+        assert!((noon - 60.9).abs() < 1.0, "{}", noon);
+        assert_eq!(daylight(berlin, utc(midsummer + 11 * 3600)), 1.0);
+        assert_eq!(daylight(berlin, utc(midsummer + 23 * 3600)), 0.0);
+        // Sunset around 19:33 UTC: twilight is partly light
+        let dusk = daylight(berlin, utc(midsummer + 19 * 3600 + 40 * 60));
+        assert!(dusk > 0.0 && dusk < 1.0, "{}", dusk);
+
+        // Polar night in Svalbard, even at noon
+        let svalbard = Location {
+            latitude: 78.22,
+            longitude: 15.65,
+        };
+        let midwinter = 1_734_782_400; // 2024-12-21 12:00 UTC
+        assert_eq!(daylight(svalbard, utc(midwinter)), 0.0);
+    }
+}
@@ -0,0 +1,60 @@
+//! Quantizes the stream's float colors to the protocol's 16 bits.
+//!
+//! Effects work in 8 bits, which steps visibly in dim scenes: a fade from 3
+//! to 0 has only three levels. The output stages after the effect (color
+//! correction, dimming) and the smoother keep colors unrounded instead, and
+//! they are quantized here, carrying each channel's rounding error into its
+//! next frame (temporal error diffusion), so the average over a few frames
+//! matches the exact color.
+use std::collections::HashMap;
+
+/// Rounding errors of the channels, carried from frame to frame.
+#[derive(Debug, Default)]
+pub struct Dither {
+    errors: HashMap<u8, [f32; 3]>,
+}
+
+impl Dither {
+    /// Quantizes `frame` (0.0 - 255.0 per component, see
+    /// [`Smoother::frame_precise`]) to 16-bit channel values.
+    ///
+    /// [`Smoother::frame_precise`]: crate::output::smoothing::Smoother::frame_precise
+    pub fn quantize(&mut self, frame: &HashMap<u8, [f32; 3]>) -> HashMap<u8, [u16; 3]> {
+        self.errors.retain(|channel, _| frame.contains_key(channel));
+        frame
+            .iter()
+            .map(|(&channel, color)| {
+                let error = self.errors.entry(channel).or_default();
+                let out = std::array::from_fn(|i| {
+                    let wanted = color[i] * 257.0 + error[i];
+                    let level = wanted.round().clamp(0.0, u16::MAX as f32);
+                    error[i] = wanted - level;
+                    level as u16
+                });
+                (channel, out)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let mut dither = Dither::default();
+        // 8-bit colors come out exactly as the encoder would scale them
+        let frame = HashMap::from([(0, [255.0, 1.0, 0.0])]);
+        assert_eq!(dither.quantize(&frame)[&0], [65535, 257, 0]);
+
+        // A level between two 16-bit steps alternates between them
+        let frame = HashMap::from([(0, [0.5 / 257.0, 0.0, 0.0])]);
+        let sum: u32 = (0..10).map(|_| dither.quantize(&frame)[&0][0] as u32).sum();
+        assert_eq!(sum, 5);
+
+        // Dim levels keep their fraction instead of snapping to 8 bits
+        let frame = HashMap::from([(1, [0.3, 0.0, 0.0])]);
+        assert_eq!(dither.quantize(&frame)[&1][0], 77);
+    }
+}
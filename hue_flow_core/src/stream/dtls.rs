@@ -1,8 +1,9 @@
+use crate::credentials::Credentials;
 use anyhow::{Context, Result};
-use openssl::ssl::{SslConnector, SslMethod, SslStream};
+use openssl::ssl::{ErrorCode, SslConnector, SslMethod, SslStream};
 use std::io::{self, Read, Write};
-use std::net::UdpSocket;
-use std::time::Duration;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
 
 // Wrapper for UdpSocket to implement Read and Write
 struct ConnectedUdpSocket(UdpSocket);
@@ -25,8 +26,74 @@ impl Write for ConnectedUdpSocket {
     }
 }
 
+/// Time the bridge gets to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Read timeout while tracing the handshake, short enough to see each step.
+const DEBUG_READ_TIMEOUT: Duration = Duration::from_millis(250);
+/// UDP port of the bridge's DTLS server.
+const STREAM_PORT: u16 = 2100;
+/// Largest datagram sent, below common path MTUs to avoid fragmentation.
+const MTU: u32 = 1400;
+
+/// Why a handshake failed, as far as can be told from the outside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeFailure {
+    /// The bridge answered with an alert: client key or application ID wrong.
+    WrongPsk,
+    /// Nothing listens on port 2100 (ICMP port unreachable).
+    PortUnreachable,
+    /// No answer at all; the bridge ignores handshakes while streaming is
+    /// not active on the entertainment area.
+    NoAnswer,
+    Other,
+}
+
+impl HandshakeFailure {
+    /// Classifies a failure from the I/O error kind and the OpenSSL error
+    /// text, if any.
+    pub fn classify(io: Option<io::ErrorKind>, ssl_reason: &str) -> Self {
+        match io {
+            Some(io::ErrorKind::ConnectionRefused) => return Self::PortUnreachable,
+            Some(io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Self::NoAnswer,
+            _ => {}
+        }
+        let reason = ssl_reason.to_lowercase();
+        if reason.contains("alert") || reason.contains("psk") {
+            Self::WrongPsk
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl std::fmt::Display for HandshakeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::WrongPsk => {
+                "wrong PSK: the bridge rejected the client key or application ID (run 'hueflow setup' again)"
+            }
+            Self::PortUnreachable => "port 2100 unreachable: is this a Hue bridge with streaming support?",
+            Self::NoAnswer => "no answer: is streaming active on the entertainment area?",
+            Self::Other => "handshake error",
+        })
+    }
+}
+
+/// The streaming port of the bridge at `ip` (IPv4, IPv6 with or without
+/// brackets, or a host name).
+fn stream_addr(ip: &str) -> io::Result<SocketAddr> {
+    let host = ip.trim_start_matches('[').trim_end_matches(']');
+    (host, STREAM_PORT)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))
+}
+
 pub struct HueStreamer {
     stream: SslStream<ConnectedUdpSocket>,
+    ip: String,
+    credentials: Credentials,
+    debug: bool,
 }
 
 impl HueStreamer {
@@ -34,19 +101,35 @@ impl HueStreamer {
     ///
     /// # Arguments
     /// * `ip` - Bridge IP address
-    /// * `application_id` - The hue-application-id (PSK Identity) from /auth/v1
-    /// * `client_key` - The client key (PSK) from registration (hex string)
-    pub fn connect(ip: &str, application_id: &str, client_key: &str) -> Result<Self> {
-        let addr = format!("{}:2100", ip);
+    /// * `credentials` - The hue-application-id (PSK Identity) from /auth/v1
+    ///   and the client key (PSK) from registration
+    pub fn connect(ip: &str, credentials: &Credentials) -> Result<Self> {
+        Self::connect_with(ip, credentials, false)
+    }
+
+    /// Like [`connect`](Self::connect); with `debug`, logs every handshake
+    /// step, the negotiated cipher and the MTU (target `hueflow::dtls`).
+    pub fn connect_with(ip: &str, credentials: &Credentials, debug: bool) -> Result<Self> {
+        let addr = stream_addr(ip).with_context(|| format!("Invalid bridge address '{}'", ip))?;
 
-        // Setup UDP Socket
-        let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to bind UDP socket")?;
+        // Setup UDP Socket, in the bridge's address family
+        let local = if addr.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local).context("Failed to bind UDP socket")?;
         socket
-            .connect(&addr)
+            .connect(addr)
             .context("Failed to connect UDP socket")?;
 
         // Set timeouts
-        socket.set_read_timeout(Some(Duration::from_secs(2))).ok();
+        let read_timeout = if debug {
+            DEBUG_READ_TIMEOUT
+        } else {
+            HANDSHAKE_TIMEOUT
+        };
+        socket.set_read_timeout(Some(read_timeout)).ok();
         socket.set_write_timeout(Some(Duration::from_secs(2))).ok();
 
         // Wrap socket
@@ -66,8 +149,8 @@ impl HueStreamer {
 
         // PSK Callback
         // IMPORTANT: PSK Identity = hue-application-id (NOT username!)
-        let psk_identity = application_id.to_string();
-        let psk_hex = client_key.to_string();
+        let psk_identity = credentials.application_id().to_string();
+        let key_bytes = credentials.client_key().to_vec();
 
         builder.set_psk_client_callback(move |_, _, identity, psk_buf| {
             // Set Identity (hue-application-id as ASCII/UTF-8 string)
@@ -82,12 +165,7 @@ impl HueStreamer {
                 identity[identity_bytes.len()] = 0;
             }
 
-            // Set PSK (client_key decoded from hex)
-            let key_bytes = match hex::decode(&psk_hex) {
-                Ok(k) => k,
-                Err(_) => return Err(openssl::error::ErrorStack::get()),
-            };
-
+            // Set PSK (client_key, decoded from hex at load time)
             if key_bytes.len() > psk_buf.len() {
                 return Err(openssl::error::ErrorStack::get());
             }
@@ -99,20 +177,86 @@ impl HueStreamer {
         let connector = builder.build();
 
         // Handshake
-        let mut ssl = connector.configure()?.into_ssl(&addr)?;
+        let mut ssl = connector.configure()?.into_ssl(&addr.ip().to_string())?;
 
         // Set MTU explicitly to avoid fragmentation issues
-        ssl.set_mtu(1400).ok();
+        ssl.set_mtu(MTU).ok();
 
         // Create and connect SSL stream
         let mut stream = SslStream::new(ssl, socket_wrapper)
             .map_err(|e| anyhow::anyhow!("Failed to create SslStream: {}", e))?;
 
-        stream
-            .connect()
-            .map_err(|e| anyhow::anyhow!("DTLS Handshake failed: {}", e))?;
+        if debug {
+            tracing::info!(
+                target: "hueflow::dtls",
+                "Connecting to {} as '{}', MTU {}",
+                addr,
+                credentials.application_id(),
+                MTU
+            );
+        }
+        let started = Instant::now();
+        let mut state = "";
+        loop {
+            let result = stream.connect();
+            let now = stream.ssl().state_string_long();
+            if debug && now != state {
+                tracing::info!(
+                    target: "hueflow::dtls",
+                    "{:>5} ms  {}",
+                    started.elapsed().as_millis(),
+                    now
+                );
+            }
+            state = now;
+            let e = match result {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+            // A read timed out; the bridge may still answer
+            if e.code() == ErrorCode::WANT_READ && started.elapsed() < HANDSHAKE_TIMEOUT {
+                continue;
+            }
+            let failure = HandshakeFailure::classify(
+                e.io_error().map(|io| io.kind()),
+                &e.ssl_error().map(|s| s.to_string()).unwrap_or_default(),
+            );
+            if debug {
+                tracing::info!(
+                    target: "hueflow::dtls",
+                    "Failed after {} ms in state '{}': {}",
+                    started.elapsed().as_millis(),
+                    state,
+                    failure
+                );
+            }
+            anyhow::bail!("DTLS Handshake failed ({}): {}", failure, e);
+        }
+
+        if debug {
+            let cipher = stream.ssl().current_cipher().map_or("none", |c| c.name());
+            tracing::info!(
+                target: "hueflow::dtls",
+                "Handshake done in {} ms: {} with {}",
+                started.elapsed().as_millis(),
+                stream.ssl().version_str(),
+                cipher
+            );
+        }
+        Ok(HueStreamer {
+            stream,
+            ip: ip.to_string(),
+            credentials: credentials.clone(),
+            debug,
+        })
+    }
 
-        Ok(HueStreamer { stream })
+    /// Re-runs the DTLS handshake with the same credentials.
+    /// Needed after the bridge dropped the session, e.g. when another
+    /// application took over the entertainment area.
+    pub fn reconnect(&mut self) -> Result<()> {
+        *self = Self::connect_with(&self.ip, &self.credentials, self.debug)?;
+        Ok(())
     }
 
     pub fn write_all(&mut self, buf: &[u8]) -> Result<()> {
@@ -123,3 +267,45 @@ impl HueStreamer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_handshake_failure() {
+        let classify = HandshakeFailure::classify;
+        assert_eq!(
+            classify(Some(io::ErrorKind::ConnectionRefused), ""),
+            HandshakeFailure::PortUnreachable
+        );
+        assert_eq!(
+            classify(Some(io::ErrorKind::WouldBlock), ""),
+            HandshakeFailure::NoAnswer
+        );
+        assert_eq!(
+            classify(
+                None,
+                "error:0A000419:SSL routines::tlsv1 alert access denied"
+            ),
+            HandshakeFailure::WrongPsk
+        );
+        assert_eq!(classify(None, "unexpected eof"), HandshakeFailure::Other);
+    }
+
+    #[test]
+    fn test_stream_addr() {
+        assert_eq!(
+            stream_addr("192.168.1.2").unwrap().to_string(),
+            "192.168.1.2:2100"
+        );
+        assert_eq!(
+            stream_addr("2001:db8::1").unwrap().to_string(),
+            "[2001:db8::1]:2100"
+        );
+        assert_eq!(
+            stream_addr("[2001:db8::1]").unwrap().to_string(),
+            "[2001:db8::1]:2100"
+        );
+    }
+}
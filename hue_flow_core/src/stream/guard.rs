@@ -0,0 +1,113 @@
+//! Releases the entertainment area even when streaming ends through an
+//! error or a panic, so it is not left locked in streaming mode until the
+//! bridge is power-cycled.
+use crate::api::error::HueError;
+use crate::api::groups::set_stream_active;
+use crate::models::{HueConfig, RetryPolicy};
+use std::time::Duration;
+
+/// How long deactivation may hold up an unwinding thread.
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Deactivates streaming on an area when dropped, unless
+/// [`release`](Self::release)d first.
+///
+/// Create it right after activating the area. Dropping happens on every
+/// way out of the owning scope, including `?` and unwinding panics; only
+/// `panic = "abort"` and `std::process::exit` bypass it.
+pub struct StreamGuard {
+    armed: Option<(HueConfig, String)>,
+}
+
+impl StreamGuard {
+    pub fn new(config: HueConfig, area_id: String) -> Self {
+        Self {
+            armed: Some((config, area_id)),
+        }
+    }
+
+    /// Deactivates streaming now, the normal way out.
+    pub async fn release(mut self) -> Result<(), HueError> {
+        match self.armed.take() {
+            Some((config, area_id)) => set_stream_active(&config, &area_id, false).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let Some((mut config, area_id)) = self.armed.take() else {
+            return;
+        };
+        // A single quick try: the process may be on its way down
+        config.retry = RetryPolicy {
+            max_attempts: 1,
+            ..config.retry
+        };
+        // Drop cannot await, and the surrounding runtime may be shutting
+        // down; deactivate on a runtime of its own
+        let released = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .ok()?;
+            runtime
+                .block_on(async {
+                    tokio::time::timeout(
+                        RELEASE_TIMEOUT,
+                        set_stream_active(&config, &area_id, false),
+                    )
+                    .await
+                })
+                .ok()
+        })
+        .join();
+        match released {
+            Ok(Some(Ok(()))) => tracing::info!("Entertainment area released after abnormal exit"),
+            _ => {
+                tracing::warn!("Could not release the entertainment area; toggle it in the Hue app")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    /// Reports whether anything connected within a few seconds, closing
+    /// the connection right away so the client fails fast.
+    fn expect_connection(listener: TcpListener) -> std::thread::JoinHandle<bool> {
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if listener.accept().is_ok() {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            false
+        })
+    }
+
+    #[test]
+    fn test_release_on_panic() {
+        let bridge = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = HueConfig {
+            bridge_ip: bridge.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+        let connected = expect_connection(bridge);
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = StreamGuard::new(config, "area".to_string());
+            panic!("effect crashed");
+        });
+        assert!(result.is_err());
+        assert!(connected.join().unwrap(), "no deactivation attempted");
+    }
+}
@@ -0,0 +1,97 @@
+//! Evens out irregular frame production.
+//!
+//! Frames are played out a fixed delay after their render tick, so a frame
+//! whose effect took longer to compute (or that waited for the scheduler)
+//! still lands on its place in the timeline instead of arriving late and
+//! squeezing the next one.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Suggested delay: two frames at the default 50 FPS stream rate.
+pub const DEFAULT_JITTER_BUFFER: Duration = Duration::from_millis(40);
+/// Frames held at most; beyond that the oldest is dropped.
+pub const MAX_QUEUED: usize = 32;
+
+/// Holds timestamped items until they are due.
+#[derive(Debug)]
+pub struct JitterBuffer<T> {
+    delay: Duration,
+    /// Due time and item, ordered by due time.
+    queue: VecDeque<(Instant, T)>,
+}
+
+impl<T> JitterBuffer<T> {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Queues `item`, rendered at `rendered`, to be due [`delay`](Self::delay)
+    /// later.
+    pub fn push(&mut self, rendered: Instant, item: T) {
+        let due = rendered + self.delay;
+        let index = self.queue.partition_point(|(at, _)| *at <= due);
+        self.queue.insert(index, (due, item));
+        if self.queue.len() > MAX_QUEUED {
+            self.queue.pop_front();
+        }
+    }
+
+    /// Takes the next item due at `now`, with the time it was due.
+    pub fn pop_due(&mut self, now: Instant) -> Option<(Instant, T)> {
+        if self.queue.front().is_some_and(|(due, _)| *due <= now) {
+            self.queue.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// When the next item is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.queue.front().map(|(due, _)| *due)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plays_out_on_render_time() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut buffer = JitterBuffer::new(Duration::from_millis(40));
+
+        // Rendered every 50 ms; the second arrives late, the third early
+        buffer.push(ms(0), 'a');
+        buffer.push(ms(100), 'c');
+        buffer.push(ms(50), 'b');
+        assert_eq!(buffer.next_due(), Some(ms(40)));
+        assert_eq!(buffer.pop_due(ms(39)), None);
+        assert_eq!(buffer.pop_due(ms(45)), Some((ms(40), 'a')));
+        assert_eq!(buffer.pop_due(ms(45)), None);
+        assert_eq!(buffer.pop_due(ms(200)), Some((ms(90), 'b')));
+        assert_eq!(buffer.pop_due(ms(200)), Some((ms(140), 'c')));
+        assert!(buffer.is_empty());
+
+        for i in 0..MAX_QUEUED as u64 + 3 {
+            buffer.push(ms(i), 'x');
+        }
+        assert_eq!(buffer.len(), MAX_QUEUED);
+        assert_eq!(buffer.next_due(), Some(ms(43)));
+    }
+}
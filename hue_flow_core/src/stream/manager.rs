@@ -1,16 +1,122 @@
+use crate::color::Rgb;
+use crate::output::smoothing::Smoother;
+use crate::stream::dither::Dither;
 use crate::stream::dtls::HueStreamer;
-use crate::stream::protocol;
-use std::collections::HashMap;
+use crate::stream::jitter::JitterBuffer;
+use crate::stream::protocol::ProtocolEncoder;
+use crate::stream::rate::{AdaptiveRate, StreamMetrics};
+use crate::stream::supervisor::StreamSupervisor;
+use crate::stream::takeover::AreaOwnership;
+use crate::timing::{Stage, StageTimings};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::Instant;
+use tracing::field;
 
-#[derive(Debug, Clone)]
+/// With [`StreamOptions::dedup`], an unchanged frame is still re-sent this
+/// often. The bridge ends sessions that go quiet for 10 s, and the repeats
+/// make up for a lost UDP packet carrying the last change.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Default)]
 pub struct LightState {
     pub id: u8,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+    /// Unrounded color (0.0 - 255.0 per component); the stream loop rounds
+    /// it to the light's 16-bit depth.
+    pub color: [f32; 3],
+    /// Fade to this color over the given time instead of jumping (zero = jump).
+    pub transition: Duration,
+    /// Render tick of the frame, for [`StreamOptions::jitter_buffer`];
+    /// `None` applies the state on arrival.
+    pub rendered: Option<std::time::Instant>,
+}
+
+impl LightState {
+    /// Converts an effect frame into stream states, with each channel's
+    /// fade time from `transition`.
+    pub fn from_frame<C: Rgb>(
+        frame: HashMap<u8, C>,
+        transition: impl Fn(u8) -> Duration,
+    ) -> Vec<LightState> {
+        frame
+            .into_iter()
+            .map(|(id, color)| LightState {
+                id,
+                color: color.to_rgb(),
+                transition: transition(id),
+                rendered: None,
+            })
+            .collect()
+    }
+
+    /// Like [`from_frame`](Self::from_frame), for a frame rendered at
+    /// `rendered`.
+    pub fn from_frame_at<C: Rgb>(
+        frame: HashMap<u8, C>,
+        transition: impl Fn(u8) -> Duration,
+        rendered: std::time::Instant,
+    ) -> Vec<LightState> {
+        let mut states = Self::from_frame(frame, transition);
+        for state in &mut states {
+            state.rendered = Some(rendered);
+        }
+        states
+    }
+}
+
+/// Optional behaviour of the streaming loop.
+#[derive(Default)]
+pub struct StreamOptions {
+    /// Area ownership from the takeover watcher. While another application
+    /// owns the area, frames are not written; the DTLS session is re-established
+    /// once the area is ours again.
+    pub ownership: Option<watch::Receiver<AreaOwnership>>,
+    /// Whether anyone is in the room, from the motion watcher. Frames are not
+    /// written while the room is empty; the area is released meanwhile.
+    pub presence: Option<watch::Receiver<bool>>,
+    /// Counters updated by the loop (current FPS, frames sent, write errors).
+    pub metrics: Arc<StreamMetrics>,
+    /// Channels omitted from every frame, so the bridge leaves those lights alone.
+    pub excluded_channels: HashSet<u8>,
+    /// Encode and send latencies.
+    pub timings: Arc<StageTimings>,
+    /// Target frame rate, changeable while streaming; the rate still backs
+    /// off below it on a congested link. Defaults to
+    /// [`MAX_FPS`](crate::stream::rate::MAX_FPS).
+    pub max_fps: Option<watch::Receiver<u32>>,
+    /// Tracks the session's state and re-establishes it: after a pause,
+    /// and once writes keep failing.
+    pub supervisor: Option<Arc<StreamSupervisor>>,
+    /// Skip frames identical to the previous one, except every
+    /// [`KEEP_ALIVE_INTERVAL`]; counted in
+    /// [`StreamMetrics::frames_skipped`].
+    pub dedup: bool,
+    /// Apply timestamped updates this long after their render tick instead
+    /// of on arrival, so uneven render times do not show as uneven motion.
+    /// See [`JitterBuffer`].
+    pub jitter_buffer: Option<Duration>,
+}
+
+/// Decides which frames to send when repeats are skipped.
+#[derive(Debug, Default)]
+struct FrameDedup<F> {
+    last: Option<(F, Instant)>,
+}
+
+impl<F: PartialEq + Clone> FrameDedup<F> {
+    /// True (and `frame` remembered as sent) unless `frame` repeats the last
+    /// one sent less than [`KEEP_ALIVE_INTERVAL`] ago.
+    fn should_send(&mut self, frame: &F, now: Instant) -> bool {
+        if let Some((last, sent_at)) = &self.last {
+            if last == frame && now.duration_since(*sent_at) < KEEP_ALIVE_INTERVAL {
+                return false;
+            }
+        }
+        self.last = Some((frame.clone(), now));
+        true
+    }
 }
 
 /// Runs the entertainment streaming loop.
@@ -20,16 +126,45 @@ pub struct LightState {
 /// * `receiver` - Channel receiving light state updates
 /// * `area_id` - The Entertainment Area ID (UUID string, 36 characters)
 pub async fn run_stream_loop(
+    streamer: HueStreamer,
+    receiver: mpsc::Receiver<Vec<LightState>>,
+    area_id: &str,
+) {
+    run_stream_loop_with_options(streamer, receiver, area_id, StreamOptions::default()).await
+}
+
+/// Same as [`run_stream_loop`], with additional [`StreamOptions`].
+pub async fn run_stream_loop_with_options(
     mut streamer: HueStreamer,
     mut receiver: mpsc::Receiver<Vec<LightState>>,
     area_id: &str,
+    mut options: StreamOptions,
 ) {
-    let target_frame_time = Duration::from_millis(20); // 50 FPS
+    // Starts at the target rate and backs off while writes fail or stall
+    let mut rate = AdaptiveRate::default();
+    if let Some(max_fps) = &mut options.max_fps {
+        rate.set_max_fps(*max_fps.borrow_and_update());
+    }
+    let mut encoder = ProtocolEncoder::new(area_id);
+    options.metrics.set_fps(rate.fps());
     let mut last_frame_time = Instant::now();
 
-    let mut current_lights: HashMap<u8, (u8, u8, u8)> = HashMap::new();
+    // Current colors, fading per the transition hints of each update
+    let mut lights = Smoother::default();
+    let mut paused = false;
+    let mut dedup = FrameDedup::default();
+    // Colors stay unrounded from the smoother to here, see `Dither`
+    let mut dither = Dither::default();
+    let mut jitter = options.jitter_buffer.map(JitterBuffer::new);
 
     loop {
+        if let Some(max_fps) = &mut options.max_fps {
+            if max_fps.has_changed().unwrap_or(false) {
+                rate.set_max_fps(*max_fps.borrow_and_update());
+                options.metrics.set_fps(rate.fps());
+            }
+        }
+        let target_frame_time = rate.frame_time();
         let deadline = last_frame_time + target_frame_time;
 
         // Wait for new data or timeout (keep-alive)
@@ -38,9 +173,10 @@ pub async fn run_stream_loop(
             res = receiver.recv() => {
                 match res {
                     Some(updates) => {
-                        // Update current state
-                        for light in updates {
-                            current_lights.insert(light.id, (light.r, light.g, light.b));
+                        // Update current state, or hold it until due
+                        match (&mut jitter, updates.first().and_then(|l| l.rendered)) {
+                            (Some(jitter), Some(rendered)) => jitter.push(rendered, updates),
+                            _ => apply_updates(&mut lights, &options.excluded_channels, updates, std::time::Instant::now()),
                         }
                     }
                     None => {
@@ -57,18 +193,124 @@ pub async fn run_stream_loop(
         // Check if we need to send
         let now = Instant::now();
         if now >= last_frame_time + target_frame_time {
+            // Buffered updates take effect at the time they were due
+            while let Some((due, updates)) = jitter.as_mut().and_then(|j| j.pop_due(now.into_std()))
+            {
+                apply_updates(&mut lights, &options.excluded_channels, updates, due);
+            }
+            if let Some(ownership) = &options.ownership {
+                let ours = *ownership.borrow() == AreaOwnership::Ours;
+                let present = options.presence.as_ref().is_none_or(|p| *p.borrow());
+                if !ours && !paused {
+                    eprintln!(
+                        "Another application took over the entertainment area, pausing stream"
+                    );
+                    paused = true;
+                } else if !present && !paused {
+                    // Reported by the caller, which watches presence as well
+                    paused = true;
+                } else if ours && present && paused {
+                    eprintln!("Entertainment area is free again, resuming stream");
+                    let resumed = match &options.supervisor {
+                        Some(supervisor) => supervisor.recover(&mut streamer),
+                        None => streamer.reconnect(),
+                    };
+                    match resumed {
+                        Ok(_) => paused = false,
+                        Err(e) => eprintln!("Failed to re-establish DTLS connection: {}", e),
+                    }
+                }
+                if paused {
+                    if let Some(supervisor) = &options.supervisor {
+                        supervisor.interrupted();
+                    }
+                }
+            }
+
+            let span = tracing::debug_span!(
+                "stream_frame",
+                fps = rate.fps(),
+                encode_us = field::Empty,
+                send_us = field::Empty
+            );
+            let _entered = span.enter();
+
             // Create message with the correct Entertainment Area ID
-            if !current_lights.is_empty() {
-                let msg = protocol::create_message(area_id, &current_lights);
+            // The encoder refuses frames closer than the bridge's 60 Hz limit
+            let encode_start = std::time::Instant::now();
+            let msg = if !paused && !lights.is_empty() {
+                let frame = dither.quantize(&lights.frame_precise(std::time::Instant::now()));
+                if options.dedup && !dedup.should_send(&frame, now) {
+                    options.metrics.record_skipped();
+                    None
+                } else {
+                    encoder.encode_pooled(&frame)
+                }
+            } else {
+                None
+            };
+            if let Some(msg) = msg {
+                let encode_time = options.timings.record(Stage::Encode, encode_start);
+                span.record("encode_us", encode_time.as_micros() as u64);
+
+                let write_start = std::time::Instant::now();
+                let result = match &options.supervisor {
+                    Some(supervisor) => supervisor.write(&mut streamer, msg),
+                    None => streamer.write_all(msg),
+                };
+                let write_time = options.timings.record(Stage::Send, write_start);
+                span.record("send_us", write_time.as_micros() as u64);
+                rate.record(result.is_ok(), write_time);
 
-                match streamer.write_all(&msg) {
-                    Ok(_) => {}
+                match result {
+                    Ok(_) => options.metrics.record_sent(),
                     Err(e) => {
+                        options.metrics.record_error();
                         eprintln!("Error sending Hue stream frame: {}", e);
                     }
                 }
+                options.metrics.set_fps(rate.fps());
             }
             last_frame_time = now;
         }
     }
 }
+
+/// Sets the targets of `updates`, fading from `at`.
+fn apply_updates(
+    lights: &mut Smoother,
+    excluded_channels: &HashSet<u8>,
+    updates: Vec<LightState>,
+    at: std::time::Instant,
+) {
+    for light in updates {
+        if excluded_channels.contains(&light.id) {
+            continue;
+        }
+        lights.set_target(light.id, light.color, light.transition, at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Frame;
+
+    #[test]
+    fn test_dedup_keeps_alive() {
+        let start = Instant::now();
+        let red = Frame::from([(0, (255, 0, 0))]);
+        let blue = Frame::from([(0, (0, 0, 255))]);
+        let mut dedup = FrameDedup::default();
+
+        assert!(dedup.should_send(&red, start));
+        assert!(!dedup.should_send(&red, start + Duration::from_millis(20)));
+        assert!(dedup.should_send(&blue, start + Duration::from_millis(40)));
+        assert!(!dedup.should_send(&blue, start + Duration::from_millis(200)));
+        // Repeated once the keep-alive interval has passed
+        assert!(dedup.should_send(
+            &blue,
+            start + Duration::from_millis(40) + KEEP_ALIVE_INTERVAL
+        ));
+    }
+}
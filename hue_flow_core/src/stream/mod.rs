@@ -1,3 +1,9 @@
+pub mod dither;
 pub mod dtls;
-pub mod protocol;
+pub mod guard;
+pub mod jitter;
 pub mod manager;
+pub mod protocol;
+pub mod rate;
+pub mod supervisor;
+pub mod takeover;
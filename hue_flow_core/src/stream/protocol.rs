@@ -1,9 +1,161 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
 
-static SEQUENCE_ID: AtomicU8 = AtomicU8::new(0);
+/// Minimum spacing between frames the bridge accepts (60 Hz).
+pub const MIN_FRAME_INTERVAL: Duration = Duration::from_micros(16_600);
 
-/// Creates a Hue Entertainment streaming message.
+/// 16-byte protocol header plus the 36-byte area ID, see [`encode_message`].
+pub const HEADER_LEN: usize = 16 + 36;
+/// Bytes per light channel.
+pub const CHANNEL_LEN: usize = 7;
+/// Position of the sequence number in the header.
+const SEQUENCE_OFFSET: usize = 11;
+
+/// A channel color the encoder can write. 8-bit colors are scaled up to the
+/// protocol's 16 bits, 16-bit ones (see [`Dither`]) are written as they are.
+///
+/// [`Dither`]: crate::stream::dither::Dither
+pub trait ChannelColor {
+    fn rgb16(&self) -> [u16; 3];
+}
+
+impl ChannelColor for (u8, u8, u8) {
+    fn rgb16(&self) -> [u16; 3] {
+        // Scale 8-bit (0-255) to 16-bit (0-65535)
+        // Formula: val * 257 (since 255 * 257 = 65535)
+        [
+            self.0 as u16 * 257,
+            self.1 as u16 * 257,
+            self.2 as u16 * 257,
+        ]
+    }
+}
+
+impl ChannelColor for [u16; 3] {
+    fn rgb16(&self) -> [u16; 3] {
+        *self
+    }
+}
+
+/// Encodes the frames of one entertainment stream.
+///
+/// Each stream owns its sequence number, so several streamers in one process
+/// do not interleave their counters, and frames sent closer together than
+/// [`MIN_FRAME_INTERVAL`] are refused.
+///
+/// The header is built once and messages are written into a buffer the
+/// encoder keeps, so [`encode_pooled`](Self::encode_pooled) allocates
+/// nothing after the first frame.
+#[derive(Debug, Clone)]
+pub struct ProtocolEncoder {
+    header: [u8; HEADER_LEN],
+    buffer: Vec<u8>,
+    sequence: u8,
+    last_frame: Option<Instant>,
+}
+
+impl ProtocolEncoder {
+    pub fn new(area_id: impl Into<String>) -> Self {
+        Self {
+            header: header(&area_id.into()),
+            buffer: Vec::new(),
+            sequence: 0,
+            last_frame: None,
+        }
+    }
+
+    /// Sequence number of the next frame.
+    pub fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    /// Time left until the next frame may be encoded (zero when ready).
+    pub fn ready_in(&self) -> Duration {
+        self.ready_in_at(Instant::now())
+    }
+
+    /// Encodes a frame and advances the sequence number. Returns `None` when
+    /// called sooner than [`MIN_FRAME_INTERVAL`] after the previous frame.
+    pub fn encode<C: ChannelColor>(&mut self, lights: &HashMap<u8, C>) -> Option<Vec<u8>> {
+        self.encode_pooled(lights).map(<[u8]>::to_vec)
+    }
+
+    /// Like [`encode`](Self::encode), but returns the message in the
+    /// encoder's buffer, valid until the next frame.
+    pub fn encode_pooled<C: ChannelColor>(&mut self, lights: &HashMap<u8, C>) -> Option<&[u8]> {
+        self.encode_at(lights, Instant::now())
+    }
+
+    fn ready_in_at(&self, now: Instant) -> Duration {
+        self.last_frame
+            .map(|last| (last + MIN_FRAME_INTERVAL).saturating_duration_since(now))
+            .unwrap_or_default()
+    }
+
+    fn encode_at<C: ChannelColor>(
+        &mut self,
+        lights: &HashMap<u8, C>,
+        now: Instant,
+    ) -> Option<&[u8]> {
+        if !self.ready_in_at(now).is_zero() {
+            return None;
+        }
+        encode_into(&mut self.buffer, &self.header, self.sequence, lights);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.last_frame = Some(now);
+        Some(&self.buffer)
+    }
+}
+
+/// The header of every message for `area_id`, with sequence number 0.
+pub fn header(area_id: &str) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    // Protocol name "HueStream" (9 bytes)
+    header[..9].copy_from_slice(b"HueStream");
+    // Version 2.0 (2 bytes: 0x02, 0x00)
+    header[9] = 0x02;
+    // Sequence ID (1 byte) is set per message, then 2 reserved bytes, the
+    // color space (0x00 = RGB) and 1 reserved byte, all zero
+
+    // ===== 36-byte Entertainment Area ID =====
+    // The area_id is a UUID like "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"
+    // It must be exactly 36 ASCII characters; others are padded or truncated
+    // (should not happen with valid UUIDs)
+    let area_bytes = area_id.as_bytes();
+    let copy_len = area_bytes.len().min(36);
+    header[16..16 + copy_len].copy_from_slice(&area_bytes[..copy_len]);
+    header
+}
+
+/// Writes a message into `buffer`, replacing its contents. `header` comes
+/// from [`header`]; the buffer keeps its capacity between frames.
+pub fn encode_into<C: ChannelColor>(
+    buffer: &mut Vec<u8>,
+    header: &[u8; HEADER_LEN],
+    sequence: u8,
+    lights: &HashMap<u8, C>,
+) {
+    buffer.clear();
+    buffer.reserve(HEADER_LEN + lights.len() * CHANNEL_LEN);
+    buffer.extend_from_slice(header);
+    buffer[SEQUENCE_OFFSET] = sequence;
+
+    // ===== Light Channel Data (7 bytes each) =====
+    for (id, color) in lights {
+        // RGB values as 16-bit Big Endian
+        let [r, g, b] = color.rgb16();
+        let ([r_hi, r_lo], [g_hi, g_lo], [b_hi, b_lo]) =
+            (r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes());
+        buffer.extend_from_slice(&[*id, r_hi, r_lo, g_hi, g_lo, b_hi, b_lo]);
+    }
+    // Sort channels by ID for deterministic output
+    let (channels, _) = buffer[HEADER_LEN..].as_chunks_mut::<CHANNEL_LEN>();
+    channels.sort_unstable_by_key(|channel| channel[0]);
+}
+
+/// Creates a Hue Entertainment streaming message. Streams should normally go
+/// through a [`ProtocolEncoder`], which tracks `sequence` and frame spacing.
 ///
 /// Format (per official Hue Entertainment API documentation):
 /// - 16-byte Header:
@@ -17,65 +169,116 @@ static SEQUENCE_ID: AtomicU8 = AtomicU8::new(0);
 /// - N x 7-byte Light Channel Data:
 ///   - 1 byte:  Channel ID (0-based index)
 ///   - 6 bytes: Color data (RGB: 3x 16-bit BE, XY+B: 2x 16-bit XY + 16-bit brightness)
-pub fn create_message(area_id: &str, lights: &HashMap<u8, (u8, u8, u8)>) -> Vec<u8> {
-    // Header (16) + Area ID (36) + lights (7 each)
-    let mut buffer = Vec::with_capacity(16 + 36 + lights.len() * 7);
+pub fn encode_message<C: ChannelColor>(
+    area_id: &str,
+    sequence: u8,
+    lights: &HashMap<u8, C>,
+) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    encode_into(&mut buffer, &header(area_id), sequence, lights);
+    buffer
+}
 
-    // ===== 16-byte Header =====
+/// Sequence numbers of [`create_message`], shared by all its callers.
+static LEGACY_SEQUENCE: AtomicU8 = AtomicU8::new(0);
 
-    // Protocol name "HueStream" (9 bytes)
-    buffer.extend_from_slice(b"HueStream");
+/// Creates a message numbered from one counter shared by every caller, as
+/// before streams had their own [`ProtocolEncoder`]. Allocates a buffer and
+/// writes the header anew on each call; [`encode_into`] reuses both.
+#[deprecated(note = "use a ProtocolEncoder per stream, or encode_into")]
+pub fn create_message(area_id: &str, lights: &HashMap<u8, (u8, u8, u8)>) -> Vec<u8> {
+    let sequence = LEGACY_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let mut buffer = Vec::with_capacity(HEADER_LEN + lights.len() * CHANNEL_LEN);
+    encode_into(&mut buffer, &header(area_id), sequence, lights);
+    buffer
+}
 
-    // Version 2.0 (2 bytes: 0x02, 0x00)
-    buffer.extend_from_slice(&[0x02, 0x00]);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Sequence ID (1 byte, wraps around)
-    let seq = SEQUENCE_ID.fetch_add(1, Ordering::SeqCst);
-    buffer.push(seq);
+    const AREA: &str = "1a8d99cc-967b-44f2-9202-43f976c0fa6b";
 
-    // Reserved (2 bytes: 0x00, 0x00)
-    buffer.extend_from_slice(&[0x00, 0x00]);
+    #[test]
+    #[allow(deprecated)]
+    fn test_create_message_still_encodes() {
+        let lights = HashMap::from([(0, (255, 0, 0))]);
+        let message = create_message(AREA, &lights);
+        assert_eq!(message.len(), HEADER_LEN + CHANNEL_LEN);
+        assert_eq!(
+            &message[HEADER_LEN..],
+            &encode_message(AREA, 0, &lights)[HEADER_LEN..]
+        );
+    }
 
-    // Color Space (1 byte: 0x00 = RGB)
-    buffer.push(0x00);
+    #[test]
+    fn test_sequence_per_encoder() {
+        let lights = HashMap::from([(0, (255, 0, 0))]);
+        let start = Instant::now();
+        let mut first = ProtocolEncoder::new(AREA);
+        let mut second = ProtocolEncoder::new(AREA);
 
-    // Reserved (1 byte: 0x00)
-    buffer.push(0x00);
+        for i in 0..3u32 {
+            let now = start + MIN_FRAME_INTERVAL * i;
+            assert_eq!(first.encode_at(&lights, now).unwrap()[11], i as u8);
+        }
+        // Another stream starts its own count
+        assert_eq!(second.encode_at(&lights, start).unwrap()[11], 0);
 
-    // ===== 36-byte Entertainment Area ID =====
-    // The area_id is a UUID like "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"
-    // It must be exactly 36 ASCII characters
-    let area_bytes = area_id.as_bytes();
-    if area_bytes.len() == 36 {
-        buffer.extend_from_slice(area_bytes);
-    } else {
-        // Pad or truncate to 36 bytes (should not happen with valid UUIDs)
-        let mut padded = [0u8; 36];
-        let copy_len = area_bytes.len().min(36);
-        padded[..copy_len].copy_from_slice(&area_bytes[..copy_len]);
-        buffer.extend_from_slice(&padded);
+        first.sequence = u8::MAX;
+        first
+            .encode_at(&lights, start + MIN_FRAME_INTERVAL * 3)
+            .unwrap();
+        assert_eq!(first.sequence(), 0);
     }
 
-    // ===== Light Channel Data (7 bytes each) =====
-    // Sort lights by ID for deterministic output
-    let mut sorted_lights: Vec<_> = lights.iter().collect();
-    sorted_lights.sort_by_key(|(id, _)| *id);
+    #[test]
+    fn test_frame_spacing() {
+        let lights = HashMap::from([(0, (255, 0, 0))]);
+        let start = Instant::now();
+        let mut encoder = ProtocolEncoder::new(AREA);
 
-    for (id, (r, g, b)) in sorted_lights {
-        // Channel ID (1 byte)
-        buffer.push(*id);
+        assert!(encoder.encode_at(&lights, start).is_some());
+        let early = start + Duration::from_millis(10);
+        assert!(encoder.encode_at(&lights, early).is_none());
+        assert_eq!(
+            encoder.ready_in_at(early),
+            MIN_FRAME_INTERVAL - Duration::from_millis(10)
+        );
+        assert!(encoder
+            .encode_at(&lights, start + MIN_FRAME_INTERVAL)
+            .is_some());
+    }
 
-        // RGB values as 16-bit Big Endian
-        // Scale 8-bit (0-255) to 16-bit (0-65535)
-        // Formula: val * 257 (since 255 * 257 = 65535)
-        let r16 = (*r as u16) * 257;
-        let g16 = (*g as u16) * 257;
-        let b16 = (*b as u16) * 257;
+    #[test]
+    fn test_message_layout() {
+        let lights = HashMap::from([(1, (0, 255, 0)), (0, (255, 0, 0))]);
+        let message = encode_message(AREA, 7, &lights);
 
-        buffer.extend_from_slice(&r16.to_be_bytes());
-        buffer.extend_from_slice(&g16.to_be_bytes());
-        buffer.extend_from_slice(&b16.to_be_bytes());
+        assert_eq!(message.len(), 16 + 36 + 2 * 7);
+        assert_eq!(&message[..9], b"HueStream");
+        assert_eq!(message[11], 7);
+        assert_eq!(&message[16..52], AREA.as_bytes());
+        // Channels sorted, 16-bit big endian colors
+        assert_eq!(&message[52..59], &[0, 0xFF, 0xFF, 0, 0, 0, 0]);
+        assert_eq!(message[59], 1);
     }
 
-    buffer
+    #[test]
+    fn test_encode_into_reuses_buffer() {
+        let area_header = header(AREA);
+        let mut buffer = Vec::new();
+        let many: HashMap<u8, (u8, u8, u8)> = (0..10).map(|i| (9 - i, (i, i, i))).collect();
+        encode_into(&mut buffer, &area_header, 3, &many);
+        assert_eq!(buffer, encode_message(AREA, 3, &many));
+        let capacity = buffer.capacity();
+
+        let lights = HashMap::from([(2, (1, 2, 3))]);
+        encode_into(&mut buffer, &area_header, 4, &lights);
+        assert_eq!(buffer, encode_message(AREA, 4, &lights));
+        assert_eq!(buffer.capacity(), capacity);
+
+        // Short area IDs are padded
+        assert_eq!(&header("short")[16..23], b"short\0\0");
+    }
 }
@@ -0,0 +1,204 @@
+use crate::stream::supervisor::StreamState;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::time::Duration;
+
+/// Highest frame rate we stream at; the bridge forwards ~25 Hz to Zigbee and
+/// the extra frames compensate for UDP loss.
+pub const MAX_FPS: u32 = 50;
+/// Lowest frame rate when backing off; still well above the keep-alive minimum.
+pub const MIN_FPS: u32 = 10;
+
+/// [`StreamState`]s by their discriminant, as stored in [`StreamMetrics`].
+const STATES: [StreamState; 5] = [
+    StreamState::Idle,
+    StreamState::Activating,
+    StreamState::Handshaking,
+    StreamState::Streaming,
+    StreamState::Recovering,
+];
+
+/// Live counters of the streaming loop, shared with the caller.
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    fps: AtomicU32,
+    frames_sent: AtomicU64,
+    frames_skipped: AtomicU64,
+    write_errors: AtomicU64,
+    state: AtomicU8,
+}
+
+impl StreamMetrics {
+    /// Frame rate the stream loop currently targets.
+    pub fn fps(&self) -> u32 {
+        self.fps.load(Ordering::Relaxed)
+    }
+
+    pub fn frames_sent(&self) -> u64 {
+        self.frames_sent.load(Ordering::Relaxed)
+    }
+
+    /// Frames not sent because they repeated the previous one.
+    pub fn frames_skipped(&self) -> u64 {
+        self.frames_skipped.load(Ordering::Relaxed)
+    }
+
+    pub fn write_errors(&self) -> u64 {
+        self.write_errors.load(Ordering::Relaxed)
+    }
+
+    /// State of the bridge stream.
+    pub fn state(&self) -> StreamState {
+        STATES
+            .get(self.state.load(Ordering::Relaxed) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_fps(&self, fps: u32) {
+        self.fps.store(fps, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sent(&self) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_skipped(&self) {
+        self.frames_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_error(&self) {
+        self.write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_state(&self, state: StreamState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+/// Adaptive frame rate controller.
+///
+/// Starts at the maximum rate, backs off multiplicatively on write failures or
+/// slow (congested) writes, and ramps back up additively once the link has
+/// been clean for about two seconds.
+#[derive(Debug, Clone)]
+pub struct AdaptiveRate {
+    fps: u32,
+    max_fps: u32,
+    min_fps: u32,
+    clean_frames: u32,
+}
+
+impl AdaptiveRate {
+    pub fn new(max_fps: u32) -> Self {
+        let max_fps = max_fps.clamp(MIN_FPS, MAX_FPS);
+        Self {
+            fps: max_fps,
+            max_fps,
+            min_fps: MIN_FPS,
+            clean_frames: 0,
+        }
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Changes the rate to ramp up to, dropping to it at once when lower.
+    pub fn set_max_fps(&mut self, max_fps: u32) {
+        self.max_fps = max_fps.clamp(MIN_FPS, MAX_FPS);
+        self.fps = self.fps.min(self.max_fps);
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps as f64)
+    }
+
+    /// Records the outcome of one frame write that took `write_time`.
+    pub fn record(&mut self, ok: bool, write_time: Duration) {
+        // A write eating more than half the frame budget means the socket is backing up
+        let congested = write_time > self.frame_time() / 2;
+        if !ok || congested {
+            self.fps = (self.fps * 3 / 4).max(self.min_fps);
+            self.clean_frames = 0;
+            return;
+        }
+
+        self.clean_frames += 1;
+        if self.clean_frames >= self.fps * 2 {
+            self.fps = (self.fps + 5).min(self.max_fps);
+            self.clean_frames = 0;
+        }
+    }
+}
+
+/// Checks a requested target frame rate against the bridge's fixed streaming
+/// limit of [`MAX_FPS`] (50 Hz, the same for every bridge).
+pub fn check_fps(fps: u32) -> Result<u32, String> {
+    if (MIN_FPS..=MAX_FPS).contains(&fps) {
+        Ok(fps)
+    } else {
+        Err(format!(
+            "Frame rate must be {}-{} FPS, got {}",
+            MIN_FPS, MAX_FPS, fps
+        ))
+    }
+}
+
+impl Default for AdaptiveRate {
+    fn default() -> Self {
+        Self::new(MAX_FPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_and_recovery() {
+        let mut rate = AdaptiveRate::default();
+        assert_eq!(rate.fps(), 50);
+
+        rate.record(false, Duration::ZERO);
+        assert_eq!(rate.fps(), 37);
+        for _ in 0..10 {
+            rate.record(false, Duration::ZERO);
+        }
+        assert_eq!(rate.fps(), MIN_FPS);
+
+        // Two seconds of clean frames ramp up one step
+        for _ in 0..MIN_FPS * 2 {
+            rate.record(true, Duration::ZERO);
+        }
+        assert_eq!(rate.fps(), MIN_FPS + 5);
+    }
+
+    #[test]
+    fn test_target_fps() {
+        let mut rate = AdaptiveRate::default();
+        rate.set_max_fps(25);
+        assert_eq!(rate.fps(), 25);
+        // Clean frames do not ramp past the target
+        for _ in 0..200 {
+            rate.record(true, Duration::ZERO);
+        }
+        assert_eq!(rate.fps(), 25);
+        rate.set_max_fps(500);
+        assert_eq!(rate.fps(), 25);
+        for _ in 0..1000 {
+            rate.record(true, Duration::ZERO);
+        }
+        assert_eq!(rate.fps(), MAX_FPS);
+
+        assert!(check_fps(60).is_err());
+        assert!(check_fps(5).is_err());
+        assert_eq!(check_fps(30), Ok(30));
+    }
+
+    #[test]
+    fn test_slow_write_counts_as_congestion() {
+        let mut rate = AdaptiveRate::default();
+        rate.record(true, Duration::from_millis(15));
+        assert!(rate.fps() < 50);
+    }
+}
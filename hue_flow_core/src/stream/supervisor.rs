@@ -0,0 +1,257 @@
+//! One stream's lifecycle on the bridge: activating the entertainment area,
+//! the DTLS handshake, recovering an interrupted session and handing the
+//! area back.
+//!
+//! Every step is published as a [`StreamState`], so the CLI, the TUI and
+//! the control API show the same thing without each tracking it.
+use crate::api::error::HueError;
+use crate::api::groups::set_stream_active;
+use crate::credentials::Credentials;
+use crate::models::HueConfig;
+use crate::stream::dtls::HueStreamer;
+use crate::stream::guard::StreamGuard;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Consecutive failed writes (about a second at full rate) after which
+/// the DTLS session is considered lost and re-established.
+pub const MAX_WRITE_FAILURES: u32 = 50;
+
+/// Where a stream is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamState {
+    /// Not streaming; the area is not held.
+    #[default]
+    Idle,
+    /// Switching the entertainment area to streaming mode.
+    Activating,
+    /// DTLS handshake with the bridge.
+    Handshaking,
+    /// Frames are flowing.
+    Streaming,
+    /// The session was interrupted (another application took the area,
+    /// nobody is in the room, writes keep failing) and waits to resume.
+    Recovering,
+}
+
+impl fmt::Display for StreamState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StreamState::Idle => "idle",
+            StreamState::Activating => "activating",
+            StreamState::Handshaking => "handshaking",
+            StreamState::Streaming => "streaming",
+            StreamState::Recovering => "recovering",
+        })
+    }
+}
+
+/// Drives the stream of one entertainment area through its
+/// [`StreamState`]s.
+///
+/// The area is deactivated by [`stop`](Self::stop), or when the supervisor
+/// is dropped while still holding it (see [`StreamGuard`]).
+pub struct StreamSupervisor {
+    config: HueConfig,
+    area_id: String,
+    debug_dtls: bool,
+    state: watch::Sender<StreamState>,
+    guard: Mutex<Option<StreamGuard>>,
+    write_failures: AtomicU32,
+}
+
+impl StreamSupervisor {
+    pub fn new(config: HueConfig, area_id: impl Into<String>) -> Self {
+        Self {
+            config,
+            area_id: area_id.into(),
+            debug_dtls: false,
+            state: watch::channel(StreamState::Idle).0,
+            guard: Mutex::new(None),
+            write_failures: AtomicU32::new(0),
+        }
+    }
+
+    /// Logs every DTLS handshake step, see [`HueStreamer::connect_with`].
+    pub fn debug_dtls(mut self, debug: bool) -> Self {
+        self.debug_dtls = debug;
+        self
+    }
+
+    /// Publishes state changes on `states` instead of a channel of its own,
+    /// e.g. one created before the area is known.
+    pub fn states(mut self, states: watch::Sender<StreamState>) -> Self {
+        states.send_replace(*self.state.borrow());
+        self.state = states;
+        self
+    }
+
+    pub fn area_id(&self) -> &str {
+        &self.area_id
+    }
+
+    pub fn state(&self) -> StreamState {
+        *self.state.borrow()
+    }
+
+    /// Receiver of every state change.
+    pub fn subscribe(&self) -> watch::Receiver<StreamState> {
+        self.state.subscribe()
+    }
+
+    fn set_state(&self, state: StreamState) {
+        self.state.send_if_modified(|current| {
+            let changed = *current != state;
+            if changed {
+                tracing::debug!("Stream {} -> {}", current, state);
+                *current = state;
+            }
+            changed
+        });
+    }
+
+    /// Switches the area to streaming mode and holds it until
+    /// [`stop`](Self::stop).
+    pub async fn activate(&self) -> Result<(), HueError> {
+        self.set_state(StreamState::Activating);
+        if let Err(e) = set_stream_active(&self.config, &self.area_id, true).await {
+            self.set_state(StreamState::Idle);
+            return Err(e);
+        }
+        let guard = StreamGuard::new(self.config.clone(), self.area_id.clone());
+        *self.guard.lock().unwrap() = Some(guard);
+        Ok(())
+    }
+
+    /// Opens the DTLS session to the activated area.
+    pub fn handshake(&self, credentials: &Credentials) -> Result<HueStreamer> {
+        self.set_state(StreamState::Handshaking);
+        match HueStreamer::connect_with(&self.config.bridge_ip, credentials, self.debug_dtls) {
+            Ok(streamer) => {
+                self.write_failures.store(0, Ordering::Relaxed);
+                self.set_state(StreamState::Streaming);
+                Ok(streamer)
+            }
+            Err(e) => {
+                self.set_state(StreamState::Recovering);
+                Err(e).context("Failed to establish DTLS connection")
+            }
+        }
+    }
+
+    /// [`activate`](Self::activate)s the area and [`handshake`](Self::handshake)s,
+    /// releasing the area again if the handshake fails.
+    pub async fn start(&self) -> Result<HueStreamer> {
+        let credentials = self.config.credentials()?;
+        self.activate().await?;
+        match self.handshake(&credentials) {
+            Ok(streamer) => Ok(streamer),
+            Err(e) => {
+                self.stop().await.ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Notes that frames stopped flowing, e.g. while another application
+    /// owns the area.
+    pub fn interrupted(&self) {
+        if self.state() == StreamState::Streaming {
+            self.set_state(StreamState::Recovering);
+        }
+    }
+
+    /// Re-establishes the DTLS session of `streamer`.
+    pub fn recover(&self, streamer: &mut HueStreamer) -> Result<()> {
+        self.set_state(StreamState::Handshaking);
+        match streamer.reconnect() {
+            Ok(()) => {
+                self.write_failures.store(0, Ordering::Relaxed);
+                self.set_state(StreamState::Streaming);
+                Ok(())
+            }
+            Err(e) => {
+                self.set_state(StreamState::Recovering);
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes one message, recovering the session once
+    /// [`MAX_WRITE_FAILURES`] writes in a row have failed.
+    pub fn write(&self, streamer: &mut HueStreamer, msg: &[u8]) -> Result<()> {
+        let result = streamer.write_all(msg);
+        if result.is_ok() {
+            self.write_failures.store(0, Ordering::Relaxed);
+        } else if self.write_failures.fetch_add(1, Ordering::Relaxed) + 1 >= MAX_WRITE_FAILURES {
+            tracing::warn!("DTLS writes keep failing, re-establishing the session");
+            self.write_failures.store(0, Ordering::Relaxed);
+            self.interrupted();
+            if let Err(e) = self.recover(streamer) {
+                tracing::warn!("Failed to re-establish DTLS connection: {:#}", e);
+            }
+        }
+        result
+    }
+
+    /// Hands the area back to the bridge.
+    pub async fn stop(&self) -> Result<(), HueError> {
+        let guard = self.guard.lock().unwrap().take();
+        let result = match guard {
+            Some(guard) => guard.release().await,
+            None => Ok(()),
+        };
+        self.set_state(StreamState::Idle);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RetryPolicy;
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_failed_activation() {
+        // A port nobody listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = HueConfig {
+            bridge_ip: format!("127.0.0.1:{}", port),
+            retry: RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (states, mut changes) = watch::channel(StreamState::Streaming);
+        let supervisor = StreamSupervisor::new(config, "area").states(states);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), StreamState::Idle);
+
+        assert!(supervisor.activate().await.is_err());
+        assert_eq!(supervisor.state(), StreamState::Idle);
+        // Not streaming, so nothing to recover from
+        supervisor.interrupted();
+        assert_eq!(supervisor.state(), StreamState::Idle);
+        assert!(supervisor.stop().await.is_ok());
+    }
+
+    #[test]
+    fn test_state_serialization() {
+        assert_eq!(
+            serde_json::to_string(&StreamState::Recovering).unwrap(),
+            "\"recovering\""
+        );
+        assert_eq!(StreamState::Handshaking.to_string(), "handshaking");
+    }
+}
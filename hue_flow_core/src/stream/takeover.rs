@@ -0,0 +1,116 @@
+use crate::api::groups::set_stream_active;
+use crate::api::v2::get_resource;
+use crate::api::v2::models::{EntertainmentConfiguration, EntertainmentStatus};
+use crate::models::HueConfig;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Who currently owns the entertainment area, as reported by the bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaOwnership {
+    /// HueFlow is the active streamer.
+    Ours,
+    /// Another application (e.g. the Hue Sync app) is streaming to the area.
+    TakenOver,
+    /// Streaming is inactive; nobody owns the area.
+    Free,
+}
+
+/// Classifies an entertainment configuration from the point of view of `application_id`.
+pub fn classify(area: &EntertainmentConfiguration, application_id: &str) -> AreaOwnership {
+    match area.status {
+        EntertainmentStatus::Active => match &area.active_streamer {
+            Some(streamer) if streamer.rid != application_id => AreaOwnership::TakenOver,
+            _ => AreaOwnership::Ours,
+        },
+        _ => AreaOwnership::Free,
+    }
+}
+
+/// Spawns a task polling the entertainment area every `poll_interval`.
+///
+/// The returned receiver reports the current ownership. When the area becomes
+/// free again after another application released it, the watcher re-activates
+/// streaming so the stream loop can reconnect and resume.
+///
+/// With `presence`, the area is released while the room is empty and only
+/// re-activated once someone is back.
+pub fn spawn_takeover_watcher(
+    config: HueConfig,
+    area_id: String,
+    poll_interval: Duration,
+    presence: Option<watch::Receiver<bool>>,
+) -> watch::Receiver<AreaOwnership> {
+    let (tx, rx) = watch::channel(AreaOwnership::Ours);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(poll_interval).await;
+
+            let ownership =
+                match get_resource::<EntertainmentConfiguration>(&config, &area_id).await {
+                    Ok(area) => classify(&area, &config.application_id),
+                    // Keep the last known state on transient REST failures
+                    Err(_) => continue,
+                };
+
+            let present = presence.as_ref().is_none_or(|p| *p.borrow());
+            if ownership == AreaOwnership::Ours
+                && !present
+                && set_stream_active(&config, &area_id, false).await.is_ok()
+            {
+                // Reported as `Free` on the next poll
+                continue;
+            }
+            if ownership == AreaOwnership::Free
+                && present
+                && set_stream_active(&config, &area_id, true).await.is_ok()
+            {
+                // Picked up again on the next poll as `Ours`
+                continue;
+            }
+
+            tx.send_if_modified(|current| {
+                let changed = *current != ownership;
+                *current = ownership;
+                changed
+            });
+            if tx.is_closed() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn area(status: &str, streamer: Option<&str>) -> EntertainmentConfiguration {
+        let mut value = json!({
+            "id": "area",
+            "status": status,
+            "channels": []
+        });
+        if let Some(rid) = streamer {
+            value["active_streamer"] = json!({ "rid": rid, "rtype": "auth_v1" });
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_classify_ownership() {
+        assert_eq!(
+            classify(&area("active", Some("me")), "me"),
+            AreaOwnership::Ours
+        );
+        assert_eq!(
+            classify(&area("active", Some("sync-app")), "me"),
+            AreaOwnership::TakenOver
+        );
+        assert_eq!(classify(&area("inactive", None), "me"), AreaOwnership::Free);
+    }
+}
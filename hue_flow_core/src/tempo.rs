@@ -0,0 +1,175 @@
+//! Tempo tracking, so animations can lock to the beat grid of the music.
+use crate::audio_interface::TempoInfo;
+use std::time::{Duration, Instant};
+
+/// Beats per bar; HueFlow assumes 4/4.
+pub const BEATS_PER_BAR: u64 = 4;
+
+/// Beat period assumed until the first beats are heard (120 BPM).
+const DEFAULT_PERIOD: f32 = 0.5;
+/// Tempo range the clock locks to (60 - 200 BPM).
+const MIN_PERIOD: f32 = 0.3;
+const MAX_PERIOD: f32 = 1.0;
+/// An onset this close to a whole number of beats (as a fraction of one
+/// beat) confirms the current tempo.
+const MATCH_TOLERANCE: f32 = 0.15;
+/// How strongly each onset pulls the period and the phase.
+const PERIOD_GAIN: f32 = 0.2;
+const PHASE_GAIN: f32 = 0.3;
+/// Confirming onsets needed before the clock reports a lock.
+const LOCK_ONSETS: u32 = 4;
+/// Beats without any onset after which the lock is dropped; shorter quiet
+/// passages (breaks, fills) keep the grid running.
+const HOLD_BEATS: f32 = 8.0;
+
+/// A phase-locked beat clock.
+///
+/// Runs freely at the current tempo and is nudged by each detected onset:
+/// the interval since the last onset corrects the period, and the onset's
+/// distance from the nearest beat corrects the phase. Between onsets it keeps
+/// counting, so the grid survives quiet sections.
+#[derive(Debug, Clone)]
+pub struct TempoClock {
+    period: f32,
+    phase: f32,
+    beat: u64,
+    matches: u32,
+    last_tick: Option<Instant>,
+    last_onset: Option<Instant>,
+}
+
+impl Default for TempoClock {
+    fn default() -> Self {
+        Self {
+            period: DEFAULT_PERIOD,
+            phase: 0.0,
+            beat: 0,
+            matches: 0,
+            last_tick: None,
+            last_onset: None,
+        }
+    }
+}
+
+impl TempoClock {
+    /// Advances the clock to `now`; `onset` reports a beat heard on this frame.
+    pub fn update(&mut self, onset: bool, now: Instant) -> TempoInfo {
+        if let Some(last) = self.last_tick {
+            self.advance(now.duration_since(last));
+        }
+        self.last_tick = Some(now);
+
+        if onset {
+            self.sync(now);
+        } else if self
+            .last_onset
+            .is_some_and(|t| now.duration_since(t).as_secs_f32() > self.period * HOLD_BEATS)
+        {
+            self.matches = 0;
+        }
+
+        TempoInfo {
+            bpm: 60.0 / self.period,
+            beat: self.beat,
+            phase: self.phase,
+            locked: self.matches >= LOCK_ONSETS,
+        }
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        self.phase += elapsed.as_secs_f32() / self.period;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.beat += 1;
+        }
+    }
+
+    fn sync(&mut self, now: Instant) {
+        let interval = self.last_onset.map(|t| now.duration_since(t).as_secs_f32());
+        self.last_onset = Some(now);
+
+        // Onsets may skip beats; compare against whole multiples of the period
+        let matched = interval.and_then(|i| {
+            let beats = (i / self.period).round().max(1.0);
+            ((i / self.period - beats).abs() < MATCH_TOLERANCE).then_some(i / beats)
+        });
+
+        if let Some(measured) = matched {
+            self.period += (measured - self.period) * PERIOD_GAIN;
+            self.matches = self.matches.saturating_add(1);
+            // The onset should fall on phase 0; pull towards it
+            let error = if self.phase >= 0.5 {
+                self.phase - 1.0
+            } else {
+                self.phase
+            };
+            self.phase -= error * PHASE_GAIN;
+        } else {
+            self.matches = 0;
+            if let Some(i) = interval.filter(|i| (MIN_PERIOD..=MAX_PERIOD).contains(i)) {
+                self.period = i;
+            }
+            // Not locked yet: restart the beat on this onset
+            if self.phase >= 0.5 {
+                self.beat += 1;
+            }
+            self.phase = 0.0;
+        }
+        self.period = self.period.clamp(MIN_PERIOD, MAX_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the clock at 50 FPS for `frames`, with onsets at the given times.
+    fn run(
+        clock: &mut TempoClock,
+        start: Instant,
+        frames: std::ops::Range<u64>,
+        onsets_ms: &[u64],
+    ) -> TempoInfo {
+        let mut info = TempoInfo::default();
+        for frame in frames {
+            let ms = frame * 20;
+            info = clock.update(onsets_ms.contains(&ms), start + Duration::from_millis(ms));
+        }
+        info
+    }
+
+    #[test]
+    fn test_locks_and_holds_through_quiet() {
+        let mut clock = TempoClock::default();
+        let start = Instant::now();
+        // 100 BPM, slightly off the grid now and then
+        let onsets = [0, 600, 1200, 1820, 2400, 3000, 3580, 4200];
+        let info = run(&mut clock, start, 0..211, &onsets);
+        assert!(info.locked);
+        assert!((info.bpm - 100.0).abs() < 3.0, "{:?}", info);
+        let beat_at_lock = info.beat;
+
+        // Two quiet seconds: the grid keeps counting
+        let info = run(&mut clock, start, 211..311, &[]);
+        assert!(info.locked);
+        assert!(
+            (info.beat - beat_at_lock).abs_diff(3) <= 1,
+            "{:?} after {}",
+            info,
+            beat_at_lock
+        );
+        assert_eq!(info.bar(), info.beat / BEATS_PER_BAR);
+    }
+
+    #[test]
+    fn test_skipped_beats_keep_tempo() {
+        let mut clock = TempoClock::default();
+        let start = Instant::now();
+        // 120 BPM with every other beat missing later on
+        let onsets = [0, 500, 1000, 1500, 2000, 2500, 3500, 4500, 5500];
+        let info = run(&mut clock, start, 0..276, &onsets);
+        assert!(info.locked);
+        assert!((info.bpm - 120.0).abs() < 2.0, "{:?}", info);
+        assert!(info.phase < 0.1 || info.phase > 0.9, "{:?}", info);
+    }
+}
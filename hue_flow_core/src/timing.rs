@@ -0,0 +1,192 @@
+//! Per-stage latency statistics for the render and streaming pipeline.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Number of recent samples kept per stage.
+const WINDOW: usize = 512;
+
+/// Time since the first call, the timeline of
+/// [`AnalysisFrame::time`](crate::audio_interface::AnalysisFrame::time) and
+/// the [`TimecodeClock`](crate::show::TimecodeClock).
+///
+/// Reads the system clock, so not for wasm32-unknown-unknown.
+pub fn now() -> Duration {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
+
+/// A step every frame passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Reading the audio source (capture and analysis).
+    Audio,
+    /// Rendering the effect.
+    Effect,
+    /// Encoding the HueStream message.
+    Encode,
+    /// Writing the frame to the DTLS session or sink.
+    Send,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Audio, Stage::Effect, Stage::Encode, Stage::Send];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Audio => "audio",
+            Stage::Effect => "effect",
+            Stage::Encode => "encode",
+            Stage::Send => "send",
+        }
+    }
+}
+
+/// Rolling window of recent stage durations, shared between the render
+/// loop and the streaming task.
+#[derive(Debug, Default)]
+pub struct StageTimings {
+    samples: Mutex<HashMap<Stage, VecDeque<Duration>>>,
+}
+
+impl StageTimings {
+    /// Records the time since `started` for `stage` and returns it.
+    pub fn record(&self, stage: Stage, started: Instant) -> Duration {
+        let elapsed = started.elapsed();
+        self.record_duration(stage, elapsed);
+        elapsed
+    }
+
+    pub fn record_duration(&self, stage: Stage, elapsed: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        let window = samples.entry(stage).or_default();
+        if window.len() == WINDOW {
+            window.pop_front();
+        }
+        window.push_back(elapsed);
+    }
+
+    /// Median and 99th percentile of the recent samples of `stage`.
+    pub fn percentiles(&self, stage: Stage) -> Option<(Duration, Duration)> {
+        let samples = self.samples.lock().unwrap();
+        let mut sorted: Vec<Duration> = samples.get(&stage)?.iter().copied().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort();
+        let at = |pct: usize| sorted[(sorted.len() - 1) * pct / 100];
+        Some((at(50), at(99)))
+    }
+
+    /// One line with p50/p99 of every stage that has samples, e.g.
+    /// `effect p50=0.02ms p99=0.11ms | send p50=0.30ms p99=1.20ms`.
+    pub fn summary(&self) -> String {
+        let mut line = String::new();
+        for stage in Stage::ALL {
+            if let Some((p50, p99)) = self.percentiles(stage) {
+                if !line.is_empty() {
+                    line.push_str(" | ");
+                }
+                let _ = write!(
+                    line,
+                    "{} p50={:.2}ms p99={:.2}ms",
+                    stage.name(),
+                    p50.as_secs_f64() * 1000.0,
+                    p99.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        line
+    }
+}
+
+/// Summary of a batch of measured round trips or delays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// `None` without samples.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let at = |pct: usize| samples[(samples.len() - 1) * pct / 100];
+        Some(Self {
+            count: samples.len(),
+            min: samples[0],
+            median: at(50),
+            p95: at(95),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+impl std::fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "min {:.1} ms, median {:.1} ms, p95 {:.1} ms, max {:.1} ms ({} samples)",
+            ms(self.min),
+            ms(self.median),
+            ms(self.p95),
+            ms(self.max),
+            self.count
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let timings = StageTimings::default();
+        assert!(timings.percentiles(Stage::Effect).is_none());
+
+        for ms in 1..=100 {
+            timings.record_duration(Stage::Effect, Duration::from_millis(ms));
+        }
+        let (p50, p99) = timings.percentiles(Stage::Effect).unwrap();
+        assert_eq!(p50, Duration::from_millis(50));
+        assert_eq!(p99, Duration::from_millis(99));
+        assert!(timings
+            .summary()
+            .starts_with("effect p50=50.00ms p99=99.00ms"));
+    }
+
+    #[test]
+    fn test_window_drops_old_samples() {
+        let timings = StageTimings::default();
+        timings.record_duration(Stage::Send, Duration::from_secs(10));
+        for _ in 0..WINDOW {
+            timings.record_duration(Stage::Send, Duration::from_millis(1));
+        }
+        let (_, p99) = timings.percentiles(Stage::Send).unwrap();
+        assert_eq!(p99, Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        assert!(LatencyStats::from_samples(Vec::new()).is_none());
+        let samples = (1..=20).rev().map(Duration::from_millis).collect();
+        let stats = LatencyStats::from_samples(samples).unwrap();
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.median, Duration::from_millis(10));
+        assert_eq!(stats.p95, Duration::from_millis(19));
+        assert_eq!(stats.max, Duration::from_millis(20));
+        assert_eq!(
+            stats.to_string(),
+            "min 1.0 ms, median 10.0 ms, p95 19.0 ms, max 20.0 ms (20 samples)"
+        );
+    }
+}
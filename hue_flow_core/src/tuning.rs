@@ -0,0 +1,130 @@
+//! Measurements for the tuning wizard (`hueflow tune`): how loud each band
+//! gets with the room's audio, and the sensitivity that lets the loud
+//! moments reach full brightness without sitting there all the time.
+use crate::audio_interface::AudioSpectrum;
+
+/// Level the loud moments reach with the suggested sensitivity.
+pub const TARGET_LEVEL: f32 = 0.9;
+/// Range of sensitivities the wizard suggests.
+pub const MIN_SENSITIVITY: f32 = 0.25;
+pub const MAX_SENSITIVITY: f32 = 4.0;
+/// Share of frames counted as "loud moments"; the rest stay below the
+/// target. Taking a percentile keeps single clicks from setting the gain.
+const LOUD_PERCENTILE: f32 = 0.95;
+/// Loudest level below which the input is taken for silence.
+const SILENCE: f32 = 0.01;
+
+/// Bass, mids and highs colors.
+pub type BandPalette = [(u8, u8, u8); 3];
+
+/// Palettes the wizard offers.
+pub const PALETTES: &[(&str, BandPalette)] = &[
+    ("Classic", [(255, 0, 0), (0, 255, 0), (0, 0, 255)]),
+    ("Sunset", [(255, 64, 0), (255, 0, 96), (255, 180, 0)]),
+    ("Ocean", [(0, 64, 255), (0, 200, 200), (180, 240, 255)]),
+    ("Forest", [(0, 160, 40), (120, 200, 0), (255, 220, 120)]),
+    ("Neon", [(255, 0, 200), (0, 255, 255), (200, 255, 0)]),
+];
+
+/// Levels of one band over the measurement.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BandStats {
+    pub average: f32,
+    /// The level of the loud moments (95th percentile).
+    pub loud: f32,
+    pub peak: f32,
+}
+
+/// Collects band levels frame by frame.
+#[derive(Debug, Clone, Default)]
+pub struct LevelMeter {
+    bass: Vec<f32>,
+    mids: Vec<f32>,
+    highs: Vec<f32>,
+}
+
+impl LevelMeter {
+    pub fn record(&mut self, spectrum: &AudioSpectrum) {
+        self.bass.push(spectrum.bass);
+        self.mids.push(spectrum.mids);
+        self.highs.push(spectrum.highs);
+    }
+
+    /// Frames recorded so far.
+    pub fn frames(&self) -> usize {
+        self.bass.len()
+    }
+
+    /// Stats of the bass, mids and highs, in that order.
+    pub fn bands(&self) -> [BandStats; 3] {
+        [
+            band_stats(&self.bass),
+            band_stats(&self.mids),
+            band_stats(&self.highs),
+        ]
+    }
+
+    /// Sensitivity bringing the loudest band's loud moments to
+    /// [`TARGET_LEVEL`]; `None` when the input was silent.
+    pub fn suggested_sensitivity(&self) -> Option<f32> {
+        let loud = self.bands().iter().map(|b| b.loud).fold(0.0, f32::max);
+        (loud >= SILENCE).then(|| (TARGET_LEVEL / loud).clamp(MIN_SENSITIVITY, MAX_SENSITIVITY))
+    }
+}
+
+fn band_stats(levels: &[f32]) -> BandStats {
+    if levels.is_empty() {
+        return BandStats::default();
+    }
+    let mut sorted = levels.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let loud_index = ((sorted.len() - 1) as f32 * LOUD_PERCENTILE).round() as usize;
+    BandStats {
+        average: levels.iter().sum::<f32>() / levels.len() as f32,
+        loud: sorted[loud_index],
+        peak: sorted[sorted.len() - 1],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrum(bass: f32, mids: f32) -> AudioSpectrum {
+        AudioSpectrum {
+            bass,
+            mids,
+            highs: 0.0,
+            energy: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_level_meter() {
+        let mut meter = LevelMeter::default();
+        assert_eq!(meter.suggested_sensitivity(), None);
+
+        for i in 0..100 {
+            meter.record(&spectrum(i as f32 / 100.0 * 0.3, 0.1));
+        }
+        // A single click does not count as loud
+        meter.record(&spectrum(1.0, 0.1));
+        let [bass, mids, highs] = meter.bands();
+        assert_eq!(meter.frames(), 101);
+        assert_eq!(bass.peak, 1.0);
+        assert!((bass.loud - 0.285).abs() < 0.01, "{:?}", bass);
+        assert!((mids.average - 0.1).abs() < 1e-6);
+        assert_eq!(highs, BandStats::default());
+
+        let suggested = meter.suggested_sensitivity().unwrap();
+        assert!((suggested - 0.9 / bass.loud).abs() < 1e-6);
+
+        // Silence suggests nothing, a very quiet room the maximum
+        let mut quiet = LevelMeter::default();
+        quiet.record(&spectrum(0.0, 0.0));
+        assert_eq!(quiet.suggested_sensitivity(), None);
+        quiet.record(&spectrum(0.05, 0.0));
+        quiet.record(&spectrum(0.05, 0.0));
+        assert_eq!(quiet.suggested_sensitivity(), Some(MAX_SENSITIVITY));
+    }
+}
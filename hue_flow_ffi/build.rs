@@ -0,0 +1,27 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Set to refresh the checked-in `include/hue_flow.h` as well.
+const UPDATE_HEADER_ENV: &str = "HUEFLOW_UPDATE_HEADER";
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed={}", UPDATE_HEADER_ENV);
+
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C bindings");
+    // Builds never touch the source tree unless asked to
+    bindings.write_to_file(out_dir.join("hue_flow.h"));
+    if env::var_os(UPDATE_HEADER_ENV).is_some() {
+        bindings.write_to_file(crate_dir.join("include/hue_flow.h"));
+    }
+}
@@ -0,0 +1,146 @@
+#ifndef HUE_FLOW_H
+#define HUE_FLOW_H
+
+/* Generated by cbindgen from hue_flow_ffi/src/lib.rs - do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An effect instance created by [`hueflow_effect_new`].
+ */
+typedef struct HueFlowEffect HueFlowEffect;
+
+/**
+ * An active entertainment stream.
+ */
+typedef struct HueFlowStream HueFlowStream;
+
+/**
+ * Color of one entertainment channel.
+ */
+typedef struct HueFlowColor {
+  uint8_t channel_id;
+  uint8_t r;
+  uint8_t g;
+  uint8_t b;
+} HueFlowColor;
+
+/**
+ * An RGB palette entry.
+ */
+typedef struct HueFlowRgb {
+  uint8_t r;
+  uint8_t g;
+  uint8_t b;
+} HueFlowRgb;
+
+/**
+ * Audio analysis for one frame; all values are normalized to 0.0-1.0.
+ */
+typedef struct HueFlowSpectrum {
+  float bass;
+  float mids;
+  float highs;
+  float energy;
+} HueFlowSpectrum;
+
+/**
+ * Position of an entertainment channel (-1.0 to 1.0 on each axis).
+ */
+typedef struct HueFlowNode {
+  uint8_t channel_id;
+  double x;
+  double y;
+  double z;
+} HueFlowNode;
+
+/**
+ * Returns the last error message of the calling thread, or `NULL` if none.
+ * The string stays valid until the next failing call on this thread.
+ */
+const char *hueflow_last_error(void);
+
+/**
+ * Activates streaming on the entertainment area and opens the DTLS session.
+ *
+ * `username` is the app key from bridge registration, `client_key` the
+ * hex-encoded PSK and `area_id` the entertainment configuration UUID.
+ *
+ * # Safety
+ * All arguments must be valid NUL-terminated strings.
+ */
+struct HueFlowStream *hueflow_connect(const char *bridge_ip,
+                                      const char *username,
+                                      const char *client_key,
+                                      const char *area_id);
+
+/**
+ * Sends one frame with `len` channel colors. Channels not listed keep
+ * their last color on the bridge.
+ *
+ * Returns 1 without sending when called sooner than 16.6 ms after the
+ * previous frame (the bridge accepts at most 60 frames per second).
+ *
+ * # Safety
+ * `stream` must come from [`hueflow_connect`]; `colors` must point to `len`
+ * elements (may be `NULL` when `len` is 0).
+ */
+int32_t hueflow_send_frame(struct HueFlowStream *stream,
+                           const struct HueFlowColor *colors,
+                           size_t len);
+
+/**
+ * Stops streaming, hands the area back to the bridge and frees the handle.
+ *
+ * The handle is freed either way; `-1` means the bridge may still hold the
+ * area for streaming (it times out on its own after a few seconds without
+ * frames).
+ *
+ * # Safety
+ * `stream` must come from [`hueflow_connect`] (or be `NULL`) and must not be
+ * used afterwards.
+ */
+int32_t hueflow_disconnect(struct HueFlowStream *stream);
+
+/**
+ * Creates an effect by name (`multiband`, `pulse`, `warm`), using `palette`
+ * for its colors where applicable.
+ *
+ * # Safety
+ * `name` must be a valid NUL-terminated string; `palette` must point to
+ * `palette_len` elements (may be `NULL` when `palette_len` is 0).
+ */
+struct HueFlowEffect *hueflow_effect_new(const char *name,
+                                         const struct HueFlowRgb *palette,
+                                         size_t palette_len);
+
+/**
+ * Renders one frame for `nodes` into `out`, sorted by channel id.
+ * Returns the number of colors written (at most `out_len`), or -1 on error.
+ *
+ * # Safety
+ * `effect` must come from [`hueflow_effect_new`]; `spectrum` must be valid;
+ * `nodes` must point to `node_len` elements and `out` to `out_len` writable
+ * elements.
+ */
+int32_t hueflow_effect_render(struct HueFlowEffect *effect,
+                              const struct HueFlowSpectrum *spectrum,
+                              const struct HueFlowNode *nodes,
+                              size_t node_len,
+                              struct HueFlowColor *out,
+                              size_t out_len);
+
+/**
+ * Frees an effect created by [`hueflow_effect_new`].
+ *
+ * # Safety
+ * `effect` must come from [`hueflow_effect_new`] (or be `NULL`) and must not
+ * be used afterwards.
+ */
+void hueflow_effect_free(struct HueFlowEffect *effect);
+
+#endif  /* HUE_FLOW_H */
@@ -0,0 +1,409 @@
+//! C ABI for HueFlow, for hosts such as OBS or VST plugins written in C/C++.
+//!
+//! The header `include/hue_flow.h` is generated by cbindgen; builds write it
+//! to `OUT_DIR` and refresh the checked-in copy only with
+//! `HUEFLOW_UPDATE_HEADER=1`.
+//!
+//! Conventions:
+//! - Functions returning a pointer return `NULL` on failure; functions
+//!   returning `int32_t` return `0` on success and `-1` on failure.
+//! - After a failure, [`hueflow_last_error`] describes what went wrong on the
+//!   calling thread.
+//! - Handles must be released with their matching `*_free`/`disconnect`
+//!   function and must not be used from several threads at once.
+use hue_flow_core::api::client::HueClient;
+use hue_flow_core::audio_interface::{AnalysisFrame, AudioSpectrum};
+use hue_flow_core::effects::{create_effect, LightEffect};
+use hue_flow_core::models::{HueConfig, LightNode};
+use hue_flow_core::stream::dtls::HueStreamer;
+use hue_flow_core::stream::protocol::ProtocolEncoder;
+use hue_flow_core::stream::supervisor::StreamSupervisor;
+use hue_flow_core::timing;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::ptr;
+use tokio::runtime::Runtime;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Display) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Color of one entertainment channel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HueFlowColor {
+    pub channel_id: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// An RGB palette entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HueFlowRgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Audio analysis for one frame; all values are normalized to 0.0-1.0.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HueFlowSpectrum {
+    pub bass: f32,
+    pub mids: f32,
+    pub highs: f32,
+    pub energy: f32,
+}
+
+/// Position of an entertainment channel (-1.0 to 1.0 on each axis).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HueFlowNode {
+    pub channel_id: u8,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// An active entertainment stream.
+pub struct HueFlowStream {
+    runtime: Runtime,
+    streamer: HueStreamer,
+    encoder: ProtocolEncoder,
+    supervisor: StreamSupervisor,
+}
+
+/// An effect instance created by [`hueflow_effect_new`].
+pub struct HueFlowEffect {
+    effect: Box<dyn LightEffect>,
+}
+
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> Option<&'a str> {
+    if value.is_null() {
+        set_last_error(format!("{} is NULL", name));
+        return None;
+    }
+    match CStr::from_ptr(value).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not valid UTF-8", name));
+            None
+        }
+    }
+}
+
+/// Returns the last error message of the calling thread, or `NULL` if none.
+/// The string stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn hueflow_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Activates streaming on the entertainment area and opens the DTLS session.
+///
+/// `username` is the app key from bridge registration, `client_key` the
+/// hex-encoded PSK and `area_id` the entertainment configuration UUID.
+///
+/// # Safety
+/// All arguments must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_connect(
+    bridge_ip: *const c_char,
+    username: *const c_char,
+    client_key: *const c_char,
+    area_id: *const c_char,
+) -> *mut HueFlowStream {
+    let (Some(bridge_ip), Some(username), Some(client_key), Some(area_id)) = (
+        str_arg(bridge_ip, "bridge_ip"),
+        str_arg(username, "username"),
+        str_arg(client_key, "client_key"),
+        str_arg(area_id, "area_id"),
+    ) else {
+        return ptr::null_mut();
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(rt) => rt,
+        Err(e) => {
+            set_last_error(e);
+            return ptr::null_mut();
+        }
+    };
+
+    let result = runtime.block_on(async {
+        let application_id = HueClient::get_application_id(bridge_ip, username).await?;
+        let config = HueConfig {
+            bridge_ip: bridge_ip.to_string(),
+            username: username.to_string(),
+            client_key: client_key.to_string(),
+            application_id,
+            entertainment_group_id: area_id.to_string(),
+            ..Default::default()
+        };
+        let supervisor = StreamSupervisor::new(config, area_id);
+        // Use application_id as PSK Identity (NOT username!)
+        let streamer = supervisor.start().await?;
+        anyhow::Ok((supervisor, streamer))
+    });
+
+    match result {
+        Ok((supervisor, streamer)) => Box::into_raw(Box::new(HueFlowStream {
+            runtime,
+            streamer,
+            encoder: ProtocolEncoder::new(area_id),
+            supervisor,
+        })),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sends one frame with `len` channel colors. Channels not listed keep
+/// their last color on the bridge.
+///
+/// Returns 1 without sending when called sooner than 16.6 ms after the
+/// previous frame (the bridge accepts at most 60 frames per second).
+///
+/// # Safety
+/// `stream` must come from [`hueflow_connect`]; `colors` must point to `len`
+/// elements (may be `NULL` when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_send_frame(
+    stream: *mut HueFlowStream,
+    colors: *const HueFlowColor,
+    len: usize,
+) -> i32 {
+    let Some(stream) = stream.as_mut() else {
+        set_last_error("stream is NULL");
+        return -1;
+    };
+    let colors = if len == 0 {
+        &[][..]
+    } else if colors.is_null() {
+        set_last_error("colors is NULL");
+        return -1;
+    } else {
+        std::slice::from_raw_parts(colors, len)
+    };
+
+    let lights: HashMap<u8, (u8, u8, u8)> = colors
+        .iter()
+        .map(|c| (c.channel_id, (c.r, c.g, c.b)))
+        .collect();
+    let Some(message) = stream.encoder.encode_pooled(&lights) else {
+        return 1;
+    };
+    match stream.supervisor.write(&mut stream.streamer, message) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Stops streaming, hands the area back to the bridge and frees the handle.
+///
+/// The handle is freed either way; `-1` means the bridge may still hold the
+/// area for streaming (it times out on its own after a few seconds without
+/// frames).
+///
+/// # Safety
+/// `stream` must come from [`hueflow_connect`] (or be `NULL`) and must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_disconnect(stream: *mut HueFlowStream) -> i32 {
+    if stream.is_null() {
+        return 0;
+    }
+    let stream = Box::from_raw(stream);
+    match stream.runtime.block_on(stream.supervisor.stop()) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Creates an effect by name (`multiband`, `pulse`, `warm`), using `palette`
+/// for its colors where applicable.
+///
+/// # Safety
+/// `name` must be a valid NUL-terminated string; `palette` must point to
+/// `palette_len` elements (may be `NULL` when `palette_len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_effect_new(
+    name: *const c_char,
+    palette: *const HueFlowRgb,
+    palette_len: usize,
+) -> *mut HueFlowEffect {
+    let Some(name) = str_arg(name, "name") else {
+        return ptr::null_mut();
+    };
+    let palette: Vec<(u8, u8, u8)> = if palette.is_null() || palette_len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(palette, palette_len)
+            .iter()
+            .map(|c| (c.r, c.g, c.b))
+            .collect()
+    };
+
+    match create_effect(name, &palette) {
+        Some(effect) => Box::into_raw(Box::new(HueFlowEffect { effect })),
+        None => {
+            set_last_error(format!("Unknown effect '{}'", name));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Renders one frame for `nodes` into `out`, sorted by channel id.
+/// Returns the number of colors written (at most `out_len`), or -1 on error.
+///
+/// # Safety
+/// `effect` must come from [`hueflow_effect_new`]; `spectrum` must be valid;
+/// `nodes` must point to `node_len` elements and `out` to `out_len` writable
+/// elements.
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_effect_render(
+    effect: *mut HueFlowEffect,
+    spectrum: *const HueFlowSpectrum,
+    nodes: *const HueFlowNode,
+    node_len: usize,
+    out: *mut HueFlowColor,
+    out_len: usize,
+) -> i32 {
+    let (Some(effect), Some(spectrum)) = (effect.as_mut(), spectrum.as_ref()) else {
+        set_last_error("effect or spectrum is NULL");
+        return -1;
+    };
+    if (nodes.is_null() && node_len > 0) || (out.is_null() && out_len > 0) {
+        set_last_error("nodes or out is NULL");
+        return -1;
+    }
+
+    let nodes: Vec<LightNode> = if node_len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(nodes, node_len)
+            .iter()
+            .map(|n| LightNode {
+                id: format!("channel_{}", n.channel_id),
+                channel_id: n.channel_id,
+                x: n.x,
+                y: n.y,
+                z: n.z,
+                segment: 0,
+            })
+            .collect()
+    };
+    let analysis = AnalysisFrame {
+        time: timing::now(),
+        ..AudioSpectrum {
+            bass: spectrum.bass,
+            mids: spectrum.mids,
+            highs: spectrum.highs,
+            energy: spectrum.energy,
+        }
+        .into()
+    };
+
+    let mut frame: Vec<_> = effect
+        .effect
+        .update_frame(&analysis, &nodes)
+        .into_iter()
+        .collect();
+    frame.sort_by_key(|(channel_id, _)| *channel_id);
+
+    let written = frame.len().min(out_len);
+    for (i, (channel_id, (r, g, b))) in frame.into_iter().take(written).enumerate() {
+        *out.add(i) = HueFlowColor {
+            channel_id,
+            r,
+            g,
+            b,
+        };
+    }
+    written as i32
+}
+
+/// Frees an effect created by [`hueflow_effect_new`].
+///
+/// # Safety
+/// `effect` must come from [`hueflow_effect_new`] (or be `NULL`) and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hueflow_effect_free(effect: *mut HueFlowEffect) {
+    if !effect.is_null() {
+        drop(Box::from_raw(effect));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effect_render() {
+        let nodes = [
+            HueFlowNode {
+                channel_id: 0,
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            HueFlowNode {
+                channel_id: 1,
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        ];
+        let spectrum = HueFlowSpectrum {
+            bass: 1.0,
+            mids: 0.0,
+            highs: 0.0,
+            energy: 1.0,
+        };
+        let mut out = [HueFlowColor::default(); 4];
+
+        unsafe {
+            let effect = hueflow_effect_new(c"multiband".as_ptr(), ptr::null(), 0);
+            assert!(!effect.is_null());
+            let written = hueflow_effect_render(
+                effect,
+                &spectrum,
+                nodes.as_ptr(),
+                nodes.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            );
+            hueflow_effect_free(effect);
+            assert_eq!(written, 2);
+        }
+        assert_eq!((out[0].channel_id, out[0].r, out[0].g), (0, 255, 0));
+        assert_eq!((out[1].channel_id, out[1].g), (1, 0));
+
+        unsafe {
+            assert!(hueflow_effect_new(c"nope".as_ptr(), ptr::null(), 0).is_null());
+            let message = CStr::from_ptr(hueflow_last_error());
+            assert!(message.to_str().unwrap().contains("nope"));
+        }
+    }
+}
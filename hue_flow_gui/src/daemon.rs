@@ -0,0 +1,132 @@
+//! A `hueflow run` process in the background, driven through its control
+//! socket.
+use anyhow::Context;
+use hue_flow_core::config;
+use hue_flow_core::control::socket::{self, Query, Reply, Request, Status};
+use hue_flow_core::control::{ControlCommand, DEFAULT_CONTROL_ADDR};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use tokio::runtime::Runtime;
+
+/// Names of the CLI next to this executable: as installed, then as built.
+const CLI_NAMES: &[&str] = &["hueflow", "hue_flow_cli"];
+
+pub struct Daemon {
+    runtime: Runtime,
+    socket: PathBuf,
+    /// The process started here, while it runs.
+    child: Option<Child>,
+}
+
+impl Daemon {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            runtime: Runtime::new()?,
+            socket: socket::default_socket_path(),
+            child: None,
+        })
+    }
+
+    /// Counters of the running instance, `None` when no instance answers,
+    /// e.g. while one is still starting.
+    pub fn status(&mut self) -> Option<Status> {
+        self.request(&Request::Query(Query::Status)).ok()?.status
+    }
+
+    /// True while a process started here has not exited yet.
+    pub fn started(&mut self) -> bool {
+        if let Some(child) = &mut self.child {
+            if !matches!(child.try_wait(), Ok(None)) {
+                self.child = None;
+            }
+        }
+        self.child.is_some()
+    }
+
+    /// Starts streaming `effect` in a new background process, or switches
+    /// an instance that is already running to it.
+    pub fn start(&mut self, effect: &str) -> anyhow::Result<()> {
+        if self.status().is_some() {
+            return self.set_effect(effect);
+        }
+        let cli = cli_path();
+        let mut command = Command::new(&cli);
+        command
+            .args(["run", "--effect", effect, "--control-socket"])
+            .arg(&self.socket);
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            // CREATE_NO_WINDOW: no console window for the background process
+            command.creation_flags(0x0800_0000);
+        }
+        let child = command
+            .spawn()
+            .with_context(|| format!("Cannot start {}", cli.display()))?;
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Ends the stream of the running instance.
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        self.command(ControlCommand::Stop)
+    }
+
+    pub fn set_effect(&mut self, name: &str) -> anyhow::Result<()> {
+        self.command(ControlCommand::SetEffect {
+            name: name.to_string(),
+        })
+    }
+
+    fn command(&mut self, command: ControlCommand) -> anyhow::Result<()> {
+        let reply = self
+            .request(&Request::Command(command))
+            .context("No running HueFlow instance")?;
+        match reply.ok {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!(reply.error.unwrap_or_default())),
+        }
+    }
+
+    fn request(&mut self, request: &Request) -> std::io::Result<Reply> {
+        self.runtime
+            .block_on(socket::request(&self.socket, request))
+    }
+}
+
+/// The web page of a running instance, served by its control API, over
+/// TLS and with a token when the stored config asks for them.
+pub fn web_ui_url() -> String {
+    let access = config::load_file(&config::config_path())
+        .ok()
+        .flatten()
+        .map(|stored| stored.control_auth)
+        .unwrap_or_default();
+    let scheme = if access.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let mut url = format!("{}://{}/overlay", scheme, DEFAULT_CONTROL_ADDR);
+    if let Some(token) = access.read_token() {
+        url.push_str("?token=");
+        url.push_str(token);
+    }
+    url
+}
+
+/// The CLI next to this executable, else `hueflow` on the `PATH`.
+fn cli_path() -> PathBuf {
+    let dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    dir.into_iter()
+        .flat_map(|dir| {
+            CLI_NAMES.iter().map(move |name| {
+                dir.join(name)
+                    .with_extension(std::env::consts::EXE_EXTENSION)
+            })
+        })
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(CLI_NAMES[0]))
+}
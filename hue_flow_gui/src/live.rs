@@ -0,0 +1,299 @@
+//! Streaming to the selected area: level meters, the colors sent, and the
+//! effect controls of the control channel.
+use crate::Shared;
+use eframe::egui::{self, Color32};
+use hue_flow_core::api::groups::GroupInfo;
+use hue_flow_core::audio_interface::AudioSpectrum;
+use hue_flow_core::control::ControlCommand;
+use hue_flow_core::effects::{create_effect, Frame, EFFECTS};
+use hue_flow_core::models::HueConfig;
+use hue_flow_core::output::blackout::DEFAULT_FADE;
+use hue_flow_core::{preset, FlowEvent, HueFlow};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::mpsc::Sender;
+
+/// How long to wait for the stream to release the area when the app closes.
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What the render loop last reported.
+#[derive(Default)]
+struct Levels {
+    audio: AudioSpectrum,
+    frame: Frame,
+    fps: u32,
+}
+
+/// A running stream: its control channel and the thread driving it.
+struct Session {
+    control: Sender<ControlCommand>,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
+pub struct LivePanel {
+    session: Option<Session>,
+    levels: Arc<Mutex<Levels>>,
+    effect: String,
+    presets: Vec<String>,
+    safe_mode: bool,
+    fps: u32,
+    blacked_out: bool,
+}
+
+impl Default for LivePanel {
+    fn default() -> Self {
+        Self {
+            session: None,
+            levels: Arc::default(),
+            effect: EFFECTS[0].name.to_string(),
+            presets: preset::list(&preset::presets_dir()).unwrap_or_default(),
+            safe_mode: false,
+            fps: 20,
+            blacked_out: false,
+        }
+    }
+}
+
+impl LivePanel {
+    pub fn ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        if self
+            .session
+            .as_ref()
+            .is_some_and(|s| s.thread.is_finished())
+        {
+            let session = self.session.take().unwrap();
+            shared.colors.clear();
+            shared.status = match session.thread.join() {
+                Ok(Ok(())) => "Stream stopped".to_string(),
+                Ok(Err(e)) => format!("Stream failed: {}", e),
+                Err(_) => "Stream failed".to_string(),
+            };
+        }
+
+        let running = self.session.is_some();
+        ui.horizontal(|ui| {
+            if running {
+                if ui.button("Stop").clicked() {
+                    self.send(ControlCommand::Stop);
+                }
+                return;
+            }
+            let ready = shared.config.is_some() && shared.group().is_some();
+            if ui.add_enabled(ready, egui::Button::new("Start")).clicked() {
+                self.start(ui.ctx(), shared);
+            }
+            if !ready {
+                ui.label("Link a bridge and select an area in Setup first.");
+            }
+        });
+        ui.separator();
+
+        ui.add_enabled_ui(running, |ui| self.controls_ui(ui));
+        ui.separator();
+        self.meters_ui(ui, shared);
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("controls").num_columns(2).show(ui, |ui| {
+            ui.label("Effect");
+            let mut chosen = None;
+            egui::ComboBox::from_id_salt("effect")
+                .selected_text(&self.effect)
+                .show_ui(ui, |ui| {
+                    for info in EFFECTS {
+                        let response = ui.selectable_label(self.effect == info.name, info.name);
+                        if response.on_hover_text(info.description).clicked() {
+                            chosen = Some(ControlCommand::SetEffect {
+                                name: info.name.to_string(),
+                            });
+                            self.effect = info.name.to_string();
+                        }
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Preset");
+            egui::ComboBox::from_id_salt("preset")
+                .selected_text("Load...")
+                .show_ui(ui, |ui| {
+                    for name in &self.presets {
+                        if ui.selectable_label(false, name).clicked() {
+                            chosen = Some(ControlCommand::LoadPreset { name: name.clone() });
+                        }
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Frame rate");
+            let slider = ui.add(egui::Slider::new(&mut self.fps, 10..=50).suffix(" FPS"));
+            if slider.drag_stopped() || (slider.changed() && !slider.dragged()) {
+                chosen = Some(ControlCommand::SetFps { fps: self.fps });
+            }
+            ui.end_row();
+
+            ui.label("Photosensitive-safe");
+            if ui.checkbox(&mut self.safe_mode, "").changed() {
+                chosen = Some(ControlCommand::SafeMode {
+                    enabled: self.safe_mode,
+                });
+            }
+            ui.end_row();
+
+            ui.label("Output");
+            let fade_ms = DEFAULT_FADE.as_millis() as u64;
+            let label = if self.blacked_out {
+                "Resume"
+            } else {
+                "Blackout"
+            };
+            if ui.button(label).clicked() {
+                chosen = Some(match self.blacked_out {
+                    true => ControlCommand::Resume { fade_ms },
+                    false => ControlCommand::Blackout { fade_ms },
+                });
+                self.blacked_out = !self.blacked_out;
+            }
+            ui.end_row();
+
+            if let Some(command) = chosen {
+                self.send(command);
+            }
+        });
+    }
+
+    fn meters_ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        let levels = self.levels.lock().unwrap();
+        egui::Grid::new("meters").num_columns(2).show(ui, |ui| {
+            for (name, level) in [
+                ("Bass", levels.audio.bass),
+                ("Mids", levels.audio.mids),
+                ("Highs", levels.audio.highs),
+                ("Energy", levels.audio.energy),
+            ] {
+                ui.label(name);
+                ui.add(egui::ProgressBar::new(level.clamp(0.0, 1.0)).desired_width(240.0));
+                ui.end_row();
+            }
+        });
+        if self.session.is_none() {
+            return;
+        }
+        ui.label(format!("{} FPS", levels.fps));
+        let mut channels: Vec<_> = levels.frame.iter().collect();
+        channels.sort_by_key(|(channel, _)| **channel);
+        ui.horizontal_wrapped(|ui| {
+            for (channel, &(r, g, b)) in channels {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(28.0, 28.0), egui::Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 4.0, Color32::from_rgb(r, g, b));
+                response
+                    .on_hover_text(format!("Channel {}: #{:02X}{:02X}{:02X}", channel, r, g, b));
+            }
+        });
+        shared.colors = levels.frame.clone();
+    }
+
+    fn start(&mut self, ctx: &egui::Context, shared: &mut Shared) {
+        let (Some(config), Some(group)) = (shared.config.clone(), shared.group().cloned()) else {
+            return;
+        };
+        *self.levels.lock().unwrap() = Levels::default();
+        self.blacked_out = false;
+        self.fps = config.fps.unwrap_or(20);
+        let levels = self.levels.clone();
+        let ctx = ctx.clone();
+        let handle = shared.runtime.handle().clone();
+        let area = group.name.clone();
+        let effect = self.effect.clone();
+        let safe_mode = self.safe_mode;
+        let (control_tx, control_rx) = mpsc::channel();
+        // The stream runs on a thread of its own: audio capture streams
+        // cannot move between threads, so the flow is built where it runs.
+        let thread = std::thread::spawn(move || {
+            let flow = build_flow(config, group, &effect, safe_mode, move |event| {
+                if let FlowEvent::Frame {
+                    audio,
+                    frame,
+                    metrics,
+                } = event
+                {
+                    let mut levels = levels.lock().unwrap();
+                    levels.audio = *audio;
+                    levels.frame.clone_from(frame);
+                    levels.fps = metrics.fps();
+                    ctx.request_repaint();
+                }
+            })?;
+            let _ = control_tx.send(flow.control());
+            run(&handle, flow)
+        });
+        match control_rx.recv() {
+            Ok(control) => {
+                shared.status = format!("Streaming {} to {}", self.effect, area);
+                self.session = Some(Session { control, thread });
+            }
+            Err(_) => {
+                shared.status = match thread.join() {
+                    Ok(Err(e)) => format!("Cannot start the stream: {}", e),
+                    _ => "Cannot start the stream".to_string(),
+                };
+            }
+        }
+    }
+
+    fn send(&self, command: ControlCommand) {
+        if let Some(session) = &self.session {
+            let _ = session.control.try_send(command);
+        }
+    }
+
+    /// Stops a running stream and waits for it to hand the area back.
+    pub fn shutdown(&mut self, runtime: &Runtime) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let _ = session.control.try_send(ControlCommand::Stop);
+        runtime.block_on(async {
+            let deadline = tokio::time::Instant::now() + STOP_TIMEOUT;
+            while !session.thread.is_finished() && tokio::time::Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+    }
+}
+
+fn build_flow(
+    config: HueConfig,
+    group: GroupInfo,
+    effect: &str,
+    safe_mode: bool,
+    on_event: impl FnMut(FlowEvent<'_>) + Send + 'static,
+) -> anyhow::Result<HueFlow> {
+    let effect =
+        create_effect(effect, &[]).ok_or_else(|| anyhow::anyhow!("Unknown effect '{}'", effect))?;
+    let builder = HueFlow::builder()
+        .effect(effect)
+        .safe_mode(safe_mode)
+        .on_event(on_event);
+    #[cfg(feature = "capture")]
+    let builder = {
+        use hue_flow_core::audio_input::{AudioInput, CaptureOptions};
+        builder.audio_source(AudioInput::start(CaptureOptions {
+            device: config.audio_device.clone(),
+            sample_format: config.audio_sample_format.clone(),
+            hum_hz: config.hum_filter_hz,
+            noise_gate_db: config.noise_gate_db,
+            ..Default::default()
+        })?)
+    };
+    #[cfg(not(feature = "capture"))]
+    let builder = builder.audio_source(hue_flow_core::audio_interface::SyntheticAudio::default());
+    Ok(builder.bridge(config).group(group).build()?)
+}
+
+fn run(handle: &Handle, flow: HueFlow) -> anyhow::Result<()> {
+    handle.block_on(flow.run())
+}
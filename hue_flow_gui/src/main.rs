@@ -0,0 +1,221 @@
+//! HueFlow desktop app: bridge setup, audio device selection, a room map
+//! with draggable lights, live meters and effect controls.
+//!
+//! Everything that talks to the bridge runs on a Tokio runtime next to the
+//! UI; results come back through [`Job`]s polled every frame.
+//!
+//! On Windows, `--tray` runs the tray icon instead of the window.
+// No console window next to the app or the tray
+#![cfg_attr(windows, windows_subsystem = "windows")]
+#[cfg(windows)]
+mod daemon;
+mod live;
+mod room;
+mod setup;
+#[cfg(windows)]
+mod tray;
+
+use eframe::egui;
+use hue_flow_core::api::groups::{get_entertainment_groups, GroupInfo};
+use hue_flow_core::config;
+use hue_flow_core::models::HueConfig;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::mpsc;
+use tokio::runtime::Runtime;
+
+fn main() -> eframe::Result {
+    tracing_subscriber::fmt::init();
+    #[cfg(windows)]
+    if std::env::args().any(|arg| arg == "--tray") {
+        if let Err(e) = tray::run() {
+            tracing::error!("Tray mode failed: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("HueFlow")
+            .with_inner_size([960.0, 640.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "HueFlow",
+        options,
+        Box::new(|cc| Ok(Box::new(App::new(&cc.egui_ctx)))),
+    )
+}
+
+/// The result of a background task, polled from the UI thread.
+pub struct Job<T> {
+    result: mpsc::Receiver<T>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Runs `task` on `runtime`, repainting `ctx` once it is done.
+    pub fn spawn(
+        runtime: &Runtime,
+        ctx: &egui::Context,
+        task: impl Future<Output = T> + Send + 'static,
+    ) -> Self {
+        let (tx, result) = mpsc::channel();
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let _ = tx.send(task.await);
+            ctx.request_repaint();
+        });
+        Self { result }
+    }
+
+    pub fn poll(&mut self) -> Option<T> {
+        self.result.try_recv().ok()
+    }
+}
+
+/// State the panels share: the runtime, the stored config and the
+/// entertainment areas of its bridge.
+pub struct Shared {
+    pub runtime: Runtime,
+    pub config: Option<HueConfig>,
+    pub groups: Vec<GroupInfo>,
+    /// Colors of the last frame sent, by channel, while streaming.
+    pub colors: HashMap<u8, (u8, u8, u8)>,
+    /// One line at the bottom of the window.
+    pub status: String,
+    groups_job: Option<Job<Result<Vec<GroupInfo>, String>>>,
+}
+
+impl Shared {
+    /// The area streamed to, as selected in the config.
+    pub fn group(&self) -> Option<&GroupInfo> {
+        let config = self.config.as_ref()?;
+        self.groups
+            .iter()
+            .find(|g| g.id == config.entertainment_group_id)
+    }
+
+    pub fn group_mut(&mut self) -> Option<&mut GroupInfo> {
+        let id = self.config.as_ref()?.entertainment_group_id.clone();
+        self.groups.iter_mut().find(|g| g.id == id)
+    }
+
+    /// Writes the config back to disk, reporting failures in the status line.
+    pub fn save_config(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        if let Err(e) = config::save_file(&config::config_path(), config) {
+            self.status = format!("Cannot save the config: {}", e);
+        }
+    }
+
+    /// Fetches the entertainment areas of the configured bridge.
+    pub fn refresh_groups(&mut self, ctx: &egui::Context) {
+        let Some(config) = self.config.clone() else {
+            return;
+        };
+        self.status = "Loading entertainment areas...".to_string();
+        self.groups_job = Some(Job::spawn(&self.runtime, ctx, async move {
+            get_entertainment_groups(&config)
+                .await
+                .map_err(|e| e.to_string())
+        }));
+    }
+
+    fn poll(&mut self) {
+        let Some(result) = self.groups_job.as_mut().and_then(Job::poll) else {
+            return;
+        };
+        self.groups_job = None;
+        match result {
+            Ok(groups) if groups.is_empty() => {
+                self.status = "No entertainment areas; create one in the Hue app first".to_string();
+            }
+            Ok(groups) => {
+                self.status = format!("{} entertainment area(s)", groups.len());
+                self.groups = groups;
+                // Pick the first area when none is selected yet
+                if self.group().is_none() {
+                    let first = self.groups[0].id.clone();
+                    if let Some(config) = &mut self.config {
+                        config.entertainment_group_id = first;
+                    }
+                    self.save_config();
+                }
+            }
+            Err(e) => self.status = format!("Cannot load entertainment areas: {}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Setup,
+    Room,
+    Live,
+}
+
+struct App {
+    shared: Shared,
+    tab: Tab,
+    setup: setup::SetupPanel,
+    room: room::RoomPanel,
+    live: live::LivePanel,
+}
+
+impl App {
+    fn new(ctx: &egui::Context) -> Self {
+        let runtime = Runtime::new().expect("Cannot start the Tokio runtime");
+        let config = config::load_file(&config::config_path()).ok().flatten();
+        let tab = if config.is_some() {
+            Tab::Live
+        } else {
+            Tab::Setup
+        };
+        let mut shared = Shared {
+            runtime,
+            config,
+            groups: Vec::new(),
+            colors: HashMap::new(),
+            status: String::new(),
+            groups_job: None,
+        };
+        shared.refresh_groups(ctx);
+        Self {
+            shared,
+            tab,
+            setup: setup::SetupPanel::default(),
+            room: room::RoomPanel::default(),
+            live: live::LivePanel::default(),
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.shared.poll();
+        egui::TopBottomPanel::top("tabs").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.tab, Tab::Setup, "Setup");
+                ui.selectable_value(&mut self.tab, Tab::Room, "Room");
+                ui.selectable_value(&mut self.tab, Tab::Live, "Live");
+            });
+        });
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.label(&self.shared.status);
+        });
+        egui::CentralPanel::default().show(ctx, |ui| match self.tab {
+            Tab::Setup => self.setup.ui(ui, &mut self.shared),
+            Tab::Room => self.room.ui(ui, &mut self.shared),
+            Tab::Live => self.live.ui(ui, &mut self.shared),
+        });
+    }
+}
+
+impl Drop for App {
+    /// Hands the entertainment area back before the runtime goes away.
+    fn drop(&mut self) {
+        self.live.shutdown(&self.shared.runtime);
+    }
+}
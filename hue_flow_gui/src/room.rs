@@ -0,0 +1,170 @@
+//! A map of the entertainment area with lights that can be dragged into
+//! place and saved back to the bridge.
+use crate::{Job, Shared};
+use eframe::egui::{self, Color32, Pos2, Rect, Sense, Stroke, Vec2};
+use hue_flow_core::api::backup::{export_area, import_area};
+use hue_flow_core::models::{HueConfig, LightNode};
+use std::collections::HashMap;
+
+const LIGHT_RADIUS: f32 = 14.0;
+
+#[derive(Default)]
+pub struct RoomPanel {
+    /// Positions as loaded from the bridge, by channel, for "Revert".
+    original: HashMap<u8, (f64, f64)>,
+    /// Area the original positions belong to.
+    area: String,
+    saving: Option<Job<Result<(), String>>>,
+}
+
+impl RoomPanel {
+    pub fn ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        if let Some(result) = self.saving.as_mut().and_then(Job::poll) {
+            self.saving = None;
+            match result {
+                Ok(()) => {
+                    shared.status = "Positions saved to the bridge".to_string();
+                    self.area.clear();
+                }
+                Err(e) => shared.status = format!("Cannot save positions: {}", e),
+            }
+        }
+        let Some(group) = shared.group() else {
+            ui.label("Select an entertainment area in Setup first.");
+            return;
+        };
+        if self.area != group.id {
+            self.area = group.id.clone();
+            self.original = group
+                .lights
+                .iter()
+                .map(|n| (n.channel_id, (n.x, n.y)))
+                .collect();
+        }
+        let moved = group
+            .lights
+            .iter()
+            .any(|n| self.original.get(&n.channel_id) != Some(&(n.x, n.y)));
+        let name = group.name.clone();
+
+        ui.horizontal(|ui| {
+            ui.heading(name);
+            let idle = self.saving.is_none();
+            if ui
+                .add_enabled(moved && idle, egui::Button::new("Save to bridge"))
+                .clicked()
+            {
+                if let (Some(config), Some(group)) = (shared.config.clone(), shared.group()) {
+                    let lights = group.lights.clone();
+                    self.saving = Some(Job::spawn(&shared.runtime, ui.ctx(), async move {
+                        save_positions(&config, &lights)
+                            .await
+                            .map_err(|e| e.to_string())
+                    }));
+                }
+            }
+            if ui
+                .add_enabled(moved && idle, egui::Button::new("Revert"))
+                .clicked()
+            {
+                if let Some(group) = shared.group_mut() {
+                    for node in &mut group.lights {
+                        if let Some(&(x, y)) = self.original.get(&node.channel_id) {
+                            (node.x, node.y) = (x, y);
+                        }
+                    }
+                }
+            }
+            if !idle {
+                ui.spinner();
+            }
+        });
+        ui.label("Front (TV side) is at the top. Drag a light to move it.");
+
+        let size = ui.available_size().min_elem();
+        let (rect, _) = ui.allocate_exact_size(Vec2::splat(size), Sense::hover());
+        let map = rect.shrink(LIGHT_RADIUS);
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 4.0, ui.visuals().extreme_bg_color);
+        painter.line_segment(
+            [map.center_top(), map.center_bottom()],
+            Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+        painter.line_segment(
+            [map.left_center(), map.right_center()],
+            Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+
+        let colors = shared.colors.clone();
+        let Some(group) = shared.group_mut() else {
+            return;
+        };
+        for node in &mut group.lights {
+            let center = to_screen(map, node);
+            let id = ui.id().with(("light", node.channel_id));
+            let response = ui.interact(
+                Rect::from_center_size(center, Vec2::splat(LIGHT_RADIUS * 2.0)),
+                id,
+                Sense::drag(),
+            );
+            if response.dragged() {
+                let delta = response.drag_delta();
+                node.x = (node.x + (delta.x / map.width() * 2.0) as f64).clamp(-1.0, 1.0);
+                node.y = (node.y - (delta.y / map.height() * 2.0) as f64).clamp(-1.0, 1.0);
+            }
+            let fill = colors
+                .get(&node.channel_id)
+                .map(|&(r, g, b)| Color32::from_rgb(r, g, b))
+                .unwrap_or(Color32::GRAY);
+            let center = to_screen(map, node);
+            let stroke = match response.hovered() || response.dragged() {
+                true => Stroke::new(2.0, ui.visuals().strong_text_color()),
+                false => Stroke::new(1.0, ui.visuals().weak_text_color()),
+            };
+            painter.circle(center, LIGHT_RADIUS, fill, stroke);
+            painter.text(
+                center,
+                egui::Align2::CENTER_CENTER,
+                node.channel_id.to_string(),
+                egui::FontId::proportional(12.0),
+                Color32::BLACK,
+            );
+            response.on_hover_text(format!(
+                "Channel {} ({:.2}, {:.2})",
+                node.channel_id, node.x, node.y
+            ));
+        }
+    }
+}
+
+/// Maps area coordinates (-1..1, y = 1 at the front) into `map`.
+fn to_screen(map: Rect, node: &LightNode) -> Pos2 {
+    Pos2::new(
+        map.left() + (node.x as f32 + 1.0) / 2.0 * map.width(),
+        map.top() + (1.0 - node.y as f32) / 2.0 * map.height(),
+    )
+}
+
+/// Writes the positions of `lights` to their area through a backup and
+/// restore, which keeps every other setting of the area.
+///
+/// Lights with several channels (gradient strips) take the positions of
+/// their channels in channel order.
+async fn save_positions(config: &HueConfig, lights: &[LightNode]) -> anyhow::Result<()> {
+    let area = &config.entertainment_group_id;
+    let mut backup = export_area(config, area).await?;
+    for placement in &mut backup.lights {
+        let mut channels: Vec<&LightNode> = lights
+            .iter()
+            .filter(|n| n.id == placement.service)
+            .collect();
+        channels.sort_by_key(|n| n.channel_id);
+        for (position, node) in placement.positions.iter_mut().zip(channels) {
+            position.x = node.x;
+            position.y = node.y;
+        }
+    }
+    let name = backup.name.clone();
+    import_area(config, &backup, &name).await?;
+    Ok(())
+}
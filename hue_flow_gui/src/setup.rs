@@ -0,0 +1,208 @@
+//! Bridge discovery and registration, area and audio device selection.
+use crate::{Job, Shared};
+use eframe::egui;
+use hue_flow_core::api::client::{HueClient, LINK_WINDOW};
+use hue_flow_core::api::discovery::{discover_bridges, Bridge};
+use hue_flow_core::models::{HueConfig, RetryPolicy};
+
+#[derive(Default)]
+pub struct SetupPanel {
+    bridges: Vec<Bridge>,
+    manual_ip: String,
+    discovery: Option<Job<Result<Vec<Bridge>, String>>>,
+    registration: Option<Job<Result<HueConfig, String>>>,
+    #[cfg(feature = "capture")]
+    devices: Vec<String>,
+}
+
+impl SetupPanel {
+    pub fn ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        self.poll(ui.ctx(), shared);
+
+        ui.heading("Bridge");
+        if let Some(config) = &shared.config {
+            ui.label(format!(
+                "Linked to {} ({} {})",
+                config.bridge_ip, config.bridge_model, config.swversion
+            ));
+        }
+        ui.horizontal(|ui| {
+            let searching = self.discovery.is_some();
+            if ui
+                .add_enabled(!searching, egui::Button::new("Find bridges"))
+                .clicked()
+            {
+                self.discover(ui.ctx(), shared);
+            }
+            if searching {
+                ui.spinner();
+            }
+        });
+        let mut link = None;
+        for bridge in &self.bridges {
+            ui.horizontal(|ui| {
+                let name = match bridge.reachable {
+                    true => format!("{} - {} ({})", bridge.ip, bridge.name, bridge.model),
+                    false => format!("{} - not reachable", bridge.ip),
+                };
+                ui.label(name);
+                if ui.button("Link").clicked() {
+                    link = Some(bridge.ip.clone());
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("IP address:");
+            ui.text_edit_singleline(&mut self.manual_ip);
+            if ui.button("Link").clicked() && !self.manual_ip.trim().is_empty() {
+                link = Some(self.manual_ip.trim().to_string());
+            }
+        });
+        if self.registration.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Press the link button on the bridge...");
+            });
+        } else if let Some(ip) = link {
+            self.register(ui.ctx(), shared, ip);
+        }
+
+        ui.separator();
+        ui.heading("Entertainment area");
+        self.area_ui(ui, shared);
+
+        ui.separator();
+        ui.heading("Audio");
+        self.audio_ui(ui, shared);
+    }
+
+    fn area_ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        if shared.config.is_none() {
+            ui.label("Link a bridge first.");
+            return;
+        }
+        let selected = shared.group().map(|g| g.name.clone()).unwrap_or_default();
+        let mut chosen = None;
+        egui::ComboBox::from_id_salt("area")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for group in &shared.groups {
+                    let label = format!("{} ({} channels)", group.name, group.lights.len());
+                    if ui.selectable_label(false, label).clicked() {
+                        chosen = Some(group.id.clone());
+                    }
+                }
+            });
+        if ui.button("Reload areas").clicked() {
+            shared.refresh_groups(ui.ctx());
+        }
+        if let (Some(id), Some(config)) = (chosen, &mut shared.config) {
+            config.entertainment_group_id = id;
+            shared.save_config();
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    fn audio_ui(&mut self, ui: &mut egui::Ui, shared: &mut Shared) {
+        let Some(config) = &mut shared.config else {
+            return;
+        };
+        if self.devices.is_empty() || ui.button("Refresh devices").clicked() {
+            self.devices = hue_flow_core::audio_input::input_device_names();
+        }
+        let selected = config
+            .audio_device
+            .clone()
+            .unwrap_or_else(|| "Default input".to_string());
+        let mut chosen = None;
+        egui::ComboBox::from_id_salt("audio_device")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(false, "Default input").clicked() {
+                    chosen = Some(None);
+                }
+                for name in &self.devices {
+                    if ui.selectable_label(false, name).clicked() {
+                        chosen = Some(Some(name.clone()));
+                    }
+                }
+            });
+        if let Some(device) = chosen {
+            config.audio_device = device;
+            shared.save_config();
+        }
+    }
+
+    #[cfg(not(feature = "capture"))]
+    fn audio_ui(&mut self, ui: &mut egui::Ui, _shared: &mut Shared) {
+        ui.label("Synthetic audio; build with `--features capture` for a microphone or line-in.");
+    }
+
+    fn discover(&mut self, ctx: &egui::Context, shared: &mut Shared) {
+        let policy = retry_policy(shared);
+        shared.status = "Looking for bridges...".to_string();
+        self.discovery = Some(Job::spawn(&shared.runtime, ctx, async move {
+            discover_bridges(&policy).await.map_err(|e| e.to_string())
+        }));
+    }
+
+    fn register(&mut self, ctx: &egui::Context, shared: &mut Shared, ip: String) {
+        let policy = retry_policy(shared);
+        self.registration = Some(Job::spawn(&shared.runtime, ctx, async move {
+            register(&ip, policy).await.map_err(|e| e.to_string())
+        }));
+    }
+
+    fn poll(&mut self, ctx: &egui::Context, shared: &mut Shared) {
+        if let Some(result) = self.discovery.as_mut().and_then(Job::poll) {
+            self.discovery = None;
+            match result {
+                Ok(bridges) => {
+                    shared.status = format!("Found {} bridge(s)", bridges.len());
+                    self.bridges = bridges;
+                }
+                Err(e) => shared.status = format!("No bridges found: {}", e),
+            }
+        }
+        if let Some(result) = self.registration.as_mut().and_then(Job::poll) {
+            self.registration = None;
+            match result {
+                Ok(config) => {
+                    shared.status = format!("Linked to {}", config.bridge_ip);
+                    shared.config = Some(config);
+                    shared.save_config();
+                    shared.refresh_groups(ctx);
+                }
+                Err(e) => shared.status = format!("Cannot link: {}", e),
+            }
+        }
+    }
+}
+
+fn retry_policy(shared: &Shared) -> RetryPolicy {
+    shared
+        .config
+        .as_ref()
+        .map(|config| config.retry)
+        .unwrap_or_default()
+}
+
+/// Registers with the bridge at `ip` once its link button is pressed, as
+/// `hueflow setup` does, and checks that it can stream.
+async fn register(ip: &str, policy: RetryPolicy) -> anyhow::Result<HueConfig> {
+    let mut config =
+        HueClient::register_when_linked(ip, "hueflow#gui", LINK_WINDOW, &policy, |_| {})
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("The link button was not pressed in time"))?;
+    config.retry = policy;
+    if config.application_id.is_empty() {
+        config.application_id =
+            HueClient::get_application_id(&config.bridge_ip, &config.username).await?;
+    }
+    let bridge = HueClient::get_bridge_config(&config.bridge_ip).await?;
+    bridge.check_compatibility()?;
+    config.bridge_model = bridge.model_id;
+    config.swversion = bridge.swversion;
+    config.bridge_id = bridge.bridge_id;
+    Ok(config)
+}
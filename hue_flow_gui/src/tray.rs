@@ -0,0 +1,203 @@
+//! Tray mode (`--tray`): HueFlow in the background on Windows, like the Hue
+//! Sync desktop app.
+//!
+//! The tray menu starts and stops a `hueflow run` process, switches its
+//! effect and opens its web page; see [`Daemon`].
+use crate::daemon::{self, Daemon};
+use hue_flow_core::color::hue_to_rgb;
+use hue_flow_core::effects::EFFECTS;
+use std::time::{Duration, Instant};
+use tray_icon::menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+use winit::application::ApplicationHandler;
+use winit::event::{StartCause, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::window::WindowId;
+
+/// How often the tray asks the running instance how it is doing.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const ICON_SIZE: u32 = 32;
+const DEFAULT_EFFECT: &str = "multiband";
+
+struct Items {
+    toggle: MenuItem,
+    effects: Vec<(&'static str, CheckMenuItem)>,
+    web_ui: MenuItem,
+    quit: MenuItem,
+}
+
+struct Tray {
+    daemon: Daemon,
+    effect: String,
+    items: Option<Items>,
+    icon: Option<TrayIcon>,
+    /// Last failure, shown in the tooltip until the next action.
+    error: Option<String>,
+}
+
+/// Runs the tray until "Quit" is picked.
+pub fn run() -> anyhow::Result<()> {
+    let event_loop = EventLoop::<MenuEvent>::with_user_event().build()?;
+    let proxy = event_loop.create_proxy();
+    MenuEvent::set_event_handler(Some(move |event| {
+        let _ = proxy.send_event(event);
+    }));
+    let mut tray = Tray {
+        daemon: Daemon::new()?,
+        effect: DEFAULT_EFFECT.to_string(),
+        items: None,
+        icon: None,
+        error: None,
+    };
+    event_loop.run_app(&mut tray)?;
+    Ok(())
+}
+
+impl Tray {
+    fn create(&mut self) -> anyhow::Result<()> {
+        let toggle = MenuItem::new("Start streaming", true, None);
+        let effects: Vec<_> = EFFECTS
+            .iter()
+            .map(|info| {
+                let checked = info.name == self.effect;
+                (
+                    info.name,
+                    CheckMenuItem::new(info.name, true, checked, None),
+                )
+            })
+            .collect();
+        let effect_menu = Submenu::new("Effect", true);
+        for (_, item) in &effects {
+            effect_menu.append(item)?;
+        }
+        let web_ui = MenuItem::new("Open web UI", false, None);
+        let quit = MenuItem::new("Quit", true, None);
+        let menu = Menu::new();
+        menu.append_items(&[
+            &toggle,
+            &effect_menu,
+            &PredefinedMenuItem::separator(),
+            &web_ui,
+            &PredefinedMenuItem::separator(),
+            &quit,
+        ])?;
+        self.icon = Some(
+            TrayIconBuilder::new()
+                .with_menu(Box::new(menu))
+                .with_tooltip("HueFlow")
+                .with_icon(icon()?)
+                .build()?,
+        );
+        self.items = Some(Items {
+            toggle,
+            effects,
+            web_ui,
+            quit,
+        });
+        Ok(())
+    }
+
+    /// Shows whether an instance is streaming in the menu and tooltip.
+    fn refresh(&mut self) {
+        let status = self.daemon.status();
+        let starting = status.is_none() && self.daemon.started();
+        let (Some(items), Some(icon)) = (&self.items, &self.icon) else {
+            return;
+        };
+        let running = status.is_some() || starting;
+        items.toggle.set_text(match running {
+            true => "Stop streaming",
+            false => "Start streaming",
+        });
+        items.web_ui.set_enabled(status.is_some());
+        let state = match (&self.error, status) {
+            (Some(error), _) => error.clone(),
+            (None, Some(status)) => format!("{}, {} FPS", status.state, status.fps),
+            (None, None) if starting => "starting".to_string(),
+            (None, None) => "stopped".to_string(),
+        };
+        let _ = icon.set_tooltip(Some(format!("HueFlow - {}", state)));
+    }
+
+    fn handle(&mut self, event_loop: &ActiveEventLoop, event: MenuEvent) {
+        let Some(items) = &self.items else {
+            return;
+        };
+        let result = if &event.id == items.toggle.id() {
+            match self.daemon.status().is_some() || self.daemon.started() {
+                true => self.daemon.stop(),
+                false => self.daemon.start(&self.effect),
+            }
+        } else if &event.id == items.web_ui.id() {
+            webbrowser::open(&daemon::web_ui_url()).map_err(Into::into)
+        } else if &event.id == items.quit.id() {
+            // Hand the area back if the stream was started from here
+            if self.daemon.started() {
+                let _ = self.daemon.stop();
+            }
+            event_loop.exit();
+            return;
+        } else if let Some((name, _)) = items.effects.iter().find(|(_, i)| &event.id == i.id()) {
+            // Check items toggle themselves; keep exactly one checked
+            for (other, item) in &items.effects {
+                item.set_checked(other == name);
+            }
+            self.effect = name.to_string();
+            match self.daemon.status() {
+                Some(_) => self.daemon.set_effect(name),
+                None => Ok(()),
+            }
+        } else {
+            return;
+        };
+        self.error = result.err().map(|e| e.to_string());
+        if let Some(error) = &self.error {
+            tracing::warn!("{}", error);
+        }
+        self.refresh();
+    }
+}
+
+impl ApplicationHandler<MenuEvent> for Tray {
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        match cause {
+            // The icon is created once the event loop runs, as the tray
+            // needs its message loop
+            StartCause::Init => {
+                if let Err(e) = self.create() {
+                    tracing::error!("Cannot create the tray icon: {}", e);
+                    event_loop.exit();
+                    return;
+                }
+            }
+            StartCause::ResumeTimeReached { .. } => {}
+            _ => return,
+        }
+        self.refresh();
+        event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now() + REFRESH_INTERVAL));
+    }
+
+    fn resumed(&mut self, _event_loop: &ActiveEventLoop) {}
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: MenuEvent) {
+        self.handle(event_loop, event);
+    }
+
+    fn window_event(&mut self, _: &ActiveEventLoop, _: WindowId, _: WindowEvent) {}
+}
+
+/// A color wheel, so the icon reads as HueFlow at tray size.
+fn icon() -> anyhow::Result<Icon> {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let (dx, dy) = (x as f32 - center, y as f32 - center);
+            let (r, g, b) = hue_to_rgb(dy.atan2(dx).to_degrees().rem_euclid(360.0));
+            // Anti-aliased edge
+            let alpha = (center + 0.5 - dx.hypot(dy)).clamp(0.0, 1.0);
+            rgba.extend([r, g, b, (alpha * 255.0) as u8]);
+        }
+    }
+    Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+}